@@ -0,0 +1,175 @@
+//! Benchmarks of the hot path of a tick, from parsing a forecast to building its points.
+//!
+//! Run with `cargo bench`, the forecasts are those of `tests/fixtures` and forecasts of 36 and
+//! 500 horizons built alike.
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use influxdb2::models::WriteDataPoint;
+use std::fs;
+use std::path::Path;
+use swat_collector::points::{
+    forecast_data_point, forecast_v2_points, issue_timestamp, GeoFields, LeadTimes, NameMapping,
+};
+use swat_collector::processing::Processing;
+use swat_collector::swat::{parse_forecast, Forecast, Location, Model, Target};
+
+const LOCATION: Location = Location {
+    group: "",
+    id: 1,
+    lat: "52.9109818816186",
+    lon: "8.23505277402053",
+    name: "WW Großenkneten",
+};
+
+/// Horizons of a usual forecast and of a much longer one.
+const HORIZONS: [usize; 2] = [36, 500];
+
+/// The bodies of the fixtures shared with the tests, by file name.
+fn fixtures() -> Vec<(String, String)> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let mut fixtures: Vec<_> = fs::read_dir(dir)
+        .expect("fixtures are checked in")
+        .map(|entry| entry.expect("fixtures are readable").path())
+        .filter(|path| path.to_string_lossy().ends_with(".body.json"))
+        .map(|path| {
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            (
+                name,
+                fs::read_to_string(path).expect("fixtures are readable"),
+            )
+        })
+        .collect();
+    fixtures.sort();
+    fixtures
+}
+
+/// A body like the swat api sends, with a horizon every 5 minutes.
+fn body(horizons: usize) -> String {
+    let issued = chrono::NaiveDate::from_ymd_opt(2024, 3, 7)
+        .unwrap()
+        .and_hms_opt(8, 5, 0)
+        .unwrap();
+    let time = |minutes: usize| {
+        (issued + chrono::Duration::minutes(minutes as i64))
+            .format("%Y-%m-%d %H:%M")
+            .to_string()
+    };
+    let forecasts: Vec<_> = (1..=horizons)
+        .map(|horizon| format!("{:?}: {}.{}", time(horizon * 5), horizon % 97, horizon % 10))
+        .collect();
+    format!(
+        r#"{{"vorhersageZeit": {from:?}, "lat": 52.9125, "lon": 8.2375,
+            "aktuell": {{{from:?}: 412.4}}, "vorhersage": {{{}}}}}"#,
+        forecasts.join(", "),
+        from = time(0),
+    )
+}
+
+fn deserialize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("deserialize");
+    for (name, body) in fixtures() {
+        group.bench_function(name, |b| {
+            b.iter(|| serde_json::from_str::<Forecast>(black_box(&body)).unwrap())
+        });
+    }
+    for horizons in HORIZONS {
+        let body = body(horizons);
+        group.bench_function(BenchmarkId::new("horizons", horizons), |b| {
+            b.iter(|| serde_json::from_str::<Forecast>(black_box(&body)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn timestamps(c: &mut Criterion) {
+    let mut group = c.benchmark_group("timestamp");
+    for (format, time) in [
+        ("padded", "2024-03-07 08:05"),
+        ("unpadded", "2024-3-7 8:05"),
+        ("iso8601_offset", "2024-03-07T08:05:00+01:00"),
+    ] {
+        group.bench_function(format, |b| {
+            b.iter(|| issue_timestamp(black_box(time)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn normalize_and_process(c: &mut Criterion) {
+    let processing = Processing::from_lookup(
+        |key| match key {
+            "PROCESSING" => Some("convert=2.7778,round=1".to_string()),
+            "PROCESSING_KEEP_RAW" => Some("true".to_string()),
+            _ => None,
+        },
+        &[LOCATION],
+    )
+    .unwrap();
+    let mut group = c.benchmark_group("normalize_and_process");
+    for horizons in HORIZONS {
+        let body = body(horizons);
+        group.bench_function(BenchmarkId::from_parameter(horizons), |b| {
+            b.iter_batched(
+                || parse_forecast(body.clone()).unwrap(),
+                |mut forecast| {
+                    forecast.normalize_timestamps().unwrap();
+                    processing.apply(&LOCATION, &mut forecast);
+                    forecast
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn points(c: &mut Criterion) {
+    let model = Model::default_model();
+    let target = Target {
+        location: &LOCATION,
+        model: &model,
+    };
+    let names = NameMapping::default();
+    let mut group = c.benchmark_group("points");
+    for horizons in HORIZONS {
+        let forecast = parse_forecast(body(horizons)).unwrap();
+        group.bench_function(BenchmarkId::new("legacy", horizons), |b| {
+            b.iter(|| {
+                let point = forecast_data_point(
+                    target,
+                    &forecast,
+                    false,
+                    false,
+                    usize::MAX,
+                    GeoFields::Off,
+                )
+                .unwrap();
+                let mut line = Vec::new();
+                point.write_data_point_to(&mut line).unwrap();
+                line
+            })
+        });
+        group.bench_function(BenchmarkId::new("v2", horizons), |b| {
+            b.iter(|| {
+                let points =
+                    forecast_v2_points(target, &forecast, &names, &mut LeadTimes::default())
+                        .unwrap();
+                let mut lines = Vec::new();
+                for point in points.points {
+                    point.write_data_point_to(&mut lines).unwrap();
+                }
+                lines
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    deserialize,
+    timestamps,
+    normalize_and_process,
+    points
+);
+criterion_main!(benches);
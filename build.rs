@@ -0,0 +1,59 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Used for every value that cannot be determined, e.g. when building from a source tarball.
+const UNKNOWN: &str = "unknown";
+
+fn main() {
+    let commit = git(&["rev-parse", "--short=8", "HEAD"]);
+    let dirty = match git(&["status", "--porcelain", "--untracked-files=no"]) {
+        Some(status) if !status.is_empty() => "-dirty",
+        _ => "",
+    };
+    let build_time = chrono::Utc::now().format("%Y-%m-%d %H:%M UTC");
+
+    println!(
+        "cargo:rustc-env=GIT_COMMIT={}",
+        commit.as_deref().unwrap_or(UNKNOWN)
+    );
+    println!("cargo:rustc-env=GIT_DIRTY={dirty}");
+    println!("cargo:rustc-env=BUILD_TIME={build_time}");
+
+    // rebuild when the checked out commit or the working tree changes
+    println!("cargo:rerun-if-changed=build.rs");
+    let head_ref = fs::read_to_string(".git/HEAD")
+        .ok()
+        .and_then(|head| Some(format!(".git/{}", head.strip_prefix("ref: ")?.trim())));
+    for path in [".git/HEAD".to_string(), ".git/index".to_string()]
+        .into_iter()
+        .chain(head_ref)
+    {
+        if Path::new(&path).exists() {
+            println!("cargo:rerun-if-changed={path}");
+        }
+    }
+
+    #[cfg(feature = "grpc")]
+    compile_protos();
+}
+
+/// Generates the gRPC service from `proto/`, with the bundled `protoc` so building does not
+/// depend on one being installed.
+#[cfg(feature = "grpc")]
+fn compile_protos() {
+    let protoc = protoc_bin_vendored::protoc_bin_path().expect("protoc is bundled");
+    std::env::set_var("PROTOC", protoc);
+    println!("cargo:rerun-if-changed=proto");
+    tonic_build::compile_protos("proto/collector.proto")
+        .unwrap_or_else(|err| panic!("cannot compile protos, {err}"));
+}
+
+/// Runs git with `args`, returns the trimmed output if it succeeded.
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8(output.stdout).ok()?.trim().to_string())
+}
@@ -0,0 +1,4183 @@
+use crate::backoff::{LoopBackoff, TickOutcome};
+use crate::canary::Canary;
+use crate::circuit_breaker::{CircuitBreaker, Permit};
+use crate::effective_config::EffectiveConfig;
+use crate::gap_watchdog::GapWatchdog;
+use crate::geo::GeoFields;
+use crate::groups::LocationGroups;
+use crate::horizons::HorizonTracker;
+use crate::http_client::HttpClientConfig;
+use crate::incident::{IncidentAction, IncidentTracker};
+use crate::issues::IssueTracker;
+use crate::janitor::Janitor;
+use crate::locations::{ApiNegotiation, ForecastSource, Models, RequestLocationError, Target};
+use crate::maintenance::{Maintenance, MaintenanceWindow};
+use crate::points::{
+    forecast_point_builder, forecast_v2_points, issue_timestamp, Batch, HandleLocationError,
+    PendingPoint,
+};
+use crate::processing::Processing;
+use crate::profiles::Profile;
+use crate::schema_mode::{SchemaCheck, SchemaMode};
+use crate::severity::Severity;
+use crate::shared_requests::SharedRequests;
+use crate::sink::{Buckets, InfluxClient, InitBucketError, Sink, WriteError};
+use crate::skipped_ticks::SkippedTicks;
+use crate::spread::Spread;
+use crate::startup::{QuietStart, Startup};
+use crate::state::AppState;
+use crate::state_file::{StateExport, StateFile};
+use crate::swat_auth::SwatAuth;
+use crate::tick_budget::TickBudget;
+use crate::tick_report::{Outcome, TickRecorder, TickReport};
+use crate::trigger::Pass;
+use crate::webhook::{
+    AlertField, Audited, Branding, Delivery, Destination, Hints, Mute, Notification,
+    NotificationQueue, QuietHours, Webhook, DEFAULT_REALERT_SUPPRESS_MINUTES,
+};
+use clap::{Parser, Subcommand};
+use influxdb2::api::buckets::ListBucketsRequest;
+use influxdb2::api::organization::ListOrganizationRequest;
+#[cfg(feature = "health-check")]
+use influxdb2::models::Status;
+use influxdb2::models::{DataPoint, PostBucketRequest};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use std::collections::BTreeMap;
+use std::env;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use twilight_model::id::Id;
+
+#[cfg(feature = "archive")]
+use crate::archive;
+#[cfg(feature = "chaos")]
+use crate::chaos::{self, Chaos, Fault};
+#[cfg(any(feature = "kafka", feature = "nats"))]
+use crate::event;
+#[cfg(feature = "grpc")]
+use crate::grpc;
+#[cfg(feature = "kafka")]
+use crate::kafka;
+#[cfg(feature = "nats")]
+use crate::nats;
+use crate::{
+    bounded_cache, canary, circuit_breaker, config, content_hash, coordinates, doctor, duplicates,
+    egress, env_file, fields, fixture, gaps, geo, groups, history, horizons, http, import,
+    incident, instance, issues, janitor, live, locations, logging, names, parse_failures, pipeline,
+    read_only, redact, retry, schema, severity, skipped_ticks, spool, startup, tick_budget,
+    tick_stats, trigger, version, COLLECTION_INTERVAL,
+};
+#[cfg(feature = "health-check")]
+use crate::{clock, health_check};
+
+/// Reads a variable of the `profile`, see [`Profile::var`].
+macro_rules! env {
+    ($profile:expr, $env:literal) => {
+        match $profile.var($env) {
+            Some(var) => var,
+            None => panic!(
+                "expected {:?} to be available in the environment or a `.env` file",
+                $env
+            ),
+        }
+    };
+}
+
+/// Parses a variable of the environment, or of a `profile` when given.
+macro_rules! env_or {
+    ($env:literal, $default:expr) => {
+        match env::var($env) {
+            Ok(var) => match var.parse() {
+                Ok(value) => value,
+                Err(err) => panic!("expected {:?} to be valid, {err}", $env),
+            },
+            Err(_) => $default,
+        }
+    };
+    ($profile:expr, $env:literal, $default:expr) => {
+        match $profile.var($env) {
+            Some(var) => match var.parse() {
+                Ok(value) => value,
+                Err(err) => panic!("expected {:?} to be valid, {err}", $env),
+            },
+            None => $default,
+        }
+    };
+}
+
+#[derive(Debug, Parser)]
+#[command(version = version::VERSION, long_version = version::LONG_VERSION)]
+pub struct Args {
+    /// Runs a health check when used, primarily for Docker to verify the application's status.
+    #[cfg(feature = "health-check")]
+    #[arg(long = "health-check", group = "report")]
+    pub health_check: bool,
+
+    /// Prints the most recent errors along with the health check.
+    #[cfg(feature = "health-check")]
+    #[arg(long = "verbose", requires = "health_check")]
+    pub verbose: bool,
+
+    /// Prints the health check as JSON, one object per health mode, or the gaps or instances as
+    /// JSON.
+    #[arg(long = "json", requires = "report")]
+    #[cfg_attr(feature = "health-check", arg(conflicts_with = "prometheus"))]
+    pub json: bool,
+
+    /// Prints the health check as Prometheus gauges, for the node exporter textfile collector.
+    #[cfg(feature = "health-check")]
+    #[arg(long = "prometheus", requires = "health_check")]
+    pub prometheus: bool,
+
+    /// Switches the running collector to the given schema mode, through the health socket.
+    #[cfg(feature = "health-check")]
+    #[arg(long = "schema-mode", value_name = "MODE")]
+    pub schema_mode: Option<SchemaMode>,
+
+    /// Prints the gaps in the collected forecasts of the last 30 days kept in the `STATE_FILE`.
+    #[arg(long = "gaps", group = "report")]
+    pub gaps: bool,
+
+    /// Lists the collectors that registered themselves in InfluxDB in the last 24 hours.
+    #[arg(long = "instances", group = "report")]
+    pub instances: bool,
+
+    /// Requests the forecast for a location once and stores the response as a test fixture.
+    #[arg(long = "capture-fixture", value_name = "LOCATION")]
+    pub capture_fixture: Option<String>,
+
+    /// Directory the captured fixtures are written into and replayed from when offline.
+    #[arg(long = "fixtures-dir", default_value = "tests/fixtures")]
+    pub fixtures_dir: PathBuf,
+
+    /// Imports historical forecasts from a CSV file with the columns `location`, `issued`,
+    /// `current` and `forecasts` into the buckets of the locations, then exits.
+    #[arg(long = "import-csv", value_name = "FILE")]
+    pub import_csv: Option<PathBuf>,
+
+    /// Reads a field of the import from a differently named column, like `issued=vorhersageZeit`.
+    #[arg(
+        long = "csv-column",
+        value_name = "FIELD=COLUMN",
+        requires = "import_csv"
+    )]
+    pub csv_columns: Vec<String>,
+
+    /// Rows of the import written at once.
+    #[arg(long = "batch-size", default_value_t = import::DEFAULT_BATCH_SIZE)]
+    pub batch_size: usize,
+
+    /// Where the last written row of the import is kept, an interrupted import continues after it.
+    #[arg(long = "import-state", default_value = import::DEFAULT_STATE_PATH)]
+    pub import_state: PathBuf,
+
+    /// Runs without network access, replaying the fixtures and printing the line protocol
+    /// instead of writing to InfluxDB, notifications are discarded.
+    #[arg(long = "offline", env = "OFFLINE")]
+    pub offline: bool,
+
+    /// Alerts with one field per erroneous location instead of grouping the errors by kind,
+    /// for smaller deployments.
+    #[arg(long = "verbose-alerts", env = "VERBOSE_ALERTS")]
+    pub verbose_alerts: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Moves the state kept in the `STATE_FILE` to another host, like its baselines and gaps.
+    #[command(subcommand)]
+    State(StateCommand),
+
+    /// Checks the configuration and the connections of a deployment, printing what to fix.
+    Doctor {
+        /// Prints the findings as JSON.
+        #[arg(long = "json")]
+        json: bool,
+    },
+
+    /// Keeps collecting the location given by name, slug or id but stops alerting about it
+    /// for the given duration, like `7d`, through the health socket.
+    #[cfg(feature = "health-check")]
+    Snooze {
+        #[arg(value_name = "LOCATION")]
+        location: String,
+
+        #[arg(value_name = "DURATION")]
+        duration: String,
+    },
+
+    /// Alerts about a snoozed location again, through the health socket.
+    #[cfg(feature = "health-check")]
+    Unsnooze {
+        #[arg(value_name = "LOCATION")]
+        location: String,
+    },
+
+    /// Prints the forecasts the running collector kept of the location given by name, slug or
+    /// id, through the health socket.
+    #[cfg(feature = "health-check")]
+    History {
+        #[arg(value_name = "LOCATION")]
+        location: String,
+    },
+
+    /// Collects a single tick and prints its report, failing if any location failed.
+    Once {
+        /// Prints the report as JSON, one object per profile.
+        #[arg(long = "json")]
+        json: bool,
+    },
+
+    /// Mutes alerts of the running collector for the given minutes, through the health socket.
+    /// The mute is kept in the `STATE_FILE`, so it outlasts a restart.
+    #[cfg(feature = "health-check")]
+    Mute {
+        #[arg(value_name = "MINUTES")]
+        minutes: u32,
+    },
+
+    /// Unmutes alerts of the running collector, through the health socket.
+    #[cfg(feature = "health-check")]
+    Unmute,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum StateCommand {
+    /// Writes the state kept in the `STATE_FILE` into a file, along with its version.
+    Export {
+        #[arg(long = "out", value_name = "FILE")]
+        out: PathBuf,
+    },
+
+    /// Replaces the `STATE_FILE` with an exported state of the same version, the state of
+    /// locations not configured is imported but reported. A running collector keeps its own
+    /// state until it is reloaded.
+    Import {
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+    },
+
+    /// Reloads the `STATE_FILE` into the running collector, through the health socket.
+    #[cfg(feature = "health-check")]
+    Reload,
+}
+
+/// Entry point of the `swat-collector` binary, not part of the public api.
+pub async fn main() -> ExitCode {
+    env_file::load();
+    config::sanitize_env().unwrap_or_else(|err| panic!("invalid configuration, {err}"));
+    let args = Args::parse();
+    logging::init(env_or!("LOG_BUFFER_SIZE", 1024));
+    Lazy::force(&logging::LOG_TIME);
+    let code = run(args).await;
+    logging::flush();
+    code
+}
+
+async fn run(args: Args) -> ExitCode {
+    let api_url: String = env_or!("SWAT_API_URL", locations::DEFAULT_API_URL.to_string());
+    let api_url = api_url.trim_end_matches('/');
+
+    if args.gaps {
+        return print_gaps(args.json);
+    }
+
+    match &args.command {
+        Some(Command::State(command)) => {
+            return match command {
+                StateCommand::Export { out } => export_state(out),
+                StateCommand::Import { file } => import_state(file),
+                #[cfg(feature = "health-check")]
+                StateCommand::Reload => health_check::reload_state().await,
+            }
+        }
+        Some(Command::Doctor { json }) => return doctor::run(*json).await,
+        #[cfg(feature = "health-check")]
+        Some(Command::Snooze { location, duration }) => {
+            return health_check::snooze(location, Some(duration)).await
+        }
+        #[cfg(feature = "health-check")]
+        Some(Command::Unsnooze { location }) => return health_check::snooze(location, None).await,
+        #[cfg(feature = "health-check")]
+        Some(Command::History { location }) => return health_check::history(location).await,
+        #[cfg(feature = "health-check")]
+        Some(Command::Mute { minutes }) => return health_check::mute(Some(*minutes)).await,
+        #[cfg(feature = "health-check")]
+        Some(Command::Unmute) => return health_check::mute(None).await,
+        Some(Command::Once { .. }) | None => (),
+    }
+
+    if let Some(location) = args.capture_fixture {
+        return fixture::capture(&location, &args.fixtures_dir, api_url).await;
+    }
+
+    #[cfg(feature = "health-check")]
+    if args.health_check {
+        let format = match (args.json, args.prometheus) {
+            (true, _) => health_check::Format::Json,
+            (_, true) => health_check::Format::Prometheus,
+            _ => health_check::Format::Plain,
+        };
+        return health_check::check(&clock::SystemClock, args.verbose, format).await;
+    }
+
+    #[cfg(feature = "health-check")]
+    if let Some(mode) = args.schema_mode {
+        return health_check::switch_schema_mode(mode).await;
+    }
+
+    if args.import_csv.is_some() || args.instances {
+        let profile = Profile::unnamed(|key| env::var(key).ok());
+        let sink = match args.offline {
+            true => Sink::Stdout,
+            false => sink(&profile, *locations::CONFIGURED).await,
+        };
+        if let Some(file) = args.import_csv {
+            return import::run(
+                file,
+                &args.csv_columns,
+                args.batch_size,
+                args.import_state,
+                &sink,
+            )
+            .await;
+        }
+        return print_instances(&sink, args.json).await;
+    }
+
+    Lazy::force(&severity::MAPPING);
+    Lazy::force(&fields::FIELD_LIMIT);
+    Lazy::force(&names::NAMES);
+    Lazy::force(&redact::REDACT_COORDINATES);
+    Lazy::force(&coordinates::DECIMALS);
+    locations::check_unique_slugs(*locations::CONFIGURED)
+        .unwrap_or_else(|err| panic!("invalid locations, {err}"));
+    groups::check(*locations::CONFIGURED).unwrap_or_else(|err| panic!("invalid locations, {err}"));
+    let profiles = Profile::from_lookup(|key| env::var(key).ok())
+        .unwrap_or_else(|err| panic!("invalid profiles, {err}"));
+    let longest_interval = profiles
+        .iter()
+        .map(|profile| {
+            let minutes: u64 = env_or!(
+                profile,
+                "COLLECTION_INTERVAL_MINUTES",
+                COLLECTION_INTERVAL.as_secs() / 60
+            );
+            Duration::from_secs(minutes * 60)
+        })
+        .max()
+        .unwrap_or(COLLECTION_INTERVAL);
+    let http_client = HttpClientConfig::from_lookup(|key| env::var(key).ok(), longest_interval)
+        .unwrap_or_else(|err| panic!("invalid http client, {err}"));
+    let client = http_client
+        .builder()
+        .build()
+        .unwrap_or_else(|err| panic!("could not build http client, {err}"));
+    let datetime = logging::datetime();
+    log_eprintln!("INFO  [{datetime}]: http client with {http_client}");
+    let mute = Arc::default();
+    let mut collectors = Vec::new();
+    for profile in profiles {
+        collectors.push(Collector::new(&args, profile, &client, &http_client, &mute).await);
+    }
+
+    #[cfg(feature = "health-check")]
+    if let Err(code) = start_health_check(&collectors) {
+        return code;
+    }
+
+    let datetime = logging::datetime();
+    match args.offline {
+        true => log_eprintln!(
+            "INFO  [{datetime}]: swat-collector {} running offline, replaying fixtures from {:?}",
+            version::LONG_VERSION,
+            args.fixtures_dir
+        ),
+        false => log_eprintln!(
+            "INFO  [{datetime}]: initialized buckets, swat-collector {} running",
+            version::LONG_VERSION
+        ),
+    }
+
+    if collectors.len() == 1 {
+        let collector = collectors.remove(0);
+        return match collector.run().await {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(code) => code,
+        };
+    }
+
+    // a profile failing, even by panicking, leaves the others collecting
+    let tasks: Vec<_> = collectors
+        .into_iter()
+        .map(|collector| (collector.profile.prefix(), tokio::spawn(collector.run())))
+        .collect();
+    let mut code = ExitCode::SUCCESS;
+    for (profile, task) in tasks {
+        let datetime = logging::datetime();
+        match task.await {
+            Ok(Ok(())) => (),
+            Ok(Err(failure)) => {
+                log_eprintln!("ERROR [{datetime}]: profile {profile} stopped collecting");
+                code = failure;
+            }
+            Err(err) => {
+                log_eprintln!("ERROR [{datetime}]: profile {profile} stopped collecting, {err}");
+                code = ExitCode::FAILURE;
+            }
+        }
+    }
+    code
+}
+
+/// Connects to InfluxDB with the settings of the `profile`, creating the buckets of its
+/// `locations`.
+async fn sink(profile: &Profile, locations: &[locations::Location]) -> Sink {
+    let influxdb_url = env!(profile, "INFLUXDB_URL");
+    let influxdb_org = env!(profile, "INFLUXDB_ORG");
+    let influxdb_token = env!(profile, "INFLUXDB_TOKEN");
+    let client = InfluxClient::new(influxdb_url, influxdb_org.clone(), influxdb_token);
+    let buckets = Buckets::from_lookup(|key| profile.var(key), locations);
+    if env_or!(profile, "READ_ONLY", false) {
+        read_only::check_buckets(buckets.names())
+            .unwrap_or_else(|err| panic!("invalid read-only mode, {err}"));
+    }
+    // the locations of a bucket that cannot be created fail writing, not the whole startup
+    for (bucket, err) in init_buckets(&client, influxdb_org, &buckets).await {
+        let routed: Vec<_> = (locations.iter())
+            .filter(|location| buckets.of(location) == bucket)
+            .map(|location| format!("{:?}", location.name))
+            .collect();
+        let datetime = logging::datetime();
+        log_eprintln!(
+            "ERROR [{datetime}]: could not initialize bucket {bucket:?}, {err}, writing {} fails \
+             until it exists",
+            routed.join(", ")
+        );
+    }
+    Sink::Influx {
+        client: Box::new(client),
+        buckets,
+        idempotent: env_or!(profile, "IDEMPOTENT_WRITES", false),
+        profile: profile.name().map(Arc::from),
+    }
+}
+
+/// Everything a profile collects with, set up before any profile starts collecting.
+struct Collector {
+    profile: Profile,
+    locations: Vec<locations::Location>,
+    models: Models,
+    source: ForecastSource,
+    sink: Arc<Sink>,
+    state: Arc<AppState>,
+    notifications: Arc<NotificationQueue>,
+    hints: Option<Hints>,
+    canary: Option<Canary>,
+    offline: bool,
+
+    /// Whether a state file was left behind by an earlier run.
+    restored: bool,
+
+    /// The configuration the last run collected with, if kept in its state file.
+    last_config: Option<EffectiveConfig>,
+
+    /// Set for `once`, whether the report of the single tick is printed as JSON.
+    once: Option<bool>,
+}
+
+/// How long `once` waits for the notifications of its tick to be delivered.
+const ONCE_FLUSH_TIMEOUT: Duration = Duration::from_secs(30);
+
+impl Collector {
+    /// Reads the configuration of the `profile`, connecting its outputs with the shared
+    /// `client` and `mute`.
+    ///
+    /// Profiles requesting the swat api with credentials get a client of their own with the
+    /// settings of `http_client`.
+    async fn new(
+        args: &Args,
+        profile: Profile,
+        client: &reqwest::Client,
+        http_client: &HttpClientConfig,
+        mute: &Arc<RwLock<Mute>>,
+    ) -> Collector {
+        let locations = profile
+            .locations()
+            .unwrap_or_else(|err| panic!("invalid profiles, {err}"));
+        let (source, sink) = match args.offline {
+            true => {
+                let bodies = fixture::load_bodies(&args.fixtures_dir).unwrap_or_else(|err| {
+                    panic!("could not load fixtures for offline mode, {err}")
+                });
+                (ForecastSource::fixtures(bodies), Sink::Stdout)
+            }
+            false => {
+                let api_url: String = env_or!(
+                    profile,
+                    "SWAT_API_URL",
+                    locations::DEFAULT_API_URL.to_string()
+                );
+                let api = ApiNegotiation::from_lookup(|key| profile.var(key))
+                    .unwrap_or_else(|err| panic!("invalid swat api, {err}"));
+                let auth = SwatAuth::from_lookup(|key| profile.var(key))
+                    .unwrap_or_else(|err| panic!("invalid swat auth, {err}"));
+                let client = match auth {
+                    Some(auth) => {
+                        let datetime = logging::datetime();
+                        log_eprintln!("INFO  [{datetime}]: requesting the swat api with {auth}");
+                        auth.apply(http_client.builder())
+                            .build()
+                            .unwrap_or_else(|err| panic!("could not build http client, {err}"))
+                    }
+                    None => client.clone(),
+                };
+                let source = ForecastSource::Api {
+                    client,
+                    url: api_url.trim_end_matches('/').to_string(),
+                    api: Arc::new(api),
+                };
+                (source, sink(&profile, &locations).await)
+            }
+        };
+        let sink = Arc::new(sink);
+        let read_only = env_or!(profile, "READ_ONLY", false);
+        if read_only {
+            let buckets: Vec<_> = (sink.bucket_names().into_iter())
+                .map(|bucket| format!("{bucket:?}"))
+                .collect();
+            let datetime = logging::datetime();
+            log_eprintln!(
+                "WARN  [{datetime}]: {}, into {}",
+                read_only::BANNER,
+                buckets.join(", ")
+            );
+        }
+
+        let destinations = match args.offline {
+            true => Vec::new(),
+            false => destinations(&profile),
+        };
+        let mut branding = Branding::from_lookup(|key| profile.var(key));
+        if profile.name().is_some() {
+            branding.title_prefix = Some(match branding.title_prefix {
+                Some(prefix) => format!("{} {prefix}", profile.prefix()),
+                None => profile.prefix(),
+            });
+        }
+        let hints = (!args.verbose_alerts).then(|| Hints::from_lookup(|key| profile.var(key)));
+        let webhook = Webhook::new(destinations).with_branding(branding);
+        geo::check_coordinates(&locations).unwrap_or_else(|err| panic!("invalid locations, {err}"));
+        let models = Models::from_lookup(|key| profile.var(key), &locations)
+            .unwrap_or_else(|err| panic!("invalid forecast models, {err}"));
+        let canary = Canary::from_lookup(|key| profile.var(key))
+            .unwrap_or_else(|err| panic!("invalid canary, {err}"));
+        let interval_minutes: u64 = env_or!(
+            profile,
+            "COLLECTION_INTERVAL_MINUTES",
+            COLLECTION_INTERVAL.as_secs() / 60
+        );
+        if interval_minutes == 0 {
+            panic!("invalid collection interval, expected at least one minute");
+        }
+        let stale_issue_threshold = chrono::Duration::minutes(env_or!(
+            profile,
+            "STALE_ISSUE_ALERT_MINUTES",
+            issues::DEFAULT_STALE_MINUTES
+        ));
+        let state = AppState::new(env_or!(
+            profile,
+            "PARSE_FAILURE_LOG_LIMIT",
+            parse_failures::DEFAULT_LIMIT
+        ))
+        .with_issue_tracker(IssueTracker::new(stale_issue_threshold))
+        .with_incident_tracker(IncidentTracker::new(env_or!(
+            profile,
+            "RESOLVE_AFTER_TICKS",
+            incident::DEFAULT_RESOLVE_AFTER_TICKS
+        )))
+        .with_tick_budget(TickBudget::new(env_or!(
+            profile,
+            "TICK_BUDGET_PERCENT",
+            tick_budget::DEFAULT_BUDGET_PERCENT
+        )))
+        .with_skipped_ticks(SkippedTicks::new(env_or!(
+            profile,
+            "SKIP_ALERT_THRESHOLD",
+            skipped_ticks::DEFAULT_ALERT_THRESHOLD
+        )))
+        .with_horizon_tracker(HorizonTracker::new(
+            env_or!(profile, "HORIZON_WINDOW", horizons::DEFAULT_WINDOW),
+            env_or!(
+                profile,
+                "SHORT_FORECAST_FRACTION",
+                horizons::DEFAULT_FRACTION
+            ),
+        ))
+        .with_duplicate_tracker(duplicates::DuplicateTracker::new(
+            env_or!(profile, "DUPLICATE_GRID_TICKS", duplicates::DEFAULT_TICKS),
+            env_or!(profile, "DUPLICATE_GRID_SKIP", false),
+        ))
+        .with_pipeline(pipeline::Pipeline::new(
+            env_or!(
+                profile,
+                "PIPELINE_POINTS_CAPACITY",
+                pipeline::DEFAULT_POINTS_CAPACITY
+            ),
+            env_or!(
+                profile,
+                "PIPELINE_ERRORS_CAPACITY",
+                pipeline::DEFAULT_ERRORS_CAPACITY
+            ),
+            env_or!(profile, "PIPELINE_BATCH_SIZE", pipeline::DEFAULT_BATCH_SIZE),
+        ))
+        .with_spread(Spread::new(
+            env_or!(profile, "SPREAD_OVER_INTERVAL", false),
+            env_or!(profile, "SPREAD_ROLLING_WRITES", false),
+        ))
+        .with_maintenance(
+            Maintenance::from_lookup(|key| profile.var(key))
+                .unwrap_or_else(|err| panic!("invalid maintenance windows, {err}")),
+        )
+        .with_janitor(
+            Janitor::from_lookup(|key| profile.var(key))
+                .unwrap_or_else(|err| panic!("invalid pruned directories, {err}")),
+        )
+        .with_interval(Duration::from_secs(interval_minutes * 60))
+        .with_config(EffectiveConfig::of(
+            &profile,
+            env::vars_os().filter_map(|(key, _)| key.into_string().ok()),
+            Duration::from_secs(interval_minutes * 60),
+            &locations,
+        ))
+        .with_profile(profile.name().map(str::to_string))
+        .with_mute(mute.clone());
+        let windows: Vec<_> = state
+            .maintenance
+            .windows()
+            .iter()
+            .map(|window| format!("{:?}", window.name))
+            .collect();
+        if !windows.is_empty() {
+            let datetime = logging::datetime();
+            log_eprintln!(
+                "INFO  [{datetime}]: not alerting failures during the maintenance windows {}",
+                windows.join(", ")
+            );
+        }
+        #[cfg(feature = "archive")]
+        let state = state.with_archive(
+            archive::Archive::from_lookup(|key| profile.var(key))
+                .unwrap_or_else(|err| panic!("invalid archive, {err}")),
+        );
+        #[cfg(feature = "kafka")]
+        let state = state.with_kafka(
+            kafka::KafkaOutput::from_lookup(|key| profile.var(key))
+                .unwrap_or_else(|err| panic!("invalid kafka output, {err}")),
+        );
+        #[cfg(feature = "chaos")]
+        let state = state.with_chaos(
+            Chaos::from_lookup(|key| profile.var(key))
+                .unwrap_or_else(|err| panic!("invalid chaos config, {err}")),
+        );
+        #[cfg(feature = "nats")]
+        let (state, nats_spools) = {
+            let nats = nats_output(&profile).await;
+            let spools = nats.is_some();
+            (state.with_nats(nats), spools)
+        };
+        #[cfg(not(feature = "nats"))]
+        let nats_spools = false;
+        let circuit_breaker = CircuitBreaker::from_lookup(|key| profile.var(key))
+            .unwrap_or_else(|err| panic!("invalid circuit breaker, {err}"));
+        let spool = (nats_spools || circuit_breaker.is_some()).then(|| {
+            let path: String = env_or!(profile, "SPOOL_PATH", spool::DEFAULT_PATH.to_string());
+            let thresholds = spool::SpoolThresholds::from_lookup(|key| profile.var(key))
+                .unwrap_or_else(|err| panic!("invalid spool thresholds, {err}"));
+            spool::Spool::load(
+                path.into(),
+                env_or!(profile, "SPOOL_LIMIT", spool::DEFAULT_LIMIT),
+            )
+            .unwrap_or_else(|err| panic!("invalid spool, {err}"))
+            .with_thresholds(thresholds)
+        });
+        let state = state
+            .with_spool(spool)
+            .with_circuit_breaker(circuit_breaker);
+        let http_addr = profile.var("HTTP_ADDR").map(|addr| {
+            addr.parse::<std::net::SocketAddr>()
+                .unwrap_or_else(|err| panic!("invalid http address, {err}"))
+        });
+        let live = http_addr.map(|_| {
+            Arc::new(live::LiveFeed::new(
+                env_or!(profile, "WS_BUFFER", live::DEFAULT_BUFFER),
+                env_or!(profile, "WS_MAX_CLIENTS", live::DEFAULT_MAX_CLIENTS),
+            ))
+        });
+        let groups = LocationGroups::new(&models.targets(&locations));
+        let state_file = profile.var("STATE_FILE").map(PathBuf::from);
+        let state = Arc::new(
+            state
+                .with_live(live.clone())
+                .with_groups(groups)
+                .with_egress(egress::METER.clone())
+                .with_schema_mode(
+                    SchemaMode::from_lookup(|key| profile.var(key))
+                        .unwrap_or_else(|err| panic!("invalid schema mode, {err}")),
+                )
+                .with_retries(
+                    retry::Retries::from_lookup(|key| profile.var(key))
+                        .unwrap_or_else(|err| panic!("invalid retries, {err}")),
+                )
+                .with_processing(
+                    Processing::from_lookup(|key| profile.var(key), &locations)
+                        .unwrap_or_else(|err| panic!("invalid processing, {err}")),
+                )
+                .with_api(match &source {
+                    ForecastSource::Api { api, .. } => Some(api.clone()),
+                    _ => None,
+                })
+                .with_state_file(state_file.clone())
+                .with_read_only(read_only)
+                .with_history(history::ForecastHistory::new(env_or!(
+                    profile,
+                    "HISTORY_DEPTH",
+                    history::DEFAULT_DEPTH
+                ))),
+        );
+        if let (Some(addr), Some(live)) = (http_addr, live) {
+            let server = http::bind(addr, live, state.history.clone())
+                .unwrap_or_else(|err| panic!("cannot bind http server to {addr}, {err}"));
+            let datetime = logging::datetime();
+            log_eprintln!("INFO  [{datetime}]: serving http on {addr}");
+            tokio::spawn(server);
+        }
+        let (restored, last_config) = match &state_file {
+            Some(path) => {
+                let found = path.exists();
+                let persisted =
+                    StateFile::load(path).unwrap_or_else(|err| panic!("invalid state file, {err}"));
+                let last_config = persisted.config.clone();
+                state.restore(persisted);
+                (found, last_config)
+            }
+            None => (false, None),
+        };
+        let cache_capacity = profile.var("CACHE_CAPACITY").map(|capacity| {
+            capacity
+                .parse()
+                .unwrap_or_else(|err| panic!("invalid cache capacity, {err}"))
+        });
+        let targets = models.targets(&locations);
+        for group in SharedRequests::new(&targets).groups() {
+            let datetime = logging::datetime();
+            log_eprintln!(
+                "INFO  [{datetime}]: requesting the forecast once for {group}, they share their \
+                 coordinates"
+            );
+        }
+        let cached: Vec<_> = targets
+            .iter()
+            .map(ToString::to_string)
+            .chain([canary::NAME.to_string()])
+            .collect();
+        state.resize_caches(
+            &cached,
+            bounded_cache::capacity(cache_capacity, targets.len()),
+        );
+        let quiet_hours = QuietHours::from_lookup(|key| profile.var(key))
+            .unwrap_or_else(|err| panic!("invalid quiet hours, {err}"));
+        let notifications = Arc::new(
+            NotificationQueue::new(env_or!(profile, "NOTIFY_QUEUE_SIZE", 16))
+                .with_quiet_hours(quiet_hours)
+                .with_mute(state.mute.clone())
+                .with_clock(state.clock.clone())
+                .with_retry_budget(state.retry_budget.clone()),
+        );
+        tokio::spawn({
+            let notifications = notifications.clone();
+            let webhook = Audited::new(Delivery::new(webhook, read_only), sink.clone())
+                .with_realert_suppress(env_or!(
+                    profile,
+                    "REALERT_SUPPRESS_MINUTES",
+                    DEFAULT_REALERT_SUPPRESS_MINUTES
+                ));
+            async move {
+                let (delay, max_delay) = (Duration::from_secs(5), Duration::from_secs(5 * 60));
+                notifications.drain(&webhook, delay, max_delay).await
+            }
+        });
+        tokio::spawn({
+            let (notifications, clock) = (notifications.clone(), state.clock.clone());
+            async move {
+                let mut interval = tokio::time::interval(Duration::from_secs(60));
+                loop {
+                    interval.tick().await;
+                    notifications.release_digest(clock.now_utc());
+                    notifications.release_muted(clock.now_utc());
+                }
+            }
+        });
+
+        if let Some(name) = profile.name() {
+            let datetime = logging::datetime();
+            log_eprintln!(
+                "INFO  [{datetime}]: profile {name:?} collecting {} locations every {interval_minutes} minutes",
+                locations.len()
+            );
+        }
+
+        Collector {
+            profile,
+            locations,
+            models,
+            source,
+            sink,
+            state,
+            notifications,
+            hints,
+            canary,
+            offline: args.offline,
+            restored,
+            last_config,
+            once: match args.command {
+                Some(Command::Once { json }) => Some(json),
+                _ => None,
+            },
+        }
+    }
+
+    /// Collects until the collector is asked to stop, failing only if the schema of a bucket
+    /// does not match in strict mode.
+    async fn run(self) -> Result<(), ExitCode> {
+        let Collector {
+            profile,
+            locations,
+            models,
+            source,
+            sink,
+            state,
+            notifications,
+            hints,
+            canary,
+            offline,
+            restored,
+            last_config,
+            once,
+        } = self;
+        let targets = models.targets(&locations);
+        if *geo::GEO_FIELDS == GeoFields::Measurement {
+            write_location_points(&sink, &locations).await;
+        }
+
+        let marked = match offline {
+            true => false,
+            false => check_schema(&sink, env_or!(profile, "STRICT_SCHEMA", false)).await?,
+        };
+        let startup = Startup::detect(restored, marked);
+        let mut quiet_start = QuietStart::new(
+            startup,
+            env_or!(
+                profile,
+                "FIRST_RUN_QUIET_TICKS",
+                startup::DEFAULT_QUIET_TICKS
+            ),
+        );
+        log_startup(
+            &state,
+            &sink,
+            startup,
+            &quiet_start,
+            last_config.as_ref(),
+            env_or!(profile, "NOTIFY_CONFIG_CHANGES", false).then_some(&*notifications),
+        );
+
+        if !offline {
+            if let Some(config) = &state.config {
+                write_config_point(&sink, config).await;
+            }
+            let instance = instance::Instance::load(chrono::Utc::now())
+                .unwrap_or_else(|err| panic!("invalid instance id, {err}"))
+                .with_read_only(state.read_only);
+            tokio::spawn(register_instance(
+                sink.clone(),
+                instance,
+                targets.len(),
+                state.interval,
+            ));
+        }
+
+        if !state.janitor.dirs().is_empty() {
+            tokio::spawn(prune_dirs(state.clone()));
+        }
+
+        let gap_watchdog = GapWatchdog::from_lookup(|key| profile.var(key), state.interval)
+            .unwrap_or_else(|err| panic!("invalid gap watchdog, {err}"));
+        if let (Some(watchdog), false) = (gap_watchdog, offline) {
+            tokio::spawn(watch_gaps(
+                state.clone(),
+                sink.clone(),
+                locations.clone(),
+                notifications.clone(),
+                watchdog,
+            ));
+        }
+
+        let schema_check = SchemaCheck::from_lookup(|key| profile.var(key))
+            .unwrap_or_else(|err| panic!("invalid schema self-check, {err}"));
+        if !offline {
+            tokio::spawn(check_schemas(
+                state.clone(),
+                sink.clone(),
+                locations.clone(),
+                notifications.clone(),
+                schema_check,
+            ));
+        }
+
+        #[cfg(feature = "health-check")]
+        tokio::spawn(watch_health(
+            state.clone(),
+            sink.clone(),
+            env_or!(profile, "HEALTH_TRANSITIONS_INFLUX", false),
+        ));
+
+        let max_backoff: u64 = env_or!(profile, "MAX_BACKOFF_MINUTES", 30);
+        let mut backoff = LoopBackoff::new(state.interval, Duration::from_secs(max_backoff * 60));
+        let mut tick_id = initial_tick_id(state.tick_id.load(Ordering::Relaxed));
+        let mut interval = tokio::time::interval(backoff.interval());
+        let trigger = Arc::new(Notify::new());
+        if let Err(err) = trigger::listen(trigger.clone()) {
+            let datetime = logging::datetime();
+            log_eprintln!("WARN  [{datetime}]: cannot trigger passes via SIGUSR1, {err}");
+        }
+        #[cfg(feature = "grpc")]
+        start_grpc(&profile, &state, &trigger, &targets, &notifications).await;
+        let shutdown = trigger::shutdown().unwrap_or_else(|err| {
+            panic!("cannot handle SIGTERM, {err}");
+        });
+        tokio::pin!(shutdown);
+        let mut due = false;
+        loop {
+            let pass = tokio::select! {
+                // a due pass is ready right away, so stopping goes first
+                biased;
+                _ = &mut shutdown => break,
+                pass = next_pass(std::mem::take(&mut due), &mut interval, &trigger) => pass,
+            };
+            tick_id += 1;
+            state.tick_id.store(tick_id, Ordering::Relaxed);
+            #[cfg(feature = "grpc")]
+            state.passes.start(tick_id);
+            if pass == Pass::Manual {
+                let datetime = logging::datetime();
+                log_eprintln!(
+                    "INFO  [{datetime}] [tick #{tick_id}]: collection pass triggered manually"
+                );
+            }
+            let (started, budgeted) = (std::time::Instant::now(), backoff.interval());
+            state.skipped_ticks.write().start_tick();
+            let tick = async {
+                let canary_result = match (&canary, &source) {
+                    (Some(canary), ForecastSource::Api { client, .. }) => {
+                        Some((canary, check_canary(&state, tick_id, canary, client).await))
+                    }
+                    _ => None,
+                };
+                if let Some(target) = targets.first() {
+                    source.negotiate(*target, state.clock.now_instant()).await;
+                }
+                let report = collect(&state, tick_id, &targets, &source, &sink).await;
+                (canary_result, report)
+            };
+            let ((canary_result, mut report), spread_due) =
+                skip_while_running(&state, tick_id, &mut interval, &notifications, tick).await;
+            due = spread_due;
+            report.elapsed = started.elapsed();
+            #[cfg(feature = "grpc")]
+            state
+                .passes
+                .finish(tick_id, targets.len(), report.failed(), report.elapsed);
+
+            if let Some(next) = backoff.record(TickOutcome::of(targets.len(), &report.errors)) {
+                let datetime = logging::datetime();
+                let minutes = next.as_secs() / 60;
+                match next == state.interval {
+                    true => log_eprintln!(
+                        "INFO  [{datetime}] [tick #{tick_id}]: swat api reachable again, collecting every {minutes} minutes"
+                    ),
+                    false => log_eprintln!(
+                        "WARN  [{datetime}] [tick #{tick_id}]: swat api unreachable, backing off to collecting every {minutes} minutes"
+                    ),
+                }
+                interval = tokio::time::interval_at(tokio::time::Instant::now() + next, next);
+                due = false;
+                #[cfg(feature = "health-check")]
+                state.health.set_interval(next, backoff.backoff());
+            }
+
+            let window = state.maintenance.active(state.clock.now_utc());
+            report.maintenance = window.map(|window| window.name.clone());
+            report.ticks_skipped_total = state.skipped_ticks.read().total();
+            write_tick_stats(&state, &sink, tick_id, &report).await;
+            let canary_field = canary_result.and_then(|(canary, result)| {
+                canary.alert_field(&result, &report.errors, targets.len(), tick_id)
+            });
+            expire_snoozes(&state, tick_id);
+            let snoozed = split_snoozed(&state, &mut report.errors);
+            let quiet = quiet_start.quiet();
+            match window {
+                Some(window) => record_maintenance_errors(&state, tick_id, window, &report.errors),
+                None if quiet => {
+                    record_first_run_errors(tick_id, &report.errors, quiet_start.remaining())
+                }
+                None => handle_location_errors(
+                    &state,
+                    tick_id,
+                    report.errors.as_slice(),
+                    canary_field,
+                    hints.as_ref(),
+                    &notifications,
+                ),
+            }
+            report.errors.extend(snoozed);
+            report_stale_issues(&state, tick_id, &notifications);
+            report_short_forecasts(&state, tick_id, &notifications);
+            report_point_rejections(&state, tick_id, &notifications);
+            report_spool(&state, tick_id, &notifications);
+            report_egress(&state, tick_id, &notifications);
+            if let Some(path) = &state.state_file {
+                save_state(&state, tick_id, path);
+            }
+            report_tick_duration(&state, tick_id, report.elapsed, budgeted, &notifications);
+            #[cfg(feature = "health-check")]
+            state
+                .health
+                .set_delivery_failures(notifications.delivery_failures());
+            report.notifications = notifications.take_actions();
+
+            if let Some(json) = once {
+                if !notifications.flushed(ONCE_FLUSH_TIMEOUT).await {
+                    let datetime = logging::datetime();
+                    log_eprintln!(
+                        "WARN  [{datetime}] [tick #{tick_id}]: notifications still queued after \
+                         {}s, exiting anyway",
+                        ONCE_FLUSH_TIMEOUT.as_secs()
+                    );
+                }
+                shut_down(&state).await;
+                match json {
+                    true => print!("{}", report.json()),
+                    false => print!("{}", report.text()),
+                }
+                return match report.errors.is_empty() {
+                    true => Ok(()),
+                    false => Err(ExitCode::FAILURE),
+                };
+            }
+        }
+
+        shut_down(&state).await;
+        Ok(())
+    }
+}
+
+/// Runs the `tick` to its end, skipping every time the `interval` fires meanwhile instead of
+/// starting an overlapping tick.
+///
+/// Like the [`TickBudget`], the time waited for the phases of a spread tick does not count as
+/// running. A spread tick ends close to the next fire of the `interval`, so a fire before it has
+/// run a whole interval on its own is not skipped, instead the returned flag has the next pass
+/// start right after it.
+async fn skip_while_running<T>(
+    state: &AppState,
+    tick_id: u64,
+    interval: &mut tokio::time::Interval,
+    notifications: &NotificationQueue,
+    tick: impl std::future::Future<Output = T>,
+) -> (T, bool) {
+    let started = tokio::time::Instant::now();
+    let mut due = false;
+    tokio::pin!(tick);
+    loop {
+        tokio::select! {
+            biased;
+            output = &mut tick => return (output, due),
+            _ = interval.tick() => {
+                let running = started
+                    .elapsed()
+                    .saturating_sub(state.tick_budget.read().waited());
+                if !due && running < interval.period() {
+                    due = true;
+                    continue;
+                }
+                let datetime = logging::datetime();
+                log_eprintln!(
+                    "WARN  [{datetime}] [tick #{tick_id}]: still running after {:.1}s, skipping the next tick",
+                    started.elapsed().as_secs_f64()
+                );
+                let warning = state.skipped_ticks.write().skip();
+                if let Some(message) = warning {
+                    log_eprintln!("WARN  [{datetime}] [tick #{tick_id}]: {message}");
+                    notifications.push(Notification::Warning(message));
+                }
+            }
+        }
+    }
+}
+
+/// Waits for the next pass, which is scheduled right away if it is `due` already.
+async fn next_pass(due: bool, interval: &mut tokio::time::Interval, trigger: &Notify) -> Pass {
+    match due {
+        true => Pass::Scheduled,
+        false => trigger::next(interval, trigger).await,
+    }
+}
+
+/// Flushes the outputs before the collector stops.
+#[cfg_attr(not(any(feature = "kafka", feature = "nats")), allow(unused_variables))]
+async fn shut_down(state: &AppState) {
+    let datetime = logging::datetime();
+    log_eprintln!("INFO  [{datetime}]: shutting down");
+
+    #[cfg(feature = "kafka")]
+    if let Some(kafka) = &state.kafka {
+        // the runtime has nothing else to do anymore, so blocking it is fine
+        if let Err(err) = kafka.flush(Duration::from_secs(5)) {
+            log_eprintln!("WARN  [{datetime}]: could not flush kafka producer, {err}");
+        }
+    }
+
+    #[cfg(feature = "nats")]
+    if let Some(nats) = &state.nats {
+        if let Err(err) = nats.close().await {
+            log_eprintln!("WARN  [{datetime}]: could not close nats connection, {err}");
+        }
+    }
+}
+
+/// Writes the statistics of the tick of the `report` into the default bucket, along with the
+/// request latencies of the hour that ended.
+#[cfg_attr(not(feature = "health-check"), allow(unused_variables))]
+async fn write_tick_stats(state: &AppState, sink: &Sink, tick_id: u64, report: &TickReport<'_>) {
+    let datetime = logging::datetime();
+    let points = tick_stats::data_point(report).map(|point| vec![point]);
+    #[cfg(feature = "health-check")]
+    let points = points.and_then(|mut points| {
+        if let Some(window) = state.health.take_latency_window() {
+            points.extend(window.data_points()?);
+        }
+        Ok(points)
+    });
+    let points = match points {
+        Ok(points) => points,
+        Err(err) => {
+            return log_eprintln!(
+                "ERROR [{datetime}] [tick #{tick_id}]: invalid tick statistics, {err}"
+            )
+        }
+    };
+    if let Err(err) = sink.write(sink.default_bucket(), points).await {
+        log_eprintln!(
+            "ERROR [{datetime}] [tick #{tick_id}]: writing the tick statistics failed, {err}"
+        );
+    }
+}
+
+/// Starts the configured health mechanisms, shared by the profiles of the `collectors`.
+///
+/// An unusable socket or file fails the startup if `REQUIRE_HEALTH` is set, otherwise the
+/// collector keeps running without health check and a warning is sent.
+#[cfg(feature = "health-check")]
+fn start_health_check(collectors: &[Collector]) -> Result<(), ExitCode> {
+    let require_health: bool = env_or!("REQUIRE_HEALTH", false);
+    let states: Vec<_> = collectors
+        .iter()
+        .map(|collector| collector.state.clone())
+        .collect();
+    let notifications: Vec<_> = collectors
+        .iter()
+        .map(|collector| collector.notifications.clone())
+        .collect();
+    let warn = move |message: String| {
+        for notifications in &notifications {
+            notifications.push(Notification::Warning(message.clone()));
+        }
+    };
+    let datetime = logging::datetime();
+    let listener = health_check::listen();
+    if states.iter().any(|state| state.profile.is_some()) {
+        tokio::spawn(health_check::write_profiles_file(states.clone()));
+    }
+    let listener = match listener {
+        Ok(Some(listener)) => listener,
+        Ok(None) => return Ok(()),
+        Err(err) if require_health => {
+            log_eprintln!("ERROR [{datetime}]: {err}, \"REQUIRE_HEALTH\" is set, exiting");
+            return Err(ExitCode::FAILURE);
+        }
+        Err(err) => {
+            log_eprintln!("ERROR [{datetime}]: {err}, continuing without health check");
+            warn(format!("Health check is unavailable, {err}"));
+            return Ok(());
+        }
+    };
+
+    tokio::spawn(async move {
+        if let Err(err) = health_check::serve(listener, states).await {
+            let datetime = logging::datetime();
+            log_eprintln!("ERROR [{datetime}]: health check stopped, {err}");
+            warn(format!("Health check stopped, {err}"));
+        }
+    });
+    Ok(())
+}
+
+/// Serves the gRPC service on `GRPC_ADDR`, if set.
+///
+/// An address that cannot be bound fails the startup, as the service was asked for explicitly.
+#[cfg(feature = "grpc")]
+async fn start_grpc(
+    profile: &Profile,
+    state: &Arc<AppState>,
+    trigger: &Arc<Notify>,
+    targets: &[Target<'_>],
+    notifications: &Arc<NotificationQueue>,
+) {
+    let Some(config) = grpc::GrpcConfig::from_lookup(|key| profile.var(key))
+        .unwrap_or_else(|err| panic!("invalid grpc server, {err}"))
+    else {
+        return;
+    };
+    let listener = tokio::net::TcpListener::bind(config.addr)
+        .await
+        .unwrap_or_else(|err| panic!("cannot bind grpc server to {}, {err}", config.addr));
+    let service = grpc::CollectorService::new(
+        state.clone(),
+        trigger.clone(),
+        targets.iter().map(ToString::to_string).collect(),
+        state.interval,
+    );
+    let datetime = logging::datetime();
+    log_eprintln!("INFO  [{datetime}]: serving grpc on {}", config.addr);
+
+    let notifications = notifications.clone();
+    tokio::spawn(async move {
+        if let Err(err) = grpc::serve(listener, config.token, service).await {
+            let datetime = logging::datetime();
+            log_eprintln!("ERROR [{datetime}]: grpc server stopped, {err}");
+            notifications.push(Notification::Warning(format!("gRPC server stopped, {err}")));
+        }
+    });
+}
+
+/// Prunes the directories of the janitor once an hour, logging every file removed.
+///
+/// Files and directories that cannot be removed are only warned about, they are retried with
+/// the next run.
+async fn prune_dirs(state: Arc<AppState>) {
+    let mut interval = tokio::time::interval(janitor::INTERVAL);
+    loop {
+        interval.tick().await;
+        for (dir, pruned) in state.janitor.run(state.clock.now_system()) {
+            let datetime = logging::datetime();
+            let pruned = match pruned {
+                Ok(pruned) => pruned,
+                Err(err) => {
+                    log_eprintln!("WARN  [{datetime}]: could not prune {dir:?}, {err}");
+                    continue;
+                }
+            };
+            for path in &pruned.removed {
+                log_eprintln!("INFO  [{datetime}]: pruned {path:?}");
+            }
+            for (path, err) in &pruned.failed {
+                log_eprintln!("WARN  [{datetime}]: could not prune {path:?}, {err}");
+            }
+        }
+    }
+}
+
+/// Checks InfluxDB for locations without points every `watchdog.every`, alerting them unless
+/// in a maintenance window. Failures of the check itself only warn.
+async fn watch_gaps(
+    state: Arc<AppState>,
+    sink: Arc<Sink>,
+    locations: Vec<locations::Location>,
+    notifications: Arc<NotificationQueue>,
+    watchdog: GapWatchdog,
+) {
+    // the first check once the collector had a window to write its points
+    let start = tokio::time::Instant::now() + watchdog.every;
+    let mut interval = tokio::time::interval_at(start, watchdog.every);
+    loop {
+        interval.tick().await;
+        let datetime = logging::datetime();
+        if state.maintenance.active(state.clock.now_utc()).is_some() {
+            continue;
+        }
+        match watchdog.check(&sink, &locations, &names::NAMES).await {
+            Ok(missing) if missing.is_empty() => (),
+            Ok(missing) => {
+                let field = watchdog.alert_field(&missing);
+                log_eprintln!(
+                    "ERROR [{datetime}]: {} locations without points in InfluxDB",
+                    missing.len()
+                );
+                notifications.push(Notification::Alert(vec![field]));
+            }
+            Err(err) => {
+                log_eprintln!("WARN  [{datetime}]: gap watchdog could not query InfluxDB, {err}")
+            }
+        }
+    }
+}
+
+/// Compares the points per horizon with the `forecast` points nightly while both are written,
+/// alerting the issues whose representations differ. Failures of the check itself only warn.
+async fn check_schemas(
+    state: Arc<AppState>,
+    sink: Arc<Sink>,
+    locations: Vec<locations::Location>,
+    notifications: Arc<NotificationQueue>,
+    check: SchemaCheck,
+) {
+    loop {
+        let now = chrono::Utc::now();
+        let wait = (check.next_run(now) - now).to_std().unwrap_or_default();
+        tokio::time::sleep(wait).await;
+        let datetime = logging::datetime();
+        if *state.schema_mode.read() != SchemaMode::Dual {
+            continue;
+        }
+        match check
+            .check(&sink, &locations, &names::NAMES, *fields::FIELD_LIMIT)
+            .await
+        {
+            Ok(mismatches) if mismatches.is_empty() => {
+                log_eprintln!("INFO  [{datetime}]: schema self-check found no mismatches")
+            }
+            Ok(mismatches) => {
+                let field = check.alert_field(&mismatches);
+                log_eprintln!(
+                    "ERROR [{datetime}]: {} issues differ between the schemas",
+                    mismatches.len()
+                );
+                notifications.push(Notification::Alert(vec![field]));
+            }
+            Err(err) => {
+                log_eprintln!(
+                    "WARN  [{datetime}]: schema self-check could not query InfluxDB, {err}"
+                )
+            }
+        }
+    }
+}
+
+/// Evaluates the health once a minute, logging its transitions and, if `write_transitions`,
+/// writing them into the `health_transitions` measurement.
+#[cfg(feature = "health-check")]
+async fn watch_health(state: Arc<AppState>, sink: Arc<Sink>, write_transitions: bool) {
+    let mut interval = tokio::time::interval(Duration::from_secs(60));
+    loop {
+        interval.tick().await;
+        let Some(transition) = state.health.evaluate_transition() else {
+            continue;
+        };
+
+        let datetime = logging::datetime();
+        let reason = &transition.reason;
+        match transition.healthy {
+            true => log_eprintln!("INFO  [{datetime}]: collector became healthy, {reason}"),
+            false => log_eprintln!("WARN  [{datetime}]: collector became unhealthy, {reason}"),
+        }
+
+        if write_transitions {
+            if let Err(err) = write_transition(&sink, &transition).await {
+                log_eprintln!("ERROR [{datetime}]: could not write health transition, {err}");
+            }
+        }
+    }
+}
+
+#[cfg(feature = "health-check")]
+async fn write_transition(
+    sink: &Sink,
+    transition: &health_check::Transition,
+) -> Result<(), HandleLocationError> {
+    let timestamp = chrono::DateTime::<chrono::Utc>::from(transition.at).timestamp();
+    let data_point = DataPoint::builder("health_transitions")
+        .timestamp(timestamp)
+        .field("healthy", transition.healthy)
+        .field("reason", transition.reason.clone())
+        .build()?;
+    let bucket = sink.default_bucket();
+    sink.write(bucket, vec![data_point])
+        .await
+        .map_err(|error| HandleLocationError::WritePoints {
+            bucket: bucket.to_string(),
+            error: Arc::new(error),
+        })
+}
+
+/// Creates every bucket written into that does not exist yet, returning those that could not
+/// be looked up or created.
+async fn init_buckets(
+    client: &influxdb2::Client,
+    org: String,
+    buckets: &Buckets,
+) -> Vec<(String, InitBucketError)> {
+    let mut failed = Vec::new();
+    for bucket in buckets.names() {
+        if let Err(err) = init_bucket(client, &org, bucket).await {
+            failed.push((bucket.to_string(), err));
+        }
+    }
+    failed
+}
+
+async fn init_bucket(
+    client: &influxdb2::Client,
+    org: &str,
+    bucket: &str,
+) -> Result<(), InitBucketError> {
+    let existing = client
+        .list_buckets(Some(ListBucketsRequest {
+            name: bucket.to_string().into(),
+            ..Default::default()
+        }))
+        .await
+        .map_err(InitBucketError::List)?;
+    if !existing.buckets.is_empty() {
+        return Ok(());
+    }
+
+    let org_id = client
+        .list_organizations(ListOrganizationRequest {
+            org: org.to_string().into(),
+            ..Default::default()
+        })
+        .await
+        .map_err(InitBucketError::Organization)?
+        .orgs
+        .into_iter()
+        .next()
+        .and_then(|org| org.id)
+        .ok_or_else(|| InitBucketError::UnknownOrganization(org.to_string()))?;
+
+    client
+        .create_bucket(Some(PostBucketRequest::new(org_id, bucket.to_owned())))
+        .await
+        .map_err(InitBucketError::Create)
+}
+
+fn destinations(profile: &Profile) -> Vec<Destination> {
+    match profile.var("DISCORD_WEBHOOKS") {
+        Some(webhooks) => Destination::parse_list(&webhooks)
+            .unwrap_or_else(|err| panic!("invalid \"DISCORD_WEBHOOKS\", {err}")),
+        None => {
+            let webhook_token = env!(profile, "DISCORD_WEBHOOK_TOKEN");
+            let webhook_id = env!(profile, "DISCORD_WEBHOOK_ID");
+            let webhook_id = Id::from_str(&webhook_id).unwrap();
+            vec![Destination::new(webhook_id, webhook_token, Severity::Info)]
+        }
+    }
+}
+
+/// Checks the schema marker of every bucket, writing the missing ones, returns whether any
+/// bucket held a marker already.
+///
+/// Buckets holding points of an older schema are warned about, and with `strict` the collector
+/// does not start.
+async fn check_schema(sink: &Sink, strict: bool) -> Result<bool, ExitCode> {
+    let mut marked = false;
+    for bucket in sink.bucket_names() {
+        let datetime = logging::datetime();
+        let records = match sink.query(schema::query(bucket)).await {
+            Ok(records) => records,
+            Err(err) => {
+                log_eprintln!(
+                    "WARN  [{datetime}]: could not query the schema marker of bucket \
+                     {bucket:?}, {err}"
+                );
+                // a bucket that cannot be queried is no evidence of a first run
+                marked = true;
+                continue;
+            }
+        };
+        let recorded = schema::Schema::of(schema::recorded(&records));
+        marked |= recorded != schema::Schema::Absent;
+        match recorded {
+            schema::Schema::Current => (),
+            schema::Schema::Absent => {
+                let written = match schema::marker(chrono::Utc::now()) {
+                    Ok(marker) => sink
+                        .write(bucket, vec![marker])
+                        .await
+                        .map_err(|e| e.to_string()),
+                    Err(err) => Err(err.to_string()),
+                };
+                match written {
+                    Ok(()) => log_eprintln!(
+                        "INFO  [{datetime}]: wrote schema version {} marker into bucket {bucket:?}",
+                        schema::SCHEMA_VERSION
+                    ),
+                    Err(err) => log_eprintln!(
+                        "WARN  [{datetime}]: could not write the schema marker into bucket \
+                         {bucket:?}, {err}"
+                    ),
+                }
+            }
+            schema::Schema::Older(version) if strict => {
+                let hint = schema::migration_hint(bucket, version);
+                log_eprintln!("ERROR [{datetime}]: {hint}, not starting as STRICT_SCHEMA is set");
+                return Err(ExitCode::FAILURE);
+            }
+            schema::Schema::Older(version) => {
+                let hint = schema::migration_hint(bucket, version);
+                log_eprintln!("WARN  [{datetime}]: {hint}");
+            }
+            schema::Schema::Newer(version) => log_eprintln!(
+                "WARN  [{datetime}]: bucket {bucket:?} holds points of schema version {version}, \
+                 newer than version {} this collector writes",
+                schema::SCHEMA_VERSION
+            ),
+        }
+    }
+    Ok(marked)
+}
+
+/// Logs what a first run collects where, or what changed since the last run, also notifying
+/// the changes if `notifications` are given.
+fn log_startup(
+    state: &AppState,
+    sink: &Sink,
+    startup: Startup,
+    quiet_start: &QuietStart,
+    last_config: Option<&EffectiveConfig>,
+    notifications: Option<&NotificationQueue>,
+) {
+    let Some(config) = &state.config else {
+        return;
+    };
+    let datetime = logging::datetime();
+    let lines = match (startup, last_config) {
+        (Startup::FirstRun, _) => {
+            let buckets: Vec<_> = sink.bucket_names().into_iter().collect();
+            startup::onboarding(config, &buckets, quiet_start.remaining())
+        }
+        (Startup::Recovering, Some(last_config)) => {
+            let changes = startup::changes(last_config, config);
+            if changes.is_empty() {
+                log_eprintln!(
+                    "INFO  [{datetime}]: configuration unchanged since last run, config hash {}",
+                    config.hash()
+                );
+                return;
+            }
+            if let Some(notifications) = notifications {
+                notifications.push(Notification::Warning(changes.join("\n")));
+            }
+            changes
+        }
+        (Startup::Recovering, None) => {
+            vec!["started before, no configuration of the last run to compare with".to_string()]
+        }
+    };
+    for line in lines {
+        log_eprintln!("INFO  [{datetime}]: {line}");
+    }
+}
+
+/// Writes the effective `config` into every bucket, so it can be queried next to the data.
+async fn write_config_point(sink: &Sink, config: &EffectiveConfig) {
+    let datetime = logging::datetime();
+    for bucket in sink.bucket_names() {
+        let written = match config.point(chrono::Utc::now()) {
+            Ok(point) => sink
+                .write(bucket, vec![point])
+                .await
+                .map_err(|e| e.to_string()),
+            Err(err) => Err(err.to_string()),
+        };
+        match written {
+            Ok(()) => log_eprintln!(
+                "INFO  [{datetime}]: wrote configuration {} into bucket {bucket:?}",
+                config.hash()
+            ),
+            Err(err) => log_eprintln!(
+                "WARN  [{datetime}]: could not write the configuration into bucket {bucket:?}, {err}"
+            ),
+        }
+    }
+}
+
+/// Writes the coordinates of every location into the `locations` measurement of its bucket.
+async fn write_location_points(sink: &Sink, locations: &[locations::Location]) {
+    let datetime = logging::datetime();
+    let mut batches: BTreeMap<&str, Vec<DataPoint>> = BTreeMap::new();
+    for location in locations {
+        match geo::location_point(location) {
+            Ok(point) => batches
+                .entry(sink.bucket(location))
+                .or_default()
+                .push(point),
+            Err(err) => log_eprintln!("WARN  [{datetime}]: {err}"),
+        }
+    }
+    for (bucket, points) in batches {
+        if let Err(err) = sink.write(bucket, points).await {
+            log_eprintln!(
+                "WARN  [{datetime}]: writing the locations into bucket {bucket:?} failed, {err}"
+            );
+        }
+    }
+}
+
+/// Prints the gaps persisted in the `STATE_FILE` as a table or as JSON.
+fn print_gaps(json: bool) -> ExitCode {
+    let Ok(path) = env::var("STATE_FILE") else {
+        eprintln!("\"STATE_FILE\" is required to read the gaps from");
+        return ExitCode::FAILURE;
+    };
+    let persisted = match StateFile::load(std::path::Path::new(&path)) {
+        Ok(persisted) => persisted,
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let rows = gaps::rows(&persisted.gaps, chrono::Utc::now());
+    match json {
+        true => println!(
+            "{}",
+            serde_json::to_string_pretty(&rows).expect("rows serialize")
+        ),
+        false => print!("{}", gaps::table(&rows)),
+    }
+    ExitCode::SUCCESS
+}
+
+/// Exports the state persisted in the `STATE_FILE` into the file at `out`.
+fn export_state(out: &std::path::Path) -> ExitCode {
+    let Ok(path) = env::var("STATE_FILE") else {
+        eprintln!("\"STATE_FILE\" is required to export the state from");
+        return ExitCode::FAILURE;
+    };
+    let exported = StateFile::load(std::path::Path::new(&path))
+        .map(|persisted| StateExport::new(persisted, chrono::Utc::now()))
+        .and_then(|export| export.write(out).map(|()| export));
+    match exported {
+        Ok(export) => {
+            println!(
+                "exported the state of {} targets to {out:?}",
+                export.state.targets().len()
+            );
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("{err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Imports the state exported into the file at `file` as the `STATE_FILE`, reporting the
+/// targets not collected with the configured locations and models.
+fn import_state(file: &std::path::Path) -> ExitCode {
+    let Ok(path) = env::var("STATE_FILE") else {
+        eprintln!("\"STATE_FILE\" is required to import the state into");
+        return ExitCode::FAILURE;
+    };
+    let export = match StateExport::read(file) {
+        Ok(export) => export,
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let locations = *locations::CONFIGURED;
+    let models = Models::from_lookup(|key| env::var(key).ok(), locations)
+        .unwrap_or_else(|err| panic!("invalid forecast models, {err}"));
+    let known: Vec<_> = models
+        .targets(locations)
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    let unknown = export.state.unknown_targets(&known);
+    if !unknown.is_empty() {
+        eprintln!(
+            "imported the state of {} targets not configured: {}",
+            unknown.len(),
+            unknown.join(", ")
+        );
+    }
+    if let Err(err) = export.state.save(std::path::Path::new(&path)) {
+        eprintln!("{err}");
+        return ExitCode::FAILURE;
+    }
+    println!(
+        "imported the state of {} targets exported at {} into {path:?}",
+        export.state.targets().len(),
+        export.exported.format("%Y-%m-%d %H:%M")
+    );
+    ExitCode::SUCCESS
+}
+
+/// Prints the collectors registered in the last day as a table or as JSON.
+async fn print_instances(sink: &Sink, json: bool) -> ExitCode {
+    let records = match sink.query(instance::query(sink.default_bucket())).await {
+        Ok(records) => records,
+        Err(err) => {
+            eprintln!("could not query instances, {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let instances = instance::parse(&records, chrono::Utc::now());
+    match json {
+        true => println!(
+            "{}",
+            serde_json::to_string_pretty(&instances).expect("instances serialize")
+        ),
+        false => print!("{}", instance::table(&instances)),
+    }
+    ExitCode::SUCCESS
+}
+
+/// Registers the collector in InfluxDB now and then every [`instance::REGISTER_INTERVAL`].
+async fn register_instance(
+    sink: Arc<Sink>,
+    instance: instance::Instance,
+    locations: usize,
+    interval: Duration,
+) {
+    let mut register = tokio::time::interval(instance::REGISTER_INTERVAL);
+    loop {
+        register.tick().await;
+        let now = chrono::Utc::now();
+        let result = match instance.data_point(locations, interval, now) {
+            Ok(point) => sink
+                .write(sink.default_bucket(), vec![point])
+                .await
+                .map_err(|err| err.to_string()),
+            Err(err) => Err(err.to_string()),
+        };
+        if let Err(err) = result {
+            let datetime = logging::LOG_TIME.format(now);
+            log_eprintln!("WARN  [{datetime}]: could not register instance, {err}");
+        }
+    }
+}
+
+/// Starts counting ticks after the `last` tick id kept in the state file or at the current
+/// epoch minute, whichever is later. With a tick every other minute the ids keep increasing
+/// across restarts, and manual passes running ahead of the epoch minute are not repeated.
+fn initial_tick_id(last: u64) -> u64 {
+    last.max(chrono::Utc::now().timestamp() as u64 / 60)
+}
+
+/// Runs a single tick, collecting the forecasts of all `targets`, the locations with each of
+/// their models.
+///
+/// The forecasts are fetched, written and their errors recorded by stages connected through
+/// the bounded channels of the [pipeline](pipeline::Pipeline).
+///
+/// Everything logged or alerted for the tick carries its `tick_id`.
+async fn collect<'l>(
+    state: &AppState,
+    tick_id: u64,
+    targets: &[Target<'l>],
+    source: &ForecastSource,
+    sink: &Sink,
+) -> TickReport<'l> {
+    let (started, started_at) = (state.clock.now_instant(), state.clock.now_utc());
+    state.parse_failures.write().start_tick();
+    state.tick_budget.write().start_tick();
+    state.pipeline.start_tick();
+    (state.retry_budget).refill(state.retries.budget(targets.len()));
+    let recorder = TickRecorder::default();
+    let (points, built) = state.pipeline.points.channel();
+    let (failures, failed) = state.pipeline.errors.channel();
+    let ((), (), errors) = tokio::join!(
+        fetch_stage(
+            state,
+            tick_id,
+            targets,
+            source,
+            &recorder,
+            points,
+            failures.clone()
+        ),
+        write_stage(state, tick_id, sink, &recorder, built, failures),
+        notify_stage(state, tick_id, failed, targets.len()),
+    );
+    record_gaps(state, targets, &errors);
+    report_duplicates(state, tick_id);
+
+    #[cfg(feature = "health-check")]
+    {
+        // nothing was written, so check InfluxDB separately to keep the sink signal fresh
+        if errors.len() == targets.len() {
+            ping_sink(state, tick_id, sink).await;
+        }
+        let evictions = state.issues.read().evictions();
+        state.health.set_cache_evictions("issue times", evictions);
+        state.health.tick();
+    }
+
+    #[cfg(feature = "archive")]
+    rotate_archive(state, tick_id).await;
+    #[cfg(feature = "nats")]
+    publish_nats(state, tick_id).await;
+
+    let retry_budget = state.retry_budget.usage();
+    if retry_budget.denied > 0 {
+        let datetime = logging::datetime();
+        log_eprintln!(
+            "WARN  [{datetime}] [tick #{tick_id}]: retry budget of {} retries exhausted, {} \
+             retries denied, set RETRY_BUDGET to grant more",
+            retry_budget.limit,
+            retry_budget.denied
+        );
+    }
+
+    let elapsed = state.clock.now_instant().duration_since(started);
+    let mut report = recorder.report(tick_id, started_at, elapsed, targets, errors);
+    report.profile = state.profile.clone();
+    report.read_only = state.read_only;
+    report.retry_budget = retry_budget;
+    report
+}
+
+/// Fetches the forecasts of the `targets` one after another and builds their points, targets
+/// at the same coordinates share a request.
+async fn fetch_stage<'l>(
+    state: &AppState,
+    tick_id: u64,
+    targets: &[Target<'l>],
+    source: &ForecastSource,
+    recorder: &TickRecorder,
+    points: pipeline::Sender<PendingPoint<'l>>,
+    failures: pipeline::Sender<(Target<'l>, HandleLocationError)>,
+) {
+    let tick_started = tokio::time::Instant::now();
+    let shared = SharedRequests::new(targets);
+    let mut requested = BTreeMap::new();
+    for (i, target) in targets.iter().copied().enumerate() {
+        let phase = tick_started + state.spread.offset(i, targets.len(), state.interval);
+        if let Some(wait) = phase.checked_duration_since(tokio::time::Instant::now()) {
+            state.tick_budget.write().wait(wait);
+            tokio::time::sleep_until(phase).await;
+        }
+
+        let started = state.clock.now_instant();
+        let forecast =
+            fetch_forecast(state, tick_id, targets, i, source, &shared, &mut requested).await;
+        let handled =
+            forecast.and_then(|forecast| handle_location(state, tick_id, target, forecast));
+        let duration = state.clock.now_instant().duration_since(started);
+        state
+            .tick_budget
+            .write()
+            .record(&target.to_string(), duration);
+        recorder.duration(&target.to_string(), duration);
+        match handled {
+            Ok(point) => {
+                let duplicate = state
+                    .duplicates
+                    .write()
+                    .record(&target.to_string(), &point.forecast_hash);
+                match duplicate {
+                    Some(canonical) => {
+                        skip_duplicate(state, tick_id, target, &canonical);
+                        recorder.outcome(&target.to_string(), Outcome::Duplicate { canonical });
+                    }
+                    None => points.send(point).await,
+                }
+            }
+            Err(err) => failures.send((target, err)).await,
+        }
+
+        // a spread tick takes the whole interval, the loop is still alive meanwhile
+        #[cfg(feature = "health-check")]
+        if state.spread.enabled {
+            state.health.tick();
+        }
+    }
+}
+
+/// Skips writing the point of `target` as it is identical to the one of `canonical`.
+#[cfg_attr(not(feature = "health-check"), allow(unused_variables))]
+fn skip_duplicate(state: &AppState, tick_id: u64, target: Target, canonical: &str) {
+    #[cfg(feature = "health-check")]
+    state.health.clear_error(&target.to_string());
+    let datetime = logging::datetime();
+    log_eprintln!(
+        "INFO  [{datetime}] [tick #{tick_id}]: location {:?} shares the grid cell of \
+         {canonical:?}, skipped it",
+        target.to_string()
+    );
+}
+
+/// Warns about the locations that just turned out to share a grid cell of the swat api.
+fn report_duplicates(state: &AppState, tick_id: u64) {
+    let (confirmed, ticks) = {
+        let mut duplicates = state.duplicates.write();
+        (duplicates.end_tick(), duplicates.ticks())
+    };
+    let datetime = logging::datetime();
+    for cluster in confirmed {
+        log_eprintln!(
+            "WARN  [{datetime}] [tick #{tick_id}]: locations {cluster:?} returned identical \
+             forecasts for {ticks} ticks, they share a grid cell of the swat api"
+        );
+    }
+}
+
+/// Writes the built `points` with one write per bucket, a bucket holding the batch size of
+/// points already is written right away.
+async fn write_stage<'l>(
+    state: &AppState,
+    tick_id: u64,
+    sink: &Sink,
+    recorder: &TickRecorder,
+    mut points: pipeline::Receiver<PendingPoint<'l>>,
+    failures: pipeline::Sender<(Target<'l>, HandleLocationError)>,
+) {
+    let mut batches: BTreeMap<&str, Batch> = BTreeMap::new();
+    while let Some(point) = points.recv().await {
+        let bucket = sink.bucket(point.target.location);
+        let batch = batches.entry(bucket).or_default();
+        batch.push(point);
+        if batch.len() >= state.spread.batch_size(state.pipeline.batch_size) {
+            let batch = std::mem::take(batch);
+            write_batch(state, tick_id, sink, recorder, bucket, batch, &failures).await;
+        }
+    }
+
+    // a failing bucket only fails its own locations
+    for (bucket, batch) in batches {
+        if !batch.is_empty() {
+            write_batch(state, tick_id, sink, recorder, bucket, batch, &failures).await;
+        }
+    }
+}
+
+/// Logs and records the errors of the other stages, returns them for alerting.
+async fn notify_stage<'l>(
+    state: &AppState,
+    tick_id: u64,
+    mut failed: pipeline::Receiver<(Target<'l>, HandleLocationError)>,
+    targets: usize,
+) -> Vec<(Target<'l>, HandleLocationError)> {
+    let mut errors = Vec::with_capacity(targets);
+    while let Some((target, error)) = failed.recv().await {
+        handle_location_error(state, tick_id, target, error, &mut errors);
+    }
+    errors
+}
+
+/// Opens and closes the gaps in the data of the `targets` by the `errors` of the tick.
+fn record_gaps(state: &AppState, targets: &[Target], errors: &[(Target, HandleLocationError)]) {
+    let now = state.clock.now_utc();
+    let failed: Vec<_> = errors
+        .iter()
+        .map(|(target, _)| target.to_string())
+        .collect();
+    let mut gaps = state.gaps.write();
+    for target in targets.iter().map(ToString::to_string) {
+        match failed.contains(&target) {
+            true => gaps.failure(&target, now),
+            false => {
+                gaps.success(&target, now);
+            }
+        }
+    }
+    gaps.prune(now);
+}
+
+/// Buffers the fetched `forecast` in the archive, if any, failing to archive it is only warned
+/// about.
+#[cfg(feature = "archive")]
+fn archive_forecast(
+    state: &AppState,
+    tick_id: u64,
+    target: Target,
+    forecast: &locations::Forecast,
+) {
+    let Some(archive) = &state.archive else {
+        return;
+    };
+    let row = archive::ArchivedForecast::new(target, forecast, state.clock.now_utc());
+    if let Err(err) = archive.lock().push(row) {
+        let datetime = logging::datetime();
+        log_eprintln!(
+            "WARN  [{datetime}] [tick #{tick_id}]: could not archive forecasts, dropped them, {err}"
+        );
+    }
+}
+
+/// Publishes the fetched `forecast` to Kafka, if configured, without waiting for the brokers.
+#[cfg(feature = "kafka")]
+fn publish_forecast(
+    state: &AppState,
+    tick_id: u64,
+    target: Target,
+    forecast: &locations::Forecast,
+) {
+    let Some(kafka) = &state.kafka else {
+        return;
+    };
+    let event = event::ForecastEvent::new(target, forecast, state.clock.now_utc(), tick_id);
+    if let Err(err) = kafka.publish(&event) {
+        let datetime = logging::datetime();
+        log_eprintln!(
+            "WARN  [{datetime}] [tick #{tick_id}]: could not publish forecast of {target} to kafka, {} dropped so far, {err}",
+            kafka.dropped()
+        );
+    }
+}
+
+/// Connects to NATS if `NATS_URL` is set, failing the startup if that is not possible.
+#[cfg(feature = "nats")]
+async fn nats_output(profile: &Profile) -> Option<nats::NatsOutput> {
+    let config = nats::NatsConfig::from_lookup(|key| profile.var(key))
+        .unwrap_or_else(|err| panic!("invalid nats output, {err}"))?;
+    let publisher = nats::JetStreamPublisher::connect(&config)
+        .await
+        .unwrap_or_else(|err| panic!("invalid nats output, {err}"));
+    Some(nats::NatsOutput::new(config, Box::new(publisher)))
+}
+
+/// Queues the fetched `forecast` for JetStream, if configured.
+#[cfg(feature = "nats")]
+fn queue_forecast(state: &AppState, tick_id: u64, target: Target, forecast: &locations::Forecast) {
+    let Some(nats) = &state.nats else {
+        return;
+    };
+    let event = event::ForecastEvent::new(target, forecast, state.clock.now_utc(), tick_id);
+    if let Err(err) = nats.queue(&event) {
+        let datetime = logging::datetime();
+        log_eprintln!(
+            "WARN  [{datetime}] [tick #{tick_id}]: could not queue forecast of {target} for nats, {err}"
+        );
+    }
+}
+
+/// Publishes the forecasts of the tick and the spooled ones to JetStream, spooling those that
+/// are not acked.
+#[cfg(feature = "nats")]
+async fn publish_nats(state: &AppState, tick_id: u64) {
+    let (Some(nats), Some(spool)) = (&state.nats, &state.spool) else {
+        return;
+    };
+    let datetime = logging::datetime();
+    // the spool is released while publishing, the lock must not be held across an await
+    let spooled = spool.lock().take("nats").unwrap_or_else(|err| {
+        log_eprintln!("WARN  [{datetime}] [tick #{tick_id}]: could not replay spool, {err}");
+        Vec::new()
+    });
+    let (failed, error) = nats.publish(spooled).await;
+    let Some(error) = error else {
+        return;
+    };
+
+    let count = failed.len();
+    let mut spool = spool.lock();
+    match spool.push(failed) {
+        Ok(()) => log_eprintln!(
+            "WARN  [{datetime}] [tick #{tick_id}]: {count} messages not acked by nats, spool holds {} ({} dropped so far), {error}",
+            spool.depth(),
+            spool.dropped()
+        ),
+        Err(err) => log_eprintln!(
+            "WARN  [{datetime}] [tick #{tick_id}]: {count} messages not acked by nats and could not be spooled, {err}"
+        ),
+    }
+}
+
+/// Closes the partitions of the archive past midnight and uploads them, if configured.
+#[cfg(feature = "archive")]
+async fn rotate_archive(state: &AppState, tick_id: u64) {
+    let Some(archive) = &state.archive else {
+        return;
+    };
+    let now = state.clock.now_utc();
+    // the archive is released before uploading, the lock must not be held across an await
+    let (closed, dir, upload) = {
+        let mut archive = archive.lock();
+        let closed = archive.rotate(now);
+        (
+            closed,
+            archive.dir().to_path_buf(),
+            archive.upload().cloned(),
+        )
+    };
+    let datetime = logging::datetime();
+    let closed = match closed {
+        Ok(closed) => closed,
+        Err(err) => {
+            log_eprintln!(
+                "WARN  [{datetime}] [tick #{tick_id}]: could not close archive partition, {err}"
+            );
+            return;
+        }
+    };
+
+    for path in closed {
+        log_eprintln!("INFO  [{datetime}] [tick #{tick_id}]: archived forecasts to {path:?}");
+        let Some(upload) = &upload else {
+            continue;
+        };
+        match upload.upload(&dir, &path, now).await {
+            Ok(key) => {
+                log_eprintln!("INFO  [{datetime}] [tick #{tick_id}]: uploaded archive as {key:?}")
+            }
+            Err(err) => log_eprintln!(
+                "WARN  [{datetime}] [tick #{tick_id}]: could not upload archive {path:?}, {err}"
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "health-check")]
+async fn ping_sink(state: &AppState, tick_id: u64, sink: &Sink) {
+    let client = match sink {
+        Sink::Influx { client, .. } => client,
+        Sink::Stdout => return state.health.update(),
+    };
+    let datetime = logging::datetime();
+    match client.health().await {
+        Ok(health) if health.status == Status::Pass => state.health.update(),
+        Ok(health) => log_eprintln!(
+            "WARN  [{datetime}] [tick #{tick_id}]: influxdb reports to be unhealthy, {}",
+            health.message.unwrap_or_default()
+        ),
+        Err(err) => {
+            log_eprintln!("WARN  [{datetime}] [tick #{tick_id}]: pinging influxdb failed, {err}")
+        }
+    }
+}
+
+/// Requests the forecast of `target`, unless the `CHAOS_CONFIG` injects a failure instead.
+#[cfg(feature = "chaos")]
+async fn request_forecast(
+    state: &AppState,
+    tick_id: u64,
+    target: Target<'_>,
+    source: &ForecastSource,
+) -> Result<locations::Forecast, RequestLocationError> {
+    let fault = (state.chaos.as_ref()).and_then(|chaos| chaos.request(target.location));
+    if let Some(fault) = fault {
+        let datetime = logging::datetime();
+        log_eprintln!(
+            "WARN  [{datetime}] [tick #{tick_id}]: injecting synthetic {fault} into the request \
+             of {target}"
+        );
+        match fault {
+            Fault::Slow(delay) => tokio::time::sleep(delay).await,
+            fault => return Err(RequestLocationError::Synthetic(fault)),
+        }
+    }
+    source.forecast(target).await
+}
+
+#[cfg(not(feature = "chaos"))]
+async fn request_forecast(
+    _state: &AppState,
+    _tick_id: u64,
+    target: Target<'_>,
+    source: &ForecastSource,
+) -> Result<locations::Forecast, RequestLocationError> {
+    source.forecast(target).await
+}
+
+/// Writes the `data_points` of the `inserted` targets into `bucket`, unless the
+/// `CHAOS_CONFIG` fails the write.
+#[cfg(feature = "chaos")]
+async fn write_points(
+    state: &AppState,
+    tick_id: u64,
+    sink: &Sink,
+    bucket: &str,
+    inserted: &[(Target<'_>, String)],
+    data_points: Vec<DataPoint>,
+) -> Result<(), WriteError> {
+    let locations = inserted.iter().map(|(target, _)| target.location);
+    if let Some(fault) = (state.chaos.as_ref()).and_then(|chaos| chaos.write(locations)) {
+        let datetime = logging::datetime();
+        log_eprintln!(
+            "WARN  [{datetime}] [tick #{tick_id}]: injecting synthetic {fault} into the write \
+             into bucket {bucket:?}"
+        );
+        return Err(chaos::write_error().into());
+    }
+    sink.write(bucket, data_points).await
+}
+
+#[cfg(not(feature = "chaos"))]
+async fn write_points(
+    _state: &AppState,
+    _tick_id: u64,
+    sink: &Sink,
+    bucket: &str,
+    _inserted: &[(Target<'_>, String)],
+    data_points: Vec<DataPoint>,
+) -> Result<(), WriteError> {
+    sink.write(bucket, data_points).await
+}
+
+/// Requests the forecast of the `i`th of the `targets` once for all those sharing its
+/// coordinates, the others take the forecast or the error of the `requested` one.
+async fn fetch_forecast(
+    state: &AppState,
+    tick_id: u64,
+    targets: &[Target<'_>],
+    i: usize,
+    source: &ForecastSource,
+    shared: &SharedRequests,
+    requested: &mut BTreeMap<usize, Result<locations::Forecast, Arc<HandleLocationError>>>,
+) -> Result<locations::Forecast, HandleLocationError> {
+    let shared_error = |error: &Arc<HandleLocationError>| HandleLocationError::SharedRequest {
+        error: error.clone(),
+        locations: shared.members(i),
+    };
+    if let Some(leader) = shared.leader(i) {
+        return match &requested[&leader] {
+            Ok(forecast) => Ok(forecast.clone()),
+            Err(error) => Err(shared_error(error)),
+        };
+    }
+    let forecast = request_with_retries(state, tick_id, targets[i], source).await;
+    if !shared.is_shared(i) {
+        return forecast;
+    }
+    match forecast {
+        Ok(forecast) => {
+            requested.insert(i, Ok(forecast.clone()));
+            Ok(forecast)
+        }
+        Err(error) => {
+            let error = Arc::new(error);
+            requested.insert(i, Err(error.clone()));
+            Err(shared_error(&error))
+        }
+    }
+}
+
+/// Requests the forecast of the location and model of `target`, retrying as configured.
+async fn request_with_retries(
+    state: &AppState,
+    tick_id: u64,
+    target: Target<'_>,
+    source: &ForecastSource,
+) -> Result<locations::Forecast, HandleLocationError> {
+    #[cfg(feature = "health-check")]
+    let started = state.clock.now_instant();
+    let (forecast, attempts) = state
+        .retries
+        .request
+        .run(&*state.clock, &state.retry_budget, || {
+            request_forecast(state, tick_id, target, source)
+        })
+        .await;
+    #[cfg(feature = "health-check")]
+    state.health.record_latency(
+        &target.to_string(),
+        state.clock.now_instant().duration_since(started),
+    );
+    if forecast.is_ok() && attempts.retried() {
+        let datetime = logging::datetime();
+        log_eprintln!(
+            "INFO  [{datetime}] [tick #{tick_id}]: requested the forecast of {target} after \
+             {attempts}"
+        );
+    }
+    forecast.map_err(|err| HandleLocationError::attempted(err.into(), attempts))
+}
+
+/// Builds the data points of the `forecast` fetched for `target`.
+#[cfg_attr(
+    not(any(feature = "archive", feature = "kafka", feature = "nats")),
+    allow(unused_variables)
+)]
+fn handle_location<'l>(
+    state: &AppState,
+    tick_id: u64,
+    target: Target<'l>,
+    mut forecast: locations::Forecast,
+) -> Result<PendingPoint<'l>, HandleLocationError> {
+    if !forecast.rejected.is_empty() {
+        let datetime = logging::datetime();
+        log_eprintln!(
+            "WARN  [{datetime}] [tick #{tick_id}]: dropped the non-finite forecasts of {target} \
+             at {}",
+            forecast.rejected.join(", ")
+        );
+    }
+    #[cfg(feature = "archive")]
+    archive_forecast(state, tick_id, target, &forecast);
+    #[cfg(feature = "kafka")]
+    publish_forecast(state, tick_id, target, &forecast);
+    #[cfg(feature = "nats")]
+    queue_forecast(state, tick_id, target, &forecast);
+    // the archive keeps the times as sent
+    forecast.normalize_timestamps()?;
+    state.processing.apply(target.location, &mut forecast);
+    if let Some(live) = &state.live {
+        live.publish(live::LiveEvent::new(target, &forecast));
+    }
+    (state.history.write()).record(target, &forecast, tick_id, state.clock.now_utc());
+    let stale_issue =
+        state
+            .issues
+            .write()
+            .observe(&target.to_string(), &forecast.from, state.clock.now_utc());
+    let short_forecast = state
+        .horizons
+        .write()
+        .observe(&target.to_string(), forecast.forecasts.len());
+    let mut builder = forecast_point_builder(
+        target,
+        &forecast,
+        stale_issue,
+        short_forecast,
+        *fields::FIELD_LIMIT,
+        *geo::GEO_FIELDS,
+        &names::NAMES,
+    )?;
+    let duplicates = state.duplicates.read().duplicates_of(&target.to_string());
+    if !duplicates.is_empty() {
+        builder = builder.tag(names::NAMES.tag("grid_duplicates"), duplicates.join(","));
+    }
+    let schema_mode = *state.schema_mode.read();
+    let mut data_points = Vec::new();
+    let mut rejected = Vec::new();
+    if schema_mode.legacy() {
+        data_points.push(builder.build()?);
+    }
+    if schema_mode.v2() {
+        let mut lead_times = state.lead_times.write();
+        let points = forecast_v2_points(target, &forecast, &names::NAMES, &mut lead_times)?;
+        let clamped = lead_times.clamped();
+        drop(lead_times);
+        if !points.clamped.is_empty() {
+            let datetime = logging::datetime();
+            log_eprintln!(
+                "WARN  [{datetime}] [tick #{tick_id}]: the horizons of {target} at {} precede \
+                 the issue time {}, wrote them with a lead time of 0, clamped {clamped} such \
+                 horizons so far",
+                points.clamped.join(", "),
+                forecast.from
+            );
+        }
+        data_points.extend(points.points);
+        rejected = points.rejected;
+    }
+    Ok(PendingPoint {
+        target,
+        data_points,
+        rejected,
+        timestamp: issue_timestamp(&forecast.from)?,
+        content_hash: content_hash::content_hash(target, &forecast)?,
+        forecast_hash: content_hash::forecast_hash(target, &forecast)?,
+        issued: forecast.from,
+    })
+}
+
+#[cfg_attr(not(feature = "health-check"), allow(unused_variables))]
+async fn write_batch<'l>(
+    state: &AppState,
+    tick_id: u64,
+    sink: &Sink,
+    recorder: &TickRecorder,
+    bucket: &str,
+    mut batch: Batch<'l>,
+    failures: &pipeline::Sender<(Target<'l>, HandleLocationError)>,
+) {
+    let permit = state.circuit_breaker.as_ref().map(|breaker| {
+        let (permit, transition) = breaker.lock().permit(&*state.clock);
+        log_circuit_transition(tick_id, transition);
+        permit
+    });
+    if permit == Some(Permit::Divert) {
+        divert_batch(state, tick_id, sink, recorder, bucket, batch, failures).await;
+        return;
+    }
+
+    // points already written, like by an instance overlapping during a deploy, are skipped
+    match sink.existing_hashes(bucket, &batch).await {
+        Ok(existing) => batch.retain(|point| {
+            if !existing.contains(&point.content_hash) {
+                return true;
+            }
+            #[cfg(feature = "health-check")]
+            state.health.clear_error(&point.target.to_string());
+            let issued = point.issued.clone();
+            recorder.outcome(&point.target.to_string(), Outcome::Existing { issued });
+            let datetime = logging::datetime();
+            log_eprintln!(
+                "INFO  [{datetime}] [tick #{tick_id}]: location {:?} is in db for {} already, \
+                 skipped it",
+                point.target.to_string(),
+                point.issued
+            );
+            false
+        }),
+        Err(err) => {
+            let datetime = logging::datetime();
+            log_eprintln!(
+                "WARN  [{datetime}] [tick #{tick_id}]: could not query written points of \
+                 bucket {bucket:?}, writing all of them, {err}"
+            );
+        }
+    }
+    if batch.is_empty() {
+        return;
+    }
+
+    let mut rejections = Vec::new();
+    let (inserted, data_points): (Vec<_>, Vec<_>) = batch
+        .into_iter()
+        .map(|point| {
+            rejections.push((point.data_points.len(), point.rejected));
+            ((point.target, point.issued), point.data_points)
+        })
+        .unzip();
+    let data_points = data_points.concat();
+    let (result, attempts) = state
+        .retries
+        .write
+        .run(&*state.clock, &state.retry_budget, || {
+            write_points(state, tick_id, sink, bucket, &inserted, data_points.clone())
+        })
+        .await;
+    if let Some(breaker) = &state.circuit_breaker {
+        let transition = breaker.lock().record(result.is_ok(), &*state.clock);
+        log_circuit_transition(tick_id, transition);
+    }
+    if let Err(error) = result {
+        let error = Arc::new(error);
+        for (target, _) in inserted {
+            let error = HandleLocationError::WritePoints {
+                bucket: bucket.to_string(),
+                error: error.clone(),
+            };
+            let error = HandleLocationError::attempted(error, attempts.clone());
+            failures.send((target, error)).await;
+        }
+        return;
+    }
+    if attempts.retried() {
+        let datetime = logging::datetime();
+        log_eprintln!(
+            "INFO  [{datetime}] [tick #{tick_id}]: wrote into bucket {bucket:?} after {attempts}"
+        );
+    }
+    recorder.written(sink.lines(data_points).len());
+
+    #[cfg(feature = "health-check")]
+    state.health.update();
+    for ((target, from), (written, rejected)) in inserted.into_iter().zip(rejections) {
+        #[cfg(feature = "health-check")]
+        state.health.clear_error(&target.to_string());
+        state
+            .point_rejections
+            .write()
+            .record(&target.to_string(), written, &rejected);
+        recorder.rejected(rejected.len());
+        let outcome = Outcome::Written {
+            issued: from.clone(),
+            points: written,
+        };
+        recorder.outcome(&target.to_string(), outcome);
+        let datetime = logging::datetime();
+        log_eprintln!(
+            "INFO  [{datetime}] [tick #{tick_id}]: inserted location {:?} into db for {}",
+            target.to_string(),
+            from
+        );
+    }
+    if state.circuit_breaker.is_some() {
+        replay_diverted(state, tick_id, sink).await;
+    }
+}
+
+fn log_circuit_transition(tick_id: u64, transition: Option<circuit_breaker::Transition>) {
+    let Some(circuit_breaker::Transition { from, to }) = transition else {
+        return;
+    };
+    let datetime = logging::datetime();
+    match to {
+        circuit_breaker::BreakerState::Open => log_eprintln!(
+            "WARN  [{datetime}] [tick #{tick_id}]: sink circuit {from} -> {to}, spooling writes"
+        ),
+        _ => log_eprintln!("INFO  [{datetime}] [tick #{tick_id}]: sink circuit {from} -> {to}"),
+    }
+}
+
+/// Spools the `batch` for `bucket` instead of writing it while the circuit is open.
+///
+/// If it cannot be spooled, its locations fail like for a failed write.
+async fn divert_batch<'l>(
+    state: &AppState,
+    tick_id: u64,
+    sink: &Sink,
+    recorder: &TickRecorder,
+    bucket: &str,
+    batch: Batch<'l>,
+    failures: &pipeline::Sender<(Target<'l>, HandleLocationError)>,
+) {
+    let datetime = logging::datetime();
+    let count = batch.len();
+    let targets: Vec<_> = batch.iter().map(|point| point.target).collect();
+    let outcomes: Vec<_> = batch
+        .iter()
+        .map(|point| {
+            let outcome = Outcome::Spooled {
+                issued: point.issued.clone(),
+                points: point.data_points.len(),
+            };
+            (point.target.to_string(), outcome)
+        })
+        .collect();
+    let lines = sink.lines(
+        batch
+            .into_iter()
+            .flat_map(|point| point.data_points)
+            .collect(),
+    );
+    let message = spool::SpooledMessage::Influx {
+        bucket: bucket.to_string(),
+        lines,
+    };
+    let error = match &state.spool {
+        Some(spool) => {
+            let mut spool = spool.lock();
+            match spool.push([message]) {
+                Ok(()) => {
+                    for (target, outcome) in outcomes {
+                        recorder.outcome(&target, outcome);
+                    }
+                    return log_eprintln!(
+                        "WARN  [{datetime}] [tick #{tick_id}]: sink circuit open, spooled {count} points for bucket {bucket:?} (spool holds {}, {} dropped so far)",
+                        spool.depth(),
+                        spool.dropped()
+                    );
+                }
+                Err(err) => Some(Arc::new(err)),
+            }
+        }
+        None => None,
+    };
+    for target in targets {
+        let error = HandleLocationError::Diverted {
+            bucket: bucket.to_string(),
+            error: error.clone(),
+        };
+        failures.send((target, error)).await;
+    }
+}
+
+/// Writes the points spooled while the circuit was open, those failing again are spooled again.
+async fn replay_diverted(state: &AppState, tick_id: u64, sink: &Sink) {
+    let Some(spool) = &state.spool else {
+        return;
+    };
+    let datetime = logging::datetime();
+    // the spool is released while writing, the lock must not be held across an await
+    let spooled = spool.lock().take("influx").unwrap_or_else(|err| {
+        log_eprintln!("WARN  [{datetime}] [tick #{tick_id}]: could not replay spool, {err}");
+        Vec::new()
+    });
+    let mut failed = Vec::new();
+    for message in spooled {
+        let spool::SpooledMessage::Influx { bucket, lines } = &message else {
+            continue;
+        };
+        match sink.write_lines(bucket, lines.clone()).await {
+            Ok(()) => log_eprintln!(
+                "INFO  [{datetime}] [tick #{tick_id}]: wrote {} spooled points into bucket {bucket:?}",
+                lines.lines().count()
+            ),
+            Err(err) => {
+                log_eprintln!(
+                    "WARN  [{datetime}] [tick #{tick_id}]: could not write spooled points into bucket {bucket:?}, {err}"
+                );
+                failed.push(message);
+            }
+        }
+    }
+    if !failed.is_empty() {
+        if let Err(err) = spool.lock().push(failed) {
+            log_eprintln!(
+                "ERROR [{datetime}] [tick #{tick_id}]: could not spool the points failing again, {err}"
+            );
+        }
+    }
+}
+
+fn handle_location_error<'l>(
+    state: &AppState,
+    tick_id: u64,
+    target: Target<'l>,
+    error: HandleLocationError,
+    errors: &mut Vec<(Target<'l>, HandleLocationError)>,
+) {
+    let datetime = logging::datetime();
+    let severity = error.severity();
+    match error.response_body() {
+        Some(body) => {
+            let body = state.parse_failures.write().body(&target.to_string(), body);
+            log_println!(
+                "ERROR [{datetime}] [tick #{tick_id}] [{severity}]: {error}, original text:\n{body}"
+            );
+        }
+        None => log_eprintln!("ERROR [{datetime}] [tick #{tick_id}] [{severity}]: {error}"),
+    }
+
+    // snoozed locations do not degrade the health
+    #[cfg(feature = "health-check")]
+    if !(state.snoozes.read()).is_snoozed(target.location.name, state.clock.now_utc()) {
+        state
+            .health
+            .record_error(&target.to_string(), error.kind().code(), &error.to_string());
+    }
+
+    errors.push((target, error));
+}
+
+/// Requests the canary, its failures are logged and kept in the health status like the ones of
+/// a location.
+#[cfg_attr(not(feature = "health-check"), allow(unused_variables))]
+async fn check_canary(
+    state: &AppState,
+    tick_id: u64,
+    canary: &Canary,
+    client: &reqwest::Client,
+) -> Result<(), RequestLocationError> {
+    let result = canary.check(client).await;
+    match &result {
+        Ok(()) => {
+            #[cfg(feature = "health-check")]
+            state.health.clear_error(canary::NAME);
+        }
+        Err(err) => {
+            let datetime = logging::datetime();
+            log_eprintln!(
+                "ERROR [{datetime}] [tick #{tick_id}]: canary failed — likely local/network issue, {err}"
+            );
+            #[cfg(feature = "health-check")]
+            state
+                .health
+                .record_error(canary::NAME, canary::NAME, &err.to_string());
+        }
+    }
+    result
+}
+
+/// Alerts about the `errors` of a tick along with the `canary` field and the open sink circuit,
+/// if any, and resolves the alert once none is left for a few ticks.
+///
+/// The errors are grouped by location group once any location has one, otherwise by kind with
+/// the remediation `hints`, without them every location gets a field of its own.
+fn handle_location_errors(
+    state: &AppState,
+    tick_id: u64,
+    errors: &[(Target, HandleLocationError)],
+    canary: Option<AlertField>,
+    hints: Option<&Hints>,
+    notifications: &NotificationQueue,
+) {
+    let mut fields = match hints {
+        _ if state.groups.is_grouped() => {
+            AlertField::by_location_group(errors, tick_id, &state.groups)
+        }
+        Some(hints) => AlertField::grouped(errors, tick_id, hints),
+        None => AlertField::from_errors(errors, tick_id),
+    };
+    fields.extend(canary);
+    let circuit = state.circuit_breaker.as_ref();
+    fields.extend(circuit.and_then(|breaker| breaker.lock().alert_field(tick_id)));
+
+    // fields without a failed location, like of the canary, are caused by the collector
+    let cause = match errors.first() {
+        Some((target, error)) => incident::cause(&target.location.slug(), error.kind().code()),
+        None => incident::cause("collector", "unhealthy"),
+    };
+    // the tracker is released before pushing, the queue has a lock of its own
+    let action = state
+        .incident
+        .write()
+        .observe(fields.len(), &cause, state.clock.now_utc());
+    match action {
+        IncidentAction::Alert => {
+            let incident = state.incident.read().open_id();
+            let fields = fields
+                .into_iter()
+                .map(|field| field.with_incident(incident.clone()))
+                .collect();
+            notifications.push(Notification::Alert(fields))
+        }
+        IncidentAction::Resolve(mut incident) => {
+            if *gaps::GAPS_IN_RESOLVED {
+                let since = state.clock.now_utc() - incident.duration;
+                incident.gaps = (state.gaps.read().closed_since(since).into_iter())
+                    .map(|(target, gap)| (target.to_string(), gap.duration()))
+                    .collect();
+            }
+            notifications.push(Notification::Resolved {
+                history: None,
+                incident: Some(incident),
+            })
+        }
+        IncidentAction::None => (),
+    }
+}
+
+/// Logs the snoozes of locations that expired.
+fn expire_snoozes(state: &AppState, tick_id: u64) {
+    let expired = state.snoozes.write().expire(state.clock.now_utc());
+    for (location, until) in expired {
+        let datetime = logging::datetime();
+        log_eprintln!(
+            "INFO  [{datetime}] [tick #{tick_id}]: snooze of location {location:?} expired at {}, \
+             alerting about it again",
+            until.format("%Y-%m-%d %H:%M UTC")
+        );
+    }
+}
+
+/// Takes the errors of snoozed locations out of the `errors`, they are collected as usual but
+/// not alerted.
+fn split_snoozed<'l>(
+    state: &AppState,
+    errors: &mut Vec<(Target<'l>, HandleLocationError)>,
+) -> Vec<(Target<'l>, HandleLocationError)> {
+    let now = state.clock.now_utc();
+    let snoozes = state.snoozes.read();
+    let (snoozed, alerted) = std::mem::take(errors)
+        .into_iter()
+        .partition(|(target, _)| snoozes.is_snoozed(target.location.name, now));
+    *errors = alerted;
+    snoozed
+}
+
+/// Logs the `errors` of a quiet tick of a first run instead of alerting them, `remaining` quiet
+/// ticks are left.
+///
+/// Like during maintenance, the incident is left untouched.
+fn record_first_run_errors(
+    tick_id: u64,
+    errors: &[(Target<'_>, HandleLocationError)],
+    remaining: u32,
+) {
+    if errors.is_empty() {
+        return;
+    }
+    let datetime = logging::datetime();
+    log_eprintln!(
+        "INFO  [{datetime}] [tick #{tick_id}]: {} locations failed on the first run, not \
+         alerting, {remaining} quiet ticks left",
+        errors.len()
+    );
+}
+
+/// Logs and counts the `errors` of a tick during the maintenance `window` instead of alerting
+/// them.
+///
+/// The incident is left untouched, so neither do the ticks in the window count towards
+/// resolving it nor is an alert pending once the window ends.
+fn record_maintenance_errors(
+    state: &AppState,
+    tick_id: u64,
+    window: &MaintenanceWindow,
+    errors: &[(Target<'_>, HandleLocationError)],
+) {
+    if errors.is_empty() {
+        return;
+    }
+    let total = state.maintenance.record(errors.len());
+    let datetime = logging::datetime();
+    log_eprintln!(
+        "INFO  [{datetime}] [tick #{tick_id}]: {} locations failed during maintenance window {:?}, \
+         not alerting, {total} failures during maintenance so far",
+        errors.len(),
+        window.name
+    );
+}
+
+/// Warns about forecasts with fewer horizons than typical and about them being complete again.
+fn report_short_forecasts(state: &AppState, tick_id: u64, notifications: &NotificationQueue) {
+    let messages = state.horizons.write().take_messages();
+    for message in messages {
+        let datetime = logging::datetime();
+        log_eprintln!("WARN  [{datetime}] [tick #{tick_id}]: {message}");
+        notifications.push(Notification::Warning(message));
+    }
+}
+
+/// Warns about the forecasts written without some of their points.
+fn report_point_rejections(state: &AppState, tick_id: u64, notifications: &NotificationQueue) {
+    let messages = state.point_rejections.write().take_messages();
+    for message in messages {
+        let datetime = logging::datetime();
+        log_eprintln!("WARN  [{datetime}] [tick #{tick_id}]: {message}");
+        notifications.push(Notification::Warning(message));
+    }
+}
+
+/// Alerts about the spool filling up or dropping messages and warns once it drained again.
+fn report_spool(state: &AppState, tick_id: u64, notifications: &NotificationQueue) {
+    let Some(spool) = &state.spool else {
+        return;
+    };
+    let notices = spool.lock().take_notices();
+    let datetime = logging::datetime();
+    for notice in notices {
+        let message = notice.message().to_string();
+        match notice.severity() {
+            Some(severity) => {
+                log_eprintln!("WARN  [{datetime}] [tick #{tick_id}]: {message}");
+                let field = AlertField::new(spool::NAME.to_string(), message, severity, tick_id);
+                notifications.push(Notification::Alert(vec![field]));
+            }
+            None => {
+                log_eprintln!("INFO  [{datetime}] [tick #{tick_id}]: {message}");
+                notifications.push(Notification::Warning(message));
+            }
+        }
+    }
+}
+
+/// Sends the bytes transferred on the days that ended, once a day.
+fn report_egress(state: &AppState, tick_id: u64, notifications: &NotificationQueue) {
+    for day in state.egress.take_ended() {
+        let datetime = logging::datetime();
+        log_eprintln!("INFO  [{datetime}] [tick #{tick_id}]: {day}");
+        notifications.push(Notification::Warning(day.to_string()));
+    }
+}
+
+/// Persists the state kept across restarts to the file at `path`.
+fn save_state(state: &AppState, tick_id: u64, path: &std::path::Path) {
+    if let Err(err) = state.persisted().save(path) {
+        let datetime = logging::datetime();
+        log_eprintln!("WARN  [{datetime}] [tick #{tick_id}]: could not save state, {err}");
+    }
+}
+
+/// Warns about forecasts the swat api stopped reissuing and about them being reissued again.
+fn report_stale_issues(state: &AppState, tick_id: u64, notifications: &NotificationQueue) {
+    let messages = state.issues.write().take_messages();
+    for message in messages {
+        let datetime = logging::datetime();
+        log_eprintln!("WARN  [{datetime}] [tick #{tick_id}]: {message}");
+        notifications.push(Notification::Warning(message));
+    }
+}
+
+/// Logs how long the tick took and warns if it took up most of the `interval`.
+fn report_tick_duration(
+    state: &AppState,
+    tick_id: u64,
+    elapsed: Duration,
+    interval: Duration,
+    notifications: &NotificationQueue,
+) {
+    let datetime = logging::datetime();
+    log_eprintln!(
+        "INFO  [{datetime}] [tick #{tick_id}]: tick took {:.1}s",
+        elapsed.as_secs_f64()
+    );
+    let overrun = state.tick_budget.read().check(elapsed, interval);
+    if let Some(message) = overrun {
+        log_eprintln!("WARN  [{datetime}] [tick #{tick_id}]: {message}");
+        notifications.push(Notification::Warning(message));
+    }
+}
+
+#[cfg(all(test, feature = "health-check"))]
+mod tests {
+    use super::*;
+    use crate::error_kind;
+    use crate::locations::Forecast;
+    use crate::names::NameMapping;
+    use crate::tick_report;
+    use std::net::SocketAddr;
+    use std::path::Path;
+    use warp::http::StatusCode;
+    use warp::Filter;
+
+    /// Serves the SWAT api and the InfluxDB write and health endpoints.
+    fn mock_backends() -> SocketAddr {
+        let forecast = warp::get()
+            .and(warp::path("Vorhersage"))
+            .map(|| include_str!("../tests/fixtures/location-1.body.json"));
+        let write = warp::post()
+            .and(warp::path!("api" / "v2" / "write"))
+            .map(|| StatusCode::NO_CONTENT);
+        let health = warp::get()
+            .and(warp::path("health"))
+            .map(|| r#"{"name": "influxdb", "status": "pass", "checks": []}"#);
+        let routes = forecast.or(write).or(health);
+        let (addr, server) = warp::serve(routes).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        addr
+    }
+
+    fn api(url: &str) -> ForecastSource {
+        ForecastSource::Api {
+            client: reqwest::Client::new(),
+            url: url.to_string(),
+            api: Arc::default(),
+        }
+    }
+
+    fn influx(url: &str) -> Sink {
+        Sink::Influx {
+            client: Box::new(InfluxClient::new(url, "org", "token")),
+            buckets: Buckets::from_lookup(|_| None, &locations::LOCATIONS.locations),
+            idempotent: false,
+            profile: None,
+        }
+    }
+
+    /// The default model of every location.
+    fn targets(locations: &[locations::Location]) -> Vec<Target<'_>> {
+        static MODELS: Lazy<Models> = Lazy::new(Models::default);
+        MODELS.targets(locations)
+    }
+
+    fn exit_code_eq(a: ExitCode, b: ExitCode) -> bool {
+        format!("{a:?}") == format!("{b:?}")
+    }
+
+    #[cfg(feature = "health-check")]
+    #[test]
+    fn mute_subcommands() {
+        let args = Args::try_parse_from(["swat-collector", "mute", "60"]).unwrap();
+        assert!(matches!(args.command, Some(Command::Mute { minutes: 60 })));
+        let args = Args::try_parse_from(["swat-collector", "unmute"]).unwrap();
+        assert!(matches!(args.command, Some(Command::Unmute)));
+        assert!(Args::try_parse_from(["swat-collector", "mute"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn tick_makes_healthy() {
+        let _lock = health_check::TEST_LOCK.lock().await;
+        health_check::reset();
+
+        let addr = mock_backends();
+        let url = format!("http://{addr}");
+        let sink = influx(&url);
+        let listener = health_check::listen()
+            .unwrap()
+            .expect("socket mode is the default");
+        tokio::spawn(health_check::serve(
+            listener,
+            vec![health_check::TEST_STATE.clone()],
+        ));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(exit_code_eq(
+            health_check::check(
+                &**health_check::TEST_CLOCK,
+                false,
+                health_check::Format::Plain
+            )
+            .await,
+            ExitCode::FAILURE
+        ));
+
+        let targets = targets(&locations::LOCATIONS.locations[..1]);
+        let state = &health_check::TEST_STATE;
+        let errors = collect(state, 1, &targets, &api(&url), &sink).await.errors;
+        assert!(errors.is_empty(), "{errors:?}");
+        assert!(exit_code_eq(
+            health_check::check(
+                &**health_check::TEST_CLOCK,
+                false,
+                health_check::Format::Plain
+            )
+            .await,
+            ExitCode::SUCCESS
+        ));
+
+        health_check::reset();
+    }
+
+    #[tokio::test]
+    async fn tick_without_writes_pings() {
+        let _lock = health_check::TEST_LOCK.lock().await;
+        health_check::reset();
+
+        let addr = mock_backends();
+        let url = format!("http://{addr}");
+        let sink = influx(&url);
+        let listener = health_check::listen()
+            .unwrap()
+            .expect("socket mode is the default");
+        tokio::spawn(health_check::serve(
+            listener,
+            vec![health_check::TEST_STATE.clone()],
+        ));
+
+        // the swat api is unavailable, but influxdb is fine
+        let targets = targets(&locations::LOCATIONS.locations[..1]);
+        let api_url = format!("{url}/unavailable");
+        let errors = collect(
+            &health_check::TEST_STATE,
+            1,
+            &targets,
+            &api(&api_url),
+            &sink,
+        )
+        .await
+        .errors;
+        assert_eq!(errors.len(), 1);
+        assert!(exit_code_eq(
+            health_check::check(
+                &**health_check::TEST_CLOCK,
+                false,
+                health_check::Format::Plain
+            )
+            .await,
+            ExitCode::SUCCESS
+        ));
+
+        health_check::reset();
+    }
+
+    #[tokio::test]
+    async fn tick_reports_outcomes() {
+        // the first location is collected, the swat api fails for the second
+        let requests = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let forecast = warp::get().and(warp::path("Vorhersage")).map(move || {
+            match requests.fetch_add(1, Ordering::SeqCst) {
+                0 => warp::reply::with_status(
+                    include_str!("../tests/fixtures/location-1.body.json"),
+                    StatusCode::OK,
+                ),
+                _ => warp::reply::with_status("", StatusCode::SERVICE_UNAVAILABLE),
+            }
+        });
+        let write = warp::post()
+            .and(warp::path!("api" / "v2" / "write"))
+            .map(|| StatusCode::NO_CONTENT);
+        let (addr, server) = warp::serve(forecast.or(write)).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let url = format!("http://{addr}");
+
+        let state = AppState::default();
+        let targets = targets(&locations::LOCATIONS.locations[..2]);
+        let report = collect(&state, 7, &targets, &api(&url), &influx(&url)).await;
+
+        assert_eq!(report.failed(), [targets[1].to_string()]);
+        assert_eq!(report.locations.len(), 2);
+        assert_eq!(report.locations[0].location, targets[0].to_string());
+        assert!(matches!(
+            &report.locations[0].outcome,
+            tick_report::Outcome::Written { points: 1, .. }
+        ));
+        assert!(report.bytes_written > 0);
+
+        let json: serde_json::Value = serde_json::from_str(&report.json()).unwrap();
+        assert_eq!(json["tick_id"], 7);
+        assert!(json.get("profile").is_none());
+        assert!(json["elapsed_ms"].is_u64());
+        assert_eq!(json["bytes_written"], report.bytes_written);
+        let locations = json["locations"].as_array().unwrap();
+        assert_eq!(locations[0]["outcome"], "written");
+        assert_eq!(locations[0]["points"], 1);
+        assert!(locations[0]["issued"].is_string());
+        assert!(locations[0]["duration_ms"].is_u64());
+        assert_eq!(locations[1]["location"], targets[1].to_string());
+        assert_eq!(locations[1]["outcome"], "failed");
+        assert_eq!(locations[1]["kind"], report.errors[0].1.kind().code());
+        assert!(locations[1]["error"].is_string());
+        assert_eq!(json["notifications"], serde_json::json!([]));
+    }
+
+    #[tokio::test]
+    async fn retries_share_tick_budget() {
+        let _lock = health_check::TEST_LOCK.lock().await;
+
+        // the swat api resets the connection of every request
+        let requests = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn({
+            let requests = requests.clone();
+            async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    requests.fetch_add(1, Ordering::SeqCst);
+                    drop(stream);
+                }
+            }
+        });
+
+        let policy = retry::RetryPolicy {
+            attempts: 3,
+            delay: Duration::from_millis(1),
+        };
+        let state = AppState::default().with_retries(retry::Retries {
+            request: policy,
+            write: policy,
+            budget: Some(3),
+        });
+        let sink = influx(&format!("http://{}", mock_backends()));
+        let targets = targets(&locations::LOCATIONS.locations[..5]);
+        logging::capture();
+        let report = collect(&state, 9, &targets, &api(&url), &sink).await;
+        let lines = logging::take_captured();
+
+        // 5 requests and the 3 retries of the budget, instead of 15 requests
+        assert_eq!(requests.load(Ordering::SeqCst), 8);
+        assert_eq!(report.errors.len(), 5);
+        let exhausted: Vec<_> = (report.errors.iter())
+            .map(|(_, error)| error.to_string().contains("retry budget exhausted"))
+            .collect();
+        assert_eq!(exhausted, [false, true, true, true, true]);
+        assert_eq!(report.retry_budget.denied, 4);
+        let json: serde_json::Value = serde_json::from_str(&report.json()).unwrap();
+        assert_eq!(
+            json["retry_budget"],
+            serde_json::json!({"limit": 3, "used": 3, "denied": 4})
+        );
+        let warnings: Vec<_> = (lines.iter())
+            .filter(|line| line.contains("[tick #9]: retry budget of 3 retries exhausted"))
+            .collect();
+        assert_eq!(warnings.len(), 1, "{lines:?}");
+
+        // the next tick is granted retries again
+        requests.store(0, Ordering::SeqCst);
+        let report = collect(&state, 10, &targets[..1], &api(&url), &sink).await;
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+        assert_eq!(report.retry_budget.denied, 0);
+    }
+
+    #[tokio::test]
+    async fn shares_requests_of_same_coordinates() {
+        let _lock = health_check::TEST_LOCK.lock().await;
+
+        let requests = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let available = Arc::new(std::sync::atomic::AtomicBool::new(true));
+        let written = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let forecast = warp::get().and(warp::path("Vorhersage")).map({
+            let (requests, available) = (requests.clone(), available.clone());
+            move || {
+                requests.fetch_add(1, Ordering::SeqCst);
+                match available.load(Ordering::SeqCst) {
+                    true => warp::reply::with_status(
+                        include_str!("../tests/fixtures/location-1.body.json"),
+                        StatusCode::OK,
+                    ),
+                    false => warp::reply::with_status("", StatusCode::SERVICE_UNAVAILABLE),
+                }
+            }
+        });
+        let write = warp::post()
+            .and(warp::path!("api" / "v2" / "write"))
+            .and(warp::body::bytes())
+            .map({
+                let written = written.clone();
+                move |body: warp::hyper::body::Bytes| {
+                    let body = String::from_utf8_lossy(&body).into_owned();
+                    written.lock().extend(body.lines().map(str::to_string));
+                    StatusCode::NO_CONTENT
+                }
+            });
+        let (addr, server) = warp::serve(forecast.or(write)).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let url = format!("http://{addr}");
+
+        // the same well twice, once with its coordinates padded
+        let locations = [
+            locations::Location {
+                group: "",
+                id: 901,
+                lat: "52.91098",
+                lon: "8.23505",
+                name: "WW Nord",
+            },
+            locations::Location {
+                group: "",
+                id: 902,
+                lat: "52.910980",
+                lon: "8.235050",
+                name: "WW Nord Brunnen 2",
+            },
+        ];
+        let sink = Sink::Influx {
+            client: Box::new(InfluxClient::new(&url, "org", "token")),
+            buckets: Buckets::from_lookup(|_| None, &locations),
+            idempotent: false,
+            profile: None,
+        };
+        let policy = retry::RetryPolicy {
+            attempts: 1,
+            delay: Duration::from_millis(1),
+        };
+        let state = AppState::default().with_retries(retry::Retries {
+            request: policy,
+            write: policy,
+            budget: None,
+        });
+        let targets = targets(&locations);
+
+        let report = collect(&state, 1, &targets, &api(&url), &sink).await;
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        let written = written.lock().clone();
+        assert_eq!(written.len(), 2, "{written:?}");
+        assert!(written[0].contains(",id=901,"), "{written:?}");
+        assert!(written[1].contains(",id=902,"), "{written:?}");
+
+        // a failed request fails every location sharing it
+        available.store(false, Ordering::SeqCst);
+        let report = collect(&state, 2, &targets, &api(&url), &sink).await;
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        let errors: Vec<_> = (report.errors.iter())
+            .map(|(target, error)| (target.location.id, error.to_string()))
+            .collect();
+        assert_eq!(errors.len(), 2, "{errors:?}");
+        for (id, (failed, error)) in [901, 902].into_iter().zip(errors) {
+            assert_eq!(failed, id);
+            assert!(
+                error.ends_with("(requested once for \"WW Nord\", \"WW Nord Brunnen 2\")"),
+                "{error}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn tick_id_is_logged() {
+        let _lock = health_check::TEST_LOCK.lock().await;
+        health_check::reset();
+
+        let addr = mock_backends();
+        let url = format!("http://{addr}");
+        let sink = influx(&url);
+        let state = &health_check::TEST_STATE;
+        let targets = targets(&locations::LOCATIONS.locations[..1]);
+
+        logging::capture();
+        let errors = collect(state, 4812, &targets, &api(&url), &sink)
+            .await
+            .errors;
+        assert!(errors.is_empty(), "{errors:?}");
+        let api_url = format!("{url}/unavailable");
+        let errors = collect(state, 4813, &targets, &api(&api_url), &sink)
+            .await
+            .errors;
+        assert_eq!(errors.len(), 1);
+        let lines = logging::take_captured();
+
+        assert!(
+            lines
+                .iter()
+                .any(|line| line.starts_with("INFO ") && line.contains("[tick #4812]: inserted")),
+            "{lines:?}"
+        );
+        assert!(
+            lines
+                .iter()
+                .any(|line| line.starts_with("ERROR") && line.contains("[tick #4813]")),
+            "{lines:?}"
+        );
+
+        health_check::reset();
+    }
+
+    #[test]
+    fn tick_ids_continue_after_restart() {
+        let epoch_minute = chrono::Utc::now().timestamp() as u64 / 60;
+        assert!(initial_tick_id(0) >= epoch_minute);
+
+        // manual passes ran the ids ahead of the epoch minute before the restart
+        let before = AppState::default();
+        before.tick_id.store(epoch_minute + 500, Ordering::Relaxed);
+        let after = AppState::default();
+        after.restore(before.persisted());
+        let last = after.tick_id.load(Ordering::Relaxed);
+        assert_eq!(last, epoch_minute + 500);
+        assert_eq!(initial_tick_id(last), epoch_minute + 500);
+
+        // nothing kept before the first tick
+        assert_eq!(AppState::default().persisted().tick_id, None);
+    }
+
+    #[cfg(feature = "chaos")]
+    #[tokio::test]
+    async fn injects_synthetic_failures() {
+        let _lock = health_check::TEST_LOCK.lock().await;
+
+        let addr = mock_backends();
+        let url = format!("http://{addr}");
+        let sink = influx(&url);
+        let chaos = Chaos::parse(
+            r#"
+            [[fault]]
+            kind = "server_error"
+            location = "WW Marienhafe"
+            every = 1
+
+            [[fault]]
+            kind = "write_failure"
+            every = 2
+            "#,
+        )
+        .unwrap();
+        let state = AppState::default().with_chaos(Some(chaos));
+        let targets = targets(&locations::LOCATIONS.locations[..2]);
+
+        logging::capture();
+        let report = collect(&state, 21, &targets, &api(&url), &sink).await;
+        assert_eq!(report.failed(), [targets[1].to_string()]);
+        assert!(report.errors[0].1.is_synthetic());
+        assert_eq!(
+            report.errors[0].1.kind(),
+            error_kind::ErrorKind::RequestStatus
+        );
+        let json: serde_json::Value = serde_json::from_str(&report.json()).unwrap();
+        assert!(json["locations"][0].get("synthetic").is_none());
+        assert_eq!(json["locations"][1]["synthetic"], true);
+
+        // the second write is failed
+        let targets = &targets[..1];
+        let report = collect(&state, 22, targets, &api(&url), &sink).await;
+        assert_eq!(report.failed(), [targets[0].to_string()]);
+        assert!(report.errors[0].1.is_synthetic());
+        assert!(
+            report.text().contains("failed (influx_write, synthetic)"),
+            "{}",
+            report.text()
+        );
+        let lines = logging::take_captured();
+        for expected in [
+            "[tick #21]: injecting synthetic 503 response into the request of",
+            "[tick #22]: injecting synthetic write failure into the write into bucket",
+        ] {
+            assert!(
+                lines.iter().any(|line| line.contains(expected)),
+                "{expected}: {lines:?}"
+            );
+        }
+        assert_eq!(
+            state.chaos.as_ref().unwrap().status_text(),
+            "synthetic failures injected: 1 server_error, 1 write_failure\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn writes_per_bucket() {
+        let _lock = health_check::TEST_LOCK.lock().await;
+        health_check::reset();
+
+        // the bucket of the first project was renamed
+        let writes = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let forecast = warp::get()
+            .and(warp::path("Vorhersage"))
+            .map(|| include_str!("../tests/fixtures/location-1.body.json"));
+        let write = warp::post()
+            .and(warp::path!("api" / "v2" / "write"))
+            .and(warp::query::<BTreeMap<String, String>>())
+            .and(warp::body::bytes())
+            .map({
+                let writes = writes.clone();
+                move |query: BTreeMap<String, String>, body: warp::hyper::body::Bytes| {
+                    let bucket = query["bucket"].clone();
+                    let points = String::from_utf8_lossy(&body).lines().count();
+                    writes.lock().push((bucket.clone(), points));
+                    match bucket.as_str() {
+                        "research-a" => StatusCode::NOT_FOUND,
+                        _ => StatusCode::NO_CONTENT,
+                    }
+                }
+            });
+        let (addr, server) = warp::serve(forecast.or(write)).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let url = format!("http://{addr}");
+
+        let locations = &locations::LOCATIONS.locations[..3];
+        let sink = Sink::Influx {
+            client: Box::new(InfluxClient::new(&url, "org", "token")),
+            buckets: Buckets::from_lookup(
+                |key| match key {
+                    "INFLUXDB_BUCKET_WW_GROSSENKNETEN" | "INFLUXDB_BUCKET_WW_MARIENHAFE" => {
+                        Some("research-a".to_string())
+                    }
+                    _ => None,
+                },
+                locations,
+            ),
+            idempotent: false,
+            profile: None,
+        };
+        let errors = collect(
+            &health_check::TEST_STATE,
+            1,
+            &targets(locations),
+            &api(&url),
+            &sink,
+        )
+        .await
+        .errors;
+
+        // a single write per bucket, the failing one only fails its own locations
+        assert_eq!(
+            *writes.lock(),
+            [("research-a".to_string(), 2), ("swat".to_string(), 1)]
+        );
+        let failed: Vec<_> = errors
+            .iter()
+            .map(|(target, _)| target.location.id)
+            .collect();
+        assert_eq!(failed, [1, 2]);
+        for (_, error) in &errors {
+            assert_eq!(error.kind(), error_kind::ErrorKind::InfluxWrite);
+            assert!(error.to_string().contains("\"research-a\""), "{error}");
+        }
+
+        health_check::reset();
+    }
+
+    #[tokio::test]
+    async fn init_buckets_fails_single_buckets() {
+        // the token may not read the bucket of the first project, the default one is missing
+        let created = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let list = warp::get()
+            .and(warp::path!("api" / "v2" / "buckets"))
+            .and(warp::query::<BTreeMap<String, String>>())
+            .map(
+                |query: BTreeMap<String, String>| match query["name"].as_str() {
+                    "research-a" => warp::reply::with_status(
+                        warp::reply::json(&serde_json::json!({"message": "forbidden"})),
+                        StatusCode::FORBIDDEN,
+                    ),
+                    _ => warp::reply::with_status(
+                        warp::reply::json(&serde_json::json!({"buckets": []})),
+                        StatusCode::OK,
+                    ),
+                },
+            );
+        let orgs = warp::get()
+            .and(warp::path!("api" / "v2" / "orgs"))
+            .map(|| warp::reply::json(&serde_json::json!({"orgs": [{"id": "1", "name": "org"}]})));
+        let create = warp::post()
+            .and(warp::path!("api" / "v2" / "buckets"))
+            .and(warp::body::json())
+            .map({
+                let created = created.clone();
+                move |bucket: serde_json::Value| {
+                    created.lock().push(bucket["name"].clone());
+                    warp::reply::with_status(warp::reply::json(&bucket), StatusCode::CREATED)
+                }
+            });
+        let (addr, server) =
+            warp::serve(list.or(orgs).or(create)).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        let client = influxdb2::Client::new(format!("http://{addr}"), "org", "token");
+        let buckets = Buckets::from_lookup(
+            |key| (key == "INFLUXDB_BUCKET_WW_GROSSENKNETEN").then(|| "research-a".to_string()),
+            &locations::LOCATIONS.locations[..2],
+        );
+        let failed = init_buckets(&client, "org".to_string(), &buckets).await;
+
+        let failed: Vec<_> = failed
+            .iter()
+            .map(|(bucket, err)| (bucket.as_str(), err.to_string()))
+            .collect();
+        assert_eq!(failed.len(), 1, "{failed:?}");
+        assert_eq!(failed[0].0, "research-a");
+        assert!(
+            failed[0].1.starts_with("could not look up bucket"),
+            "{failed:?}"
+        );
+        assert_eq!(*created.lock(), [serde_json::json!("swat")]);
+    }
+
+    #[tokio::test]
+    async fn unspooled_diversions_fail() {
+        let _lock = health_check::TEST_LOCK.lock().await;
+        health_check::reset();
+
+        let state = |spool: Option<spool::Spool>| {
+            let state = AppState::default().with_spool(spool);
+            let mut breaker = CircuitBreaker::new(1, Duration::from_secs(3600));
+            breaker.record(false, &*state.clock);
+            state.with_circuit_breaker(Some(breaker))
+        };
+        let url = format!("http://{}", mock_backends());
+        let sink = influx(&url);
+        let targets = targets(&locations::LOCATIONS.locations[..2]);
+
+        // without a spool, and with one whose directory turned out to be a file
+        let file = env::temp_dir().join(format!("swat-collector-divert-{}", std::process::id()));
+        let unwritable = spool::Spool::load(file.join("spool.json"), 8).unwrap();
+        std::fs::write(&file, "").unwrap();
+        let spools = [
+            (None, "no spool"),
+            (Some(unwritable), "could not access spool"),
+        ];
+        for (spool, reason) in spools {
+            let report = collect(&state(spool), 1, &targets, &api(&url), &sink).await;
+            assert_eq!(report.errors.len(), 2);
+            for (_, error) in &report.errors {
+                assert_eq!(error.kind(), error_kind::ErrorKind::InfluxWrite);
+                let error = error.to_string();
+                assert!(error.starts_with("sink circuit open"), "{error}");
+                assert!(error.contains(reason), "{error}");
+            }
+        }
+
+        std::fs::remove_file(file).unwrap();
+        health_check::reset();
+    }
+
+    #[tokio::test]
+    async fn slow_sink_holds_up_fetching() {
+        let _lock = health_check::TEST_LOCK.lock().await;
+        health_check::reset();
+
+        let written = Arc::new(parking_lot::Mutex::new(0));
+        let forecast = warp::get()
+            .and(warp::path("Vorhersage"))
+            .map(|| include_str!("../tests/fixtures/location-1.body.json"));
+        let write = warp::post()
+            .and(warp::path!("api" / "v2" / "write"))
+            .and(warp::body::bytes())
+            .then({
+                let written = written.clone();
+                move |body: warp::hyper::body::Bytes| {
+                    let written = written.clone();
+                    async move {
+                        tokio::time::sleep(Duration::from_millis(20)).await;
+                        *written.lock() += String::from_utf8_lossy(&body).lines().count();
+                        StatusCode::NO_CONTENT
+                    }
+                }
+            });
+        let (addr, server) = warp::serve(forecast.or(write)).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let url = format!("http://{addr}");
+
+        let state = AppState::default().with_pipeline(pipeline::Pipeline::new(2, 2, 1));
+        let targets = targets(&locations::LOCATIONS.locations);
+        let errors = collect(&state, 1, &targets, &api(&url), &influx(&url))
+            .await
+            .errors;
+
+        // the built points queued up to the capacity and every one of them was written
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(state.pipeline.points.peak(), 2);
+        assert_eq!(state.pipeline.points.depth(), 0);
+        assert_eq!(*written.lock(), targets.len());
+
+        health_check::reset();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn spread_fetches_over_interval() {
+        let _lock = health_check::TEST_LOCK.lock().await;
+        health_check::reset();
+
+        let bodies = fixture::load_bodies(Path::new("tests/fixtures")).unwrap();
+        let source = ForecastSource::fixtures(bodies);
+        let fetched = || match &source {
+            ForecastSource::Fixtures { next, .. } => next.load(Ordering::SeqCst),
+            ForecastSource::Api { .. } => unreachable!(),
+        };
+        let written = |lines: Vec<String>| {
+            (lines.iter())
+                .filter(|line| line.starts_with("forecast,"))
+                .count()
+        };
+        let state = AppState::default().with_spread(Spread::new(true, true));
+        let targets = targets(&locations::LOCATIONS.locations[..4]);
+        let phase = COLLECTION_INTERVAL / 4;
+
+        let started = tokio::time::Instant::now();
+        logging::capture();
+        let (report, ()) = tokio::join!(
+            collect(&state, 1, &targets, &source, &Sink::Stdout),
+            async {
+                // every location is fetched at its own phase and written right away
+                for i in 0..4 {
+                    tokio::time::sleep_until(started + phase * i + Duration::from_secs(1)).await;
+                    assert_eq!(fetched(), i as usize + 1, "phase {i}");
+                    assert_eq!(written(logging::take_captured()), 1, "phase {i}");
+                    logging::capture();
+                }
+            }
+        );
+        logging::take_captured();
+        let errors = report.errors;
+        assert!(errors.is_empty(), "{errors:?}");
+
+        // fetched exactly once within the interval, without counting the phases as overrun
+        assert_eq!(fetched(), targets.len());
+        let elapsed = started.elapsed();
+        assert!(elapsed < COLLECTION_INTERVAL, "{elapsed:?}");
+        assert_eq!(
+            state.tick_budget.read().check(elapsed, COLLECTION_INTERVAL),
+            None
+        );
+
+        health_check::reset();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn spread_ticks_keep_the_interval() {
+        let _lock = health_check::TEST_LOCK.lock().await;
+        health_check::reset();
+
+        let bodies = fixture::load_bodies(Path::new("tests/fixtures")).unwrap();
+        let source = ForecastSource::fixtures(bodies);
+        let state = AppState::default().with_spread(Spread::new(true, false));
+        let targets = targets(&locations::LOCATIONS.locations[..4]);
+        let notifications = NotificationQueue::new(16);
+        let trigger = Notify::new();
+        let mut interval = tokio::time::interval(COLLECTION_INTERVAL);
+
+        // the write after the last phase runs past the end of the interval every time
+        let started = tokio::time::Instant::now();
+        let mut starts = Vec::new();
+        let mut due = false;
+        logging::capture();
+        for tick_id in 1..=3 {
+            next_pass(due, &mut interval, &trigger).await;
+            starts.push(started.elapsed().as_secs());
+            let tick = async {
+                let report = collect(&state, tick_id, &targets, &source, &Sink::Stdout).await;
+                tokio::time::sleep(COLLECTION_INTERVAL / 3).await;
+                report
+            };
+            let report;
+            (report, due) =
+                skip_while_running(&state, tick_id, &mut interval, &notifications, tick).await;
+            assert!(report.errors.is_empty(), "{:?}", report.errors);
+            assert!(due, "tick #{tick_id}");
+        }
+        logging::take_captured();
+
+        // the next pass starts right after the one running into the interval, none is skipped
+        assert_eq!(starts, [0, 130, 260]);
+        assert_eq!(state.skipped_ticks.read().total(), 0);
+        let fetched = match &source {
+            ForecastSource::Fixtures { next, .. } => next.load(Ordering::SeqCst),
+            ForecastSource::Api { .. } => unreachable!(),
+        };
+        assert_eq!(fetched, 3 * targets.len());
+
+        health_check::reset();
+    }
+
+    #[tokio::test]
+    async fn idempotent_writes_skip_existing() {
+        let _lock = health_check::TEST_LOCK.lock().await;
+        health_check::reset();
+
+        // the point of the first location was written already
+        let locations = &locations::LOCATIONS.locations[..2];
+        let body = include_str!("../tests/fixtures/location-1.body.json");
+        let forecast: Forecast = serde_json::from_str(body).unwrap();
+        let existing = content_hash::content_hash(targets(locations)[0], &forecast).unwrap();
+        let csv = format!(
+            "#datatype,string,long,string\n\
+             #group,false,false,false\n\
+             #default,_result,,\n\
+             ,result,table,content_hash\n\
+             ,,0,{existing}\n"
+        );
+
+        let written = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let forecast = warp::get().and(warp::path("Vorhersage")).map(move || body);
+        let query = warp::post()
+            .and(warp::path!("api" / "v2" / "query"))
+            .map(move || csv.clone());
+        let write = warp::post()
+            .and(warp::path!("api" / "v2" / "write"))
+            .and(warp::body::bytes())
+            .map({
+                let written = written.clone();
+                move |body: warp::hyper::body::Bytes| {
+                    let body = String::from_utf8_lossy(&body).into_owned();
+                    written.lock().extend(body.lines().map(str::to_string));
+                    StatusCode::NO_CONTENT
+                }
+            });
+        let (addr, server) =
+            warp::serve(forecast.or(query).or(write)).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let url = format!("http://{addr}");
+
+        let sink = Sink::Influx {
+            client: Box::new(InfluxClient::new(&url, "org", "token")),
+            buckets: Buckets::from_lookup(|_| None, locations),
+            idempotent: true,
+            profile: None,
+        };
+        logging::capture();
+        let errors = collect(
+            &health_check::TEST_STATE,
+            1,
+            &targets(locations),
+            &api(&url),
+            &sink,
+        )
+        .await
+        .errors;
+        let lines = logging::take_captured();
+
+        assert!(errors.is_empty(), "{errors:?}");
+        let written = written.lock();
+        assert_eq!(written.len(), 1);
+        assert!(written[0].contains(",id=2,"), "{written:?}");
+        assert!(
+            lines
+                .iter()
+                .any(|line| line.contains("\"WW Großenkneten\" is in db for")),
+            "{lines:?}"
+        );
+
+        health_check::reset();
+    }
+
+    #[tokio::test]
+    async fn schema_markers() {
+        let _lock = health_check::TEST_LOCK.lock().await;
+        let marker = |version: i64| {
+            format!(
+                "#datatype,string,long,dateTime:RFC3339,long\n\
+                 #group,false,false,false,false\n\
+                 #default,_result,,,\n\
+                 ,result,table,_time,_value\n\
+                 ,,0,2024-03-07T08:00:00Z,{version}\n"
+            )
+        };
+        let check = |response: String, strict| async move {
+            let written = Arc::new(parking_lot::Mutex::new(Vec::new()));
+            let query = warp::post()
+                .and(warp::path!("api" / "v2" / "query"))
+                .map(move || response.clone());
+            let write = warp::post()
+                .and(warp::path!("api" / "v2" / "write"))
+                .and(warp::body::bytes())
+                .map({
+                    let written = written.clone();
+                    move |body: warp::hyper::body::Bytes| {
+                        written
+                            .lock()
+                            .push(String::from_utf8_lossy(&body).into_owned());
+                        StatusCode::NO_CONTENT
+                    }
+                });
+            let (addr, server) = warp::serve(query.or(write)).bind_ephemeral(([127, 0, 0, 1], 0));
+            tokio::spawn(server);
+
+            let sink = influx(&format!("http://{addr}"));
+            logging::capture();
+            let result = check_schema(&sink, strict).await;
+            let lines = logging::take_captured();
+            let written = written.lock().clone();
+            (result.ok(), written, lines)
+        };
+
+        // absent, the marker is written into the bucket, as on a first run
+        let (marked, written, _) = check(String::new(), true).await;
+        assert_eq!(marked, Some(false));
+        assert_eq!(written.len(), 1);
+        assert!(
+            written[0].starts_with("collector_schema,collector_version="),
+            "{written:?}"
+        );
+        assert!(written[0].contains(&format!(" version={}i ", schema::SCHEMA_VERSION)));
+
+        // matching, nothing to do
+        let (marked, written, lines) = check(marker(schema::SCHEMA_VERSION), true).await;
+        assert!(written.is_empty() && lines.is_empty(), "{lines:?}");
+        assert_eq!(marked, Some(true));
+
+        // older, warned about unless strict
+        let (marked, written, lines) = check(marker(0), false).await;
+        assert!(written.is_empty());
+        assert_eq!(marked, Some(true));
+        assert!(
+            lines.iter().any(|line| line.starts_with("WARN ")
+                && line.contains("holds points of schema version 0")),
+            "{lines:?}"
+        );
+        let (marked, _, lines) = check(marker(0), true).await;
+        assert_eq!(marked, None);
+        assert!(lines.iter().any(|line| line.contains("STRICT_SCHEMA")));
+    }
+
+    #[tokio::test]
+    async fn models_are_collected_independently() {
+        let _lock = health_check::TEST_LOCK.lock().await;
+        health_check::reset();
+
+        let written = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let forecast = warp::get()
+            .and(warp::path("Vorhersage"))
+            .map(|| include_str!("../tests/fixtures/location-1.body.json"));
+        let nowcast = warp::get()
+            .and(warp::path("Nowcast"))
+            .map(|| "<html>maintenance</html>");
+        let write = warp::post()
+            .and(warp::path!("api" / "v2" / "write"))
+            .and(warp::body::bytes())
+            .map({
+                let written = written.clone();
+                move |body: warp::hyper::body::Bytes| {
+                    let body = String::from_utf8_lossy(&body).into_owned();
+                    written.lock().extend(body.lines().map(str::to_string));
+                    StatusCode::NO_CONTENT
+                }
+            });
+        let (addr, server) =
+            warp::serve(forecast.or(nowcast).or(write)).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let url = format!("http://{addr}");
+
+        let locations = &locations::LOCATIONS.locations[..1];
+        let models = Models::from_lookup(
+            |key| {
+                (key == "FORECAST_MODELS")
+                    .then(|| "vorhersage=/Vorhersage,nowcast=/Nowcast".to_string())
+            },
+            locations,
+        )
+        .unwrap();
+        let targets = models.targets(locations);
+        let errors = collect(
+            &health_check::TEST_STATE,
+            1,
+            &targets,
+            &api(&url),
+            &influx(&url),
+        )
+        .await
+        .errors;
+
+        // the failing model does not keep the other one from being written
+        let written = written.lock();
+        assert_eq!(written.len(), 1);
+        assert!(written[0].contains(",model=vorhersage,"), "{written:?}");
+        let failed: Vec<_> = errors
+            .iter()
+            .map(|(target, _)| target.to_string())
+            .collect();
+        assert_eq!(failed, ["WW Großenkneten (nowcast)"]);
+
+        health_check::reset();
+    }
+
+    #[test]
+    fn renamed_fields_and_tags() {
+        let body = include_str!("../tests/fixtures/location-1.body.json");
+        let forecast: Forecast = serde_json::from_str(body).unwrap();
+        let model = locations::Model::default_model();
+        let target = Target {
+            location: &locations::LOCATIONS.locations[0],
+            model: &model,
+        };
+        let line = |names: &NameMapping, field_limit| {
+            let point = forecast_point_builder(
+                target,
+                &forecast,
+                false,
+                false,
+                field_limit,
+                GeoFields::Off,
+                names,
+            )
+            .unwrap()
+            .build()
+            .unwrap();
+            let mut line = Vec::new();
+            influxdb2::models::WriteDataPoint::write_data_point_to(&point, &mut line).unwrap();
+            String::from_utf8(line).unwrap()
+        };
+
+        let default = line(&NameMapping::default(), usize::MAX);
+        assert!(default.contains(",name=") && default.contains(" current="));
+        assert!(default.contains(",forecasts="));
+
+        let names = NameMapping::from_lookup(|key| match key {
+            "FIELD_NAME_MAP" => Some("current=aktuell_wert,forecasts=vorhersage_json".into()),
+            "TAG_NAME_MAP" => Some("name=standort".into()),
+            _ => None,
+        })
+        .unwrap();
+        let renamed = line(&names, usize::MAX);
+        assert!(renamed.contains(",standort=") && !renamed.contains(",name="));
+        assert!(renamed.contains(" aktuell_wert=") && !renamed.contains("current="));
+        assert!(renamed.contains(",vorhersage_json="));
+        assert_eq!(renamed.len(), default.len() + 4 + 5 + 6);
+
+        // split forecasts keep the renamed prefix
+        let split = line(&names, 64);
+        assert!(split.contains(",vorhersage_json_0=") && !split.contains("forecasts"));
+    }
+
+    #[tokio::test]
+    async fn offline_ticks() {
+        let _lock = health_check::TEST_LOCK.lock().await;
+        health_check::reset();
+
+        let listener = health_check::listen()
+            .unwrap()
+            .expect("socket mode is the default");
+        tokio::spawn(health_check::serve(
+            listener,
+            vec![health_check::TEST_STATE.clone()],
+        ));
+
+        let bodies = fixture::load_bodies(Path::new("tests/fixtures")).unwrap();
+        let source = ForecastSource::fixtures(bodies);
+        let state = &health_check::TEST_STATE;
+        let targets = targets(&locations::LOCATIONS.locations[..2]);
+
+        logging::capture();
+        for tick_id in 1..=2 {
+            let errors = collect(state, tick_id, &targets, &source, &Sink::Stdout)
+                .await
+                .errors;
+            assert!(errors.is_empty(), "{errors:?}");
+        }
+        let lines = logging::take_captured();
+
+        // the fixtures are replayed round-robin, one point per location and tick
+        let points: Vec<_> = lines
+            .iter()
+            .filter(|line| line.starts_with("forecast,"))
+            .collect();
+        assert_eq!(points.len(), 4);
+        assert!(points[0].contains(" current=\"{\\\"2024-03-07 08:05\\\":0}\""));
+        assert!(points[0].contains(",name=WW\\ Großenkneten,"));
+        assert!(exit_code_eq(
+            health_check::check(
+                &**health_check::TEST_CLOCK,
+                false,
+                health_check::Format::Plain
+            )
+            .await,
+            ExitCode::SUCCESS
+        ));
+
+        health_check::reset();
+    }
+
+    /// Records the notifications instead of delivering them.
+    #[derive(Default)]
+    struct Recorder {
+        /// Fields per alert.
+        alerts: parking_lot::Mutex<Vec<usize>>,
+        warnings: parking_lot::Mutex<Vec<String>>,
+    }
+
+    impl crate::webhook::Notifier for Recorder {
+        type Error = std::convert::Infallible;
+
+        fn channel(&self) -> &'static str {
+            "recorder"
+        }
+
+        fn deliver<'a>(
+            &'a self,
+            notification: &'a Notification,
+        ) -> futures::future::BoxFuture<'a, Result<(), Self::Error>> {
+            match notification {
+                Notification::Alert(fields) => self.alerts.lock().push(fields.len()),
+                Notification::Warning(message) => self.warnings.lock().push(message.clone()),
+                _ => (),
+            }
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    /// Serves the SWAT api and records the lines written into InfluxDB.
+    fn mock_profile() -> (SocketAddr, Arc<parking_lot::Mutex<Vec<String>>>) {
+        let writes = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let forecast = warp::get()
+            .and(warp::path("Vorhersage"))
+            .map(|| include_str!("../tests/fixtures/location-1.body.json"));
+        let write = warp::post()
+            .and(warp::path!("api" / "v2" / "write"))
+            .and(warp::body::bytes())
+            .map({
+                let writes = writes.clone();
+                move |body: warp::hyper::body::Bytes| {
+                    let body = String::from_utf8_lossy(&body);
+                    writes.lock().extend(body.lines().map(str::to_string));
+                    StatusCode::NO_CONTENT
+                }
+            });
+        let health = warp::get()
+            .and(warp::path("health"))
+            .map(|| r#"{"name": "influxdb", "status": "pass", "checks": []}"#);
+        let (addr, server) =
+            warp::serve(forecast.or(write).or(health)).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        (addr, writes)
+    }
+
+    #[tokio::test]
+    async fn profiles_are_isolated() {
+        let (addr_a, writes_a) = mock_profile();
+        let (addr_b, writes_b) = mock_profile();
+        let profile = |name: &str, addr: SocketAddr, api_path: &str| {
+            let url = format!("http://{addr}");
+            let sink = Sink::Influx {
+                client: Box::new(InfluxClient::new(&url, "org", "token")),
+                buckets: Buckets::from_lookup(|_| None, &locations::LOCATIONS.locations),
+                idempotent: false,
+                profile: Some(name.into()),
+            };
+            let state = AppState::default().with_profile(Some(name.to_string()));
+            let source = api(&format!("{url}{api_path}"));
+            (state, source, sink, NotificationQueue::new(16))
+        };
+        let (state_a, source_a, sink_a, queue_a) = profile("a", addr_a, "");
+        let (state_b, source_b, sink_b, queue_b) = profile("b", addr_b, "/unavailable");
+
+        // the swat api is unavailable for the second profile only
+        let targets = targets(&locations::LOCATIONS.locations[..2]);
+        let (report_a, report_b) = tokio::join!(
+            collect(&state_a, 1, &targets, &source_a, &sink_a),
+            collect(&state_b, 1, &targets, &source_b, &sink_b),
+        );
+        let (errors_a, errors_b) = (report_a.errors, report_b.errors);
+        assert!(errors_a.is_empty(), "{errors_a:?}");
+        assert_eq!(errors_b.len(), 2);
+
+        // each profile writes only its own, tagged points
+        let lines = writes_a.lock().clone();
+        assert_eq!(lines.len(), 2, "{lines:?}");
+        assert!(lines
+            .iter()
+            .all(|line| line.starts_with("forecast,profile=a,")));
+        assert!(writes_b.lock().is_empty());
+
+        handle_location_errors(&state_a, 1, &errors_a, None, None, &queue_a);
+        handle_location_errors(&state_b, 1, &errors_b, None, None, &queue_b);
+        let (recorder_a, recorder_b) = (Recorder::default(), Recorder::default());
+        let delay = Duration::from_millis(10);
+        let _ = tokio::time::timeout(Duration::from_millis(200), async {
+            tokio::join!(
+                queue_a.drain(&recorder_a, delay, delay),
+                queue_b.drain(&recorder_b, delay, delay),
+            )
+        })
+        .await;
+        assert!(recorder_a.alerts.lock().is_empty());
+        assert_eq!(*recorder_b.alerts.lock(), [2]);
+
+        assert!(state_a.health.stale_locations().is_empty());
+        assert_eq!(state_b.health.stale_locations().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn snoozed_locations_are_not_alerted() {
+        let _lock = health_check::TEST_LOCK.lock().await;
+        let clock = Arc::new(crate::clock::MockClock::new());
+        let state = AppState::default().with_clock(clock.clone());
+        let until = state.clock.now_utc() + chrono::Duration::hours(1);
+        state.snoozes.write().snooze("WW Großenkneten", until);
+        let notifications = NotificationQueue::new(16);
+
+        // the swat api is unavailable for both locations, but only one is alerted
+        let url = format!("http://{}/unavailable", mock_backends());
+        let targets = targets(&locations::LOCATIONS.locations[..2]);
+        let sink = influx(&url);
+        let mut report = collect(&state, 1, &targets, &api(&url), &sink).await;
+        assert_eq!(report.errors.len(), 2);
+        let snoozed = split_snoozed(&state, &mut report.errors);
+        assert_eq!(snoozed.len(), 1);
+        assert_eq!(snoozed[0].0.location.name, "WW Großenkneten");
+        handle_location_errors(&state, 1, &report.errors, None, None, &notifications);
+        handle_location_errors(&state, 1, &snoozed, None, None, &notifications);
+
+        let recorder = Recorder::default();
+        let delay = Duration::from_millis(10);
+        let _ = tokio::time::timeout(
+            Duration::from_millis(200),
+            notifications.drain(&recorder, delay, delay),
+        )
+        .await;
+        assert_eq!(*recorder.alerts.lock(), [1]);
+        assert_eq!(state.health.stale_locations(), ["WW Marienhafe"]);
+
+        // the snooze expires with a log line
+        clock.advance(Duration::from_secs(2 * 60 * 60));
+        logging::capture();
+        expire_snoozes(&state, 2);
+        let lines = logging::take_captured();
+        assert_eq!(lines.len(), 1, "{lines:?}");
+        assert!(lines[0].contains("[tick #2]: snooze of location \"WW Großenkneten\" expired at "));
+        let mut errors = snoozed;
+        assert!(split_snoozed(&state, &mut errors).is_empty());
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn skips_ticks_while_running() {
+        let _lock = health_check::TEST_LOCK.lock().await;
+        let state = AppState::default().with_skipped_ticks(SkippedTicks::new(2));
+        let notifications = NotificationQueue::new(16);
+        let period = Duration::from_secs(120);
+        let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+
+        // the tick runs into the fourth fire of the interval, but is never cancelled
+        logging::capture();
+        let slow_tick = async {
+            tokio::time::sleep(period * 3 + period / 2).await;
+            "finished"
+        };
+        let (output, due) =
+            skip_while_running(&state, 7, &mut interval, &notifications, slow_tick).await;
+        let lines = logging::take_captured();
+        assert_eq!(output, "finished");
+        assert!(!due);
+        assert_eq!(state.skipped_ticks.read().total(), 3);
+        assert!(
+            lines
+                .iter()
+                .any(|line| line.contains("[tick #7]: still running after 240.0s")),
+            "{lines:?}"
+        );
+
+        // the next fire is not a catch-up burst
+        let started = tokio::time::Instant::now();
+        interval.tick().await;
+        assert_eq!(started.elapsed(), period / 2);
+
+        // the third skip in a row exceeds the threshold of two and warns once
+        let recorder = Recorder::default();
+        let delay = Duration::from_millis(10);
+        let _ = tokio::time::timeout(
+            Duration::from_secs(1),
+            notifications.drain(&recorder, delay, delay),
+        )
+        .await;
+        assert_eq!(recorder.warnings.lock().len(), 1);
+        assert!(recorder.warnings.lock()[0].starts_with("skipped 3 ticks in a row"));
+
+        // a tick ending in time skips nothing
+        state.skipped_ticks.write().start_tick();
+        skip_while_running(&state, 8, &mut interval, &notifications, async {}).await;
+        assert_eq!(state.skipped_ticks.read().total(), 3);
+    }
+}
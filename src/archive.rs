@@ -0,0 +1,434 @@
+use crate::locations::{Forecast, Target};
+use arrow_array::builder::{Float64Builder, ListBuilder, StringBuilder};
+use arrow_array::{ArrayRef, Float64Array, RecordBatch, StringArray, TimestampMillisecondArray};
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef, TimeUnit};
+use chrono::{DateTime, NaiveDate, Utc};
+use parquet::arrow::ArrowWriter;
+use parquet::errors::ParquetError;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use thiserror::Error;
+
+mod s3;
+pub use s3::S3Upload;
+
+/// Forecasts kept in memory before they are written to the partition, unless
+/// `ARCHIVE_BUFFER_ROWS` is set.
+pub const DEFAULT_BUFFER_ROWS: usize = 1024;
+
+#[derive(Debug, Error)]
+pub enum ArchiveError {
+    #[error("could not access archive, {0}")]
+    Io(#[from] io::Error),
+
+    #[error("could not write parquet, {0}")]
+    Parquet(#[from] ParquetError),
+
+    #[error("could not build record batch, {0}")]
+    Arrow(#[from] ArrowError),
+}
+
+#[derive(Debug, Error)]
+pub enum ArchiveConfigError {
+    #[error("expected \"ARCHIVE_BUFFER_ROWS\" to be valid, {0}")]
+    BufferRows(#[from] std::num::ParseIntError),
+
+    #[error("{0:?} is required to upload to {1:?}")]
+    Missing(&'static str, String),
+}
+
+/// A successfully fetched forecast along with when and for which model it was fetched.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchivedForecast {
+    pub location: String,
+    pub model: String,
+    pub fetched_at: DateTime<Utc>,
+    pub issued: String,
+    pub lat: f64,
+    pub lon: f64,
+    pub current_time: String,
+    pub current_value: f64,
+    pub horizon_times: Vec<String>,
+    pub horizon_values: Vec<f64>,
+}
+
+impl ArchivedForecast {
+    pub fn new(target: Target, forecast: &Forecast, fetched_at: DateTime<Utc>) -> Self {
+        ArchivedForecast {
+            location: target.location.name.to_string(),
+            model: target.model.name.clone(),
+            fetched_at,
+            issued: forecast.from.clone(),
+            lat: forecast.lat,
+            lon: forecast.lon,
+            current_time: forecast.current.0.clone(),
+            current_value: forecast.current.1,
+            horizon_times: forecast.forecasts.keys().cloned().collect(),
+            horizon_values: forecast.forecasts.values().copied().collect(),
+        }
+    }
+}
+
+/// Schema of the archived forecasts, one row per fetch.
+pub fn schema() -> SchemaRef {
+    let list = |item| DataType::List(Arc::new(Field::new("item", item, true)));
+    Arc::new(Schema::new(vec![
+        Field::new("location", DataType::Utf8, false),
+        Field::new("model", DataType::Utf8, false),
+        Field::new(
+            "fetched_at",
+            DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+            false,
+        ),
+        Field::new("issued", DataType::Utf8, false),
+        Field::new("lat", DataType::Float64, false),
+        Field::new("lon", DataType::Float64, false),
+        Field::new("current_time", DataType::Utf8, false),
+        Field::new("current_value", DataType::Float64, false),
+        Field::new("horizon_times", list(DataType::Utf8), false),
+        Field::new("horizon_values", list(DataType::Float64), false),
+    ]))
+}
+
+fn record_batch(rows: &[ArchivedForecast]) -> Result<RecordBatch, ArrowError> {
+    let strings = |value: fn(&ArchivedForecast) -> &str| {
+        Arc::new(StringArray::from_iter_values(rows.iter().map(value))) as ArrayRef
+    };
+    let floats = |value: fn(&ArchivedForecast) -> f64| {
+        Arc::new(Float64Array::from_iter_values(rows.iter().map(value))) as ArrayRef
+    };
+    let fetched_at = rows.iter().map(|row| row.fetched_at.timestamp_millis());
+    let mut horizon_times = ListBuilder::new(StringBuilder::new());
+    let mut horizon_values = ListBuilder::new(Float64Builder::new());
+    for row in rows {
+        horizon_times.append_value(row.horizon_times.iter().map(Some));
+        horizon_values.append_value(row.horizon_values.iter().copied().map(Some));
+    }
+
+    RecordBatch::try_new(
+        schema(),
+        vec![
+            strings(|row| &row.location),
+            strings(|row| &row.model),
+            Arc::new(TimestampMillisecondArray::from_iter_values(fetched_at).with_timezone("UTC")),
+            strings(|row| &row.issued),
+            floats(|row| row.lat),
+            floats(|row| row.lon),
+            strings(|row| &row.current_time),
+            floats(|row| row.current_value),
+            Arc::new(horizon_times.finish()),
+            Arc::new(horizon_values.finish()),
+        ],
+    )
+}
+
+/// The parquet file of a day being written, named `.tmp` until it is closed.
+struct Partition {
+    date: NaiveDate,
+    path: PathBuf,
+    writer: ArrowWriter<File>,
+}
+
+impl Partition {
+    fn temporary(path: &Path) -> PathBuf {
+        path.with_extension("parquet.tmp")
+    }
+
+    fn close(self) -> Result<PathBuf, ArchiveError> {
+        self.writer.close()?;
+        fs::rename(Partition::temporary(&self.path), &self.path)?;
+        Ok(self.path)
+    }
+}
+
+/// Preserves every fetched forecast in daily partitions of parquet files under `ARCHIVE_DIR`,
+/// independent of InfluxDB.
+///
+/// Forecasts are buffered and written once [`buffer_rows`](Self::new) of them are pending,
+/// the partition of a day is closed and renamed to `<dir>/date=<day>/forecasts-<millis>.parquet`
+/// after midnight UTC. Partitions still open when the collector stops stay behind as `.tmp`
+/// files without a footer, as parquet files cannot be appended to.
+pub struct Archive {
+    dir: PathBuf,
+    buffer_rows: usize,
+    rows: Vec<ArchivedForecast>,
+    partition: Option<Partition>,
+
+    /// Partitions closed since the last [`rotate`](Self::rotate).
+    closed: Vec<PathBuf>,
+    upload: Option<S3Upload>,
+}
+
+impl std::fmt::Debug for Archive {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Archive")
+            .field("dir", &self.dir)
+            .field("buffer_rows", &self.buffer_rows)
+            .field("rows", &self.rows.len())
+            .field("partition", &self.partition.as_ref().map(|p| &p.path))
+            .field("closed", &self.closed)
+            .field("upload", &self.upload)
+            .finish()
+    }
+}
+
+impl Archive {
+    pub fn new(dir: PathBuf, buffer_rows: usize) -> Archive {
+        Archive {
+            dir,
+            buffer_rows: buffer_rows.max(1),
+            rows: Vec::new(),
+            partition: None,
+            closed: Vec::new(),
+            upload: None,
+        }
+    }
+
+    /// Reads `ARCHIVE_DIR`, `ARCHIVE_BUFFER_ROWS` and the upload to `ARCHIVE_S3_BUCKET` from
+    /// `lookup`, returns `None` if no archive is configured.
+    pub fn from_lookup(
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<Option<Archive>, ArchiveConfigError> {
+        let Some(dir) = lookup("ARCHIVE_DIR") else {
+            return Ok(None);
+        };
+        let buffer_rows = match lookup("ARCHIVE_BUFFER_ROWS") {
+            Some(rows) => rows.parse()?,
+            None => DEFAULT_BUFFER_ROWS,
+        };
+        let archive = Archive::new(dir.into(), buffer_rows);
+        Ok(Some(match S3Upload::from_lookup(lookup)? {
+            Some(upload) => archive.with_upload(upload),
+            None => archive,
+        }))
+    }
+
+    pub fn with_upload(self, upload: S3Upload) -> Archive {
+        Archive {
+            upload: Some(upload),
+            ..self
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn upload(&self) -> Option<&S3Upload> {
+        self.upload.as_ref()
+    }
+
+    /// Buffers the `row`, writing the buffer once it is full.
+    ///
+    /// The buffered rows are dropped if writing them fails, so the buffer stays bounded.
+    pub fn push(&mut self, row: ArchivedForecast) -> Result<(), ArchiveError> {
+        self.rows.push(row);
+        match self.rows.len() >= self.buffer_rows {
+            true => self.flush(),
+            false => Ok(()),
+        }
+    }
+
+    /// Writes the buffered rows into the partitions of their days.
+    pub fn flush(&mut self) -> Result<(), ArchiveError> {
+        let rows = std::mem::take(&mut self.rows);
+        for day in rows.chunk_by(|a, b| a.fetched_at.date_naive() == b.fetched_at.date_naive()) {
+            let first = &day[0].fetched_at;
+            let result = self.open(first).and_then(|partition| {
+                partition.writer.write(&record_batch(day)?)?;
+                partition.writer.flush()?;
+                Ok(())
+            });
+            if let Err(err) = result {
+                // the writer may have written half a row group, so the partition is abandoned
+                self.partition = None;
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the buffered rows of days before `now` and closes their partition, returns the
+    /// partitions closed since the last call.
+    pub fn rotate(&mut self, now: DateTime<Utc>) -> Result<Vec<PathBuf>, ArchiveError> {
+        let today = now.date_naive();
+        if self
+            .rows
+            .first()
+            .is_some_and(|row| row.fetched_at.date_naive() < today)
+        {
+            self.flush()?;
+        }
+        if self
+            .partition
+            .as_ref()
+            .is_some_and(|partition| partition.date < today)
+        {
+            self.close()?;
+        }
+        Ok(std::mem::take(&mut self.closed))
+    }
+
+    /// The partition for the day of `fetched_at`, closing the one of another day.
+    fn open(&mut self, fetched_at: &DateTime<Utc>) -> Result<&mut Partition, ArchiveError> {
+        let date = fetched_at.date_naive();
+        if self
+            .partition
+            .as_ref()
+            .is_some_and(|partition| partition.date != date)
+        {
+            self.close()?;
+        }
+
+        if self.partition.is_none() {
+            let dir = self.dir.join(format!("date={date}"));
+            fs::create_dir_all(&dir)?;
+            let path = dir.join(format!(
+                "forecasts-{}.parquet",
+                fetched_at.timestamp_millis()
+            ));
+            let file = File::create(Partition::temporary(&path))?;
+            self.partition = Some(Partition {
+                date,
+                path,
+                writer: ArrowWriter::try_new(file, schema(), None)?,
+            });
+        }
+        Ok(self.partition.as_mut().expect("opened above"))
+    }
+
+    fn close(&mut self) -> Result<(), ArchiveError> {
+        if let Some(partition) = self.partition.take() {
+            self.closed.push(partition.close()?);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{Float64Type, TimestampMillisecondType};
+    use chrono::TimeZone;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use std::env;
+
+    fn row(location: &str, fetched_at: DateTime<Utc>, horizons: u32) -> ArchivedForecast {
+        ArchivedForecast {
+            location: location.to_string(),
+            model: "vorhersage".to_string(),
+            fetched_at,
+            issued: "2024-03-07 08:05".to_string(),
+            lat: 52.9,
+            lon: 8.2,
+            current_time: "2024-03-07 08:00".to_string(),
+            current_value: 412.5,
+            horizon_times: (0..horizons)
+                .map(|h| format!("2024-03-07 {:02}:00", 9 + h))
+                .collect(),
+            horizon_values: (0..horizons).map(|h| 400.0 + h as f64).collect(),
+        }
+    }
+
+    fn read(path: &Path) -> RecordBatch {
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let mut batches: Vec<_> = reader.map(Result::unwrap).collect();
+        assert_eq!(batches.len(), 1, "row groups are read as one batch");
+        batches.remove(0)
+    }
+
+    #[test]
+    fn writes_daily_partitions() {
+        let dir = env::temp_dir().join(format!("swat-collector-archive-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut archive = Archive::new(dir.clone(), 2);
+        let day = Utc.with_ymd_and_hms(2024, 3, 7, 8, 6, 0).unwrap();
+        let next_day = Utc.with_ymd_and_hms(2024, 3, 8, 0, 1, 0).unwrap();
+
+        archive.push(row("WW Großenkneten", day, 3)).unwrap();
+        archive.push(row("WW Marienhafe", day, 0)).unwrap();
+        archive.push(row("WW Thülsfelde", day, 1)).unwrap();
+        assert_eq!(archive.rotate(day).unwrap(), Vec::<PathBuf>::new());
+
+        // after midnight the buffered row is written to its day before the partition closes
+        let closed = archive.rotate(next_day).unwrap();
+        let path = dir.join("date=2024-03-07/forecasts-1709798760000.parquet");
+        assert_eq!(closed, std::slice::from_ref(&path));
+        assert!(!Partition::temporary(&path).exists());
+
+        let batch = read(&path);
+        assert_eq!(batch.schema(), schema());
+        assert_eq!(batch.num_rows(), 3);
+        let locations: Vec<_> = batch
+            .column(0)
+            .as_string::<i32>()
+            .iter()
+            .flatten()
+            .collect();
+        assert_eq!(
+            locations,
+            ["WW Großenkneten", "WW Marienhafe", "WW Thülsfelde"]
+        );
+        let fetched_at = batch.column(2).as_primitive::<TimestampMillisecondType>();
+        assert_eq!(fetched_at.value(0), day.timestamp_millis());
+        let horizon_times = batch.column(8).as_list::<i32>();
+        let first: Vec<_> = horizon_times
+            .value(0)
+            .as_string::<i32>()
+            .iter()
+            .flatten()
+            .map(str::to_string)
+            .collect();
+        assert_eq!(first, row("", day, 3).horizon_times);
+        assert_eq!(horizon_times.value(1).len(), 0);
+        let horizon_values = batch.column(9).as_list::<i32>();
+        assert_eq!(
+            horizon_values
+                .value(2)
+                .as_primitive::<Float64Type>()
+                .values(),
+            &[400.0]
+        );
+
+        // the next day gets a partition of its own
+        archive.push(row("WW Großenkneten", next_day, 2)).unwrap();
+        archive.flush().unwrap();
+        assert!(
+            Partition::temporary(&dir.join("date=2024-03-08/forecasts-1709856060000.parquet"))
+                .exists()
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn config_from_lookup() {
+        assert!(Archive::from_lookup(|_| None).unwrap().is_none());
+
+        let archive = Archive::from_lookup(|key| match key {
+            "ARCHIVE_DIR" => Some("/var/lib/swat".to_string()),
+            "ARCHIVE_BUFFER_ROWS" => Some("12".to_string()),
+            _ => None,
+        })
+        .unwrap()
+        .unwrap();
+        assert_eq!(archive.dir(), Path::new("/var/lib/swat"));
+        assert_eq!(archive.buffer_rows, 12);
+        assert!(archive.upload().is_none());
+
+        let missing = Archive::from_lookup(|key| match key {
+            "ARCHIVE_DIR" => Some("/var/lib/swat".to_string()),
+            "ARCHIVE_S3_BUCKET" => Some("forecasts".to_string()),
+            _ => None,
+        });
+        assert!(matches!(
+            missing,
+            Err(ArchiveConfigError::Missing("ARCHIVE_S3_ACCESS_KEY_ID", _))
+        ));
+    }
+}
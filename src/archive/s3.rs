@@ -0,0 +1,257 @@
+use super::ArchiveConfigError;
+use crate::egress::{self, Class};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::fmt::{self, Write};
+use std::path::Path;
+use std::{fs, io};
+use thiserror::Error;
+
+const DEFAULT_REGION: &str = "us-east-1";
+
+#[derive(Debug, Error)]
+pub enum UploadError {
+    #[error("could not read partition, {0}")]
+    Io(#[from] io::Error),
+
+    #[error("upload failed, {0}")]
+    Request(#[from] reqwest::Error),
+}
+
+/// Uploads the closed partitions to S3-compatible storage, signed with AWS signature version 4.
+///
+/// Configured via `ARCHIVE_S3_BUCKET`, `ARCHIVE_S3_ACCESS_KEY_ID` and
+/// `ARCHIVE_S3_SECRET_ACCESS_KEY`, optionally `ARCHIVE_S3_REGION`, `ARCHIVE_S3_ENDPOINT` for
+/// storage other than AWS and `ARCHIVE_S3_PREFIX` for the object keys. Objects are addressed by
+/// path, which every S3-compatible storage supports.
+#[derive(Clone)]
+pub struct S3Upload {
+    client: reqwest::Client,
+    endpoint: String,
+    bucket: String,
+    region: String,
+    prefix: String,
+    access_key_id: String,
+    secret_access_key: String,
+}
+
+impl fmt::Debug for S3Upload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("S3Upload")
+            .field("endpoint", &self.endpoint)
+            .field("bucket", &self.bucket)
+            .field("region", &self.region)
+            .field("prefix", &self.prefix)
+            .finish_non_exhaustive()
+    }
+}
+
+impl S3Upload {
+    /// Reads the `ARCHIVE_S3_*` variables from `lookup`, returns `None` if no bucket is
+    /// configured.
+    pub fn from_lookup(
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<Option<S3Upload>, ArchiveConfigError> {
+        let Some(bucket) = lookup("ARCHIVE_S3_BUCKET") else {
+            return Ok(None);
+        };
+        let required =
+            |key| lookup(key).ok_or_else(|| ArchiveConfigError::Missing(key, bucket.clone()));
+        let access_key_id = required("ARCHIVE_S3_ACCESS_KEY_ID")?;
+        let secret_access_key = required("ARCHIVE_S3_SECRET_ACCESS_KEY")?;
+        let region = lookup("ARCHIVE_S3_REGION").unwrap_or_else(|| DEFAULT_REGION.to_string());
+        let endpoint = lookup("ARCHIVE_S3_ENDPOINT")
+            .unwrap_or_else(|| format!("https://s3.{region}.amazonaws.com"));
+        Ok(Some(S3Upload {
+            client: reqwest::Client::new(),
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            bucket,
+            region,
+            prefix: lookup("ARCHIVE_S3_PREFIX")
+                .map(|prefix| prefix.trim_matches('/').to_string() + "/")
+                .filter(|prefix| prefix != "/")
+                .unwrap_or_default(),
+            access_key_id,
+            secret_access_key,
+        }))
+    }
+
+    /// Uploads the partition at `path` under its path relative to the archive `dir`, returns
+    /// the object key.
+    pub async fn upload(
+        &self,
+        dir: &Path,
+        path: &Path,
+        now: DateTime<Utc>,
+    ) -> Result<String, UploadError> {
+        let relative = path.strip_prefix(dir).unwrap_or(path);
+        let key = format!("{}{}", self.prefix, relative.to_string_lossy());
+        let body = fs::read(path)?;
+
+        let uri = format!("/{}/{}", uri_encode(&self.bucket), uri_encode(&key));
+        let url = format!("{}{uri}", self.endpoint);
+        let host = reqwest::Url::parse(&url)
+            .ok()
+            .and_then(|url| {
+                let host = url.host_str()?.to_string();
+                Some(match url.port() {
+                    Some(port) => format!("{host}:{port}"),
+                    None => host,
+                })
+            })
+            .unwrap_or_default();
+        let payload_hash = hex(&Sha256::digest(&body));
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let authorization = self.authorization(&uri, &host, &payload_hash, &amz_date);
+
+        let request = self
+            .client
+            .put(url)
+            .header("x-amz-content-sha256", payload_hash)
+            .header("x-amz-date", amz_date)
+            .header("authorization", authorization)
+            .body(body);
+        egress::METER
+            .send(Class::Other, request)
+            .await?
+            .error_for_status()?;
+        Ok(key)
+    }
+
+    fn authorization(&self, uri: &str, host: &str, payload_hash: &str, amz_date: &str) -> String {
+        const SIGNED_HEADERS: &str = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "PUT\n{uri}\n\nhost:{host}\nx-amz-content-sha256:{payload_hash}\nx-amz-date:{amz_date}\n\n{SIGNED_HEADERS}\n{payload_hash}"
+        );
+        let date = &amz_date[..8];
+        let scope = format!("{date}/{}/s3/aws4_request", self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+            hex(&Sha256::digest(canonical_request))
+        );
+        let key = signing_key(&self.secret_access_key, date, &self.region, "s3");
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={SIGNED_HEADERS}, Signature={}",
+            self.access_key_id,
+            hex(&hmac(&key, &string_to_sign))
+        )
+    }
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac takes keys of any length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn signing_key(secret: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let key = hmac(format!("AWS4{secret}").as_bytes(), date);
+    let key = hmac(&key, region);
+    let key = hmac(&key, service);
+    hmac(&key, "aws4_request")
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
+
+/// Percent-encodes everything but the unreserved characters and `/`, as the signature expects.
+fn uri_encode(path: &str) -> String {
+    path.bytes().fold(String::new(), |mut encoded, byte| {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => {
+                let _ = write!(encoded, "%{byte:02X}");
+            }
+        }
+        encoded
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+    use warp::http::HeaderMap;
+    use warp::Filter;
+
+    #[test]
+    fn derives_signing_key() {
+        // the example of the AWS documentation on deriving the signing key
+        let key = signing_key(
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "20120215",
+            "us-east-1",
+            "iam",
+        );
+        assert_eq!(
+            hex(&key),
+            "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+        );
+        assert_eq!(uri_encode("date=2024-03-07/a b"), "date%3D2024-03-07/a%20b");
+    }
+
+    #[tokio::test]
+    async fn uploads_partition() {
+        type Received = Arc<Mutex<Vec<(String, HeaderMap, Vec<u8>)>>>;
+        let received = Received::default();
+        let recorded = received.clone();
+        let route = warp::put()
+            .and(warp::path::full())
+            .and(warp::header::headers_cloned())
+            .and(warp::body::bytes())
+            .map(
+                move |path: warp::path::FullPath, headers, body: warp::hyper::body::Bytes| {
+                    recorded
+                        .lock()
+                        .push((path.as_str().to_string(), headers, body.to_vec()));
+                    warp::reply()
+                },
+            );
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        let upload = S3Upload::from_lookup(|key| match key {
+            "ARCHIVE_S3_BUCKET" => Some("forecasts".to_string()),
+            "ARCHIVE_S3_ENDPOINT" => Some(format!("http://{addr}/")),
+            "ARCHIVE_S3_PREFIX" => Some("/swat/".to_string()),
+            "ARCHIVE_S3_ACCESS_KEY_ID" => Some("AKIDEXAMPLE".to_string()),
+            "ARCHIVE_S3_SECRET_ACCESS_KEY" => Some("secret".to_string()),
+            _ => None,
+        })
+        .unwrap()
+        .unwrap();
+        assert!(!format!("{upload:?}").contains("secret\""));
+
+        let dir = std::env::temp_dir().join(format!("swat-collector-s3-{}", std::process::id()));
+        let path = dir.join("date=2024-03-07/forecasts-1.parquet");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "PAR1").unwrap();
+        let now = Utc.with_ymd_and_hms(2024, 3, 8, 0, 1, 0).unwrap();
+        let key = upload.upload(&dir, &path, now).await.unwrap();
+        fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(key, "swat/date=2024-03-07/forecasts-1.parquet");
+        let received = received.lock();
+        let (path, headers, body) = &received[0];
+        assert_eq!(
+            path,
+            "/forecasts/swat/date%3D2024-03-07/forecasts-1.parquet"
+        );
+        assert_eq!(body, b"PAR1");
+        assert_eq!(headers["x-amz-date"], "20240308T000100Z");
+        let authorization = headers["authorization"].to_str().unwrap();
+        assert!(authorization.starts_with(
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20240308/us-east-1/s3/aws4_request, \
+             SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature="
+        ));
+    }
+}
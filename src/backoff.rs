@@ -0,0 +1,145 @@
+use crate::locations::Target;
+use crate::points::HandleLocationError;
+use std::time::Duration;
+
+/// How a tick went, deciding whether the collection loop backs off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TickOutcome {
+    /// At least one location was collected.
+    Collected,
+
+    /// Every location failed with a request error, the swat api is most likely down.
+    Unreachable,
+
+    /// Every location failed, but not only because of the requests.
+    Failed,
+}
+
+impl TickOutcome {
+    /// The outcome of collecting `targets` forecasts, of which `errors` failed.
+    pub fn of(targets: usize, errors: &[(Target, HandleLocationError)]) -> TickOutcome {
+        if errors.len() < targets || targets == 0 {
+            return TickOutcome::Collected;
+        }
+        match errors.iter().all(|(_, error)| error.kind().is_request()) {
+            true => TickOutcome::Unreachable,
+            false => TickOutcome::Failed,
+        }
+    }
+}
+
+/// Interval of the collection loop, doubled up to `max` for every tick the swat api is
+/// unreachable and reset once a location is collected again.
+#[derive(Debug)]
+pub struct LoopBackoff {
+    configured: Duration,
+    max: Duration,
+    current: Duration,
+}
+
+impl LoopBackoff {
+    pub fn new(interval: Duration, max: Duration) -> LoopBackoff {
+        LoopBackoff {
+            configured: interval,
+            max: max.max(interval),
+            current: interval,
+        }
+    }
+
+    /// The effective interval until the next tick.
+    pub fn interval(&self) -> Duration {
+        self.current
+    }
+
+    /// How much longer than configured the effective interval currently is.
+    #[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+    pub fn backoff(&self) -> Duration {
+        self.current - self.configured
+    }
+
+    /// Records the `outcome` of a tick, returns the new interval if it changed.
+    pub fn record(&mut self, outcome: TickOutcome) -> Option<Duration> {
+        let next = match outcome {
+            TickOutcome::Collected => self.configured,
+            TickOutcome::Unreachable => (self.current * 2).min(self.max),
+            TickOutcome::Failed => self.current,
+        };
+        if next == self.current {
+            return None;
+        }
+        self.current = next;
+        Some(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locations::{Model, RequestLocationError};
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn backs_off_up_to_max() {
+        let mut backoff = LoopBackoff::new(2 * MINUTE, 30 * MINUTE);
+        let intervals: Vec<_> = (0..6)
+            .map(|_| backoff.record(TickOutcome::Unreachable))
+            .collect();
+        assert_eq!(
+            intervals,
+            [
+                Some(4 * MINUTE),
+                Some(8 * MINUTE),
+                Some(16 * MINUTE),
+                Some(30 * MINUTE),
+                None,
+                None
+            ]
+        );
+        assert_eq!(backoff.interval(), 30 * MINUTE);
+        assert_eq!(backoff.backoff(), 28 * MINUTE);
+    }
+
+    #[test]
+    fn collecting_resets() {
+        let mut backoff = LoopBackoff::new(2 * MINUTE, 30 * MINUTE);
+        assert_eq!(backoff.record(TickOutcome::Collected), None);
+        backoff.record(TickOutcome::Unreachable);
+        backoff.record(TickOutcome::Unreachable);
+
+        // other failures neither back off further nor reset
+        assert_eq!(backoff.record(TickOutcome::Failed), None);
+        assert_eq!(backoff.interval(), 8 * MINUTE);
+
+        assert_eq!(backoff.record(TickOutcome::Collected), Some(2 * MINUTE));
+        assert_eq!(backoff.backoff(), Duration::ZERO);
+    }
+
+    #[test]
+    fn outcome_of_tick() {
+        let locations = &crate::locations::LOCATIONS.locations;
+        let model = Model::default_model();
+        let target = |i: usize| Target {
+            location: &locations[i],
+            model: &model,
+        };
+        let parse_error = crate::timestamp::parse("").unwrap_err();
+        let request_error = || {
+            let error = reqwest::Client::new().get("no url").build().unwrap_err();
+            HandleLocationError::RequestForecast(RequestLocationError::from(error))
+        };
+
+        assert_eq!(TickOutcome::of(2, &[]), TickOutcome::Collected);
+        let errors = [(target(0), request_error())];
+        assert_eq!(TickOutcome::of(2, &errors), TickOutcome::Collected);
+        assert_eq!(TickOutcome::of(1, &errors), TickOutcome::Unreachable);
+        let errors = [
+            (target(0), request_error()),
+            (
+                target(1),
+                HandleLocationError::ParseFromTimestamp(parse_error),
+            ),
+        ];
+        assert_eq!(TickOutcome::of(2, &errors), TickOutcome::Failed);
+    }
+}
@@ -0,0 +1,193 @@
+use std::borrow::Borrow;
+use std::collections::BTreeMap;
+
+/// Per-location caches hold this many entries per location and model unless `CACHE_CAPACITY`
+/// is set.
+pub const DEFAULT_CAPACITY_FACTOR: usize = 4;
+
+/// Capacity of the per-location caches until they are sized for the collected locations.
+pub const DEFAULT_CAPACITY: usize = 256;
+
+/// Map holding at most `capacity` entries, evicting the least recently used one.
+///
+/// Used for the state kept per location and model, so entries of locations that are no longer
+/// collected cannot pile up. Those are also dropped right away with [`retain`](Self::retain).
+#[derive(Debug)]
+pub struct BoundedCache<K, V> {
+    capacity: usize,
+    entries: BTreeMap<K, (u64, V)>,
+
+    /// Keys by the stamp of their last use, the least recently used first.
+    order: BTreeMap<u64, K>,
+    next_stamp: u64,
+    evictions: u64,
+}
+
+impl<K: Ord + Clone, V> BoundedCache<K, V> {
+    pub const fn new(capacity: usize) -> BoundedCache<K, V> {
+        BoundedCache {
+            capacity,
+            entries: BTreeMap::new(),
+            order: BTreeMap::new(),
+            next_stamp: 0,
+            evictions: 0,
+        }
+    }
+
+    /// Entries evicted to stay within the capacity since the cache was created.
+    pub fn evictions(&self) -> u64 {
+        self.evictions
+    }
+
+    /// The entry of `key` without marking it as used.
+    #[cfg_attr(not(any(test, feature = "grpc")), allow(dead_code))]
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.entries.get(key).map(|(_, value)| value)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let stamp = self.next_stamp;
+        let (last_used, value) = self.entries.get_mut(key)?;
+        let key = self
+            .order
+            .remove(last_used)
+            .expect("every entry is ordered");
+        *last_used = stamp;
+        self.order.insert(stamp, key);
+        self.next_stamp += 1;
+        Some(value)
+    }
+
+    /// The entry of `key`, inserted with `default` if there is none.
+    pub fn get_or_insert_with(&mut self, key: K, default: impl FnOnce() -> V) -> &mut V {
+        if !self.entries.contains_key(&key) {
+            self.insert(key.clone(), default());
+        }
+        self.get_mut(&key).expect("inserted above")
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let previous = self.remove(&key);
+        self.evict(self.capacity.max(1) - 1);
+        self.order.insert(self.next_stamp, key.clone());
+        self.entries.insert(key, (self.next_stamp, value));
+        self.next_stamp += 1;
+        previous
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let (stamp, value) = self.entries.remove(key)?;
+        self.order.remove(&stamp);
+        Some(value)
+    }
+
+    /// Drops the entries whose key does not satisfy `keep`, these do not count as evictions.
+    pub fn retain(&mut self, mut keep: impl FnMut(&K) -> bool) {
+        self.entries.retain(|key, _| keep(key));
+        self.order.retain(|_, key| self.entries.contains_key(key));
+    }
+
+    /// Sets the capacity, evicting the least recently used entries exceeding it.
+    pub fn resize(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict(capacity.max(1));
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.entries.values().map(|(_, value)| value)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.entries.keys()
+    }
+
+    /// Evicts the least recently used entries until at most `len` are left.
+    fn evict(&mut self, len: usize) {
+        while self.entries.len() > len {
+            let Some((_, key)) = self.order.pop_first() else {
+                break;
+            };
+            self.entries.remove(&key);
+            self.evictions += 1;
+        }
+    }
+}
+
+/// Capacity of the per-location caches for `targets` locations and models, `configured` via
+/// `CACHE_CAPACITY` or [`DEFAULT_CAPACITY_FACTOR`] times the targets.
+pub fn capacity(configured: Option<usize>, targets: usize) -> usize {
+    configured
+        .unwrap_or(DEFAULT_CAPACITY_FACTOR * targets)
+        .max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(cache: &BoundedCache<String, u32>) -> Vec<&str> {
+        cache.keys().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = BoundedCache::new(3);
+        for (i, key) in ["A", "B", "C"].into_iter().enumerate() {
+            cache.insert(key.to_string(), i as u32);
+        }
+
+        // reading without using keeps the order, using moves an entry to the back
+        assert_eq!(cache.get("A"), Some(&0));
+        *cache.get_mut("A").unwrap() += 10;
+        cache.insert("D".to_string(), 3);
+        assert_eq!(keys(&cache), ["A", "C", "D"]);
+        assert_eq!(cache.evictions(), 1);
+
+        *cache.get_or_insert_with("E".to_string(), || 4) += 1;
+        assert_eq!(keys(&cache), ["A", "D", "E"]);
+        assert_eq!(cache.get("E"), Some(&5));
+        assert_eq!(cache.evictions(), 2);
+
+        // replacing and removing does not evict
+        cache.insert("A".to_string(), 0);
+        assert_eq!(cache.remove("D"), Some(3));
+        assert_eq!(cache.keys().count(), 2);
+        assert_eq!(cache.evictions(), 2);
+    }
+
+    #[test]
+    fn retain_and_resize() {
+        let mut cache = BoundedCache::new(4);
+        for (i, key) in ["A", "B", "C", "D"].into_iter().enumerate() {
+            cache.insert(key.to_string(), i as u32);
+        }
+
+        cache.retain(|key| key != "B");
+        assert_eq!(keys(&cache), ["A", "C", "D"]);
+        assert_eq!(cache.evictions(), 0);
+
+        cache.resize(2);
+        assert_eq!(keys(&cache), ["C", "D"]);
+        assert_eq!(cache.evictions(), 1);
+
+        cache.resize(3);
+        cache.insert("E".to_string(), 4);
+        assert_eq!(keys(&cache), ["C", "D", "E"]);
+
+        assert_eq!(capacity(None, 24), 96);
+        assert_eq!(capacity(Some(10), 24), 10);
+        assert_eq!(capacity(None, 0), 1);
+    }
+}
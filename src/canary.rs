@@ -0,0 +1,183 @@
+use crate::egress::{self, Class};
+use crate::locations::{self, RequestLocationError, Target};
+use crate::points::HandleLocationError;
+use crate::severity::{ParseSeverityError, Severity};
+use crate::webhook::AlertField;
+use reqwest::Client as ReqwestClient;
+
+/// Name the canary is reported under in alerts and the health status.
+pub const NAME: &str = "canary";
+
+/// Endpoint under our own control serving a forecast in the format of the swat api, requested
+/// every tick to tell a broken swat api apart from a broken network or host.
+///
+/// Configured via `CANARY_URL`, its failures are alerted with `CANARY_SEVERITY`, critical by
+/// default.
+#[derive(Debug)]
+pub struct Canary {
+    url: String,
+    severity: Severity,
+}
+
+/// What the canary and the locations of a tick tell about the cause of the errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Diagnosis {
+    /// The canary and every location succeeded.
+    Healthy,
+
+    /// Only some locations failed, so the swat api is reachable.
+    Partial,
+
+    /// Every location failed while the canary succeeded, likely the swat api is broken.
+    Upstream,
+
+    /// The canary failed, likely our network or host is broken.
+    Local,
+}
+
+impl Diagnosis {
+    pub fn of(canary_ok: bool, failed: usize, targets: usize) -> Diagnosis {
+        match (canary_ok, failed) {
+            (false, _) => Diagnosis::Local,
+            (true, 0) => Diagnosis::Healthy,
+            (true, failed) if failed >= targets => Diagnosis::Upstream,
+            (true, _) => Diagnosis::Partial,
+        }
+    }
+}
+
+impl Canary {
+    pub fn new(url: String, severity: Severity) -> Canary {
+        Canary { url, severity }
+    }
+
+    /// Reads `CANARY_URL` and `CANARY_SEVERITY` from `lookup`, returns `None` if no canary is
+    /// configured.
+    pub fn from_lookup(
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<Option<Canary>, ParseSeverityError> {
+        let Some(url) = lookup("CANARY_URL") else {
+            return Ok(None);
+        };
+        let severity = match lookup("CANARY_SEVERITY") {
+            Some(severity) => severity.parse()?,
+            None => Severity::Critical,
+        };
+        Ok(Some(Canary::new(url, severity)))
+    }
+
+    /// Requests the canary forecast, which has to parse like one of the swat api.
+    pub async fn check(&self, client: &ReqwestClient) -> Result<(), RequestLocationError> {
+        let response = egress::METER
+            .send(Class::Swat, client.get(&self.url))
+            .await?;
+        locations::parse_forecast(response.text().await?).map(drop)
+    }
+
+    /// The field added to the alert of the tick, if the canary tells anything about its
+    /// `errors`.
+    ///
+    /// A failed canary is alerted on its own, alerts for every location failing note that the
+    /// canary succeeded.
+    pub fn alert_field(
+        &self,
+        result: &Result<(), RequestLocationError>,
+        errors: &[(Target, HandleLocationError)],
+        targets: usize,
+        tick_id: u64,
+    ) -> Option<AlertField> {
+        match (Diagnosis::of(result.is_ok(), errors.len(), targets), result) {
+            (Diagnosis::Local, Err(err)) => Some(AlertField::new(
+                NAME.to_string(),
+                format!("canary failed — likely local/network issue, {err}"),
+                self.severity,
+                tick_id,
+            )),
+            (Diagnosis::Upstream, _) => {
+                // routed along with the errors it is about
+                let severity = errors.iter().map(|(_, error)| error.severity()).max()?;
+                Some(AlertField::new(
+                    NAME.to_string(),
+                    "canary succeeded in the same tick, likely an upstream issue".to_string(),
+                    severity,
+                    tick_id,
+                ))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locations::{Model, LOCATIONS};
+    use warp::Filter;
+
+    #[test]
+    fn classification() {
+        // canary ok or failed, every other location ok or failed
+        assert_eq!(Diagnosis::of(true, 0, 3), Diagnosis::Healthy);
+        assert_eq!(Diagnosis::of(true, 3, 3), Diagnosis::Upstream);
+        assert_eq!(Diagnosis::of(false, 0, 3), Diagnosis::Local);
+        assert_eq!(Diagnosis::of(false, 3, 3), Diagnosis::Local);
+
+        assert_eq!(Diagnosis::of(true, 1, 3), Diagnosis::Partial);
+        assert_eq!(Diagnosis::of(false, 1, 3), Diagnosis::Local);
+    }
+
+    #[tokio::test]
+    async fn alert_fields() {
+        let routes = warp::path("ok")
+            .map(|| include_str!("../tests/fixtures/location-1.body.json"))
+            .or(warp::path("broken").map(|| "<html>maintenance</html>"));
+        let (addr, server) = warp::serve(routes).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let client = ReqwestClient::new();
+        let canary = |path: &str| Canary::new(format!("http://{addr}/{path}"), Severity::Critical);
+
+        let ok = canary("ok").check(&client).await;
+        assert!(ok.is_ok(), "{ok:?}");
+        let failed = canary("broken").check(&client).await;
+        assert!(failed.is_err());
+
+        let model = Model::default_model();
+        let parse_error = crate::timestamp::parse("").unwrap_err();
+        let errors = [(
+            Target {
+                location: &LOCATIONS.locations[0],
+                model: &model,
+            },
+            HandleLocationError::ParseFromTimestamp(parse_error),
+        )];
+
+        let canary = canary("ok");
+        assert!(canary.alert_field(&ok, &[], 1, 1).is_none());
+        assert!(canary.alert_field(&ok, &errors, 2, 1).is_none());
+        let upstream = format!("{:?}", canary.alert_field(&ok, &errors, 1, 1).unwrap());
+        assert!(upstream.contains("likely an upstream issue"), "{upstream}");
+        assert!(upstream.contains("severity: Warning"), "{upstream}");
+        for errors in [&errors[..], &[]] {
+            let local = format!("{:?}", canary.alert_field(&failed, errors, 1, 1).unwrap());
+            assert!(
+                local.contains("\"canary failed — likely local/network issue, parsing failed"),
+                "{local}"
+            );
+            assert!(local.contains("severity: Critical"), "{local}");
+        }
+    }
+
+    #[test]
+    fn parse_config() {
+        assert!(Canary::from_lookup(|_| None).unwrap().is_none());
+        let canary = Canary::from_lookup(|key| match key {
+            "CANARY_URL" => Some("http://canary.local/forecast.json".to_string()),
+            "CANARY_SEVERITY" => Some("warning".to_string()),
+            _ => None,
+        })
+        .unwrap()
+        .unwrap();
+        assert_eq!(canary.severity, Severity::Warning);
+        assert!(Canary::from_lookup(|key| Some(key.to_string())).is_err());
+    }
+}
@@ -0,0 +1,448 @@
+use crate::fixture;
+use crate::locations::Location;
+use parking_lot::Mutex;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+use std::{fmt, fs, io};
+use thiserror::Error;
+
+/// Status of the injected server errors unless `status` is set.
+pub const DEFAULT_STATUS: u16 = 503;
+
+/// Delay of the injected slow responses unless `delay_seconds` is set.
+pub const DEFAULT_DELAY: Duration = Duration::from_secs(30);
+
+/// Text of the injected write failures, telling them apart from those of InfluxDB.
+pub const WRITE_FAILURE_TEXT: &str = "synthetic write failure, injected by CHAOS_CONFIG";
+
+#[derive(Debug, Error)]
+pub enum ChaosError {
+    #[error("could not read {path:?}, {error}")]
+    Read { path: String, error: io::Error },
+
+    #[error(transparent)]
+    Toml(#[from] toml::de::Error),
+
+    #[error("fault #{0} needs either `probability` or `every`")]
+    Schedule(usize),
+
+    #[error("fault #{0} has a probability outside of 0 to 1")]
+    Probability(usize),
+
+    #[error("fault #{0} is never injected, `every` must be at least 1")]
+    Every(usize),
+
+    #[error("fault #{0} has status {1}, expected a server error")]
+    Status(usize, u16),
+
+    #[error("fault #{0} is injected into the unknown location {1:?}")]
+    Location(usize, String),
+}
+
+/// A failure injected instead of a forecast request or a write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// The forecast request times out.
+    Timeout,
+
+    /// The swat api answers with the status.
+    ServerError(u16),
+
+    /// The swat api answers with a body that cannot be parsed.
+    Garbage,
+
+    /// The forecast is requested after the delay, the request itself succeeds.
+    Slow(Duration),
+
+    /// InfluxDB fails the write.
+    WriteFailure,
+}
+
+impl Fault {
+    /// Short code counting the injections in the status.
+    pub fn code(self) -> &'static str {
+        match self {
+            Fault::Timeout => "timeout",
+            Fault::ServerError(_) => "server_error",
+            Fault::Garbage => "garbage",
+            Fault::Slow(_) => "slow",
+            Fault::WriteFailure => "write_failure",
+        }
+    }
+
+    fn is_write(self) -> bool {
+        self == Fault::WriteFailure
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fault::Timeout => write!(f, "request timeout"),
+            Fault::ServerError(status) => write!(f, "{status} response"),
+            Fault::Garbage => write!(f, "unparsable response"),
+            Fault::Slow(delay) => write!(f, "slow response of {}s", delay.as_secs()),
+            Fault::WriteFailure => write!(f, "write failure"),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Spec {
+    #[serde(default)]
+    seed: u64,
+
+    #[serde(default, rename = "fault")]
+    faults: Vec<FaultSpec>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum FaultKind {
+    Timeout,
+    ServerError,
+    Garbage,
+    Slow,
+    WriteFailure,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FaultSpec {
+    kind: FaultKind,
+    location: Option<String>,
+    probability: Option<f64>,
+    every: Option<u64>,
+    status: Option<u16>,
+    delay_seconds: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Schedule {
+    /// Every n-th request of a location, or every n-th write.
+    Every(u64),
+
+    Probability(f64),
+}
+
+#[derive(Debug)]
+struct Rule {
+    fault: Fault,
+
+    /// Id of the only location injected into, every location if unset.
+    location: Option<i64>,
+
+    schedule: Schedule,
+}
+
+#[derive(Debug)]
+struct Draws {
+    /// State of the xorshift generator deciding the probabilistic faults.
+    rng: u64,
+
+    /// Requests or writes seen per rule and location, `None` for the writes of a rule
+    /// injected everywhere.
+    seen: HashMap<(usize, Option<i64>), u64>,
+
+    /// Faults injected per code.
+    injected: BTreeMap<&'static str, u64>,
+}
+
+impl Draws {
+    fn uniform(&mut self) -> f64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Failures injected into the forecast requests and the writes, for verifying the alerts, the
+/// spool and the health in staging without breaking the swat api or InfluxDB.
+///
+/// Read from the TOML file at `CHAOS_CONFIG`, only built with the `chaos` feature:
+///
+/// ```toml
+/// seed = 7
+///
+/// [[fault]]
+/// kind = "timeout"           # server_error, garbage, slow or write_failure
+/// location = "WW Marienhafe" # every location if unset
+/// every = 3                  # or `probability = 0.25`
+/// ```
+///
+/// `status` sets the status of a `server_error` and `delay_seconds` the delay of a `slow`
+/// response. The faults are decided per attempt, so retries may or may not succeed.
+#[derive(Debug)]
+pub struct Chaos {
+    rules: Vec<Rule>,
+    draws: Mutex<Draws>,
+}
+
+impl Chaos {
+    pub fn from_lookup(
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<Option<Chaos>, ChaosError> {
+        let Some(path) = lookup("CHAOS_CONFIG") else {
+            return Ok(None);
+        };
+        let spec = fs::read_to_string(&path).map_err(|error| ChaosError::Read { path, error })?;
+        Chaos::parse(&spec).map(Some)
+    }
+
+    pub fn parse(spec: &str) -> Result<Chaos, ChaosError> {
+        let spec: Spec = toml::from_str(spec)?;
+        let mut rules = Vec::new();
+        for (i, fault) in spec.faults.into_iter().enumerate() {
+            let n = i + 1;
+            let schedule = match (fault.every, fault.probability) {
+                (Some(0), None) => return Err(ChaosError::Every(n)),
+                (Some(every), None) => Schedule::Every(every),
+                (None, Some(probability)) if (0.0..=1.0).contains(&probability) => {
+                    Schedule::Probability(probability)
+                }
+                (None, Some(_)) => return Err(ChaosError::Probability(n)),
+                _ => return Err(ChaosError::Schedule(n)),
+            };
+            let location = match fault.location {
+                Some(name) => match fixture::find_location(&name) {
+                    Some(location) => Some(location.id),
+                    None => return Err(ChaosError::Location(n, name)),
+                },
+                None => None,
+            };
+            let fault = match fault.kind {
+                FaultKind::Timeout => Fault::Timeout,
+                FaultKind::ServerError => match fault.status.unwrap_or(DEFAULT_STATUS) {
+                    status @ 500..=599 => Fault::ServerError(status),
+                    status => return Err(ChaosError::Status(n, status)),
+                },
+                FaultKind::Garbage => Fault::Garbage,
+                FaultKind::Slow => Fault::Slow(
+                    fault
+                        .delay_seconds
+                        .map_or(DEFAULT_DELAY, Duration::from_secs),
+                ),
+                FaultKind::WriteFailure => Fault::WriteFailure,
+            };
+            rules.push(Rule {
+                fault,
+                location,
+                schedule,
+            });
+        }
+        Ok(Chaos {
+            rules,
+            draws: Mutex::new(Draws {
+                // the generator is stuck at zero
+                rng: spec.seed.max(1),
+                seen: HashMap::new(),
+                injected: BTreeMap::new(),
+            }),
+        })
+    }
+
+    /// The fault injected into the forecast request of `location`, if any.
+    pub fn request(&self, location: &Location) -> Option<Fault> {
+        self.draw(false, |rule| match rule.location {
+            Some(id) if id != location.id => None,
+            _ => Some(Some(location.id)),
+        })
+    }
+
+    /// The fault injected into the write of the points of `locations`, if any.
+    pub fn write<'l>(&self, locations: impl IntoIterator<Item = &'l Location>) -> Option<Fault> {
+        let ids: Vec<_> = locations.into_iter().map(|location| location.id).collect();
+        self.draw(true, |rule| match rule.location {
+            Some(id) => ids.contains(&id).then_some(Some(id)),
+            None => Some(None),
+        })
+    }
+
+    /// Decides the rules of the requests or the `writes`, `applies` tells the key counting a
+    /// rule if it applies. The first fault due is injected.
+    fn draw(&self, writes: bool, applies: impl Fn(&Rule) -> Option<Option<i64>>) -> Option<Fault> {
+        let mut draws = self.draws.lock();
+        let mut injected = None;
+        for (i, rule) in self.rules.iter().enumerate() {
+            if rule.fault.is_write() != writes {
+                continue;
+            }
+            let Some(key) = applies(rule) else {
+                continue;
+            };
+            let seen = draws.seen.entry((i, key)).or_default();
+            *seen += 1;
+            let seen = *seen;
+            // every rule is decided, so that the schedules do not depend on each other
+            let due = match rule.schedule {
+                Schedule::Every(every) => seen.is_multiple_of(every),
+                Schedule::Probability(probability) => draws.uniform() < probability,
+            };
+            if due && injected.is_none() {
+                injected = Some(rule.fault);
+            }
+        }
+        if let Some(fault) = injected {
+            *draws.injected.entry(fault.code()).or_default() += 1;
+        }
+        injected
+    }
+
+    /// Faults injected so far per code.
+    pub fn injected(&self) -> BTreeMap<&'static str, u64> {
+        self.draws.lock().injected.clone()
+    }
+
+    /// The faults injected so far, for the status page.
+    #[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+    pub fn status_text(&self) -> String {
+        let injected: Vec<_> = (self.injected().into_iter())
+            .map(|(code, count)| format!("{count} {code}"))
+            .collect();
+        match injected.is_empty() {
+            true => "synthetic failures injected: none\n".to_string(),
+            false => format!("synthetic failures injected: {}\n", injected.join(", ")),
+        }
+    }
+}
+
+/// Whether the write `error` was injected.
+pub fn is_synthetic_write(error: &influxdb2::RequestError) -> bool {
+    matches!(error, influxdb2::RequestError::Http { text, .. } if text == WRITE_FAILURE_TEXT)
+}
+
+/// The error of an injected write failure.
+pub fn write_error() -> influxdb2::RequestError {
+    influxdb2::RequestError::Http {
+        status: reqwest::StatusCode::SERVICE_UNAVAILABLE,
+        text: WRITE_FAILURE_TEXT.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locations;
+
+    fn location(id: i64) -> &'static Location {
+        (locations::CONFIGURED.iter())
+            .find(|location| location.id == id)
+            .unwrap()
+    }
+
+    #[test]
+    fn honors_schedules() {
+        let chaos = Chaos::parse(
+            r#"
+            [[fault]]
+            kind = "timeout"
+            location = "WW Großenkneten"
+            every = 3
+
+            [[fault]]
+            kind = "server_error"
+            status = 502
+            every = 2
+
+            [[fault]]
+            kind = "write_failure"
+            location = "2"
+            every = 1
+            "#,
+        )
+        .unwrap();
+
+        let requests: Vec<_> = (0..6).map(|_| chaos.request(location(1))).collect();
+        let (timeout, server) = (Some(Fault::Timeout), Some(Fault::ServerError(502)));
+        // the third request is due for both, the rule listed first wins
+        assert_eq!(requests, [None, server, timeout, server, None, timeout]);
+        // the requests of every location are counted on their own
+        assert_eq!(chaos.request(location(2)), None);
+        assert_eq!(chaos.request(location(2)), server);
+
+        assert_eq!(chaos.write([location(1), location(3)]), None);
+        assert_eq!(
+            chaos.write([location(1), location(2)]),
+            Some(Fault::WriteFailure)
+        );
+        assert_eq!(
+            chaos.injected(),
+            BTreeMap::from([("server_error", 3), ("timeout", 2), ("write_failure", 1)])
+        );
+        assert_eq!(
+            chaos.status_text(),
+            "synthetic failures injected: 3 server_error, 2 timeout, 1 write_failure\n"
+        );
+    }
+
+    #[test]
+    fn draws_probabilities_from_seed() {
+        let spec = |probability: f64| {
+            format!(
+                "seed = 42\n[[fault]]\nkind = \"slow\"\ndelay_seconds = 5\nprobability = {probability:?}"
+            )
+        };
+        let draws = |probability| {
+            let chaos = Chaos::parse(&spec(probability)).unwrap();
+            (0..1000)
+                .map(|_| chaos.request(location(1)))
+                .collect::<Vec<_>>()
+        };
+        assert!(draws(0.0).iter().all(Option::is_none));
+        assert!(draws(1.0)
+            .iter()
+            .all(|fault| *fault == Some(Fault::Slow(Duration::from_secs(5)))));
+
+        let quarter = draws(0.25);
+        assert_eq!(quarter, draws(0.25), "the same seed draws alike");
+        let injected = quarter.iter().filter(|fault| fault.is_some()).count();
+        assert!((200..300).contains(&injected), "{injected}");
+    }
+
+    #[test]
+    fn rejects_invalid_specs() {
+        let error = |spec: &str| Chaos::parse(spec).unwrap_err().to_string();
+        assert_eq!(
+            error("[[fault]]\nkind = \"garbage\""),
+            "fault #1 needs either `probability` or `every`"
+        );
+        assert_eq!(
+            error("[[fault]]\nkind = \"garbage\"\nevery = 2\nprobability = 0.5"),
+            "fault #1 needs either `probability` or `every`"
+        );
+        assert_eq!(
+            error("[[fault]]\nkind = \"garbage\"\nprobability = 1.5"),
+            "fault #1 has a probability outside of 0 to 1"
+        );
+        assert_eq!(
+            error("[[fault]]\nkind = \"garbage\"\nevery = 0"),
+            "fault #1 is never injected, `every` must be at least 1"
+        );
+        assert_eq!(
+            error("[[fault]]\nkind = \"timeout\"\nevery = 1\n[[fault]]\nkind = \"server_error\"\nstatus = 404\nevery = 1"),
+            "fault #2 has status 404, expected a server error"
+        );
+        assert_eq!(
+            error("[[fault]]\nkind = \"timeout\"\nevery = 1\nlocation = \"WW Atlantis\""),
+            "fault #1 is injected into the unknown location \"WW Atlantis\""
+        );
+        assert!(matches!(
+            Chaos::parse("[[fault]]\nkind = \"meteor\"\nevery = 1"),
+            Err(ChaosError::Toml(_))
+        ));
+        assert!(Chaos::from_lookup(|_| None).unwrap().is_none());
+    }
+
+    #[test]
+    fn tags_write_failures() {
+        assert!(is_synthetic_write(&write_error()));
+        let real = influxdb2::RequestError::Http {
+            status: reqwest::StatusCode::SERVICE_UNAVAILABLE,
+            text: "unavailable".to_string(),
+        };
+        assert!(!is_synthetic_write(&real));
+    }
+}
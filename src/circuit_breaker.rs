@@ -0,0 +1,270 @@
+use crate::clock::Clock;
+use crate::severity::Severity;
+use crate::webhook::AlertField;
+use chrono::{DateTime, Utc};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// Name of the alert field reporting an open circuit.
+pub const NAME: &str = "sink circuit";
+
+/// The circuit stays open this long before probing unless `CB_OPEN_SECONDS` is set.
+pub const DEFAULT_OPEN: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("expected {0:?} to be valid, {1}")]
+pub struct CircuitBreakerError(&'static str, String);
+
+/// Whether a write may go to InfluxDB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permit {
+    /// The circuit is closed, write.
+    Write,
+
+    /// The circuit was open long enough, this write decides whether it closes again.
+    Probe,
+
+    /// The circuit is open or a probe is in flight, spool instead of writing.
+    Divert,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakerState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+impl fmt::Display for BreakerState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BreakerState::Closed => "closed",
+            BreakerState::Open => "open",
+            BreakerState::HalfOpen => "half-open",
+        })
+    }
+}
+
+/// A change of the [`BreakerState`], to be logged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transition {
+    pub from: BreakerState,
+    pub to: BreakerState,
+}
+
+/// Circuit breaker around the writes into InfluxDB, enabled via `CB_FAILURE_THRESHOLD`.
+///
+/// After the threshold of consecutive failed writes the circuit opens for `CB_OPEN_SECONDS`,
+/// writes are spooled to disk meanwhile instead of waiting on a dead endpoint. The first write
+/// afterwards is a probe, closing the circuit if it succeeds and opening it again otherwise.
+/// The time the circuit opened first is kept until it closes, for the alerts, the period it stays
+/// open is timed monotonically.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    open_for: Duration,
+    state: BreakerState,
+    failures: u32,
+    opened: Option<Instant>,
+    since: Option<DateTime<Utc>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, open_for: Duration) -> CircuitBreaker {
+        CircuitBreaker {
+            threshold: threshold.max(1),
+            open_for,
+            state: BreakerState::Closed,
+            failures: 0,
+            opened: None,
+            since: None,
+        }
+    }
+
+    /// Reads the breaker from `lookup`, `None` if `CB_FAILURE_THRESHOLD` is not set.
+    pub fn from_lookup(
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<Option<CircuitBreaker>, CircuitBreakerError> {
+        let Some(threshold) = lookup("CB_FAILURE_THRESHOLD") else {
+            return Ok(None);
+        };
+        let threshold = u32::from_str(threshold.trim())
+            .map_err(|err| CircuitBreakerError("CB_FAILURE_THRESHOLD", err.to_string()))?;
+        let open_for = lookup("CB_OPEN_SECONDS")
+            .map(|seconds| u64::from_str(seconds.trim()).map(Duration::from_secs))
+            .transpose()
+            .map_err(|err| CircuitBreakerError("CB_OPEN_SECONDS", err.to_string()))?;
+        Ok(Some(CircuitBreaker::new(
+            threshold,
+            open_for.unwrap_or(DEFAULT_OPEN),
+        )))
+    }
+
+    /// When the circuit opened, `None` while it is closed.
+    pub fn open_since(&self) -> Option<DateTime<Utc>> {
+        self.since
+    }
+
+    /// Whether a write may go to InfluxDB now, turning an open circuit half-open once it was
+    /// open long enough.
+    pub fn permit(&mut self, clock: &dyn Clock) -> (Permit, Option<Transition>) {
+        match self.state {
+            BreakerState::Closed => (Permit::Write, None),
+            BreakerState::HalfOpen => (Permit::Divert, None),
+            BreakerState::Open => {
+                let opened = self.opened.expect("an open circuit has been opened");
+                match clock.now_instant().saturating_duration_since(opened) < self.open_for {
+                    true => (Permit::Divert, None),
+                    false => (Permit::Probe, self.transition(BreakerState::HalfOpen)),
+                }
+            }
+        }
+    }
+
+    /// Records the outcome of a [permitted](Self::permit) write.
+    pub fn record(&mut self, succeeded: bool, clock: &dyn Clock) -> Option<Transition> {
+        if succeeded {
+            self.failures = 0;
+            self.since = None;
+            return self.transition(BreakerState::Closed);
+        }
+        self.failures += 1;
+        if self.state == BreakerState::HalfOpen || self.failures >= self.threshold {
+            self.opened = Some(clock.now_instant());
+            self.since.get_or_insert(clock.now_utc());
+            return self.transition(BreakerState::Open);
+        }
+        None
+    }
+
+    fn transition(&mut self, to: BreakerState) -> Option<Transition> {
+        let from = std::mem::replace(&mut self.state, to);
+        (from != to).then_some(Transition { from, to })
+    }
+
+    /// The critical alert about the circuit, while it is not closed.
+    pub fn alert_field(&self, tick_id: u64) -> Option<AlertField> {
+        let since = self.open_since()?;
+        Some(AlertField::new(
+            NAME.to_string(),
+            format!("sink circuit open since {}", since.format("%H:%M")),
+            Severity::Critical,
+            tick_id,
+        ))
+    }
+
+    #[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+    pub fn status_text(&self) -> String {
+        match self.open_since() {
+            Some(since) => format!(
+                "sink circuit: {} since {}\n",
+                self.state,
+                since.format("%Y-%m-%d %H:%M")
+            ),
+            None => "sink circuit: closed\n".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, MockClock};
+
+    fn open(clock: &MockClock) -> CircuitBreaker {
+        let mut breaker = CircuitBreaker::new(2, Duration::from_secs(60));
+        assert_eq!(breaker.permit(clock), (Permit::Write, None));
+        assert_eq!(breaker.record(false, clock), None);
+        // a success in between resets the count
+        assert_eq!(breaker.record(true, clock), None);
+        assert_eq!(breaker.record(false, clock), None);
+        assert_eq!(
+            breaker.record(false, clock),
+            Some(Transition {
+                from: BreakerState::Closed,
+                to: BreakerState::Open
+            })
+        );
+        assert_eq!(breaker.open_since(), Some(clock.now_utc()));
+        breaker
+    }
+
+    #[test]
+    fn opens_and_probes() {
+        let clock = MockClock::new();
+        let mut breaker = open(&clock);
+        clock.advance(Duration::from_secs(59));
+        assert_eq!(breaker.permit(&clock), (Permit::Divert, None));
+
+        clock.advance(Duration::from_secs(1));
+        let (permit, transition) = breaker.permit(&clock);
+        assert_eq!(permit, Permit::Probe);
+        assert_eq!(transition.unwrap().to, BreakerState::HalfOpen);
+        // one probe at a time
+        assert_eq!(breaker.permit(&clock), (Permit::Divert, None));
+
+        assert_eq!(
+            breaker.record(true, &clock),
+            Some(Transition {
+                from: BreakerState::HalfOpen,
+                to: BreakerState::Closed
+            })
+        );
+        assert_eq!(breaker.open_since(), None);
+        assert!(breaker.alert_field(1).is_none());
+        assert_eq!(breaker.permit(&clock), (Permit::Write, None));
+    }
+
+    #[test]
+    fn failed_probe_opens_again() {
+        let clock = MockClock::new();
+        let opened = clock.now_utc();
+        let mut breaker = open(&clock);
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(breaker.permit(&clock).0, Permit::Probe);
+
+        // a single failed probe suffices, for another full period
+        assert_eq!(
+            breaker.record(false, &clock),
+            Some(Transition {
+                from: BreakerState::HalfOpen,
+                to: BreakerState::Open
+            })
+        );
+        clock.advance(Duration::from_secs(30));
+        assert_eq!(breaker.permit(&clock), (Permit::Divert, None));
+
+        // open since it first opened
+        assert_eq!(breaker.open_since(), Some(opened));
+        let alert = format!("{:?}", breaker.alert_field(7).unwrap());
+        let since = format!("sink circuit open since {}", opened.format("%H:%M"));
+        assert!(alert.contains(&since), "{alert}");
+        assert!(breaker
+            .status_text()
+            .starts_with("sink circuit: open since "));
+    }
+
+    #[test]
+    fn config() {
+        assert!(CircuitBreaker::from_lookup(|_| None).unwrap().is_none());
+        let breaker = CircuitBreaker::from_lookup(|key| match key {
+            "CB_FAILURE_THRESHOLD" => Some("5".to_string()),
+            "CB_OPEN_SECONDS" => Some("300".to_string()),
+            _ => None,
+        })
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            (breaker.threshold, breaker.open_for),
+            (5, Duration::from_secs(300))
+        );
+        assert_eq!(
+            CircuitBreaker::from_lookup(|_| Some("often".to_string()))
+                .unwrap_err()
+                .to_string(),
+            "expected \"CB_FAILURE_THRESHOLD\" to be valid, invalid digit found in string"
+        );
+    }
+}
@@ -0,0 +1,89 @@
+use chrono::{DateTime, Utc};
+use std::fmt::Debug;
+use std::time::{Instant, SystemTime};
+
+/// Source of the current time for the logic depending on it, like stale forecasts, quiet hours
+/// and the health thresholds.
+///
+/// Tests use a [`MockClock`] advanced by hand instead of sleeping.
+pub trait Clock: Debug + Send + Sync {
+    fn now_utc(&self) -> DateTime<Utc>;
+
+    /// Monotonic time for measuring durations.
+    fn now_instant(&self) -> Instant;
+
+    #[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+    fn now_system(&self) -> SystemTime {
+        self.now_utc().into()
+    }
+}
+
+/// The clock of the system, used outside of tests.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_utc(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    fn now_instant(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Clock standing still at the time it was created until [advanced](MockClock::advance).
+#[cfg(test)]
+#[derive(Debug)]
+pub struct MockClock {
+    now: parking_lot::Mutex<(DateTime<Utc>, Instant)>,
+}
+
+#[cfg(test)]
+impl MockClock {
+    pub fn new() -> MockClock {
+        MockClock {
+            now: parking_lot::Mutex::new((Utc::now(), Instant::now())),
+        }
+    }
+
+    pub fn advance(&self, duration: std::time::Duration) {
+        let mut now = self.now.lock();
+        now.0 += chrono::Duration::from_std(duration).expect("duration in range");
+        now.1 += duration;
+    }
+}
+
+#[cfg(test)]
+impl Clock for MockClock {
+    fn now_utc(&self) -> DateTime<Utc> {
+        self.now.lock().0
+    }
+
+    fn now_instant(&self) -> Instant {
+        self.now.lock().1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn mock_advances_by_hand() {
+        let clock = MockClock::new();
+        let (utc, instant, system) = (clock.now_utc(), clock.now_instant(), clock.now_system());
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(clock.now_utc(), utc);
+        assert_eq!(clock.now_instant(), instant);
+
+        clock.advance(Duration::from_secs(90));
+        assert_eq!(clock.now_utc() - utc, chrono::Duration::seconds(90));
+        assert_eq!(clock.now_instant() - instant, Duration::from_secs(90));
+        assert_eq!(
+            clock.now_system().duration_since(system).unwrap(),
+            Duration::from_secs(90)
+        );
+    }
+}
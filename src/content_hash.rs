@@ -0,0 +1,96 @@
+use crate::locations::{Forecast, Target};
+use crate::values;
+use std::collections::BTreeMap;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// Deterministic hash of the forecast of a location, written as the `content_hash` tag so
+/// points written twice can be recognized.
+///
+/// The hash must stay the same across releases, so it is the 64 bit FNV-1a hash, as 16 lower
+/// case hex digits, of this canonical serialization, joined by `\n`:
+///
+/// 1. the location id in decimal
+/// 2. the issue time (`vorhersageZeit`) as sent by the swat api
+/// 3. the current value (`aktuell`) as compact JSON object
+/// 4. the forecasts (`vorhersage`) as compact JSON object, keys in ascending order
+/// 5. the model name, left out for the default model so existing hashes stay valid
+pub fn content_hash(target: Target<'_>, forecast: &Forecast) -> Result<String, serde_json::Error> {
+    let canonical = format!("{}\n{}", target.location.id, canonical(target, forecast)?);
+    Ok(format!("{:016x}", fnv1a(canonical.as_bytes())))
+}
+
+/// Hash of the forecast alone, the [content_hash] without the location id, equal for the
+/// locations the swat api snaps to the same grid cell.
+pub fn forecast_hash(target: Target<'_>, forecast: &Forecast) -> Result<String, serde_json::Error> {
+    Ok(format!(
+        "{:016x}",
+        fnv1a(canonical(target, forecast)?.as_bytes())
+    ))
+}
+
+/// The serialization of the forecast from the issue time on.
+fn canonical(target: Target<'_>, forecast: &Forecast) -> Result<String, serde_json::Error> {
+    let current = BTreeMap::from([forecast.current.clone()]);
+    let current = serde_json::to_string(&values::exact(&current))?;
+    let forecasts = serde_json::to_string(&values::exact(&forecast.forecasts))?;
+    let mut canonical = format!("{}\n{current}\n{forecasts}", forecast.from);
+    if !target.model.is_default() {
+        canonical = format!("{canonical}\n{}", target.model.name);
+    }
+    Ok(canonical)
+}
+
+/// The FNV-1a hash of `bytes` as 16 lower case hex digits.
+pub fn hash(bytes: &[u8]) -> String {
+    format!("{:016x}", fnv1a(bytes))
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locations::{Model, LOCATIONS};
+
+    #[test]
+    fn fnv1a_reference() {
+        // reference values of the FNV-1a specification
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x85944171f73967e8);
+    }
+
+    #[test]
+    fn stable_for_fixture() {
+        let body = include_str!("../tests/fixtures/location-1.body.json");
+        let forecast: Forecast = serde_json::from_str(body).unwrap();
+        let mut model = Model::default_model();
+        let target = Target {
+            location: &LOCATIONS.locations[0],
+            model: &model,
+        };
+        assert_eq!(content_hash(target, &forecast).unwrap(), "878e0dbd54225895");
+        let other = Target {
+            location: &LOCATIONS.locations[1],
+            model: &model,
+        };
+        assert_ne!(content_hash(other, &forecast).unwrap(), "878e0dbd54225895");
+        assert_eq!(
+            forecast_hash(other, &forecast).unwrap(),
+            forecast_hash(target, &forecast).unwrap()
+        );
+
+        model.name = "nowcast".to_string();
+        let target = Target {
+            location: &LOCATIONS.locations[0],
+            model: &model,
+        };
+        assert_ne!(content_hash(target, &forecast).unwrap(), "878e0dbd54225895");
+    }
+}
@@ -0,0 +1,203 @@
+use once_cell::sync::Lazy;
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use std::env;
+use std::fmt;
+use thiserror::Error;
+
+/// Decimal places of the coordinates in tags and request urls, configurable via
+/// `COORDINATE_DECIMALS`.
+///
+/// Five places are about a meter, finer than any well is located.
+pub static DECIMALS: Lazy<usize> = Lazy::new(|| match env::var("COORDINATE_DECIMALS") {
+    Ok(decimals) => parse_decimals(&decimals)
+        .unwrap_or_else(|err| panic!("expected \"COORDINATE_DECIMALS\" to be valid, {err}")),
+    Err(_) => DEFAULT_DECIMALS,
+});
+
+pub const DEFAULT_DECIMALS: usize = 5;
+
+/// More places than a double holds for coordinates would only show its rounding errors.
+const MAX_DECIMALS: usize = 10;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum CoordinatesError {
+    #[error("expected the {axis} to be a number, found {value:?}")]
+    NotNumeric { axis: &'static str, value: String },
+
+    #[error("expected the {axis} to be within ±{bound}, found {value}")]
+    Range {
+        axis: &'static str,
+        value: f64,
+        bound: f64,
+    },
+
+    #[error("expected at most {MAX_DECIMALS} decimal places, found {0:?}")]
+    Decimals(String),
+}
+
+/// Latitude and longitude of a location in WGS 84, in range by construction.
+///
+/// Both are formatted with a fixed number of decimal places wherever they leave the collector,
+/// so `53.14` and `53.140` end up as the same tag and the same request.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Coordinates {
+    lat: f64,
+    lon: f64,
+    decimals: usize,
+}
+
+impl Coordinates {
+    /// The coordinates formatted with the configured [`DECIMALS`].
+    pub fn new(lat: f64, lon: f64) -> Result<Coordinates, CoordinatesError> {
+        Coordinates::with_decimals(lat, lon, *DECIMALS)
+    }
+
+    pub fn with_decimals(
+        lat: f64,
+        lon: f64,
+        decimals: usize,
+    ) -> Result<Coordinates, CoordinatesError> {
+        let check = |axis, value: f64, bound: f64| match (-bound..=bound).contains(&value) {
+            true => Ok(value),
+            false => Err(CoordinatesError::Range { axis, value, bound }),
+        };
+        Ok(Coordinates {
+            lat: check("latitude", lat, 90.0)?,
+            lon: check("longitude", lon, 180.0)?,
+            decimals: decimals.min(MAX_DECIMALS),
+        })
+    }
+
+    /// Parses the coordinates as written in `locations.toml`.
+    pub fn parse(lat: &str, lon: &str) -> Result<Coordinates, CoordinatesError> {
+        let parse = |axis, value: &str| {
+            value
+                .trim()
+                .parse::<f64>()
+                .map_err(|_| CoordinatesError::NotNumeric {
+                    axis,
+                    value: value.to_string(),
+                })
+        };
+        Coordinates::new(parse("latitude", lat)?, parse("longitude", lon)?)
+    }
+
+    pub fn lat(&self) -> f64 {
+        self.lat
+    }
+
+    pub fn lon(&self) -> f64 {
+        self.lon
+    }
+
+    /// The latitude as written into tags and urls.
+    pub fn lat_text(&self) -> String {
+        format(self.lat, self.decimals)
+    }
+
+    /// The longitude as written into tags and urls.
+    pub fn lon_text(&self) -> String {
+        format(self.lon, self.decimals)
+    }
+}
+
+impl fmt::Display for Coordinates {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}, {}", self.lat_text(), self.lon_text())
+    }
+}
+
+/// Serializes the coordinates as `lat` and `lon` numbers rounded like their text.
+impl Serialize for Coordinates {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Coordinates", 2)?;
+        state.serialize_field("lat", &round(self.lat, self.decimals))?;
+        state.serialize_field("lon", &round(self.lon, self.decimals))?;
+        state.end()
+    }
+}
+
+fn parse_decimals(decimals: &str) -> Result<usize, CoordinatesError> {
+    match decimals.trim().parse() {
+        Ok(decimals) if decimals <= MAX_DECIMALS => Ok(decimals),
+        _ => Err(CoordinatesError::Decimals(decimals.to_string())),
+    }
+}
+
+/// The `value` rounded to `decimals` places, ties away from zero and without a negative zero.
+pub fn round(value: f64, decimals: usize) -> f64 {
+    let scale = 10f64.powi(decimals as i32);
+    let rounded = (value * scale).round() / scale;
+    // adding zero turns a negative zero positive
+    rounded + 0.0
+}
+
+/// The `value` with exactly `decimals` places.
+pub fn format(value: f64, decimals: usize) -> String {
+    format!("{:.*}", decimals, round(value, decimals))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes() {
+        let coordinates = Coordinates::with_decimals(53.14, 8.200000000000001, 5).unwrap();
+        assert_eq!(coordinates.to_string(), "53.14000, 8.20000");
+        assert_eq!(
+            Coordinates::with_decimals(53.140, 8.2, 5)
+                .unwrap()
+                .lat_text(),
+            coordinates.lat_text()
+        );
+        let precise = Coordinates::parse(" 52.9109818816186", "8.23505277402053").unwrap();
+        assert_eq!(precise.to_string(), "52.91098, 8.23505");
+        assert_eq!(precise.lat(), 52.9109818816186);
+
+        // negative zero
+        let zero = Coordinates::with_decimals(-0.0, -0.000001, 5).unwrap();
+        assert_eq!(zero.to_string(), "0.00000, 0.00000");
+        assert_eq!(format(-0.4, 0), "0");
+
+        // ties round away from zero, unlike the formatting of std
+        assert_eq!(format(0.125, 2), "0.13");
+        assert_eq!(format(-0.125, 2), "-0.13");
+        assert_eq!(format(2.5, 0), "3");
+        // 1.005 is a little less in binary, not a tie
+        assert_eq!(format(1.005, 2), "1.00");
+
+        let json = serde_json::to_string(&precise).unwrap();
+        assert_eq!(json, r#"{"lat":52.91098,"lon":8.23505}"#);
+    }
+
+    #[test]
+    fn validates() {
+        assert_eq!(
+            Coordinates::parse("52.91", "n/a"),
+            Err(CoordinatesError::NotNumeric {
+                axis: "longitude",
+                value: "n/a".to_string()
+            })
+        );
+        assert_eq!(
+            Coordinates::new(90.5, 8.2).unwrap_err().to_string(),
+            "expected the latitude to be within ±90, found 90.5"
+        );
+        assert_eq!(
+            Coordinates::new(52.9, -180.01).unwrap_err().to_string(),
+            "expected the longitude to be within ±180, found -180.01"
+        );
+        assert!(Coordinates::new(f64::NAN, 8.2).is_err());
+        assert!(Coordinates::parse("inf", "8.2").is_err());
+        assert!(Coordinates::new(-90.0, 180.0).is_ok());
+
+        assert_eq!(parse_decimals(" 3"), Ok(3));
+        assert_eq!(
+            parse_decimals("11").unwrap_err().to_string(),
+            "expected at most 10 decimal places, found \"11\""
+        );
+        assert!(parse_decimals("-1").is_err());
+    }
+}
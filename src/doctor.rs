@@ -0,0 +1,838 @@
+use crate::clock::{Clock, SystemClock};
+use crate::config;
+use crate::geo;
+use crate::groups;
+use crate::http_client::HttpClientConfig;
+use crate::locations::{self, Location, LOCATIONS};
+use crate::severity::Severity;
+use crate::sink::Buckets;
+use crate::webhook::Destination;
+use chrono::{DateTime, Utc};
+use influxdb2::api::buckets::ListBucketsRequest;
+use influxdb2::api::write::TimestampPrecision;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::Duration;
+use std::{env, fmt, fs};
+use tokio::net::TcpStream;
+use url::Url;
+
+/// Measurement of the point written to check the write permission, deleted right after.
+pub const MEASUREMENT: &str = "_doctor";
+
+/// Base url of the Discord api the webhooks are checked against.
+const DISCORD_API_URL: &str = "https://discord.com/api/v10";
+
+/// How long a single network check waits.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Clock offsets above this are warned about, as stale issues are told by the local time.
+const CLOCK_WARN_SECS: i64 = 30;
+
+/// Clock offsets above this fail, a tick would be collected in the wrong window.
+const CLOCK_FAIL_SECS: i64 = 300;
+
+/// Result of a single check, ordered so the worst of them sets the exit code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    /// Not run as a check it depends on failed, which is reported on its own.
+    Skip,
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl Outcome {
+    /// `0` if every check passed, `1` with warnings and `2` with failures.
+    pub fn exit_code(self) -> ExitCode {
+        match self {
+            Outcome::Skip | Outcome::Pass => ExitCode::SUCCESS,
+            Outcome::Warn => ExitCode::from(1),
+            Outcome::Fail => ExitCode::from(2),
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Outcome::Skip => "SKIP",
+            Outcome::Pass => "PASS",
+            Outcome::Warn => "WARN",
+            Outcome::Fail => "FAIL",
+        })
+    }
+}
+
+/// What a check found, with a hint how to fix it unless it passed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Finding {
+    pub check: String,
+    pub outcome: Outcome,
+    pub detail: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+impl Finding {
+    fn pass(check: impl Into<String>, detail: impl Into<String>) -> Finding {
+        Finding {
+            check: check.into(),
+            outcome: Outcome::Pass,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn skip(check: impl Into<String>, detail: impl Into<String>) -> Finding {
+        Finding {
+            outcome: Outcome::Skip,
+            ..Finding::pass(check, detail)
+        }
+    }
+
+    fn warn(check: impl Into<String>, detail: impl Into<String>, hint: &str) -> Finding {
+        Finding {
+            outcome: Outcome::Warn,
+            hint: Some(hint.to_string()),
+            ..Finding::pass(check, detail)
+        }
+    }
+
+    fn fail(check: impl Into<String>, detail: impl Into<String>, hint: &str) -> Finding {
+        Finding {
+            outcome: Outcome::Fail,
+            ..Finding::warn(check, detail, hint)
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}  {}: {}", self.outcome, self.check, self.detail)?;
+        if let Some(hint) = &self.hint {
+            write!(f, "\n      hint: {hint}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs every check against the environment and prints the findings, as JSON with `json`.
+pub async fn run(json: bool) -> ExitCode {
+    let findings = diagnose(|key| env::var(key).ok(), &SystemClock).await;
+    match json {
+        true => println!(
+            "{}",
+            serde_json::to_string_pretty(&findings).expect("findings serialize")
+        ),
+        false => {
+            for finding in &findings {
+                println!("{finding}");
+            }
+        }
+    }
+    let worst = findings.iter().map(|finding| finding.outcome).max();
+    worst.unwrap_or(Outcome::Pass).exit_code()
+}
+
+/// The findings of every check, those depending on a variable that is missing or invalid are
+/// skipped.
+async fn diagnose(lookup: impl Fn(&str) -> Option<String>, clock: &dyn Clock) -> Vec<Finding> {
+    let mut findings = vec![check_env(&lookup)];
+    let client = HttpClientConfig::from_lookup(&lookup, crate::COLLECTION_INTERVAL)
+        .map(|config| config.builder())
+        .unwrap_or_default()
+        .timeout(TIMEOUT)
+        .build()
+        .unwrap_or_default();
+
+    let api_url = lookup("SWAT_API_URL").unwrap_or_else(|| locations::DEFAULT_API_URL.to_string());
+    let influxdb_url = lookup("INFLUXDB_URL").unwrap_or_default();
+    for (name, url) in [("swat api", api_url.as_str()), ("influxdb", &influxdb_url)] {
+        match Url::parse(url) {
+            Ok(url) => {
+                findings.push(check_dns(name, &url).await);
+                findings.push(check_connection(&client, name, &url).await);
+            }
+            Err(err) => {
+                findings.push(Finding::skip(
+                    format!("dns {name}"),
+                    format!("no url, {err}"),
+                ));
+                findings.push(Finding::skip(format!("connection {name}"), "no url"));
+            }
+        }
+    }
+
+    let influxdb = match (lookup("INFLUXDB_ORG"), lookup("INFLUXDB_TOKEN")) {
+        (Some(org), Some(token)) if !influxdb_url.is_empty() => {
+            Some(influxdb2::Client::new(&influxdb_url, org, token))
+        }
+        _ => None,
+    };
+    let locations = match lookup("LOCATIONS_GEOJSON") {
+        Some(path) => locations::with_geojson(Path::new(&path)).unwrap_or_default(),
+        None => LOCATIONS.locations.to_vec(),
+    };
+    let buckets = Buckets::from_lookup(&lookup, &locations);
+    match &influxdb {
+        Some(influxdb) => {
+            findings.push(check_influx_read(influxdb, &buckets.names()).await);
+            findings.push(
+                check_influx_write(influxdb, buckets.default_bucket(), clock.now_utc()).await,
+            );
+        }
+        None => {
+            findings.push(Finding::skip("influxdb read", "no influxdb configured"));
+            findings.push(Finding::skip("influxdb write", "no influxdb configured"));
+        }
+    }
+
+    let destinations = match lookup("DISCORD_WEBHOOKS") {
+        Some(webhooks) => Destination::parse_list(&webhooks).ok(),
+        None => match (
+            lookup("DISCORD_WEBHOOK_ID"),
+            lookup("DISCORD_WEBHOOK_TOKEN"),
+        ) {
+            (Some(id), Some(token)) => id
+                .trim()
+                .parse()
+                .ok()
+                .map(|id| vec![Destination::new(id, token, Severity::Info)]),
+            _ => None,
+        },
+    };
+    findings.push(match destinations {
+        Some(destinations) => check_webhooks(&client, DISCORD_API_URL, &destinations).await,
+        None => Finding::skip("webhooks", "no valid webhook configured"),
+    });
+
+    #[cfg(feature = "health-check")]
+    findings.push(
+        match crate::health_check::HealthConfig::from_lookup(&lookup) {
+            Ok(config) => check_socket_path(&config.socket_path),
+            Err(err) => Finding::fail(
+                "health socket",
+                format!("invalid health configuration, {err}"),
+                "fix the \"HEALTH_SOCKET_*\" variables",
+            ),
+        },
+    );
+    findings.push(check_locations(lookup("LOCATIONS_GEOJSON").as_deref()));
+    findings.push(match Url::parse(&api_url) {
+        Ok(url) => check_clock(&client, &url, clock).await,
+        Err(_) => Finding::skip("clock", "no swat api url to compare with"),
+    });
+    findings
+}
+
+/// Whether the variables the collector needs are set and can be used.
+pub fn check_env(lookup: impl Fn(&str) -> Option<String>) -> Finding {
+    const CHECK: &str = "env vars";
+    let mut problems = Vec::new();
+    for key in ["INFLUXDB_URL", "INFLUXDB_ORG", "INFLUXDB_TOKEN"] {
+        if lookup(key)
+            .filter(|value| !value.trim().is_empty())
+            .is_none()
+        {
+            problems.push(format!("{key:?} is missing"));
+        }
+    }
+    match lookup("DISCORD_WEBHOOKS") {
+        Some(webhooks) => {
+            if let Err(err) = Destination::parse_list(&webhooks) {
+                problems.push(format!("\"DISCORD_WEBHOOKS\" is invalid, {err}"));
+            }
+        }
+        None => {
+            for key in ["DISCORD_WEBHOOK_ID", "DISCORD_WEBHOOK_TOKEN"] {
+                if lookup(key).is_none() {
+                    problems.push(format!("{key:?} is missing, as is \"DISCORD_WEBHOOKS\""));
+                }
+            }
+            let id = lookup("DISCORD_WEBHOOK_ID").map(|id| id.trim().parse::<u64>());
+            if let Some(Err(err)) = id {
+                problems.push(format!("\"DISCORD_WEBHOOK_ID\" is invalid, {err}"));
+            }
+        }
+    }
+    for key in ["INFLUXDB_URL", "SWAT_API_URL"] {
+        let Some(value) = lookup(key) else {
+            continue;
+        };
+        // the values are sanitized before the collector reads them
+        let url = config::sanitize(key, &value)
+            .map_err(|err| err.to_string())
+            .and_then(|sanitized| Url::parse(&sanitized.value).map_err(|err| err.to_string()));
+        match url {
+            Ok(url) if ["http", "https"].contains(&url.scheme()) => (),
+            Ok(url) => problems.push(format!(
+                "{key:?} is invalid, expected an http or https url, found {:?}",
+                url.scheme()
+            )),
+            Err(err) => problems.push(format!("{key:?} is invalid, {err}")),
+        }
+    }
+    if let Some(minutes) = lookup("COLLECTION_INTERVAL_MINUTES") {
+        if !matches!(minutes.trim().parse::<u64>(), Ok(minutes) if minutes > 0) {
+            problems.push(format!(
+                "\"COLLECTION_INTERVAL_MINUTES\" is invalid, expected minutes above 0, found \
+                 {minutes:?}"
+            ));
+        }
+    }
+    if let Err(err) = HttpClientConfig::from_lookup(&lookup, crate::COLLECTION_INTERVAL) {
+        problems.push(err.to_string());
+    }
+    match problems.is_empty() {
+        true => Finding::pass(CHECK, "the required variables are set"),
+        false => Finding::fail(
+            CHECK,
+            problems.join("; "),
+            "set the variables in the environment or a `.env` file",
+        ),
+    }
+}
+
+/// Whether the host of the `url` resolves.
+pub async fn check_dns(name: &str, url: &Url) -> Finding {
+    let check = format!("dns {name}");
+    let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) else {
+        return Finding::fail(
+            check,
+            format!("{url} names no host"),
+            "set a url with a host",
+        );
+    };
+    let hint = "check the host name and the DNS servers of the container";
+    match tokio::time::timeout(TIMEOUT, tokio::net::lookup_host((host, port))).await {
+        Ok(Ok(addrs)) => {
+            let addrs: BTreeSet<_> = addrs.map(|addr| addr.ip().to_string()).collect();
+            match addrs.is_empty() {
+                true => Finding::fail(check, format!("{host} resolves to no address"), hint),
+                false => Finding::pass(
+                    check,
+                    format!(
+                        "{host} resolves to {}",
+                        addrs.into_iter().collect::<Vec<_>>().join(", ")
+                    ),
+                ),
+            }
+        }
+        Ok(Err(err)) => Finding::fail(check, format!("could not resolve {host}, {err}"), hint),
+        Err(_) => Finding::fail(check, format!("resolving {host} timed out"), hint),
+    }
+}
+
+/// Whether a tcp connection to the host of the `url` opens and, for https, a tls session is
+/// established with the `client`.
+pub async fn check_connection(client: &reqwest::Client, name: &str, url: &Url) -> Finding {
+    let check = format!("connection {name}");
+    let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) else {
+        return Finding::fail(
+            check,
+            format!("{url} names no host"),
+            "set a url with a host",
+        );
+    };
+    let hint = "check firewalls and whether the host is only reachable through a proxy";
+    match tokio::time::timeout(TIMEOUT, TcpStream::connect((host, port))).await {
+        Ok(Ok(_)) => (),
+        Ok(Err(err)) => {
+            return Finding::fail(
+                check,
+                format!("could not connect to {host}:{port}, {err}"),
+                hint,
+            )
+        }
+        Err(_) => return Finding::fail(check, format!("connecting {host}:{port} timed out"), hint),
+    }
+    if url.scheme() != "https" {
+        return Finding::pass(check, format!("connected to {host}:{port} over tcp"));
+    }
+    // any response, even an error status, completed the tls handshake
+    match client.head(url.clone()).send().await {
+        Ok(_) => Finding::pass(check, format!("connected to {host}:{port} over tls")),
+        Err(err) => Finding::fail(
+            check,
+            format!("tls to {host}:{port} failed, {}", error_chain(&err)),
+            "check the certificates trusted by the container and any tls intercepting proxy",
+        ),
+    }
+}
+
+/// Whether the token of `client` can read the `buckets`.
+pub async fn check_influx_read(client: &influxdb2::Client, buckets: &BTreeSet<&str>) -> Finding {
+    const CHECK: &str = "influxdb read";
+    let mut missing = Vec::new();
+    for bucket in buckets {
+        let request = ListBucketsRequest {
+            name: bucket.to_string().into(),
+            ..Default::default()
+        };
+        match client.list_buckets(Some(request)).await {
+            Ok(found) if found.buckets.is_empty() => missing.push(format!("{bucket:?}")),
+            Ok(_) => (),
+            Err(err) => {
+                return Finding::fail(
+                    CHECK,
+                    format!("could not read bucket {bucket:?}, {err}"),
+                    "give the token read access to the buckets of the org",
+                )
+            }
+        }
+    }
+    match missing.is_empty() {
+        true => Finding::pass(CHECK, format!("read {} buckets", buckets.len())),
+        false => Finding::warn(
+            CHECK,
+            format!("buckets {} do not exist yet", missing.join(", ")),
+            "the collector creates them on startup if the token may create buckets",
+        ),
+    }
+}
+
+/// Whether the token of `client` can write into `bucket`, with a point into the [`MEASUREMENT`]
+/// deleted right after.
+pub async fn check_influx_write(
+    client: &influxdb2::Client,
+    bucket: &str,
+    now: DateTime<Utc>,
+) -> Finding {
+    const CHECK: &str = "influxdb write";
+    let line = format!(
+        "{MEASUREMENT},purpose=doctor written=true {}",
+        now.timestamp()
+    );
+    let precision = TimestampPrecision::Seconds;
+    let written = client
+        .write_line_protocol_with_precision(&client.org, bucket, line, precision)
+        .await;
+    if let Err(err) = written {
+        return Finding::fail(
+            CHECK,
+            format!("could not write into bucket {bucket:?}, {err}"),
+            "give the token write access to the bucket",
+        );
+    }
+    let start = now.naive_utc() - chrono::Duration::seconds(1);
+    let stop = now.naive_utc() + chrono::Duration::seconds(1);
+    let predicate = format!("_measurement=\"{MEASUREMENT}\"");
+    match client.delete(bucket, start, stop, Some(predicate)).await {
+        Ok(()) => Finding::pass(CHECK, format!("wrote into bucket {bucket:?}")),
+        Err(err) => Finding::warn(
+            CHECK,
+            format!(
+                "wrote into bucket {bucket:?} but could not delete the {MEASUREMENT:?} point, {err}"
+            ),
+            "delete the measurement by hand, the collector itself does not need to delete",
+        ),
+    }
+}
+
+/// Whether Discord knows every webhook of the `destinations`, asking the api at `base_url`.
+pub async fn check_webhooks(
+    client: &reqwest::Client,
+    base_url: &str,
+    destinations: &[Destination],
+) -> Finding {
+    const CHECK: &str = "webhooks";
+    let mut invalid = Vec::new();
+    for destination in destinations {
+        let id = destination.id();
+        let url = format!("{base_url}/webhooks/{id}/{}", destination.token());
+        match client.get(url).send().await {
+            Ok(response) if response.status().is_success() => (),
+            Ok(response) => invalid.push(format!("webhook {id} answered {}", response.status())),
+            Err(err) => invalid.push(format!("webhook {id} failed, {}", error_chain(&err))),
+        }
+    }
+    match invalid.is_empty() {
+        true => Finding::pass(CHECK, format!("{} webhooks are valid", destinations.len())),
+        false => Finding::fail(
+            CHECK,
+            invalid.join("; "),
+            "copy the id and token of the webhook from the integration settings of the channel",
+        ),
+    }
+}
+
+/// Whether the health socket can be created at `path`, by creating a file next to it.
+#[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+pub fn check_socket_path(path: &Path) -> Finding {
+    const CHECK: &str = "health socket";
+    let hint = "mount a writable directory or set \"HEALTH_SOCKET_PATH\"";
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+        return Finding::fail(CHECK, format!("{path:?} names no file"), hint);
+    };
+    let dir = match dir.as_os_str().is_empty() {
+        true => Path::new("."),
+        false => dir,
+    };
+    let probe = dir.join(format!(".{}.doctor", name.to_string_lossy()));
+    match fs::write(&probe, []).and_then(|()| fs::remove_file(&probe)) {
+        Ok(()) => Finding::pass(CHECK, format!("{dir:?} is writable")),
+        Err(err) => Finding::fail(CHECK, format!("{dir:?} is not writable, {err}"), hint),
+    }
+}
+
+/// Whether the configured locations and the GeoJSON file at `geojson` can be collected.
+pub fn check_locations(geojson: Option<&str>) -> Finding {
+    const CHECK: &str = "locations";
+    let locations: Vec<Location> = match geojson {
+        Some(path) => match locations::with_geojson(Path::new(path)) {
+            Ok(locations) => locations,
+            Err(err) => {
+                return Finding::fail(
+                    CHECK,
+                    format!("invalid \"LOCATIONS_GEOJSON\", {err}"),
+                    "fix the GeoJSON file or unset \"LOCATIONS_GEOJSON\"",
+                )
+            }
+        },
+        None => LOCATIONS.locations.to_vec(),
+    };
+    let checked = locations::check_unique_slugs(&locations)
+        .map_err(|err| err.to_string())
+        .and_then(|()| groups::check(&locations).map_err(|err| err.to_string()))
+        .and_then(|()| geo::check_coordinates(&locations).map_err(|err| err.to_string()));
+    match checked {
+        Ok(()) => Finding::pass(CHECK, format!("{} locations configured", locations.len())),
+        Err(err) => Finding::fail(
+            CHECK,
+            err,
+            "rename the locations or groups, see `locations.toml`",
+        ),
+    }
+}
+
+/// Whether the `clock` agrees with the `Date` header of the response of the `url`.
+pub async fn check_clock(client: &reqwest::Client, url: &Url, clock: &dyn Clock) -> Finding {
+    const CHECK: &str = "clock";
+    let hint = "synchronize the clock of the host with NTP";
+    let response = match client.head(url.clone()).send().await {
+        Ok(response) => response,
+        Err(err) => {
+            return Finding::skip(CHECK, format!("no response to compare with, {err}"));
+        }
+    };
+    let now = clock.now_utc();
+    let date = response
+        .headers()
+        .get(reqwest::header::DATE)
+        .and_then(|date| date.to_str().ok())
+        .and_then(|date| DateTime::parse_from_rfc2822(date).ok());
+    let Some(date) = date else {
+        return Finding::skip(
+            CHECK,
+            format!("no \"Date\" header from {url} to compare with"),
+        );
+    };
+    let offset = (now - date.with_timezone(&Utc)).num_seconds();
+    let detail = format!(
+        "{offset:+}s off the clock of {}",
+        url.host_str().unwrap_or("the host")
+    );
+    match offset.abs() {
+        secs if secs > CLOCK_FAIL_SECS => Finding::fail(CHECK, detail, hint),
+        secs if secs > CLOCK_WARN_SECS => Finding::warn(CHECK, detail, hint),
+        _ => Finding::pass(CHECK, detail),
+    }
+}
+
+/// The `err` with its sources, reqwest hides the cause like a certificate error in them.
+fn error_chain(err: &dyn std::error::Error) -> String {
+    let mut text = err.to_string();
+    let mut source = err.source();
+    while let Some(err) = source {
+        text += &format!(", {err}");
+        source = err.source();
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use parking_lot::Mutex;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use warp::http::StatusCode;
+    use warp::Filter;
+
+    fn lookup(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<_, _> = vars
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        move |key| vars.get(key).cloned()
+    }
+
+    const REQUIRED: [(&str, &str); 4] = [
+        ("INFLUXDB_URL", "http://influxdb:8086"),
+        ("INFLUXDB_ORG", "wisdom"),
+        ("INFLUXDB_TOKEN", "token"),
+        ("DISCORD_WEBHOOKS", "1:token"),
+    ];
+
+    #[test]
+    fn checks_env() {
+        assert_eq!(check_env(lookup(&REQUIRED)).outcome, Outcome::Pass);
+
+        let finding = check_env(lookup(&REQUIRED[1..3]));
+        assert_eq!(finding.outcome, Outcome::Fail);
+        assert_eq!(
+            finding.detail,
+            "\"INFLUXDB_URL\" is missing; \"DISCORD_WEBHOOK_ID\" is missing, as is \
+             \"DISCORD_WEBHOOKS\"; \"DISCORD_WEBHOOK_TOKEN\" is missing, as is \
+             \"DISCORD_WEBHOOKS\""
+        );
+
+        let invalid = [
+            ("INFLUXDB_URL", "influxdb:8086"),
+            ("SWAT_API_URL", "ftp://swat.itwh.de"),
+            ("COLLECTION_INTERVAL_MINUTES", "0"),
+        ];
+        let finding = check_env(lookup(&[&REQUIRED[1..], &invalid[..]].concat()));
+        assert_eq!(
+            finding.to_string(),
+            "FAIL  env vars: \"INFLUXDB_URL\" is invalid, expected an http or https url, found \
+             \"influxdb\"; \"SWAT_API_URL\" is invalid, expected an http or https url, found \
+             \"ftp\"; \"COLLECTION_INTERVAL_MINUTES\" is invalid, expected minutes above 0, \
+             found \"0\"\n      hint: set the variables in the environment or a `.env` file"
+        );
+    }
+
+    #[tokio::test]
+    async fn checks_dns() {
+        let url = Url::parse("http://localhost:8086").unwrap();
+        let finding = check_dns("influxdb", &url).await;
+        assert_eq!(finding.outcome, Outcome::Pass, "{finding}");
+        assert!(finding.detail.starts_with("localhost resolves to "));
+
+        let url = Url::parse("unix:/run/influxdb.sock").unwrap();
+        let finding = check_dns("influxdb", &url).await;
+        assert_eq!(finding.outcome, Outcome::Fail);
+        assert_eq!(finding.detail, "unix:/run/influxdb.sock names no host");
+    }
+
+    #[tokio::test]
+    async fn checks_connection() {
+        let (addr, server) =
+            warp::serve(warp::any().map(warp::reply)).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let client = reqwest::Client::new();
+
+        let url = Url::parse(&format!("http://{addr}")).unwrap();
+        let finding = check_connection(&client, "swat api", &url).await;
+        assert_eq!(
+            finding,
+            Finding::pass(
+                "connection swat api",
+                format!("connected to {addr} over tcp")
+            )
+        );
+
+        // the server does not speak tls
+        let url = Url::parse(&format!("https://{addr}")).unwrap();
+        let finding = check_connection(&client, "swat api", &url).await;
+        assert_eq!(finding.outcome, Outcome::Fail);
+        assert!(finding
+            .detail
+            .starts_with(&format!("tls to {addr} failed, ")));
+
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = closed.local_addr().unwrap();
+        drop(closed);
+        let url = Url::parse(&format!("http://{addr}")).unwrap();
+        let finding = check_connection(&client, "swat api", &url).await;
+        assert_eq!(finding.outcome, Outcome::Fail);
+        assert!(finding
+            .detail
+            .starts_with(&format!("could not connect to {addr}, ")));
+    }
+
+    /// An InfluxDB knowing the `buckets`, answering deletes with `delete_status`, recording the
+    /// written lines.
+    fn influxdb(
+        buckets: &'static [&'static str],
+        delete_status: StatusCode,
+    ) -> (influxdb2::Client, Arc<Mutex<Vec<String>>>) {
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let list = warp::get()
+            .and(warp::path!("api" / "v2" / "buckets"))
+            .and(warp::query::<HashMap<String, String>>())
+            .map(move |query: HashMap<String, String>| {
+                let name = query.get("name").cloned().unwrap_or_default();
+                let found: Vec<_> = buckets
+                    .iter()
+                    .filter(|bucket| **bucket == name)
+                    .map(|bucket| serde_json::json!({"name": bucket, "retentionRules": []}))
+                    .collect();
+                warp::reply::json(&serde_json::json!({ "buckets": found }))
+            });
+        let write = warp::post()
+            .and(warp::path!("api" / "v2" / "write"))
+            .and(warp::body::bytes())
+            .map({
+                let written = written.clone();
+                move |body: warp::hyper::body::Bytes| {
+                    written
+                        .lock()
+                        .push(String::from_utf8(body.to_vec()).unwrap());
+                    StatusCode::NO_CONTENT
+                }
+            });
+        let delete = warp::post()
+            .and(warp::path!("api" / "v2" / "delete"))
+            .map(move || warp::reply::with_status("", delete_status));
+        let (addr, server) =
+            warp::serve(list.or(write).or(delete)).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let client = influxdb2::Client::new(format!("http://{addr}"), "wisdom", "token");
+        (client, written)
+    }
+
+    #[tokio::test]
+    async fn checks_influxdb() {
+        let (client, written) = influxdb(&["swat"], StatusCode::NO_CONTENT);
+        let finding = check_influx_read(&client, &BTreeSet::from(["swat"])).await;
+        assert_eq!(finding, Finding::pass("influxdb read", "read 1 buckets"));
+        let finding = check_influx_read(&client, &BTreeSet::from(["swat", "harz"])).await;
+        assert_eq!(finding.outcome, Outcome::Warn);
+        assert_eq!(finding.detail, "buckets \"harz\" do not exist yet");
+
+        let now = DateTime::from_timestamp(1714564800, 0).unwrap();
+        let finding = check_influx_write(&client, "swat", now).await;
+        assert_eq!(
+            finding,
+            Finding::pass("influxdb write", "wrote into bucket \"swat\"")
+        );
+        assert_eq!(
+            *written.lock(),
+            ["_doctor,purpose=doctor written=true 1714564800"]
+        );
+
+        let (client, _) = influxdb(&[], StatusCode::FORBIDDEN);
+        let finding = check_influx_write(&client, "swat", now).await;
+        assert_eq!(finding.outcome, Outcome::Warn);
+        assert!(finding
+            .detail
+            .starts_with("wrote into bucket \"swat\" but could not delete the \"_doctor\" point"));
+
+        let unreachable = influxdb2::Client::new("http://127.0.0.1:9", "wisdom", "token");
+        let finding = check_influx_read(&unreachable, &BTreeSet::from(["swat"])).await;
+        assert_eq!(finding.outcome, Outcome::Fail);
+    }
+
+    #[tokio::test]
+    async fn checks_webhooks() {
+        let webhook = warp::get().and(warp::path!("webhooks" / u64 / String)).map(
+            |id, token: String| match (id, token.as_str()) {
+                (1, "token") => warp::reply::with_status("{}", StatusCode::OK),
+                _ => warp::reply::with_status("{}", StatusCode::UNAUTHORIZED),
+            },
+        );
+        let (addr, server) = warp::serve(webhook).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let client = reqwest::Client::new();
+        let base_url = format!("http://{addr}");
+
+        let destinations = Destination::parse_list("1:token").unwrap();
+        let finding = check_webhooks(&client, &base_url, &destinations).await;
+        assert_eq!(finding, Finding::pass("webhooks", "1 webhooks are valid"));
+
+        let destinations = Destination::parse_list("1:token;2:revoked").unwrap();
+        let finding = check_webhooks(&client, &base_url, &destinations).await;
+        assert_eq!(finding.outcome, Outcome::Fail);
+        assert_eq!(finding.detail, "webhook 2 answered 401 Unauthorized");
+    }
+
+    #[test]
+    fn checks_socket_path() {
+        let dir = env::temp_dir().join(format!("swat-collector-doctor-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let finding = check_socket_path(&dir.join("health.sock"));
+        assert_eq!(finding.outcome, Outcome::Pass);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+
+        let finding = check_socket_path(&dir.join("missing").join("health.sock"));
+        assert_eq!(finding.outcome, Outcome::Fail);
+        assert_eq!(
+            finding.hint.as_deref(),
+            Some("mount a writable directory or set \"HEALTH_SOCKET_PATH\"")
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn checks_locations() {
+        let finding = check_locations(None);
+        assert_eq!(finding.outcome, Outcome::Pass);
+        assert_eq!(
+            finding.detail,
+            format!("{} locations configured", LOCATIONS.locations.len())
+        );
+
+        // the fixture ends with a MultiPolygon
+        let finding = check_locations(Some("tests/fixtures/locations.geojson"));
+        assert_eq!(finding.outcome, Outcome::Fail);
+        assert_eq!(
+            finding.detail,
+            "invalid \"LOCATIONS_GEOJSON\", feature 3: expected a Point geometry, found \
+             \"MultiPolygon\""
+        );
+    }
+
+    #[tokio::test]
+    async fn checks_clock() {
+        let (addr, server) =
+            warp::serve(warp::any().map(warp::reply)).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let client = reqwest::Client::new();
+        let url = Url::parse(&format!("http://{addr}")).unwrap();
+
+        let clock = MockClock::new();
+        assert_eq!(
+            check_clock(&client, &url, &clock).await.outcome,
+            Outcome::Pass
+        );
+        clock.advance(Duration::from_secs(120));
+        let finding = check_clock(&client, &url, &clock).await;
+        assert_eq!(finding.outcome, Outcome::Warn);
+        assert!(finding.detail.starts_with("+1"), "{finding}");
+        clock.advance(Duration::from_secs(600));
+        assert_eq!(
+            check_clock(&client, &url, &clock).await.outcome,
+            Outcome::Fail
+        );
+    }
+
+    #[test]
+    fn exit_code_of_the_worst() {
+        let findings = [
+            Finding::skip("influxdb read", "no influxdb configured"),
+            Finding::pass("locations", "8 locations configured"),
+            Finding::warn("clock", "+42s off", "synchronize"),
+        ];
+        let worst = findings
+            .iter()
+            .map(|finding| finding.outcome)
+            .max()
+            .unwrap();
+        assert_eq!(worst, Outcome::Warn);
+        assert_eq!(worst.exit_code(), ExitCode::from(1));
+        assert_eq!(
+            serde_json::to_value(&findings[0]).unwrap(),
+            serde_json::json!({
+                "check": "influxdb read",
+                "outcome": "skip",
+                "detail": "no influxdb configured",
+            })
+        );
+    }
+}
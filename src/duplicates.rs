@@ -0,0 +1,203 @@
+use std::fmt::Write;
+
+/// Consecutive ticks locations need identical forecasts for to be reported, unless
+/// `DUPLICATE_GRID_TICKS` is set.
+pub const DEFAULT_TICKS: u32 = 10;
+
+/// Tracks locations the swat api returns identical forecasts for, as it snaps the coordinates
+/// to a grid and close locations end up in the same cell.
+///
+/// A cluster, the locations sharing the issue time and forecast hash in a tick, is confirmed
+/// once it stays the same for `ticks` consecutive ticks. Any change to it, like a location
+/// diverging, starts it over.
+///
+/// Skipping the duplicates, if enabled, writes only the first location of a confirmed cluster
+/// in fetch order, the canonical one, as long as the others are still identical to it.
+#[derive(Debug)]
+pub struct DuplicateTracker {
+    ticks: u32,
+    skip: bool,
+
+    /// Forecast hashes of the current tick in fetch order.
+    hashes: Vec<(String, String)>,
+
+    /// Clusters of the last tick and for how many ticks in a row they were identical.
+    streaks: Vec<(Vec<String>, u32)>,
+}
+
+impl DuplicateTracker {
+    pub fn new(ticks: u32, skip: bool) -> DuplicateTracker {
+        DuplicateTracker {
+            ticks: ticks.max(1),
+            skip,
+            hashes: Vec::new(),
+            streaks: Vec::new(),
+        }
+    }
+
+    pub fn ticks(&self) -> u32 {
+        self.ticks
+    }
+
+    /// Records the forecast `hash` of `target`, returns the canonical location it duplicates
+    /// if its point is to be skipped.
+    pub fn record(&mut self, target: &str, hash: &str) -> Option<String> {
+        self.hashes.push((target.to_string(), hash.to_string()));
+        if !self.skip {
+            return None;
+        }
+        let canonical = self
+            .confirmed()
+            .find(|cluster| cluster[1..].iter().any(|member| member == target))?[0]
+            .clone();
+        self.hashes
+            .iter()
+            .any(|(target, other)| *target == canonical && other == hash)
+            .then_some(canonical)
+    }
+
+    /// The locations whose points are skipped in favor of `target`, if it is canonical.
+    pub fn duplicates_of(&self, target: &str) -> Vec<String> {
+        if !self.skip {
+            return Vec::new();
+        }
+        self.confirmed()
+            .filter(|cluster| cluster[0] == target)
+            .flat_map(|cluster| cluster[1..].to_vec())
+            .collect()
+    }
+
+    /// Ends the tick, returns the clusters just confirmed.
+    pub fn end_tick(&mut self) -> Vec<Vec<String>> {
+        let hashes = std::mem::take(&mut self.hashes);
+        let streaks = clusters(&hashes)
+            .into_iter()
+            .map(|cluster| {
+                let streak = self
+                    .streaks
+                    .iter()
+                    .find(|(last, _)| *last == cluster)
+                    .map_or(0, |(_, streak)| *streak);
+                (cluster, streak + 1)
+            })
+            .collect();
+        self.streaks = streaks;
+        self.streaks
+            .iter()
+            .filter(|(_, streak)| *streak == self.ticks)
+            .map(|(cluster, _)| cluster.clone())
+            .collect()
+    }
+
+    pub fn confirmed(&self) -> impl Iterator<Item = &[String]> {
+        self.streaks
+            .iter()
+            .filter(|(_, streak)| *streak >= self.ticks)
+            .map(|(cluster, _)| cluster.as_slice())
+    }
+
+    /// The confirmed clusters shown in the verbose health check.
+    #[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+    pub fn status_text(&self) -> String {
+        let mut text = String::new();
+        for (cluster, streak) in &self.streaks {
+            if *streak >= self.ticks {
+                let _ = writeln!(text, "  {} for {streak} ticks", cluster.join(", "));
+            }
+        }
+        text
+    }
+}
+
+/// Groups the targets of a tick by their forecast hash, keeping the fetch order. Only groups of
+/// two or more targets are clusters.
+pub fn clusters(hashes: &[(String, String)]) -> Vec<Vec<String>> {
+    let mut groups: Vec<(&str, Vec<String>)> = Vec::new();
+    for (target, hash) in hashes {
+        match groups.iter_mut().find(|(other, _)| other == hash) {
+            Some((_, group)) => group.push(target.clone()),
+            None => groups.push((hash, vec![target.clone()])),
+        }
+    }
+    groups
+        .into_iter()
+        .map(|(_, group)| group)
+        .filter(|group| group.len() > 1)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tick(tracker: &mut DuplicateTracker, hashes: &[(&str, &str)]) -> Vec<Vec<String>> {
+        for (target, hash) in hashes {
+            tracker.record(target, hash);
+        }
+        tracker.end_tick()
+    }
+
+    fn owned(hashes: &[(&str, &str)]) -> Vec<(String, String)> {
+        hashes
+            .iter()
+            .map(|(target, hash)| (target.to_string(), hash.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn groups_identical_hashes() {
+        let hashes = owned(&[("A", "1"), ("B", "2"), ("C", "1"), ("D", "3"), ("E", "2")]);
+        assert_eq!(clusters(&hashes), [["A", "C"], ["B", "E"]]);
+        assert!(clusters(&owned(&[("A", "1"), ("B", "2")])).is_empty());
+        assert!(clusters(&[]).is_empty());
+    }
+
+    #[test]
+    fn confirms_after_ticks() {
+        let mut tracker = DuplicateTracker::new(3, false);
+        let same = [("A", "1"), ("B", "1"), ("C", "2")];
+        assert!(tick(&mut tracker, &same).is_empty());
+        assert!(tick(&mut tracker, &same).is_empty());
+        assert_eq!(tick(&mut tracker, &same), [["A", "B"]]);
+        assert_eq!(tracker.status_text(), "  A, B for 3 ticks\n");
+
+        // reported once, but stays listed
+        assert!(tick(&mut tracker, &same).is_empty());
+        assert_eq!(tracker.confirmed().count(), 1);
+
+        // diverging contents start over
+        assert!(tick(&mut tracker, &[("A", "3"), ("B", "4")]).is_empty());
+        assert_eq!(tracker.confirmed().count(), 0);
+        assert_eq!(tracker.status_text(), "");
+        assert!(tick(&mut tracker, &same).is_empty());
+        assert!(tick(&mut tracker, &same).is_empty());
+        assert_eq!(tick(&mut tracker, &same), [["A", "B"]]);
+
+        // a location joining makes a new cluster
+        assert!(tick(&mut tracker, &[("A", "1"), ("B", "1"), ("C", "1")]).is_empty());
+        assert_eq!(tracker.confirmed().count(), 0);
+    }
+
+    #[test]
+    fn skips_confirmed_duplicates() {
+        let mut tracker = DuplicateTracker::new(1, true);
+        assert_eq!(tracker.record("A", "1"), None);
+        assert_eq!(tracker.record("B", "1"), None);
+        tracker.end_tick();
+        assert_eq!(tracker.duplicates_of("A"), ["B"]);
+        assert!(tracker.duplicates_of("B").is_empty());
+
+        assert_eq!(tracker.record("A", "2"), None);
+        assert_eq!(tracker.record("B", "2"), Some("A".to_string()));
+        tracker.end_tick();
+
+        // a duplicate diverging from the canonical location is written again
+        assert_eq!(tracker.record("A", "3"), None);
+        assert_eq!(tracker.record("B", "4"), None);
+
+        let mut reporting = DuplicateTracker::new(1, false);
+        tick(&mut reporting, &[("A", "1"), ("B", "1")]);
+        assert_eq!(reporting.record("B", "1"), None);
+        assert!(reporting.duplicates_of("A").is_empty());
+    }
+}
@@ -0,0 +1,429 @@
+use crate::clock::{Clock, SystemClock};
+use crate::janitor::Size;
+use chrono::NaiveDate;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use reqwest::{Request, RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// Bytes transferred by the collector, shared by all of its profiles.
+pub static METER: Lazy<Arc<Meter>> = Lazy::new(Arc::default);
+
+/// Where the bytes are transferred to, as the egress is billed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Class {
+    Swat,
+    Influx,
+    Discord,
+
+    /// The archive in S3, Kafka and NATS.
+    Other,
+}
+
+impl Class {
+    pub const ALL: [Class; 4] = [Class::Swat, Class::Influx, Class::Discord, Class::Other];
+}
+
+impl fmt::Display for Class {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Class::Swat => "swat api",
+            Class::Influx => "influxdb",
+            Class::Discord => "discord",
+            Class::Other => "other sinks",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transfer {
+    pub sent: u64,
+    pub received: u64,
+}
+
+impl Transfer {
+    pub fn sent(sent: usize) -> Transfer {
+        Transfer {
+            sent: sent as u64,
+            received: 0,
+        }
+    }
+
+    fn add(&mut self, other: Transfer) {
+        self.sent += other.sent;
+        self.received += other.received;
+    }
+}
+
+/// The bytes transferred per [`Class`] on a day, kept in the state file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DailyEgress {
+    pub day: Option<NaiveDate>,
+    pub classes: BTreeMap<Class, Transfer>,
+}
+
+impl DailyEgress {
+    pub fn total(&self, class: Class) -> Transfer {
+        self.classes.get(&class).copied().unwrap_or_default()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.classes
+            .values()
+            .all(|transfer| *transfer == Transfer::default())
+    }
+}
+
+/// One line for the day, like `egress on 2024-05-01: swat api 1.2 KiB sent, 3.4 MiB received,
+/// ...`.
+impl fmt::Display for DailyEgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.day {
+            Some(day) => write!(f, "egress on {day}:")?,
+            None => f.write_str("egress:")?,
+        }
+        for (index, class) in Class::ALL.into_iter().enumerate() {
+            let Transfer { sent, received } = self.total(class);
+            let separator = if index == 0 { "" } else { "," };
+            write!(
+                f,
+                "{separator} {class} {} sent, {} received",
+                Size(sent),
+                Size(received)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Accounts the bytes sent and received per [`Class`] and day.
+///
+/// Requests made with reqwest go through [`send`](Meter::send), the clients of InfluxDB,
+/// Discord, Kafka and NATS record the size of their payloads instead. The sizes leave out
+/// TLS and the headers the clients add on their own.
+#[derive(Debug)]
+pub struct Meter {
+    today: Mutex<DailyEgress>,
+
+    /// Days that ended and are yet to be reported.
+    ended: Mutex<Vec<DailyEgress>>,
+
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for Meter {
+    fn default() -> Self {
+        Meter {
+            today: Mutex::default(),
+            ended: Mutex::default(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+}
+
+impl Meter {
+    #[cfg(test)]
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Meter {
+        Meter {
+            clock,
+            ..Meter::default()
+        }
+    }
+
+    pub fn record(&self, class: Class, transfer: Transfer) {
+        let mut today = self.roll_over();
+        today.classes.entry(class).or_default().add(transfer);
+    }
+
+    /// The totals of today so far.
+    pub fn today(&self) -> DailyEgress {
+        self.roll_over().clone()
+    }
+
+    /// The totals of the days that ended since the last call.
+    pub fn take_ended(&self) -> Vec<DailyEgress> {
+        drop(self.roll_over());
+        std::mem::take(&mut *self.ended.lock())
+    }
+
+    /// Continues with the totals `persisted` in the state file, those of an earlier day are
+    /// reported as ended.
+    pub fn restore(&self, persisted: DailyEgress) {
+        let mut today = self.roll_over();
+        match persisted.day {
+            Some(day) if Some(day) == today.day => *today = persisted,
+            Some(_) if !persisted.is_empty() => {
+                let mut ended = self.ended.lock();
+                if !ended.iter().any(|ended| ended.day == persisted.day) {
+                    ended.push(persisted);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    #[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+    pub fn status_text(&self) -> String {
+        let today = self.today();
+        match today.is_empty() {
+            true => String::new(),
+            false => format!("{today}\n"),
+        }
+    }
+
+    /// Sends the `request`, recording its size and that of the response.
+    ///
+    /// The response body counts with its `content-length`, or as it is streamed through
+    /// [`Metered::text`] if the server sends none.
+    pub async fn send(
+        &self,
+        class: Class,
+        request: RequestBuilder,
+    ) -> reqwest::Result<Metered<'_>> {
+        let (client, request) = request.build_split();
+        let request = request?;
+        let sent = request_size(&request);
+        let response = match client.execute(request).await {
+            Ok(response) => response,
+            Err(err) => {
+                self.record(class, Transfer { sent, received: 0 });
+                return Err(err);
+            }
+        };
+        let length = response.content_length();
+        let received = head_size(&response) + length.unwrap_or_default();
+        self.record(class, Transfer { sent, received });
+        Ok(Metered {
+            meter: self,
+            class,
+            response,
+            streamed: length.is_none(),
+        })
+    }
+
+    /// The totals of today, after moving those of an earlier day to the ended ones.
+    fn roll_over(&self) -> parking_lot::MutexGuard<'_, DailyEgress> {
+        let day = self.clock.now_utc().date_naive();
+        let mut today = self.today.lock();
+        if today.day != Some(day) {
+            let ended = std::mem::replace(
+                &mut *today,
+                DailyEgress {
+                    day: Some(day),
+                    classes: BTreeMap::new(),
+                },
+            );
+            if ended.day.is_some() && !ended.is_empty() {
+                self.ended.lock().push(ended);
+            }
+        }
+        today
+    }
+}
+
+/// A response [sent](Meter::send) through a [`Meter`].
+#[derive(Debug)]
+pub struct Metered<'m> {
+    meter: &'m Meter,
+    class: Class,
+    response: Response,
+
+    /// Whether the body is counted as it is read, without a `content-length`.
+    streamed: bool,
+}
+
+impl Metered<'_> {
+    pub fn status(&self) -> StatusCode {
+        self.response.status()
+    }
+
+    #[cfg_attr(not(feature = "archive"), allow(dead_code))]
+    pub fn error_for_status(self) -> reqwest::Result<Self> {
+        let Metered {
+            meter,
+            class,
+            response,
+            streamed,
+        } = self;
+        Ok(Metered {
+            meter,
+            class,
+            response: response.error_for_status()?,
+            streamed,
+        })
+    }
+
+    pub async fn text(mut self) -> reqwest::Result<String> {
+        let mut body = Vec::new();
+        while let Some(chunk) = self.response.chunk().await? {
+            if self.streamed {
+                self.meter.record(
+                    self.class,
+                    Transfer {
+                        sent: 0,
+                        received: chunk.len() as u64,
+                    },
+                );
+            }
+            body.extend_from_slice(&chunk);
+        }
+        Ok(String::from_utf8_lossy(&body).into_owned())
+    }
+}
+
+/// The bytes of the request line, the headers set on the `request` and its body.
+fn request_size(request: &Request) -> u64 {
+    let url = request.url();
+    let target = &url[url::Position::BeforePath..url::Position::AfterQuery];
+    let line = format!("{} {target} HTTP/1.1\r\n", request.method());
+    let host = url
+        .host_str()
+        .map_or(0, |host| "host: \r\n".len() + host.len());
+    let body = request
+        .body()
+        .and_then(|body| body.as_bytes())
+        .map_or(0, <[u8]>::len);
+    (line.len() + host + headers_size(request.headers()) + "\r\n".len() + body) as u64
+}
+
+/// The bytes of the status line and the headers of the `response`.
+fn head_size(response: &Response) -> u64 {
+    let line = format!("HTTP/1.1 {}\r\n", response.status());
+    (line.len() + headers_size(response.headers()) + "\r\n".len()) as u64
+}
+
+fn headers_size(headers: &reqwest::header::HeaderMap) -> usize {
+    headers
+        .iter()
+        .map(|(name, value)| name.as_str().len() + ": \r\n".len() + value.len())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use chrono::Timelike;
+    use std::time::Duration;
+    use warp::Filter;
+
+    fn body(size: usize) -> String {
+        "x".repeat(size)
+    }
+
+    #[tokio::test]
+    async fn accounts_transfers_per_class() {
+        let sized = warp::path("sized").map(|| body(1000));
+        let streamed = warp::path("streamed").map(|| {
+            let chunks = futures::stream::iter(
+                [body(300), body(200)].map(Ok::<_, std::convert::Infallible>),
+            );
+            warp::reply::Response::new(warp::hyper::Body::wrap_stream(chunks))
+        });
+        let (addr, server) = warp::serve(sized.or(streamed)).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        let meter = Meter::default();
+        let client = reqwest::Client::new();
+        let response = meter
+            .send(Class::Swat, client.get(format!("http://{addr}/sized")))
+            .await
+            .unwrap();
+        let swat = meter.today().total(Class::Swat);
+        // the content-length counts before the body is read
+        assert!(swat.received > 1000, "{swat:?}");
+        assert_eq!(response.text().await.unwrap().len(), 1000);
+        assert_eq!(meter.today().total(Class::Swat), swat);
+        let request_line = "GET /sized HTTP/1.1\r\nhost: 127.0.0.1\r\n\r\n";
+        assert_eq!(swat.sent, request_line.len() as u64);
+
+        let request = client
+            .post(format!("http://{addr}/streamed"))
+            .body(body(250));
+        let response = meter.send(Class::Other, request).await.unwrap();
+        let head = meter.today().total(Class::Other).received;
+        assert!(head > 0);
+        assert_eq!(response.text().await.unwrap().len(), 500);
+        let other = meter.today().total(Class::Other);
+        assert_eq!(other.received, head + 500);
+        assert!(other.sent > 250, "{other:?}");
+
+        meter.record(Class::Influx, Transfer::sent(4096));
+        meter.record(Class::Influx, Transfer::sent(1024));
+        assert_eq!(meter.today().total(Class::Influx), Transfer::sent(5120));
+        assert_eq!(meter.today().total(Class::Discord), Transfer::default());
+        assert!(meter
+            .status_text()
+            .contains(", influxdb 5.0 KiB sent, 0 bytes received, discord 0 bytes sent"));
+    }
+
+    #[test]
+    fn rolls_over_daily() {
+        let clock = Arc::new(MockClock::new());
+        let meter = Meter::with_clock(clock.clone());
+        let now = clock.now_utc();
+        meter.record(Class::Discord, Transfer::sent(700));
+        assert!(meter.take_ended().is_empty());
+
+        let to_midnight = 24 * 3600 - now.num_seconds_from_midnight() as u64;
+        clock.advance(Duration::from_secs(to_midnight));
+        meter.record(Class::Swat, Transfer::sent(10));
+        assert_eq!(meter.today().total(Class::Discord), Transfer::default());
+        assert_eq!(meter.today().total(Class::Swat), Transfer::sent(10));
+
+        let ended = meter.take_ended();
+        assert_eq!(ended.len(), 1);
+        assert_eq!(ended[0].day, Some(now.date_naive()));
+        assert_eq!(ended[0].total(Class::Discord), Transfer::sent(700));
+        assert_eq!(
+            ended[0].to_string(),
+            format!(
+                "egress on {}: swat api 0 bytes sent, 0 bytes received, influxdb 0 bytes sent, \
+                 0 bytes received, discord 700 bytes sent, 0 bytes received, other sinks 0 \
+                 bytes sent, 0 bytes received",
+                now.date_naive()
+            )
+        );
+        assert!(meter.take_ended().is_empty());
+
+        // a day without transfers is not reported
+        clock.advance(Duration::from_secs(2 * 24 * 3600));
+        meter.record(Class::Swat, Transfer::sent(10));
+        let ended = meter.take_ended();
+        let days: Vec<_> = ended.iter().map(|day| day.day).collect();
+        assert_eq!(days, [now.date_naive().succ_opt()]);
+    }
+
+    #[test]
+    fn restores_persisted_totals() {
+        let clock = Arc::new(MockClock::new());
+        let today = clock.now_utc().date_naive();
+        let persisted = |day, sent| DailyEgress {
+            day: Some(day),
+            classes: BTreeMap::from([(Class::Swat, Transfer::sent(sent))]),
+        };
+
+        let meter = Meter::with_clock(clock.clone());
+        meter.restore(persisted(today, 42));
+        meter.record(Class::Swat, Transfer::sent(8));
+        assert_eq!(meter.today().total(Class::Swat), Transfer::sent(50));
+
+        // the totals of the day the collector stopped on are reported once
+        let meter = Meter::with_clock(clock);
+        let yesterday = today.pred_opt().unwrap();
+        meter.restore(persisted(yesterday, 42));
+        meter.restore(persisted(yesterday, 42));
+        assert_eq!(meter.today().total(Class::Swat), Transfer::default());
+        assert_eq!(meter.take_ended(), vec![persisted(yesterday, 42)]);
+
+        let json = serde_json::to_string(&persisted(today, 42)).unwrap();
+        assert_eq!(
+            json,
+            format!(r#"{{"day":"{today}","classes":{{"swat":{{"sent":42,"received":0}}}}}}"#)
+        );
+    }
+}
@@ -0,0 +1,73 @@
+use crate::logging;
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
+
+/// Loaded if `ENV_FILE` is not set, a missing file is fine.
+const DEFAULT_ENV_FILE: &str = ".env";
+
+/// Loads the variables of the file at `ENV_FILE` or `./.env` into the environment.
+///
+/// Variables already set in the environment take precedence over the file, malformed lines are
+/// skipped with a warning.
+pub fn load() {
+    let datetime = logging::datetime();
+    let configured = env::var_os("ENV_FILE").map(PathBuf::from);
+    let path = configured
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_ENV_FILE));
+    match load_file(&path) {
+        Ok(skipped) => {
+            for (line, err) in skipped {
+                log_eprintln!("WARN  [{datetime}]: skipped line {line} of {path:?}, {err}");
+            }
+        }
+        Err(err) if configured.is_none() && err.kind() == io::ErrorKind::NotFound => (),
+        Err(err) => log_eprintln!("WARN  [{datetime}]: could not read env file {path:?}, {err}"),
+    }
+}
+
+/// Sets the variables of the file at `path` not set yet, returns the numbers and errors of the
+/// malformed lines.
+fn load_file(path: &Path) -> io::Result<Vec<(usize, dotenvy::Error)>> {
+    let content = fs::read_to_string(path)?;
+    let mut skipped = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        for item in dotenvy::from_read_iter(line.as_bytes()) {
+            match item {
+                Ok((key, value)) if env::var_os(&key).is_none() => env::set_var(key, value),
+                Ok(_) => (),
+                Err(err) => skipped.push((index + 1, err)),
+            }
+        }
+    }
+    Ok(skipped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn environment_takes_precedence() {
+        let path = env::temp_dir().join(format!("swat-collector-{}.env", std::process::id()));
+        fs::write(
+            &path,
+            "# local development\n\
+             ENV_FILE_TEST_SET=file\n\
+             ENV_FILE_TEST_NEW=\"from file\"\n\
+             this line is malformed\n\
+             export ENV_FILE_TEST_EXPORT=exported\n",
+        )
+        .unwrap();
+        env::set_var("ENV_FILE_TEST_SET", "from environment");
+
+        let skipped = load_file(&path).unwrap();
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].0, 4);
+        assert_eq!(env::var("ENV_FILE_TEST_SET").unwrap(), "from environment");
+        assert_eq!(env::var("ENV_FILE_TEST_NEW").unwrap(), "from file");
+        assert_eq!(env::var("ENV_FILE_TEST_EXPORT").unwrap(), "exported");
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,378 @@
+#[cfg(feature = "chaos")]
+use crate::chaos::{self, Fault};
+use crate::locations::RequestLocationError;
+use crate::points::HandleLocationError;
+use std::error::Error;
+use std::{fmt, io, iter};
+
+/// Machine-readable classification of the collection errors, used for routing and labels while
+/// the `Display` output stays meant for humans.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ErrorKind {
+    RequestTimeout,
+    RequestStatus,
+    Dns,
+    TlsHandshake,
+    ConnectTimeout,
+    ConnectionReset,
+    Request,
+    Unauthorized,
+    Parse,
+    TimestampParse,
+    Serialize,
+    PointBuild,
+    InfluxWrite,
+}
+
+impl ErrorKind {
+    pub const ALL: [ErrorKind; 13] = [
+        ErrorKind::RequestTimeout,
+        ErrorKind::RequestStatus,
+        ErrorKind::Dns,
+        ErrorKind::TlsHandshake,
+        ErrorKind::ConnectTimeout,
+        ErrorKind::ConnectionReset,
+        ErrorKind::Request,
+        ErrorKind::Unauthorized,
+        ErrorKind::Parse,
+        ErrorKind::TimestampParse,
+        ErrorKind::Serialize,
+        ErrorKind::PointBuild,
+        ErrorKind::InfluxWrite,
+    ];
+
+    /// Short code that stays stable across releases.
+    pub fn code(self) -> &'static str {
+        match self {
+            ErrorKind::RequestTimeout => "request_timeout",
+            ErrorKind::RequestStatus => "request_status",
+            ErrorKind::Dns => "dns",
+            ErrorKind::TlsHandshake => "tls_handshake",
+            ErrorKind::ConnectTimeout => "connect_timeout",
+            ErrorKind::ConnectionReset => "connection_reset",
+            ErrorKind::Request => "request",
+            ErrorKind::Unauthorized => "unauthorized",
+            ErrorKind::Parse => "parse",
+            ErrorKind::TimestampParse => "timestamp_parse",
+            ErrorKind::Serialize => "serialize",
+            ErrorKind::PointBuild => "point_build",
+            ErrorKind::InfluxWrite => "influx_write",
+        }
+    }
+
+    /// Whether the forecast request itself failed, i.e. the swat api could not be reached.
+    ///
+    /// Refused credentials are not, the api was reached and answers the same until they change.
+    pub fn is_request(self) -> bool {
+        matches!(
+            self,
+            ErrorKind::RequestTimeout
+                | ErrorKind::RequestStatus
+                | ErrorKind::Dns
+                | ErrorKind::TlsHandshake
+                | ErrorKind::ConnectTimeout
+                | ErrorKind::ConnectionReset
+                | ErrorKind::Request
+        )
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+/// Network level failure of a request, as the runbooks differ for each.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetworkFailure {
+    Dns,
+    TlsHandshake,
+    ConnectTimeout,
+    ConnectionReset,
+    Other,
+}
+
+impl NetworkFailure {
+    pub fn of(err: &reqwest::Error) -> NetworkFailure {
+        NetworkFailure::classify(err.is_connect(), err.is_timeout(), err)
+    }
+
+    /// Classifies the chain of `err`, whether it happened while `connect`ing and is a `timeout`
+    /// is told by reqwest.
+    ///
+    /// Neither hyper nor rustls are dependencies of their own, so their errors are told apart by
+    /// the `io::Error` they are wrapped in or by their message, anything not recognized is
+    /// [`NetworkFailure::Other`].
+    pub fn classify(connect: bool, timeout: bool, err: &(dyn Error + 'static)) -> NetworkFailure {
+        let chain: Vec<_> = iter::successors(Some(err), |&err| err.source()).collect();
+        let io_kind = |kinds: &[io::ErrorKind]| {
+            chain.iter().any(|err| {
+                err.downcast_ref::<io::Error>()
+                    .is_some_and(|err| kinds.contains(&err.kind()))
+            })
+        };
+        let message = |parts: &[&str]| {
+            chain.iter().any(|err| {
+                let message = err.to_string();
+                parts.iter().any(|part| message.contains(part))
+            })
+        };
+
+        if connect && message(&["dns error", "failed to lookup address"]) {
+            return NetworkFailure::Dns;
+        }
+        if connect && (timeout || io_kind(&[io::ErrorKind::TimedOut])) {
+            return NetworkFailure::ConnectTimeout;
+        }
+        // rustls errors surface as invalid data of the tls stream
+        if connect && io_kind(&[io::ErrorKind::InvalidData]) {
+            return NetworkFailure::TlsHandshake;
+        }
+        let reset = [
+            io::ErrorKind::ConnectionReset,
+            io::ErrorKind::ConnectionAborted,
+            io::ErrorKind::BrokenPipe,
+        ];
+        if io_kind(&reset) || message(&["connection closed before message completed"]) {
+            return NetworkFailure::ConnectionReset;
+        }
+        NetworkFailure::Other
+    }
+}
+
+impl RequestLocationError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            RequestLocationError::Dns(_) => ErrorKind::Dns,
+            RequestLocationError::TlsHandshake(_) => ErrorKind::TlsHandshake,
+            RequestLocationError::ConnectTimeout(_) => ErrorKind::ConnectTimeout,
+            RequestLocationError::ConnectionReset(_) => ErrorKind::ConnectionReset,
+            RequestLocationError::Request(err) if err.is_timeout() => ErrorKind::RequestTimeout,
+            RequestLocationError::Request(err) if err.is_status() => ErrorKind::RequestStatus,
+            RequestLocationError::Request(_) => ErrorKind::Request,
+            RequestLocationError::Unauthorized(_) => ErrorKind::Unauthorized,
+            RequestLocationError::Parse { .. } => ErrorKind::Parse,
+            #[cfg(feature = "chaos")]
+            RequestLocationError::Synthetic(fault) => match fault {
+                Fault::Timeout | Fault::Slow(_) => ErrorKind::RequestTimeout,
+                Fault::ServerError(_) => ErrorKind::RequestStatus,
+                Fault::Garbage => ErrorKind::Parse,
+                Fault::WriteFailure => ErrorKind::InfluxWrite,
+            },
+        }
+    }
+}
+
+impl HandleLocationError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            HandleLocationError::RequestForecast(err) => err.kind(),
+            HandleLocationError::ParseFromTimestamp(_) => ErrorKind::TimestampParse,
+            HandleLocationError::SerializeData(_) => ErrorKind::Serialize,
+            HandleLocationError::DataPoint(_) => ErrorKind::PointBuild,
+            HandleLocationError::PointsRejected { .. } => ErrorKind::PointBuild,
+            HandleLocationError::WritePoints { .. } => ErrorKind::InfluxWrite,
+            HandleLocationError::Diverted { .. } => ErrorKind::InfluxWrite,
+            HandleLocationError::SharedRequest { error, .. } => error.kind(),
+            HandleLocationError::Retried { error, .. } => error.kind(),
+        }
+    }
+
+    /// Whether the error was injected by the `CHAOS_CONFIG`.
+    #[cfg(feature = "chaos")]
+    pub fn is_synthetic(&self) -> bool {
+        match self {
+            HandleLocationError::RequestForecast(RequestLocationError::Synthetic(_)) => true,
+            HandleLocationError::WritePoints { error, .. } => {
+                chaos::is_synthetic_write(&error.error)
+            }
+            HandleLocationError::SharedRequest { error, .. } => error.is_synthetic(),
+            HandleLocationError::Retried { error, .. } => error.is_synthetic(),
+            _ => false,
+        }
+    }
+
+    /// The response body that could not be parsed, if that is the error.
+    pub fn response_body(&self) -> Option<&str> {
+        match self {
+            HandleLocationError::RequestForecast(RequestLocationError::Parse { from, .. }) => {
+                Some(from)
+            }
+            HandleLocationError::SharedRequest { error, .. } => error.response_body(),
+            HandleLocationError::Retried { error, .. } => error.response_body(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use influxdb2::models::DataPoint;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use warp::http::StatusCode;
+    use warp::Filter;
+
+    async fn request_error(
+        client: &reqwest::Client,
+        url: &str,
+        check_status: bool,
+    ) -> RequestLocationError {
+        let response = client.get(url).send().await;
+        let err = match (response, check_status) {
+            (Ok(response), true) => response.error_for_status().unwrap_err(),
+            (Ok(_), false) => panic!("expected request to fail"),
+            (Err(err), _) => err,
+        };
+        RequestLocationError::from(err)
+    }
+
+    #[tokio::test]
+    async fn request_kinds() {
+        let routes = warp::path("slow")
+            .then(|| async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                "too late"
+            })
+            .or(warp::path("error").map(|| StatusCode::INTERNAL_SERVER_ERROR));
+        let (addr, server) = warp::serve(routes).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(50))
+            .build()
+            .unwrap();
+        let timeout = request_error(&client, &format!("http://{addr}/slow"), false).await;
+        assert_eq!(timeout.kind(), ErrorKind::RequestTimeout);
+        let status = request_error(&client, &format!("http://{addr}/error"), true).await;
+        assert_eq!(status.kind(), ErrorKind::RequestStatus);
+        let connect = request_error(&client, "http://127.0.0.1:1", false).await;
+        assert_eq!(connect.kind(), ErrorKind::Request);
+
+        let parse = RequestLocationError::Parse {
+            error: serde_json::from_str::<()>("<html>").unwrap_err(),
+            from: "<html>".to_string(),
+        };
+        assert_eq!(parse.kind(), ErrorKind::Parse);
+        let parse = HandleLocationError::RequestForecast(parse);
+        assert_eq!(parse.kind(), ErrorKind::Parse);
+        assert_eq!(parse.response_body(), Some("<html>"));
+    }
+
+    /// An error of a dependency, which is only known by its message.
+    #[derive(Debug)]
+    struct Wrapped(&'static str, Option<Box<dyn Error + Send + Sync>>);
+
+    impl fmt::Display for Wrapped {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str(self.0)
+        }
+    }
+
+    impl Error for Wrapped {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            self.1.as_deref().map(|err| err as _)
+        }
+    }
+
+    fn connect_error(source: impl Error + Send + Sync + 'static) -> Wrapped {
+        Wrapped("error trying to connect", Some(Box::new(source)))
+    }
+
+    #[test]
+    fn network_failures() {
+        let io = |kind, message| io::Error::new(kind, message);
+        let classify = NetworkFailure::classify;
+
+        let lookup = io(io::ErrorKind::Other, "failed to lookup address information");
+        let dns = connect_error(Wrapped("dns error", Some(Box::new(lookup))));
+        assert_eq!(classify(true, false, &dns), NetworkFailure::Dns);
+
+        let certificate = io(io::ErrorKind::InvalidData, "invalid peer certificate");
+        let tls = connect_error(certificate);
+        assert_eq!(classify(true, false, &tls), NetworkFailure::TlsHandshake);
+
+        let elapsed = connect_error(Wrapped("operation timed out", None));
+        assert_eq!(
+            classify(true, true, &elapsed),
+            NetworkFailure::ConnectTimeout
+        );
+        let timed_out = connect_error(io(io::ErrorKind::TimedOut, "connection timed out"));
+        assert_eq!(
+            classify(true, false, &timed_out),
+            NetworkFailure::ConnectTimeout
+        );
+
+        let reset = io(io::ErrorKind::ConnectionReset, "connection reset by peer");
+        assert_eq!(
+            classify(false, false, &reset),
+            NetworkFailure::ConnectionReset
+        );
+        let closed = Wrapped("connection closed before message completed", None);
+        assert_eq!(
+            classify(false, false, &closed),
+            NetworkFailure::ConnectionReset
+        );
+
+        // anything else is left to the status and timeout checks of the request
+        let refused = connect_error(io(io::ErrorKind::ConnectionRefused, "connection refused"));
+        assert_eq!(classify(true, false, &refused), NetworkFailure::Other);
+        let body = io(
+            io::ErrorKind::InvalidData,
+            "stream did not contain valid UTF-8",
+        );
+        assert_eq!(classify(false, false, &body), NetworkFailure::Other);
+        assert_eq!(classify(false, true, &elapsed), NetworkFailure::Other);
+    }
+
+    #[test]
+    fn handle_kinds() {
+        let timestamp = crate::timestamp::parse("").unwrap_err();
+        let timestamp = HandleLocationError::ParseFromTimestamp(timestamp);
+        assert_eq!(timestamp.kind(), ErrorKind::TimestampParse);
+        assert_eq!(timestamp.response_body(), None);
+
+        let serialize = serde_json::from_str::<u32>("-1").unwrap_err();
+        let serialize = HandleLocationError::SerializeData(serialize);
+        assert_eq!(serialize.kind(), ErrorKind::Serialize);
+
+        let point = DataPoint::builder("forecast").build().unwrap_err();
+        let point = HandleLocationError::DataPoint(point);
+        assert_eq!(point.kind(), ErrorKind::PointBuild);
+
+        let write = HandleLocationError::WritePoints {
+            bucket: "swat".to_string(),
+            error: Arc::new(crate::sink::WriteError::from(
+                influxdb2::RequestError::Deserializing {
+                    text: "unexpected end of input".to_string(),
+                },
+            )),
+        };
+        assert_eq!(write.kind(), ErrorKind::InfluxWrite);
+    }
+
+    #[test]
+    fn codes() {
+        let codes: Vec<_> = ErrorKind::ALL.iter().map(|kind| kind.code()).collect();
+        assert_eq!(
+            codes,
+            [
+                "request_timeout",
+                "request_status",
+                "dns",
+                "tls_handshake",
+                "connect_timeout",
+                "connection_reset",
+                "request",
+                "unauthorized",
+                "parse",
+                "timestamp_parse",
+                "serialize",
+                "point_build",
+                "influx_write",
+            ]
+        );
+    }
+}
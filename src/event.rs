@@ -0,0 +1,54 @@
+use crate::locations::{Forecast, Target};
+use crate::values;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A successfully fetched forecast as published to the streaming outputs, in the JSON schema
+/// shared by all of them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ForecastEvent {
+    pub location: String,
+    pub slug: String,
+    pub model: String,
+
+    /// Issue time of the forecast, in local time as the swat api states it.
+    pub issued: String,
+    pub current: Horizon,
+    #[serde(serialize_with = "values::serialize_exact_map")]
+    pub forecasts: BTreeMap<String, f64>,
+
+    /// When the collector fetched the forecast, in RFC 3339.
+    pub fetched_at: String,
+    pub tick_id: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Horizon {
+    pub time: String,
+    #[serde(serialize_with = "values::serialize_exact")]
+    pub value: f64,
+}
+
+impl ForecastEvent {
+    pub fn new(
+        target: Target,
+        forecast: &Forecast,
+        fetched_at: DateTime<Utc>,
+        tick_id: u64,
+    ) -> ForecastEvent {
+        ForecastEvent {
+            location: target.location.name.to_string(),
+            slug: target.location.slug(),
+            model: target.model.name.clone(),
+            issued: forecast.from.clone(),
+            current: Horizon {
+                time: forecast.current.0.clone(),
+                value: forecast.current.1,
+            },
+            forecasts: forecast.forecasts.clone(),
+            fetched_at: fetched_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+            tick_id,
+        }
+    }
+}
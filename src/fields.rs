@@ -0,0 +1,87 @@
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::env;
+
+/// InfluxDB rejects string fields of 64 KiB, so stay clear of that by default.
+const DEFAULT_FIELD_LIMIT: usize = 60 * 1024;
+
+/// Maximum length of a string field in bytes, configurable via `INFLUX_FIELD_LIMIT`.
+pub static FIELD_LIMIT: Lazy<usize> = Lazy::new(|| match env::var("INFLUX_FIELD_LIMIT") {
+    Ok(limit) => limit
+        .parse()
+        .unwrap_or_else(|err| panic!("expected \"INFLUX_FIELD_LIMIT\" to be valid, {err}")),
+    Err(_) => DEFAULT_FIELD_LIMIT,
+});
+
+/// Serializes `map` into JSON objects of at most `limit` bytes each.
+///
+/// A map fitting the limit results in a single object, otherwise its entries are split in
+/// order across as many objects as needed. A single entry exceeding the limit on its own still
+/// gets an object of its own.
+pub fn split_json_map<K, V>(
+    map: &BTreeMap<K, V>,
+    limit: usize,
+) -> Result<Vec<String>, serde_json::Error>
+where
+    K: Serialize + Ord,
+    V: Serialize,
+{
+    let json = serde_json::to_string(map)?;
+    if json.len() <= limit {
+        return Ok(vec![json]);
+    }
+
+    let mut chunks = Vec::new();
+    let mut chunk = String::from("{");
+    for (key, value) in map {
+        let entry = format!(
+            "{}:{}",
+            serde_json::to_string(key)?,
+            serde_json::to_string(value)?
+        );
+        // the separating comma and the closing brace
+        if chunk.len() > 1 && chunk.len() + 1 + entry.len() + 1 > limit {
+            chunks.push(chunk + "}");
+            chunk = String::from("{");
+        }
+        if chunk.len() > 1 {
+            chunk.push(',');
+        }
+        chunk += &entry;
+    }
+    chunks.push(chunk + "}");
+    Ok(chunks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use influxdb2::models::DataPoint;
+
+    #[test]
+    fn small_map_is_kept() {
+        let map = BTreeMap::from([("2024-05-01 12:00", 5), ("2024-05-01 13:00", 7)]);
+        let chunks = split_json_map(&map, DEFAULT_FIELD_LIMIT).unwrap();
+        assert_eq!(chunks, [r#"{"2024-05-01 12:00":5,"2024-05-01 13:00":7}"#]);
+    }
+
+    #[test]
+    fn large_map_is_split() {
+        let map: BTreeMap<_, _> = (0..100_000u32)
+            .map(|i| (format!("2024-05-01 {i:06}"), i))
+            .collect();
+        let chunks = split_json_map(&map, DEFAULT_FIELD_LIMIT).unwrap();
+        assert!(chunks.len() > 1);
+
+        let mut builder = DataPoint::builder("forecast").timestamp(0);
+        let mut merged = BTreeMap::new();
+        for (i, chunk) in chunks.iter().enumerate() {
+            assert!(chunk.len() <= DEFAULT_FIELD_LIMIT, "{} bytes", chunk.len());
+            merged.extend(serde_json::from_str::<BTreeMap<String, u32>>(chunk).unwrap());
+            builder = builder.field(format!("forecasts_{i}"), chunk.clone());
+        }
+        assert_eq!(merged, map);
+        builder.build().unwrap();
+    }
+}
@@ -0,0 +1,202 @@
+use crate::locations::{self, Location, Model};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::{fs, io};
+use thiserror::Error;
+
+const BODY_EXTENSION: &str = "body.json";
+const META_EXTENSION: &str = "meta.json";
+
+#[derive(Debug, Error)]
+pub enum CaptureFixtureError {
+    #[error("no location named {0:?} configured")]
+    UnknownLocation(String),
+
+    #[error("request failed, {0}")]
+    Request(#[from] reqwest::Error),
+
+    #[error("could not serialize fixture metadata, {0}")]
+    Metadata(#[from] serde_json::Error),
+
+    #[error("could not write fixture, {0}")]
+    Write(#[from] io::Error),
+}
+
+pub async fn capture(location: &str, dir: &Path, api_url: &str) -> ExitCode {
+    match capture_impl(location, dir, api_url).await {
+        Ok(path) => {
+            println!("captured fixture {}", path.display());
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn capture_impl(
+    location: &str,
+    dir: &Path,
+    api_url: &str,
+) -> Result<PathBuf, CaptureFixtureError> {
+    let location = find_location(location)
+        .ok_or_else(|| CaptureFixtureError::UnknownLocation(location.to_string()))?;
+
+    let url = location.forecast_url(api_url, &Model::default_model());
+    let response = reqwest::get(&url).await?;
+    let status = response.status().as_u16();
+    let body = response.text().await?;
+
+    let meta = serde_json::json!({
+        "location": {
+            "id": location.id,
+            "name": location.name,
+        },
+        "url": url,
+        "status": status,
+        "captured_at": chrono::Utc::now().to_rfc3339(),
+    });
+
+    fs::create_dir_all(dir)?;
+    let body_path = fixture_path(dir, location, BODY_EXTENSION);
+    fs::write(&body_path, body)?;
+    let meta_path = fixture_path(dir, location, META_EXTENSION);
+    fs::write(meta_path, serde_json::to_string_pretty(&meta)? + "\n")?;
+    Ok(body_path)
+}
+
+/// Reads the captured response bodies in `dir`, ordered by file name.
+pub fn load_bodies(dir: &Path) -> io::Result<Vec<String>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.to_string_lossy().ends_with(BODY_EXTENSION) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    if paths.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no *.{BODY_EXTENSION} fixtures in {}", dir.display()),
+        ));
+    }
+    paths.into_iter().map(fs::read_to_string).collect()
+}
+
+/// Finds a configured location by its name, slug or id.
+pub fn find_location(name_or_id: &str) -> Option<&'static Location> {
+    locations::CONFIGURED
+        .iter()
+        .find(|l| l.name == name_or_id || l.slug() == name_or_id || l.id.to_string() == name_or_id)
+}
+
+fn fixture_path(dir: &Path, location: &Location, extension: &str) -> PathBuf {
+    dir.join(format!("location-{}.{extension}", location.id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locations::{parse_forecast_v2, Forecast, Target};
+    use influxdb2::models::WriteDataPoint;
+    use std::collections::BTreeSet;
+
+    /// Every top-level key of the API response that is mapped onto `Forecast`.
+    const KNOWN_FIELDS: &[&str] = &["vorhersageZeit", "lat", "lon", "aktuell", "vorhersage"];
+
+    fn fixture_location(body_path: &Path) -> &'static Location {
+        let meta_path = body_path
+            .to_str()
+            .unwrap()
+            .replace(BODY_EXTENSION, META_EXTENSION);
+        let meta: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(meta_path).unwrap()).unwrap();
+        let id = meta["location"]["id"].to_string();
+        find_location(&id).expect("fixture location is configured")
+    }
+
+    #[test]
+    fn fixtures_have_no_unknown_fields() {
+        insta::glob!("../tests/fixtures", "*.body.json", |path| {
+            let body: serde_json::Value =
+                serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+            let unknown: BTreeSet<_> = body
+                .as_object()
+                .expect("forecast is a json object")
+                .keys()
+                .filter(|key| !KNOWN_FIELDS.contains(&key.as_str()))
+                .collect();
+            assert!(
+                unknown.is_empty(),
+                "{} contains fields `Forecast` drops: {unknown:?}",
+                path.display()
+            );
+        });
+    }
+
+    #[test]
+    fn fixtures_deserialize() {
+        insta::glob!("../tests/fixtures", "*.body.json", |path| {
+            let forecast: Forecast =
+                serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+            insta::assert_debug_snapshot!(forecast);
+        });
+    }
+
+    #[test]
+    fn fixtures_data_points() {
+        insta::glob!("../tests/fixtures", "*.body.json", |path| {
+            let forecast: Forecast =
+                serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+            let target = Target {
+                location: fixture_location(path),
+                model: &Model::default_model(),
+            };
+            let data_point = crate::points::forecast_data_point(
+                target,
+                &forecast,
+                false,
+                false,
+                usize::MAX,
+                crate::geo::GeoFields::Point,
+            )
+            .unwrap();
+            let mut line_protocol = Vec::new();
+            data_point.write_data_point_to(&mut line_protocol).unwrap();
+            insta::assert_snapshot!(String::from_utf8(line_protocol).unwrap());
+        });
+    }
+
+    #[test]
+    fn v2_fixtures_deserialize() {
+        insta::glob!("../tests/fixtures/v2", "*.body.json", |path| {
+            let forecast = parse_forecast_v2(fs::read_to_string(path).unwrap()).unwrap();
+            insta::assert_debug_snapshot!(forecast);
+        });
+    }
+
+    #[test]
+    fn v2_fixtures_data_points() {
+        insta::glob!("../tests/fixtures/v2", "*.body.json", |path| {
+            let forecast = parse_forecast_v2(fs::read_to_string(path).unwrap()).unwrap();
+            let target = Target {
+                location: fixture_location(path),
+                model: &Model::default_model(),
+            };
+            let data_point = crate::points::forecast_data_point(
+                target,
+                &forecast,
+                false,
+                false,
+                usize::MAX,
+                crate::geo::GeoFields::Point,
+            )
+            .unwrap();
+            let mut line_protocol = Vec::new();
+            data_point.write_data_point_to(&mut line_protocol).unwrap();
+            insta::assert_snapshot!(String::from_utf8(line_protocol).unwrap());
+        });
+    }
+}
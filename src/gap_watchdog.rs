@@ -0,0 +1,234 @@
+use crate::locations::Location;
+use crate::names::NameMapping;
+use crate::points::MEASUREMENT_V2;
+use crate::severity::Severity;
+use crate::sink::Sink;
+use crate::webhook::AlertField;
+use influxdb2::api::query::FluxRecord;
+use influxdb2_structmap::value::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+use thiserror::Error;
+
+/// Name of the alert field listing the locations without points.
+const NAME: &str = "data gap watchdog";
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum GapWatchdogError {
+    #[error("expected GAP_CHECK_MINUTES to be a positive number of minutes, got {0:?}")]
+    Minutes(String),
+}
+
+/// Checks InfluxDB itself for locations without recent points, enabled via
+/// `GAP_CHECK_MINUTES`.
+///
+/// The collector's own bookkeeping misses points lost on their way, like through a bug of the
+/// write path, so every check queries the latest point per location over the window and alerts
+/// the locations without any. The window is the check interval, but at least two collection
+/// intervals so a single slow tick is no gap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GapWatchdog {
+    pub every: Duration,
+    pub window: Duration,
+}
+
+impl GapWatchdog {
+    /// Reads `GAP_CHECK_MINUTES` which `lookup` returns, `None` if it is not set.
+    pub fn from_lookup(
+        lookup: impl Fn(&str) -> Option<String>,
+        interval: Duration,
+    ) -> Result<Option<GapWatchdog>, GapWatchdogError> {
+        let Some(value) = lookup("GAP_CHECK_MINUTES") else {
+            return Ok(None);
+        };
+        let minutes = value
+            .trim()
+            .parse::<u64>()
+            .ok()
+            .filter(|minutes| *minutes > 0)
+            .ok_or(GapWatchdogError::Minutes(value))?;
+        let every = Duration::from_secs(minutes * 60);
+        Ok(Some(GapWatchdog {
+            every,
+            window: every.max(interval * 2),
+        }))
+    }
+
+    /// The latest forecast point per location id in `bucket` within the window.
+    pub fn query(&self, bucket: &str, names: &NameMapping) -> String {
+        let (id, current) = (names.tag("id"), names.field("current"));
+        format!(
+            r#"from(bucket: {bucket:?})
+            |> range(start: -{}s)
+            |> filter(fn: (r) => r._measurement == "forecast" or r._measurement == "{MEASUREMENT_V2}")
+            |> filter(fn: (r) => r._field == "{current}")
+            |> group(columns: ["{id}"])
+            |> last()
+            |> keep(columns: ["{id}"])"#,
+            self.window.as_secs()
+        )
+    }
+
+    /// The `locations` without a point in the window, in the order given.
+    pub async fn check<'l>(
+        &self,
+        sink: &Sink,
+        locations: &'l [Location],
+        names: &NameMapping,
+    ) -> Result<Vec<&'l Location>, influxdb2::RequestError> {
+        let mut buckets: BTreeMap<&str, Vec<&Location>> = BTreeMap::new();
+        for location in locations {
+            buckets
+                .entry(sink.bucket(location))
+                .or_default()
+                .push(location);
+        }
+        let mut present = BTreeSet::new();
+        for (bucket, locations) in buckets {
+            let records = sink.query(self.query(bucket, names)).await?;
+            let ids = ids(&records, names);
+            present.extend(
+                locations
+                    .into_iter()
+                    .filter(|location| ids.contains(&location.id.to_string()))
+                    .map(|location| location.id),
+            );
+        }
+        Ok(locations
+            .iter()
+            .filter(|location| !present.contains(&location.id))
+            .collect())
+    }
+
+    /// The critical alert about the `missing` locations.
+    pub fn alert_field(&self, missing: &[&Location]) -> AlertField {
+        let names: Vec<_> = missing.iter().map(|location| location.name).collect();
+        AlertField::outside_tick(
+            NAME.to_string(),
+            format!(
+                "no points in InfluxDB for the last {} minutes: {}",
+                self.window.as_secs() / 60,
+                names.join(", ")
+            ),
+            Severity::Critical,
+        )
+    }
+}
+
+/// The location ids of the `records` of the [query](GapWatchdog::query).
+fn ids(records: &[FluxRecord], names: &NameMapping) -> BTreeSet<String> {
+    records
+        .iter()
+        .filter_map(|record| match record.values.get(names.tag("id")) {
+            Some(Value::String(id)) => Some(id.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locations::LOCATIONS;
+    use crate::sink::Buckets;
+    use warp::Filter;
+
+    fn watchdog(minutes: &str) -> Result<Option<GapWatchdog>, GapWatchdogError> {
+        GapWatchdog::from_lookup(
+            |key| (key == "GAP_CHECK_MINUTES").then(|| minutes.to_string()),
+            Duration::from_secs(120),
+        )
+    }
+
+    #[test]
+    fn config() {
+        assert_eq!(
+            GapWatchdog::from_lookup(|_| None, Duration::from_secs(120)),
+            Ok(None)
+        );
+        let watchdog_every_hour = watchdog("60").unwrap().unwrap();
+        assert_eq!(watchdog_every_hour.every, Duration::from_secs(3600));
+        assert_eq!(watchdog_every_hour.window, Duration::from_secs(3600));
+
+        // the window covers at least two ticks
+        assert_eq!(
+            watchdog("1").unwrap().unwrap().window,
+            Duration::from_secs(240)
+        );
+        assert_eq!(
+            watchdog("0"),
+            Err(GapWatchdogError::Minutes("0".to_string()))
+        );
+        assert!(watchdog("hourly").is_err());
+
+        // cheap, the latest point per location
+        let query = watchdog_every_hour.query("swat", &NameMapping::default());
+        assert!(query.contains("range(start: -3600s)"), "{query}");
+        assert!(query.contains("group(columns: [\"id\"])"), "{query}");
+        assert!(query.contains("|> last()"), "{query}");
+    }
+
+    #[tokio::test]
+    async fn alerts_missing_locations() {
+        // only the first and third location have points
+        let csv = "#datatype,string,long,string\n\
+                   #group,false,false,true\n\
+                   #default,_result,,\n\
+                   ,result,table,id\n\
+                   ,,0,1\n\
+                   ,,1,3\n";
+        let query = warp::post()
+            .and(warp::path!("api" / "v2" / "query"))
+            .map(move || csv);
+        let (addr, server) = warp::serve(query).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        let locations = &LOCATIONS.locations[..3];
+        let sink = Sink::Influx {
+            client: Box::new(crate::sink::InfluxClient::new(
+                format!("http://{addr}"),
+                "org",
+                "token",
+            )),
+            buckets: Buckets::from_lookup(|_| None, locations),
+            idempotent: false,
+            profile: None,
+        };
+        let watchdog = watchdog("30").unwrap().unwrap();
+        let missing = watchdog
+            .check(&sink, locations, &NameMapping::default())
+            .await
+            .unwrap();
+        let ids: Vec<_> = missing.iter().map(|location| location.id).collect();
+        assert_eq!(ids, [LOCATIONS.locations[1].id]);
+
+        let alert = format!("{:?}", watchdog.alert_field(&missing));
+        assert!(alert.contains("name: \"data gap watchdog\""), "{alert}");
+        assert!(
+            alert.contains(&format!(
+                "no points in InfluxDB for the last 30 minutes: {}",
+                LOCATIONS.locations[1].name
+            )),
+            "{alert}"
+        );
+        assert!(alert.contains("severity: Critical"), "{alert}");
+        // raised outside of the ticks
+        assert!(alert.contains("tick_id: None"), "{alert}");
+
+        // a failing query is an error of the watchdog, not a gap
+        let sink = Sink::Influx {
+            client: Box::new(crate::sink::InfluxClient::new(
+                "http://127.0.0.1:1",
+                "org",
+                "token",
+            )),
+            buckets: Buckets::from_lookup(|_| None, locations),
+            idempotent: false,
+            profile: None,
+        };
+        let failed = watchdog
+            .check(&sink, locations, &NameMapping::default())
+            .await;
+        assert!(failed.is_err());
+    }
+}
@@ -0,0 +1,309 @@
+use chrono::{DateTime, Duration, Utc};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::Write;
+
+/// Gaps are kept for this many days after they closed.
+pub const RETENTION_DAYS: i64 = 30;
+
+/// Whether the resolved notification lists the gaps closed during the incident, configurable
+/// via `GAPS_IN_RESOLVED`.
+pub static GAPS_IN_RESOLVED: Lazy<bool> = Lazy::new(|| match env::var("GAPS_IN_RESOLVED") {
+    Ok(enabled) => enabled
+        .parse()
+        .unwrap_or_else(|err| panic!("expected \"GAPS_IN_RESOLVED\" to be valid, {err}")),
+    Err(_) => false,
+});
+
+/// A time span the forecasts of a location are missing for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Gap {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+impl Gap {
+    pub fn duration(&self) -> Duration {
+        self.end - self.start
+    }
+}
+
+/// The collection history of a location and model, as persisted in the state file.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TargetGaps {
+    #[serde(default)]
+    pub last_success: Option<DateTime<Utc>>,
+
+    /// Start of the gap still lasting, the first failed tick.
+    #[serde(default)]
+    pub open: Option<DateTime<Utc>>,
+
+    #[serde(default)]
+    pub gaps: Vec<Gap>,
+}
+
+/// Tracks per location and model the time spans no forecast was collected for, so gaps in the
+/// data do not need to be searched for in the dashboards after outages.
+///
+/// A gap starts with the first failed tick, or after the last success if the collector was not
+/// running, and ends with the next success. Gaps shorter than the `interval` are not recorded,
+/// closed ones are kept for [`RETENTION_DAYS`].
+#[derive(Debug)]
+pub struct GapTracker {
+    interval: Duration,
+    targets: BTreeMap<String, TargetGaps>,
+}
+
+impl GapTracker {
+    pub fn new(interval: std::time::Duration) -> GapTracker {
+        GapTracker {
+            interval: Duration::from_std(interval).expect("interval in range"),
+            targets: BTreeMap::new(),
+        }
+    }
+
+    /// Records that collecting `target` succeeded at `now`, returns the gap this closed.
+    pub fn success(&mut self, target: &str, now: DateTime<Utc>) -> Option<Gap> {
+        let interval = self.interval;
+        let history = self.targets.entry(target.to_string()).or_default();
+        let start = history.open.take().or_else(|| {
+            // ticks were missed while the collector was not running
+            let next = history.last_success? + interval;
+            (now - next >= interval).then_some(next)
+        });
+        history.last_success = Some(now);
+        let gap = Gap {
+            start: start?,
+            end: now,
+        };
+        if gap.duration() < interval {
+            return None;
+        }
+        history.gaps.push(gap);
+        Some(gap)
+    }
+
+    /// Records that collecting `target` failed at `now`, opening a gap unless one is open.
+    pub fn failure(&mut self, target: &str, now: DateTime<Utc>) {
+        let history = self.targets.entry(target.to_string()).or_default();
+        history.open.get_or_insert(now);
+    }
+
+    /// Forgets the gaps that closed more than [`RETENTION_DAYS`] before `now`.
+    pub fn prune(&mut self, now: DateTime<Utc>) {
+        let cutoff = now - Duration::days(RETENTION_DAYS);
+        for history in self.targets.values_mut() {
+            history.gaps.retain(|gap| gap.end >= cutoff);
+        }
+    }
+
+    /// Forgets the locations and models not in `targets`.
+    pub fn retain(&mut self, targets: &[String]) {
+        self.targets.retain(|target, _| targets.contains(target));
+    }
+
+    /// The gaps that closed at or after `since`, like during an incident.
+    pub fn closed_since(&self, since: DateTime<Utc>) -> Vec<(&str, Gap)> {
+        self.targets
+            .iter()
+            .flat_map(|(target, history)| {
+                history
+                    .gaps
+                    .iter()
+                    .filter(move |gap| gap.end >= since)
+                    .map(move |gap| (target.as_str(), *gap))
+            })
+            .collect()
+    }
+
+    /// The histories to persist.
+    pub fn export(&self) -> BTreeMap<String, TargetGaps> {
+        self.targets.clone()
+    }
+
+    /// Continues with the persisted histories.
+    pub fn restore(&mut self, targets: BTreeMap<String, TargetGaps>) {
+        self.targets = targets;
+    }
+
+    /// The number and total duration of the gaps per location shown in the verbose health check.
+    #[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+    pub fn summary_text(&self, now: DateTime<Utc>) -> String {
+        let mut text = String::new();
+        for (target, history) in &self.targets {
+            let closed = history.gaps.iter().map(Gap::duration);
+            let open = history.open.map(|start| now - start);
+            let count = history.gaps.len() + open.iter().len();
+            if count == 0 {
+                continue;
+            }
+            let total = closed.chain(open).fold(Duration::zero(), |a, b| a + b);
+            let _ = write!(text, "  {target}: {count} gaps, {}", format_duration(total));
+            if let Some(start) = history.open {
+                let _ = write!(text, ", missing since {}", start.format("%Y-%m-%d %H:%M"));
+            }
+            text.push('\n');
+        }
+        text
+    }
+}
+
+/// A gap as printed by `--gaps`, `end` is `None` while it lasts.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GapRow {
+    pub location: String,
+    pub start: DateTime<Utc>,
+    pub end: Option<DateTime<Utc>>,
+    pub minutes: i64,
+}
+
+/// The gaps of the persisted `targets`, ordered by location and start.
+pub fn rows(targets: &BTreeMap<String, TargetGaps>, now: DateTime<Utc>) -> Vec<GapRow> {
+    let mut rows = Vec::new();
+    for (target, history) in targets {
+        let closed = history.gaps.iter().map(|gap| (gap.start, Some(gap.end)));
+        for (start, end) in closed.chain(history.open.map(|start| (start, None))) {
+            rows.push(GapRow {
+                location: target.clone(),
+                start,
+                end,
+                minutes: (end.unwrap_or(now) - start).num_minutes(),
+            });
+        }
+    }
+    rows
+}
+
+/// The `rows` as a table with aligned columns.
+pub fn table(rows: &[GapRow]) -> String {
+    let width = rows
+        .iter()
+        .map(|row| row.location.chars().count())
+        .chain(["location".len()])
+        .max()
+        .unwrap_or_default();
+    let mut table = format!(
+        "{:width$}  {:16}  {:16}  duration\n",
+        "location", "start", "end"
+    );
+    for row in rows {
+        let end = match row.end {
+            Some(end) => end.format("%Y-%m-%d %H:%M").to_string(),
+            None => "ongoing".to_string(),
+        };
+        let _ = writeln!(
+            table,
+            "{:width$}  {}  {end:16}  {}",
+            row.location,
+            row.start.format("%Y-%m-%d %H:%M"),
+            format_duration(Duration::minutes(row.minutes))
+        );
+    }
+    table
+}
+
+/// Formats like `2h 5min`, shorter durations like `42min`.
+pub fn format_duration(duration: Duration) -> String {
+    let minutes = duration.num_minutes();
+    match minutes < 60 {
+        true => format!("{minutes}min"),
+        false => format!("{}h {}min", minutes / 60, minutes % 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    const TARGET: &str = "WW Großenkneten";
+
+    fn at(minutes: i64) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, 7, 8, 0, 0).unwrap() + Duration::minutes(minutes)
+    }
+
+    fn tracker() -> GapTracker {
+        GapTracker::new(std::time::Duration::from_secs(120))
+    }
+
+    #[test]
+    fn computes_intervals() {
+        let mut gaps = tracker();
+        assert_eq!(gaps.success(TARGET, at(0)), None);
+
+        // failures open a gap at the first one and the next success closes it
+        gaps.failure(TARGET, at(2));
+        gaps.failure(TARGET, at(4));
+        let closed = gaps.success(TARGET, at(6));
+        assert_eq!(
+            closed,
+            Some(Gap {
+                start: at(2),
+                end: at(6)
+            })
+        );
+
+        // a failure retried right away is too short to be a gap
+        gaps.failure(TARGET, at(8));
+        assert_eq!(gaps.success(TARGET, at(9)), None);
+
+        // the collector not running misses the ticks after the last success
+        assert_eq!(gaps.success(TARGET, at(11)), None);
+        assert_eq!(
+            gaps.success(TARGET, at(30)),
+            Some(Gap {
+                start: at(13),
+                end: at(30)
+            })
+        );
+
+        gaps.failure(TARGET, at(32));
+        let rows = rows(&gaps.export(), at(40));
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[2].end, None);
+        assert_eq!(rows[2].minutes, 8);
+        assert_eq!(
+            table(&rows),
+            "location         start             end               duration\n\
+             WW Großenkneten  2024-03-07 08:02  2024-03-07 08:06  4min\n\
+             WW Großenkneten  2024-03-07 08:13  2024-03-07 08:30  17min\n\
+             WW Großenkneten  2024-03-07 08:32  ongoing           8min\n"
+        );
+        assert_eq!(
+            gaps.summary_text(at(40)),
+            "  WW Großenkneten: 3 gaps, 29min, missing since 2024-03-07 08:32\n"
+        );
+        assert_eq!(gaps.closed_since(at(6)).len(), 2);
+        assert_eq!(gaps.closed_since(at(7)).len(), 1);
+    }
+
+    #[test]
+    fn prunes_old_gaps() {
+        let mut gaps = tracker();
+        gaps.failure(TARGET, at(0));
+        gaps.success(TARGET, at(10));
+        gaps.failure(TARGET, at(60 * 24));
+        gaps.success(TARGET, at(60 * 24 + 10));
+
+        let mut restored = tracker();
+        restored.restore(gaps.export());
+        restored.prune(at(10) + Duration::days(RETENTION_DAYS));
+        assert_eq!(restored.closed_since(at(0)).len(), 2);
+        restored.prune(at(11) + Duration::days(RETENTION_DAYS));
+        assert_eq!(
+            restored.closed_since(at(0)),
+            [(
+                TARGET,
+                Gap {
+                    start: at(60 * 24),
+                    end: at(60 * 24 + 10)
+                }
+            )]
+        );
+
+        restored.retain(&[]);
+        assert_eq!(restored.export(), BTreeMap::new());
+    }
+}
@@ -0,0 +1,151 @@
+use crate::coordinates::{Coordinates, CoordinatesError};
+use crate::locations::Location;
+use influxdb2::models::data_point::DataPointError;
+use influxdb2::models::DataPoint;
+use once_cell::sync::Lazy;
+use std::env;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Where the coordinates are written as numeric fields, configurable via `GEO_FIELDS`.
+pub static GEO_FIELDS: Lazy<GeoFields> = Lazy::new(|| match env::var("GEO_FIELDS") {
+    Ok(geo_fields) => geo_fields
+        .parse()
+        .unwrap_or_else(|err| panic!("expected \"GEO_FIELDS\" to be valid, {err}")),
+    Err(_) => GeoFields::Point,
+});
+
+/// Numeric `latitude` and `longitude` fields for map panels, the `lat` and `lon` tags are
+/// written regardless.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeoFields {
+    /// On every forecast point.
+    Point,
+
+    /// Once per location at startup, in the `locations` measurement.
+    Measurement,
+
+    Off,
+}
+
+#[derive(Debug, Error)]
+#[error("unknown geo fields {0:?}, expected one of \"point\", \"measurement\" or \"off\"")]
+pub struct ParseGeoFieldsError(String);
+
+impl FromStr for GeoFields {
+    type Err = ParseGeoFieldsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "point" => Ok(GeoFields::Point),
+            "measurement" => Ok(GeoFields::Measurement),
+            "off" => Ok(GeoFields::Off),
+            _ => Err(ParseGeoFieldsError(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum GeoError {
+    #[error("invalid coordinates of location {name:?}, {error}")]
+    Coordinates {
+        name: &'static str,
+        error: CoordinatesError,
+    },
+
+    #[error("error while building location point, {0}")]
+    DataPoint(#[from] DataPointError),
+}
+
+/// The coordinates of `location`.
+pub fn coordinates(location: &Location) -> Result<Coordinates, GeoError> {
+    location
+        .coordinates()
+        .map_err(|error| GeoError::Coordinates {
+            name: location.name,
+            error,
+        })
+}
+
+/// Checks that the coordinates of every location are valid, so the tags, fields and requests
+/// can be built.
+pub fn check_coordinates(locations: &[Location]) -> Result<(), GeoError> {
+    locations
+        .iter()
+        .try_for_each(|location| coordinates(location).map(drop))
+}
+
+/// A point in the `locations` measurement with the coordinates of `location` as fields.
+pub fn location_point(location: &Location) -> Result<DataPoint, GeoError> {
+    let coordinates = coordinates(location)?;
+    let point = DataPoint::builder("locations")
+        .tag("id", location.id.to_string())
+        .tag("name", location.name)
+        .tag("slug", location.slug())
+        .field("latitude", coordinates.lat())
+        .field("longitude", coordinates.lon())
+        .build()?;
+    Ok(point)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locations::{Model, Target, LOCATIONS};
+    use influxdb2::models::WriteDataPoint;
+
+    fn line(point: DataPoint) -> String {
+        let mut line = Vec::new();
+        point.write_data_point_to(&mut line).unwrap();
+        String::from_utf8(line).unwrap()
+    }
+
+    #[test]
+    fn parse_config() {
+        assert_eq!("point".parse::<GeoFields>().unwrap(), GeoFields::Point);
+        assert_eq!(
+            " Measurement".parse::<GeoFields>().unwrap(),
+            GeoFields::Measurement
+        );
+        assert_eq!("off".parse::<GeoFields>().unwrap(), GeoFields::Off);
+        assert!("tags".parse::<GeoFields>().is_err());
+    }
+
+    #[test]
+    fn representations() {
+        check_coordinates(&LOCATIONS.locations).unwrap();
+        let location = &LOCATIONS.locations[0];
+        assert_eq!(
+            line(location_point(location).unwrap()),
+            "locations,id=1,name=WW\\ Großenkneten,slug=ww-grossenkneten \
+             latitude=52.9109818816186,longitude=8.23505277402053\n"
+        );
+
+        let body = include_str!("../tests/fixtures/location-1.body.json");
+        let forecast = serde_json::from_str(body).unwrap();
+        let target = Target {
+            location,
+            model: &Model::default_model(),
+        };
+        let point = |geo_fields| {
+            line(
+                crate::points::forecast_data_point(
+                    target,
+                    &forecast,
+                    false,
+                    false,
+                    usize::MAX,
+                    geo_fields,
+                )
+                .unwrap(),
+            )
+        };
+        let with_fields = point(GeoFields::Point);
+        assert!(with_fields.contains(",latitude=52.9109818816186,longitude=8.23505277402053"));
+        for geo_fields in [GeoFields::Measurement, GeoFields::Off] {
+            let without_fields = point(geo_fields);
+            assert!(!without_fields.contains("latitude="), "{without_fields}");
+            assert!(without_fields.contains(",lat=52.91098,lon=8.23505"));
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use crate::locations::{Location, Target};
+use std::collections::{BTreeMap, BTreeSet};
+use thiserror::Error;
+
+/// Group of the locations without one.
+pub const DEFAULT_GROUP: &str = "default";
+
+/// Groups are cut off in dashboards beyond this many bytes.
+const MAX_LENGTH: usize = 64;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("location {location:?} has the invalid group {group:?}, {reason}")]
+pub struct GroupError {
+    location: &'static str,
+    group: &'static str,
+    reason: &'static str,
+}
+
+/// The group of `location`, the [`DEFAULT_GROUP`] unless it has one.
+pub fn of(location: &Location) -> &'static str {
+    match location.group {
+        "" => DEFAULT_GROUP,
+        group => group,
+    }
+}
+
+/// Fails if the group of any of the `locations` is unfit for a tag.
+///
+/// Groups are free-form like the names of the locations, but must not contain control
+/// characters like line breaks, nor surrounding whitespace, and need a letter or digit.
+pub fn check(locations: &[Location]) -> Result<(), GroupError> {
+    for location in locations {
+        let group = location.group;
+        let reason = if group.is_empty() {
+            continue;
+        } else if group.chars().any(char::is_control) {
+            "expected no control characters"
+        } else if group.trim() != group {
+            "expected no surrounding whitespace"
+        } else if group.len() > MAX_LENGTH {
+            "expected at most 64 bytes"
+        } else if !group.chars().any(char::is_alphanumeric) {
+            "expected a letter or digit"
+        } else {
+            continue;
+        };
+        return Err(GroupError {
+            location: location.name,
+            group,
+            reason,
+        });
+    }
+    Ok(())
+}
+
+/// The groups of the collected targets, for aggregating alerts and the status by them.
+#[derive(Debug, Clone, Default)]
+pub struct LocationGroups {
+    targets: BTreeMap<String, &'static str>,
+}
+
+impl LocationGroups {
+    pub fn new(targets: &[Target]) -> LocationGroups {
+        LocationGroups {
+            targets: targets
+                .iter()
+                .map(|target| (target.to_string(), of(target.location)))
+                .collect(),
+        }
+    }
+
+    /// Whether any target has a group, otherwise alerts and the status stay flat.
+    pub fn is_grouped(&self) -> bool {
+        self.targets.values().any(|group| *group != DEFAULT_GROUP)
+    }
+
+    /// The failing and total targets per group, with the [`DEFAULT_GROUP`] last, of the
+    /// `failing` targets named like [`Target`]'s `Display`.
+    pub fn summary<'f>(&self, failing: impl IntoIterator<Item = &'f str>) -> Vec<GroupSummary> {
+        let failing: BTreeSet<_> = failing.into_iter().collect();
+        let mut groups: BTreeMap<&str, GroupSummary> = BTreeMap::new();
+        for (target, group) in &self.targets {
+            let summary = groups.entry(group).or_insert_with(|| GroupSummary {
+                group,
+                failing: 0,
+                total: 0,
+            });
+            summary.total += 1;
+            summary.failing += failing.contains(target.as_str()) as usize;
+        }
+        let (default, mut summaries): (Vec<_>, Vec<_>) = groups
+            .into_values()
+            .partition(|summary| summary.group == DEFAULT_GROUP);
+        summaries.extend(default);
+        summaries
+    }
+
+    /// The failing and total targets per group while any has a group, empty otherwise.
+    #[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+    pub fn status_text(&self, failing: &[String]) -> String {
+        if !self.is_grouped() {
+            return String::new();
+        }
+        let summary = self.summary(failing.iter().map(String::as_str));
+        let width = summary.iter().map(|summary| summary.group.len()).max();
+        let mut text = "location groups:\n".to_string();
+        for GroupSummary {
+            group,
+            failing,
+            total,
+        } in summary
+        {
+            let width = width.unwrap_or_default() + 1;
+            text += &format!(
+                "  {:<width$} {failing}/{total} failing\n",
+                format!("{group}:")
+            );
+        }
+        text
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GroupSummary {
+    pub group: &'static str,
+    pub failing: usize,
+    pub total: usize,
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::locations::Model;
+
+    /// Two locations in Oldenburg, one in Wildeshausen and two without a group.
+    pub(crate) fn mixed_locations() -> &'static [Location] {
+        let location = |id, name, group| Location {
+            group,
+            id,
+            lat: "53.1",
+            lon: "8.2",
+            name,
+        };
+        Box::leak(Box::new([
+            location(1, "WW Alexandersfeld", "Oldenburg"),
+            location(2, "WW Donnerschwee", "Oldenburg"),
+            location(3, "WW Wildeshausen", "Wildeshausen"),
+            location(4, "WW Großenkneten", ""),
+            location(5, "WW Harpstedt", ""),
+        ]))
+    }
+
+    pub(crate) fn targets(locations: &'static [Location]) -> Vec<Target<'static>> {
+        let model: &'static Model = Box::leak(Box::new(Model::default_model()));
+        locations
+            .iter()
+            .map(|location| Target { location, model })
+            .collect()
+    }
+
+    #[test]
+    fn validates_groups() {
+        check(&crate::locations::LOCATIONS.locations).unwrap();
+        check(mixed_locations()).unwrap();
+
+        for (group, reason) in [
+            ("Olden\nburg", "expected no control characters"),
+            (" Oldenburg", "expected no surrounding whitespace"),
+            ("--", "expected a letter or digit"),
+        ] {
+            let invalid = [Location {
+                group,
+                ..mixed_locations()[0].clone()
+            }];
+            let err = check(&invalid).unwrap_err();
+            assert_eq!(err.reason, reason, "{group:?}");
+        }
+        let long: &'static str = "Landkreis ".repeat(7).leak();
+        let long = long.trim();
+        let invalid = [Location {
+            group: long,
+            ..mixed_locations()[0].clone()
+        }];
+        assert_eq!(
+            check(&invalid).unwrap_err().to_string(),
+            format!(
+                "location \"WW Alexandersfeld\" has the invalid group {long:?}, expected at most \
+                 64 bytes"
+            )
+        );
+    }
+
+    #[test]
+    fn status_table() {
+        let groups = LocationGroups::new(&targets(mixed_locations()));
+        assert!(groups.is_grouped());
+        let failing = ["WW Donnerschwee".to_string(), "WW Harpstedt".to_string()];
+        insta::assert_snapshot!(groups.status_text(&failing));
+
+        // flat without any group
+        let ungrouped = LocationGroups::new(&targets(&crate::locations::LOCATIONS.locations));
+        assert!(!ungrouped.is_grouped());
+        assert_eq!(ungrouped.status_text(&failing), "");
+    }
+}
@@ -0,0 +1,451 @@
+use crate::read_only;
+use crate::state::AppState;
+use crate::version;
+use proto::collector_server::{Collector, CollectorServer};
+use proto::{
+    ConfigSummary, GetStatusRequest, Incident, LocationStatus, MuteRequest, MuteResponse, Status,
+    TickSummary, TriggerCollectionRequest,
+};
+use std::net::{AddrParseError, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::net::TcpListener;
+use tokio::sync::{watch, Notify};
+use tonic::service::Interceptor;
+use tonic::transport::server::TcpIncoming;
+use tonic::{Request, Response};
+
+pub mod proto {
+    tonic::include_proto!("swat_collector.v1");
+}
+
+#[derive(Debug, Error)]
+pub enum GrpcConfigError {
+    #[error("expected \"GRPC_ADDR\" to be a socket address, {0}")]
+    Addr(#[from] AddrParseError),
+
+    #[error("\"GRPC_TOKEN\" is required along with \"GRPC_ADDR\"")]
+    MissingToken,
+}
+
+#[derive(Debug, Error)]
+pub enum GrpcError {
+    #[error("could not listen, {0}")]
+    Listen(Box<dyn std::error::Error + Send + Sync>),
+
+    #[error("server failed, {0}")]
+    Serve(#[from] tonic::transport::Error),
+}
+
+/// Where to serve the gRPC service and the token its clients authenticate with.
+#[derive(Clone, PartialEq, Eq)]
+pub struct GrpcConfig {
+    pub addr: SocketAddr,
+    pub token: String,
+}
+
+impl std::fmt::Debug for GrpcConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GrpcConfig")
+            .field("addr", &self.addr)
+            .finish_non_exhaustive()
+    }
+}
+
+impl GrpcConfig {
+    /// Reads `GRPC_ADDR` and `GRPC_TOKEN` from `lookup`, returns `None` if no address is set.
+    pub fn from_lookup(
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<Option<GrpcConfig>, GrpcConfigError> {
+        let Some(addr) = lookup("GRPC_ADDR") else {
+            return Ok(None);
+        };
+        let token = lookup("GRPC_TOKEN")
+            .filter(|token| !token.is_empty())
+            .ok_or(GrpcConfigError::MissingToken)?;
+        Ok(Some(GrpcConfig {
+            addr: addr.parse()?,
+            token,
+        }))
+    }
+}
+
+/// The passes started and finished by the collection loop, so a triggered pass can be waited
+/// for.
+#[derive(Debug)]
+pub struct Passes {
+    started: AtomicU64,
+    finished: watch::Sender<TickSummary>,
+}
+
+impl Default for Passes {
+    fn default() -> Self {
+        Passes {
+            started: AtomicU64::new(0),
+            finished: watch::channel(TickSummary::default()).0,
+        }
+    }
+}
+
+impl Passes {
+    pub fn start(&self, tick_id: u64) {
+        self.started.store(tick_id, Ordering::SeqCst);
+    }
+
+    pub fn finish(&self, tick_id: u64, targets: usize, failed: Vec<String>, elapsed: Duration) {
+        self.finished.send_replace(TickSummary {
+            tick_id,
+            targets: targets as u32,
+            failed,
+            elapsed_millis: elapsed.as_millis() as u64,
+        });
+    }
+
+    /// Requests a pass via `trigger` and waits for the first one started afterwards to finish.
+    async fn trigger(&self, trigger: &Notify) -> Option<TickSummary> {
+        let mut finished = self.finished.subscribe();
+        let after = self.started.load(Ordering::SeqCst);
+        trigger.notify_one();
+        let summary = finished.wait_for(|summary| summary.tick_id > after).await;
+        summary.ok().map(|summary| summary.clone())
+    }
+}
+
+/// Serves the status of the collector and lets orchestration tooling trigger passes and mute
+/// the alerts, sharing the state with the collection loop and the health listener.
+pub struct CollectorService {
+    state: Arc<AppState>,
+    trigger: Arc<Notify>,
+
+    /// The collected locations and models, as shown in the logs.
+    targets: Vec<String>,
+    interval: Duration,
+}
+
+impl CollectorService {
+    pub fn new(
+        state: Arc<AppState>,
+        trigger: Arc<Notify>,
+        targets: Vec<String>,
+        interval: Duration,
+    ) -> CollectorService {
+        CollectorService {
+            state,
+            trigger,
+            targets,
+            interval,
+        }
+    }
+
+    fn config(&self) -> ConfigSummary {
+        let outputs = [
+            Some("influxdb"),
+            #[cfg(feature = "archive")]
+            self.state.archive.as_ref().map(|_| "archive"),
+            #[cfg(feature = "kafka")]
+            self.state.kafka.as_ref().map(|_| "kafka"),
+            #[cfg(feature = "nats")]
+            self.state.nats.as_ref().map(|_| "nats"),
+        ];
+        ConfigSummary {
+            version: version::LONG_VERSION.to_string(),
+            targets: self.targets.len() as u32,
+            interval_seconds: self.interval.as_secs(),
+            outputs: outputs.into_iter().flatten().map(String::from).collect(),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Collector for CollectorService {
+    async fn get_status(
+        &self,
+        _: Request<GetStatusRequest>,
+    ) -> Result<Response<Status>, tonic::Status> {
+        let state = &self.state;
+        let now = state.clock.now_utc();
+        let failing = state.health.stale_locations();
+        let locations = {
+            let issues = state.issues.read();
+            self.targets
+                .iter()
+                .map(|target| {
+                    let (issued, since) = issues
+                        .issued(target)
+                        .map(|(issued, since)| (issued.to_string(), since.timestamp()))
+                        .unwrap_or_default();
+                    LocationStatus {
+                        target: target.clone(),
+                        issued,
+                        issued_since: since,
+                        failing: failing.contains(target),
+                    }
+                })
+                .collect()
+        };
+        let incident = state
+            .incident
+            .read()
+            .open()
+            .map(|(since, failures)| Incident {
+                since: since.timestamp(),
+                failures,
+            });
+        let mute = {
+            let mute = state.mute.read();
+            match mute.is_muted(now) {
+                true => mute.status(now),
+                false => String::new(),
+            }
+        };
+        Ok(Response::new(Status {
+            healthy: state.health.signals().healthy(),
+            locations,
+            incident,
+            config: Some(self.config()),
+            mute,
+        }))
+    }
+
+    async fn trigger_collection(
+        &self,
+        _: Request<TriggerCollectionRequest>,
+    ) -> Result<Response<TickSummary>, tonic::Status> {
+        match self.state.passes.trigger(&self.trigger).await {
+            Some(summary) => Ok(Response::new(summary)),
+            None => Err(tonic::Status::unavailable("collector is shutting down")),
+        }
+    }
+
+    async fn mute(
+        &self,
+        request: Request<MuteRequest>,
+    ) -> Result<Response<MuteResponse>, tonic::Status> {
+        if self.state.read_only {
+            return Err(tonic::Status::permission_denied(read_only::REJECTED));
+        }
+        let now = self.state.clock.now_utc();
+        let mut mute = self.state.mute.write();
+        match request.into_inner().minutes {
+            0 => mute.unmute(),
+            minutes => mute.mute(now + chrono::Duration::minutes(minutes as i64), now),
+        }
+        Ok(Response::new(MuteResponse {
+            status: mute.status(now),
+        }))
+    }
+}
+
+/// Rejects requests without the token as bearer token in their `authorization` metadata.
+#[derive(Clone)]
+struct Authorization {
+    expected: String,
+}
+
+impl Authorization {
+    fn new(token: &str) -> Authorization {
+        Authorization {
+            expected: format!("Bearer {token}"),
+        }
+    }
+}
+
+impl Interceptor for Authorization {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, tonic::Status> {
+        let given = request
+            .metadata()
+            .get("authorization")
+            .map(|value| value.as_bytes())
+            .unwrap_or_default();
+        // compares every byte, so the time taken does not tell how much of the token matched
+        let matches = given.len() == self.expected.len()
+            && given
+                .iter()
+                .zip(self.expected.as_bytes())
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0;
+        match matches {
+            true => Ok(request),
+            false => Err(tonic::Status::unauthenticated("invalid bearer token")),
+        }
+    }
+}
+
+/// Serves the `service` on the `listener` to clients presenting the `token`.
+pub async fn serve(
+    listener: TcpListener,
+    token: String,
+    service: CollectorService,
+) -> Result<(), GrpcError> {
+    let incoming = TcpIncoming::from_listener(listener, true, None).map_err(GrpcError::Listen)?;
+    let service = CollectorServer::with_interceptor(service, Authorization::new(&token));
+    tonic::transport::Server::builder()
+        .add_service(service)
+        .serve_with_incoming(incoming)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, MockClock};
+    use proto::collector_client::CollectorClient;
+    use tonic::transport::Channel;
+
+    const TOKEN: &str = "s3cret";
+
+    fn authorized<T>(message: T) -> Request<T> {
+        let mut request = Request::new(message);
+        let value = format!("Bearer {TOKEN}").parse().unwrap();
+        request.metadata_mut().insert("authorization", value);
+        request
+    }
+
+    #[test]
+    fn config_from_lookup() {
+        assert_eq!(GrpcConfig::from_lookup(|_| None).unwrap(), None);
+        let missing = GrpcConfig::from_lookup(|key| match key {
+            "GRPC_ADDR" => Some("127.0.0.1:50051".to_string()),
+            _ => None,
+        });
+        assert!(matches!(missing, Err(GrpcConfigError::MissingToken)));
+        let config = GrpcConfig::from_lookup(|key| match key {
+            "GRPC_ADDR" => Some("[::]:50051".to_string()),
+            "GRPC_TOKEN" => Some(TOKEN.to_string()),
+            _ => None,
+        })
+        .unwrap()
+        .unwrap();
+        assert_eq!(config.addr.port(), 50051);
+        assert!(!format!("{config:?}").contains(TOKEN));
+    }
+
+    #[tokio::test]
+    async fn serves_status_trigger_and_mute() {
+        let clock = Arc::new(MockClock::new());
+        let now = clock.now_utc();
+        let state = Arc::new(AppState::new(8).with_clock(clock));
+        state
+            .issues
+            .write()
+            .observe("WW Großenkneten", "2024-03-07 08:05", now);
+        state
+            .health
+            .record_error("WW Marienhafe", "http", "connection refused");
+        state
+            .incident
+            .write()
+            .observe(1, "ww-marienhafe/request_forecast", now);
+
+        let trigger = Arc::new(Notify::new());
+        let targets = vec!["WW Großenkneten".to_string(), "WW Marienhafe".to_string()];
+        let service = CollectorService::new(
+            state.clone(),
+            trigger.clone(),
+            targets,
+            Duration::from_secs(120),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, TOKEN.to_string(), service));
+
+        // stands in for the collection loop, running a pass whenever triggered
+        tokio::spawn({
+            let (state, trigger) = (state.clone(), trigger.clone());
+            async move {
+                for tick_id in 1.. {
+                    trigger.notified().await;
+                    state.passes.start(tick_id);
+                    let failed = vec!["WW Marienhafe".to_string()];
+                    state
+                        .passes
+                        .finish(tick_id, 2, failed, Duration::from_millis(1500));
+                }
+            }
+        });
+
+        let channel = Channel::from_shared(format!("http://{addr}"))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        let mut client = CollectorClient::new(channel);
+
+        let rejected = client
+            .get_status(Request::new(GetStatusRequest {}))
+            .await
+            .unwrap_err();
+        assert_eq!(rejected.code(), tonic::Code::Unauthenticated);
+
+        let status = client
+            .get_status(authorized(GetStatusRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(
+            status.locations,
+            [
+                LocationStatus {
+                    target: "WW Großenkneten".to_string(),
+                    issued: "2024-03-07 08:05".to_string(),
+                    issued_since: now.timestamp(),
+                    failing: false,
+                },
+                LocationStatus {
+                    target: "WW Marienhafe".to_string(),
+                    issued: String::new(),
+                    issued_since: 0,
+                    failing: true,
+                },
+            ]
+        );
+        assert_eq!(
+            status.incident,
+            Some(Incident {
+                since: now.timestamp(),
+                failures: 1,
+            })
+        );
+        let config = status.config.unwrap();
+        assert_eq!((config.targets, config.interval_seconds), (2, 120));
+        assert_eq!(config.outputs[0], "influxdb");
+        assert_eq!(status.mute, "");
+
+        let summary = client
+            .trigger_collection(authorized(TriggerCollectionRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(
+            summary,
+            TickSummary {
+                tick_id: 1,
+                targets: 2,
+                failed: vec!["WW Marienhafe".to_string()],
+                elapsed_millis: 1500,
+            }
+        );
+        let summary = client
+            .trigger_collection(authorized(TriggerCollectionRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(summary.tick_id, 2);
+
+        let muted = client
+            .mute(authorized(MuteRequest { minutes: 30 }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(state.mute.read().is_muted(now));
+        assert_eq!(muted.status, state.mute.read().status(now));
+        client
+            .mute(authorized(MuteRequest { minutes: 0 }))
+            .await
+            .unwrap();
+        assert!(!state.mute.read().is_muted(now));
+    }
+}
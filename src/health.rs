@@ -0,0 +1,17 @@
+//! Health signals of a collector, checked by `--health-check`.
+//!
+//! ```
+//! use swat_collector::health::HealthState;
+//!
+//! let health = HealthState::new();
+//! assert!(!health.signals().healthy());
+//! health.tick();
+//! health.update();
+//! assert!(health.signals().healthy());
+//! ```
+
+pub use crate::clock::{Clock, SystemClock};
+pub use crate::health_check::{
+    check, Format, HealthConfig, HealthConfigError, HealthMode, HealthState, ParseHealthModeError,
+    Signals, Transition,
+};
@@ -0,0 +1,311 @@
+use crate::tick_stats;
+use chrono::{DateTime, Duration, Utc};
+use influxdb2::models::data_point::DataPointError;
+use influxdb2::models::DataPoint;
+use std::collections::BTreeMap;
+
+/// Most locations tracked on their own, further locations share [`OVERFLOW`].
+const MAX_LOCATIONS: usize = 256;
+
+/// Name of the histogram shared by the locations beyond [`MAX_LOCATIONS`].
+const OVERFLOW: &str = "(other locations)";
+
+/// Upper bounds of the histogram buckets in milliseconds, the last bucket is unbounded.
+const BOUNDS: [u64; 16] = [
+    10, 20, 50, 100, 200, 300, 500, 750, 1_000, 1_500, 2_000, 3_000, 5_000, 10_000, 20_000, 30_000,
+];
+
+/// Request latencies bucketed into [`BOUNDS`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Histogram {
+    counts: [u32; BOUNDS.len() + 1],
+    total: u32,
+}
+
+impl Histogram {
+    fn record(&mut self, latency: std::time::Duration) {
+        let millis = latency.as_millis();
+        let bucket = BOUNDS
+            .iter()
+            .position(|&bound| millis <= bound as u128)
+            .unwrap_or(BOUNDS.len());
+        self.counts[bucket] = self.counts[bucket].saturating_add(1);
+        self.total = self.total.saturating_add(1);
+    }
+
+    /// Upper bound of the bucket the `percentile` falls into, `None` for the unbounded bucket.
+    fn percentile(&self, percentile: u32) -> Option<u64> {
+        let rank = (self.total as u64 * percentile as u64).div_ceil(100).max(1);
+        let mut seen = 0;
+        for (bucket, &count) in self.counts.iter().enumerate() {
+            seen += count as u64;
+            if seen >= rank {
+                return BOUNDS.get(bucket).copied();
+            }
+        }
+        None
+    }
+}
+
+/// Latency percentiles of the requests of a location.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Percentiles {
+    pub p50: Option<u64>,
+    pub p95: Option<u64>,
+    pub p99: Option<u64>,
+    pub requests: u32,
+}
+
+/// The percentiles of an hourly window of [`Latencies`] that ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LatencyWindow {
+    pub start: DateTime<Utc>,
+    pub percentiles: Vec<(String, Percentiles)>,
+}
+
+impl LatencyWindow {
+    /// A [`tick_stats::MEASUREMENT`] point per location with its percentiles in milliseconds,
+    /// at the start of the window.
+    ///
+    /// Percentiles in the unbounded bucket, above the largest bound, are left out.
+    pub fn data_points(&self) -> Result<Vec<DataPoint>, DataPointError> {
+        (self.percentiles.iter())
+            .map(|(location, percentiles)| {
+                let mut point = DataPoint::builder(tick_stats::MEASUREMENT)
+                    .timestamp(self.start.timestamp())
+                    .tag("location", location.as_str())
+                    .tag("window", "1h")
+                    .field("requests", percentiles.requests as i64);
+                for (field, bound) in [
+                    ("latency_p50_ms", percentiles.p50),
+                    ("latency_p95_ms", percentiles.p95),
+                    ("latency_p99_ms", percentiles.p99),
+                ] {
+                    if let Some(bound) = bound {
+                        point = point.field(field, bound as i64);
+                    }
+                }
+                point.build()
+            })
+            .collect()
+    }
+}
+
+/// Request latencies per location in hourly windows.
+///
+/// The window is reset with the first request recorded an hour after it started, so the
+/// percentiles cover at most the last hour. The percentiles of the window reset are kept until
+/// they are [taken](Self::take_window) for writing them.
+#[derive(Debug)]
+pub struct Latencies {
+    window_start: Option<DateTime<Utc>>,
+    locations: BTreeMap<String, Histogram>,
+    ended: Option<LatencyWindow>,
+}
+
+impl Latencies {
+    pub const fn new() -> Latencies {
+        Latencies {
+            window_start: None,
+            locations: BTreeMap::new(),
+            ended: None,
+        }
+    }
+
+    /// Records the `latency` of a request for `location` at `now`.
+    pub fn record(&mut self, location: &str, latency: std::time::Duration, now: DateTime<Utc>) {
+        match self.window_start {
+            Some(start) if now - start < Duration::hours(1) => (),
+            start => {
+                if let Some(start) = start {
+                    self.ended = Some(LatencyWindow {
+                        start,
+                        percentiles: (self.percentiles())
+                            .map(|(name, percentiles)| (name.to_string(), percentiles))
+                            .collect(),
+                    });
+                }
+                self.window_start = Some(now);
+                self.locations.clear();
+            }
+        }
+
+        let tracked = self.locations.len() - self.locations.contains_key(OVERFLOW) as usize;
+        let name = match self.locations.contains_key(location) || tracked < MAX_LOCATIONS {
+            true => location,
+            false => OVERFLOW,
+        };
+        self.locations
+            .entry(name.to_string())
+            .or_default()
+            .record(latency);
+    }
+
+    /// Forgets the locations not in `locations`.
+    pub fn retain(&mut self, locations: &[String]) {
+        self.locations
+            .retain(|name, _| name == OVERFLOW || locations.contains(name));
+    }
+
+    /// Percentiles per location of the current window.
+    pub fn percentiles(&self) -> impl Iterator<Item = (&str, Percentiles)> {
+        self.locations.iter().map(|(name, histogram)| {
+            let percentiles = Percentiles {
+                p50: histogram.percentile(50),
+                p95: histogram.percentile(95),
+                p99: histogram.percentile(99),
+                requests: histogram.total,
+            };
+            (name.as_str(), percentiles)
+        })
+    }
+
+    /// The percentiles of the window that ended last, unless taken already.
+    pub fn take_window(&mut self) -> Option<LatencyWindow> {
+        self.ended.take()
+    }
+
+    /// One line per location with its percentiles.
+    pub fn text(&self) -> String {
+        let format = |bound: Option<u64>| match bound {
+            Some(bound) => format!("<={bound}ms"),
+            None => format!(">{}ms", BOUNDS[BOUNDS.len() - 1]),
+        };
+        self.percentiles()
+            .map(|(name, p)| {
+                format!(
+                    "{name}: p50 {}, p95 {}, p99 {} ({} requests)\n",
+                    format(p.p50),
+                    format(p.p95),
+                    format(p.p99),
+                    p.requests
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::time::Duration as StdDuration;
+
+    fn at(minute: i64) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap() + Duration::minutes(minute)
+    }
+
+    fn ms(millis: u64) -> StdDuration {
+        StdDuration::from_millis(millis)
+    }
+
+    #[test]
+    fn reports_percentiles() {
+        let mut latencies = Latencies::new();
+        // 90 fast requests, 8 slow ones and 2 timing out
+        for i in 0..100 {
+            let latency = match i {
+                0..=89 => ms(80),
+                90..=97 => ms(1_200),
+                _ => ms(45_000),
+            };
+            latencies.record("A", latency, at(0));
+        }
+        latencies.record("B", ms(5), at(1));
+
+        let percentiles: Vec<_> = latencies.percentiles().collect();
+        assert_eq!(
+            percentiles,
+            [
+                (
+                    "A",
+                    Percentiles {
+                        p50: Some(100),
+                        p95: Some(1_500),
+                        p99: None,
+                        requests: 100
+                    }
+                ),
+                (
+                    "B",
+                    Percentiles {
+                        p50: Some(10),
+                        p95: Some(10),
+                        p99: Some(10),
+                        requests: 1
+                    }
+                ),
+            ]
+        );
+        assert_eq!(
+            latencies.text(),
+            "A: p50 <=100ms, p95 <=1500ms, p99 >30000ms (100 requests)\n\
+             B: p50 <=10ms, p95 <=10ms, p99 <=10ms (1 requests)\n"
+        );
+    }
+
+    #[test]
+    fn resets_hourly() {
+        let mut latencies = Latencies::new();
+        latencies.record("A", ms(2_500), at(0));
+        latencies.record("A", ms(2_500), at(59));
+        assert_eq!(latencies.percentiles().next().unwrap().1.requests, 2);
+        assert_eq!(latencies.take_window(), None);
+
+        latencies.record("B", ms(50), at(60));
+        let percentiles: Vec<_> = latencies.percentiles().collect();
+        assert_eq!(percentiles.len(), 1);
+        assert_eq!(percentiles[0].0, "B");
+        assert_eq!(percentiles[0].1.requests, 1);
+
+        // the ended window is kept for writing it once
+        let window = latencies.take_window().unwrap();
+        assert_eq!(window.start, at(0));
+        assert_eq!(window.percentiles.len(), 1);
+        assert_eq!(window.percentiles[0].0, "A");
+        assert_eq!(window.percentiles[0].1.p50, Some(3_000));
+        assert_eq!(latencies.take_window(), None);
+    }
+
+    #[test]
+    fn writes_window_points() {
+        let mut latencies = Latencies::new();
+        latencies.record("WW Großenkneten", ms(80), at(0));
+        latencies.record("WW Marienhafe", ms(45_000), at(0));
+        latencies.record("WW Großenkneten", ms(80), at(60));
+
+        let lines: Vec<_> = (latencies.take_window().unwrap().data_points().unwrap())
+            .iter()
+            .map(|point| {
+                let mut line = Vec::new();
+                influxdb2::models::WriteDataPoint::write_data_point_to(point, &mut line).unwrap();
+                String::from_utf8(line).unwrap()
+            })
+            .collect();
+        let time = at(0).timestamp();
+        assert_eq!(
+            lines,
+            [
+                format!(
+                    "collector_stats,location=WW\\ Großenkneten,window=1h latency_p50_ms=100i,\
+                     latency_p95_ms=100i,latency_p99_ms=100i,requests=1i {time}\n"
+                ),
+                format!("collector_stats,location=WW\\ Marienhafe,window=1h requests=1i {time}\n"),
+            ]
+        );
+    }
+
+    #[test]
+    fn bounds_locations() {
+        let mut latencies = Latencies::new();
+        for i in 0..MAX_LOCATIONS + 10 {
+            latencies.record(&format!("location {i}"), ms(50), at(0));
+        }
+        // known locations keep their own histogram
+        latencies.record("location 0", ms(50), at(1));
+
+        assert_eq!(latencies.locations.len(), MAX_LOCATIONS + 1);
+        assert_eq!(latencies.locations[OVERFLOW].total, 10);
+        assert_eq!(latencies.locations["location 0"].total, 2);
+    }
+}
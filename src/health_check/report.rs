@@ -0,0 +1,242 @@
+use serde::Serialize;
+use std::fmt::Write;
+
+/// How the result of the health check is printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    /// The ages of the signals and, if verbose, the recent errors.
+    #[default]
+    Plain,
+
+    /// One JSON object per checked mode.
+    Json,
+
+    /// Gauges in the Prometheus exposition format, for the textfile collector.
+    Prometheus,
+}
+
+/// Result of checking one health mode, printed by the machine-readable formats.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Report {
+    /// The health mode checked, `socket` or `file`.
+    pub mode: &'static str,
+
+    /// `healthy`, `unhealthy` or `error` if the state of the collector could not be read.
+    pub status: &'static str,
+
+    pub last_tick_secs_ago: Option<u64>,
+    pub last_write_secs_ago: Option<u64>,
+    pub threshold_secs: u64,
+
+    /// Locations whose last collection failed.
+    pub locations_stale: Vec<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Report {
+    pub fn healthy(&self) -> bool {
+        self.status == "healthy"
+    }
+}
+
+/// One JSON object per line and report.
+pub fn json(reports: &[Report]) -> String {
+    reports
+        .iter()
+        .map(|report| serde_json::to_string(report).expect("reports serialize") + "\n")
+        .collect()
+}
+
+/// Gauges of every report labeled with their mode, each metric described once.
+pub fn prometheus(reports: &[Report]) -> String {
+    let mut text = String::new();
+    let mut metric = |name: &str, help: &str, samples: Vec<(String, u64)>| {
+        if samples.is_empty() {
+            return;
+        }
+        let _ = writeln!(text, "# HELP swat_collector_{name} {help}");
+        let _ = writeln!(text, "# TYPE swat_collector_{name} gauge");
+        for (labels, value) in samples {
+            let _ = writeln!(text, "swat_collector_{name}{{{labels}}} {value}");
+        }
+    };
+    let mode = |report: &Report| format!("mode=\"{}\"", report.mode);
+    let gauge = |value: fn(&Report) -> Option<u64>| {
+        reports
+            .iter()
+            .filter_map(|report| Some((mode(report), value(report)?)))
+            .collect()
+    };
+
+    metric(
+        "healthy",
+        "Whether the collector is healthy.",
+        gauge(|report| Some(report.healthy() as u64)),
+    );
+    metric(
+        "last_tick_seconds",
+        "Seconds since the last run of the collection loop.",
+        gauge(|report| report.last_tick_secs_ago),
+    );
+    metric(
+        "last_write_seconds",
+        "Seconds since the last successful contact with InfluxDB.",
+        gauge(|report| report.last_write_secs_ago),
+    );
+    metric(
+        "threshold_seconds",
+        "Longest time without signals that is still healthy.",
+        gauge(|report| (report.status != "error").then_some(report.threshold_secs)),
+    );
+    metric(
+        "location_stale",
+        "Locations whose last collection failed.",
+        reports
+            .iter()
+            .flat_map(|report| {
+                report.locations_stale.iter().map(|location| {
+                    let location = escape_label(location);
+                    (format!("{},location=\"{location}\"", mode(report)), 1)
+                })
+            })
+            .collect(),
+    );
+    text
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    fn reports() -> Vec<Report> {
+        vec![
+            Report {
+                mode: "socket",
+                status: "unhealthy",
+                last_tick_secs_ago: Some(12),
+                last_write_secs_ago: Some(240),
+                threshold_secs: 180,
+                locations_stale: vec!["WW Großenkneten".to_string(), "Brunnen \"3\"".to_string()],
+                error: None,
+            },
+            Report {
+                mode: "file",
+                status: "error",
+                last_tick_secs_ago: None,
+                last_write_secs_ago: None,
+                threshold_secs: 180,
+                locations_stale: Vec::new(),
+                error: Some("could not read health file".to_string()),
+            },
+        ]
+    }
+
+    #[test]
+    fn json_lines() {
+        let text = json(&reports());
+        let lines: Vec<serde_json::Value> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            lines[0],
+            serde_json::json!({
+                "mode": "socket",
+                "status": "unhealthy",
+                "last_tick_secs_ago": 12,
+                "last_write_secs_ago": 240,
+                "threshold_secs": 180,
+                "locations_stale": ["WW Großenkneten", "Brunnen \"3\""],
+            })
+        );
+        assert_eq!(lines[1]["status"], "error");
+        assert_eq!(lines[1]["last_write_secs_ago"], serde_json::Value::Null);
+        assert_eq!(lines[1]["error"], "could not read health file");
+    }
+
+    /// Checks the text against the exposition format, returns the samples without labels.
+    fn parse_exposition(text: &str) -> Vec<(String, f64)> {
+        let is_name = |name: &str| {
+            !name.is_empty()
+                && !name.starts_with(|c: char| c.is_ascii_digit())
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        };
+        let mut described = BTreeSet::new();
+        let mut samples = Vec::new();
+        for line in text.lines() {
+            if let Some(comment) = line.strip_prefix("# ") {
+                let mut parts = comment.splitn(3, ' ');
+                let (kind, name) = (parts.next().unwrap(), parts.next().unwrap());
+                assert!(is_name(name), "{line}");
+                match kind {
+                    "HELP" => assert!(described.insert(name.to_string()), "described twice"),
+                    "TYPE" => assert_eq!(parts.next(), Some("gauge"), "{line}"),
+                    _ => panic!("unexpected comment {line}"),
+                }
+                continue;
+            }
+
+            let (series, value) = line.rsplit_once(' ').unwrap();
+            let (name, labels) = series.split_once('{').unwrap();
+            assert!(is_name(name) && described.contains(name), "{line}");
+            let labels = labels.strip_suffix('}').unwrap();
+            let mut rest = labels;
+            while !rest.is_empty() {
+                let (label, value) = rest.split_once("=\"").unwrap();
+                assert!(is_name(label), "{line}");
+                // the value ends at the first unescaped quote
+                let mut end = None;
+                let mut escaped = false;
+                for (i, c) in value.char_indices() {
+                    match c {
+                        _ if escaped => escaped = false,
+                        '\\' => escaped = true,
+                        '"' => {
+                            end = Some(i);
+                            break;
+                        }
+                        _ => {}
+                    }
+                }
+                let end = end.unwrap_or_else(|| panic!("unterminated label in {line}"));
+                rest = value[end + 1..]
+                    .strip_prefix(',')
+                    .unwrap_or(&value[end + 1..]);
+            }
+            samples.push((name.to_string(), value.parse().unwrap()));
+        }
+        samples
+    }
+
+    #[test]
+    fn prometheus_exposition() {
+        let text = prometheus(&reports());
+        let samples = parse_exposition(&text);
+        assert_eq!(
+            samples,
+            [
+                ("swat_collector_healthy", 0.0),
+                ("swat_collector_healthy", 0.0),
+                ("swat_collector_last_tick_seconds", 12.0),
+                ("swat_collector_last_write_seconds", 240.0),
+                ("swat_collector_threshold_seconds", 180.0),
+                ("swat_collector_location_stale", 1.0),
+                ("swat_collector_location_stale", 1.0),
+            ]
+            .map(|(name, value)| (name.to_string(), value))
+        );
+        assert!(text.contains(
+            "swat_collector_location_stale{mode=\"socket\",location=\"Brunnen \\\"3\\\"\"} 1\n"
+        ));
+    }
+}
@@ -0,0 +1,186 @@
+use super::Signals;
+use chrono::{DateTime, Utc};
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::SystemTime;
+
+/// Most transitions kept, the oldest is dropped first.
+const CAPACITY: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transition {
+    pub at: SystemTime,
+    pub healthy: bool,
+    pub reason: String,
+}
+
+impl fmt::Display for Transition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let at = DateTime::<Utc>::from(self.at).format("%Y-%m-%d %H:%M:%S");
+        let state = match self.healthy {
+            true => "healthy",
+            false => "unhealthy",
+        };
+        write!(f, "{at} {state}: {}", self.reason)
+    }
+}
+
+/// State machine recording the changes between healthy and unhealthy.
+///
+/// The collector starts unhealthy, so the first transition is the one to healthy.
+#[derive(Debug)]
+pub struct Transitions {
+    healthy: bool,
+    entries: VecDeque<Transition>,
+}
+
+impl Transitions {
+    pub const fn new() -> Transitions {
+        Transitions {
+            healthy: false,
+            entries: VecDeque::new(),
+        }
+    }
+
+    /// Evaluates the `signals` seen at `now`, returns the transition if the state changed.
+    pub fn evaluate(&mut self, signals: Signals, now: SystemTime) -> Option<Transition> {
+        let reason = signals.stale_reason();
+        let healthy = reason.is_none();
+        if healthy == self.healthy {
+            return None;
+        }
+
+        self.healthy = healthy;
+        let transition = Transition {
+            at: now,
+            healthy,
+            reason: reason
+                .unwrap_or_else(|| "collection ticks and InfluxDB contact are recent".to_string()),
+        };
+        if self.entries.len() >= CAPACITY {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(transition.clone());
+        Some(transition)
+    }
+
+    /// One line per transition, the oldest first.
+    pub fn text(&self) -> String {
+        self.entries.iter().map(|t| format!("{t}\n")).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health_check::HEALTHY_UPDATE_TIME;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    /// Signals of a tick and a write the seconds ago.
+    fn signals(tick: u64, sink: u64) -> Signals {
+        Signals {
+            tick: Some(Duration::from_secs(tick)),
+            sink: Some(Duration::from_secs(sink)),
+            backoff: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn records_transitions() {
+        let start = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut transitions = Transitions::new();
+
+        // nothing happened yet, the collector starts unhealthy
+        let never = Signals::NONE;
+        assert_eq!(transitions.evaluate(never, start), None);
+
+        // the first tick with a write makes it healthy
+        let transition = transitions.evaluate(signals(0, 0), start).unwrap();
+        assert!(transition.healthy);
+        let half = HEALTHY_UPDATE_TIME / 2;
+        assert_eq!(
+            transitions.evaluate(signals(half.as_secs(), half.as_secs()), start + half),
+            None
+        );
+
+        // the sink fails while the loop keeps running
+        let later = start + HEALTHY_UPDATE_TIME * 2;
+        let sink_failing = signals(0, (HEALTHY_UPDATE_TIME * 2).as_secs());
+        let transition = transitions.evaluate(sink_failing, later).unwrap();
+        assert!(!transition.healthy);
+        assert_eq!(
+            transition.reason,
+            format!(
+                "no successful InfluxDB contact for {} seconds",
+                (HEALTHY_UPDATE_TIME * 2).as_secs()
+            )
+        );
+        assert_eq!(transitions.evaluate(sink_failing, later), None);
+
+        // and recovers
+        assert!(transitions.evaluate(signals(0, 0), later).unwrap().healthy);
+
+        let text = transitions.text();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[0],
+            "2023-11-14 22:13:20 healthy: collection ticks and InfluxDB contact are recent"
+        );
+        assert!(lines[1].contains(" unhealthy: no successful InfluxDB contact for "));
+    }
+
+    #[test]
+    fn backoff_extends_threshold() {
+        let start = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut transitions = Transitions::new();
+        let backing_off = |age: Duration| Signals {
+            tick: Some(age),
+            sink: Some(age),
+            backoff: HEALTHY_UPDATE_TIME * 4,
+        };
+        assert!(
+            transitions
+                .evaluate(backing_off(Duration::ZERO), start)
+                .unwrap()
+                .healthy
+        );
+        let later = HEALTHY_UPDATE_TIME * 3;
+        assert_eq!(
+            transitions.evaluate(backing_off(later), start + later),
+            None
+        );
+        let too_late = HEALTHY_UPDATE_TIME * 5;
+        assert!(
+            !transitions
+                .evaluate(backing_off(too_late), start + too_late)
+                .unwrap()
+                .healthy
+        );
+    }
+
+    #[test]
+    fn keeps_latest_transitions() {
+        let start = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut transitions = Transitions::new();
+        for i in 0..CAPACITY as u64 * 2 + 1 {
+            let now = start + HEALTHY_UPDATE_TIME * 2 * i as u32;
+            let signals = match i % 2 {
+                0 => signals(0, 0),
+                _ => Signals {
+                    tick: None,
+                    ..signals(0, 0)
+                },
+            };
+            assert!(transitions.evaluate(signals, now).is_some());
+        }
+
+        assert_eq!(transitions.entries.len(), CAPACITY);
+        let last = transitions.entries.back().unwrap();
+        assert!(last.healthy);
+        assert_eq!(
+            transitions.entries.front().unwrap().reason,
+            "no collection tick yet"
+        );
+    }
+}
@@ -0,0 +1,264 @@
+use crate::locations::{Forecast, Target};
+use crate::values;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write;
+
+/// Forecasts kept per location unless `HISTORY_DEPTH` is set.
+pub const DEFAULT_DEPTH: usize = 5;
+
+/// Forecasts with more horizons than this are kept as their count, minimum and maximum only.
+pub const SUMMARIZE_ABOVE: usize = 48;
+
+/// The forecasted values of a kept forecast.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Horizons {
+    Full(BTreeMap<String, f64>),
+    Summary { count: usize, min: f64, max: f64 },
+}
+
+impl Horizons {
+    fn new(forecasts: &BTreeMap<String, f64>) -> Horizons {
+        if forecasts.len() <= SUMMARIZE_ABOVE {
+            return Horizons::Full(forecasts.clone());
+        }
+        let values = forecasts.values().copied();
+        Horizons::Summary {
+            count: forecasts.len(),
+            min: values.clone().fold(f64::INFINITY, f64::min),
+            max: values.fold(f64::NEG_INFINITY, f64::max),
+        }
+    }
+
+    #[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+    fn count(&self) -> usize {
+        match self {
+            Horizons::Full(forecasts) => forecasts.len(),
+            Horizons::Summary { count, .. } => *count,
+        }
+    }
+
+    #[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+    fn range(&self) -> Option<(f64, f64)> {
+        match self {
+            Horizons::Full(forecasts) if forecasts.is_empty() => None,
+            Horizons::Full(forecasts) => {
+                let values = forecasts.values().copied();
+                Some((
+                    values.clone().fold(f64::INFINITY, f64::min),
+                    values.fold(f64::NEG_INFINITY, f64::max),
+                ))
+            }
+            Horizons::Summary { min, max, .. } => Some((*min, *max)),
+        }
+    }
+}
+
+/// A fetched forecast as kept in the [`ForecastHistory`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HistoryEntry {
+    pub model: String,
+    pub tick_id: u64,
+    pub fetched: DateTime<Utc>,
+    pub api_version: String,
+    pub issued: String,
+    #[serde(serialize_with = "values::serialize_exact")]
+    pub current: f64,
+    pub horizons: Horizons,
+}
+
+/// The forecasts fetched last per location, for looking into what the collector saw without
+/// querying InfluxDB.
+///
+/// At most `depth` forecasts are kept per location, the oldest is dropped first, and large
+/// forecasts are kept [summarized](Horizons::Summary), so the history is bounded by the depth
+/// times the collected locations.
+#[derive(Debug)]
+pub struct ForecastHistory {
+    depth: usize,
+    locations: BTreeMap<String, LocationHistory>,
+}
+
+#[derive(Debug)]
+struct LocationHistory {
+    slug: String,
+    id: i64,
+    entries: VecDeque<HistoryEntry>,
+}
+
+impl Default for ForecastHistory {
+    fn default() -> ForecastHistory {
+        ForecastHistory::new(DEFAULT_DEPTH)
+    }
+}
+
+impl ForecastHistory {
+    pub fn new(depth: usize) -> ForecastHistory {
+        ForecastHistory {
+            depth,
+            locations: BTreeMap::new(),
+        }
+    }
+
+    /// Keeps the `forecast` of the `target` fetched at `fetched` in the `tick_id`.
+    pub fn record(
+        &mut self,
+        target: Target,
+        forecast: &Forecast,
+        tick_id: u64,
+        fetched: DateTime<Utc>,
+    ) {
+        if self.depth == 0 {
+            return;
+        }
+        let location = target.location;
+        let history = (self.locations)
+            .entry(location.name.to_string())
+            .or_insert_with(|| LocationHistory {
+                slug: location.slug(),
+                id: location.id,
+                entries: VecDeque::with_capacity(self.depth),
+            });
+        if history.entries.len() >= self.depth {
+            history.entries.pop_front();
+        }
+        history.entries.push_back(HistoryEntry {
+            model: target.model.name.clone(),
+            tick_id,
+            fetched,
+            api_version: forecast.api_version.to_string(),
+            issued: forecast.from.clone(),
+            current: forecast.current.1,
+            horizons: Horizons::new(&forecast.forecasts),
+        });
+    }
+
+    /// The kept forecasts of the location given by name, slug or id, the oldest first.
+    pub fn recent(&self, location: &str) -> Option<(&str, Vec<&HistoryEntry>)> {
+        self.locations
+            .iter()
+            .find(|(name, history)| {
+                *name == location || history.slug == location || history.id.to_string() == location
+            })
+            .map(|(name, history)| (name.as_str(), history.entries.iter().collect()))
+    }
+
+    /// Forgets the locations of none of the `targets`, like [`crate::gaps::GapTracker::retain`].
+    pub fn retain(&mut self, targets: &[String]) {
+        self.locations.retain(|name, _| {
+            let model = format!("{name} (");
+            (targets.iter()).any(|target| target == name || target.starts_with(&model))
+        });
+    }
+}
+
+/// The kept forecasts of the `location` as a table of their issue times and current values.
+#[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+pub fn table(location: &str, entries: &[&HistoryEntry]) -> String {
+    let mut table = format!("last {} forecasts of {location}\n", entries.len());
+    let _ = writeln!(
+        table,
+        "{:16}  {:16}  {:12}  {:>10}  horizons",
+        "fetched", "issued", "model", "current"
+    );
+    for entry in entries {
+        let range = match entry.horizons.range() {
+            Some((min, max)) => format!("{}, {min} to {max}", entry.horizons.count()),
+            None => "0".to_string(),
+        };
+        let _ = writeln!(
+            table,
+            "{:16}  {:16}  {:12}  {:>10}  {range}",
+            entry.fetched.format("%Y-%m-%d %H:%M").to_string(),
+            entry.issued,
+            entry.model,
+            entry.current,
+        );
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locations::{parse_forecast, Model, LOCATIONS};
+    use chrono::TimeZone;
+
+    fn forecast(issued: u32, horizons: usize) -> Forecast {
+        let forecasts: Vec<_> = (1..=horizons)
+            .map(|horizon| {
+                format!(
+                    "\"2024-03-07 {:02}:{:02}\": {horizon}.5",
+                    horizon / 60,
+                    horizon % 60
+                )
+            })
+            .collect();
+        parse_forecast(format!(
+            r#"{{"vorhersageZeit": "2024-03-07 08:{issued:02}", "lat": 52.9, "lon": 8.2,
+                "aktuell": {{"2024-03-07 08:{issued:02}": 41{issued}.0}},
+                "vorhersage": {{{}}}}}"#,
+            forecasts.join(", ")
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn keeps_recent_forecasts() {
+        let model = Model::default_model();
+        let location = &LOCATIONS.locations[0];
+        let target = Target {
+            location,
+            model: &model,
+        };
+        let fetched = Utc.with_ymd_and_hms(2024, 3, 7, 8, 10, 0).unwrap();
+        let mut history = ForecastHistory::new(3);
+        for tick in 1..=5 {
+            history.record(target, &forecast(tick as u32, 2), tick, fetched);
+        }
+        let (name, entries) = history.recent(&location.slug()).unwrap();
+        assert_eq!(name, location.name);
+        let ticks: Vec<_> = entries.iter().map(|entry| entry.tick_id).collect();
+        assert_eq!(ticks, [3, 4, 5]);
+        assert_eq!(entries[2].issued, "2024-03-07 08:05");
+        assert_eq!(entries[2].current, 415.0);
+        assert!(history.recent(&location.id.to_string()).is_some());
+        assert!(history.recent("WW Nirgendwo").is_none());
+
+        history.retain(&[format!("{} (mittelwert)", location.name)]);
+        assert!(history.recent(location.name).is_some());
+        history.retain(&[]);
+        assert!(history.recent(location.name).is_none());
+    }
+
+    #[test]
+    fn summarizes_large_forecasts() {
+        let model = Model::default_model();
+        let target = Target {
+            location: &LOCATIONS.locations[0],
+            model: &model,
+        };
+        let fetched = Utc.with_ymd_and_hms(2024, 3, 7, 8, 10, 0).unwrap();
+        let mut history = ForecastHistory::new(2);
+        history.record(target, &forecast(5, SUMMARIZE_ABOVE), 1, fetched);
+        history.record(target, &forecast(5, SUMMARIZE_ABOVE + 1), 2, fetched);
+        let (name, entries) = history.recent(target.location.name).unwrap();
+        assert!(
+            matches!(&entries[0].horizons, Horizons::Full(forecasts) if forecasts.len() == SUMMARIZE_ABOVE)
+        );
+        assert_eq!(
+            entries[1].horizons,
+            Horizons::Summary {
+                count: SUMMARIZE_ABOVE + 1,
+                min: 1.5,
+                max: SUMMARIZE_ABOVE as f64 + 1.5,
+            }
+        );
+        assert_eq!(
+            table(name, &entries).lines().nth(3),
+            Some("2024-03-07 08:10  2024-03-07 08:05  vorhersage           415  49, 1.5 to 49.5")
+        );
+    }
+}
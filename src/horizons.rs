@@ -0,0 +1,184 @@
+use crate::bounded_cache::{self, BoundedCache};
+use std::collections::{BTreeMap, VecDeque};
+
+/// Successful fetches per location and model the typical horizon count is the median of,
+/// unless `HORIZON_WINDOW` is set.
+pub const DEFAULT_WINDOW: usize = 20;
+
+/// Forecasts with fewer horizons than this fraction of the typical count are short unless
+/// `SHORT_FORECAST_FRACTION` is set.
+pub const DEFAULT_FRACTION: f64 = 0.5;
+
+/// Fetches needed before a forecast can be short.
+const MIN_FETCHES: usize = 3;
+
+#[derive(Debug, Default)]
+struct Baseline {
+    /// Horizon counts of the last fetches, the oldest first.
+    counts: VecDeque<usize>,
+    alerted: bool,
+}
+
+impl Baseline {
+    fn typical(&self) -> Option<usize> {
+        if self.counts.len() < MIN_FETCHES {
+            return None;
+        }
+        let mut counts: Vec<_> = self.counts.iter().copied().collect();
+        counts.sort_unstable();
+        Some(counts[counts.len() / 2])
+    }
+}
+
+/// Tracks per location and model how many horizons the swat api typically returns.
+///
+/// While degraded the api sometimes returns only a few horizons, which goes unnoticed as the
+/// request succeeds, so a warning is queued once a forecast is short and a recovery message once
+/// it is complete again. The baseline adapts as the short forecasts enter the rolling median.
+#[derive(Debug)]
+pub struct HorizonTracker {
+    window: usize,
+    fraction: f64,
+    baselines: BoundedCache<String, Baseline>,
+    messages: Vec<String>,
+}
+
+impl HorizonTracker {
+    pub fn new(window: usize, fraction: f64) -> HorizonTracker {
+        HorizonTracker {
+            window: window.max(1),
+            fraction,
+            baselines: BoundedCache::new(bounded_cache::DEFAULT_CAPACITY),
+            messages: Vec::new(),
+        }
+    }
+
+    /// Records that the forecast of `target`, the location and model, has `count` horizons,
+    /// returns whether it is short.
+    pub fn observe(&mut self, target: &str, count: usize) -> bool {
+        let baseline = self
+            .baselines
+            .get_or_insert_with(target.to_string(), Baseline::default);
+        let typical = baseline.typical();
+        let short = typical.is_some_and(|typical| (count as f64) < self.fraction * typical as f64);
+
+        match (short, baseline.alerted) {
+            (true, false) => self.messages.push(format!(
+                "forecast for {target} has only {count} horizons, typically {}",
+                typical.unwrap_or_default()
+            )),
+            (false, true) => self
+                .messages
+                .push(format!("forecast for {target} has {count} horizons again")),
+            _ => (),
+        }
+        baseline.alerted = short;
+
+        baseline.counts.push_back(count);
+        while baseline.counts.len() > self.window {
+            baseline.counts.pop_front();
+        }
+        short
+    }
+
+    /// Takes the warnings and recoveries queued since the last call.
+    pub fn take_messages(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.messages)
+    }
+
+    /// Forgets the locations and models not in `targets` and keeps at most `capacity`.
+    pub fn resize(&mut self, targets: &[String], capacity: usize) {
+        self.baselines.retain(|target| targets.contains(target));
+        self.baselines.resize(capacity);
+    }
+
+    /// The recent horizon counts per location and model, to persist them across restarts.
+    pub fn counts(&self) -> BTreeMap<String, Vec<usize>> {
+        self.baselines
+            .keys()
+            .cloned()
+            .zip(self.baselines.values())
+            .map(|(target, baseline)| (target, baseline.counts.iter().copied().collect()))
+            .collect()
+    }
+
+    /// Restores the `counts` persisted by an earlier run.
+    pub fn restore(&mut self, counts: BTreeMap<String, Vec<usize>>) {
+        for (target, counts) in counts {
+            let skip = counts.len().saturating_sub(self.window);
+            let baseline = Baseline {
+                counts: counts.into_iter().skip(skip).collect(),
+                alerted: false,
+            };
+            self.baselines.insert(target, baseline);
+        }
+    }
+}
+
+impl Default for HorizonTracker {
+    fn default() -> Self {
+        HorizonTracker::new(DEFAULT_WINDOW, DEFAULT_FRACTION)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn observe_all(tracker: &mut HorizonTracker, counts: &[usize]) -> Vec<bool> {
+        counts
+            .iter()
+            .map(|count| tracker.observe("WW Großenkneten", *count))
+            .collect()
+    }
+
+    #[test]
+    fn alerts_short_forecast_once() {
+        let mut tracker = HorizonTracker::default();
+
+        // no baseline yet, so nothing is short
+        assert_eq!(observe_all(&mut tracker, &[3, 36, 36]), [false; 3]);
+
+        assert_eq!(
+            observe_all(&mut tracker, &[35, 17, 3, 36]),
+            [false, true, true, false]
+        );
+        assert_eq!(
+            tracker.take_messages(),
+            [
+                "forecast for WW Großenkneten has only 17 horizons, typically 36",
+                "forecast for WW Großenkneten has 36 horizons again",
+            ]
+        );
+
+        // at least half of the typical count is not short
+        assert_eq!(observe_all(&mut tracker, &[18]), [false]);
+    }
+
+    #[test]
+    fn baseline_adapts() {
+        let mut tracker = HorizonTracker::new(5, 0.5);
+        observe_all(&mut tracker, &[36; 5]);
+
+        // the api switching to fewer horizons is short until they dominate the window
+        assert_eq!(
+            observe_all(&mut tracker, &[12; 4]),
+            [true, true, true, false]
+        );
+        assert_eq!(tracker.take_messages().len(), 2);
+
+        // counts beyond the window are forgotten
+        assert_eq!(tracker.counts()["WW Großenkneten"], [36, 12, 12, 12, 12]);
+    }
+
+    #[test]
+    fn restores_counts() {
+        let mut tracker = HorizonTracker::new(4, 0.5);
+        tracker.restore(BTreeMap::from([(
+            "WW Großenkneten".to_string(),
+            vec![1, 36, 36, 36, 36],
+        )]));
+        assert_eq!(tracker.counts()["WW Großenkneten"], [36, 36, 36, 36]);
+        assert!(tracker.observe("WW Großenkneten", 3));
+    }
+}
@@ -0,0 +1,182 @@
+use crate::history::ForecastHistory;
+use crate::live::LiveFeed;
+use parking_lot::RwLock;
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use warp::http::StatusCode;
+use warp::reply::Reply;
+use warp::Filter;
+
+#[derive(Debug, Deserialize)]
+struct WsQuery {
+    location: Option<String>,
+}
+
+/// The routes of the HTTP server, `GET /ws` streams the fetched forecasts of the `feed` and
+/// `GET /forecast/<location>/history` lists those kept in the `history`, by location slug or id.
+pub fn routes(
+    feed: Arc<LiveFeed>,
+    history: Arc<RwLock<ForecastHistory>>,
+) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone {
+    let ws = warp::get()
+        .and(warp::path("ws"))
+        .and(warp::path::end())
+        .and(warp::query::<WsQuery>())
+        .and(warp::ws())
+        .and(warp::any().map(move || feed.clone()))
+        .and_then(ws);
+    let history = warp::get()
+        .and(warp::path!("forecast" / String / "history"))
+        .map(move |location: String| forecast_history(&history.read(), &location));
+    ws.or(history)
+}
+
+fn forecast_history(history: &ForecastHistory, location: &str) -> warp::reply::Response {
+    match history.recent(location) {
+        Some((location, forecasts)) => warp::reply::json(&serde_json::json!({
+            "location": location,
+            "forecasts": forecasts,
+        }))
+        .into_response(),
+        None => warp::reply::with_status("no forecasts of the location", StatusCode::NOT_FOUND)
+            .into_response(),
+    }
+}
+
+async fn ws(
+    query: WsQuery,
+    upgrade: warp::ws::Ws,
+    feed: Arc<LiveFeed>,
+) -> Result<warp::reply::Response, Infallible> {
+    let Some(client) = feed.connect() else {
+        let reply = warp::reply::with_status("too many clients", StatusCode::SERVICE_UNAVAILABLE);
+        return Ok(reply.into_response());
+    };
+    let reply = upgrade.on_upgrade(move |socket| client.stream(socket, query.location));
+    Ok(reply.into_response())
+}
+
+/// Binds `addr` to serve the [routes] on, the returned future runs the server.
+pub fn bind(
+    addr: SocketAddr,
+    feed: Arc<LiveFeed>,
+    history: Arc<RwLock<ForecastHistory>>,
+) -> Result<impl Future<Output = ()>, warp::Error> {
+    let (_, server) = warp::serve(routes(feed, history)).try_bind_ephemeral(addr)?;
+    Ok(server)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::live::LiveEvent;
+    use std::time::Duration;
+
+    fn event(location: &str, slug: &str) -> LiveEvent {
+        LiveEvent {
+            location: location.to_string(),
+            slug: slug.to_string(),
+            model: "vorhersage".to_string(),
+            issued: "2024-03-07 08:05".to_string(),
+            current: 412.0,
+            horizons: 48,
+        }
+    }
+
+    async fn text(client: &mut warp::test::WsClient) -> serde_json::Value {
+        let message = tokio::time::timeout(Duration::from_secs(5), client.recv())
+            .await
+            .expect("message in time")
+            .unwrap();
+        serde_json::from_str(message.to_str().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn pushes_events_and_drops_lagging_clients() {
+        let feed = Arc::new(LiveFeed::new(2, 2));
+        let routes = routes(feed.clone(), Arc::default());
+        let all = warp::test::ws().path("/ws").handshake(routes.clone());
+        let mut all = all.await.unwrap();
+        let filtered = warp::test::ws().path("/ws?location=ww-marienhafe");
+        let mut filtered = filtered.handshake(routes.clone()).await.unwrap();
+        assert_eq!(feed.clients(), 2);
+        let rejected = warp::test::ws().path("/ws").handshake(routes.clone()).await;
+        assert!(rejected.is_err());
+
+        // one tick
+        feed.publish(event("WW Großenkneten", "ww-grossenkneten"));
+        feed.publish(event("WW Marienhafe", "ww-marienhafe"));
+        assert_eq!(text(&mut all).await["slug"], "ww-grossenkneten");
+        assert_eq!(text(&mut all).await["slug"], "ww-marienhafe");
+        assert_eq!(
+            text(&mut filtered).await,
+            serde_json::json!({
+                "location": "WW Marienhafe",
+                "slug": "ww-marienhafe",
+                "model": "vorhersage",
+                "issued": "2024-03-07 08:05",
+                "current": 412,
+                "horizons": 48,
+            })
+        );
+
+        // publishing does not wait for the clients, those falling behind are dropped
+        for _ in 0..3 {
+            feed.publish(event("WW Marienhafe", "ww-marienhafe"));
+        }
+        for client in [&mut all, &mut filtered] {
+            let closed = tokio::time::timeout(Duration::from_secs(5), client.recv_closed());
+            assert!(closed.await.expect("closed in time").is_ok());
+        }
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while feed.clients() > 0 {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("slots freed in time");
+    }
+
+    #[tokio::test]
+    async fn serves_forecast_history() {
+        let history = Arc::new(RwLock::new(ForecastHistory::new(5)));
+        let routes = routes(Arc::new(LiveFeed::new(2, 2)), history.clone());
+        let location = &crate::locations::LOCATIONS.locations[0];
+        let model = crate::locations::Model::default_model();
+        let target = crate::locations::Target {
+            location,
+            model: &model,
+        };
+        let forecast = crate::locations::parse_forecast(
+            r#"{"vorhersageZeit": "2024-03-07 08:05", "lat": 52.9, "lon": 8.2,
+                "aktuell": {"2024-03-07 08:05": 412.0},
+                "vorhersage": {"2024-03-07 08:10": 410.5}}"#
+                .to_string(),
+        )
+        .unwrap();
+        let fetched = chrono::DateTime::parse_from_rfc3339("2024-03-07T08:10:00Z").unwrap();
+        (history.write()).record(target, &forecast, 7, fetched.into());
+
+        let path = format!("/forecast/{}/history", location.slug());
+        let response = warp::test::request().path(&path).reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["location"], location.name);
+        assert_eq!(body["forecasts"][0]["tick_id"], 7);
+        assert_eq!(body["forecasts"][0]["issued"], "2024-03-07 08:05");
+        assert_eq!(
+            body["forecasts"][0]["horizons"],
+            serde_json::json!({"full": {"2024-03-07 08:10": 410.5}})
+        );
+
+        let response = (warp::test::request())
+            .path("/forecast/ww-nirgendwo/history")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
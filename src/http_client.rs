@@ -0,0 +1,199 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use thiserror::Error;
+
+/// Idle connections are kept this much longer than the collection interval unless
+/// `HTTP_POOL_IDLE_SECONDS` is set.
+const IDLE_MARGIN: Duration = Duration::from_secs(60);
+
+/// Idle connections kept per host unless `HTTP_POOL_MAX_IDLE_PER_HOST` is set.
+pub const DEFAULT_MAX_IDLE_PER_HOST: usize = 8;
+
+/// Interval of the TCP keepalive probes unless `HTTP_TCP_KEEPALIVE_SECONDS` is set.
+pub const DEFAULT_TCP_KEEPALIVE: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("expected {0:?} to be valid, {1}")]
+pub struct HttpClientConfigError(&'static str, String);
+
+/// Settings of the client shared by every request to the swat api, read from `HTTP_*`.
+///
+/// The pool keeps its connections longer than the collection interval, otherwise every tick
+/// would open fresh TLS connections. HTTP/2 is negotiated with servers supporting it unless
+/// `HTTP_H2=false`, as the api has been flaky over it before.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HttpClientConfig {
+    pub pool_idle_timeout: Duration,
+    pub pool_max_idle_per_host: usize,
+    pub tcp_keepalive: Duration,
+    pub http2: bool,
+}
+
+impl HttpClientConfig {
+    /// Reads the configuration from `lookup`, keeping idle connections longer than the
+    /// longest collection `interval`.
+    pub fn from_lookup(
+        lookup: impl Fn(&str) -> Option<String>,
+        interval: Duration,
+    ) -> Result<HttpClientConfig, HttpClientConfigError> {
+        let parse = |key: &'static str| -> Result<Option<u64>, HttpClientConfigError> {
+            lookup(key)
+                .map(|value| u64::from_str(value.trim()))
+                .transpose()
+                .map_err(|err| HttpClientConfigError(key, err.to_string()))
+        };
+        let http2 = lookup("HTTP_H2")
+            .map(|value| bool::from_str(value.trim()))
+            .transpose()
+            .map_err(|err| HttpClientConfigError("HTTP_H2", err.to_string()))?;
+        Ok(HttpClientConfig {
+            pool_idle_timeout: parse("HTTP_POOL_IDLE_SECONDS")?
+                .map_or(interval + IDLE_MARGIN, Duration::from_secs),
+            pool_max_idle_per_host: parse("HTTP_POOL_MAX_IDLE_PER_HOST")?
+                .map_or(DEFAULT_MAX_IDLE_PER_HOST, |max| max as usize),
+            tcp_keepalive: parse("HTTP_TCP_KEEPALIVE_SECONDS")?
+                .map_or(DEFAULT_TCP_KEEPALIVE, Duration::from_secs),
+            http2: http2.unwrap_or(true),
+        })
+    }
+
+    /// The client with these settings.
+    pub fn builder(&self) -> reqwest::ClientBuilder {
+        let builder = reqwest::Client::builder()
+            .pool_idle_timeout(self.pool_idle_timeout)
+            .pool_max_idle_per_host(self.pool_max_idle_per_host)
+            .tcp_keepalive(self.tcp_keepalive);
+        match self.http2 {
+            true => builder,
+            false => builder.http1_only(),
+        }
+    }
+}
+
+impl fmt::Display for HttpClientConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "pool idle timeout {}s, at most {} idle connections per host, tcp keepalive {}s, {}",
+            self.pool_idle_timeout.as_secs(),
+            self.pool_max_idle_per_host,
+            self.tcp_keepalive.as_secs(),
+            match self.http2 {
+                true => "http/2 where supported",
+                false => "http/1.1 only",
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::net::TcpListener;
+    use tokio_rustls::rustls;
+    use warp::Filter;
+
+    /// Serves `hello` over TLS for `localhost`, counting the accepted connections.
+    async fn tls_server() -> (u16, reqwest::Certificate, Arc<AtomicUsize>) {
+        let cert = include_bytes!("../tests/tls/localhost.crt");
+        let key = include_bytes!("../tests/tls/localhost.key");
+        let certs = rustls_pemfile::certs(&mut &cert[..]).unwrap();
+        let key = rustls_pemfile::pkcs8_private_keys(&mut &key[..]).unwrap();
+        let mut config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(
+                certs.into_iter().map(rustls::Certificate).collect(),
+                rustls::PrivateKey(key[0].clone()),
+            )
+            .unwrap();
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let connections = futures::stream::unfold(listener, |listener| async {
+            let (stream, _) = listener.accept().await.ok()?;
+            Some((stream, listener))
+        })
+        .then({
+            let accepted = accepted.clone();
+            move |stream| {
+                accepted.fetch_add(1, Ordering::Relaxed);
+                acceptor.accept(stream)
+            }
+        });
+        let route = warp::path("hello").map(|| "hello");
+        tokio::spawn(warp::serve(route).serve_incoming(connections));
+        let cert = reqwest::Certificate::from_pem(cert).unwrap();
+        (port, cert, accepted)
+    }
+
+    #[test]
+    fn config() {
+        let interval = Duration::from_secs(120);
+        let config = HttpClientConfig::from_lookup(|_| None, interval).unwrap();
+        assert_eq!(config.pool_idle_timeout, Duration::from_secs(180));
+        assert!(config.http2);
+        assert_eq!(
+            config.to_string(),
+            "pool idle timeout 180s, at most 8 idle connections per host, tcp keepalive 60s, \
+             http/2 where supported"
+        );
+
+        let config = HttpClientConfig::from_lookup(
+            |key| match key {
+                "HTTP_POOL_IDLE_SECONDS" => Some("600".to_string()),
+                "HTTP_H2" => Some("false".to_string()),
+                _ => None,
+            },
+            interval,
+        )
+        .unwrap();
+        assert_eq!(config.pool_idle_timeout, Duration::from_secs(600));
+        assert!(!config.http2);
+
+        let invalid = HttpClientConfig::from_lookup(
+            |key| (key == "HTTP_POOL_MAX_IDLE_PER_HOST").then(|| "many".to_string()),
+            interval,
+        );
+        assert_eq!(
+            invalid.unwrap_err().to_string(),
+            "expected \"HTTP_POOL_MAX_IDLE_PER_HOST\" to be valid, invalid digit found in string"
+        );
+    }
+
+    #[tokio::test]
+    async fn reuses_connections() {
+        let (port, cert, accepted) = tls_server().await;
+        let url = format!("https://localhost:{port}/hello");
+        for (http2, version) in [
+            (true, reqwest::Version::HTTP_2),
+            (false, reqwest::Version::HTTP_11),
+        ] {
+            let config = HttpClientConfig {
+                http2,
+                ..HttpClientConfig::from_lookup(|_| None, Duration::from_secs(120)).unwrap()
+            };
+            let client = config
+                .builder()
+                .add_root_certificate(cert.clone())
+                .resolve("localhost", ([127, 0, 0, 1], port).into())
+                .build()
+                .unwrap();
+            let before = accepted.load(Ordering::Relaxed);
+            for _ in 0..2 {
+                let response = client.get(&url).send().await.unwrap();
+                assert_eq!(response.version(), version);
+                assert_eq!(response.text().await.unwrap(), "hello");
+            }
+            // both requests over the same connection
+            assert_eq!(accepted.load(Ordering::Relaxed), before + 1, "{version:?}");
+        }
+    }
+}
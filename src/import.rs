@@ -0,0 +1,568 @@
+use crate::locations::{ApiVersion, Forecast, Location, Model, Target};
+use crate::points::HandleLocationError;
+use crate::sink::{Sink, WriteError};
+use crate::{fields, fixture, geo, logging};
+use influxdb2::models::DataPoint;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use thiserror::Error;
+
+/// Rows written per batch unless `--batch-size` is given.
+pub const DEFAULT_BATCH_SIZE: usize = 500;
+
+/// Where the last committed row is kept unless `--import-state` is given.
+pub const DEFAULT_STATE_PATH: &str = ".import-state";
+
+#[derive(Debug, Error)]
+pub enum ImportError {
+    #[error("could not open the csv file, {0}")]
+    Open(io::Error),
+
+    #[error("could not read the csv file, {0}")]
+    Read(#[from] csv::Error),
+
+    #[error("expected column mapping in the form of `field=column` with field one of \"location\", \"issued\", \"current\" or \"forecasts\", got {0:?}")]
+    Mapping(String),
+
+    #[error("column {0:?} is missing in the header")]
+    MissingColumn(String),
+
+    #[error("could not access the import state, {0}")]
+    State(#[from] io::Error),
+
+    #[error("invalid import state, {0}")]
+    ParseState(#[from] serde_json::Error),
+
+    #[error("the import state belongs to {0:?}, remove it to import another file")]
+    OtherFile(PathBuf),
+
+    #[error("writing rows up to {row} into bucket {bucket:?} failed, {error}")]
+    Write {
+        row: u64,
+        bucket: String,
+        error: WriteError,
+    },
+}
+
+#[derive(Debug, Error)]
+enum RowError {
+    #[error("{0}")]
+    Read(#[from] csv::Error),
+
+    #[error("unknown location {0:?}")]
+    UnknownLocation(String),
+
+    #[error("invalid current value, {0}")]
+    Current(serde_json::Error),
+
+    #[error("current value is empty")]
+    EmptyCurrent,
+
+    #[error("invalid forecasts, {0}")]
+    Forecasts(serde_json::Error),
+
+    #[error("{0}")]
+    Point(#[from] HandleLocationError),
+}
+
+/// The CSV columns the fields of a forecast are read from, named like the fields unless mapped
+/// otherwise.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnMapping {
+    /// Name, slug or id of the location.
+    pub location: String,
+
+    /// Issue time of the forecast like `2024-03-07 08:05`, the `vorhersageZeit`.
+    pub issued: String,
+
+    /// JSON object with the single current value, the `aktuell`.
+    pub current: String,
+
+    /// JSON object with the forecast values, the `vorhersage`.
+    pub forecasts: String,
+}
+
+/// Indices of the mapped columns in a header.
+struct Columns {
+    location: usize,
+    issued: usize,
+    current: usize,
+    forecasts: usize,
+}
+
+impl ColumnMapping {
+    /// Applies every `field=column` of `columns` to the default mapping.
+    pub fn from_args(columns: &[String]) -> Result<ColumnMapping, ImportError> {
+        let mut mapping = ColumnMapping::default();
+        for arg in columns {
+            let (field, column) = arg
+                .split_once('=')
+                .ok_or_else(|| ImportError::Mapping(arg.clone()))?;
+            let target = match field.trim() {
+                "location" => &mut mapping.location,
+                "issued" => &mut mapping.issued,
+                "current" => &mut mapping.current,
+                "forecasts" => &mut mapping.forecasts,
+                _ => return Err(ImportError::Mapping(arg.clone())),
+            };
+            *target = column.trim().to_string();
+        }
+        Ok(mapping)
+    }
+
+    fn resolve(&self, headers: &csv::StringRecord) -> Result<Columns, ImportError> {
+        let index = |column: &String| {
+            headers
+                .iter()
+                .position(|header| header.trim() == column)
+                .ok_or_else(|| ImportError::MissingColumn(column.clone()))
+        };
+        Ok(Columns {
+            location: index(&self.location)?,
+            issued: index(&self.issued)?,
+            current: index(&self.current)?,
+            forecasts: index(&self.forecasts)?,
+        })
+    }
+}
+
+impl Default for ColumnMapping {
+    fn default() -> Self {
+        ColumnMapping {
+            location: "location".to_string(),
+            issued: "issued".to_string(),
+            current: "current".to_string(),
+            forecasts: "forecasts".to_string(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ImportOptions {
+    pub file: PathBuf,
+    pub mapping: ColumnMapping,
+    pub batch_size: usize,
+    pub state_path: PathBuf,
+}
+
+/// The last row of `file` that was written, so an interrupted import can continue after it.
+#[derive(Debug, Serialize, Deserialize)]
+struct ImportState {
+    file: PathBuf,
+    row: u64,
+}
+
+impl ImportState {
+    /// The row to continue after, 0 if there is nothing to resume.
+    fn load(path: &Path, file: &Path) -> Result<u64, ImportError> {
+        let state = match fs::read_to_string(path) {
+            Ok(state) => state,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(err) => return Err(err.into()),
+        };
+        let state: ImportState = serde_json::from_str(&state)?;
+        match state.file == file {
+            true => Ok(state.row),
+            false => Err(ImportError::OtherFile(state.file)),
+        }
+    }
+
+    fn save(path: &Path, file: &Path, row: u64) -> Result<(), ImportError> {
+        let state = ImportState {
+            file: file.to_path_buf(),
+            row,
+        };
+        fs::write(path, serde_json::to_string(&state)? + "\n")?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    /// The rows before this one were imported by an earlier run.
+    pub resumed_after: u64,
+    pub imported: u64,
+    pub duplicates: u64,
+    pub malformed: u64,
+}
+
+pub async fn run(
+    file: PathBuf,
+    columns: &[String],
+    batch_size: usize,
+    state_path: PathBuf,
+    sink: &Sink,
+) -> ExitCode {
+    let result = match ColumnMapping::from_args(columns) {
+        Ok(mapping) => {
+            let options = ImportOptions {
+                file,
+                mapping,
+                batch_size,
+                state_path,
+            };
+            import(&options, sink)
+                .await
+                .map(|summary| (options, summary))
+        }
+        Err(err) => Err(err),
+    };
+    match result {
+        Ok((options, summary)) => {
+            println!(
+                "imported {} rows of {}, skipped {} duplicate and {} malformed rows",
+                summary.imported,
+                options.file.display(),
+                summary.duplicates,
+                summary.malformed
+            );
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Streams the forecasts of the CSV file into the `sink` in batches, with the same points the
+/// collection writes.
+///
+/// The last row of every written batch is kept in the import state, an interrupted import
+/// continues after it. Rows repeating the location and issue time of an earlier row are
+/// skipped, as are rows that cannot be read.
+pub async fn import(options: &ImportOptions, sink: &Sink) -> Result<ImportSummary, ImportError> {
+    let mut reader =
+        csv::Reader::from_reader(File::open(&options.file).map_err(ImportError::Open)?);
+    let columns = options.mapping.resolve(reader.headers()?)?;
+    let resumed_after = ImportState::load(&options.state_path, &options.file)?;
+    let model = Model::default_model();
+
+    let mut summary = ImportSummary {
+        resumed_after,
+        ..ImportSummary::default()
+    };
+    let mut seen = HashSet::new();
+    let mut batch = Vec::with_capacity(options.batch_size);
+    let mut row = 0;
+    for record in reader.records() {
+        row += 1;
+        let resumed = row <= resumed_after;
+        let (location, timestamp, point) = match parse_row(record, &columns, &model) {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                if !resumed {
+                    summary.malformed += 1;
+                    let datetime = logging::datetime();
+                    log_eprintln!("WARN  [{datetime}]: skipped malformed row {row}, {err}");
+                }
+                continue;
+            }
+        };
+
+        // rows before the resume point still count for the duplicates
+        if !seen.insert((location.id, timestamp)) {
+            if !resumed {
+                summary.duplicates += 1;
+            }
+            continue;
+        }
+        if resumed {
+            continue;
+        }
+
+        batch.push((location, point));
+        if batch.len() >= options.batch_size.max(1) {
+            write_batch(sink, std::mem::take(&mut batch), row, &mut summary).await?;
+            ImportState::save(&options.state_path, &options.file, row)?;
+        }
+    }
+
+    if !batch.is_empty() {
+        write_batch(sink, batch, row, &mut summary).await?;
+    }
+    match fs::remove_file(&options.state_path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(summary),
+    }
+}
+
+fn parse_row(
+    record: Result<csv::StringRecord, csv::Error>,
+    columns: &Columns,
+    model: &Model,
+) -> Result<(&'static Location, i64, DataPoint), RowError> {
+    let record = record?;
+    let field = |index: usize| record.get(index).unwrap_or_default().trim();
+
+    let location = fixture::find_location(field(columns.location))
+        .ok_or_else(|| RowError::UnknownLocation(field(columns.location).to_string()))?;
+    let current: BTreeMap<String, f64> =
+        serde_json::from_str(field(columns.current)).map_err(RowError::Current)?;
+    let forecasts = serde_json::from_str(field(columns.forecasts)).map_err(RowError::Forecasts)?;
+    let (lat, lon) = geo::coordinates(location)
+        .map(|coordinates| (coordinates.lat(), coordinates.lon()))
+        .unwrap_or_default();
+    let mut forecast = Forecast {
+        from: field(columns.issued).to_string(),
+        lat,
+        lon,
+        current: current.into_iter().next().ok_or(RowError::EmptyCurrent)?,
+        forecasts,
+        rejected: Vec::new(),
+        api_version: ApiVersion::V1,
+        extras: BTreeMap::new(),
+        raw: None,
+    };
+
+    forecast
+        .normalize_timestamps()
+        .map_err(HandleLocationError::from)?;
+    let timestamp =
+        crate::points::issue_timestamp(&forecast.from).map_err(HandleLocationError::from)?;
+    let target = Target { location, model };
+    let point = crate::points::forecast_data_point(
+        target,
+        &forecast,
+        false,
+        false,
+        *fields::FIELD_LIMIT,
+        *geo::GEO_FIELDS,
+    )?;
+    Ok((location, timestamp, point))
+}
+
+/// Writes the points of a batch ending at `row`, one write per bucket.
+async fn write_batch(
+    sink: &Sink,
+    batch: Vec<(&Location, DataPoint)>,
+    row: u64,
+    summary: &mut ImportSummary,
+) -> Result<(), ImportError> {
+    let count = batch.len() as u64;
+    let mut buckets: BTreeMap<&str, Vec<DataPoint>> = BTreeMap::new();
+    for (location, point) in batch {
+        buckets
+            .entry(sink.bucket(location))
+            .or_default()
+            .push(point);
+    }
+    for (bucket, points) in buckets {
+        sink.write(bucket, points)
+            .await
+            .map_err(|error| ImportError::Write {
+                row,
+                bucket: bucket.to_string(),
+                error,
+            })?;
+    }
+
+    summary.imported += count;
+    let datetime = logging::datetime();
+    log_eprintln!(
+        "INFO  [{datetime}]: imported rows up to {row}, {} points written, {} duplicate and {} \
+         malformed rows skipped",
+        summary.imported,
+        summary.duplicates,
+        summary.malformed
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging;
+    use crate::sink::Buckets;
+    use std::env;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use warp::http::StatusCode;
+    use warp::Filter;
+
+    const CURRENT: &str = r#""{""2024-03-07 08:05"":0}""#;
+    const FORECASTS: &str = r#""{""2024-03-07 08:10"":1,""2024-03-07 08:15"":2}""#;
+
+    fn csv_file(name: &str, rows: &[String]) -> PathBuf {
+        let path = env::temp_dir().join(format!(
+            "swat-collector-import-{name}-{}.csv",
+            std::process::id()
+        ));
+        fs::write(&path, rows.join("\n") + "\n").unwrap();
+        path
+    }
+
+    fn options(file: PathBuf, batch_size: usize) -> ImportOptions {
+        let state_path = file.with_extension("import-state");
+        let _ = fs::remove_file(&state_path);
+        ImportOptions {
+            file,
+            mapping: ColumnMapping::default(),
+            batch_size,
+            state_path,
+        }
+    }
+
+    fn row(location: &str, issued: &str) -> String {
+        format!("{location},{issued},{CURRENT},{FORECASTS}")
+    }
+
+    #[test]
+    fn column_mapping() {
+        let mapping = ColumnMapping::from_args(&[
+            "location=standort".to_string(),
+            "issued = vorhersageZeit".to_string(),
+        ])
+        .unwrap();
+        assert_eq!(mapping.location, "standort");
+        assert_eq!(mapping.issued, "vorhersageZeit");
+        assert_eq!(mapping.current, "current");
+
+        let headers = csv::StringRecord::from(vec!["current", "standort", "forecasts"]);
+        let err = mapping.resolve(&headers).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "column \"vorhersageZeit\" is missing in the header"
+        );
+        let headers =
+            csv::StringRecord::from(vec!["current", "standort", "forecasts", "vorhersageZeit"]);
+        let columns = mapping.resolve(&headers).unwrap();
+        assert_eq!(
+            (columns.location, columns.issued, columns.current),
+            (1, 3, 0)
+        );
+
+        for arg in ["standort", "station=standort"] {
+            let err = ColumnMapping::from_args(&[arg.to_string()]).unwrap_err();
+            assert!(matches!(err, ImportError::Mapping(_)));
+        }
+    }
+
+    #[tokio::test]
+    async fn skips_malformed_and_duplicate_rows() {
+        let file = csv_file(
+            "malformed",
+            &[
+                "location,issued,current,forecasts".to_string(),
+                row("WW Großenkneten", "2024-03-07 08:05"),
+                row("ww-grossenkneten", "2024-03-07 08:05"),
+                row("2", "2024-03-07 08:05"),
+                row("Atlantis", "2024-03-07 08:05"),
+                row("2", "07.03.2024 08:10"),
+                format!("2,2024-03-07 08:10,{{}},{FORECASTS}"),
+                "2,2024-03-07 08:10".to_string(),
+                row("1", "2024-03-07 08:10"),
+            ],
+        );
+        let options = options(file, 2);
+
+        logging::capture();
+        let summary = import(&options, &Sink::Stdout).await.unwrap();
+        let lines = logging::take_captured();
+
+        assert_eq!(
+            summary,
+            ImportSummary {
+                resumed_after: 0,
+                imported: 3,
+                duplicates: 1,
+                malformed: 4,
+            }
+        );
+        let points: Vec<_> = lines
+            .iter()
+            .filter(|line| line.starts_with("forecast,"))
+            .collect();
+        assert_eq!(points.len(), 3);
+        assert!(points[0].contains(",id=1,"));
+        assert!(points[1].contains(",id=2,"));
+        assert!(points
+            .iter()
+            .all(|point| point.ends_with(" 1709798700") || point.ends_with(" 1709799000")));
+        let skipped: Vec<_> = lines
+            .iter()
+            .filter_map(|line| line.split("skipped malformed row ").nth(1))
+            .collect();
+        assert!(skipped[0].starts_with("4, unknown location \"Atlantis\""));
+        assert!(skipped[1].starts_with("5, parsing forecast timestamp failed"));
+        assert!(skipped[2].starts_with("6, current value is empty"));
+        assert!(skipped[3].starts_with("7, "));
+        assert!(!options.state_path.exists());
+        fs::remove_file(&options.file).unwrap();
+    }
+
+    #[tokio::test]
+    async fn resumes_after_last_batch() {
+        // the third write fails, like an import interrupted after four rows
+        let writes = Arc::new(AtomicUsize::new(0));
+        let write = warp::post()
+            .and(warp::path!("api" / "v2" / "write"))
+            .and(warp::body::bytes())
+            .map({
+                let writes = writes.clone();
+                move |body: warp::hyper::body::Bytes| {
+                    let points = String::from_utf8_lossy(&body).lines().count();
+                    match writes.fetch_add(points, Ordering::Relaxed) {
+                        4 => StatusCode::SERVICE_UNAVAILABLE,
+                        _ => StatusCode::NO_CONTENT,
+                    }
+                }
+            });
+        let (addr, server) = warp::serve(write).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let sink = Sink::Influx {
+            client: Box::new(crate::sink::InfluxClient::new(
+                format!("http://{addr}"),
+                "org",
+                "token",
+            )),
+            buckets: Buckets::from_lookup(|_| None, &crate::locations::LOCATIONS.locations),
+            idempotent: false,
+            profile: None,
+        };
+
+        let mut rows = vec!["issued,location,forecasts,current".to_string()];
+        for minute in 0..7 {
+            rows.push(format!(
+                "2024-03-07 08:{:02},1,{FORECASTS},{CURRENT}",
+                minute * 5
+            ));
+        }
+        let options = options(csv_file("resume", &rows), 2);
+
+        let err = import(&options, &sink).await.unwrap_err();
+        assert!(matches!(err, ImportError::Write { row: 6, .. }), "{err}");
+        assert_eq!(
+            fs::read_to_string(&options.state_path).unwrap(),
+            format!(
+                "{{\"file\":{},\"row\":4}}\n",
+                serde_json::to_string(&options.file).unwrap()
+            )
+        );
+
+        // the failed batch is written again, the rows before it are not
+        let summary = import(&options, &sink).await.unwrap();
+        assert_eq!(
+            summary,
+            ImportSummary {
+                resumed_after: 4,
+                imported: 3,
+                duplicates: 0,
+                malformed: 0,
+            }
+        );
+        assert_eq!(writes.load(Ordering::Relaxed), 4 + 2 + 3);
+        assert!(!options.state_path.exists());
+
+        // a state of another file is not resumed
+        ImportState::save(&options.state_path, Path::new("other.csv"), 2).unwrap();
+        let err = import(&options, &sink).await.unwrap_err();
+        assert!(matches!(err, ImportError::OtherFile(_)));
+        fs::remove_file(&options.state_path).unwrap();
+        fs::remove_file(&options.file).unwrap();
+    }
+}
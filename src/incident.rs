@@ -0,0 +1,294 @@
+use crate::gaps;
+use chrono::{DateTime, Utc};
+use std::fmt;
+
+/// An alert is only resolved after this many healthy ticks in a row unless
+/// `RESOLVE_AFTER_TICKS` is set.
+pub const DEFAULT_RESOLVE_AFTER_TICKS: u32 = 3;
+
+/// Errors that were alerted and are not resolved yet.
+#[derive(Debug)]
+struct Incident {
+    /// The [id] of the incident, kept when restored from the state file.
+    id: String,
+    since: DateTime<Utc>,
+    failures: u64,
+
+    /// Consecutive ticks without errors.
+    healthy_ticks: u32,
+}
+
+/// The cause of an incident, the location that failed first and its error kind.
+pub fn cause(slug: &str, kind: &str) -> String {
+    format!("{slug}/{kind}")
+}
+
+/// The id of the incident with the `cause` opened at `since`, like
+/// `ww-grossenkneten/request_forecast/20240501T12Z`.
+///
+/// Alerts, their audit in InfluxDB and the state file refer to incidents by it. It only
+/// depends on the hour the incident opened in, so a collector restarted during an incident
+/// derives the same id again and its alert is recognized as a duplicate.
+pub fn id(cause: &str, since: DateTime<Utc>) -> String {
+    format!("{cause}/{}", since.format("%Y%m%dT%HZ"))
+}
+
+/// How long a resolved incident lasted, shown in the resolved message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IncidentSummary {
+    /// The [id] of the incident.
+    pub id: String,
+    pub duration: chrono::Duration,
+    pub failures: u64,
+
+    /// The gaps in the data of the locations that closed during the incident, if asked for.
+    pub gaps: Vec<(String, chrono::Duration)>,
+}
+
+impl fmt::Display for IncidentSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let minutes = self.duration.num_minutes();
+        match minutes {
+            0 => f.write_str("The incident lasted less than a minute")?,
+            1 => f.write_str("The incident lasted 1 minute")?,
+            minutes if minutes < 120 => write!(f, "The incident lasted {minutes} minutes")?,
+            minutes => write!(
+                f,
+                "The incident lasted {}h {}min",
+                minutes / 60,
+                minutes % 60
+            )?,
+        }
+        match self.failures {
+            1 => f.write_str(" with 1 failure.")?,
+            failures => write!(f, " with {failures} failures.")?,
+        }
+        if !self.gaps.is_empty() {
+            let gaps: Vec<_> = self
+                .gaps
+                .iter()
+                .map(|(target, duration)| format!("{target} {}", gaps::format_duration(*duration)))
+                .collect();
+            write!(f, "\nData gaps: {}.", gaps.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// What to notify about after a tick.
+#[derive(Debug, PartialEq, Eq)]
+pub enum IncidentAction {
+    Alert,
+    Resolve(IncidentSummary),
+    None,
+}
+
+/// Pairs alerts with their resolution, resolving only after a number of healthy ticks.
+///
+/// A flapping location would otherwise alternate alerts and resolutions every tick, so errors
+/// during the pending resolution continue the incident without a new alert.
+#[derive(Debug)]
+pub struct IncidentTracker {
+    resolve_after: u32,
+    incident: Option<Incident>,
+
+    /// The id of the incident open before a restart, continued by the next incident unless a
+    /// healthy tick comes first.
+    restored: Option<String>,
+}
+
+impl IncidentTracker {
+    pub fn new(resolve_after: u32) -> IncidentTracker {
+        IncidentTracker {
+            resolve_after: resolve_after.max(1),
+            incident: None,
+            restored: None,
+        }
+    }
+
+    /// Continues the incident with the `id` persisted before a restart, if any.
+    pub fn restore(&mut self, id: Option<String>) {
+        self.restored = id;
+    }
+
+    /// Records a tick with `failures` errors at `now`, the first of which has the [cause].
+    pub fn observe(&mut self, failures: usize, cause: &str, now: DateTime<Utc>) -> IncidentAction {
+        match (&mut self.incident, failures) {
+            (None, 0) => {
+                self.restored = None;
+                IncidentAction::None
+            }
+            (None, failures) => {
+                self.incident = Some(Incident {
+                    id: (self.restored.take()).unwrap_or_else(|| id(cause, now)),
+                    since: now,
+                    failures: failures as u64,
+                    healthy_ticks: 0,
+                });
+                IncidentAction::Alert
+            }
+            (Some(incident), 0) => {
+                incident.healthy_ticks += 1;
+                if incident.healthy_ticks < self.resolve_after {
+                    return IncidentAction::None;
+                }
+                let summary = IncidentSummary {
+                    id: incident.id.clone(),
+                    duration: now - incident.since,
+                    failures: incident.failures,
+                    gaps: Vec::new(),
+                };
+                self.incident = None;
+                IncidentAction::Resolve(summary)
+            }
+            (Some(incident), failures) => {
+                incident.failures += failures as u64;
+                incident.healthy_ticks = 0;
+                IncidentAction::None
+            }
+        }
+    }
+
+    /// Since when the open incident lasts and its failures so far, if there is one.
+    #[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+    pub fn open(&self) -> Option<(DateTime<Utc>, u64)> {
+        self.incident
+            .as_ref()
+            .map(|incident| (incident.since, incident.failures))
+    }
+
+    /// The [id] of the open incident, if there is one.
+    pub fn open_id(&self) -> Option<String> {
+        self.incident.as_ref().map(|incident| incident.id.clone())
+    }
+}
+
+impl Default for IncidentTracker {
+    fn default() -> Self {
+        IncidentTracker::new(DEFAULT_RESOLVE_AFTER_TICKS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    const CAUSE: &str = "ww-grossenkneten/request_forecast";
+
+    fn tick(n: i64) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap() + chrono::Duration::minutes(2 * n)
+    }
+
+    #[test]
+    fn flapping_is_one_incident() {
+        let mut tracker = IncidentTracker::default();
+        let failures = [2, 0, 1, 0, 0, 1, 0, 0, 0];
+        let actions: Vec<_> = failures
+            .iter()
+            .enumerate()
+            .map(|(n, failures)| tracker.observe(*failures, CAUSE, tick(n as i64)))
+            .collect();
+
+        let resolved = IncidentSummary {
+            id: "ww-grossenkneten/request_forecast/20240501T12Z".to_string(),
+            duration: chrono::Duration::minutes(16),
+            failures: 4,
+            gaps: Vec::new(),
+        };
+        assert_eq!(
+            actions,
+            [
+                IncidentAction::Alert,
+                IncidentAction::None,
+                IncidentAction::None,
+                IncidentAction::None,
+                IncidentAction::None,
+                IncidentAction::None,
+                IncidentAction::None,
+                IncidentAction::None,
+                IncidentAction::Resolve(resolved),
+            ]
+        );
+
+        // the next failure starts a new incident
+        assert_eq!(tracker.open_id(), None);
+        assert_eq!(
+            tracker.observe(1, "ww-harpstedt/parse", tick(30)),
+            IncidentAction::Alert
+        );
+        assert_eq!(
+            tracker.open_id().unwrap(),
+            "ww-harpstedt/parse/20240501T13Z"
+        );
+    }
+
+    #[test]
+    fn continues_restored_incident() {
+        let mut restarted = IncidentTracker::default();
+        restarted.restore(Some("ww-harpstedt/parse/20240501T11Z".to_string()));
+        assert_eq!(restarted.observe(1, CAUSE, tick(0)), IncidentAction::Alert);
+        assert_eq!(
+            restarted.open_id().unwrap(),
+            "ww-harpstedt/parse/20240501T11Z"
+        );
+
+        // the incident ended during the restart
+        let mut restarted = IncidentTracker::default();
+        restarted.restore(Some("ww-harpstedt/parse/20240501T11Z".to_string()));
+        assert_eq!(restarted.observe(0, CAUSE, tick(0)), IncidentAction::None);
+        assert_eq!(restarted.observe(1, CAUSE, tick(1)), IncidentAction::Alert);
+        assert_eq!(
+            restarted.open_id().unwrap(),
+            "ww-grossenkneten/request_forecast/20240501T12Z"
+        );
+    }
+
+    #[test]
+    fn resolves_after_one_tick() {
+        let mut tracker = IncidentTracker::new(1);
+        assert_eq!(tracker.observe(0, CAUSE, tick(0)), IncidentAction::None);
+        assert_eq!(tracker.observe(1, CAUSE, tick(1)), IncidentAction::Alert);
+        assert_eq!(tracker.observe(1, CAUSE, tick(2)), IncidentAction::None);
+        assert!(matches!(
+            tracker.observe(0, CAUSE, tick(3)),
+            IncidentAction::Resolve(IncidentSummary { failures: 2, .. })
+        ));
+        assert_eq!(tracker.observe(0, CAUSE, tick(4)), IncidentAction::None);
+    }
+
+    #[test]
+    fn summary_text() {
+        let summary = |minutes, failures| {
+            IncidentSummary {
+                id: id(CAUSE, tick(0)),
+                duration: chrono::Duration::minutes(minutes),
+                failures,
+                gaps: Vec::new(),
+            }
+            .to_string()
+        };
+        assert_eq!(
+            summary(0, 1),
+            "The incident lasted less than a minute with 1 failure."
+        );
+        assert_eq!(
+            summary(16, 4),
+            "The incident lasted 16 minutes with 4 failures."
+        );
+        assert_eq!(
+            summary(185, 90),
+            "The incident lasted 3h 5min with 90 failures."
+        );
+        let with_gaps = IncidentSummary {
+            id: id(CAUSE, tick(0)),
+            duration: chrono::Duration::minutes(16),
+            failures: 4,
+            gaps: vec![("WW Marienhafe".to_string(), chrono::Duration::minutes(62))],
+        };
+        assert_eq!(
+            with_gaps.to_string(),
+            "The incident lasted 16 minutes with 4 failures.\nData gaps: WW Marienhafe 1h 2min."
+        );
+    }
+}
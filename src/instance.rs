@@ -0,0 +1,303 @@
+use chrono::{DateTime, Duration, Utc};
+use influxdb2::api::query::FluxRecord;
+use influxdb2::models::data_point::DataPointError;
+use influxdb2::models::DataPoint;
+use influxdb2_structmap::value::Value;
+use serde::Serialize;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
+
+/// Measurement every running collector registers itself in.
+pub const MEASUREMENT: &str = "collector_instances";
+
+/// How often a running collector registers itself.
+pub const REGISTER_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+/// Instances not seen for this long after their last registration are flagged, the slack
+/// covers clocks of other hosts running behind.
+const STALE_TOLERANCE_MINUTES: i64 = 15;
+
+/// Where the instance id is kept unless `STATE_FILE` names a directory for it.
+const DEFAULT_DIR: &str = "/tmp/wisdom";
+
+/// Identity of this collector, kept stable across restarts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instance {
+    pub id: String,
+    pub hostname: String,
+    pub started: DateTime<Utc>,
+
+    /// Registered with the [`crate::read_only::TAG`] while running with `READ_ONLY`.
+    pub read_only: bool,
+}
+
+impl Instance {
+    /// Reads the instance id kept in the state dir, creating one on the first start.
+    pub fn load(started: DateTime<Utc>) -> io::Result<Instance> {
+        let dir = env::var("STATE_FILE")
+            .ok()
+            .and_then(|path| Some(Path::new(&path).parent()?.to_path_buf()))
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_DIR));
+        Ok(Instance {
+            id: instance_id(&dir.join("swat-collector.instance-id"))?,
+            hostname: hostname(),
+            started,
+            read_only: false,
+        })
+    }
+
+    pub fn with_read_only(self, read_only: bool) -> Instance {
+        Instance { read_only, ..self }
+    }
+
+    /// The registration point at `now`.
+    pub fn data_point(
+        &self,
+        locations: usize,
+        interval: std::time::Duration,
+        now: DateTime<Utc>,
+    ) -> Result<DataPoint, DataPointError> {
+        let mut point = DataPoint::builder(MEASUREMENT)
+            .timestamp(now.timestamp())
+            .tag("hostname", self.hostname.as_str())
+            .tag("instance_id", self.id.as_str())
+            .tag("version", crate::version::VERSION);
+        if self.read_only {
+            point = point.tag(crate::read_only::TAG, "true");
+        }
+        point
+            .field("locations", locations as i64)
+            .field("poll_interval_seconds", interval.as_secs() as i64)
+            .field("uptime_seconds", (now - self.started).num_seconds())
+            .build()
+    }
+}
+
+/// Reads the id at `path`, a missing one is generated and written.
+fn instance_id(path: &Path) -> io::Result<String> {
+    match fs::read_to_string(path) {
+        Ok(id) if !id.trim().is_empty() => return Ok(id.trim().to_string()),
+        Ok(_) => (),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+        Err(err) => return Err(err),
+    }
+    let id = uuid::Uuid::new_v4().to_string();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, format!("{id}\n"))?;
+    Ok(id)
+}
+
+fn hostname() -> String {
+    env::var("HOSTNAME")
+        .ok()
+        .or_else(|| fs::read_to_string("/proc/sys/kernel/hostname").ok())
+        .map(|hostname| hostname.trim().to_string())
+        .filter(|hostname| !hostname.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// The last registration of every instance seen in the last day, in `bucket`.
+pub fn query(bucket: &str) -> String {
+    format!(
+        r#"from(bucket: {bucket:?})
+            |> range(start: -24h)
+            |> filter(fn: (r) => r._measurement == "{MEASUREMENT}" and r._field == "uptime_seconds")
+            |> group(columns: ["instance_id"])
+            |> last()"#
+    )
+}
+
+/// An instance as listed by `--instances`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SeenInstance {
+    pub instance_id: String,
+    pub hostname: String,
+    pub version: String,
+    pub last_seen: DateTime<Utc>,
+    pub uptime_seconds: i64,
+    pub stale: bool,
+}
+
+/// Reads the instances from the `records` of the [query], flagging those that stopped
+/// registering, ordered by hostname.
+///
+/// Staleness is judged against the newest registration if that is ahead of `now`, so a host
+/// with its clock ahead does not get every other instance flagged.
+pub fn parse(records: &[FluxRecord], now: DateTime<Utc>) -> Vec<SeenInstance> {
+    let string = |record: &FluxRecord, key: &str| match record.values.get(key) {
+        Some(Value::String(value)) => value.clone(),
+        _ => String::new(),
+    };
+    let mut instances: Vec<_> = records
+        .iter()
+        .filter_map(|record| {
+            let Some(Value::TimeRFC(time)) = record.values.get("_time") else {
+                return None;
+            };
+            let uptime_seconds = match record.values.get("_value") {
+                Some(Value::Long(uptime)) => *uptime,
+                Some(Value::Double(uptime)) => uptime.0 as i64,
+                _ => 0,
+            };
+            Some(SeenInstance {
+                instance_id: string(record, "instance_id"),
+                hostname: string(record, "hostname"),
+                version: string(record, "version"),
+                last_seen: time.with_timezone(&Utc),
+                uptime_seconds,
+                stale: false,
+            })
+        })
+        .collect();
+
+    let reference = instances
+        .iter()
+        .map(|instance| instance.last_seen)
+        .chain([now])
+        .max()
+        .unwrap_or(now);
+    let threshold = Duration::from_std(REGISTER_INTERVAL).expect("interval in range")
+        + Duration::minutes(STALE_TOLERANCE_MINUTES);
+    for instance in &mut instances {
+        instance.stale = reference - instance.last_seen > threshold;
+    }
+    instances.sort_by(|a, b| (&a.hostname, &a.instance_id).cmp(&(&b.hostname, &b.instance_id)));
+    instances
+}
+
+/// The `instances` as a table with aligned columns.
+pub fn table(instances: &[SeenInstance]) -> String {
+    let width = instances
+        .iter()
+        .map(|instance| instance.hostname.chars().count())
+        .chain(["hostname".len()])
+        .max()
+        .unwrap_or_default();
+    let mut table = format!(
+        "{:width$}  {:36}  {:16}  last seen\n",
+        "hostname", "instance", "version"
+    );
+    for instance in instances {
+        let _ = writeln!(
+            table,
+            "{:width$}  {:36}  {:16}  {}{}",
+            instance.hostname,
+            instance.instance_id,
+            instance.version,
+            instance.last_seen.format("%Y-%m-%d %H:%M"),
+            if instance.stale {
+                "  stopped reporting"
+            } else {
+                ""
+            }
+        );
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::collections::BTreeMap;
+
+    fn record(id: &str, hostname: &str, time: &str, uptime: i64) -> FluxRecord {
+        FluxRecord {
+            table: 0,
+            values: BTreeMap::from([
+                ("_time".to_string(), Value::TimeRFC(time.parse().unwrap())),
+                ("_value".to_string(), Value::Long(uptime)),
+                ("instance_id".to_string(), Value::String(id.to_string())),
+                ("hostname".to_string(), Value::String(hostname.to_string())),
+                ("version".to_string(), Value::String("1.4.0".to_string())),
+            ]),
+        }
+    }
+
+    #[test]
+    fn persists_instance_id() {
+        let dir = env::temp_dir().join(format!("swat-collector-instance-{}", std::process::id()));
+        let path = dir.join("swat-collector.instance-id");
+        let id = instance_id(&path).unwrap();
+        assert!(uuid::Uuid::parse_str(&id).is_ok());
+        assert_eq!(instance_id(&path).unwrap(), id);
+
+        fs::write(&path, "").unwrap();
+        assert_ne!(instance_id(&path).unwrap(), id);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn registers_instance() {
+        let started = Utc.with_ymd_and_hms(2024, 3, 7, 8, 0, 0).unwrap();
+        let instance = Instance {
+            id: "0b6c6a64-50a5-4b5e-9d55-4be0f42e9bd9".to_string(),
+            hostname: "collector-1".to_string(),
+            started,
+            read_only: false,
+        };
+        let point = instance
+            .data_point(
+                12,
+                std::time::Duration::from_secs(120),
+                started + Duration::minutes(90),
+            )
+            .unwrap();
+        let mut line = Vec::new();
+        influxdb2::models::WriteDataPoint::write_data_point_to(&point, &mut line).unwrap();
+        let line = String::from_utf8(line).unwrap();
+        assert!(line.starts_with(&format!(
+            "collector_instances,hostname=collector-1,instance_id={},version=",
+            instance.id
+        )));
+        assert!(line.contains("uptime_seconds=5400i"));
+        assert!(line.contains("locations=12i"));
+
+        let point = (instance.clone().with_read_only(true))
+            .data_point(12, std::time::Duration::from_secs(120), started)
+            .unwrap();
+        let mut line = Vec::new();
+        influxdb2::models::WriteDataPoint::write_data_point_to(&point, &mut line).unwrap();
+        let line = String::from_utf8(line).unwrap();
+        assert!(line.contains(&format!(",instance_id={},read_only=true,", instance.id)));
+    }
+
+    #[test]
+    fn lists_instances() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 7, 12, 0, 0).unwrap();
+        let records = [
+            record("b", "collector-2", "2024-03-07T11:10:00Z", 7200),
+            // running ahead of this host, so the others are judged against it
+            record("a", "collector-1", "2024-03-07T12:20:00Z", 86400),
+            record("c", "collector-3", "2024-03-07T11:00:00Z", 600),
+        ];
+        let instances = parse(&records, now);
+        let stale: Vec<_> = instances
+            .iter()
+            .map(|instance| (instance.hostname.as_str(), instance.stale))
+            .collect();
+        assert_eq!(
+            stale,
+            [
+                ("collector-1", false),
+                ("collector-2", false),
+                ("collector-3", true)
+            ]
+        );
+        assert_eq!(instances[1].uptime_seconds, 7200);
+
+        // a clock running a few minutes behind is not stale right away
+        let behind = [record("d", "collector-4", "2024-03-07T10:50:00Z", 60)];
+        assert!(!parse(&behind, now).pop().unwrap().stale);
+
+        assert_eq!(
+            table(&instances[2..]),
+            "hostname     instance                              version           last seen\n\
+             collector-3  c                                     1.4.0             2024-03-07 11:00  stopped reporting\n"
+        );
+    }
+}
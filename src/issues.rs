@@ -0,0 +1,180 @@
+use crate::bounded_cache::{self, BoundedCache};
+use chrono::{DateTime, Duration, Utc};
+
+/// Forecasts not reissued for this many minutes are alerted unless `STALE_ISSUE_ALERT_MINUTES`
+/// is set.
+pub const DEFAULT_STALE_MINUTES: i64 = 60;
+
+/// Issue time of the forecasts of a location and model and since when it is unchanged.
+#[derive(Debug)]
+struct Issue {
+    issued: String,
+    since: DateTime<Utc>,
+    alerted: bool,
+}
+
+/// Tracks per location and model how long the swat api keeps serving the same `vorhersageZeit`.
+///
+/// Successful requests returning an old forecast would otherwise go unnoticed, so once the issue
+/// time is unchanged for longer than the threshold a warning is queued, and a recovery message
+/// once it changes again.
+#[derive(Debug)]
+pub struct IssueTracker {
+    threshold: Duration,
+    issues: BoundedCache<String, Issue>,
+    messages: Vec<String>,
+}
+
+impl IssueTracker {
+    pub fn new(threshold: Duration) -> IssueTracker {
+        IssueTracker {
+            threshold,
+            issues: BoundedCache::new(bounded_cache::DEFAULT_CAPACITY),
+            messages: Vec::new(),
+        }
+    }
+
+    /// Records that `target`, the location and model, currently serves the forecast `issued` at
+    /// `now`, returns whether that forecast is stale.
+    pub fn observe(&mut self, target: &str, issued: &str, now: DateTime<Utc>) -> bool {
+        let issue = self
+            .issues
+            .get_or_insert_with(target.to_string(), || Issue {
+                issued: issued.to_string(),
+                since: now,
+                alerted: false,
+            });
+
+        if issue.issued != issued {
+            if issue.alerted {
+                self.messages.push(format!(
+                    "forecast for {target} is reissued again, last issue is {issued}"
+                ));
+            }
+            *issue = Issue {
+                issued: issued.to_string(),
+                since: now,
+                alerted: false,
+            };
+            return false;
+        }
+
+        let stale = now - issue.since > self.threshold;
+        if stale && !issue.alerted {
+            issue.alerted = true;
+            self.messages.push(format!(
+                "forecast for {target} has not been reissued since {issued}"
+            ));
+        }
+        stale
+    }
+
+    /// Forgets the locations and models not in `targets` and keeps at most `capacity`.
+    pub fn resize(&mut self, targets: &[String], capacity: usize) {
+        self.issues.retain(|target| targets.contains(target));
+        self.issues.resize(capacity);
+    }
+
+    /// The issue time last served for `target` and since when it is unchanged.
+    #[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+    pub fn issued(&self, target: &str) -> Option<(&str, DateTime<Utc>)> {
+        self.issues
+            .get(target)
+            .map(|issue| (issue.issued.as_str(), issue.since))
+    }
+
+    /// Issue times evicted to stay within the capacity.
+    #[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+    pub fn evictions(&self) -> u64 {
+        self.issues.evictions()
+    }
+
+    /// Takes the warnings and recoveries queued since the last call.
+    pub fn take_messages(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.messages)
+    }
+}
+
+impl Default for IssueTracker {
+    fn default() -> Self {
+        IssueTracker::new(Duration::minutes(DEFAULT_STALE_MINUTES))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(minute: i64) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap() + Duration::minutes(minute)
+    }
+
+    #[test]
+    fn alerts_stale_issue_once() {
+        let mut tracker = IssueTracker::default();
+        let observe = |tracker: &mut IssueTracker, minute, issued| {
+            tracker.observe("WW Großenkneten", issued, at(minute))
+        };
+
+        // unchanged up to the threshold is fine
+        assert!(!observe(&mut tracker, 0, "2024-05-01 12:00"));
+        assert!(!observe(&mut tracker, 60, "2024-05-01 12:00"));
+        assert!(tracker.take_messages().is_empty());
+
+        // exceeding it alerts once, but every point is stale
+        assert!(observe(&mut tracker, 62, "2024-05-01 12:00"));
+        assert!(observe(&mut tracker, 64, "2024-05-01 12:00"));
+        assert_eq!(
+            tracker.take_messages(),
+            ["forecast for WW Großenkneten has not been reissued since 2024-05-01 12:00"]
+        );
+
+        // a new issue time recovers
+        assert!(!observe(&mut tracker, 66, "2024-05-01 13:05"));
+        assert_eq!(
+            tracker.take_messages(),
+            ["forecast for WW Großenkneten is reissued again, last issue is 2024-05-01 13:05"]
+        );
+        assert!(!observe(&mut tracker, 120, "2024-05-01 13:05"));
+        assert!(observe(&mut tracker, 127, "2024-05-01 13:05"));
+    }
+
+    #[test]
+    fn reissue_without_alert_is_silent() {
+        let mut tracker = IssueTracker::new(Duration::minutes(10));
+        assert!(!tracker.observe("A", "12:00", at(0)));
+        assert!(!tracker.observe("B", "12:00", at(0)));
+        assert!(!tracker.observe("A", "12:05", at(5)));
+        assert!(tracker.observe("B", "12:00", at(11)));
+        assert!(!tracker.observe("A", "12:05", at(11)));
+        assert_eq!(tracker.take_messages().len(), 1);
+    }
+
+    #[test]
+    fn resize_for_locations() {
+        let mut tracker = IssueTracker::default();
+        let targets = |names: &[&str]| names.iter().map(ToString::to_string).collect::<Vec<_>>();
+        tracker.observe("A", "12:00", at(0));
+        tracker.observe("B", "12:00", at(0));
+        tracker.observe("C", "12:00", at(0));
+
+        // a removed location is dropped right away, not evicted
+        tracker.resize(&targets(&["A", "C"]), 8);
+        assert_eq!(tracker.issues.keys().collect::<Vec<_>>(), ["A", "C"]);
+        assert_eq!(tracker.evictions(), 0);
+
+        // fewer locations shrink the capacity, the least recently observed is evicted
+        tracker.observe("A", "12:00", at(1));
+        tracker.resize(&targets(&["A", "C"]), 1);
+        assert_eq!(tracker.issues.keys().collect::<Vec<_>>(), ["A"]);
+        assert_eq!(tracker.evictions(), 1);
+
+        // more locations grow it again
+        tracker.resize(&targets(&["A", "C", "D"]), 12);
+        tracker.observe("C", "12:00", at(2));
+        tracker.observe("D", "12:00", at(2));
+        assert_eq!(tracker.issues.keys().count(), 3);
+        assert_eq!(tracker.evictions(), 1);
+    }
+}
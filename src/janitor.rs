@@ -0,0 +1,453 @@
+use parking_lot::RwLock;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+
+/// How often the directories are pruned.
+pub const INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum JanitorError {
+    #[error(
+        "expected pruned directories in the form of `dir=limit[,limit]` separated by \
+         semicolons, got {0:?}"
+    )]
+    Format(String),
+
+    #[error("invalid limit {0:?}, expected a size like `500M` or an age like `7d`")]
+    Limit(String),
+
+    #[error("directory {0:?} is listed twice")]
+    Duplicate(PathBuf),
+}
+
+/// Limits a directory is pruned to, either may be absent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Total size of the files in the directory.
+    pub max_bytes: Option<u64>,
+
+    /// Age of a file since it was last modified.
+    pub max_age: Option<Duration>,
+}
+
+/// Size of the files in a directory, the temporary ones included.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// Outcome of pruning a directory once.
+#[derive(Debug, Default)]
+pub struct Pruned {
+    /// Files removed, oldest first.
+    pub removed: Vec<PathBuf>,
+
+    /// Files that could not be removed, they are retried with the next run.
+    pub failed: Vec<(PathBuf, io::Error)>,
+
+    /// What is left in the directory.
+    pub usage: Usage,
+}
+
+/// Keeps the directories the collector writes files into within their limits, configured via
+/// `PRUNE_DIRS` like `/var/lib/swat/archive=10G,30d;/var/lib/swat/captures=7d`.
+///
+/// Files are removed oldest first, by modification time, until none is older than the age and
+/// the total fits the size. Files named `*.tmp` are still being written and renamed once
+/// complete, they count towards the total but are never removed.
+#[derive(Debug, Default)]
+pub struct Janitor {
+    dirs: Vec<(PathBuf, Limits)>,
+
+    /// Usage per directory as of the last run.
+    usage: RwLock<Vec<Option<Usage>>>,
+}
+
+impl Janitor {
+    pub fn new(dirs: Vec<(PathBuf, Limits)>) -> Janitor {
+        let usage = RwLock::new(vec![None; dirs.len()]);
+        Janitor { dirs, usage }
+    }
+
+    /// Reads the pruned directories from `PRUNE_DIRS` which `lookup` returns.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Janitor, JanitorError> {
+        let Some(value) = lookup("PRUNE_DIRS") else {
+            return Ok(Janitor::default());
+        };
+        let mut dirs: Vec<(PathBuf, Limits)> = Vec::new();
+        for entry in value.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+            let Some((dir, limits)) = entry
+                .split_once('=')
+                .map(|(dir, limits)| (dir.trim(), limits.trim()))
+                .filter(|(dir, limits)| !dir.is_empty() && !limits.is_empty())
+            else {
+                return Err(JanitorError::Format(entry.to_string()));
+            };
+            let dir = PathBuf::from(dir);
+            if dirs.iter().any(|(listed, _)| *listed == dir) {
+                return Err(JanitorError::Duplicate(dir));
+            }
+            dirs.push((dir, parse_limits(limits)?));
+        }
+        Ok(Janitor::new(dirs))
+    }
+
+    pub fn dirs(&self) -> &[(PathBuf, Limits)] {
+        &self.dirs
+    }
+
+    /// Prunes every directory at `now`, returning what was done per directory.
+    ///
+    /// A directory that cannot be read is returned as an error and keeps its last usage.
+    pub fn run(&self, now: SystemTime) -> Vec<(&Path, io::Result<Pruned>)> {
+        self.dirs
+            .iter()
+            .enumerate()
+            .map(|(i, (dir, limits))| {
+                let pruned = prune(dir, *limits, now);
+                if let Ok(pruned) = &pruned {
+                    self.usage.write()[i] = Some(pruned.usage);
+                }
+                (dir.as_path(), pruned)
+            })
+            .collect()
+    }
+
+    /// Usage of the directories as of the last run, empty before the first one.
+    #[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+    pub fn status_text(&self) -> String {
+        let usage = self.usage.read();
+        self.dirs
+            .iter()
+            .zip(usage.iter())
+            .filter_map(|((dir, limits), usage)| {
+                let usage = usage.as_ref()?;
+                let limit = match limits.max_bytes {
+                    Some(max_bytes) => format!(" of {}", Size(max_bytes)),
+                    None => String::new(),
+                };
+                Some(format!(
+                    "  {dir:?}: {}{limit} in {} files\n",
+                    Size(usage.bytes),
+                    usage.files
+                ))
+            })
+            .collect()
+    }
+}
+
+fn parse_limits(limits: &str) -> Result<Limits, JanitorError> {
+    let mut parsed = Limits::default();
+    for limit in limits.split(',').map(str::trim) {
+        let invalid = || JanitorError::Limit(limit.to_string());
+        let split = limit
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(limit.len());
+        let (number, unit) = limit.split_at(split);
+        let number: u64 = number.parse().map_err(|_| invalid())?;
+        let (max_age, max_bytes) = match unit {
+            "h" => (Some(Duration::from_secs(number * 60 * 60)), None),
+            "d" => (Some(Duration::from_secs(number * 24 * 60 * 60)), None),
+            "" => (None, Some(number)),
+            "K" => (None, Some(number << 10)),
+            "M" => (None, Some(number << 20)),
+            "G" => (None, Some(number << 30)),
+            _ => return Err(invalid()),
+        };
+        if max_age.is_some() && parsed.max_age.is_some()
+            || max_bytes.is_some() && parsed.max_bytes.is_some()
+        {
+            return Err(invalid());
+        }
+        parsed.max_age = parsed.max_age.or(max_age);
+        parsed.max_bytes = parsed.max_bytes.or(max_bytes);
+    }
+    Ok(parsed)
+}
+
+/// Whether the file at `path` is still being written, to be renamed once complete.
+fn in_progress(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "tmp")
+}
+
+/// Files below `dir` with their modification time and size, a missing directory is empty.
+fn files(dir: &Path) -> io::Result<Vec<(PathBuf, SystemTime, u64)>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    let mut found = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        match metadata.is_dir() {
+            true => found.extend(files(&entry.path())?),
+            false => found.push((entry.path(), metadata.modified()?, metadata.len())),
+        }
+    }
+    Ok(found)
+}
+
+/// Removes the oldest files in `dir` until it is within the `limits` at `now`.
+///
+/// Directories left empty by the removal are removed as well, failing to remove a file is
+/// returned with the others and does not stop the pruning.
+pub fn prune(dir: &Path, limits: Limits, now: SystemTime) -> io::Result<Pruned> {
+    let mut files = files(dir)?;
+    files.sort_by(|(a, a_modified, _), (b, b_modified, _)| {
+        a_modified.cmp(b_modified).then_with(|| a.cmp(b))
+    });
+    let mut pruned = Pruned {
+        usage: Usage {
+            files: files.len(),
+            bytes: files.iter().map(|(_, _, len)| len).sum(),
+        },
+        ..Pruned::default()
+    };
+    for (path, modified, len) in files {
+        if in_progress(&path) {
+            continue;
+        }
+        let expired = limits
+            .max_age
+            .is_some_and(|max_age| now.duration_since(modified).is_ok_and(|age| age > max_age));
+        let oversized = limits
+            .max_bytes
+            .is_some_and(|max_bytes| pruned.usage.bytes > max_bytes);
+        if !expired && !oversized {
+            continue;
+        }
+        match fs::remove_file(&path) {
+            Ok(()) => {
+                pruned.usage.files -= 1;
+                pruned.usage.bytes -= len;
+                if let Some(parent) = path.parent().filter(|parent| *parent != dir) {
+                    // fails unless the directory is empty now
+                    let _ = fs::remove_dir(parent);
+                }
+                pruned.removed.push(path);
+            }
+            Err(err) => pruned.failed.push((path, err)),
+        }
+    }
+    Ok(pruned)
+}
+
+/// Bytes in the largest binary unit below them.
+pub struct Size(pub u64);
+
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let units = ["bytes", "KiB", "MiB", "GiB", "TiB"];
+        let mut size = self.0 as f64;
+        let mut unit = 0;
+        while size >= 1024.0 && unit < units.len() - 1 {
+            size /= 1024.0;
+            unit += 1;
+        }
+        match unit {
+            0 => write!(f, "{} bytes", self.0),
+            _ => write!(f, "{size:.1} {}", units[unit]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs::File;
+
+    /// A fresh directory for the `test`, removed when dropped.
+    struct Dir(PathBuf);
+
+    impl Dir {
+        fn new(test: &str) -> Dir {
+            let dir = env::temp_dir().join(format!(
+                "swat-collector-janitor-{test}-{}",
+                std::process::id()
+            ));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Dir(dir)
+        }
+
+        /// Writes `len` bytes into `name`, last modified `hours` before `now`.
+        fn file(&self, name: &str, len: usize, hours: u64, now: SystemTime) {
+            let path = self.0.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, vec![0; len]).unwrap();
+            File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(now - Duration::from_secs(hours * 60 * 60))
+                .unwrap();
+        }
+
+        fn removed(&self, pruned: &Pruned) -> Vec<String> {
+            pruned
+                .removed
+                .iter()
+                .map(|path| {
+                    let path = path.strip_prefix(&self.0).unwrap();
+                    path.to_string_lossy().into_owned()
+                })
+                .collect()
+        }
+    }
+
+    impl Drop for Dir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn parses_dirs() {
+        let janitor = Janitor::from_lookup(|_| {
+            Some("/var/lib/swat/archive = 10G, 30d; /var/lib/swat/captures=36h;".to_string())
+        })
+        .unwrap();
+        assert_eq!(
+            janitor.dirs(),
+            [
+                (
+                    PathBuf::from("/var/lib/swat/archive"),
+                    Limits {
+                        max_bytes: Some(10 << 30),
+                        max_age: Some(Duration::from_secs(30 * 24 * 60 * 60)),
+                    }
+                ),
+                (
+                    PathBuf::from("/var/lib/swat/captures"),
+                    Limits {
+                        max_bytes: None,
+                        max_age: Some(Duration::from_secs(36 * 60 * 60)),
+                    }
+                ),
+            ]
+        );
+        assert!(Janitor::from_lookup(|_| None).unwrap().dirs().is_empty());
+
+        let parse = |value: &str| Janitor::from_lookup(|_| Some(value.to_string())).unwrap_err();
+        assert_eq!(
+            parse("/var/lib/swat"),
+            JanitorError::Format("/var/lib/swat".to_string())
+        );
+        assert_eq!(parse("/a=7w"), JanitorError::Limit("7w".to_string()));
+        assert_eq!(parse("/a=1d,2d"), JanitorError::Limit("2d".to_string()));
+        assert_eq!(
+            parse("/a=1d;/a=1G"),
+            JanitorError::Duplicate(PathBuf::from("/a"))
+        );
+    }
+
+    #[test]
+    fn removes_oldest_first_until_within_size() {
+        let (dir, now) = (Dir::new("size"), SystemTime::now());
+        dir.file("c", 100, 1, now);
+        dir.file("a", 100, 3, now);
+        dir.file("nested/b", 100, 2, now);
+        dir.file("d", 100, 0, now);
+
+        let limits = Limits {
+            max_bytes: Some(250),
+            max_age: None,
+        };
+        let pruned = prune(&dir.0, limits, now).unwrap();
+        assert_eq!(dir.removed(&pruned), ["a", "nested/b"]);
+        assert!(pruned.failed.is_empty());
+        assert_eq!(
+            pruned.usage,
+            Usage {
+                files: 2,
+                bytes: 200
+            }
+        );
+        assert!(!dir.0.join("nested").exists());
+
+        // already within the limits
+        let pruned = prune(&dir.0, limits, now).unwrap();
+        assert!(pruned.removed.is_empty());
+    }
+
+    #[test]
+    fn removes_expired() {
+        let (dir, now) = (Dir::new("age"), SystemTime::now());
+        dir.file("date=2024-03-06/forecasts.parquet", 10, 50, now);
+        dir.file("date=2024-03-07/forecasts.parquet", 10, 26, now);
+        dir.file("date=2024-03-08/forecasts.parquet", 10, 2, now);
+
+        let limits = Limits {
+            max_bytes: Some(1 << 20),
+            max_age: Some(Duration::from_secs(24 * 60 * 60)),
+        };
+        let pruned = prune(&dir.0, limits, now).unwrap();
+        assert_eq!(
+            dir.removed(&pruned),
+            [
+                "date=2024-03-06/forecasts.parquet",
+                "date=2024-03-07/forecasts.parquet"
+            ]
+        );
+        assert_eq!(pruned.usage.files, 1);
+    }
+
+    #[test]
+    fn keeps_files_in_progress() {
+        let (dir, now) = (Dir::new("progress"), SystemTime::now());
+        dir.file("forecasts.parquet.tmp", 100, 48, now);
+        dir.file("forecasts.parquet", 100, 24, now);
+        dir.file("spool.tmp", 100, 1, now);
+
+        let limits = Limits {
+            max_bytes: Some(150),
+            max_age: Some(Duration::from_secs(60 * 60)),
+        };
+        let pruned = prune(&dir.0, limits, now).unwrap();
+        assert_eq!(dir.removed(&pruned), ["forecasts.parquet"]);
+        assert!(dir.0.join("forecasts.parquet.tmp").exists());
+        assert!(dir.0.join("spool.tmp").exists());
+
+        // the files in progress still count towards the usage
+        assert_eq!(
+            pruned.usage,
+            Usage {
+                files: 2,
+                bytes: 200
+            }
+        );
+    }
+
+    #[test]
+    fn reports_usage() {
+        let (dir, now) = (Dir::new("usage"), SystemTime::now());
+        dir.file("a", 3 << 10, 0, now);
+        let limits = Limits {
+            max_bytes: Some(1 << 20),
+            max_age: None,
+        };
+        let missing = dir.0.join("missing");
+        let janitor = Janitor::new(vec![(dir.0.clone(), limits), (missing, limits)]);
+        assert_eq!(janitor.status_text(), "");
+
+        let runs = janitor.run(now);
+        assert!(runs.iter().all(|(_, pruned)| pruned.is_ok()));
+        let status = janitor.status_text();
+        assert!(
+            status.contains(": 3.0 KiB of 1.0 MiB in 1 files\n"),
+            "{status}"
+        );
+        assert!(
+            status.contains("missing\": 0 bytes of 1.0 MiB in 0 files\n"),
+            "{status}"
+        );
+    }
+}
@@ -0,0 +1,310 @@
+use crate::egress::{self, Class, Transfer};
+use crate::event::ForecastEvent;
+use crate::logging;
+use crate::version;
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+use rdkafka::message::{Header, OwnedHeaders};
+use rdkafka::producer::{
+    BaseRecord, DeliveryResult, Producer as _, ProducerContext, ThreadedProducer,
+};
+use rdkafka::{ClientConfig, ClientContext};
+use std::num::ParseIntError;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use thiserror::Error;
+
+/// Messages librdkafka queues locally while the brokers are slow or unreachable, unless
+/// `KAFKA_QUEUE_SIZE` is set.
+pub const DEFAULT_QUEUE_SIZE: u32 = 10_000;
+
+#[derive(Debug, Error)]
+pub enum KafkaConfigError {
+    #[error("\"KAFKA_TOPIC\" is required along with \"KAFKA_BROKERS\"")]
+    MissingTopic,
+
+    #[error("expected \"KAFKA_QUEUE_SIZE\" to be valid, {0}")]
+    QueueSize(#[from] ParseIntError),
+
+    #[error("could not create producer, {0}")]
+    Kafka(#[from] KafkaError),
+}
+
+#[derive(Debug, Error)]
+pub enum ProduceError {
+    #[error("local queue is full")]
+    QueueFull,
+
+    #[error("could not serialize event, {0}")]
+    Serialize(#[from] serde_json::Error),
+
+    #[error("{0}")]
+    Kafka(#[from] KafkaError),
+}
+
+/// A message to be produced, the topic is up to the producer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub key: String,
+    pub payload: Vec<u8>,
+    pub headers: Vec<(&'static str, String)>,
+}
+
+/// Seam between the forecast output and librdkafka, so tests can record the messages.
+pub trait Producer: Send + Sync {
+    /// Queues the `message` without blocking, retrying its delivery is up to the producer.
+    fn send(&self, message: Message) -> Result<(), ProduceError>;
+
+    /// Waits up to `timeout` for the queued messages to be delivered.
+    fn flush(&self, timeout: Duration) -> Result<(), ProduceError>;
+}
+
+/// Counts the messages whose delivery failed for good, after librdkafka's retries.
+#[derive(Debug, Default)]
+struct DeliveryContext {
+    failed: AtomicU64,
+}
+
+impl ClientContext for DeliveryContext {}
+
+impl ProducerContext for DeliveryContext {
+    type DeliveryOpaque = ();
+
+    fn delivery(&self, result: &DeliveryResult<'_>, _: ()) {
+        if let Err((err, _)) = result {
+            let failed = self.failed.fetch_add(1, Ordering::Relaxed) + 1;
+            let datetime = logging::datetime();
+            log_eprintln!(
+                "WARN  [{datetime}]: could not deliver forecast event to kafka, {failed} failed so far, {err}"
+            );
+        }
+    }
+}
+
+/// Idempotent librdkafka producer, polled on a thread of its own.
+pub struct KafkaProducer {
+    producer: ThreadedProducer<DeliveryContext>,
+    topic: String,
+}
+
+impl KafkaProducer {
+    pub fn new(brokers: &str, topic: String, queue_size: u32) -> Result<Self, KafkaError> {
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("enable.idempotence", "true")
+            .set("queue.buffering.max.messages", queue_size.to_string())
+            .set("client.id", format!("swat-collector/{}", version::VERSION))
+            .create_with_context(DeliveryContext::default())?;
+        Ok(KafkaProducer { producer, topic })
+    }
+}
+
+impl Producer for KafkaProducer {
+    fn send(&self, message: Message) -> Result<(), ProduceError> {
+        let headers_size: usize = message
+            .headers
+            .iter()
+            .map(|(key, value)| key.len() + value.len())
+            .sum();
+        let size = message.key.len() + message.payload.len() + headers_size;
+        egress::METER.record(Class::Other, Transfer::sent(size));
+        let headers = message
+            .headers
+            .iter()
+            .fold(OwnedHeaders::new(), |headers, (key, value)| {
+                headers.insert(Header {
+                    key,
+                    value: Some(value),
+                })
+            });
+        let record = BaseRecord::to(&self.topic)
+            .key(&message.key)
+            .payload(&message.payload)
+            .headers(headers);
+        self.producer.send(record).map_err(|(err, _)| match err {
+            KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull) => ProduceError::QueueFull,
+            err => err.into(),
+        })
+    }
+
+    fn flush(&self, timeout: Duration) -> Result<(), ProduceError> {
+        Ok(self.producer.flush(timeout)?)
+    }
+}
+
+/// Publishes every successfully fetched forecast to `KAFKA_TOPIC` at `KAFKA_BROKERS`, keyed
+/// by the slug of its location.
+///
+/// Messages carry the collector version and the tick in their headers. Publishing never blocks
+/// the tick, messages not fitting the local queue are dropped and counted.
+pub struct KafkaOutput {
+    producer: Box<dyn Producer>,
+    dropped: AtomicU64,
+}
+
+impl std::fmt::Debug for KafkaOutput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KafkaOutput")
+            .field("dropped", &self.dropped)
+            .finish_non_exhaustive()
+    }
+}
+
+impl KafkaOutput {
+    pub fn new(producer: Box<dyn Producer>) -> KafkaOutput {
+        KafkaOutput {
+            producer,
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Reads `KAFKA_BROKERS`, `KAFKA_TOPIC` and `KAFKA_QUEUE_SIZE` from `lookup`, returns
+    /// `None` if no brokers are configured.
+    pub fn from_lookup(
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<Option<KafkaOutput>, KafkaConfigError> {
+        let Some(brokers) = lookup("KAFKA_BROKERS") else {
+            return Ok(None);
+        };
+        let topic = lookup("KAFKA_TOPIC").ok_or(KafkaConfigError::MissingTopic)?;
+        let queue_size = match lookup("KAFKA_QUEUE_SIZE") {
+            Some(size) => size.parse()?,
+            None => DEFAULT_QUEUE_SIZE,
+        };
+        let producer = KafkaProducer::new(&brokers, topic, queue_size)?;
+        Ok(Some(KafkaOutput::new(Box::new(producer))))
+    }
+
+    /// Queues the `event`, a message dropped as the queue is full is counted.
+    pub fn publish(&self, event: &ForecastEvent) -> Result<(), ProduceError> {
+        let message = Message {
+            key: event.slug.clone(),
+            payload: serde_json::to_vec(event)?,
+            headers: vec![
+                ("collector-version", version::VERSION.to_string()),
+                ("tick-id", event.tick_id.to_string()),
+            ],
+        };
+        let result = self.producer.send(message);
+        if result.is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        result
+    }
+
+    /// Messages dropped since the start.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    pub fn flush(&self, timeout: Duration) -> Result<(), ProduceError> {
+        self.producer.flush(timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::Horizon;
+    use parking_lot::Mutex;
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+
+    #[derive(Default)]
+    struct MockProducer {
+        capacity: usize,
+        sent: Arc<Mutex<Vec<Message>>>,
+    }
+
+    impl Producer for MockProducer {
+        fn send(&self, message: Message) -> Result<(), ProduceError> {
+            let mut sent = self.sent.lock();
+            if sent.len() >= self.capacity {
+                return Err(ProduceError::QueueFull);
+            }
+            sent.push(message);
+            Ok(())
+        }
+
+        fn flush(&self, _: Duration) -> Result<(), ProduceError> {
+            Ok(())
+        }
+    }
+
+    fn event(tick_id: u64) -> ForecastEvent {
+        ForecastEvent {
+            location: "WW Großenkneten".to_string(),
+            slug: "ww-grossenkneten".to_string(),
+            model: "vorhersage".to_string(),
+            issued: "2024-03-07 08:05".to_string(),
+            current: Horizon {
+                time: "2024-03-07 08:00".to_string(),
+                value: 412.0,
+            },
+            forecasts: BTreeMap::from([("2024-03-07 09:00".to_string(), 398.0)]),
+            fetched_at: "2024-03-07T07:06:00Z".to_string(),
+            tick_id,
+        }
+    }
+
+    #[test]
+    fn publishes_keyed_events() {
+        let sent = Arc::default();
+        let producer = MockProducer {
+            capacity: 1,
+            sent: Arc::clone(&sent),
+        };
+        let output = KafkaOutput::new(Box::new(producer));
+
+        output.publish(&event(7)).unwrap();
+        let message = sent.lock()[0].clone();
+        assert_eq!(message.key, "ww-grossenkneten");
+        assert_eq!(
+            message.headers,
+            [
+                ("collector-version", version::VERSION.to_string()),
+                ("tick-id", "7".to_string()),
+            ]
+        );
+        let payload: serde_json::Value = serde_json::from_slice(&message.payload).unwrap();
+        assert_eq!(
+            payload,
+            serde_json::json!({
+                "location": "WW Großenkneten",
+                "slug": "ww-grossenkneten",
+                "model": "vorhersage",
+                "issued": "2024-03-07 08:05",
+                "current": {"time": "2024-03-07 08:00", "value": 412},
+                "forecasts": {"2024-03-07 09:00": 398},
+                "fetched_at": "2024-03-07T07:06:00Z",
+                "tick_id": 7,
+            })
+        );
+
+        // a full queue drops the message instead of waiting
+        assert!(matches!(
+            output.publish(&event(8)),
+            Err(ProduceError::QueueFull)
+        ));
+        assert_eq!(output.dropped(), 1);
+        assert_eq!(sent.lock().len(), 1);
+    }
+
+    #[test]
+    fn config_from_lookup() {
+        assert!(KafkaOutput::from_lookup(|_| None).unwrap().is_none());
+        let missing = KafkaOutput::from_lookup(|key| match key {
+            "KAFKA_BROKERS" => Some("localhost:9092".to_string()),
+            _ => None,
+        });
+        assert!(matches!(missing, Err(KafkaConfigError::MissingTopic)));
+
+        // creating the producer does not connect yet
+        let output = KafkaOutput::from_lookup(|key| match key {
+            "KAFKA_BROKERS" => Some("127.0.0.1:1".to_string()),
+            "KAFKA_TOPIC" => Some("forecasts".to_string()),
+            "KAFKA_QUEUE_SIZE" => Some("2".to_string()),
+            _ => None,
+        });
+        assert!(output.unwrap().is_some());
+    }
+}
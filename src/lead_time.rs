@@ -0,0 +1,172 @@
+use crate::timestamp::{self, TimestampError};
+use chrono::{DateTime, Duration, TimeZone};
+use chrono_tz::Tz;
+
+/// Coarse lead time of a forecast horizon, tagged so grouping by it stays cheap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LeadBucket {
+    UpToOneHour,
+    UpToThreeHours,
+    UpToSixHours,
+    Longer,
+}
+
+impl LeadBucket {
+    pub fn of(minutes: i64) -> LeadBucket {
+        match minutes {
+            ..=60 => LeadBucket::UpToOneHour,
+            61..=180 => LeadBucket::UpToThreeHours,
+            181..=360 => LeadBucket::UpToSixHours,
+            _ => LeadBucket::Longer,
+        }
+    }
+
+    /// Value of the `lead_bucket` tag.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LeadBucket::UpToOneHour => "0-1h",
+            LeadBucket::UpToThreeHours => "1-3h",
+            LeadBucket::UpToSixHours => "3-6h",
+            LeadBucket::Longer => "6h+",
+        }
+    }
+}
+
+/// How far ahead of its issue time a forecast horizon is, for verifying the forecast skill.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LeadTime {
+    pub minutes: i64,
+    pub bucket: LeadBucket,
+}
+
+/// Computes the lead times of the horizons of the forecasts.
+///
+/// The swat api states its times in local time, so they are compared in the `timezone` to get
+/// the lead times right across daylight saving changes. Horizons before the issue time, which
+/// the api occasionally sends as the first entry, are clamped to a lead time of 0 and counted.
+#[derive(Debug)]
+pub struct LeadTimes {
+    timezone: Tz,
+    clamped: u64,
+}
+
+impl Default for LeadTimes {
+    /// Lead times in German local time, which the swat api states its times in.
+    fn default() -> Self {
+        LeadTimes::new(Tz::Europe__Berlin)
+    }
+}
+
+impl LeadTimes {
+    pub fn new(timezone: Tz) -> LeadTimes {
+        LeadTimes {
+            timezone,
+            clamped: 0,
+        }
+    }
+
+    /// The lead time of the horizon at `target` of the forecast `issued`.
+    pub fn lead_time(&mut self, issued: &str, target: &str) -> Result<LeadTime, TimestampError> {
+        let minutes = (self.parse(target)? - self.parse(issued)?).num_minutes();
+        let minutes = match minutes < 0 {
+            true => {
+                self.clamped += 1;
+                0
+            }
+            false => minutes,
+        };
+        Ok(LeadTime {
+            minutes,
+            bucket: LeadBucket::of(minutes),
+        })
+    }
+
+    /// Horizons before their issue time clamped so far, reported as a warning.
+    pub fn clamped(&self) -> u64 {
+        self.clamped
+    }
+
+    fn parse(&self, time: &str) -> Result<DateTime<Tz>, TimestampError> {
+        let (time, _) = timestamp::parse(time)?;
+        // the earlier of a repeated hour, a time skipped in spring is taken as standard time
+        let local = |time| self.timezone.from_local_datetime(&time).earliest();
+        Ok(local(time)
+            .or_else(|| Some(local(time - Duration::hours(1))? + Duration::hours(1)))
+            .expect("daylight saving skips at most an hour"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn minutes(lead_times: &mut LeadTimes, issued: &str, target: &str) -> i64 {
+        lead_times.lead_time(issued, target).unwrap().minutes
+    }
+
+    #[test]
+    fn across_daylight_saving() {
+        let mut lead_times = LeadTimes::new(Tz::Europe__Berlin);
+
+        // on the same day it is the plain difference
+        assert_eq!(
+            minutes(&mut lead_times, "2024-03-07 08:05", "2024-03-07 11:05"),
+            180
+        );
+
+        // 02:00 to 03:00 is skipped in spring
+        assert_eq!(
+            minutes(&mut lead_times, "2024-03-31 01:30", "2024-03-31 03:30"),
+            60
+        );
+        // and 02:00 to 03:00 repeats in autumn
+        assert_eq!(
+            minutes(&mut lead_times, "2024-10-27 01:30", "2024-10-27 03:30"),
+            180
+        );
+
+        // in UTC every hour counts
+        let mut utc = LeadTimes::new(Tz::UTC);
+        assert_eq!(
+            minutes(&mut utc, "2024-03-31 01:30", "2024-03-31 03:30"),
+            120
+        );
+        assert_eq!(lead_times.clamped() + utc.clamped(), 0);
+
+        // a skipped time is an hour later than the one before the change
+        assert_eq!(
+            minutes(&mut lead_times, "2024-03-31 01:30", "2024-03-31 02:30"),
+            60
+        );
+    }
+
+    #[test]
+    fn clamps_horizons_before_issue() {
+        let mut lead_times = LeadTimes::new(Tz::Europe__Berlin);
+        let lead_time = lead_times
+            .lead_time("2024-03-07 08:05", "2024-03-07 08:00")
+            .unwrap();
+        assert_eq!(
+            lead_time,
+            LeadTime {
+                minutes: 0,
+                bucket: LeadBucket::UpToOneHour
+            }
+        );
+        assert_eq!(lead_times.clamped(), 1);
+        assert!(lead_times
+            .lead_time("2024-03-07", "2024-03-07 08:00")
+            .is_err());
+    }
+
+    #[test]
+    fn buckets() {
+        let buckets: Vec<_> = [0, 60, 61, 180, 181, 360, 361, 2000]
+            .map(|minutes| LeadBucket::of(minutes).as_str())
+            .into();
+        assert_eq!(
+            buckets,
+            ["0-1h", "0-1h", "1-3h", "1-3h", "3-6h", "3-6h", "6h+", "6h+"]
+        );
+    }
+}
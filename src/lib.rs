@@ -0,0 +1,105 @@
+//! Collects the forecasts of the SWAT api into InfluxDB and alerts about failures via Discord.
+//!
+//! Besides the `swat-collector` binary, the parts of the collection are usable on their own:
+//!
+//! - [`swat`] requests and parses the forecasts of the SWAT api,
+//! - [`points`] builds the InfluxDB points of the forecasts,
+//! - [`notify`] delivers alerts, via the [`notify::Notifier`] trait,
+//! - `health` tracks the health signals of a collector, with the `health-check` feature.
+//!
+//! Everything else is internal to the binary and may change with any release.
+
+use std::time::Duration;
+
+#[macro_use]
+mod logging;
+
+#[doc(hidden)]
+pub mod app;
+#[cfg(feature = "archive")]
+mod archive;
+mod backoff;
+mod bounded_cache;
+mod canary;
+#[cfg(feature = "chaos")]
+mod chaos;
+mod circuit_breaker;
+mod clock;
+mod config;
+mod content_hash;
+mod coordinates;
+mod doctor;
+mod duplicates;
+mod effective_config;
+mod egress;
+mod env_file;
+mod error_kind;
+#[cfg(any(feature = "kafka", feature = "nats"))]
+mod event;
+mod fields;
+mod fixture;
+mod gap_watchdog;
+mod gaps;
+mod geo;
+mod groups;
+#[cfg(feature = "grpc")]
+mod grpc;
+#[cfg(feature = "health-check")]
+pub mod health;
+#[cfg(feature = "health-check")]
+mod health_check;
+mod history;
+mod horizons;
+mod http;
+mod http_client;
+mod import;
+mod incident;
+mod instance;
+mod issues;
+mod janitor;
+#[cfg(feature = "kafka")]
+mod kafka;
+mod lead_time;
+mod live;
+mod locations;
+mod maintenance;
+mod names;
+#[cfg(feature = "nats")]
+mod nats;
+pub mod notify;
+mod parse_failures;
+mod pipeline;
+mod point_rejections;
+pub mod points;
+// used by the benchmarks
+#[doc(hidden)]
+pub mod processing;
+mod profiles;
+mod read_only;
+mod redact;
+mod retry;
+mod schema;
+mod schema_mode;
+mod severity;
+mod shared_requests;
+mod sink;
+mod skipped_ticks;
+mod snooze;
+mod spool;
+mod spread;
+mod startup;
+mod state;
+mod state_file;
+pub mod swat;
+mod swat_auth;
+mod tick_budget;
+mod tick_report;
+mod tick_stats;
+mod timestamp;
+mod trigger;
+mod values;
+mod version;
+mod webhook;
+
+/// Interval of the collection loop while the swat api is reachable.
+const COLLECTION_INTERVAL: Duration = Duration::from_secs(120);
@@ -0,0 +1,140 @@
+use crate::locations::{Forecast, Target};
+use crate::values;
+use futures::{SinkExt, StreamExt};
+use serde::Serialize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
+use warp::ws::{Message, WebSocket};
+
+/// Events buffered per client unless `WS_BUFFER` is set, clients falling further behind are
+/// disconnected.
+pub const DEFAULT_BUFFER: usize = 64;
+
+/// Clients connected at once unless `WS_MAX_CLIENTS` is set.
+pub const DEFAULT_MAX_CLIENTS: usize = 16;
+
+/// A successfully fetched forecast as pushed to the live clients.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LiveEvent {
+    pub location: String,
+    pub slug: String,
+    pub model: String,
+    pub issued: String,
+    #[serde(serialize_with = "values::serialize_exact")]
+    pub current: f64,
+    pub horizons: usize,
+}
+
+impl LiveEvent {
+    pub fn new(target: Target, forecast: &Forecast) -> LiveEvent {
+        LiveEvent {
+            location: target.location.name.to_string(),
+            slug: target.location.slug(),
+            model: target.model.name.clone(),
+            issued: forecast.from.clone(),
+            current: forecast.current.1,
+            horizons: forecast.forecasts.len(),
+        }
+    }
+
+    /// Whether the event is about the `location`, given by its name or slug.
+    fn concerns(&self, location: &str) -> bool {
+        self.location == location || self.slug == location
+    }
+}
+
+/// Pushes every fetched forecast to the connected websocket clients.
+///
+/// Publishing never waits for the clients, a client not keeping up with the last `buffer`
+/// events is disconnected instead. At most `max_clients` are connected at once.
+#[derive(Debug)]
+pub struct LiveFeed {
+    sender: broadcast::Sender<LiveEvent>,
+    clients: Arc<AtomicUsize>,
+    max_clients: usize,
+}
+
+impl LiveFeed {
+    pub fn new(buffer: usize, max_clients: usize) -> LiveFeed {
+        LiveFeed {
+            sender: broadcast::channel(buffer.max(1)).0,
+            clients: Arc::default(),
+            max_clients,
+        }
+    }
+
+    pub fn publish(&self, event: LiveEvent) {
+        // no receivers only means no client is connected
+        let _ = self.sender.send(event);
+    }
+
+    #[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+    pub fn clients(&self) -> usize {
+        self.clients.load(Ordering::SeqCst)
+    }
+
+    #[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+    pub fn max_clients(&self) -> usize {
+        self.max_clients
+    }
+
+    /// Takes a client slot, `None` if all are taken.
+    pub fn connect(&self) -> Option<Client> {
+        self.clients
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |clients| {
+                (clients < self.max_clients).then_some(clients + 1)
+            })
+            .ok()?;
+        Some(Client {
+            events: self.sender.subscribe(),
+            clients: self.clients.clone(),
+        })
+    }
+}
+
+/// A connected client, its slot is freed on drop.
+#[derive(Debug)]
+pub struct Client {
+    events: broadcast::Receiver<LiveEvent>,
+    clients: Arc<AtomicUsize>,
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.clients.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Client {
+    /// Streams the events to the `socket`, only those about `location` if given, until the
+    /// client disconnects or falls behind.
+    pub async fn stream(mut self, socket: WebSocket, location: Option<String>) {
+        let (mut sink, mut incoming) = socket.split();
+        loop {
+            let event = tokio::select! {
+                event = self.events.recv() => event,
+                message = incoming.next() => match message {
+                    Some(Ok(message)) if !message.is_close() => continue,
+                    _ => return,
+                },
+            };
+            let message = match event {
+                Ok(event) if location.as_deref().is_some_and(|l| !event.concerns(l)) => continue,
+                Ok(event) => match serde_json::to_string(&event) {
+                    Ok(json) => Message::text(json),
+                    Err(_) => continue,
+                },
+                Err(RecvError::Lagged(missed)) => {
+                    let reason = format!("missed {missed} events, reconnect to catch up");
+                    let _ = sink.send(Message::close_with(1008u16, reason)).await;
+                    return;
+                }
+                Err(RecvError::Closed) => return,
+            };
+            if sink.send(message).await.is_err() {
+                return;
+            }
+        }
+    }
+}
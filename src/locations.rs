@@ -1,82 +1,376 @@
+use crate::coordinates::{Coordinates, CoordinatesError};
+use crate::egress::{self, Class};
+use crate::error_kind::NetworkFailure;
+use crate::processing::RawValues;
+use crate::timestamp::{self, TimestampError};
+use crate::values::Reading;
+use once_cell::sync::Lazy;
 use reqwest::Client as ReqwestClient;
-use serde::de::Error;
-use serde::{Deserialize, Deserializer};
+use serde::Deserialize;
 use static_toml::static_toml;
 use std::collections::BTreeMap;
+use std::env;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
 use thiserror::Error;
 
+mod api;
+mod geojson;
+mod models;
+mod slug;
+
+pub use api::{parse_forecast_v2, ApiNegotiation, ApiNegotiationError, ApiVersion, Extra};
+pub use geojson::GeoJsonError;
+pub use models::{Model, Models, Target, DEFAULT_MODEL};
+pub use slug::check_unique as check_unique_slugs;
+
 static_toml! {
     #[static_toml(values_ident = Location)]
-    #[derive(Debug)]
+    #[derive(Debug, Clone)]
     pub static LOCATIONS = include_toml!("locations.toml");
 }
 
 pub use locations::locations::location::Location;
 
-#[derive(Debug, serde::Deserialize)]
+/// The locations of `locations.toml` and, if `LOCATIONS_GEOJSON` is set, those of the Point
+/// features in the GeoJSON file it names.
+pub static CONFIGURED: Lazy<&'static [Location]> = Lazy::new(|| {
+    let Ok(path) = env::var("LOCATIONS_GEOJSON") else {
+        return &LOCATIONS.locations;
+    };
+    let locations = with_geojson(Path::new(&path))
+        .unwrap_or_else(|err| panic!("expected \"LOCATIONS_GEOJSON\" to be valid, {err}"));
+    locations.leak()
+});
+
+/// The locations of `locations.toml` followed by those of the GeoJSON file at `path`.
+pub fn with_geojson(path: &Path) -> Result<Vec<Location>, GeoJsonError> {
+    geojson::load(path).and_then(|added| geojson::union(&LOCATIONS.locations, added))
+}
+
+/// Base url of the SWAT api, overridable via `SWAT_API_URL`.
+pub const DEFAULT_API_URL: &str = "https://swat.itwh.de";
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(try_from = "RawForecast")]
+#[non_exhaustive]
 pub struct Forecast {
-    #[serde(rename(deserialize = "vorhersageZeit"))]
     pub from: String,
 
+    // only read via the `Debug` output unless archived
+    #[cfg_attr(not(feature = "archive"), allow(dead_code))]
     pub lat: f64,
+    #[cfg_attr(not(feature = "archive"), allow(dead_code))]
     pub lon: f64,
 
-    #[serde(
-        rename(deserialize = "aktuell"),
-        deserialize_with = "deserialize_current_forecast"
-    )]
-    pub current: (String, u32),
+    pub current: (String, f64),
+
+    pub forecasts: BTreeMap<String, f64>,
+
+    /// Times of the forecasts dropped for not being finite, like `"NaN"`.
+    pub rejected: Vec<String>,
+
+    /// Version of the swat api the forecast was requested from.
+    pub api_version: ApiVersion,
 
-    #[serde(rename(deserialize = "vorhersage"))]
-    pub forecasts: BTreeMap<String, u32>,
+    /// Values the api sends beyond the forecast, written as additional fields.
+    pub extras: BTreeMap<String, Extra>,
+
+    /// The values as sent, if kept while [processing](crate::processing) them.
+    pub raw: Option<RawValues>,
+}
+
+/// A [Forecast] as sent by the swat api.
+#[derive(Deserialize)]
+struct RawForecast {
+    #[serde(rename = "vorhersageZeit")]
+    from: String,
+    lat: f64,
+    lon: f64,
+    #[serde(rename = "aktuell")]
+    current: BTreeMap<String, Reading>,
+    #[serde(rename = "vorhersage")]
+    forecasts: BTreeMap<String, Reading>,
 }
 
-fn deserialize_current_forecast<'de, D>(deserializer: D) -> Result<(String, u32), D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let map = BTreeMap::deserialize(deserializer)?;
-    let entry = map
-        .into_iter()
-        .next()
-        .ok_or(D::Error::custom("expected at least one element"))?;
-    Ok(entry)
+impl TryFrom<RawForecast> for Forecast {
+    type Error = String;
+
+    fn try_from(raw: RawForecast) -> Result<Self, Self::Error> {
+        Forecast::from_readings(
+            (raw.from, raw.lat, raw.lon),
+            raw.current.into_iter().next(),
+            raw.forecasts,
+        )
+    }
 }
 
 #[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum RequestLocationError {
+    #[error("resolving the host failed, {0}")]
+    Dns(reqwest::Error),
+
+    #[error("tls handshake failed, {0}")]
+    TlsHandshake(reqwest::Error),
+
+    #[error("connecting timed out, {0}")]
+    ConnectTimeout(reqwest::Error),
+
+    #[error("connection reset, {0}")]
+    ConnectionReset(reqwest::Error),
+
+    /// Any other failure of the request, like error statuses or the response timing out.
     #[error("request failed, {0}")]
-    Request(#[from] reqwest::Error),
+    Request(reqwest::Error),
+
+    /// The swat api refused the credentials of `SWAT_AUTH`, or asks for some if unset.
+    #[error("swat api answered {0}, check the credentials of \"SWAT_AUTH\"")]
+    Unauthorized(reqwest::StatusCode),
 
     #[error("parsing failed, {error}")]
     Parse {
         error: serde_json::Error,
         from: String,
     },
+
+    /// Injected by the `CHAOS_CONFIG` instead of requesting the forecast.
+    #[cfg(feature = "chaos")]
+    #[error("synthetic {0}, injected by CHAOS_CONFIG")]
+    Synthetic(crate::chaos::Fault),
+}
+
+impl From<reqwest::Error> for RequestLocationError {
+    fn from(mut err: reqwest::Error) -> Self {
+        if let Some(url) = err.url_mut() {
+            crate::redact::url(url);
+        }
+        match NetworkFailure::of(&err) {
+            NetworkFailure::Dns => RequestLocationError::Dns(err),
+            NetworkFailure::TlsHandshake => RequestLocationError::TlsHandshake(err),
+            NetworkFailure::ConnectTimeout => RequestLocationError::ConnectTimeout(err),
+            NetworkFailure::ConnectionReset => RequestLocationError::ConnectionReset(err),
+            NetworkFailure::Other => RequestLocationError::Request(err),
+        }
+    }
+}
+
+impl Forecast {
+    /// The forecast of v1 of the swat api, issued `from` at `lat` and `lon`, dropping the
+    /// forecasts that are not finite.
+    fn from_readings(
+        (from, lat, lon): (String, f64, f64),
+        current: Option<(String, Reading)>,
+        forecasts: impl IntoIterator<Item = (String, Reading)>,
+    ) -> Result<Forecast, String> {
+        let current = match current {
+            Some((time, Reading::Finite(value))) => (time, value),
+            Some((time, Reading::NonFinite)) => {
+                return Err(format!("expected a finite current value at {time:?}"))
+            }
+            None => return Err("expected at least one element".to_string()),
+        };
+        let mut rejected = Vec::new();
+        let forecasts = forecasts
+            .into_iter()
+            .filter_map(|(time, reading)| match reading {
+                Reading::Finite(value) => Some((time, value)),
+                Reading::NonFinite => {
+                    rejected.push(time);
+                    None
+                }
+            })
+            .collect();
+        Ok(Forecast {
+            from,
+            lat,
+            lon,
+            current,
+            forecasts,
+            rejected,
+            api_version: ApiVersion::V1,
+            extras: BTreeMap::new(),
+            raw: None,
+        })
+    }
+
+    /// Rewrites the issue time and the times of the horizons in the usual format, fails if any
+    /// of them matches none of the known formats.
+    pub fn normalize_timestamps(&mut self) -> Result<(), TimestampError> {
+        timestamp::normalize(&mut self.from)?;
+        timestamp::normalize(&mut self.current.0)?;
+        self.forecasts = std::mem::take(&mut self.forecasts)
+            .into_iter()
+            .map(|(mut time, value)| {
+                timestamp::normalize(&mut time)?;
+                Ok((time, value))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(())
+    }
 }
 
 impl Location {
+    /// Normalized name for tags, file names and topics, the `name` stays for humans.
+    pub fn slug(&self) -> String {
+        slug::slugify(self.name)
+    }
+
+    /// The validated coordinates, checked for every location at startup.
+    pub fn coordinates(&self) -> Result<Coordinates, CoordinatesError> {
+        Coordinates::parse(self.lat, self.lon)
+    }
+
+    /// Latitude and longitude as written into tags and urls, as configured if they are invalid
+    /// which the collector refuses at startup.
+    pub fn coordinate_texts(&self) -> (String, String) {
+        match self.coordinates() {
+            Ok(coordinates) => (coordinates.lat_text(), coordinates.lon_text()),
+            Err(_) => (self.lat.to_string(), self.lon.to_string()),
+        }
+    }
+
+    pub fn forecast_url(&self, api_url: &str, model: &Model) -> String {
+        let (lat, lon) = self.coordinate_texts();
+        format!("{api_url}{}?lat={lat}&lon={lon}", model.path)
+    }
+
     pub async fn request_forecast(
         &self,
         client: &ReqwestClient,
+        api_url: &str,
+        model: &Model,
     ) -> Result<Forecast, RequestLocationError> {
-        let Location { lat, lon, .. } = self;
-
-        let response = client
-            .get(format!(
-                "https://swat.itwh.de/Vorhersage?lat={lat}&lon={lon}"
-            ))
-            .send()
-            .await?;
+        let request = client.get(self.forecast_url(api_url, model));
+        let response = egress::METER.send(Class::Swat, request).await?;
+        check_authorized(response.status())?;
 
         let text = response.text().await?;
+        parse_forecast(text)
+    }
+}
+
+/// Fails for the statuses the swat api refuses the credentials with, which are not retried.
+pub(crate) fn check_authorized(status: reqwest::StatusCode) -> Result<(), RequestLocationError> {
+    match status {
+        reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN => {
+            Err(RequestLocationError::Unauthorized(status))
+        }
+        _ => Ok(()),
+    }
+}
+
+pub fn parse_forecast(text: String) -> Result<Forecast, RequestLocationError> {
+    match serde_json::from_str(&text) {
+        Ok(forecast) => Ok(forecast),
+        Err(err) => Err(RequestLocationError::Parse {
+            error: err,
+            from: text,
+        }),
+    }
+}
+
+/// Where the forecasts are collected from.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ForecastSource {
+    /// The swat api at `url`, in the version negotiated by `api`.
+    Api {
+        client: ReqwestClient,
+        url: String,
+        api: Arc<ApiNegotiation>,
+    },
+
+    /// Captured response bodies replayed round-robin, for running offline.
+    Fixtures {
+        bodies: Vec<String>,
+        next: AtomicUsize,
+    },
+}
 
-        match serde_json::from_str(&text) {
-            Ok(forecast) => Ok(forecast),
-            Err(err) => Err(RequestLocationError::Parse {
-                error: err,
-                from: text,
-            }),
+impl ForecastSource {
+    pub fn fixtures(bodies: Vec<String>) -> ForecastSource {
+        ForecastSource::Fixtures {
+            bodies,
+            next: AtomicUsize::new(0),
         }
     }
+
+    /// Probes the api for the version to request if due, with the coordinates of `target`.
+    pub async fn negotiate(&self, target: Target<'_>, now: Instant) {
+        if let ForecastSource::Api { client, url, api } = self {
+            api.probe_if_due(client, url, target, now).await;
+        }
+    }
+
+    pub async fn forecast(&self, target: Target<'_>) -> Result<Forecast, RequestLocationError> {
+        match self {
+            ForecastSource::Api { client, url, api } => api.request(client, url, target).await,
+            ForecastSource::Fixtures { bodies, next } => {
+                let i = next.fetch_add(1, Ordering::Relaxed) % bodies.len();
+                parse_forecast(bodies[i].clone())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body(current: &str, forecasts: &str) -> String {
+        format!(
+            r#"{{"vorhersageZeit": "2024-05-01 12:05", "lat": 52.9, "lon": 8.2,
+                "aktuell": {{"2024-05-01 12:00": {current}}},
+                "vorhersage": {{{forecasts}}}}}"#
+        )
+    }
+
+    #[test]
+    fn parses_integers_and_floats() {
+        let forecast = parse_forecast(body(
+            "412",
+            r#""2024-05-01 13:00": 412.5, "2024-05-01 14:00": 398"#,
+        ))
+        .unwrap();
+        assert_eq!(forecast.current, ("2024-05-01 12:00".to_string(), 412.0));
+        assert_eq!(
+            forecast.forecasts.values().copied().collect::<Vec<_>>(),
+            [412.5, 398.0]
+        );
+        assert!(forecast.rejected.is_empty());
+
+        // no lenient mode, numbers in strings fail the forecast
+        for current in [r#""412.5""#, r#""412""#] {
+            let err = parse_forecast(body(current, "")).unwrap_err();
+            assert!(
+                err.to_string().contains("expected a number"),
+                "{current}: {err}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_non_finite_entries() {
+        let forecast = parse_forecast(body(
+            "412.25",
+            r#""2024-05-01 13:00": "NaN", "2024-05-01 14:00": 398.5,
+               "2024-05-01 15:00": "Infinity""#,
+        ))
+        .unwrap();
+        assert_eq!(
+            forecast.forecasts,
+            BTreeMap::from([("2024-05-01 14:00".to_string(), 398.5)])
+        );
+        assert_eq!(forecast.rejected, ["2024-05-01 13:00", "2024-05-01 15:00"]);
+
+        // a forecast without current value is useless
+        let err = parse_forecast(body(r#""NaN""#, "")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "parsing failed, expected a finite current value at \"2024-05-01 12:00\""
+        );
+    }
 }
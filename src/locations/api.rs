@@ -0,0 +1,386 @@
+use super::{check_authorized, Forecast, RequestLocationError, Target};
+use crate::egress::{self, Class};
+use crate::logging;
+use crate::values::Reading;
+use parking_lot::RwLock;
+use reqwest::{Client as ReqwestClient, StatusCode};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// Hours after which the v2 api is probed again, after falling back to v1 or finding it.
+pub const PROBE_INTERVAL_HOURS: u64 = 24;
+
+const PROBE_INTERVAL: Duration = Duration::from_secs(PROBE_INTERVAL_HOURS * 60 * 60);
+
+/// Version of the swat api the forecasts are requested from.
+///
+/// v2 serves the forecasts below `/v2` with the coordinates in a `position` and the values as
+/// lists of `zeit` and `wert`. Values beyond those are kept as [`Extra`]s.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ApiVersion {
+    #[default]
+    V1,
+    V2,
+}
+
+impl fmt::Display for ApiVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiVersion::V1 => f.write_str("v1"),
+            ApiVersion::V2 => f.write_str("v2"),
+        }
+    }
+}
+
+/// A value of a forecast of the v2 api beyond those of v1, like the unit of the values.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Extra {
+    Number(f64),
+    Text(String),
+    Flag(bool),
+}
+
+impl Extra {
+    /// The `value` unless it is no single value, like a list or an object.
+    fn of(value: serde_json::Value) -> Option<Extra> {
+        match value {
+            serde_json::Value::Number(number) => number.as_f64().map(Extra::Number),
+            serde_json::Value::String(text) => Some(Extra::Text(text)),
+            serde_json::Value::Bool(flag) => Some(Extra::Flag(flag)),
+            _ => None,
+        }
+    }
+}
+
+/// A [Forecast] as sent by v2 of the swat api.
+#[derive(Deserialize)]
+struct RawForecastV2 {
+    #[serde(rename = "vorhersageZeit")]
+    from: String,
+    position: Position,
+    #[serde(rename = "aktuell")]
+    current: Value,
+    #[serde(rename = "vorhersage")]
+    forecasts: Vec<Value>,
+    #[serde(flatten)]
+    extras: BTreeMap<String, serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct Position {
+    lat: f64,
+    lon: f64,
+}
+
+#[derive(Deserialize)]
+struct Value {
+    #[serde(rename = "zeit")]
+    time: String,
+    #[serde(rename = "wert")]
+    value: Reading,
+}
+
+impl TryFrom<RawForecastV2> for Forecast {
+    type Error = String;
+
+    fn try_from(raw: RawForecastV2) -> Result<Self, Self::Error> {
+        let forecast = Forecast::from_readings(
+            (raw.from, raw.position.lat, raw.position.lon),
+            Some((raw.current.time, raw.current.value)),
+            (raw.forecasts.into_iter()).map(|value| (value.time, value.value)),
+        )?;
+        Ok(Forecast {
+            api_version: ApiVersion::V2,
+            extras: (raw.extras.into_iter())
+                .filter_map(|(name, value)| Some((name, Extra::of(value)?)))
+                .collect(),
+            ..forecast
+        })
+    }
+}
+
+/// Parses a response `text` of v2 of the swat api.
+pub fn parse_forecast_v2(text: String) -> Result<Forecast, RequestLocationError> {
+    let raw = serde_json::from_str::<RawForecastV2>(&text);
+    match raw.map(Forecast::try_from) {
+        Ok(Ok(forecast)) => Ok(forecast),
+        Ok(Err(err)) => Err(RequestLocationError::Parse {
+            error: serde::de::Error::custom(err),
+            from: text,
+        }),
+        Err(err) => Err(RequestLocationError::Parse {
+            error: err,
+            from: text,
+        }),
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("expected {0:?} to be valid, {1}")]
+pub struct ApiNegotiationError(&'static str, String);
+
+/// The version of the swat api requested by a profile, v2 if `PREFER_API_V2` is set and the
+/// api serves it, v1 otherwise.
+///
+/// v2 is probed on the first tick and again every [`PROBE_INTERVAL_HOURS`]. If v2 stops being
+/// served in between, the requests fall back to v1 right away.
+#[derive(Debug, Default)]
+pub struct ApiNegotiation {
+    prefer_v2: bool,
+    negotiated: RwLock<Negotiated>,
+}
+
+#[derive(Debug, Default)]
+struct Negotiated {
+    active: ApiVersion,
+    /// Monotonic, so a clock set backwards does not postpone the next probe.
+    probed: Option<Instant>,
+}
+
+impl ApiNegotiation {
+    pub fn from_lookup(
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<ApiNegotiation, ApiNegotiationError> {
+        let prefer_v2 = match lookup("PREFER_API_V2") {
+            Some(value) => value
+                .parse()
+                .map_err(|err| ApiNegotiationError("PREFER_API_V2", format!("{err}")))?,
+            None => false,
+        };
+        Ok(ApiNegotiation {
+            prefer_v2,
+            ..ApiNegotiation::default()
+        })
+    }
+
+    /// The version the forecasts are requested from.
+    pub fn active(&self) -> ApiVersion {
+        self.negotiated.read().active
+    }
+
+    /// Requests the forecast of `target` from the [active](ApiNegotiation::active) version,
+    /// falling back to v1 if v2 is not found anymore.
+    pub async fn request(
+        &self,
+        client: &ReqwestClient,
+        api_url: &str,
+        target: Target<'_>,
+    ) -> Result<Forecast, RequestLocationError> {
+        let Target { location, model } = target;
+        if self.active() == ApiVersion::V2 {
+            let url = location.forecast_url(&format!("{api_url}/v2"), model);
+            let response = egress::METER.send(Class::Swat, client.get(url)).await?;
+            check_authorized(response.status())?;
+            if response.status() != StatusCode::NOT_FOUND {
+                return parse_forecast_v2(response.text().await?);
+            }
+            self.fall_back(Instant::now());
+        }
+        location.request_forecast(client, api_url, model).await
+    }
+
+    /// Probes whether v2 is served if preferred and the last probe is due, with the
+    /// coordinates of `target`.
+    pub async fn probe_if_due(
+        &self,
+        client: &ReqwestClient,
+        api_url: &str,
+        target: Target<'_>,
+        now: Instant,
+    ) {
+        let due = match self.negotiated.read().probed {
+            Some(probed) => now.saturating_duration_since(probed) >= PROBE_INTERVAL,
+            None => true,
+        };
+        if !self.prefer_v2 || !due {
+            return;
+        }
+        let probed = probe(client, api_url, target).await;
+        let datetime = logging::datetime();
+        let active = match probed {
+            Ok(()) => {
+                log_eprintln!(
+                    "INFO  [{datetime}]: swat api v2 is available, requesting the forecasts from it"
+                );
+                ApiVersion::V2
+            }
+            Err(reason) => {
+                log_eprintln!(
+                    "INFO  [{datetime}]: swat api v2 is not available, {reason}, requesting v1 \
+                     until probing again in {PROBE_INTERVAL_HOURS} hours"
+                );
+                ApiVersion::V1
+            }
+        };
+        *self.negotiated.write() = Negotiated {
+            active,
+            probed: Some(now),
+        };
+    }
+
+    /// Requests v1 until the next probe, after v2 was not found at `now`.
+    fn fall_back(&self, now: Instant) {
+        let mut negotiated = self.negotiated.write();
+        if negotiated.active == ApiVersion::V1 {
+            return;
+        }
+        *negotiated = Negotiated {
+            active: ApiVersion::V1,
+            probed: Some(now),
+        };
+        let datetime = logging::datetime();
+        log_eprintln!(
+            "WARN  [{datetime}]: swat api v2 answered 404 Not Found, falling back to v1 until \
+             probing again in {PROBE_INTERVAL_HOURS} hours"
+        );
+    }
+
+    /// The version requested, and why not v2 if preferred.
+    #[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+    pub fn status_text(&self) -> String {
+        let negotiated = self.negotiated.read();
+        match (self.prefer_v2, negotiated.active, negotiated.probed) {
+            (true, ApiVersion::V1, Some(probed)) => format!(
+                "swat api: v1, v2 preferred but unavailable, probing again in {} minutes\n",
+                PROBE_INTERVAL
+                    .saturating_sub(probed.elapsed())
+                    .as_secs()
+                    .div_ceil(60)
+            ),
+            (true, ApiVersion::V1, None) => {
+                "swat api: v1, v2 preferred but not probed yet\n".into()
+            }
+            (_, active, _) => format!("swat api: {active}\n"),
+        }
+    }
+}
+
+/// Whether v2 serves a forecast for `target`, the reason if not.
+async fn probe(client: &ReqwestClient, api_url: &str, target: Target<'_>) -> Result<(), String> {
+    let Target { location, model } = target;
+    let url = location.forecast_url(&format!("{api_url}/v2"), model);
+    let response = egress::METER
+        .send(Class::Swat, client.get(url))
+        .await
+        .map_err(|err| RequestLocationError::from(err).to_string())?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!("it answered {status}"));
+    }
+    let text = response.text().await.map_err(|err| err.to_string())?;
+    parse_forecast_v2(text)
+        .map(drop)
+        .map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locations::{parse_forecast, Model, LOCATIONS};
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+    use warp::Filter;
+
+    const V1: &str = include_str!("../../tests/fixtures/location-1.body.json");
+    const V2: &str = include_str!("../../tests/fixtures/v2/location-1.body.json");
+
+    #[test]
+    fn maps_v2_onto_forecast() {
+        let v1 = parse_forecast(V1.to_string()).unwrap();
+        let v2 = parse_forecast_v2(V2.to_string()).unwrap();
+        assert_eq!(v1.api_version, ApiVersion::V1);
+        assert_eq!(v2.api_version, ApiVersion::V2);
+        assert_eq!(
+            (&v2.from, v2.lat, v2.lon, &v2.current, &v2.forecasts),
+            (&v1.from, v1.lat, v1.lon, &v1.current, &v1.forecasts)
+        );
+        assert!(v1.extras.is_empty());
+        assert_eq!(v2.extras["einheit"], Extra::Text("mm".to_string()));
+
+        // a v1 response is no v2 forecast
+        assert!(parse_forecast_v2(V1.to_string()).is_err());
+    }
+
+    /// A swat api serving v1 and, while `v2` is set, v2, counting the requests of each.
+    fn mock_api(v2: Arc<Mutex<bool>>) -> (String, Arc<Mutex<(usize, usize)>>) {
+        let requests = Arc::new(Mutex::new((0, 0)));
+        let v1_route = warp::path("Vorhersage").map({
+            let requests = requests.clone();
+            move || {
+                requests.lock().0 += 1;
+                warp::reply::with_status(V1, warp::http::StatusCode::OK)
+            }
+        });
+        let v2_route = warp::path!("v2" / "Vorhersage").map({
+            let requests = requests.clone();
+            move || {
+                requests.lock().1 += 1;
+                match *v2.lock() {
+                    true => warp::reply::with_status(V2, warp::http::StatusCode::OK),
+                    false => warp::reply::with_status("", warp::http::StatusCode::NOT_FOUND),
+                }
+            }
+        });
+        let (addr, server) = warp::serve(v2_route.or(v1_route)).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        (format!("http://{addr}"), requests)
+    }
+
+    #[tokio::test]
+    async fn negotiates_and_falls_back() {
+        let v2 = Arc::new(Mutex::new(true));
+        let (url, requests) = mock_api(v2.clone());
+        let client = ReqwestClient::new();
+        let model = Model::default_model();
+        let target = Target {
+            location: &LOCATIONS.locations[0],
+            model: &model,
+        };
+        let now = Instant::now();
+
+        // v1 unless preferred
+        let api = ApiNegotiation::from_lookup(|_| None).unwrap();
+        api.probe_if_due(&client, &url, target, now).await;
+        assert_eq!(api.active(), ApiVersion::V1);
+        assert_eq!(*requests.lock(), (0, 0));
+        assert_eq!(api.status_text(), "swat api: v1\n");
+
+        let api =
+            ApiNegotiation::from_lookup(|key| (key == "PREFER_API_V2").then(|| "true".to_string()))
+                .unwrap();
+        assert_eq!(
+            api.status_text(),
+            "swat api: v1, v2 preferred but not probed yet\n"
+        );
+        api.probe_if_due(&client, &url, target, now).await;
+        assert_eq!(api.active(), ApiVersion::V2);
+        let forecast = api.request(&client, &url, target).await.unwrap();
+        assert_eq!(forecast.api_version, ApiVersion::V2);
+        assert_eq!(*requests.lock(), (0, 2));
+        assert_eq!(api.status_text(), "swat api: v2\n");
+
+        // v2 disappearing mid-run falls back within the same request
+        *v2.lock() = false;
+        let forecast = api.request(&client, &url, target).await.unwrap();
+        assert_eq!(forecast.api_version, ApiVersion::V1);
+        assert_eq!(api.active(), ApiVersion::V1);
+        assert_eq!(*requests.lock(), (1, 3));
+        assert_eq!(
+            api.status_text(),
+            "swat api: v1, v2 preferred but unavailable, probing again in 1440 minutes\n"
+        );
+
+        // and returns with the next probe, not before
+        *v2.lock() = true;
+        api.probe_if_due(&client, &url, target, Instant::now())
+            .await;
+        assert_eq!(api.active(), ApiVersion::V1);
+        let later = Instant::now() + PROBE_INTERVAL;
+        api.probe_if_due(&client, &url, target, later).await;
+        assert_eq!(api.active(), ApiVersion::V2);
+        assert_eq!(*requests.lock(), (1, 4));
+    }
+}
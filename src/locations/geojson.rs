@@ -0,0 +1,276 @@
+use super::Location;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::{fs, io};
+use thiserror::Error;
+
+/// Names of the coordinate reference system GeoJSON is in, WGS 84 in lon/lat order.
+///
+/// RFC 7946 dropped the `crs` member, older files may still name it.
+const LON_LAT_CRS: [&str; 3] = [
+    "urn:ogc:def:crs:OGC:1.3:CRS84",
+    "urn:ogc:def:crs:OGC::CRS84",
+    "EPSG:4326",
+];
+
+#[derive(Debug, Error)]
+pub enum GeoJsonError {
+    #[error("could not read locations, {0}")]
+    Io(#[from] io::Error),
+
+    #[error("invalid GeoJSON, {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("expected a \"FeatureCollection\", found {0:?}")]
+    Collection(String),
+
+    #[error("expected coordinates in WGS 84, found crs {0:?}")]
+    Crs(String),
+
+    #[error("feature {index}: {reason}")]
+    Feature { index: usize, reason: String },
+
+    #[error("location {0:?} is configured twice")]
+    Name(String),
+
+    #[error("location id {0} is configured twice")]
+    Id(i64),
+}
+
+#[derive(Deserialize)]
+struct RawCollection {
+    #[serde(rename = "type")]
+    kind: String,
+    crs: Option<RawCrs>,
+    #[serde(default)]
+    features: Vec<RawFeature>,
+}
+
+#[derive(Deserialize)]
+struct RawCrs {
+    properties: RawCrsProperties,
+}
+
+#[derive(Deserialize)]
+struct RawCrsProperties {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct RawFeature {
+    id: Option<Value>,
+    geometry: Option<RawGeometry>,
+    #[serde(default)]
+    properties: RawProperties,
+}
+
+#[derive(Deserialize)]
+struct RawGeometry {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    coordinates: Value,
+}
+
+/// The properties mapped onto a [`Location`], any others are left to the GIS.
+#[derive(Default, Deserialize)]
+struct RawProperties {
+    id: Option<i64>,
+    name: Option<String>,
+    #[serde(default)]
+    group: String,
+    enabled: Option<bool>,
+}
+
+/// Reads the locations of the Point features in the FeatureCollection at `path`.
+pub fn load(path: &Path) -> Result<Vec<Location>, GeoJsonError> {
+    parse(&fs::read_to_string(path)?)
+}
+
+/// The locations of the Point features in the FeatureCollection `geojson`, skipping those with
+/// `enabled` set to false.
+///
+/// Every feature needs a `name` property and a numeric id, either the id of the feature or an
+/// `id` property, as the id is written with every point.
+pub fn parse(geojson: &str) -> Result<Vec<Location>, GeoJsonError> {
+    let collection: RawCollection = serde_json::from_str(geojson)?;
+    if collection.kind != "FeatureCollection" {
+        return Err(GeoJsonError::Collection(collection.kind));
+    }
+    if let Some(RawCrs { properties }) = collection.crs {
+        if !LON_LAT_CRS.contains(&properties.name.as_str()) {
+            return Err(GeoJsonError::Crs(properties.name));
+        }
+    }
+    let mut locations = Vec::new();
+    for (index, feature) in collection.features.into_iter().enumerate() {
+        let feature_error = |reason: String| GeoJsonError::Feature { index, reason };
+        if feature.properties.enabled == Some(false) {
+            continue;
+        }
+        let (lon, lat) = point(feature.geometry).map_err(feature_error)?;
+        let id = match (feature.id, feature.properties.id) {
+            (_, Some(id)) => id,
+            (Some(Value::Number(id)), None) => id
+                .as_i64()
+                .ok_or_else(|| feature_error(format!("expected an integer id, found {id}")))?,
+            _ => return Err(feature_error("expected a numeric id".to_string())),
+        };
+        let name = match feature.properties.name {
+            Some(name) if !name.trim().is_empty() => name,
+            _ => return Err(feature_error("expected a \"name\" property".to_string())),
+        };
+        locations.push(Location {
+            group: leak(feature.properties.group),
+            id,
+            lat: leak(lat.to_string()),
+            lon: leak(lon.to_string()),
+            name: leak(name),
+        });
+    }
+    Ok(locations)
+}
+
+/// Longitude and latitude of a Point `geometry`, in this order as GeoJSON has them.
+fn point(geometry: Option<RawGeometry>) -> Result<(f64, f64), String> {
+    let Some(geometry) = geometry else {
+        return Err("expected a Point geometry, found none".to_string());
+    };
+    if geometry.kind != "Point" {
+        return Err(format!(
+            "expected a Point geometry, found {:?}",
+            geometry.kind
+        ));
+    }
+    let position: Vec<f64> = serde_json::from_value(geometry.coordinates)
+        .map_err(|err| format!("expected coordinates of numbers, {err}"))?;
+    let [lon, lat, ..] = position[..] else {
+        return Err("expected a longitude and latitude".to_string());
+    };
+    if !(-180.0..=180.0).contains(&lon) || !(-90.0..=90.0).contains(&lat) {
+        return Err(format!(
+            "expected longitude and latitude in this order, found [{lon}, {lat}]"
+        ));
+    }
+    Ok((lon, lat))
+}
+
+/// The locations live as long as the collector, like those of `locations.toml`.
+fn leak(value: String) -> &'static str {
+    Box::leak(value.into_boxed_str())
+}
+
+/// The `configured` locations followed by the `added` ones, failing if a name or id is taken
+/// twice.
+pub fn union(configured: &[Location], added: Vec<Location>) -> Result<Vec<Location>, GeoJsonError> {
+    let mut names = BTreeSet::new();
+    let mut ids = BTreeSet::new();
+    let locations: Vec<_> = configured.iter().cloned().chain(added).collect();
+    for location in &locations {
+        if !names.insert(location.name) {
+            return Err(GeoJsonError::Name(location.name.to_string()));
+        }
+        if !ids.insert(location.id) {
+            return Err(GeoJsonError::Id(location.id));
+        }
+    }
+    Ok(locations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locations::LOCATIONS;
+
+    const FIXTURE: &str = include_str!("../../tests/fixtures/locations.geojson");
+
+    /// The fixture without its last feature, the MultiPolygon.
+    fn without_polygon() -> String {
+        let mut collection: serde_json::Value = serde_json::from_str(FIXTURE).unwrap();
+        collection["features"].as_array_mut().unwrap().pop();
+        collection.to_string()
+    }
+
+    fn error(geojson: &str) -> String {
+        parse(geojson).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn parses_points() {
+        let locations = parse(&without_polygon()).unwrap();
+        let names: Vec<_> = locations.iter().map(|location| location.name).collect();
+        assert_eq!(names, ["Brunnen Ahlhorn", "Brunnen Dötlingen"]);
+
+        // GeoJSON has the longitude first
+        let ahlhorn = &locations[0];
+        assert_eq!((ahlhorn.lat, ahlhorn.lon), ("52.9041278", "8.2341716"));
+        assert_eq!((ahlhorn.id, ahlhorn.group), (101, "Großenkneten"));
+        let doetlingen = &locations[1];
+        assert_eq!((doetlingen.id, doetlingen.group), (102, ""));
+    }
+
+    #[test]
+    fn rejects_other_geometries() {
+        assert_eq!(
+            error(FIXTURE),
+            "feature 3: expected a Point geometry, found \"MultiPolygon\""
+        );
+        let feature = |properties: &str, geometry: &str| {
+            format!(
+                r#"{{"type": "FeatureCollection", "features": [{{"type": "Feature",
+                "properties": {properties}, "geometry": {geometry}}}]}}"#
+            )
+        };
+        let point = r#"{"type": "Point", "coordinates": [8.23, 52.91]}"#;
+        assert_eq!(
+            error(&feature(r#"{"id": 1}"#, point)),
+            "feature 0: expected a \"name\" property"
+        );
+        assert_eq!(
+            error(&feature(r#"{"name": "Brunnen"}"#, point)),
+            "feature 0: expected a numeric id"
+        );
+        let swapped = r#"{"type": "Point", "coordinates": [52.91, 98.23]}"#;
+        assert_eq!(
+            error(&feature(r#"{"id": 1, "name": "Brunnen"}"#, swapped)),
+            "feature 0: expected longitude and latitude in this order, found [52.91, 98.23]"
+        );
+        assert_eq!(
+            error(&feature(r#"{"id": 1, "name": "Brunnen"}"#, "null")),
+            "feature 0: expected a Point geometry, found none"
+        );
+        assert_eq!(
+            error(r#"{"type": "Feature"}"#),
+            "expected a \"FeatureCollection\", found \"Feature\""
+        );
+        let projected = r#"{"type": "FeatureCollection", "features": [],
+            "crs": {"type": "name", "properties": {"name": "EPSG:25832"}}}"#;
+        assert_eq!(
+            error(projected),
+            "expected coordinates in WGS 84, found crs \"EPSG:25832\""
+        );
+    }
+
+    #[test]
+    fn unites_with_configured() {
+        let added = parse(&without_polygon()).unwrap();
+        let locations = union(&LOCATIONS.locations, added.clone()).unwrap();
+        assert_eq!(locations.len(), LOCATIONS.locations.len() + 2);
+        assert_eq!(locations.last().unwrap().name, "Brunnen Dötlingen");
+
+        let mut taken = added.clone();
+        taken[0].name = LOCATIONS.locations[0].name;
+        assert_eq!(
+            union(&LOCATIONS.locations, taken).unwrap_err().to_string(),
+            "location \"WW Großenkneten\" is configured twice"
+        );
+        let mut taken = added;
+        taken[1].id = taken[0].id;
+        assert_eq!(
+            union(&LOCATIONS.locations, taken).unwrap_err().to_string(),
+            "location id 101 is configured twice"
+        );
+    }
+}
@@ -0,0 +1,216 @@
+use super::Location;
+use std::collections::BTreeMap;
+use std::fmt;
+use thiserror::Error;
+
+/// Name of the forecast model every location is collected for unless configured otherwise.
+pub const DEFAULT_MODEL: &str = "vorhersage";
+
+/// Forecast product of the swat api, requested at its `path` with the coordinates of a
+/// location.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Model {
+    pub name: String,
+    pub path: String,
+}
+
+impl Model {
+    pub fn default_model() -> Model {
+        Model {
+            name: DEFAULT_MODEL.to_string(),
+            path: "/Vorhersage".to_string(),
+        }
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.name == DEFAULT_MODEL
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ModelsError {
+    #[error("{key}, expected models in the form of `name=/path,...`, got {value:?}")]
+    Format { key: String, value: String },
+
+    #[error("{key}, model {name:?} is listed twice")]
+    Duplicate { key: String, name: String },
+}
+
+/// The models collected per location.
+///
+/// Every location is collected for the models in `FORECAST_MODELS` unless
+/// `FORECAST_MODELS_<SLUG>` lists its own, with the slug in upper case and `_` as separator.
+/// Both take a list like `vorhersage=/Vorhersage,nowcast=/Nowcast`, without either only the
+/// [`DEFAULT_MODEL`] is collected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Models {
+    default: Vec<Model>,
+    locations: BTreeMap<i64, Vec<Model>>,
+}
+
+impl Models {
+    pub fn from_lookup(
+        lookup: impl Fn(&str) -> Option<String>,
+        locations: &[Location],
+    ) -> Result<Models, ModelsError> {
+        let default = match lookup("FORECAST_MODELS") {
+            Some(value) => parse_list("FORECAST_MODELS", &value)?,
+            None => vec![Model::default_model()],
+        };
+
+        let mut by_location = BTreeMap::new();
+        for location in locations {
+            let key =
+                format!("FORECAST_MODELS_{}", location.slug().to_uppercase()).replace('-', "_");
+            if let Some(value) = lookup(&key) {
+                by_location.insert(location.id, parse_list(&key, &value)?);
+            }
+        }
+
+        Ok(Models {
+            default,
+            locations: by_location,
+        })
+    }
+
+    pub fn of(&self, location: &Location) -> &[Model] {
+        self.locations.get(&location.id).unwrap_or(&self.default)
+    }
+
+    /// Every pair of location and model to collect.
+    pub fn targets<'l>(&'l self, locations: &'l [Location]) -> Vec<Target<'l>> {
+        locations
+            .iter()
+            .flat_map(|location| {
+                self.of(location)
+                    .iter()
+                    .map(move |model| Target { location, model })
+            })
+            .collect()
+    }
+}
+
+impl Default for Models {
+    fn default() -> Self {
+        Models {
+            default: vec![Model::default_model()],
+            locations: BTreeMap::new(),
+        }
+    }
+}
+
+fn parse_list(key: &str, value: &str) -> Result<Vec<Model>, ModelsError> {
+    let mut models: Vec<Model> = Vec::new();
+    for entry in value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+    {
+        let model = entry
+            .split_once('=')
+            .map(|(name, path)| (name.trim(), path.trim()))
+            .filter(|(name, path)| !name.is_empty() && path.starts_with('/'))
+            .map(|(name, path)| Model {
+                name: name.to_string(),
+                path: path.to_string(),
+            })
+            .ok_or_else(|| ModelsError::Format {
+                key: key.to_string(),
+                value: value.to_string(),
+            })?;
+        if models.iter().any(|m| m.name == model.name) {
+            return Err(ModelsError::Duplicate {
+                key: key.to_string(),
+                name: model.name,
+            });
+        }
+        models.push(model);
+    }
+
+    match models.is_empty() {
+        true => Err(ModelsError::Format {
+            key: key.to_string(),
+            value: value.to_string(),
+        }),
+        false => Ok(models),
+    }
+}
+
+/// A location together with one of its models, what a single forecast is collected for.
+#[derive(Debug, Clone, Copy)]
+pub struct Target<'l> {
+    pub location: &'l Location,
+    pub model: &'l Model,
+}
+
+/// The location name, followed by the model unless it is the [`DEFAULT_MODEL`].
+impl fmt::Display for Target<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.model.is_default() {
+            true => f.write_str(self.location.name),
+            false => write!(f, "{} ({})", self.location.name, self.model.name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locations::LOCATIONS;
+
+    #[test]
+    fn models_per_location() {
+        let locations = &LOCATIONS.locations[..3];
+        let models = Models::from_lookup(
+            |key| match key {
+                "FORECAST_MODELS" => Some("vorhersage=/Vorhersage, nowcast=/Nowcast".to_string()),
+                "FORECAST_MODELS_WW_MARIENHAFE" => Some("nowcast=/Nowcast".to_string()),
+                _ => None,
+            },
+            locations,
+        )
+        .unwrap();
+
+        let targets: Vec<_> = models
+            .targets(locations)
+            .iter()
+            .map(|target| target.to_string())
+            .collect();
+        assert_eq!(
+            targets,
+            [
+                "WW Großenkneten",
+                "WW Großenkneten (nowcast)",
+                "WW Marienhafe (nowcast)",
+                "WW Thülsfelde",
+                "WW Thülsfelde (nowcast)",
+            ]
+        );
+
+        let models = Models::from_lookup(|_| None, locations).unwrap();
+        assert_eq!(models, Models::default());
+        assert_eq!(models.targets(locations).len(), 3);
+    }
+
+    #[test]
+    fn invalid_models() {
+        let parse = |value: &str| {
+            let value = value.to_string();
+            Models::from_lookup(
+                move |key| (key == "FORECAST_MODELS").then(|| value.clone()),
+                &[],
+            )
+        };
+        assert!(matches!(parse("nowcast"), Err(ModelsError::Format { .. })));
+        assert!(matches!(
+            parse("nowcast=Nowcast"),
+            Err(ModelsError::Format { .. })
+        ));
+        assert!(matches!(parse(" , "), Err(ModelsError::Format { .. })));
+        let err = parse("a=/A,a=/B").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "FORECAST_MODELS, model \"a\" is listed twice"
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use super::Location;
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// Slugs are cut to this many bytes, at a separator if possible.
+const MAX_SLUG_LENGTH: usize = 48;
+
+#[derive(Debug, Error)]
+#[error("locations {first:?} and {second:?} both normalize to {slug:?}")]
+pub struct SlugCollisionError {
+    first: &'static str,
+    second: &'static str,
+    slug: String,
+}
+
+/// Normalizes a location `name` into a slug usable in tags, file names and topics.
+///
+/// The slug is lowercase ASCII with `-` separating words, German umlauts and `ß` are
+/// transliterated, other characters separate words.
+pub fn slugify(name: &str) -> String {
+    let mut slug = String::with_capacity(name.len());
+    let mut separate = false;
+    for c in name.chars().flat_map(char::to_lowercase) {
+        let replacement = match c {
+            'ä' => "ae",
+            'ö' => "oe",
+            'ü' => "ue",
+            'ß' => "ss",
+            c if c.is_ascii_alphanumeric() => {
+                if separate && !slug.is_empty() {
+                    slug.push('-');
+                }
+                separate = false;
+                slug.push(c);
+                continue;
+            }
+            _ => {
+                separate = true;
+                continue;
+            }
+        };
+        if separate && !slug.is_empty() {
+            slug.push('-');
+        }
+        separate = false;
+        slug.push_str(replacement);
+    }
+
+    if slug.len() > MAX_SLUG_LENGTH {
+        slug.truncate(MAX_SLUG_LENGTH);
+        if let Some(cut) = slug.rfind('-').filter(|cut| *cut > MAX_SLUG_LENGTH / 2) {
+            slug.truncate(cut);
+        }
+        slug = slug.trim_end_matches('-').to_string();
+    }
+    slug
+}
+
+/// Fails if two `locations` normalize to the same slug.
+pub fn check_unique(locations: &[Location]) -> Result<(), SlugCollisionError> {
+    let mut seen: HashMap<String, &'static str> = HashMap::new();
+    for location in locations {
+        let slug = slugify(location.name);
+        if let Some(first) = seen.insert(slug.clone(), location.name) {
+            return Err(SlugCollisionError {
+                first,
+                second: location.name,
+                slug,
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_names() {
+        assert_eq!(slugify("WW Großenkneten"), "ww-grossenkneten");
+        assert_eq!(slugify("WW Thülsfelde"), "ww-thuelsfelde");
+        assert_eq!(slugify("ÄÖÜ"), "aeoeue");
+        assert_eq!(slugify("Bad Zwischenahn / Nord"), "bad-zwischenahn-nord");
+        assert_eq!(slugify("  --Nord//Süd--  "), "nord-sued");
+        assert_eq!(slugify("Wasserwerk 💧 Ost"), "wasserwerk-ost");
+        assert_eq!(slugify("💧"), "");
+    }
+
+    #[test]
+    fn caps_length() {
+        let slug = slugify(&"Wasserwerk ".repeat(10));
+        assert!(slug.len() <= MAX_SLUG_LENGTH);
+        assert!(!slug.ends_with('-'));
+        assert!(slug.ends_with("wasserwerk"));
+    }
+
+    #[test]
+    fn detects_collisions() {
+        check_unique(&crate::locations::LOCATIONS.locations).unwrap();
+
+        let locations = Box::leak(Box::new([
+            Location {
+                group: "",
+                id: 1,
+                lat: "0",
+                lon: "0",
+                name: "Bad Zwischenahn / Nord",
+            },
+            Location {
+                group: "",
+                id: 2,
+                lat: "0",
+                lon: "0",
+                name: "bad zwischenahn nord",
+            },
+        ]));
+        let err = check_unique(locations).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "locations \"Bad Zwischenahn / Nord\" and \"bad zwischenahn nord\" both normalize to \
+             \"bad-zwischenahn-nord\""
+        );
+    }
+}
@@ -0,0 +1,377 @@
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, SecondsFormat, TimeZone, Utc};
+use once_cell::sync::{Lazy, OnceCell};
+use std::fmt::Display;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::{env, thread};
+use thiserror::Error;
+
+static LOGGER: OnceCell<Logger> = OnceCell::new();
+
+/// How the log lines are timestamped, configurable via `LOG_TIMEZONE` and `LOG_TIME_FORMAT`.
+pub static LOG_TIME: Lazy<LogTime> = Lazy::new(|| {
+    LogTime::from_lookup(|key| env::var(key).ok())
+        .unwrap_or_else(|err| panic!("invalid log time, {err}"))
+});
+
+#[cfg(test)]
+thread_local! {
+    static CAPTURED: std::cell::RefCell<Option<Vec<String>>> = const { std::cell::RefCell::new(None) };
+}
+
+/// Logs a line to stderr without blocking, see [`init`].
+macro_rules! log_eprintln {
+    ($($arg:tt)*) => {
+        $crate::logging::log(false, format!($($arg)*))
+    };
+}
+
+/// Logs a line to stdout without blocking, see [`init`].
+macro_rules! log_println {
+    ($($arg:tt)*) => {
+        $crate::logging::log(true, format!($($arg)*))
+    };
+}
+
+enum Message {
+    Line { stdout: bool, text: String },
+    Flush(SyncSender<()>),
+}
+
+/// Hands log lines to a dedicated writer thread, so a stalled log pipe cannot stall the
+/// collection.
+///
+/// Lines are dropped while the channel is full, the number of dropped lines is logged once the
+/// writer catches up again.
+struct Logger {
+    sender: SyncSender<Message>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl Logger {
+    fn spawn(
+        capacity: usize,
+        stdout: Box<dyn Write + Send>,
+        stderr: Box<dyn Write + Send>,
+    ) -> Logger {
+        let (sender, receiver) = mpsc::sync_channel(capacity.max(1));
+        let dropped = Arc::new(AtomicU64::new(0));
+        thread::Builder::new()
+            .name("log-writer".to_string())
+            .spawn({
+                let dropped = dropped.clone();
+                move || write_lines(receiver, &dropped, stdout, stderr)
+            })
+            .expect("could not spawn log writer thread");
+        Logger { sender, dropped }
+    }
+
+    fn log(&self, stdout: bool, text: String) {
+        match self.sender.try_send(Message::Line { stdout, text }) {
+            Ok(()) => (),
+            Err(TrySendError::Full(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            // the writer is gone, nothing left to log to
+            Err(TrySendError::Disconnected(_)) => (),
+        }
+    }
+
+    /// Waits until every line logged so far is written.
+    fn flush(&self) {
+        let (ack, acked) = mpsc::sync_channel(1);
+        if self.sender.send(Message::Flush(ack)).is_ok() {
+            let _ = acked.recv();
+        }
+    }
+}
+
+fn write_lines(
+    receiver: Receiver<Message>,
+    dropped: &AtomicU64,
+    mut stdout: Box<dyn Write + Send>,
+    mut stderr: Box<dyn Write + Send>,
+) {
+    for message in receiver {
+        match message {
+            Message::Line { stdout: true, text } => {
+                let _ = writeln!(stdout, "{text}");
+            }
+            Message::Line {
+                stdout: false,
+                text,
+            } => {
+                let _ = writeln!(stderr, "{text}");
+            }
+            Message::Flush(ack) => {
+                let _ = stdout.flush();
+                let _ = stderr.flush();
+                report_dropped(dropped, &mut stderr);
+                let _ = ack.send(());
+                continue;
+            }
+        }
+        report_dropped(dropped, &mut stderr);
+    }
+}
+
+fn report_dropped(dropped: &AtomicU64, stderr: &mut Box<dyn Write + Send>) {
+    let dropped = dropped.swap(0, Ordering::Relaxed);
+    if dropped > 0 {
+        let datetime = datetime();
+        let _ = writeln!(
+            stderr,
+            "WARN  [{datetime}]: log output stalled, dropped {dropped} log lines"
+        );
+    }
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum LogTimeError {
+    #[error("expected \"LOG_TIMEZONE\" to be utc or local, got {0:?}")]
+    Timezone(String),
+
+    #[error("expected \"LOG_TIME_FORMAT\" to be a strftime format, got {0:?}")]
+    Format(String),
+}
+
+/// Timezone the log lines are timestamped in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogTimezone {
+    Utc,
+
+    /// The timezone of the host, like of the logs of a proxy next to the collector.
+    Local,
+}
+
+/// How the log lines are timestamped.
+///
+/// By default in RFC 3339 with milliseconds in UTC, like `2024-05-01T12:00:00.250Z`, so lines
+/// can be related to the timestamps of writes and of other logs even during fast cascades of
+/// failures. `LOG_TIMEZONE=local` switches to the timezone of the host, `LOG_TIME_FORMAT`
+/// replaces the format with a strftime one like `%Y-%m-%d %H:%M:%S`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogTime {
+    timezone: LogTimezone,
+    format: Option<String>,
+}
+
+impl LogTime {
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<LogTime, LogTimeError> {
+        let timezone = match lookup("LOG_TIMEZONE") {
+            Some(timezone) => match timezone.trim().to_lowercase().as_str() {
+                "utc" => LogTimezone::Utc,
+                "local" => LogTimezone::Local,
+                _ => return Err(LogTimeError::Timezone(timezone)),
+            },
+            None => LogTimezone::Utc,
+        };
+        let format = match lookup("LOG_TIME_FORMAT") {
+            Some(format) if StrftimeItems::new(&format).any(|item| item == Item::Error) => {
+                return Err(LogTimeError::Format(format))
+            }
+            format => format,
+        };
+        Ok(LogTime { timezone, format })
+    }
+
+    /// The `time` as timestamp of a log line.
+    pub fn format(&self, time: DateTime<Utc>) -> String {
+        match self.timezone {
+            LogTimezone::Utc => self.format_in(time, &Utc),
+            LogTimezone::Local => self.format_in(time, &Local),
+        }
+    }
+
+    fn format_in<Tz: TimeZone>(&self, time: DateTime<Utc>, timezone: &Tz) -> String
+    where
+        Tz::Offset: Display,
+    {
+        let time = time.with_timezone(timezone);
+        match &self.format {
+            Some(format) => time.format(format).to_string(),
+            None => time.to_rfc3339_opts(SecondsFormat::Millis, true),
+        }
+    }
+}
+
+/// The timestamp of a line logged now, see [`LOG_TIME`].
+pub fn datetime() -> String {
+    LOG_TIME.format(Utc::now())
+}
+
+/// Routes the log macros through a writer thread buffering up to `capacity` lines.
+///
+/// Until this is called, the macros write directly.
+pub fn init(capacity: usize) {
+    LOGGER.get_or_init(|| Logger::spawn(capacity, Box::new(io::stdout()), Box::new(io::stderr())));
+}
+
+/// Logs the `text`, with the coordinates redacted on stderr, see [`crate::redact::text`].
+///
+/// Stdout only carries line protocol when running offline, which keeps them like InfluxDB.
+pub fn log(stdout: bool, text: String) {
+    let text = redacted(stdout, text, *crate::redact::REDACT_COORDINATES);
+    #[cfg(test)]
+    CAPTURED.with_borrow_mut(|captured| {
+        if let Some(lines) = captured {
+            lines.push(text.clone());
+        }
+    });
+
+    match (LOGGER.get(), stdout) {
+        (Some(logger), _) => logger.log(stdout, text),
+        (None, true) => println!("{text}"),
+        (None, false) => eprintln!("{text}"),
+    }
+}
+
+fn redacted(stdout: bool, text: String, redact: bool) -> String {
+    match stdout {
+        true => text,
+        false => crate::redact::text_with(&text, redact).into_owned(),
+    }
+}
+
+/// Writes out the buffered log lines, called before the collector exits.
+pub fn flush() {
+    if let Some(logger) = LOGGER.get() {
+        logger.flush();
+    }
+}
+
+/// Captures the lines logged on the current thread until [`take_captured`] is called.
+#[cfg(test)]
+pub fn capture() {
+    CAPTURED.set(Some(Vec::new()));
+}
+
+#[cfg(test)]
+pub fn take_captured() -> Vec<String> {
+    CAPTURED.take().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+    use std::time::{Duration, Instant};
+
+    /// Writer taking a while for every write, like a backed up pipe.
+    #[derive(Clone, Default)]
+    struct SlowWriter(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SlowWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            thread::sleep(Duration::from_millis(10));
+            self.0.lock().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn slow_writer_does_not_block() {
+        let (stdout, stderr) = (SlowWriter::default(), SlowWriter::default());
+        let logger = Logger::spawn(8, Box::new(stdout.clone()), Box::new(stderr.clone()));
+
+        let start = Instant::now();
+        for i in 0..100 {
+            logger.log(false, format!("line {i}"));
+        }
+        logger.log(true, "to stdout".to_string());
+        // writing all lines would take at least a second
+        assert!(start.elapsed() < Duration::from_millis(500));
+
+        logger.flush();
+        let stderr = String::from_utf8(stderr.0.lock().clone()).unwrap();
+        let written = stderr.lines().filter(|l| l.starts_with("line ")).count();
+        assert!(written < 100);
+        let reported: u64 = stderr
+            .lines()
+            .filter_map(|l| l.split("dropped ").nth(1)?.strip_suffix(" log lines"))
+            .map(|n| n.parse::<u64>().unwrap())
+            .sum();
+        let stdout = String::from_utf8(stdout.0.lock().clone()).unwrap();
+        let written = written + stdout.lines().count();
+        assert_eq!(written as u64 + reported, 101);
+    }
+
+    #[test]
+    fn captures_lines() {
+        capture();
+        log_eprintln!("ERROR [{}]: captured", 1);
+        assert_eq!(take_captured(), ["ERROR [1]: captured"]);
+        assert!(take_captured().is_empty());
+    }
+
+    #[test]
+    fn formats_log_times() {
+        let time = DateTime::parse_from_rfc3339("2024-05-01T12:00:00.250Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        // the timezone of the host stands in as a fixed offset
+        let local = chrono::FixedOffset::east_opt(2 * 60 * 60).unwrap();
+        let mut lines = Vec::new();
+        for (timezone, format) in [
+            ("utc", None),
+            ("utc", Some("%Y-%m-%d %H:%M:%S")),
+            ("local", None),
+            ("local", Some("%Y-%m-%d %H:%M:%S%.3f %:z")),
+        ] {
+            let log_time = LogTime::from_lookup(|key| match key {
+                "LOG_TIMEZONE" => Some(timezone.to_string()),
+                "LOG_TIME_FORMAT" => format.map(str::to_string),
+                _ => None,
+            })
+            .unwrap();
+            let datetime = match log_time.timezone {
+                LogTimezone::Utc => log_time.format(time),
+                LogTimezone::Local => log_time.format_in(time, &local),
+            };
+            lines.push(format!(
+                "INFO  [{datetime}] [tick #7]: collection pass triggered manually"
+            ));
+        }
+        insta::assert_snapshot!(lines.join("\n"));
+    }
+
+    #[test]
+    fn rejects_invalid_log_times() {
+        let default = LogTime::from_lookup(|_| None).unwrap();
+        assert_eq!(default.timezone, LogTimezone::Utc);
+        assert_eq!(default.format, None);
+        assert_eq!(
+            LogTime::from_lookup(|key| (key == "LOG_TIMEZONE").then(|| "Europe/Berlin".into())),
+            Err(LogTimeError::Timezone("Europe/Berlin".to_string()))
+        );
+        assert_eq!(
+            LogTime::from_lookup(|key| (key == "LOG_TIME_FORMAT").then(|| "%Y-%m-%d %!".into())),
+            Err(LogTimeError::Format("%Y-%m-%d %!".to_string()))
+        );
+    }
+
+    #[test]
+    fn redacts_coordinates_on_stderr() {
+        let body = "WARN  [2024-05-01 12:00] [tick #1]: could not parse the forecast of WW \
+                    Großenkneten, {\"lat\": 52.9109818816186, \"lon\": 8.23505277402053}";
+        assert!(crate::redact::has_precise_coordinates(body));
+        let logged = redacted(false, body.to_string(), true);
+        assert!(!crate::redact::has_precise_coordinates(&logged), "{logged}");
+        assert!(
+            logged.ends_with("{\"lat\": 52.9, \"lon\": 8.2}"),
+            "{logged}"
+        );
+        assert_eq!(redacted(false, body.to_string(), false), body);
+
+        // offline line protocol keeps the coordinates like InfluxDB
+        let line = "forecast,lat=52.9109818816186,lon=8.23505277402053 value=1i";
+        assert_eq!(redacted(true, line.to_string(), true), line);
+    }
+}
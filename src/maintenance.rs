@@ -0,0 +1,364 @@
+use chrono::{DateTime, Datelike, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
+use std::sync::atomic::{AtomicU64, Ordering};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum MaintenanceError {
+    #[error(
+        "expected maintenance windows in the form of `name=[days ]HH:MM-HH:MM` separated by \
+         semicolons, got {0:?}"
+    )]
+    Format(String),
+
+    #[error("unknown weekday {0:?}, expected one like `Tue` or a range like `Mon-Fri`")]
+    Weekday(String),
+
+    #[error("invalid time {0:?}, {1}")]
+    Time(String, #[source] chrono::ParseError),
+
+    #[error("maintenance window {0:?} is listed twice")]
+    Duplicate(String),
+
+    #[error("unknown timezone {0:?}")]
+    Timezone(String),
+}
+
+/// Recurring window of announced upstream maintenance, like `itwh=Tue 05:00-05:30`.
+///
+/// The window starts on the listed `days`, or every day without any, and may span midnight,
+/// ending on the following day then.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MaintenanceWindow {
+    pub name: String,
+    days: Vec<Weekday>,
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl MaintenanceWindow {
+    pub fn new(name: &str, days: Vec<Weekday>, start: NaiveTime, end: NaiveTime) -> Self {
+        MaintenanceWindow {
+            name: name.to_string(),
+            days,
+            start,
+            end,
+        }
+    }
+
+    fn starts_on(&self, day: Weekday) -> bool {
+        self.days.is_empty() || self.days.contains(&day)
+    }
+
+    /// Whether the wall clock `day` and `time` lie within the window.
+    ///
+    /// The start is inclusive, the end exclusive and equal start and end are never active,
+    /// like [`QuietHours`](crate::webhook::QuietHours).
+    fn contains(&self, day: Weekday, time: NaiveTime) -> bool {
+        match self.start <= self.end {
+            true => self.starts_on(day) && self.start <= time && time < self.end,
+            false => {
+                (self.starts_on(day) && self.start <= time)
+                    || (self.starts_on(day.pred()) && time < self.end)
+            }
+        }
+    }
+}
+
+/// The maintenance windows of `MAINTENANCE_WINDOWS`, evaluated on the wall clock of the
+/// `MAINTENANCE_TIMEZONE`.
+///
+/// Failures during a window are logged and counted, also on the [tick
+/// statistics](crate::tick_stats), but not alerted. The open incident is left as it is, so it
+/// neither resolves nor alerts because of the ticks in the window.
+///
+/// Windows follow the local time across daylight saving changes, a window in the skipped hour
+/// does not happen that day and one in the repeated hour happens twice.
+#[derive(Debug, Default)]
+pub struct Maintenance {
+    windows: Vec<MaintenanceWindow>,
+    timezone: Tz,
+
+    /// Failures during the windows since the start.
+    failures: AtomicU64,
+}
+
+impl Maintenance {
+    pub fn new(windows: Vec<MaintenanceWindow>, timezone: Tz) -> Maintenance {
+        Maintenance {
+            windows,
+            timezone,
+            failures: AtomicU64::new(0),
+        }
+    }
+
+    /// Reads `MAINTENANCE_WINDOWS` like `itwh=Tue 05:00-05:30; backup=Mon-Fri 23:45-00:15` and
+    /// `MAINTENANCE_TIMEZONE` (defaulting to UTC) from `lookup`.
+    pub fn from_lookup(
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<Maintenance, MaintenanceError> {
+        let timezone = match lookup("MAINTENANCE_TIMEZONE") {
+            Some(timezone) => timezone
+                .trim()
+                .parse()
+                .map_err(|_| MaintenanceError::Timezone(timezone))?,
+            None => Tz::UTC,
+        };
+        let Some(value) = lookup("MAINTENANCE_WINDOWS") else {
+            return Ok(Maintenance::new(Vec::new(), timezone));
+        };
+
+        let mut windows: Vec<MaintenanceWindow> = Vec::new();
+        for entry in value.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+            let window = parse_window(entry)?;
+            if windows.iter().any(|other| other.name == window.name) {
+                return Err(MaintenanceError::Duplicate(window.name));
+            }
+            windows.push(window);
+        }
+        Ok(Maintenance::new(windows, timezone))
+    }
+
+    pub fn windows(&self) -> &[MaintenanceWindow] {
+        &self.windows
+    }
+
+    /// The window `now` lies in, the first listed one if they overlap.
+    pub fn active(&self, now: DateTime<Utc>) -> Option<&MaintenanceWindow> {
+        let local = now.with_timezone(&self.timezone);
+        let (day, time) = (local.weekday(), local.time());
+        self.windows
+            .iter()
+            .find(|window| window.contains(day, time))
+    }
+
+    /// Counts the `failures` of a tick during a window, returns the failures since the start.
+    pub fn record(&self, failures: usize) -> u64 {
+        self.failures.fetch_add(failures as u64, Ordering::Relaxed) + failures as u64
+    }
+
+    #[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+    pub fn failures(&self) -> u64 {
+        self.failures.load(Ordering::Relaxed)
+    }
+
+    /// Like `in maintenance until 05:30 (itwh), 3 failures during maintenance so far` while a
+    /// window is active at `now`.
+    #[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+    pub fn status(&self, now: DateTime<Utc>) -> Option<String> {
+        let window = self.active(now)?;
+        Some(format!(
+            "in maintenance until {} ({}), {} failures during maintenance so far",
+            window.end.format("%H:%M"),
+            window.name,
+            self.failures()
+        ))
+    }
+}
+
+fn parse_window(entry: &str) -> Result<MaintenanceWindow, MaintenanceError> {
+    let format = || MaintenanceError::Format(entry.to_string());
+    let (name, schedule) = entry.split_once('=').ok_or_else(format)?;
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(format());
+    }
+    let schedule = schedule.trim();
+    let (days, times) = match schedule.starts_with(char::is_alphabetic) {
+        true => {
+            let (days, times) = schedule
+                .split_once(char::is_whitespace)
+                .ok_or_else(format)?;
+            (parse_days(days)?, times)
+        }
+        false => (Vec::new(), schedule),
+    };
+    let (start, end) = times.split_once('-').ok_or_else(format)?;
+    let parse = |time: &str| {
+        NaiveTime::parse_from_str(time.trim(), "%H:%M")
+            .map_err(|err| MaintenanceError::Time(time.trim().to_string(), err))
+    };
+    Ok(MaintenanceWindow::new(
+        name,
+        days,
+        parse(start)?,
+        parse(end)?,
+    ))
+}
+
+/// Days like `Tue`, `Mon,Thu` or `Mon-Fri`, a range may wrap around the week like `Sat-Mon`.
+fn parse_days(days: &str) -> Result<Vec<Weekday>, MaintenanceError> {
+    let parse = |day: &str| {
+        day.trim()
+            .parse::<Weekday>()
+            .map_err(|_| MaintenanceError::Weekday(day.trim().to_string()))
+    };
+    let mut parsed = Vec::new();
+    for part in days.split(',') {
+        match part.split_once('-') {
+            Some((first, last)) => {
+                let (mut day, last) = (parse(first)?, parse(last)?);
+                parsed.push(day);
+                while day != last {
+                    day = day.succ();
+                    parsed.push(day);
+                }
+            }
+            None => parsed.push(parse(part)?),
+        }
+    }
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn time(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    fn utc(month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, month, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    fn configured(windows: &str, timezone: &str) -> Maintenance {
+        Maintenance::from_lookup(|key| match key {
+            "MAINTENANCE_WINDOWS" => Some(windows.to_string()),
+            "MAINTENANCE_TIMEZONE" => Some(timezone.to_string()),
+            _ => None,
+        })
+        .unwrap()
+    }
+
+    fn active(maintenance: &Maintenance, now: DateTime<Utc>) -> Option<&str> {
+        maintenance.active(now).map(|window| window.name.as_str())
+    }
+
+    #[test]
+    fn parses_windows() {
+        let maintenance = configured(
+            " itwh = Tue 05:00-05:30; backup=Sat-Mon 23:45 - 00:15;daily=02:00-02:10;",
+            "Europe/Berlin",
+        );
+        assert_eq!(
+            maintenance.windows(),
+            [
+                MaintenanceWindow::new("itwh", vec![Weekday::Tue], time(5, 0), time(5, 30)),
+                MaintenanceWindow::new(
+                    "backup",
+                    vec![Weekday::Sat, Weekday::Sun, Weekday::Mon],
+                    time(23, 45),
+                    time(0, 15)
+                ),
+                MaintenanceWindow::new("daily", vec![], time(2, 0), time(2, 10)),
+            ]
+        );
+        assert_eq!(
+            Maintenance::from_lookup(|_| None).unwrap().windows(),
+            &[] as &[MaintenanceWindow]
+        );
+
+        let invalid = |windows: &str| {
+            Maintenance::from_lookup(|key| {
+                (key == "MAINTENANCE_WINDOWS").then(|| windows.to_string())
+            })
+            .unwrap_err()
+        };
+        assert!(matches!(
+            invalid("Tue 05:00-05:30"),
+            MaintenanceError::Format(_)
+        ));
+        assert!(matches!(
+            invalid("itwh=Tue 05:00"),
+            MaintenanceError::Format(_)
+        ));
+        assert!(matches!(
+            invalid("itwh=Tues,Thursday 05:00-05:30"),
+            MaintenanceError::Weekday(day) if day == "Thursday" || day == "Tues"
+        ));
+        assert!(matches!(
+            invalid("itwh=Tue 5-6"),
+            MaintenanceError::Time(..)
+        ));
+        assert_eq!(
+            invalid("a=01:00-02:00; a=03:00-04:00").to_string(),
+            "maintenance window \"a\" is listed twice"
+        );
+    }
+
+    #[test]
+    fn weekday_window() {
+        // 2024-05-07 is a tuesday
+        let maintenance = configured("itwh=Tue 05:00-05:30", "UTC");
+        assert_eq!(active(&maintenance, utc(5, 7, 4, 59)), None);
+        assert_eq!(active(&maintenance, utc(5, 7, 5, 0)), Some("itwh"));
+        assert_eq!(active(&maintenance, utc(5, 7, 5, 29)), Some("itwh"));
+        assert_eq!(active(&maintenance, utc(5, 7, 5, 30)), None);
+        assert_eq!(active(&maintenance, utc(5, 8, 5, 15)), None);
+        assert_eq!(
+            maintenance.status(utc(5, 7, 5, 10)).unwrap(),
+            "in maintenance until 05:30 (itwh), 0 failures during maintenance so far"
+        );
+        assert_eq!(maintenance.status(utc(5, 7, 6, 0)), None);
+    }
+
+    #[test]
+    fn window_spanning_midnight() {
+        // starting on sunday, ending on monday
+        let maintenance = configured("backup=Sun 23:30-00:30", "UTC");
+        assert_eq!(active(&maintenance, utc(5, 5, 23, 29)), None);
+        assert_eq!(active(&maintenance, utc(5, 5, 23, 30)), Some("backup"));
+        assert_eq!(active(&maintenance, utc(5, 6, 0, 29)), Some("backup"));
+        assert_eq!(active(&maintenance, utc(5, 6, 0, 30)), None);
+
+        // monday night does not start it, and the sunday start does not cover sunday morning
+        assert_eq!(active(&maintenance, utc(5, 6, 23, 45)), None);
+        assert_eq!(active(&maintenance, utc(5, 5, 0, 15)), None);
+
+        // the end on saturday after a saturday to sunday range wraps around the week
+        let maintenance = configured("weekend=Sat-Sun 22:00-01:00", "UTC");
+        assert_eq!(active(&maintenance, utc(5, 6, 0, 30)), Some("weekend"));
+        assert_eq!(active(&maintenance, utc(5, 4, 0, 30)), None);
+    }
+
+    #[test]
+    fn follows_daylight_saving_time() {
+        // 05:00 in Berlin is 04:00 UTC in winter and 03:00 UTC in summer
+        let maintenance = configured("itwh=05:00-05:30", "Europe/Berlin");
+        assert_eq!(active(&maintenance, utc(3, 30, 4, 10)), Some("itwh"));
+        assert_eq!(active(&maintenance, utc(3, 31, 3, 10)), Some("itwh"));
+        assert_eq!(active(&maintenance, utc(3, 31, 4, 10)), None);
+
+        // the clocks skip from 02:00 to 03:00 on 2024-03-31, the window does not happen
+        let skipped = configured("skipped=Sun 02:00-02:30", "Europe/Berlin");
+        for minute in (0..120).step_by(10) {
+            let now = utc(3, 31, 0, 0) + chrono::Duration::minutes(minute);
+            assert_eq!(active(&skipped, now), None, "{now}");
+        }
+
+        // and repeat 02:00 to 03:00 on 2024-10-27, the window happens twice
+        let repeated = configured("repeated=Sun 02:00-02:30", "Europe/Berlin");
+        assert_eq!(active(&repeated, utc(10, 27, 0, 15)), Some("repeated"));
+        assert_eq!(active(&repeated, utc(10, 27, 0, 45)), None);
+        assert_eq!(active(&repeated, utc(10, 27, 1, 15)), Some("repeated"));
+        assert_eq!(active(&repeated, utc(10, 27, 1, 45)), None);
+
+        // a window spanning midnight across the change keeps its wall clock times
+        let night = configured("night=Sat 23:00-03:30", "Europe/Berlin");
+        assert_eq!(active(&night, utc(3, 30, 21, 59)), None);
+        assert_eq!(active(&night, utc(3, 30, 22, 0)), Some("night"));
+        assert_eq!(active(&night, utc(3, 31, 1, 29)), Some("night"));
+        assert_eq!(active(&night, utc(3, 31, 1, 30)), None);
+    }
+
+    #[test]
+    fn counts_failures() {
+        let maintenance = Maintenance::default();
+        assert_eq!(maintenance.record(2), 2);
+        assert_eq!(maintenance.record(3), 5);
+        assert_eq!(maintenance.failures(), 5);
+    }
+}
@@ -0,0 +1,191 @@
+use once_cell::sync::Lazy;
+use std::collections::BTreeMap;
+use std::env;
+use thiserror::Error;
+
+/// Fields of the forecast points, the split `forecasts_<n>` fields follow `forecasts`.
+pub const FIELDS: [&str; 4] = ["current", "forecasts", "latitude", "longitude"];
+
+/// Tags of the forecast points.
+pub const TAGS: [&str; 13] = [
+    "id",
+    "name",
+    "slug",
+    "group",
+    "model",
+    "api_version",
+    "content_hash",
+    "lat",
+    "lon",
+    "stale_issue",
+    "short_forecast",
+    "grid_duplicates",
+    "serialize_error",
+];
+
+/// Names the forecast points are written with, configurable via `FIELD_NAME_MAP` and
+/// `TAG_NAME_MAP`.
+pub static NAMES: Lazy<NameMapping> = Lazy::new(|| {
+    NameMapping::from_lookup(|key| env::var(key).ok())
+        .unwrap_or_else(|err| panic!("invalid name mapping, {err}"))
+});
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum NameMappingError {
+    #[error("{key}, expected `name=new_name` entries separated by commas, got {entry:?}")]
+    Entry { key: &'static str, entry: String },
+
+    #[error("{key}, unknown name {name:?}, expected one of {known}")]
+    Unknown {
+        key: &'static str,
+        name: String,
+        known: String,
+    },
+
+    #[error("{key}, {name:?} is written more than once")]
+    Duplicate { key: &'static str, name: String },
+}
+
+/// Renames the fields and tags of the forecast points for dashboards expecting other names,
+/// like the ones of an older collector. Names not mapped stay as they are.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct NameMapping {
+    fields: BTreeMap<&'static str, String>,
+    tags: BTreeMap<&'static str, String>,
+}
+
+impl NameMapping {
+    /// Reads the mappings like `current=aktuell_wert,forecasts=vorhersage_json` from
+    /// `FIELD_NAME_MAP` and `TAG_NAME_MAP` which `lookup` returns.
+    pub fn from_lookup(
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<NameMapping, NameMappingError> {
+        let parse = |key, known: &[&'static str]| match lookup(key) {
+            Some(value) => parse(key, &value, known),
+            None => Ok(BTreeMap::new()),
+        };
+        Ok(NameMapping {
+            fields: parse("FIELD_NAME_MAP", &FIELDS)?,
+            tags: parse("TAG_NAME_MAP", &TAGS)?,
+        })
+    }
+
+    /// The name the field `name` is written with.
+    pub fn field<'a>(&'a self, name: &'a str) -> &'a str {
+        self.fields.get(name).map_or(name, String::as_str)
+    }
+
+    /// The name the tag `name` is written with.
+    pub fn tag<'a>(&'a self, name: &'a str) -> &'a str {
+        self.tags.get(name).map_or(name, String::as_str)
+    }
+}
+
+fn parse(
+    key: &'static str,
+    value: &str,
+    known: &[&'static str],
+) -> Result<BTreeMap<&'static str, String>, NameMappingError> {
+    let mut mapping = BTreeMap::new();
+    for entry in value.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let Some((name, renamed)) = entry
+            .split_once('=')
+            .map(|(name, renamed)| (name.trim(), renamed.trim()))
+            .filter(|(name, renamed)| !name.is_empty() && !renamed.is_empty())
+        else {
+            return Err(NameMappingError::Entry {
+                key,
+                entry: entry.to_string(),
+            });
+        };
+        let Some(name) = known.iter().find(|known| **known == name) else {
+            return Err(NameMappingError::Unknown {
+                key,
+                name: name.to_string(),
+                known: known.join(", "),
+            });
+        };
+        mapping.insert(*name, renamed.to_string());
+    }
+
+    // renaming onto a name still in use would merge two of them
+    let mut written: Vec<_> = known
+        .iter()
+        .map(|name| mapping.get(name).map_or(*name, String::as_str))
+        .collect();
+    written.sort_unstable();
+    if let Some(name) = written.windows(2).find(|pair| pair[0] == pair[1]) {
+        return Err(NameMappingError::Duplicate {
+            key,
+            name: name[0].to_string(),
+        });
+    }
+    Ok(mapping)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping(key: &'static str, value: &str) -> Result<NameMapping, NameMappingError> {
+        NameMapping::from_lookup(|lookup| (lookup == key).then(|| value.to_string()))
+    }
+
+    #[test]
+    fn identity_by_default() {
+        let names = NameMapping::from_lookup(|_| None).unwrap();
+        assert_eq!(names, NameMapping::default());
+        for field in FIELDS {
+            assert_eq!(names.field(field), field);
+        }
+        assert_eq!(names.tag("name"), "name");
+    }
+
+    #[test]
+    fn renames() {
+        let names = mapping(
+            "FIELD_NAME_MAP",
+            " current=aktuell_wert, forecasts = vorhersage_json,",
+        )
+        .unwrap();
+        assert_eq!(names.field("current"), "aktuell_wert");
+        assert_eq!(names.field("forecasts"), "vorhersage_json");
+        assert_eq!(names.field("latitude"), "latitude");
+        assert_eq!(names.tag("name"), "name");
+
+        let names = mapping("TAG_NAME_MAP", "name=standort").unwrap();
+        assert_eq!(names.tag("name"), "standort");
+        assert_eq!(names.field("current"), "current");
+    }
+
+    #[test]
+    fn invalid_mappings() {
+        assert_eq!(
+            mapping("FIELD_NAME_MAP", "curent=aktuell_wert")
+                .unwrap_err()
+                .to_string(),
+            "FIELD_NAME_MAP, unknown name \"curent\", \
+             expected one of current, forecasts, latitude, longitude"
+        );
+        assert!(matches!(
+            mapping("TAG_NAME_MAP", "current=aktuell_wert"),
+            Err(NameMappingError::Unknown { .. })
+        ));
+        assert!(matches!(
+            mapping("FIELD_NAME_MAP", "current"),
+            Err(NameMappingError::Entry { .. })
+        ));
+        assert!(matches!(
+            mapping("FIELD_NAME_MAP", "current="),
+            Err(NameMappingError::Entry { .. })
+        ));
+        assert_eq!(
+            mapping("TAG_NAME_MAP", "slug=name"),
+            Err(NameMappingError::Duplicate {
+                key: "TAG_NAME_MAP",
+                name: "name".to_string()
+            })
+        );
+    }
+}
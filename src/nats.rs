@@ -0,0 +1,352 @@
+use crate::egress::{self, Class, Transfer};
+use crate::event::ForecastEvent;
+use crate::spool::SpooledMessage;
+use async_nats::jetstream::{self, context::PublishError};
+use async_nats::ConnectOptions;
+use futures::future::BoxFuture;
+use futures::{FutureExt, StreamExt};
+use parking_lot::Mutex;
+use std::path::PathBuf;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Subjects are prefixed with this unless `NATS_SUBJECT_PREFIX` is set.
+pub const DEFAULT_SUBJECT_PREFIX: &str = "swat.forecasts";
+
+/// Publishes awaiting their ack at once unless `NATS_CONCURRENCY` is set.
+pub const DEFAULT_CONCURRENCY: usize = 8;
+
+#[derive(Debug, Error)]
+pub enum NatsConfigError {
+    #[error("expected {0:?} to be valid, {1}")]
+    Invalid(&'static str, String),
+
+    #[error("could not read credentials file, {0}")]
+    Credentials(#[from] std::io::Error),
+
+    #[error("could not connect, {0}")]
+    Connect(#[from] async_nats::ConnectError),
+}
+
+#[derive(Debug, Error)]
+pub enum NatsError {
+    #[error("publish failed, {0}")]
+    Publish(#[from] PublishError),
+
+    #[error("could not drain connection, {0}")]
+    Drain(#[from] async_nats::client::DrainError),
+}
+
+/// Connection settings read from `NATS_*`.
+///
+/// TLS is used for `tls://` urls, with `NATS_TLS=true` or with `NATS_TLS_CA` naming the root
+/// certificates to trust. `NATS_CREDENTIALS_FILE` authenticates with a `.creds` file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NatsConfig {
+    pub url: String,
+    pub subject_prefix: String,
+    pub concurrency: usize,
+    pub require_tls: bool,
+    pub tls_ca: Option<PathBuf>,
+    pub credentials_file: Option<PathBuf>,
+}
+
+impl NatsConfig {
+    /// Reads the configuration from `lookup`, returns `None` if `NATS_URL` is not set.
+    pub fn from_lookup(
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<Option<NatsConfig>, NatsConfigError> {
+        let Some(url) = lookup("NATS_URL") else {
+            return Ok(None);
+        };
+        fn parse<T: FromStr<Err: ToString>>(
+            key: &'static str,
+            value: Option<String>,
+            default: T,
+        ) -> Result<T, NatsConfigError> {
+            value.map_or(Ok(default), |value| {
+                value
+                    .parse()
+                    .map_err(|err: T::Err| NatsConfigError::Invalid(key, err.to_string()))
+            })
+        }
+
+        let subject_prefix = lookup("NATS_SUBJECT_PREFIX")
+            .map(|prefix| prefix.trim_end_matches('.').to_string())
+            .unwrap_or_else(|| DEFAULT_SUBJECT_PREFIX.to_string());
+        if subject_prefix.is_empty() || subject_prefix.contains([' ', '*', '>']) {
+            return Err(NatsConfigError::Invalid(
+                "NATS_SUBJECT_PREFIX",
+                format!("{subject_prefix:?} is no subject"),
+            ));
+        }
+        Ok(Some(NatsConfig {
+            require_tls: url.starts_with("tls://") || parse("NATS_TLS", lookup("NATS_TLS"), false)?,
+            url,
+            subject_prefix,
+            concurrency: parse(
+                "NATS_CONCURRENCY",
+                lookup("NATS_CONCURRENCY"),
+                DEFAULT_CONCURRENCY,
+            )?
+            .max(1),
+            tls_ca: lookup("NATS_TLS_CA").map(PathBuf::from),
+            credentials_file: lookup("NATS_CREDENTIALS_FILE").map(PathBuf::from),
+        }))
+    }
+
+    /// The subject the forecasts of the location with the `slug` are published to.
+    pub fn subject(&self, slug: &str) -> String {
+        format!("{}.{slug}", self.subject_prefix)
+    }
+}
+
+/// Seam between the output and JetStream, so tests can decide about the acks.
+pub trait Publisher: Send + Sync {
+    /// Publishes the `payload` to the `subject` and waits for JetStream's ack.
+    fn publish(&self, subject: String, payload: Vec<u8>) -> BoxFuture<'_, Result<(), NatsError>>;
+
+    /// Delivers what is still buffered and closes the connection.
+    fn close(&self) -> BoxFuture<'_, Result<(), NatsError>>;
+}
+
+pub struct JetStreamPublisher {
+    client: async_nats::Client,
+    context: jetstream::Context,
+}
+
+impl JetStreamPublisher {
+    pub async fn connect(config: &NatsConfig) -> Result<JetStreamPublisher, NatsConfigError> {
+        let mut options = match &config.credentials_file {
+            Some(path) => ConnectOptions::with_credentials_file(path).await?,
+            None => ConnectOptions::new(),
+        };
+        options = options
+            .name(format!("swat-collector/{}", crate::version::VERSION))
+            .require_tls(config.require_tls || config.tls_ca.is_some());
+        if let Some(ca) = &config.tls_ca {
+            options = options.add_root_certificates(ca.clone());
+        }
+        let client = options.connect(&config.url).await?;
+        Ok(JetStreamPublisher {
+            context: jetstream::new(client.clone()),
+            client,
+        })
+    }
+}
+
+impl Publisher for JetStreamPublisher {
+    fn publish(&self, subject: String, payload: Vec<u8>) -> BoxFuture<'_, Result<(), NatsError>> {
+        async move {
+            let size = subject.len() + payload.len();
+            egress::METER.record(Class::Other, Transfer::sent(size));
+            let ack = self.context.publish(subject, payload.into()).await?;
+            ack.await?;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn close(&self) -> BoxFuture<'_, Result<(), NatsError>> {
+        async move { Ok(self.client.drain().await?) }.boxed()
+    }
+}
+
+/// Publishes every successfully fetched forecast to JetStream with at-least-once semantics.
+///
+/// The forecasts of a tick are queued and published at its end, at most `concurrency` at once.
+/// Messages JetStream does not ack are spooled tagged with this output and replayed along with
+/// the next tick, so they may arrive twice but are not lost.
+pub struct NatsOutput {
+    config: NatsConfig,
+    publisher: Box<dyn Publisher>,
+    queued: Mutex<Vec<(String, Vec<u8>)>>,
+}
+
+impl std::fmt::Debug for NatsOutput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NatsOutput")
+            .field("config", &self.config)
+            .field("queued", &self.queued.lock().len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl NatsOutput {
+    pub fn new(config: NatsConfig, publisher: Box<dyn Publisher>) -> NatsOutput {
+        NatsOutput {
+            config,
+            publisher,
+            queued: Mutex::default(),
+        }
+    }
+
+    /// Queues the `event` for [`publish`](Self::publish).
+    pub fn queue(&self, event: &ForecastEvent) -> Result<(), serde_json::Error> {
+        let payload = serde_json::to_vec(event)?;
+        self.queued
+            .lock()
+            .push((self.config.subject(&event.slug), payload));
+        Ok(())
+    }
+
+    /// Publishes the `spooled` messages and the queued ones, returns those that failed to be
+    /// spooled again along with the last error.
+    pub async fn publish(
+        &self,
+        spooled: Vec<SpooledMessage>,
+    ) -> (Vec<SpooledMessage>, Option<NatsError>) {
+        let queued = std::mem::take(&mut *self.queued.lock());
+        let messages = spooled
+            .into_iter()
+            .filter_map(|message| match message {
+                SpooledMessage::Nats { subject, payload } => Some((subject, payload.into_bytes())),
+                // taken for nats only
+                SpooledMessage::Influx { .. } => None,
+            })
+            .chain(queued);
+
+        let results: Vec<_> = futures::stream::iter(messages)
+            .map(|(subject, payload)| async move {
+                let result = self
+                    .publisher
+                    .publish(subject.clone(), payload.clone())
+                    .await;
+                (subject, payload, result)
+            })
+            .buffer_unordered(self.config.concurrency)
+            .collect()
+            .await;
+
+        let mut failed = Vec::new();
+        let mut last_error = None;
+        for (subject, payload, result) in results {
+            if let Err(err) = result {
+                let payload = String::from_utf8_lossy(&payload).into_owned();
+                failed.push(SpooledMessage::Nats { subject, payload });
+                last_error = Some(err);
+            }
+        }
+        (failed, last_error)
+    }
+
+    pub async fn close(&self) -> Result<(), NatsError> {
+        self.publisher.close().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::Horizon;
+    use async_nats::jetstream::context::PublishErrorKind;
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+
+    /// Acks every message but those to the `rejected` subject.
+    #[derive(Default)]
+    struct MockPublisher {
+        rejected: String,
+        published: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Publisher for MockPublisher {
+        fn publish(&self, subject: String, _: Vec<u8>) -> BoxFuture<'_, Result<(), NatsError>> {
+            async move {
+                if subject == self.rejected {
+                    return Err(PublishError::from(PublishErrorKind::TimedOut).into());
+                }
+                self.published.lock().push(subject);
+                Ok(())
+            }
+            .boxed()
+        }
+
+        fn close(&self) -> BoxFuture<'_, Result<(), NatsError>> {
+            async { Ok(()) }.boxed()
+        }
+    }
+
+    fn config() -> NatsConfig {
+        NatsConfig::from_lookup(|key| match key {
+            "NATS_URL" => Some("tls://nats.example.com:4222".to_string()),
+            "NATS_SUBJECT_PREFIX" => Some("wisdom.swat.".to_string()),
+            _ => None,
+        })
+        .unwrap()
+        .unwrap()
+    }
+
+    fn event(slug: &str) -> ForecastEvent {
+        ForecastEvent {
+            location: slug.to_string(),
+            slug: slug.to_string(),
+            model: "vorhersage".to_string(),
+            issued: "2024-03-07 08:05".to_string(),
+            current: Horizon {
+                time: "2024-03-07 08:00".to_string(),
+                value: 412.0,
+            },
+            forecasts: BTreeMap::new(),
+            fetched_at: "2024-03-07T07:06:00Z".to_string(),
+            tick_id: 7,
+        }
+    }
+
+    #[test]
+    fn config_from_lookup() {
+        assert_eq!(NatsConfig::from_lookup(|_| None).unwrap(), None);
+        let config = config();
+        assert!(config.require_tls);
+        assert_eq!(config.concurrency, DEFAULT_CONCURRENCY);
+        assert_eq!(
+            config.subject("ww-grossenkneten"),
+            "wisdom.swat.ww-grossenkneten"
+        );
+
+        let invalid = NatsConfig::from_lookup(|key| match key {
+            "NATS_URL" => Some("nats://localhost".to_string()),
+            "NATS_SUBJECT_PREFIX" => Some("swat.*".to_string()),
+            _ => None,
+        });
+        assert!(matches!(
+            invalid,
+            Err(NatsConfigError::Invalid("NATS_SUBJECT_PREFIX", _))
+        ));
+    }
+
+    #[tokio::test]
+    async fn spools_unacked_messages() {
+        let published = Arc::default();
+        let publisher = MockPublisher {
+            rejected: "wisdom.swat.ww-marienhafe".to_string(),
+            published: Arc::clone(&published),
+        };
+        let output = NatsOutput::new(config(), Box::new(publisher));
+        output.queue(&event("ww-grossenkneten")).unwrap();
+        output.queue(&event("ww-marienhafe")).unwrap();
+        let spooled = SpooledMessage::Nats {
+            subject: "wisdom.swat.ww-thuelsfelde".to_string(),
+            payload: "{}".to_string(),
+        };
+
+        let (failed, error) = output.publish(vec![spooled]).await;
+        let mut published = published.lock().clone();
+        published.sort();
+        assert_eq!(
+            published,
+            ["wisdom.swat.ww-grossenkneten", "wisdom.swat.ww-thuelsfelde"]
+        );
+        assert!(matches!(error, Some(NatsError::Publish(_))));
+        let [SpooledMessage::Nats { subject, payload }] = failed.as_slice() else {
+            panic!("expected one failed message, got {failed:?}");
+        };
+        assert_eq!(subject, "wisdom.swat.ww-marienhafe");
+        let payload: ForecastEvent = serde_json::from_str(payload).unwrap();
+        assert_eq!(payload.slug, "ww-marienhafe");
+        assert_eq!(failed[0].destination(), "nats");
+
+        // the queue is emptied by publishing
+        assert_eq!(output.publish(Vec::new()).await.0, []);
+    }
+}
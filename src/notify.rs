@@ -0,0 +1,35 @@
+//! Delivery of alerts about failing locations.
+//!
+//! Notifications are queued in a [`NotificationQueue`] and drained to a [`Notifier`], which
+//! retries failed deliveries in order. The [`Webhook`] notifies Discord.
+//!
+//! ```
+//! use futures::future::BoxFuture;
+//! use swat_collector::notify::{Notification, Notifier};
+//!
+//! struct Stdout;
+//!
+//! impl Notifier for Stdout {
+//!     type Error = std::convert::Infallible;
+//!
+//!     fn channel(&self) -> &'static str {
+//!         "stdout"
+//!     }
+//!
+//!     fn deliver<'a>(
+//!         &'a self,
+//!         notification: &'a Notification,
+//!     ) -> BoxFuture<'a, Result<(), Self::Error>> {
+//!         Box::pin(async move {
+//!             println!("{notification:?}");
+//!             Ok(())
+//!         })
+//!     }
+//! }
+//! ```
+
+pub use crate::severity::Severity;
+pub use crate::webhook::{
+    AlertField, Destination, Mute, Notification, NotificationQueue, Notifier, QuietHours, Webhook,
+    WebhookDeliveryError,
+};
@@ -0,0 +1,111 @@
+use crate::webhook::truncate;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+/// Bodies of failed responses are cut to this many bytes unless `PARSE_FAILURE_LOG_LIMIT`
+/// is set.
+pub const DEFAULT_LIMIT: usize = 2 * 1024;
+
+/// Decides how much of a response body that failed to parse is logged.
+///
+/// Bodies are truncated, a body already logged in the same tick is only referenced and a body
+/// unchanged since the previous tick for the same location is not logged again.
+#[derive(Debug)]
+pub struct ParseFailureLog {
+    limit: usize,
+
+    /// Body hashes per location and model of the previous tick.
+    previous: HashMap<String, u64>,
+
+    /// Body hashes per location and model of the current tick.
+    current: HashMap<String, u64>,
+
+    /// Locations and models by the hashes of the bodies logged in the current tick.
+    logged: HashMap<u64, String>,
+}
+
+impl ParseFailureLog {
+    pub fn new(limit: usize) -> ParseFailureLog {
+        ParseFailureLog {
+            limit,
+            previous: HashMap::new(),
+            current: HashMap::new(),
+            logged: HashMap::new(),
+        }
+    }
+
+    /// Starts a new tick, bodies of locations that failed in the last tick are not logged again.
+    pub fn start_tick(&mut self) {
+        self.previous = std::mem::take(&mut self.current);
+        self.logged.clear();
+    }
+
+    /// Returns the text to log for the `body` the forecast of `target`, the location and model,
+    /// failed to parse.
+    pub fn body(&mut self, target: &str, body: &str) -> String {
+        let mut hasher = DefaultHasher::new();
+        body.hash(&mut hasher);
+        let hash = hasher.finish();
+        self.current.insert(target.to_string(), hash);
+
+        if self.previous.get(target) == Some(&hash) {
+            return "same as previous failure".to_string();
+        }
+
+        if let Some(name) = self.logged.get(&hash) {
+            return format!("same as for {name:?}");
+        }
+        self.logged.insert(hash, target.to_string());
+
+        if body.len() <= self.limit {
+            return body.to_string();
+        }
+        format!(
+            "{}\n(truncated, {} bytes in total)",
+            truncate(body, self.limit),
+            body.len()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncates_at_char_boundary() {
+        let mut log = ParseFailureLog::new(8);
+        assert_eq!(log.body("A", "short"), "short");
+        assert_eq!(
+            log.body("B", "Wartungsarbeiten äöü"),
+            "Wartu…\n(truncated, 23 bytes in total)"
+        );
+        assert_eq!(
+            log.body("C", "äöüäöüäöü"),
+            "äö…\n(truncated, 18 bytes in total)"
+        );
+    }
+
+    #[test]
+    fn deduplicates_bodies() {
+        let mut log = ParseFailureLog::new(DEFAULT_LIMIT);
+        let maintenance = "<html>maintenance</html>";
+
+        // identical bodies within a tick are logged once
+        log.start_tick();
+        assert_eq!(log.body("A", maintenance), maintenance);
+        assert_eq!(log.body("B", maintenance), "same as for \"A\"");
+        assert_eq!(log.body("C", "{}"), "{}");
+
+        // unchanged bodies of the same location are not logged again
+        log.start_tick();
+        assert_eq!(log.body("A", maintenance), "same as previous failure");
+        assert_eq!(log.body("C", "[]"), "[]");
+
+        // location 2 succeeded in the last tick, so its body is logged again
+        log.start_tick();
+        assert_eq!(log.body("B", maintenance), maintenance);
+        assert_eq!(log.body("A", maintenance), "same as previous failure");
+    }
+}
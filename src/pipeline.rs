@@ -0,0 +1,199 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+/// Built points buffered for the write stage unless `PIPELINE_POINTS_CAPACITY` is set.
+pub const DEFAULT_POINTS_CAPACITY: usize = 64;
+
+/// Errors buffered for the notify stage unless `PIPELINE_ERRORS_CAPACITY` is set.
+pub const DEFAULT_ERRORS_CAPACITY: usize = 64;
+
+/// Points of a bucket written at once unless `PIPELINE_BATCH_SIZE` is set, more points of a
+/// tick are written in several batches.
+pub const DEFAULT_BATCH_SIZE: usize = 5000;
+
+/// The bounded channels connecting the stages of a tick, fetching and building the points,
+/// writing them in batches and recording the errors of both.
+///
+/// A stage waits for room in the channel to the next one, so a slow InfluxDB holds up the
+/// fetching instead of piling up built points.
+#[derive(Debug)]
+pub struct Pipeline {
+    pub points: Arc<Queue>,
+    pub errors: Arc<Queue>,
+    pub batch_size: usize,
+}
+
+impl Pipeline {
+    pub fn new(points: usize, errors: usize, batch_size: usize) -> Pipeline {
+        Pipeline {
+            points: Arc::new(Queue::new("points", points)),
+            errors: Arc::new(Queue::new("errors", errors)),
+            batch_size: batch_size.max(1),
+        }
+    }
+
+    /// Resets the peak depths, so they cover the current tick.
+    pub fn start_tick(&self) {
+        self.points.peak.store(0, Ordering::SeqCst);
+        self.errors.peak.store(0, Ordering::SeqCst);
+    }
+
+    /// The depths of the channels shown in the verbose health check, nothing before a tick
+    /// passed anything through them.
+    #[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+    pub fn status_text(&self) -> String {
+        if self.points.peak() == 0 && self.errors.peak() == 0 {
+            return String::new();
+        }
+        let queues: Vec<_> = [&self.points, &self.errors]
+            .iter()
+            .map(|queue| {
+                format!(
+                    "{} {} of {} (peak {})",
+                    queue.name,
+                    queue.depth(),
+                    queue.capacity,
+                    queue.peak()
+                )
+            })
+            .collect();
+        format!("pipeline queues: {}\n", queues.join(", "))
+    }
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Pipeline::new(
+            DEFAULT_POINTS_CAPACITY,
+            DEFAULT_ERRORS_CAPACITY,
+            DEFAULT_BATCH_SIZE,
+        )
+    }
+}
+
+/// A channel between two stages, keeping its depth for the status.
+#[derive(Debug)]
+pub struct Queue {
+    name: &'static str,
+    capacity: usize,
+    depth: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+impl Queue {
+    fn new(name: &'static str, capacity: usize) -> Queue {
+        Queue {
+            name,
+            capacity: capacity.max(1),
+            depth: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+        }
+    }
+
+    /// Opens the channel of a tick.
+    pub fn channel<T>(self: &Arc<Queue>) -> (Sender<T>, Receiver<T>) {
+        let (sender, receiver) = mpsc::channel(self.capacity);
+        self.depth.store(0, Ordering::SeqCst);
+        let sender = Sender {
+            sender,
+            queue: self.clone(),
+        };
+        let receiver = Receiver {
+            receiver,
+            queue: self.clone(),
+        };
+        (sender, receiver)
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth.load(Ordering::SeqCst)
+    }
+
+    /// The highest depth since the tick started.
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::SeqCst)
+    }
+}
+
+#[derive(Debug)]
+pub struct Sender<T> {
+    sender: mpsc::Sender<T>,
+    queue: Arc<Queue>,
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Sender {
+            sender: self.sender.clone(),
+            queue: self.queue.clone(),
+        }
+    }
+}
+
+impl<T> Sender<T> {
+    /// Waits for room in the channel and sends the `value`.
+    pub async fn send(&self, value: T) {
+        // the receiving stage runs until every sender is dropped
+        let sent = self.sender.send(value).await;
+        assert!(sent.is_ok(), "{} stage stopped early", self.queue.name);
+        let depth = self.sender.max_capacity() - self.sender.capacity();
+        self.queue.depth.store(depth, Ordering::SeqCst);
+        self.queue.peak.fetch_max(depth, Ordering::SeqCst);
+    }
+}
+
+#[derive(Debug)]
+pub struct Receiver<T> {
+    receiver: mpsc::Receiver<T>,
+    queue: Arc<Queue>,
+}
+
+impl<T> Receiver<T> {
+    /// The next value, `None` once every sender is dropped and the channel is drained.
+    pub async fn recv(&mut self) -> Option<T> {
+        let value = self.receiver.recv().await;
+        self.queue
+            .depth
+            .store(self.receiver.len(), Ordering::SeqCst);
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn tracks_depth() {
+        let pipeline = Pipeline::new(2, 0, 0);
+        assert_eq!(pipeline.batch_size, 1);
+        let (sender, mut receiver) = pipeline.points.channel();
+        sender.send(1).await;
+        sender.send(2).await;
+        assert_eq!(pipeline.points.depth(), 2);
+
+        // a full channel holds up the sender until there is room
+        let blocked = tokio::spawn({
+            let sender = sender.clone();
+            async move { sender.send(3).await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!blocked.is_finished());
+        assert_eq!(receiver.recv().await, Some(1));
+        blocked.await.unwrap();
+        drop(sender);
+        assert_eq!(receiver.recv().await, Some(2));
+        assert_eq!(pipeline.points.depth(), 1);
+        assert_eq!(receiver.recv().await, Some(3));
+        assert_eq!(receiver.recv().await, None);
+
+        assert_eq!(
+            pipeline.status_text(),
+            "pipeline queues: points 0 of 2 (peak 2), errors 0 of 1 (peak 0)\n"
+        );
+        pipeline.start_tick();
+        assert_eq!(pipeline.points.peak(), 0);
+        assert_eq!(pipeline.status_text(), "");
+    }
+}
@@ -0,0 +1,136 @@
+use crate::points::RejectedPoint;
+
+/// Rejected points listed in the summary of a forecast, the others are only counted.
+const MAX_REASONS: usize = 3;
+
+/// Summaries warned about per tick, the forecasts beyond are only counted.
+pub const MAX_MESSAGES: usize = 5;
+
+/// The summary of a forecast of which `written` points were written and the `rejected` not.
+///
+/// ```text
+/// wrote 35/36 points, 1 rejected: empty tag value for "slug" at 2024-03-07 08:15
+/// ```
+pub fn summary(written: usize, rejected: &[RejectedPoint]) -> String {
+    let mut reasons: Vec<_> = rejected
+        .iter()
+        .take(MAX_REASONS)
+        .map(RejectedPoint::to_string)
+        .collect();
+    if rejected.len() > MAX_REASONS {
+        reasons.push(format!("{} more", rejected.len() - MAX_REASONS));
+    }
+    format!(
+        "wrote {written}/{} points, {} rejected: {}",
+        written + rejected.len(),
+        rejected.len(),
+        reasons.join("; ")
+    )
+}
+
+/// Counts the points written and rejected since the start and queues the summaries of the
+/// forecasts partially written in the current tick.
+///
+/// A forecast of which some points could not be built is still written, so its rejected points
+/// are warned about instead of failing the location.
+#[derive(Debug, Default)]
+pub struct PointRejections {
+    written: u64,
+    rejected: u64,
+    messages: Vec<String>,
+
+    /// Summaries not queued for exceeding [`MAX_MESSAGES`] in the current tick.
+    omitted: usize,
+}
+
+impl PointRejections {
+    /// Records that `written` points of the forecast of `target`, the location and model, were
+    /// written and the `rejected` were not.
+    pub fn record(&mut self, target: &str, written: usize, rejected: &[RejectedPoint]) {
+        self.written += written as u64;
+        self.rejected += rejected.len() as u64;
+        if rejected.is_empty() {
+            return;
+        }
+        match self.messages.len() < MAX_MESSAGES {
+            true => self
+                .messages
+                .push(format!("{target}: {}", summary(written, rejected))),
+            false => self.omitted += 1,
+        }
+    }
+
+    /// Takes the summaries queued since the last call.
+    pub fn take_messages(&mut self) -> Vec<String> {
+        let mut messages = std::mem::take(&mut self.messages);
+        match std::mem::take(&mut self.omitted) {
+            0 => (),
+            1 => messages.push("1 more forecast with rejected points".to_string()),
+            omitted => messages.push(format!("{omitted} more forecasts with rejected points")),
+        }
+        messages
+    }
+
+    /// Line for the status page, empty unless any point was rejected.
+    #[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+    pub fn status_text(&self) -> String {
+        match self.rejected {
+            0 => String::new(),
+            rejected => format!(
+                "points rejected: {rejected} of {}\n",
+                self.written + rejected
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::points::PointError;
+
+    fn rejected(count: usize) -> Vec<RejectedPoint> {
+        (0..count)
+            .map(|i| RejectedPoint {
+                horizon: format!("2024-03-07 08:{:02}", 10 + 5 * i),
+                error: PointError::EmptyTag("slug".to_string()),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn summarizes_rejected_points() {
+        assert_eq!(
+            summary(35, &rejected(1)),
+            "wrote 35/36 points, 1 rejected: empty tag value for \"slug\" at 2024-03-07 08:10"
+        );
+        assert_eq!(
+            summary(1, &rejected(5)),
+            "wrote 1/6 points, 5 rejected: empty tag value for \"slug\" at 2024-03-07 08:10; \
+             empty tag value for \"slug\" at 2024-03-07 08:15; \
+             empty tag value for \"slug\" at 2024-03-07 08:20; 2 more"
+        );
+    }
+
+    #[test]
+    fn caps_messages_per_tick() {
+        let mut rejections = PointRejections::default();
+        rejections.record("WW Großenkneten", 36, &[]);
+        assert!(rejections.take_messages().is_empty());
+        assert_eq!(rejections.status_text(), "");
+
+        for _ in 0..MAX_MESSAGES + 2 {
+            rejections.record("WW Großenkneten", 35, &rejected(1));
+        }
+        let messages = rejections.take_messages();
+        assert_eq!(messages.len(), MAX_MESSAGES + 1);
+        assert!(messages[0].starts_with("WW Großenkneten: wrote 35/36 points, 1 rejected"));
+        assert_eq!(
+            messages[MAX_MESSAGES],
+            "2 more forecasts with rejected points"
+        );
+        assert!(rejections.take_messages().is_empty());
+
+        assert_eq!(rejections.status_text(), "points rejected: 7 of 288\n");
+    }
+}
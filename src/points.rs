@@ -0,0 +1,792 @@
+//! Building the InfluxDB points of the forecasts.
+//!
+//! Every forecast becomes a point of the `forecast` measurement at its issue time, tagged with
+//! the location and model it was collected for.
+
+use crate::content_hash;
+use crate::fields;
+use crate::groups;
+use crate::lead_time::LeadTime;
+use crate::locations::{Extra, Forecast, RequestLocationError, Target};
+use crate::logging;
+use crate::names;
+use crate::retry::AttemptLog;
+use crate::sink::WriteError;
+use crate::spool::SpoolError;
+use crate::timestamp;
+use crate::values;
+use influxdb2::models::data_point::{DataPointBuilder, DataPointError};
+use influxdb2::models::DataPoint;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use thiserror::Error;
+
+pub use crate::geo::GeoFields;
+pub use crate::lead_time::LeadTimes;
+pub use crate::names::{NameMapping, NameMappingError, FIELDS, TAGS};
+pub use crate::timestamp::TimestampError;
+
+/// Failure to collect the forecast of a location, from requesting it to writing its point.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum HandleLocationError {
+    #[error("forecast request failed, {0}")]
+    RequestForecast(#[from] RequestLocationError),
+
+    #[error("parsing forecast timestamp failed, {0}")]
+    ParseFromTimestamp(#[from] TimestampError),
+
+    /// Serializing failed for every field of the point, or for its content hash.
+    #[error("could not serialize data for query, {0}")]
+    SerializeData(#[from] serde_json::Error),
+
+    #[error("error while building data point, {0}")]
+    DataPoint(#[from] DataPointError),
+
+    /// None of the points per horizon could be built, `first` is the failure found first.
+    #[error("all {count} points rejected, {first}")]
+    PointsRejected { count: usize, first: RejectedPoint },
+
+    #[error("writing influxdb query into bucket {bucket:?} failed, {error}")]
+    WritePoints {
+        bucket: String,
+
+        /// Shared by the locations of the failed write.
+        error: Arc<WriteError>,
+    },
+
+    /// The circuit of the sink was open, and the points for `bucket` could not be spooled
+    /// instead, the `error` is `None` without a spool.
+    #[error(
+        "sink circuit open, could not spool points for bucket {bucket:?}, {}",
+        .error.as_ref().map_or("no spool".to_string(), ToString::to_string)
+    )]
+    Diverted {
+        bucket: String,
+
+        /// Shared by the locations of the batch.
+        error: Option<Arc<SpoolError>>,
+    },
+
+    /// The `error` of the forecast requested once for all the `locations` at the same
+    /// coordinates.
+    #[error("{error} (requested once for {locations})")]
+    SharedRequest {
+        /// Shared by the `locations`.
+        error: Arc<HandleLocationError>,
+        locations: String,
+    },
+
+    /// The `error` of the last of several attempts, or of one that was not retried as the
+    /// retry budget was used up.
+    #[error("{error} ({attempts})")]
+    Retried {
+        error: Box<HandleLocationError>,
+        attempts: AttemptLog,
+    },
+}
+
+impl HandleLocationError {
+    /// The `error` along with the `attempts` that led to it, if it was retried or could not be
+    /// for the retry budget.
+    pub fn attempted(error: HandleLocationError, attempts: AttemptLog) -> HandleLocationError {
+        match attempts.retried() || attempts.budget_exhausted() {
+            true => HandleLocationError::Retried {
+                error: Box::new(error),
+                attempts,
+            },
+            false => error,
+        }
+    }
+}
+
+/// Measurement of the points per horizon, written next to or instead of `forecast` depending
+/// on the `SCHEMA_MODE`.
+pub const MEASUREMENT_V2: &str = "forecast_v2";
+
+/// Tag of the points per horizon with the minutes between the issue time and the horizon as the
+/// api states them, telling the horizons of an issue apart.
+pub const HORIZON_TAG: &str = "horizon";
+
+/// Integer field of the points per horizon with the [lead time](LeadTimes) of the horizon.
+pub const LEAD_FIELD: &str = "lead_minutes";
+
+/// Tag of the points per horizon with the [bucket](crate::lead_time::LeadBucket) of their lead
+/// time.
+pub const LEAD_BUCKET_TAG: &str = "lead_bucket";
+
+/// Tag of the `forecast` points written without some of their JSON fields, which could not be
+/// serialized.
+pub const SERIALIZE_ERROR_TAG: &str = "serialize_error";
+
+/// Failure to build one of the points per horizon, which leaves out only that point.
+#[derive(Debug, Error)]
+pub enum PointError {
+    #[error("empty tag value for {0:?}")]
+    EmptyTag(String),
+
+    #[error("unparsable horizon")]
+    Horizon(#[source] TimestampError),
+
+    #[error(transparent)]
+    DataPoint(#[from] DataPointError),
+}
+
+/// A point per horizon left out of the write.
+#[derive(Debug, Error)]
+#[error("{error} at {horizon}")]
+pub struct RejectedPoint {
+    /// Time of the horizon as sent by the swat api.
+    pub horizon: String,
+    pub error: PointError,
+}
+
+/// The points per horizon of a forecast and the ones that could not be built.
+#[derive(Debug, Default)]
+pub struct HorizonPoints {
+    pub points: Vec<DataPoint>,
+    pub rejected: Vec<RejectedPoint>,
+
+    /// Times of the horizons before the issue time, written with a lead time of 0.
+    pub clamped: Vec<String>,
+}
+
+/// Data points of a location waiting to be written.
+pub(crate) struct PendingPoint<'l> {
+    pub(crate) target: Target<'l>,
+
+    /// The point of the `forecast` measurement, the points per horizon or both.
+    pub(crate) data_points: Vec<DataPoint>,
+
+    /// The points per horizon left out, reported once the others are written.
+    pub(crate) rejected: Vec<RejectedPoint>,
+
+    /// Issue time of the forecast as sent by the swat api and as unix timestamp.
+    pub(crate) issued: String,
+    pub(crate) timestamp: i64,
+
+    pub(crate) content_hash: String,
+
+    /// Hash of the forecast without the location, see [`content_hash::forecast_hash`].
+    pub(crate) forecast_hash: String,
+}
+
+/// Points of a tick to write into the same bucket.
+pub(crate) type Batch<'l> = Vec<PendingPoint<'l>>;
+
+/// The unix timestamp of the issue time `from` as sent by the swat api, in UTC.
+pub fn issue_timestamp(from: &str) -> Result<i64, TimestampError> {
+    let (timestamp, _) = timestamp::parse(from)?;
+    Ok(timestamp.and_utc().timestamp())
+}
+
+/// The point of the `forecast` of `target`, written into InfluxDB with the names of
+/// `FIELD_NAME_MAP` and `TAG_NAME_MAP`.
+///
+/// Forecasts exceeding `field_limit` bytes are split across numbered fields.
+///
+/// ```
+/// use swat_collector::points::{forecast_data_point, GeoFields};
+/// use swat_collector::swat::{parse_forecast, Location, Model, Target};
+///
+/// let location = Location { group: "", id: 1, lat: "52.9", lon: "8.2", name: "WW Großenkneten" };
+/// let model = Model::default_model();
+/// let target = Target { location: &location, model: &model };
+/// let forecast = parse_forecast(
+///     r#"{"vorhersageZeit": "2024-03-07 08:05", "lat": 52.9125, "lon": 8.2375,
+///         "aktuell": {"2024-03-07 08:05": 0}, "vorhersage": {"2024-03-07 08:10": 1}}"#
+///         .to_string(),
+/// )?;
+/// let point = forecast_data_point(target, &forecast, false, false, usize::MAX, GeoFields::Off)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn forecast_data_point(
+    target: Target<'_>,
+    forecast: &Forecast,
+    stale_issue: bool,
+    short_forecast: bool,
+    field_limit: usize,
+    geo_fields: GeoFields,
+) -> Result<DataPoint, HandleLocationError> {
+    let builder = forecast_point_builder(
+        target,
+        forecast,
+        stale_issue,
+        short_forecast,
+        field_limit,
+        geo_fields,
+        &names::NAMES,
+    )?;
+    Ok(builder.build()?)
+}
+
+/// The [forecast_data_point] before building, for adding tags of the tick.
+pub fn forecast_point_builder(
+    target: Target<'_>,
+    forecast: &Forecast,
+    stale_issue: bool,
+    short_forecast: bool,
+    field_limit: usize,
+    geo_fields: GeoFields,
+    names: &NameMapping,
+) -> Result<DataPointBuilder, HandleLocationError> {
+    let location = target.location;
+    let (lat, lon) = location.coordinate_texts();
+    let timestamp = issue_timestamp(&forecast.from)?;
+
+    let mut builder = DataPoint::builder("forecast")
+        .timestamp(timestamp)
+        .tag(names.tag("id"), location.id.to_string())
+        .tag(names.tag("name"), location.name)
+        .tag(names.tag("slug"), location.slug())
+        .tag(names.tag("group"), groups::of(location))
+        .tag(names.tag("model"), target.model.name.as_str())
+        .tag(names.tag("api_version"), forecast.api_version.to_string())
+        .tag(
+            names.tag("content_hash"),
+            content_hash::content_hash(target, forecast)?,
+        )
+        .tag(names.tag("lat"), lat)
+        .tag(names.tag("lon"), lon);
+    if stale_issue {
+        builder = builder.tag(names.tag("stale_issue"), "true");
+    }
+    if short_forecast {
+        builder = builder.tag(names.tag("short_forecast"), "true");
+    }
+    let mut fields = JsonFields::default();
+    // the coordinates are checked at startup
+    if let (GeoFields::Point, Ok(coordinates)) = (geo_fields, location.coordinates()) {
+        builder = builder
+            .field(names.field("latitude"), coordinates.lat())
+            .field(names.field("longitude"), coordinates.lon());
+        fields.written += 2;
+    }
+
+    // extras never replace the fields of the forecast
+    let forecasts_field = names.field("forecasts");
+    for (name, extra) in &forecast.extras {
+        let taken = names::FIELDS
+            .iter()
+            .any(|field| name == field || name == names.field(field))
+            || name.starts_with(forecasts_field);
+        if taken {
+            continue;
+        }
+        builder = match extra {
+            Extra::Number(number) => builder.field(name.as_str(), *number),
+            Extra::Text(text) => builder.field(name.as_str(), text.as_str()),
+            Extra::Flag(flag) => builder.field(name.as_str(), *flag),
+        };
+        fields.written += 1;
+    }
+
+    // dashboards parse the values of the json fields as integers
+    let current = BTreeMap::from([forecast.current.clone()]);
+    let field = names.field("current");
+    builder = fields.add(
+        builder,
+        target,
+        field,
+        &values::legacy(&current),
+        usize::MAX,
+    );
+    let field = names.field("forecasts");
+    let forecasts = values::legacy(&forecast.forecasts);
+    builder = fields.add(builder, target, field, &forecasts, field_limit);
+    // the values as sent, if kept while processing them
+    if let Some(raw) = &forecast.raw {
+        let current = BTreeMap::from([(forecast.current.0.clone(), raw.current)]);
+        let current_raw = format!("{}_raw", names.field("current"));
+        builder = fields.add(
+            builder,
+            target,
+            &current_raw,
+            &values::legacy(&current),
+            usize::MAX,
+        );
+        let forecasts_raw = format!("{field}_raw");
+        let forecasts = values::legacy(&raw.forecasts);
+        builder = fields.add(builder, target, &forecasts_raw, &forecasts, field_limit);
+    }
+
+    fields.finish(builder, target, names)
+}
+
+/// The JSON string fields of a `forecast` point, added best-effort so a field failing to
+/// serialize leaves out just that field.
+#[derive(Debug, Default)]
+struct JsonFields {
+    /// Fields added to the point so far, numeric ones included.
+    written: usize,
+
+    /// The fields that could not be serialized, and why.
+    failed: Vec<(String, serde_json::Error)>,
+}
+
+impl JsonFields {
+    /// Adds the `values` as JSON `field`, split across numbered fields if exceeding the
+    /// `field_limit`, or remembers why they could not be serialized.
+    fn add<V: Serialize>(
+        &mut self,
+        mut builder: DataPointBuilder,
+        target: Target<'_>,
+        field: &str,
+        values: &BTreeMap<&str, V>,
+        field_limit: usize,
+    ) -> DataPointBuilder {
+        let chunks = match fields::split_json_map(values, field_limit) {
+            Ok(chunks) => chunks,
+            Err(err) => {
+                self.failed.push((field.to_string(), err));
+                return builder;
+            }
+        };
+        self.written += chunks.len();
+        match chunks.len() {
+            1 => builder = builder.field(field, chunks[0].clone()),
+            count => {
+                let datetime = logging::datetime();
+                log_eprintln!(
+                    "WARN  [{datetime}]: forecasts of location {:?} exceed {field_limit} bytes, \
+                     split into {count} fields \"{field}_0\" to \"{field}_{}\"",
+                    target.to_string(),
+                    count - 1
+                );
+                for (i, chunk) in chunks.into_iter().enumerate() {
+                    builder = builder.field(format!("{field}_{i}"), chunk);
+                }
+            }
+        }
+        builder
+    }
+
+    /// Tags the point built by `builder` with the [`SERIALIZE_ERROR_TAG`] if any field failed
+    /// to serialize, which fails the point only if it was left without any field.
+    fn finish(
+        mut self,
+        builder: DataPointBuilder,
+        target: Target<'_>,
+        names: &NameMapping,
+    ) -> Result<DataPointBuilder, HandleLocationError> {
+        if self.failed.is_empty() {
+            return Ok(builder);
+        }
+        let failed: Vec<_> = (self.failed.iter())
+            .map(|(field, _)| format!("{field:?}"))
+            .collect();
+        let datetime = logging::datetime();
+        log_eprintln!(
+            "WARN  [{datetime}]: could not serialize the fields {} of location {:?}, {}",
+            failed.join(", "),
+            target.to_string(),
+            self.failed[0].1
+        );
+        match self.written {
+            0 => Err(self.failed.swap_remove(0).1.into()),
+            _ => Ok(builder.tag(names.tag(SERIALIZE_ERROR_TAG), "true")),
+        }
+    }
+}
+
+/// The points of the `forecast` of `target` in the [`MEASUREMENT_V2`], one per horizon with
+/// its numeric `value`.
+///
+/// The points share the issue time and the tags of the location with the `forecast` point, the
+/// horizons are told apart by their [`HORIZON_TAG`]. The current value is a `current` field on
+/// the point of its own horizon, so both representations carry the same information. Every
+/// point has the [`LEAD_FIELD`] and [`LEAD_BUCKET_TAG`] of the `lead_times`, horizons before
+/// the issue time are clamped to a lead time of 0 and returned among the `clamped`.
+///
+/// A horizon whose point cannot be built, like for an empty tag value that InfluxDB would refuse
+/// the whole write for, is left out and returned among the `rejected`. Only if every point is
+/// rejected does the forecast fail.
+pub fn forecast_v2_points(
+    target: Target<'_>,
+    forecast: &Forecast,
+    names: &NameMapping,
+    lead_times: &mut LeadTimes,
+) -> Result<HorizonPoints, HandleLocationError> {
+    let location = target.location;
+    let (lat, lon) = location.coordinate_texts();
+    let timestamp = issue_timestamp(&forecast.from)?;
+    let (issued, _) = timestamp::parse(&forecast.from)?;
+    let content_hash = content_hash::content_hash(target, forecast)?;
+    let tags = [
+        (names.tag("id"), location.id.to_string()),
+        (names.tag("name"), location.name.to_string()),
+        (names.tag("slug"), location.slug()),
+        (names.tag("group"), groups::of(location).to_string()),
+        (names.tag("model"), target.model.name.clone()),
+        (names.tag("api_version"), forecast.api_version.to_string()),
+        (names.tag("content_hash"), content_hash),
+        (names.tag("lat"), lat),
+        (names.tag("lon"), lon),
+    ];
+
+    let mut points = HorizonPoints::default();
+    // the fields per horizon and the time and lead time of the first entry at it
+    let mut fields: BTreeMap<i64, Vec<(&str, f64)>> = BTreeMap::new();
+    let mut times: BTreeMap<i64, (&str, LeadTime)> = BTreeMap::new();
+    let (current_time, current) = &forecast.current;
+    // the values as sent, if kept while processing them
+    let raw = forecast.raw.as_ref();
+    let current_raw = format!("{}_raw", names.field("current"));
+    let horizons = std::iter::once((
+        current_time,
+        names.field("current"),
+        *current,
+        raw.map(|raw| (current_raw.as_str(), raw.current)),
+    ))
+    .chain(forecast.forecasts.iter().map(|(time, value)| {
+        let raw = raw.and_then(|raw| Some(("value_raw", *raw.forecasts.get(time)?)));
+        (time, "value", *value, raw)
+    }));
+    for (time, field, value, raw) in horizons {
+        let clamped = lead_times.clamped();
+        let parsed = timestamp::parse(time)
+            .and_then(|(parsed, _)| Ok((parsed, lead_times.lead_time(&forecast.from, time)?)));
+        match parsed {
+            Ok((parsed, lead)) => {
+                if lead_times.clamped() > clamped {
+                    points.clamped.push(time.clone());
+                }
+                let horizon = (parsed - issued).num_minutes();
+                times.entry(horizon).or_insert((time, lead));
+                let fields = fields.entry(horizon).or_default();
+                fields.push((field, value));
+                fields.extend(raw);
+            }
+            Err(err) => points.rejected.push(RejectedPoint {
+                horizon: time.clone(),
+                error: PointError::Horizon(err),
+            }),
+        }
+    }
+    for (horizon, fields) in fields {
+        let (time, lead) = times[&horizon];
+        match horizon_point(timestamp, &tags, (horizon, lead), fields) {
+            Ok(point) => points.points.push(point),
+            Err(error) => points.rejected.push(RejectedPoint {
+                horizon: time.to_string(),
+                error,
+            }),
+        }
+    }
+
+    if points.points.is_empty() && !points.rejected.is_empty() {
+        let count = points.rejected.len();
+        let first = points.rejected.swap_remove(0);
+        return Err(HandleLocationError::PointsRejected { count, first });
+    }
+    Ok(points)
+}
+
+/// The point of the `horizon` minutes from the issue time with its `lead` time.
+fn horizon_point(
+    timestamp: i64,
+    tags: &[(&str, String)],
+    (horizon, lead): (i64, LeadTime),
+    fields: Vec<(&str, f64)>,
+) -> Result<DataPoint, PointError> {
+    let mut builder = DataPoint::builder(MEASUREMENT_V2).timestamp(timestamp);
+    for (tag, value) in tags {
+        if value.is_empty() {
+            return Err(PointError::EmptyTag(tag.to_string()));
+        }
+        builder = builder.tag(*tag, value.as_str());
+    }
+    builder = builder
+        .tag(HORIZON_TAG, horizon.to_string())
+        .tag(LEAD_BUCKET_TAG, lead.bucket.as_str())
+        .field(LEAD_FIELD, lead.minutes);
+    for (field, value) in fields {
+        builder = builder.field(field, value);
+    }
+    Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locations::{parse_forecast, Location, Model};
+    use crate::processing::RawValues;
+
+    const BODY: &str = r#"{"vorhersageZeit": "2024-03-07 08:05", "lat": 52.9, "lon": 8.2,
+        "aktuell": {"2024-03-07 08:05": 412.4},
+        "vorhersage": {"2024-03-07 08:10": 413, "2024-03-07 08:15": 415.5}}"#;
+
+    #[test]
+    fn leaves_out_rejected_horizons() {
+        let location = Location {
+            group: "",
+            id: 1,
+            lat: "52.9",
+            lon: "8.2",
+            name: "WW Großenkneten",
+        };
+        let model = Model::default_model();
+        let target = Target {
+            location: &location,
+            model: &model,
+        };
+        let mut forecast = parse_forecast(BODY.to_string()).unwrap();
+        forecast
+            .forecasts
+            .insert("2024-03-07 08:75".to_string(), 414.0);
+
+        let points = forecast_v2_points(
+            target,
+            &forecast,
+            &NameMapping::default(),
+            &mut LeadTimes::default(),
+        )
+        .unwrap();
+        let lines = crate::sink::Sink::Stdout.lines(points.points.clone());
+        let horizons: Vec<_> = lines
+            .lines()
+            .map(|line| line.split(',').find(|tag| tag.starts_with(HORIZON_TAG)))
+            .collect();
+        assert_eq!(
+            horizons,
+            [Some("horizon=0"), Some("horizon=5"), Some("horizon=10")]
+        );
+        assert_eq!(points.rejected.len(), 1);
+        assert_eq!(
+            crate::point_rejections::summary(points.points.len(), &points.rejected),
+            "wrote 3/4 points, 1 rejected: unparsable horizon at 2024-03-07 08:75"
+        );
+
+        // the tags are the same for every horizon, so an empty one rejects all
+        forecast.forecasts.remove("2024-03-07 08:75");
+        let location = Location {
+            name: "---",
+            ..location
+        };
+        let target = Target {
+            location: &location,
+            model: &model,
+        };
+        let err = forecast_v2_points(
+            target,
+            &forecast,
+            &NameMapping::default(),
+            &mut LeadTimes::default(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "all 3 points rejected, empty tag value for \"slug\" at 2024-03-07 08:05"
+        );
+    }
+
+    /// Fails serializing, like map keys that are no strings.
+    struct Unserializable;
+
+    impl Serialize for Unserializable {
+        fn serialize<S: serde::Serializer>(&self, _: S) -> Result<S::Ok, S::Error> {
+            Err(serde::ser::Error::custom("injected failure"))
+        }
+    }
+
+    #[test]
+    fn degrades_unserializable_fields() {
+        let location = Location {
+            group: "",
+            id: 1,
+            lat: "52.9",
+            lon: "8.2",
+            name: "WW Großenkneten",
+        };
+        let model = Model::default_model();
+        let target = Target {
+            location: &location,
+            model: &model,
+        };
+        let forecast = parse_forecast(BODY.to_string()).unwrap();
+        let point = forecast_point_builder(
+            target,
+            &forecast,
+            false,
+            false,
+            usize::MAX,
+            GeoFields::Point,
+            &NameMapping::default(),
+        )
+        .unwrap();
+        let line = crate::sink::Sink::Stdout.lines(vec![point.build().unwrap()]);
+        assert!(!line.contains(SERIALIZE_ERROR_TAG), "{line}");
+
+        // the failing field is left out, the others are written
+        let mut fields = JsonFields::default();
+        let builder = DataPoint::builder("forecast")
+            .timestamp(1709798700)
+            .tag("id", "1")
+            .field("latitude", 52.9);
+        fields.written += 1;
+        let current = BTreeMap::from([("2024-03-07 08:05", 412)]);
+        let builder = fields.add(builder, target, "current", &current, usize::MAX);
+        let forecasts = BTreeMap::from([("2024-03-07 08:10", Unserializable)]);
+        let builder = fields.add(builder, target, "forecasts", &forecasts, usize::MAX);
+        let point = fields
+            .finish(builder, target, &NameMapping::default())
+            .unwrap()
+            .build()
+            .unwrap();
+        let line = crate::sink::Sink::Stdout.lines(vec![point]);
+        assert!(line.contains(",serialize_error=true "), "{line}");
+        assert!(line.contains("latitude=52.9"), "{line}");
+        assert!(
+            line.contains(r#"current="{\"2024-03-07 08:05\":412}""#),
+            "{line}"
+        );
+        assert!(!line.contains("forecasts"), "{line}");
+
+        // without any field left, the point fails
+        let mut fields = JsonFields::default();
+        let builder = DataPoint::builder("forecast").timestamp(1709798700);
+        let builder = fields.add(builder, target, "forecasts", &forecasts, usize::MAX);
+        let err = fields
+            .finish(builder, target, &NameMapping::default())
+            .unwrap_err();
+        assert!(
+            matches!(err, HandleLocationError::SerializeData(_)),
+            "{err}"
+        );
+        assert_eq!(
+            err.to_string(),
+            "could not serialize data for query, injected failure"
+        );
+    }
+
+    #[test]
+    fn writes_lead_times() {
+        let location = Location {
+            group: "",
+            id: 1,
+            lat: "52.9",
+            lon: "8.2",
+            name: "WW Großenkneten",
+        };
+        let model = Model::default_model();
+        let target = Target {
+            location: &location,
+            model: &model,
+        };
+        // issued the night daylight saving starts, with a horizon before the issue time
+        let body = r#"{"vorhersageZeit": "2024-03-31 01:30", "lat": 52.9, "lon": 8.2,
+            "aktuell": {"2024-03-31 01:30": 412.4},
+            "vorhersage": {"2024-03-31 01:25": 412, "2024-03-31 03:30": 413,
+                           "2024-03-31 07:30": 415.5}}"#;
+        let forecast = parse_forecast(body.to_string()).unwrap();
+        let mut lead_times = LeadTimes::default();
+
+        let points =
+            forecast_v2_points(target, &forecast, &NameMapping::default(), &mut lead_times)
+                .unwrap();
+        assert_eq!(points.clamped, ["2024-03-31 01:25"]);
+        assert_eq!(lead_times.clamped(), 1);
+        let lines = crate::sink::Sink::Stdout.lines(points.points);
+        let leads: Vec<_> = lines
+            .lines()
+            .map(|line| {
+                let tag = |key: &str| {
+                    let tag = line.split(',').find(|tag| tag.starts_with(key)).unwrap();
+                    tag.split(' ').next().unwrap()
+                };
+                let fields = line.rsplit(' ').nth(1).unwrap().split(',');
+                (tag(HORIZON_TAG), tag(LEAD_BUCKET_TAG), fields.collect())
+            })
+            .collect();
+        assert_eq!(
+            leads,
+            [
+                (
+                    "horizon=-5",
+                    "lead_bucket=0-1h",
+                    vec!["lead_minutes=0i", "value=412"]
+                ),
+                (
+                    "horizon=0",
+                    "lead_bucket=0-1h",
+                    vec!["current=412.4", "lead_minutes=0i"]
+                ),
+                // an hour is skipped at 02:00
+                (
+                    "horizon=120",
+                    "lead_bucket=0-1h",
+                    vec!["lead_minutes=60i", "value=413"]
+                ),
+                (
+                    "horizon=360",
+                    "lead_bucket=3-6h",
+                    vec!["lead_minutes=300i", "value=415.5"]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_raw_values() {
+        let location = Location {
+            group: "",
+            id: 1,
+            lat: "52.9",
+            lon: "8.2",
+            name: "WW Großenkneten",
+        };
+        let model = Model::default_model();
+        let target = Target {
+            location: &location,
+            model: &model,
+        };
+        let mut forecast = parse_forecast(BODY.to_string()).unwrap();
+        forecast.raw = Some(RawValues {
+            current: forecast.current.1,
+            forecasts: forecast.forecasts.clone(),
+        });
+        forecast.current.1 *= 2.0;
+        for value in forecast.forecasts.values_mut() {
+            *value *= 2.0;
+        }
+
+        let points = forecast_v2_points(
+            target,
+            &forecast,
+            &NameMapping::default(),
+            &mut LeadTimes::default(),
+        )
+        .unwrap();
+        let lines = crate::sink::Sink::Stdout.lines(points.points);
+        let fields: Vec<_> = lines
+            .lines()
+            .map(|line| line.rsplit(' ').nth(1).unwrap())
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                "current=824.8,current_raw=412.4,lead_minutes=0i",
+                "lead_minutes=5i,value=826,value_raw=413",
+                "lead_minutes=10i,value=831,value_raw=415.5"
+            ]
+        );
+
+        let point =
+            forecast_data_point(target, &forecast, false, false, usize::MAX, GeoFields::Off)
+                .unwrap();
+        let line = crate::sink::Sink::Stdout.lines(vec![point]);
+        assert!(
+            line.contains(r#"current="{\"2024-03-07 08:05\":825}""#),
+            "{line}"
+        );
+        assert!(
+            line.contains(r#"current_raw="{\"2024-03-07 08:05\":412}""#),
+            "{line}"
+        );
+        assert!(
+            line.contains(r#"forecasts_raw="{\"2024-03-07 08:10\":413,\"2024-03-07 08:15\":416}""#),
+            "{line}"
+        );
+    }
+}
@@ -0,0 +1,303 @@
+use crate::locations::{Forecast, Location};
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+/// Values are rounded to at most this many decimal places, beyond `f64` has no precision left.
+const MAX_PLACES: u32 = 12;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ProcessingError {
+    #[error(
+        "{key}, expected processors in the form of `convert=<factor>[:<offset>],round=<places>`, \
+         got {value:?}"
+    )]
+    Format { key: String, value: String },
+
+    #[error("{key}, expected a finite non-zero factor and a finite offset, got {value:?}")]
+    Convert { key: String, value: String },
+
+    #[error("{key}, expected at most {MAX_PLACES} decimal places, got {value:?}")]
+    Round { key: String, value: String },
+
+    #[error("expected \"PROCESSING_KEEP_RAW\" to be true or false, got {0:?}")]
+    KeepRaw(String),
+}
+
+/// A step applied to every value of a forecast.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Processor {
+    /// Linear unit conversion, like from mm/h to l/(s·ha) with a factor of `2.7778`.
+    Convert { factor: f64, offset: f64 },
+
+    /// Rounding to a number of decimal places.
+    Round { places: u32 },
+}
+
+impl Processor {
+    pub fn apply(&self, value: f64) -> f64 {
+        match *self {
+            Processor::Convert { factor, offset } => value * factor + offset,
+            Processor::Round { places } => {
+                let scale = 10f64.powi(places as i32);
+                (value * scale).round() / scale
+            }
+        }
+    }
+}
+
+/// The values of a forecast as sent by the swat api, kept before processing them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawValues {
+    pub current: f64,
+    pub forecasts: BTreeMap<String, f64>,
+}
+
+/// The processors applied to the forecasts before building their points.
+///
+/// Every location is processed by the processors in `PROCESSING` unless `PROCESSING_<SLUG>`
+/// lists its own, with the slug in upper case and `_` as separator. Both take a list applied in
+/// order like `convert=2.7778,round=1`, an empty `PROCESSING_<SLUG>` leaves the location as
+/// sent. With `PROCESSING_KEEP_RAW=true`, the values as sent are written into `*_raw` fields
+/// next to the processed ones.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Processing {
+    default: Vec<Processor>,
+    locations: BTreeMap<i64, Vec<Processor>>,
+    keep_raw: bool,
+}
+
+impl Processing {
+    pub fn from_lookup(
+        lookup: impl Fn(&str) -> Option<String>,
+        locations: &[Location],
+    ) -> Result<Processing, ProcessingError> {
+        let default = match lookup("PROCESSING") {
+            Some(value) => parse_list("PROCESSING", &value)?,
+            None => Vec::new(),
+        };
+        let mut by_location = BTreeMap::new();
+        for location in locations {
+            let key = format!("PROCESSING_{}", location.slug().to_uppercase()).replace('-', "_");
+            if let Some(value) = lookup(&key) {
+                by_location.insert(location.id, parse_list(&key, &value)?);
+            }
+        }
+        let keep_raw = match lookup("PROCESSING_KEEP_RAW") {
+            Some(value) => match value.trim() {
+                "true" => true,
+                "false" => false,
+                _ => return Err(ProcessingError::KeepRaw(value)),
+            },
+            None => false,
+        };
+
+        Ok(Processing {
+            default,
+            locations: by_location,
+            keep_raw,
+        })
+    }
+
+    pub fn of(&self, location: &Location) -> &[Processor] {
+        self.locations.get(&location.id).unwrap_or(&self.default)
+    }
+
+    /// Processes the values of the `forecast` of `location`, keeping the values as sent in its
+    /// `raw` if asked for.
+    pub fn apply(&self, location: &Location, forecast: &mut Forecast) {
+        let processors = self.of(location);
+        if processors.is_empty() {
+            return;
+        }
+        if self.keep_raw {
+            forecast.raw = Some(RawValues {
+                current: forecast.current.1,
+                forecasts: forecast.forecasts.clone(),
+            });
+        }
+        let process = |value| {
+            processors
+                .iter()
+                .fold(value, |value, processor| processor.apply(value))
+        };
+        forecast.current.1 = process(forecast.current.1);
+        for value in forecast.forecasts.values_mut() {
+            *value = process(*value);
+        }
+    }
+}
+
+fn parse_list(key: &str, value: &str) -> Result<Vec<Processor>, ProcessingError> {
+    let format = || ProcessingError::Format {
+        key: key.to_string(),
+        value: value.to_string(),
+    };
+    let mut processors = Vec::new();
+    for entry in value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+    {
+        let (name, argument) = entry
+            .split_once('=')
+            .map(|(name, argument)| (name.trim(), argument.trim()))
+            .ok_or_else(format)?;
+        let processor = match name {
+            "convert" => {
+                let (factor, offset) = argument.split_once(':').unwrap_or((argument, "0"));
+                let convert = ProcessingError::Convert {
+                    key: key.to_string(),
+                    value: argument.to_string(),
+                };
+                let (Ok(factor), Ok(offset)) =
+                    (factor.trim().parse::<f64>(), offset.trim().parse::<f64>())
+                else {
+                    return Err(convert);
+                };
+                if !factor.is_finite() || factor == 0.0 || !offset.is_finite() {
+                    return Err(convert);
+                }
+                Processor::Convert { factor, offset }
+            }
+            "round" => match argument.parse() {
+                Ok(places) if places <= MAX_PLACES => Processor::Round { places },
+                _ => {
+                    return Err(ProcessingError::Round {
+                        key: key.to_string(),
+                        value: argument.to_string(),
+                    })
+                }
+            },
+            _ => return Err(format()),
+        };
+        processors.push(processor);
+    }
+    Ok(processors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locations::{parse_forecast, LOCATIONS};
+
+    const BODY: &str = r#"{"vorhersageZeit": "2024-03-07 08:05", "lat": 52.9, "lon": 8.2,
+        "aktuell": {"2024-03-07 08:05": 1.234}, "vorhersage": {"2024-03-07 08:10": 0.56}}"#;
+
+    fn processing(vars: &[(&str, &str)]) -> Result<Processing, ProcessingError> {
+        Processing::from_lookup(
+            |key| {
+                vars.iter()
+                    .find(|(name, _)| *name == key)
+                    .map(|(_, value)| value.to_string())
+            },
+            &LOCATIONS.locations[..2],
+        )
+    }
+
+    fn processed(processing: &Processing, location: usize) -> Forecast {
+        let mut forecast = parse_forecast(BODY.to_string()).unwrap();
+        processing.apply(&LOCATIONS.locations[location], &mut forecast);
+        forecast
+    }
+
+    #[test]
+    fn converts_units() {
+        let convert = Processor::Convert {
+            factor: 2.7778,
+            offset: 0.0,
+        };
+        assert!((convert.apply(1.5) - 4.1667).abs() < 1e-9);
+        let celsius = Processor::Convert {
+            factor: 1.8,
+            offset: 32.0,
+        };
+        assert_eq!(celsius.apply(10.0), 50.0);
+    }
+
+    #[test]
+    fn rounds_values() {
+        assert_eq!(Processor::Round { places: 1 }.apply(4.16675), 4.2);
+        assert_eq!(Processor::Round { places: 2 }.apply(-0.125), -0.13);
+        assert_eq!(Processor::Round { places: 0 }.apply(412.5), 413.0);
+    }
+
+    #[test]
+    fn applies_in_order() {
+        let rounded_first = processing(&[("PROCESSING", "round=0,convert=10")]).unwrap();
+        let forecast = processed(&rounded_first, 0);
+        assert_eq!(forecast.current.1, 10.0);
+        assert_eq!(forecast.forecasts["2024-03-07 08:10"], 10.0);
+
+        let converted_first = processing(&[("PROCESSING", "convert=10,round=0")]).unwrap();
+        let forecast = processed(&converted_first, 0);
+        assert_eq!(forecast.current.1, 12.0);
+        assert_eq!(forecast.forecasts["2024-03-07 08:10"], 6.0);
+        assert_eq!(forecast.raw, None);
+    }
+
+    #[test]
+    fn configures_per_location() {
+        let processing =
+            processing(&[("PROCESSING", "round=1"), ("PROCESSING_WW_MARIENHAFE", "")]).unwrap();
+        assert_eq!(processing.of(&LOCATIONS.locations[0]).len(), 1);
+        assert!(processing.of(&LOCATIONS.locations[1]).is_empty());
+        assert_eq!(processed(&processing, 0).current.1, 1.2);
+        assert_eq!(processed(&processing, 1).current.1, 1.234);
+    }
+
+    #[test]
+    fn keeps_raw_values() {
+        let processing = processing(&[
+            ("PROCESSING", "convert=2"),
+            ("PROCESSING_WW_MARIENHAFE", ""),
+            ("PROCESSING_KEEP_RAW", "true"),
+        ])
+        .unwrap();
+        let forecast = processed(&processing, 0);
+        assert_eq!(forecast.current.1, 2.468);
+        assert_eq!(
+            forecast.raw,
+            Some(RawValues {
+                current: 1.234,
+                forecasts: BTreeMap::from([("2024-03-07 08:10".to_string(), 0.56)]),
+            })
+        );
+
+        // nothing to keep for unprocessed forecasts
+        assert_eq!(processed(&processing, 1).raw, None);
+    }
+
+    #[test]
+    fn rejects_invalid_processors() {
+        assert_eq!(processing(&[]).unwrap(), Processing::default());
+        for value in ["convert", "scale=2", "round:1", "=1"] {
+            assert!(
+                matches!(
+                    processing(&[("PROCESSING", value)]),
+                    Err(ProcessingError::Format { .. })
+                ),
+                "{value}"
+            );
+        }
+        for value in ["convert=0", "convert=inf", "convert=2:x", "convert=mm"] {
+            assert!(
+                matches!(
+                    processing(&[("PROCESSING", value)]),
+                    Err(ProcessingError::Convert { .. })
+                ),
+                "{value}"
+            );
+        }
+        assert_eq!(
+            processing(&[("PROCESSING_WW_GROSSENKNETEN", "round=13")]),
+            Err(ProcessingError::Round {
+                key: "PROCESSING_WW_GROSSENKNETEN".to_string(),
+                value: "13".to_string()
+            })
+        );
+        assert_eq!(
+            processing(&[("PROCESSING_KEEP_RAW", "yes")]),
+            Err(ProcessingError::KeepRaw("yes".to_string()))
+        );
+    }
+}
@@ -0,0 +1,277 @@
+use crate::fixture;
+use crate::locations::{Location, CONFIGURED};
+use crate::severity;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::sync::Arc;
+use thiserror::Error;
+
+/// Variables naming files or addresses, never shared between profiles as they would write
+/// into each other's files or fail to bind.
+const UNSHARED: [&str; 6] = [
+    "STATE_FILE",
+    "SPOOL_PATH",
+    "ARCHIVE_DIR",
+    "HTTP_ADDR",
+    "GRPC_ADDR",
+    "PRUNE_DIRS",
+];
+
+/// Variables read once for the whole process, like the `SEVERITY_<KIND>` ones, so no profile
+/// can set its own.
+const PROCESS_WIDE: [&str; 7] = [
+    "FIELD_NAME_MAP",
+    "TAG_NAME_MAP",
+    "REDACT_COORDINATES",
+    "GEO_FIELDS",
+    "INFLUX_FIELD_LIMIT",
+    "COORDINATE_DECIMALS",
+    "GAPS_IN_RESOLVED",
+];
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ProfileError {
+    #[error("invalid profile name {0:?}, expected lower case letters, digits and `-`")]
+    Name(String),
+
+    #[error("profile {0:?} is listed twice")]
+    Duplicate(String),
+
+    #[error("profile {profile:?}, unknown location {location:?}")]
+    Location { profile: String, location: String },
+
+    #[error("profile {profile:?}, {key} is not read as {shared} applies to every profile")]
+    ProcessWide {
+        profile: String,
+        key: String,
+        shared: String,
+    },
+}
+
+type Lookup = Arc<dyn Fn(&str) -> Option<String> + Send + Sync>;
+
+/// Independent collection of one project, configured via `PROFILES` like `harz,weser`.
+///
+/// Each profile has its own locations, buckets, webhooks and interval, but they share the
+/// process, its http client and the health listener. A variable of a profile is read with the
+/// upper cased name as prefix first, like `HARZ_INFLUXDB_BUCKET`, falling back to the shared
+/// unprefixed one. The locations are listed by name, slug or id in `<NAME>_LOCATIONS`, every
+/// configured location without it.
+///
+/// The point names, the severities, the coordinate and field settings and `GAPS_IN_RESOLVED` are
+/// read once for the whole process, a profile setting them with its prefix fails the startup.
+///
+/// Without `PROFILES` there is a single unnamed profile reading the variables as they are.
+#[derive(Clone)]
+pub struct Profile {
+    name: Option<String>,
+    lookup: Lookup,
+}
+
+impl fmt::Debug for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Profile").field("name", &self.name).finish()
+    }
+}
+
+impl Profile {
+    /// The only profile when none are configured.
+    pub fn unnamed(lookup: impl Fn(&str) -> Option<String> + Send + Sync + 'static) -> Profile {
+        Profile {
+            name: None,
+            lookup: Arc::new(lookup),
+        }
+    }
+
+    /// Reads the profiles listed in `PROFILES` which `lookup` returns, or the unnamed one.
+    pub fn from_lookup(
+        lookup: impl Fn(&str) -> Option<String> + Send + Sync + 'static,
+    ) -> Result<Vec<Profile>, ProfileError> {
+        let lookup: Lookup = Arc::new(lookup);
+        let Some(value) = lookup("PROFILES").filter(|value| !value.trim().is_empty()) else {
+            return Ok(vec![Profile { name: None, lookup }]);
+        };
+        let mut names = BTreeSet::new();
+        let mut profiles = Vec::new();
+        for name in value.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            let valid = name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+            if !valid || name.starts_with('-') {
+                return Err(ProfileError::Name(name.to_string()));
+            }
+            if !names.insert(name) {
+                return Err(ProfileError::Duplicate(name.to_string()));
+            }
+            let process_wide = PROCESS_WIDE.map(String::from);
+            for shared in process_wide.into_iter().chain(severity::all_keys()) {
+                let key = prefixed(name, &shared);
+                if lookup(&key).is_some() {
+                    let profile = name.to_string();
+                    return Err(ProfileError::ProcessWide {
+                        profile,
+                        key,
+                        shared,
+                    });
+                }
+            }
+            profiles.push(Profile {
+                name: Some(name.to_string()),
+                lookup: lookup.clone(),
+            });
+        }
+        Ok(profiles)
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// The variable `key` of the profile.
+    pub fn var(&self, key: &str) -> Option<String> {
+        let Some(name) = &self.name else {
+            return (self.lookup)(key);
+        };
+        (self.lookup)(&prefixed(name, key)).or_else(|| match UNSHARED.contains(&key) {
+            true => None,
+            false => (self.lookup)(key),
+        })
+    }
+
+    /// The configured locations collected by the profile.
+    pub fn locations(&self) -> Result<Vec<Location>, ProfileError> {
+        let Some(listed) = self.var("LOCATIONS") else {
+            return Ok(CONFIGURED.to_vec());
+        };
+        listed
+            .split(',')
+            .map(str::trim)
+            .filter(|location| !location.is_empty())
+            .map(|location| {
+                fixture::find_location(location)
+                    .cloned()
+                    .ok_or_else(|| ProfileError::Location {
+                        profile: self.name.clone().unwrap_or_default(),
+                        location: location.to_string(),
+                    })
+            })
+            .collect()
+    }
+
+    /// Tags the points and prefixes the alerts, like `[harz]`, empty for the unnamed profile.
+    pub fn prefix(&self) -> String {
+        match &self.name {
+            Some(name) => format!("[{name}]"),
+            None => String::new(),
+        }
+    }
+}
+
+/// The `key` of the profile `name`, like `HARZ_INFLUXDB_BUCKET`.
+fn prefixed(name: &str, key: &str) -> String {
+    format!("{}_{key}", name.to_uppercase().replace('-', "_"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locations::LOCATIONS;
+    use std::collections::BTreeMap;
+
+    fn profiles(vars: &[(&str, &str)]) -> Result<Vec<Profile>, ProfileError> {
+        let vars: BTreeMap<String, String> = vars
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        Profile::from_lookup(move |key| vars.get(key).cloned())
+    }
+
+    #[test]
+    fn unnamed_without_profiles() {
+        let profiles = profiles(&[("INFLUXDB_BUCKET", "swat")]).unwrap();
+        assert_eq!(profiles.len(), 1);
+        assert_eq!(profiles[0].name(), None);
+        assert_eq!(profiles[0].prefix(), "");
+        assert_eq!(profiles[0].var("INFLUXDB_BUCKET").unwrap(), "swat");
+        assert_eq!(
+            profiles[0].locations().unwrap().len(),
+            LOCATIONS.locations.len()
+        );
+    }
+
+    #[test]
+    fn prefixed_variables() {
+        let profiles = profiles(&[
+            ("PROFILES", "harz, weser-ems"),
+            ("INFLUXDB_BUCKET", "swat"),
+            ("HARZ_INFLUXDB_BUCKET", "harz"),
+            ("STATE_FILE", "/var/lib/swat/state.json"),
+            ("WESER_EMS_STATE_FILE", "/var/lib/swat/weser-ems.json"),
+            ("HARZ_LOCATIONS", "WW Großenkneten, 3"),
+        ])
+        .unwrap();
+        let [harz, weser] = &profiles[..] else {
+            panic!("expected two profiles, got {profiles:?}");
+        };
+        assert_eq!(harz.name(), Some("harz"));
+        assert_eq!(weser.prefix(), "[weser-ems]");
+
+        assert_eq!(harz.var("INFLUXDB_BUCKET").unwrap(), "harz");
+        assert_eq!(weser.var("INFLUXDB_BUCKET").unwrap(), "swat");
+
+        // files are never shared
+        assert_eq!(harz.var("STATE_FILE"), None);
+        assert_eq!(
+            weser.var("STATE_FILE").unwrap(),
+            "/var/lib/swat/weser-ems.json"
+        );
+
+        let locations: Vec<_> = harz.locations().unwrap().iter().map(|l| l.id).collect();
+        assert_eq!(locations, [LOCATIONS.locations[0].id, 3]);
+        assert_eq!(weser.locations().unwrap().len(), LOCATIONS.locations.len());
+    }
+
+    #[test]
+    fn invalid_profiles() {
+        assert_eq!(
+            profiles(&[("PROFILES", "harz,Weser")]).unwrap_err(),
+            ProfileError::Name("Weser".to_string())
+        );
+        assert_eq!(
+            profiles(&[("PROFILES", "harz,harz")]).unwrap_err(),
+            ProfileError::Duplicate("harz".to_string())
+        );
+        let profiles = profiles(&[("PROFILES", "harz"), ("HARZ_LOCATIONS", "Atlantis")]).unwrap();
+        assert_eq!(
+            profiles[0].locations().unwrap_err().to_string(),
+            "profile \"harz\", unknown location \"Atlantis\""
+        );
+    }
+
+    #[test]
+    fn rejects_process_wide_variables() {
+        let rejected = |key: &str| {
+            profiles(&[("PROFILES", "harz,weser-ems"), (key, "x")])
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            rejected("HARZ_REDACT_COORDINATES"),
+            "profile \"harz\", HARZ_REDACT_COORDINATES is not read as REDACT_COORDINATES \
+             applies to every profile"
+        );
+        assert_eq!(
+            rejected("WESER_EMS_SEVERITY_REQUEST_FORECAST"),
+            "profile \"weser-ems\", WESER_EMS_SEVERITY_REQUEST_FORECAST is not read as \
+             SEVERITY_REQUEST_FORECAST applies to every profile"
+        );
+        assert!(matches!(
+            profiles(&[("PROFILES", "harz"), ("HARZ_FIELD_NAME_MAP", "x")]).unwrap_err(),
+            ProfileError::ProcessWide { shared, .. } if shared == "FIELD_NAME_MAP"
+        ));
+
+        // the shared ones apply to every profile
+        let shared = profiles(&[("PROFILES", "harz"), ("REDACT_COORDINATES", "true")]);
+        assert_eq!(shared.unwrap().len(), 1);
+    }
+}
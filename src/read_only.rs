@@ -0,0 +1,49 @@
+use thiserror::Error;
+
+/// Suffix of the only buckets written into with `READ_ONLY`.
+pub const STAGING_SUFFIX: &str = "-staging";
+
+/// Tag of the collector's own points, set while it runs read-only.
+pub const TAG: &str = "read_only";
+
+/// Answer to the requests changing a read-only collector, like muting it.
+#[cfg_attr(not(any(feature = "health-check", feature = "grpc")), allow(dead_code))]
+pub const REJECTED: &str = "rejected, the collector is read-only";
+
+/// Heads the startup log and the status of a read-only collector.
+pub const BANNER: &str = "READ-ONLY, writing only into staging buckets, logging alerts instead of \
+                          sending them, rejecting control commands";
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("READ_ONLY writes only into buckets ending in {STAGING_SUFFIX:?}, refusing to write into {}", .0.join(", "))]
+pub struct ReadOnlyError(Vec<String>);
+
+/// Checks that every bucket of `buckets` is a staging bucket, so a read-only collector pointed
+/// at the production buckets refuses to start.
+pub fn check_buckets<'b>(buckets: impl IntoIterator<Item = &'b str>) -> Result<(), ReadOnlyError> {
+    let production: Vec<_> = (buckets.into_iter())
+        .filter(|bucket| !bucket.ends_with(STAGING_SUFFIX))
+        .map(|bucket| format!("{bucket:?}"))
+        .collect();
+    match production.is_empty() {
+        true => Ok(()),
+        false => Err(ReadOnlyError(production)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_bucket_suffix() {
+        assert_eq!(check_buckets(["swat-staging", "research-staging"]), Ok(()));
+        assert_eq!(check_buckets([]), Ok(()));
+        let err = check_buckets(["swat-staging", "swat", "swat-staging-old"]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "READ_ONLY writes only into buckets ending in \"-staging\", refusing to write into \
+             \"swat\", \"swat-staging-old\""
+        );
+    }
+}
@@ -0,0 +1,194 @@
+use crate::coordinates;
+use once_cell::sync::Lazy;
+use std::borrow::Cow;
+use std::env;
+
+/// Whether coordinates are rounded in the logs, alerts and the status, configurable via
+/// `REDACT_COORDINATES`.
+///
+/// The requests to the swat api and the tags in InfluxDB keep the decimal places of
+/// [`coordinates::DECIMALS`].
+pub static REDACT_COORDINATES: Lazy<bool> = Lazy::new(|| match env::var("REDACT_COORDINATES") {
+    Ok(enabled) => enabled
+        .parse()
+        .unwrap_or_else(|err| panic!("expected \"REDACT_COORDINATES\" to be valid, {err}")),
+    Err(_) => false,
+});
+
+/// Keys the coordinates follow in urls, response bodies and line protocol.
+const KEYS: [&str; 4] = ["latitude", "longitude", "lat", "lon"];
+
+/// A single coordinate, values that are no number are hidden entirely when redacting.
+fn shown(value: &str, redact: bool) -> Cow<'_, str> {
+    match (redact, value.trim().parse::<f64>()) {
+        (false, _) => Cow::Borrowed(value),
+        (true, Ok(value)) if value.is_finite() => Cow::Owned(coordinates::format(value, 1)),
+        (true, _) => Cow::Borrowed("?"),
+    }
+}
+
+/// The `text` with the coordinates in it rounded if [`REDACT_COORDINATES`] is set.
+///
+/// Coordinates are the numbers following a `lat` or `lon` key, like `lat=52.91` in the request
+/// urls within errors or `"lat": 52.91` in logged response bodies.
+pub fn text(text: &str) -> Cow<'_, str> {
+    text_with(text, *REDACT_COORDINATES)
+}
+
+/// The `text` with the coordinates in it rounded if `redact` is set.
+pub fn text_with(text: &str, redact: bool) -> Cow<'_, str> {
+    if !redact {
+        return Cow::Borrowed(text);
+    }
+    let mut redacted = String::new();
+    let mut copied = 0;
+    let mut rest = 0;
+    while let Some((start, end)) = next_value(text, rest) {
+        let value = &text[start..end];
+        redacted.push_str(&text[copied..start]);
+        redacted.push_str(&shown(value, true));
+        copied = end;
+        rest = end;
+    }
+    match copied {
+        0 => Cow::Borrowed(text),
+        _ => {
+            redacted.push_str(&text[copied..]);
+            Cow::Owned(redacted)
+        }
+    }
+}
+
+/// The byte range of the next coordinate in `text` from `from` on.
+fn next_value(text: &str, from: usize) -> Option<(usize, usize)> {
+    let bytes = text.as_bytes();
+    let mut position = from;
+    while position < bytes.len() {
+        let key = KEYS
+            .iter()
+            .find(|key| bytes[position..].starts_with(key.as_bytes()));
+        let starts_word = position == 0 || !bytes[position - 1].is_ascii_alphanumeric();
+        let Some(key) = key.filter(|_| starts_word) else {
+            position += 1;
+            continue;
+        };
+        let mut cursor = position + key.len();
+        if bytes.get(cursor) == Some(&b'"') {
+            cursor += 1;
+        }
+        while bytes.get(cursor) == Some(&b' ') {
+            cursor += 1;
+        }
+        if !matches!(bytes.get(cursor), Some(b'=' | b':')) {
+            position = cursor;
+            continue;
+        }
+        cursor += 1;
+        while bytes.get(cursor) == Some(&b' ') {
+            cursor += 1;
+        }
+        let start = cursor;
+        while bytes.get(cursor).is_some_and(|byte| {
+            byte.is_ascii_digit() || matches!(byte, b'-' | b'+' | b'.' | b'e' | b'E')
+        }) {
+            cursor += 1;
+        }
+        if cursor > start {
+            return Some((start, cursor));
+        }
+        position = cursor;
+    }
+    None
+}
+
+/// Rounds the coordinates in the query of the `url` if [`REDACT_COORDINATES`] is set, for
+/// the urls in request errors.
+pub fn url(url: &mut url::Url) {
+    redact_url(url, *REDACT_COORDINATES);
+}
+
+fn redact_url(url: &mut url::Url, redact: bool) {
+    let query = url
+        .query()
+        .map(|query| text_with(query, redact).into_owned());
+    url.set_query(query.as_deref());
+}
+
+/// Whether `text` contains a coordinate with more than one decimal place.
+#[cfg(test)]
+pub fn has_precise_coordinates(text: &str) -> bool {
+    let mut rest = 0;
+    while let Some((start, end)) = next_value(text, rest) {
+        let decimals = text[start..end]
+            .split_once('.')
+            .map_or(0, |(_, decimals)| decimals.len());
+        if decimals > 1 {
+            return true;
+        }
+        rest = end;
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shows_coordinates() {
+        assert_eq!(shown("52.9109818816186", false), "52.9109818816186");
+        assert_eq!(shown("52.9109818816186", true), "52.9");
+        assert_eq!(shown("8.25", true), "8.3");
+        assert_eq!(shown("-0.04", true), "0.0");
+        assert_eq!(shown("n/a", true), "?");
+    }
+
+    #[test]
+    fn redacts_texts() {
+        let error = "request failed, error sending request for url \
+                     (http://127.0.0.1:8080/Vorhersage?lat=52.9109818816186&lon=8.23505277402053)";
+        assert_eq!(text_with(error, false), error);
+        let redacted = text_with(error, true);
+        assert_eq!(
+            redacted,
+            "request failed, error sending request for url \
+             (http://127.0.0.1:8080/Vorhersage?lat=52.9&lon=8.2)"
+        );
+        assert!(has_precise_coordinates(error));
+        assert!(!has_precise_coordinates(&redacted));
+
+        let body =
+            r#"{"vorhersageZeit": "2024-05-01 12:00", "lat": 52.9109818816186, "lon":8.235}"#;
+        assert_eq!(
+            text_with(body, true),
+            r#"{"vorhersageZeit": "2024-05-01 12:00", "lat": 52.9, "lon":8.2}"#
+        );
+        let line = "forecast,lat=52.91,lon=8.23 latitude=52.91,longitude=8.23";
+        assert_eq!(
+            text_with(line, true),
+            "forecast,lat=52.9,lon=8.2 latitude=52.9,longitude=8.2"
+        );
+
+        // words merely containing the keys stay
+        let unrelated = "along=12.345 salon: 3.21 lat is unknown";
+        assert!(matches!(text_with(unrelated, true), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn redacts_urls() {
+        let mut url: url::Url = "https://swat.itwh.de/Vorhersage?lat=52.9109818816186&lon=8.2350"
+            .parse()
+            .unwrap();
+        redact_url(&mut url, false);
+        assert_eq!(url.query(), Some("lat=52.9109818816186&lon=8.2350"));
+        redact_url(&mut url, true);
+        assert_eq!(
+            url.as_str(),
+            "https://swat.itwh.de/Vorhersage?lat=52.9&lon=8.2"
+        );
+
+        let mut url: url::Url = "https://swat.itwh.de/health".parse().unwrap();
+        redact_url(&mut url, true);
+        assert_eq!(url.as_str(), "https://swat.itwh.de/health");
+    }
+}
@@ -0,0 +1,604 @@
+use crate::clock::Clock;
+use crate::error_kind::ErrorKind;
+use crate::locations::RequestLocationError;
+use crate::sink::WriteError;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::fmt;
+use std::future::Future;
+use std::str::FromStr;
+use std::time::Duration;
+use thiserror::Error;
+
+/// Attempts kept by an [`AttemptLog`], older ones are only counted.
+pub const MAX_LOGGED: usize = 10;
+
+/// Delay before the first retry unless `REQUEST_RETRY_DELAY_MS` or `WRITE_RETRY_DELAY_MS` is
+/// set, doubled for every further one.
+pub const DEFAULT_DELAY: Duration = Duration::from_secs(1);
+
+/// Retries per collected location and tick unless `RETRY_BUDGET` is set.
+pub const DEFAULT_BUDGET_PER_TARGET: u32 = 2;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("expected {0:?} to be valid, {1}")]
+pub struct RetryError(&'static str, String);
+
+/// How an attempt ended, as short as it is listed in the alerts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttemptOutcome {
+    Succeeded,
+    Timeout,
+    Status(u16),
+    Failed(ErrorKind),
+}
+
+impl fmt::Display for AttemptOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AttemptOutcome::Succeeded => f.write_str("ok"),
+            AttemptOutcome::Timeout => f.write_str("timeout"),
+            AttemptOutcome::Status(status) => write!(f, "{status}"),
+            AttemptOutcome::Failed(kind) => write!(f, "{kind}"),
+        }
+    }
+}
+
+/// A failed attempt and how long it was waited for the next one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Attempt {
+    pub outcome: AttemptOutcome,
+    pub delay: Duration,
+}
+
+/// What a retried operation went through, like `3 attempts over 7s: timeout, 503, timeout`,
+/// so responders can tell whether retrying by hand is worth it.
+///
+/// Nothing is allocated unless an attempt fails, at most [`MAX_LOGGED`] attempts are kept.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AttemptLog {
+    attempts: Vec<Attempt>,
+    count: usize,
+    elapsed: Duration,
+
+    /// Whether a retry was due but the [`RetryBudget`] of the tick was used up.
+    budget_exhausted: bool,
+}
+
+impl AttemptLog {
+    /// Records an attempt ending with the `outcome`, followed by waiting `delay`.
+    ///
+    /// Successes are only counted unless an attempt failed before.
+    pub fn record(&mut self, outcome: AttemptOutcome, delay: Duration) {
+        self.count += 1;
+        if outcome == AttemptOutcome::Succeeded && self.attempts.is_empty() {
+            return;
+        }
+        if self.attempts.len() == MAX_LOGGED {
+            self.attempts.remove(0);
+        }
+        self.attempts.push(Attempt { outcome, delay });
+    }
+
+    /// Sets the time from the first attempt until the last one ended.
+    pub fn set_elapsed(&mut self, elapsed: Duration) {
+        self.elapsed = elapsed;
+    }
+
+    /// Number of attempts made, including those no longer kept.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Whether more than one attempt was made.
+    pub fn retried(&self) -> bool {
+        self.count > 1
+    }
+
+    /// Whether the operation failed fast, as the [`RetryBudget`] of the tick was used up.
+    pub fn budget_exhausted(&self) -> bool {
+        self.budget_exhausted
+    }
+
+    /// The most recent attempts, the oldest first.
+    pub fn attempts(&self) -> &[Attempt] {
+        &self.attempts
+    }
+}
+
+impl fmt::Display for AttemptLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.count {
+            1 => write!(f, "1 attempt")?,
+            count => write!(f, "{count} attempts")?,
+        }
+        match self.elapsed.as_millis() {
+            0..=999 => write!(f, " over {}ms", self.elapsed.as_millis())?,
+            _ => write!(f, " over {}s", self.elapsed.as_secs_f64().round())?,
+        }
+        let outcomes: Vec<_> = self
+            .attempts
+            .iter()
+            .map(|attempt| attempt.outcome.to_string())
+            .collect();
+        match self.count - outcomes.len() {
+            _ if outcomes.is_empty() => (),
+            0 => write!(f, ": {}", outcomes.join(", "))?,
+            _ => write!(f, ": …, {}", outcomes.join(", "))?,
+        }
+        if self.budget_exhausted {
+            write!(f, ", retry budget exhausted")?;
+        }
+        Ok(())
+    }
+}
+
+/// Errors of an operation that may be retried.
+pub trait Retriable {
+    /// How the attempt ended, for the [`AttemptLog`].
+    fn outcome(&self) -> AttemptOutcome;
+
+    /// Whether another attempt could succeed.
+    fn retriable(&self) -> bool;
+}
+
+impl Retriable for RequestLocationError {
+    fn outcome(&self) -> AttemptOutcome {
+        match (self.kind(), self.status()) {
+            (ErrorKind::RequestTimeout | ErrorKind::ConnectTimeout, _) => AttemptOutcome::Timeout,
+            (_, Some(status)) => AttemptOutcome::Status(status),
+            (kind, None) => AttemptOutcome::Failed(kind),
+        }
+    }
+
+    /// Only failures to reach the api are retried, a forecast that cannot be parsed stays so.
+    fn retriable(&self) -> bool {
+        self.kind().is_request()
+    }
+}
+
+impl RequestLocationError {
+    fn status(&self) -> Option<u16> {
+        match self {
+            RequestLocationError::Request(err) => err.status().map(|status| status.as_u16()),
+            #[cfg(feature = "chaos")]
+            RequestLocationError::Synthetic(crate::chaos::Fault::ServerError(status)) => {
+                Some(*status)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Retriable for WriteError {
+    fn outcome(&self) -> AttemptOutcome {
+        match &self.error {
+            influxdb2::RequestError::Http { status, .. } => AttemptOutcome::Status(status.as_u16()),
+            influxdb2::RequestError::ReqwestProcessing { source } if source.is_timeout() => {
+                AttemptOutcome::Timeout
+            }
+            _ => AttemptOutcome::Failed(ErrorKind::InfluxWrite),
+        }
+    }
+
+    /// Rejected points are rejected again, only server errors and unreachable servers are
+    /// retried.
+    fn retriable(&self) -> bool {
+        match &self.error {
+            influxdb2::RequestError::Http { status, .. } => {
+                status.is_server_error() || status.as_u16() == 429
+            }
+            influxdb2::RequestError::ReqwestProcessing { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+/// How often an operation is attempted and how long it is waited in between.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub attempts: u32,
+    pub delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            attempts: 1,
+            delay: DEFAULT_DELAY,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Attempts the operation until it succeeds, fails for good or the attempts are used up,
+    /// doubling the delay after every failed attempt.
+    ///
+    /// Every retry is taken from the `budget` of the tick, once it is used up the operation
+    /// fails fast.
+    pub async fn run<T, E, Fut>(
+        &self,
+        clock: &dyn Clock,
+        budget: &RetryBudget,
+        mut attempt: impl FnMut() -> Fut,
+    ) -> (Result<T, E>, AttemptLog)
+    where
+        Fut: Future<Output = Result<T, E>>,
+        E: Retriable,
+    {
+        let started = clock.now_instant();
+        let mut log = AttemptLog::default();
+        let mut delay = self.delay;
+        loop {
+            let result = attempt().await;
+            let outcome = match &result {
+                Ok(_) => AttemptOutcome::Succeeded,
+                Err(err) => err.outcome(),
+            };
+            let mut retry = matches!(&result, Err(err) if err.retriable())
+                && log.count() + 1 < self.attempts as usize;
+            if retry && !budget.take() {
+                log.budget_exhausted = true;
+                retry = false;
+            }
+            if !retry {
+                log.record(outcome, Duration::ZERO);
+                log.set_elapsed(clock.now_instant().duration_since(started));
+                return (result, log);
+            }
+            log.record(outcome, delay);
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+        }
+    }
+}
+
+/// Retries granted and denied by a [`RetryBudget`] in a tick.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct BudgetUsage {
+    pub limit: u32,
+    pub used: u32,
+    pub denied: u32,
+}
+
+/// Retries left in the current tick, shared by the forecast requests, the writes and the
+/// webhook deliveries so that their policies do not multiply during an outage.
+///
+/// Unlimited until [refilled](Self::refill) at the start of a tick.
+#[derive(Debug)]
+pub struct RetryBudget {
+    usage: Mutex<BudgetUsage>,
+}
+
+impl Default for RetryBudget {
+    fn default() -> Self {
+        RetryBudget {
+            usage: Mutex::new(BudgetUsage {
+                limit: u32::MAX,
+                used: 0,
+                denied: 0,
+            }),
+        }
+    }
+}
+
+impl RetryBudget {
+    /// Grants `limit` retries to the tick starting.
+    pub fn refill(&self, limit: u32) {
+        *self.usage.lock() = BudgetUsage {
+            limit,
+            used: 0,
+            denied: 0,
+        };
+    }
+
+    /// Takes a retry from the budget, returns whether one was left.
+    pub fn take(&self) -> bool {
+        let mut usage = self.usage.lock();
+        let granted = usage.used < usage.limit;
+        match granted {
+            true => usage.used += 1,
+            false => usage.denied += 1,
+        }
+        granted
+    }
+
+    /// The retries granted and denied in the current tick.
+    pub fn usage(&self) -> BudgetUsage {
+        *self.usage.lock()
+    }
+
+    /// Describes the usage of the budget for the status page, empty unless retries were denied.
+    #[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+    pub fn status_text(&self) -> String {
+        let usage = self.usage();
+        match usage.denied {
+            0 => String::new(),
+            denied => format!(
+                "retry budget of {} retries used up, {denied} retries denied this tick\n",
+                usage.limit
+            ),
+        }
+    }
+}
+
+/// The policies of the forecast requests and of the writes into InfluxDB, attempted once each
+/// unless configured otherwise.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Retries {
+    pub request: RetryPolicy,
+    pub write: RetryPolicy,
+
+    /// Retries per tick, from `RETRY_BUDGET`, twice the collected locations if unset.
+    pub budget: Option<u32>,
+}
+
+impl Retries {
+    /// The retries granted to a tick collecting `targets` locations and models.
+    pub fn budget(&self, targets: usize) -> u32 {
+        (self.budget).unwrap_or(DEFAULT_BUDGET_PER_TARGET.saturating_mul(targets as u32))
+    }
+
+    /// Reads `REQUEST_ATTEMPTS`, `REQUEST_RETRY_DELAY_MS`, `WRITE_ATTEMPTS`,
+    /// `WRITE_RETRY_DELAY_MS` and `RETRY_BUDGET` which `lookup` returns.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Retries, RetryError> {
+        let parse = |key: &'static str| -> Result<Option<u64>, RetryError> {
+            lookup(key)
+                .map(|value| u64::from_str(value.trim()))
+                .transpose()
+                .map_err(|err| RetryError(key, err.to_string()))
+        };
+        let policy = |attempts: &'static str, delay: &'static str| {
+            let policy = RetryPolicy {
+                attempts: parse(attempts)?.map_or(1, |attempts| attempts as u32),
+                delay: parse(delay)?.map_or(DEFAULT_DELAY, Duration::from_millis),
+            };
+            match policy.attempts {
+                0 => Err(RetryError(attempts, "expected at least 1".to_string())),
+                _ => Ok(policy),
+            }
+        };
+        Ok(Retries {
+            request: policy("REQUEST_ATTEMPTS", "REQUEST_RETRY_DELAY_MS")?,
+            write: policy("WRITE_ATTEMPTS", "WRITE_RETRY_DELAY_MS")?,
+            budget: parse("RETRY_BUDGET")?.map(|budget| budget.min(u32::MAX as u64) as u32),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use crate::points::HandleLocationError;
+    use crate::timestamp;
+    use std::cell::Cell;
+
+    #[derive(Debug, Clone, Copy)]
+    struct Failure(AttemptOutcome, bool);
+
+    impl Retriable for Failure {
+        fn outcome(&self) -> AttemptOutcome {
+            self.0
+        }
+
+        fn retriable(&self) -> bool {
+            self.1
+        }
+    }
+
+    fn log(outcomes: &[AttemptOutcome], elapsed: Duration) -> AttemptLog {
+        let mut log = AttemptLog::default();
+        for &outcome in outcomes {
+            log.record(outcome, Duration::from_secs(1));
+        }
+        log.set_elapsed(elapsed);
+        log
+    }
+
+    #[test]
+    fn renders_attempts() {
+        let (timeout, unavailable) = (AttemptOutcome::Timeout, AttemptOutcome::Status(503));
+        assert_eq!(
+            log(
+                &[timeout, unavailable, timeout],
+                Duration::from_millis(7300)
+            )
+            .to_string(),
+            "3 attempts over 7s: timeout, 503, timeout"
+        );
+        assert_eq!(
+            log(&[timeout], Duration::from_millis(250)).to_string(),
+            "1 attempt over 250ms: timeout"
+        );
+        let reset = AttemptOutcome::Failed(ErrorKind::ConnectionReset);
+        assert_eq!(
+            log(&[reset, AttemptOutcome::Succeeded], Duration::from_secs(2)).to_string(),
+            "2 attempts over 2s: connection_reset, ok"
+        );
+
+        // only the most recent attempts are kept
+        let mut outcomes = vec![timeout; 11];
+        outcomes.push(unavailable);
+        let bounded = log(&outcomes, Duration::from_secs(40));
+        assert_eq!(bounded.attempts().len(), MAX_LOGGED);
+        assert_eq!(bounded.count(), 12);
+        assert_eq!(
+            bounded.to_string(),
+            "12 attempts over 40s: …, timeout, timeout, timeout, timeout, timeout, timeout, \
+             timeout, timeout, timeout, 503"
+        );
+    }
+
+    #[test]
+    fn success_allocates_nothing() {
+        let success = log(&[AttemptOutcome::Succeeded], Duration::ZERO);
+        assert_eq!(success.count(), 1);
+        assert!(!success.retried());
+        assert_eq!(success.attempts.capacity(), 0);
+        assert_eq!(success.to_string(), "1 attempt over 0ms");
+    }
+
+    #[tokio::test]
+    async fn retries_until_used_up() {
+        let clock = MockClock::new();
+        let policy = RetryPolicy {
+            attempts: 3,
+            delay: Duration::from_millis(1),
+        };
+        let budget = RetryBudget::default();
+        let calls = Cell::new(0);
+        let (result, log) = policy
+            .run(&clock, &budget, || async {
+                calls.set(calls.get() + 1);
+                Err::<(), _>(Failure(AttemptOutcome::Timeout, true))
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(calls.get(), 3);
+        let delays: Vec<_> = log.attempts().iter().map(|attempt| attempt.delay).collect();
+        assert_eq!(
+            delays,
+            [
+                Duration::from_millis(1),
+                Duration::from_millis(2),
+                Duration::ZERO
+            ]
+        );
+
+        // succeeding on the second attempt
+        calls.set(0);
+        let (result, log) = policy
+            .run(&clock, &budget, || async {
+                calls.set(calls.get() + 1);
+                match calls.get() {
+                    1 => Err(Failure(AttemptOutcome::Status(503), true)),
+                    _ => Ok(()),
+                }
+            })
+            .await;
+        assert!(result.is_ok());
+        assert!(log.retried());
+        assert_eq!(log.to_string(), "2 attempts over 0ms: 503, ok");
+
+        // failing for good
+        calls.set(0);
+        let (_, log) = policy
+            .run(&clock, &budget, || async {
+                calls.set(calls.get() + 1);
+                Err::<(), _>(Failure(AttemptOutcome::Status(400), false))
+            })
+            .await;
+        assert_eq!((calls.get(), log.count()), (1, 1));
+
+        let (result, log) = RetryPolicy::default()
+            .run(&clock, &budget, || async { Ok::<_, Failure>(()) })
+            .await;
+        assert!(result.is_ok());
+        assert_eq!(log.attempts.capacity(), 0);
+    }
+
+    #[tokio::test]
+    async fn budget_fails_fast() {
+        let clock = MockClock::new();
+        let policy = RetryPolicy {
+            attempts: 3,
+            delay: Duration::from_millis(1),
+        };
+        let budget = RetryBudget::default();
+        budget.refill(3);
+        let calls = Cell::new(0);
+        let mut logs = Vec::new();
+        for _ in 0..3 {
+            let (_, log) = policy
+                .run(&clock, &budget, || async {
+                    calls.set(calls.get() + 1);
+                    Err::<(), _>(Failure(AttemptOutcome::Timeout, true))
+                })
+                .await;
+            logs.push(log);
+        }
+        // 3 operations and the 3 retries of the budget, instead of 9 attempts
+        assert_eq!(calls.get(), 6);
+        let counts: Vec<_> = logs.iter().map(AttemptLog::count).collect();
+        assert_eq!(counts, [3, 2, 1]);
+        assert!(!logs[0].budget_exhausted());
+        assert!(logs[1].budget_exhausted());
+        assert_eq!(
+            logs[2].to_string(),
+            "1 attempt over 0ms: timeout, retry budget exhausted"
+        );
+        assert_eq!(
+            budget.usage(),
+            BudgetUsage {
+                limit: 3,
+                used: 3,
+                denied: 2
+            }
+        );
+        assert_eq!(
+            budget.status_text(),
+            "retry budget of 3 retries used up, 2 retries denied this tick\n"
+        );
+
+        // every tick starts with a full budget
+        budget.refill(3);
+        assert!(budget.take());
+        assert_eq!(budget.status_text(), "");
+    }
+
+    #[test]
+    fn annotates_errors() {
+        let error = || HandleLocationError::ParseFromTimestamp(timestamp::parse("").unwrap_err());
+        let once = log(&[AttemptOutcome::Timeout], Duration::ZERO);
+        let attempted = HandleLocationError::attempted(error(), once);
+        assert_eq!(attempted.to_string(), error().to_string());
+
+        let outcomes = [
+            AttemptOutcome::Timeout,
+            AttemptOutcome::Status(503),
+            AttemptOutcome::Timeout,
+        ];
+        let retried = log(&outcomes, Duration::from_secs(7));
+        let attempted = HandleLocationError::attempted(error(), retried);
+        assert_eq!(
+            attempted.to_string(),
+            format!("{} (3 attempts over 7s: timeout, 503, timeout)", error())
+        );
+        assert_eq!(attempted.kind(), ErrorKind::TimestampParse);
+
+        let mut exhausted = log(&[], Duration::ZERO);
+        exhausted.record(AttemptOutcome::Status(503), Duration::ZERO);
+        exhausted.budget_exhausted = true;
+        assert_eq!(
+            HandleLocationError::attempted(error(), exhausted).to_string(),
+            format!(
+                "{} (1 attempt over 0ms: 503, retry budget exhausted)",
+                error()
+            )
+        );
+    }
+
+    #[test]
+    fn reads_policies() {
+        assert_eq!(Retries::from_lookup(|_| None), Ok(Retries::default()));
+        let retries = Retries::from_lookup(|key| match key {
+            "REQUEST_ATTEMPTS" => Some("3".to_string()),
+            "WRITE_RETRY_DELAY_MS" => Some("250".to_string()),
+            _ => None,
+        })
+        .unwrap();
+        assert_eq!(retries.request.attempts, 3);
+        assert_eq!(retries.write.delay, Duration::from_millis(250));
+        assert_eq!(retries.budget(60), 120);
+        let retries =
+            Retries::from_lookup(|key| (key == "RETRY_BUDGET").then(|| "25".to_string())).unwrap();
+        assert_eq!(retries.budget(60), 25);
+        assert_eq!(
+            Retries::from_lookup(|key| (key == "WRITE_ATTEMPTS").then(|| "0".to_string()))
+                .unwrap_err()
+                .to_string(),
+            "expected \"WRITE_ATTEMPTS\" to be valid, expected at least 1"
+        );
+    }
+}
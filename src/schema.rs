@@ -0,0 +1,121 @@
+use chrono::{DateTime, Utc};
+use influxdb2::api::query::FluxRecord;
+use influxdb2::models::data_point::DataPointError;
+use influxdb2::models::DataPoint;
+use influxdb2_structmap::value::Value;
+
+/// Version of the shape of the points written, recorded by a marker point in every bucket.
+///
+/// Bump it with every change to the measurements, fields or tags existing queries depend on,
+/// like numeric fields or points per horizon.
+pub const SCHEMA_VERSION: i64 = 3;
+
+/// Measurement of the marker points.
+pub const MEASUREMENT: &str = "collector_schema";
+
+/// The schema a bucket was written with, judged by its latest marker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schema {
+    /// No marker yet, a new bucket or one written before the markers.
+    Absent,
+    Current,
+
+    /// The bucket holds points of an older shape, queries spanning both break.
+    Older(i64),
+
+    /// Written by a newer collector, this one is likely rolled back.
+    Newer(i64),
+}
+
+impl Schema {
+    pub fn of(recorded: Option<i64>) -> Schema {
+        match recorded {
+            None => Schema::Absent,
+            Some(version) if version < SCHEMA_VERSION => Schema::Older(version),
+            Some(version) if version > SCHEMA_VERSION => Schema::Newer(version),
+            Some(_) => Schema::Current,
+        }
+    }
+}
+
+/// The latest marker in `bucket`.
+pub fn query(bucket: &str) -> String {
+    format!(
+        r#"from(bucket: {bucket:?})
+            |> range(start: 0)
+            |> filter(fn: (r) => r._measurement == "{MEASUREMENT}" and r._field == "version")
+            |> group()
+            |> last()"#
+    )
+}
+
+/// The version of the latest marker in the `records` of the [query].
+pub fn recorded(records: &[FluxRecord]) -> Option<i64> {
+    records
+        .iter()
+        .filter_map(|record| {
+            let time = match record.values.get("_time") {
+                Some(Value::TimeRFC(time)) => Some(*time),
+                _ => None,
+            };
+            match record.values.get("_value") {
+                Some(Value::Long(version)) => Some((time, *version)),
+                _ => None,
+            }
+        })
+        .max()
+        .map(|(_, version)| version)
+}
+
+/// The marker of the [`SCHEMA_VERSION`] written at `now`.
+pub fn marker(now: DateTime<Utc>) -> Result<DataPoint, DataPointError> {
+    DataPoint::builder(MEASUREMENT)
+        .timestamp(now.timestamp())
+        .tag("collector_version", crate::version::VERSION)
+        .field("version", SCHEMA_VERSION)
+        .build()
+}
+
+/// How to get rid of the points of the older `version` in `bucket`.
+pub fn migration_hint(bucket: &str, version: i64) -> String {
+    format!(
+        "bucket {bucket:?} holds points of schema version {version} while this collector \
+         writes version {SCHEMA_VERSION}, queries spanning both will break. Either point the \
+         collector at a new bucket, or delete the older points together with their \
+         \"{MEASUREMENT}\" markers so the current one is written"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn record(time: &str, version: i64) -> FluxRecord {
+        FluxRecord {
+            table: 0,
+            values: BTreeMap::from([
+                ("_time".to_string(), Value::TimeRFC(time.parse().unwrap())),
+                ("_value".to_string(), Value::Long(version)),
+            ]),
+        }
+    }
+
+    #[test]
+    fn judges_markers() {
+        assert_eq!(Schema::of(recorded(&[])), Schema::Absent);
+        let records = [
+            record("2024-03-07T08:00:00Z", SCHEMA_VERSION),
+            record("2023-01-01T00:00:00Z", SCHEMA_VERSION + 1),
+        ];
+        assert_eq!(Schema::of(recorded(&records)), Schema::Current);
+        assert_eq!(
+            Schema::of(Some(SCHEMA_VERSION - 1)),
+            Schema::Older(SCHEMA_VERSION - 1)
+        );
+        assert_eq!(
+            Schema::of(Some(SCHEMA_VERSION + 1)),
+            Schema::Newer(SCHEMA_VERSION + 1)
+        );
+    }
+}
@@ -0,0 +1,786 @@
+use crate::fields;
+use crate::locations::Location;
+use crate::names::NameMapping;
+use crate::points::{HORIZON_TAG, MEASUREMENT_V2};
+use crate::severity::Severity;
+use crate::sink::Sink;
+use crate::timestamp;
+use crate::values;
+use crate::webhook::AlertField;
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use influxdb2::api::query::FluxRecord;
+use influxdb2_structmap::value::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Name of the alert field listing the issues whose representations differ.
+const NAME: &str = "schema self-check";
+
+/// Hour of the day in UTC the self-check runs at unless `SCHEMA_CHECK_HOUR` is set.
+const DEFAULT_HOUR: u32 = 3;
+
+/// Issues compared by the self-check unless `SCHEMA_CHECK_SAMPLE` is set.
+const DEFAULT_SAMPLE: usize = 20;
+
+/// Which points of a forecast are written, configurable via `SCHEMA_MODE` and switchable at
+/// runtime through the health socket.
+///
+/// The `forecast` measurement holds the horizons as a JSON string field, [`MEASUREMENT_V2`] a
+/// numeric point per horizon. Dashboards move over while both are written in the dual mode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SchemaMode {
+    #[default]
+    Legacy,
+    Dual,
+    New,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum SchemaModeError {
+    #[error("unknown schema mode {0:?}, expected one of \"legacy\", \"dual\" or \"new\"")]
+    Mode(String),
+
+    #[error("expected {0:?} to be valid, {1}")]
+    Check(&'static str, String),
+}
+
+impl SchemaMode {
+    /// Reads `SCHEMA_MODE` which `lookup` returns, [`SchemaMode::Legacy`] if it is not set.
+    pub fn from_lookup(
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<SchemaMode, SchemaModeError> {
+        lookup("SCHEMA_MODE").map_or(Ok(SchemaMode::default()), |mode| mode.parse())
+    }
+
+    /// Whether the `forecast` point is written.
+    pub fn legacy(self) -> bool {
+        self != SchemaMode::New
+    }
+
+    /// Whether the points per horizon are written.
+    pub fn v2(self) -> bool {
+        self != SchemaMode::Legacy
+    }
+
+    /// The byte the mode is sent as through the health socket.
+    #[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+    pub fn to_byte(self) -> u8 {
+        match self {
+            SchemaMode::Legacy => 0,
+            SchemaMode::Dual => 1,
+            SchemaMode::New => 2,
+        }
+    }
+
+    #[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+    pub fn from_byte(byte: u8) -> Option<SchemaMode> {
+        match byte {
+            0 => Some(SchemaMode::Legacy),
+            1 => Some(SchemaMode::Dual),
+            2 => Some(SchemaMode::New),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for SchemaMode {
+    type Err = SchemaModeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "legacy" => Ok(SchemaMode::Legacy),
+            "dual" => Ok(SchemaMode::Dual),
+            "new" => Ok(SchemaMode::New),
+            _ => Err(SchemaModeError::Mode(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for SchemaMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SchemaMode::Legacy => "legacy",
+            SchemaMode::Dual => "dual",
+            SchemaMode::New => "new",
+        })
+    }
+}
+
+/// Verifies nightly that the points per horizon reconstruct the JSON fields of the `forecast`
+/// points, while the [`SchemaMode::Dual`] is on.
+///
+/// The check samples the latest issues of the last day and rebuilds their `current` and
+/// `forecasts` fields from the numeric points, any difference is alerted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SchemaCheck {
+    /// Hour of the day in UTC the check runs at, from `SCHEMA_CHECK_HOUR`.
+    pub hour: u32,
+
+    /// Issues compared per bucket, from `SCHEMA_CHECK_SAMPLE`.
+    pub sample: usize,
+}
+
+/// An issue whose points per horizon differ from its `forecast` point.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub location: String,
+    pub model: String,
+    pub issued: DateTime<Utc>,
+    pub reason: String,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}) issued {}: {}",
+            self.location,
+            self.model,
+            self.issued.format(timestamp::FORMAT),
+            self.reason
+        )
+    }
+}
+
+/// The JSON fields of a `forecast` point.
+#[derive(Debug, Default)]
+struct LegacyIssue {
+    current: Option<String>,
+
+    /// The `forecasts` field or its numbered chunks, by their number.
+    forecasts: BTreeMap<usize, String>,
+}
+
+impl SchemaCheck {
+    pub fn from_lookup(
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<SchemaCheck, SchemaModeError> {
+        let hour = match lookup("SCHEMA_CHECK_HOUR") {
+            Some(hour) => hour
+                .trim()
+                .parse::<u32>()
+                .map_err(|err| err.to_string())
+                .and_then(|hour| match hour < 24 {
+                    true => Ok(hour),
+                    false => Err(format!("expected an hour below 24, got {hour}")),
+                })
+                .map_err(|err| SchemaModeError::Check("SCHEMA_CHECK_HOUR", err))?,
+            None => DEFAULT_HOUR,
+        };
+        let sample = match lookup("SCHEMA_CHECK_SAMPLE") {
+            Some(sample) => sample
+                .trim()
+                .parse()
+                .map_err(|err| SchemaModeError::Check("SCHEMA_CHECK_SAMPLE", format!("{err}")))?,
+            None => DEFAULT_SAMPLE,
+        };
+        Ok(SchemaCheck { hour, sample })
+    }
+
+    /// The next time the check runs after `now`.
+    pub fn next_run(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        let today = now
+            .date_naive()
+            .and_hms_opt(self.hour, 0, 0)
+            .expect("hour below 24")
+            .and_utc();
+        match today > now {
+            true => today,
+            false => today + Duration::days(1),
+        }
+    }
+
+    /// The JSON fields of the `forecast` points of the last day in `bucket`.
+    pub fn legacy_query(&self, bucket: &str, names: &NameMapping) -> String {
+        let (id, model) = (names.tag("id"), names.tag("model"));
+        let (current, forecasts) = (names.field("current"), names.field("forecasts"));
+        format!(
+            r#"import "strings"
+            from(bucket: {bucket:?})
+            |> range(start: -1d)
+            |> filter(fn: (r) => r._measurement == "forecast")
+            |> filter(fn: (r) => r._field == {current:?} or strings.hasPrefix(v: r._field, prefix: {forecasts:?}))
+            |> keep(columns: ["_time", "_field", "_value", {id:?}, {model:?}])"#
+        )
+    }
+
+    /// The points per horizon of the issue at `issued` of the location `id` and `model`.
+    pub fn v2_query(
+        &self,
+        bucket: &str,
+        (id, model, issued): (&str, &str, DateTime<Utc>),
+        names: &NameMapping,
+    ) -> String {
+        let time = |time: DateTime<Utc>| time.to_rfc3339_opts(SecondsFormat::Secs, true);
+        let (id_tag, model_tag) = (names.tag("id"), names.tag("model"));
+        format!(
+            r#"from(bucket: {bucket:?})
+            |> range(start: {}, stop: {})
+            |> filter(fn: (r) => r._measurement == "{MEASUREMENT_V2}")
+            |> filter(fn: (r) => r[{id_tag:?}] == {id:?} and r[{model_tag:?}] == {model:?})
+            |> keep(columns: ["_field", "_value", "{HORIZON_TAG}"])"#,
+            time(issued),
+            time(issued + Duration::seconds(1)),
+        )
+    }
+
+    /// Compares a sample of the issues of the last day of the `locations`, the mismatches are
+    /// named after the locations.
+    pub async fn check(
+        &self,
+        sink: &Sink,
+        locations: &[Location],
+        names: &NameMapping,
+        field_limit: usize,
+    ) -> Result<Vec<Mismatch>, influxdb2::RequestError> {
+        let mut buckets: BTreeMap<&str, Vec<&Location>> = BTreeMap::new();
+        for location in locations {
+            buckets
+                .entry(sink.bucket(location))
+                .or_default()
+                .push(location);
+        }
+        let mut mismatches = Vec::new();
+        for (bucket, locations) in buckets {
+            let records = sink.query(self.legacy_query(bucket, names)).await?;
+            let issues = legacy_issues(&records, names);
+            // the latest issues, as older ones may predate the dual mode
+            let sampled = issues
+                .iter()
+                .filter(|((id, _, _), _)| {
+                    locations
+                        .iter()
+                        .any(|location| location.id.to_string() == *id)
+                })
+                .rev()
+                .take(self.sample);
+            for ((id, model, issued), legacy) in sampled {
+                let query = self.v2_query(bucket, (id, model, *issued), names);
+                let records = sink.query(query).await?;
+                let Some(reason) = compare(legacy, &records, *issued, names, field_limit) else {
+                    continue;
+                };
+                let location = locations
+                    .iter()
+                    .find(|location| location.id.to_string() == *id)
+                    .map_or(id.clone(), |location| location.name.to_string());
+                mismatches.push(Mismatch {
+                    location,
+                    model: model.clone(),
+                    issued: *issued,
+                    reason,
+                });
+            }
+        }
+        Ok(mismatches)
+    }
+
+    /// The alert about the `mismatches`.
+    pub fn alert_field(&self, mismatches: &[Mismatch]) -> AlertField {
+        let lines: Vec<_> = mismatches.iter().map(ToString::to_string).collect();
+        AlertField::outside_tick(
+            NAME.to_string(),
+            format!(
+                "the points per horizon differ from the JSON fields of {} issues:\n{}",
+                mismatches.len(),
+                lines.join("\n")
+            ),
+            Severity::Warning,
+        )
+    }
+}
+
+/// The JSON fields of the `records` of the [legacy query](SchemaCheck::legacy_query) by
+/// location id, model and issue time, in the order of the issue times.
+fn legacy_issues(
+    records: &[FluxRecord],
+    names: &NameMapping,
+) -> BTreeMap<(String, String, DateTime<Utc>), LegacyIssue> {
+    let mut issues: BTreeMap<_, LegacyIssue> = BTreeMap::new();
+    let (current, forecasts) = (names.field("current"), names.field("forecasts"));
+    for record in records {
+        let text = |column: &str| match record.values.get(column) {
+            Some(Value::String(text)) => Some(text.clone()),
+            _ => None,
+        };
+        let (Some(id), Some(model), Some(field), Some(value)) = (
+            text(names.tag("id")),
+            text(names.tag("model")),
+            text("_field"),
+            text("_value"),
+        ) else {
+            continue;
+        };
+        let Some(Value::TimeRFC(time)) = record.values.get("_time") else {
+            continue;
+        };
+        let issue = issues
+            .entry((id, model, time.with_timezone(&Utc)))
+            .or_default();
+        if field == current {
+            issue.current = Some(value);
+        } else if field == forecasts {
+            issue.forecasts.insert(0, value);
+        } else if let Some(Ok(chunk)) = field
+            .strip_prefix(forecasts)
+            .and_then(|suffix| suffix.strip_prefix('_'))
+            .map(str::parse)
+        {
+            issue.forecasts.insert(chunk, value);
+        }
+    }
+    issues
+}
+
+/// The reason the points per horizon in `records` differ from the `legacy` fields of the issue
+/// at `issued`, if they do.
+fn compare(
+    legacy: &LegacyIssue,
+    records: &[FluxRecord],
+    issued: DateTime<Utc>,
+    names: &NameMapping,
+    field_limit: usize,
+) -> Option<String> {
+    if records.is_empty() {
+        return Some("no points per horizon".to_string());
+    }
+    let (current, forecasts) = match reconstruct(records, issued, names) {
+        Ok(reconstructed) => reconstructed,
+        Err(reason) => return Some(reason),
+    };
+    let current_json = serde_json::to_string(&values::legacy(&current)).ok()?;
+    if legacy.current.as_deref() != Some(current_json.as_str()) {
+        return Some(format!(
+            "current {current_json} instead of {}",
+            legacy.current.as_deref().unwrap_or("none")
+        ));
+    }
+    let chunks = fields::split_json_map(&values::legacy(&forecasts), field_limit).ok()?;
+    let legacy_chunks: Vec<_> = legacy.forecasts.values().cloned().collect();
+    if chunks != legacy_chunks {
+        return Some(format!(
+            "forecasts {} instead of {}",
+            chunks.concat(),
+            legacy_chunks.concat()
+        ));
+    }
+    None
+}
+
+/// The current value and the forecasts rebuilt from the points per horizon in `records`, keyed
+/// by their times like the JSON fields.
+type Reconstructed = (BTreeMap<String, f64>, BTreeMap<String, f64>);
+
+fn reconstruct(
+    records: &[FluxRecord],
+    issued: DateTime<Utc>,
+    names: &NameMapping,
+) -> Result<Reconstructed, String> {
+    let (mut current, mut forecasts) = (BTreeMap::new(), BTreeMap::new());
+    for record in records {
+        let horizon = match record.values.get(HORIZON_TAG) {
+            Some(Value::String(horizon)) => horizon
+                .parse::<i64>()
+                .map_err(|err| format!("invalid horizon {horizon:?}, {err}"))?,
+            _ => return Err(format!("point without a {HORIZON_TAG:?} tag")),
+        };
+        let value = match record.values.get("_value") {
+            Some(Value::Double(value)) => value.0,
+            Some(Value::Long(value)) => *value as f64,
+            value => return Err(format!("expected a numeric value, found {value:?}")),
+        };
+        // not the lead time, which differs across daylight saving changes and is clamped
+        let time = (issued + Duration::minutes(horizon))
+            .naive_utc()
+            .format(timestamp::FORMAT)
+            .to_string();
+        match record.values.get("_field") {
+            Some(Value::String(field)) if field == "value" => forecasts.insert(time, value),
+            Some(Value::String(field)) if field == names.field("current") => {
+                current.insert(time, value)
+            }
+            _ => continue,
+        };
+    }
+    Ok((current, forecasts))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locations::{parse_forecast, Model, Target, LOCATIONS};
+    use crate::points::{forecast_data_point, forecast_v2_points, GeoFields, LeadTimes};
+    use crate::sink::Buckets;
+    use chrono::TimeZone;
+    use influxdb2::models::DataPoint;
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+    use warp::Filter;
+
+    const BODY: &str = r#"{"vorhersageZeit": "2024-05-01 12:00", "lat": 52.91, "lon": 8.23,
+        "aktuell": {"2024-05-01 12:00": 412.4},
+        "vorhersage": {"2024-05-01 12:15": 413, "2024-05-01 12:30": 415.5,
+                       "2024-05-01 13:00": 398.49}}"#;
+
+    fn points(mode: SchemaMode) -> Vec<DataPoint> {
+        points_of(BODY, mode)
+    }
+
+    /// The points of the forecast in the response `body` written in the `mode`.
+    fn points_of(body: &str, mode: SchemaMode) -> Vec<DataPoint> {
+        let model = Model::default_model();
+        let target = Target {
+            location: &LOCATIONS.locations[0],
+            model: &model,
+        };
+        let forecast = parse_forecast(body.to_string()).unwrap();
+        let mut points = Vec::new();
+        if mode.legacy() {
+            let point =
+                forecast_data_point(target, &forecast, false, false, usize::MAX, GeoFields::Off);
+            points.push(point.unwrap());
+        }
+        if mode.v2() {
+            points.extend(
+                forecast_v2_points(
+                    target,
+                    &forecast,
+                    &NameMapping::default(),
+                    &mut LeadTimes::default(),
+                )
+                .unwrap()
+                .points,
+            );
+        }
+        points
+    }
+
+    fn line(point: DataPoint) -> String {
+        use influxdb2::models::WriteDataPoint;
+        let mut line = Vec::new();
+        point.write_data_point_to(&mut line).unwrap();
+        String::from_utf8(line).unwrap()
+    }
+
+    /// The parts of a line protocol `text` between the unescaped, unquoted `separator`s, with
+    /// their escapes kept.
+    fn split(text: &str, separator: char) -> Vec<String> {
+        let (mut parts, mut part) = (Vec::new(), String::new());
+        let (mut quoted, mut escaped) = (false, false);
+        for c in text.chars() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => quoted = !quoted,
+                _ if c == separator && !quoted => {
+                    parts.push(std::mem::take(&mut part));
+                    continue;
+                }
+                _ => (),
+            }
+            part.push(c);
+        }
+        parts.push(part);
+        parts
+    }
+
+    fn unescape(text: &str) -> String {
+        let mut unescaped = String::new();
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            unescaped.push(match c {
+                '\\' => chars.next().unwrap(),
+                c => c,
+            });
+        }
+        unescaped
+    }
+
+    /// The `line` protocol of a point as the record of a query, with the `_field` and
+    /// `_value` of `field`.
+    fn record(line: &str, field: &str) -> FluxRecord {
+        let [series, fields, time] = &split(line.trim_end(), ' ')[..] else {
+            panic!("expected a series, fields and a time in {line:?}");
+        };
+        let pair = |pair: &String| {
+            let (key, value) = pair.split_once('=').unwrap();
+            (unescape(key), value.to_string())
+        };
+        let mut values: BTreeMap<_, _> = split(series, ',')
+            .iter()
+            .skip(1)
+            .map(pair)
+            .map(|(key, value)| (key, Value::String(unescape(&value))))
+            .collect();
+        let (_, value) = split(fields, ',')
+            .iter()
+            .map(pair)
+            .find(|(key, _)| key == field)
+            .unwrap();
+        values.insert("_field".to_string(), Value::String(field.to_string()));
+        values.insert(
+            "_value".to_string(),
+            match value.strip_prefix('"') {
+                Some(text) => Value::String(unescape(text.strip_suffix('"').unwrap())),
+                None => Value::Double(value.parse::<f64>().unwrap().into()),
+            },
+        );
+        let time = Utc.timestamp_opt(time.parse().unwrap(), 0).unwrap();
+        values.insert("_time".to_string(), Value::TimeRFC(time.into()));
+        FluxRecord { table: 0, values }
+    }
+
+    fn v2_records(points: Vec<DataPoint>) -> Vec<FluxRecord> {
+        points
+            .into_iter()
+            .map(line)
+            .filter(|line| line.starts_with(MEASUREMENT_V2))
+            .flat_map(|line| {
+                let fields = split(&split(line.trim_end(), ' ')[1], ',');
+                ["value", "current"]
+                    .into_iter()
+                    .filter(|field| {
+                        let key = format!("{field}=");
+                        fields.iter().any(|pair| pair.starts_with(&key))
+                    })
+                    .map(|field| record(&line, field))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    fn legacy(points: &[DataPoint]) -> (DateTime<Utc>, LegacyIssue) {
+        let line = line(points[0].clone());
+        let records = [record(&line, "current"), record(&line, "forecasts")];
+        let issues = legacy_issues(&records, &NameMapping::default());
+        let ((_, _, issued), issue) = issues.into_iter().next().unwrap();
+        (issued, issue)
+    }
+
+    #[test]
+    fn modes() {
+        assert_eq!(SchemaMode::from_lookup(|_| None), Ok(SchemaMode::Legacy));
+        let mode = |mode: &str| SchemaMode::from_lookup(|_| Some(mode.to_string()));
+        assert_eq!(mode(" Dual"), Ok(SchemaMode::Dual));
+        assert!(mode("both").is_err());
+        for mode in [SchemaMode::Legacy, SchemaMode::Dual, SchemaMode::New] {
+            assert_eq!(SchemaMode::from_byte(mode.to_byte()), Some(mode));
+            assert_eq!(mode.to_string().parse::<SchemaMode>(), Ok(mode));
+        }
+
+        let check = SchemaCheck::from_lookup(|_| None).unwrap();
+        let now = Utc.with_ymd_and_hms(2024, 5, 1, 2, 30, 0).unwrap();
+        assert_eq!(
+            check.next_run(now),
+            Utc.with_ymd_and_hms(2024, 5, 1, 3, 0, 0).unwrap()
+        );
+        let later = Utc.with_ymd_and_hms(2024, 5, 1, 3, 0, 0).unwrap();
+        assert_eq!(
+            check.next_run(later),
+            Utc.with_ymd_and_hms(2024, 5, 2, 3, 0, 0).unwrap()
+        );
+        assert!(SchemaCheck::from_lookup(
+            |key| (key == "SCHEMA_CHECK_HOUR").then(|| "24".to_string())
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn writes_per_mode() {
+        let measurements = |mode| -> Vec<_> {
+            points(mode)
+                .into_iter()
+                .map(line)
+                .map(|line| line.split_once(',').unwrap().0.to_string())
+                .collect()
+        };
+        assert_eq!(measurements(SchemaMode::Legacy), ["forecast"]);
+        // the current value at horizon 0 and one point per horizon
+        assert_eq!(
+            measurements(SchemaMode::Dual),
+            [
+                "forecast",
+                MEASUREMENT_V2,
+                MEASUREMENT_V2,
+                MEASUREMENT_V2,
+                MEASUREMENT_V2
+            ]
+        );
+        assert_eq!(measurements(SchemaMode::New).len(), 4);
+
+        let lines: Vec<_> = points(SchemaMode::New).into_iter().map(line).collect();
+        assert!(lines[0].contains(",horizon=0,"), "{}", lines[0]);
+        assert!(
+            lines[0].contains(" current=412.4,lead_minutes=0i "),
+            "{}",
+            lines[0]
+        );
+        assert!(lines[2].contains(",horizon=30,"), "{}", lines[2]);
+        assert!(
+            lines[2].contains(" lead_minutes=30i,value=415.5 "),
+            "{}",
+            lines[2]
+        );
+        // the points of an issue share its time and content hash
+        let hash = |line: &str| line.split(",content_hash=").nth(1).unwrap()[..16].to_string();
+        assert!(lines.iter().all(|line| hash(line) == hash(&lines[0])));
+        assert!(lines.iter().all(|line| line.ends_with(" 1714564800\n")));
+    }
+
+    #[test]
+    fn reconstructs_the_json_fields() {
+        let points = points(SchemaMode::Dual);
+        let (issued, issue) = legacy(&points);
+        assert_eq!(issued, Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap());
+        let names = NameMapping::default();
+        let records = v2_records(points.clone());
+        assert_eq!(compare(&issue, &records, issued, &names, usize::MAX), None);
+
+        // a horizon off by one
+        let mut off = records.clone();
+        off[2]
+            .values
+            .insert("_value".to_string(), Value::Double(416.5.into()));
+        assert_eq!(
+            compare(&issue, &off, issued, &names, usize::MAX).unwrap(),
+            "forecasts {\"2024-05-01 12:15\":413,\"2024-05-01 12:30\":417,\"2024-05-01 13:00\":398} \
+             instead of {\"2024-05-01 12:15\":413,\"2024-05-01 12:30\":416,\"2024-05-01 13:00\":398}"
+        );
+        // a missing horizon
+        let missing = [&records[..3], &records[4..]].concat();
+        assert!(compare(&issue, &missing, issued, &names, usize::MAX)
+            .unwrap()
+            .starts_with("forecasts "));
+        // a shifted horizon
+        let mut shifted = records.clone();
+        shifted[0]
+            .values
+            .insert(HORIZON_TAG.to_string(), Value::String("5".to_string()));
+        assert_eq!(
+            compare(&issue, &shifted, issued, &names, usize::MAX).unwrap(),
+            "current {\"2024-05-01 12:05\":412} instead of {\"2024-05-01 12:00\":412}"
+        );
+        assert_eq!(
+            compare(&issue, &[], issued, &names, usize::MAX).unwrap(),
+            "no points per horizon"
+        );
+    }
+
+    #[test]
+    fn reconstructs_across_daylight_saving() {
+        // the lead times skip the hour at 02:00 and clamp the horizon before the issue time,
+        // the horizons keep the times as stated
+        let body = r#"{"vorhersageZeit": "2024-03-31 01:30", "lat": 52.91, "lon": 8.23,
+            "aktuell": {"2024-03-31 01:30": 412.4},
+            "vorhersage": {"2024-03-31 01:25": 412, "2024-03-31 03:30": 413}}"#;
+        let points = points_of(body, SchemaMode::Dual);
+        let lines: Vec<_> = points[1..].iter().cloned().map(line).collect();
+        assert!(lines[0].contains(",horizon=-5,"), "{}", lines[0]);
+        assert!(lines[0].contains(" lead_minutes=0i,"), "{}", lines[0]);
+        assert!(lines[2].contains(",horizon=120,"), "{}", lines[2]);
+        assert!(lines[2].contains(" lead_minutes=60i,"), "{}", lines[2]);
+
+        let (issued, issue) = legacy(&points);
+        let records = v2_records(points);
+        let names = NameMapping::default();
+        assert_eq!(compare(&issue, &records, issued, &names, usize::MAX), None);
+    }
+
+    #[tokio::test]
+    async fn alerts_mismatches() {
+        let points = points(SchemaMode::Dual);
+        // annotated CSV of the `columns` of the `records`, by their names and data types
+        let csv = |records: Vec<FluxRecord>, columns: &[(&str, &str)]| {
+            let (names, types): (Vec<_>, Vec<_>) = columns.iter().copied().unzip();
+            let mut csv = format!(
+                "#datatype,string,long,{}\n#group,false,false{}\n#default,_result,{}\n\
+                 ,result,table,{}\n",
+                types.join(","),
+                ",false".repeat(columns.len()),
+                ",".repeat(columns.len()),
+                names.join(",")
+            );
+            for record in records {
+                let row: Vec<_> = names
+                    .iter()
+                    .map(|column| match &record.values[*column] {
+                        Value::String(text) => format!("\"{}\"", text.replace('"', "\"\"")),
+                        Value::Double(value) => value.to_string(),
+                        Value::TimeRFC(time) => time.to_rfc3339(),
+                        value => panic!("unexpected {value:?}"),
+                    })
+                    .collect();
+                csv += &format!(",,0,{}\n", row.join(","));
+            }
+            csv
+        };
+        let line = line(points[0].clone());
+        let legacy_csv = csv(
+            vec![record(&line, "current"), record(&line, "forecasts")],
+            &[
+                ("_time", "dateTime:RFC3339"),
+                ("_field", "string"),
+                ("_value", "string"),
+                ("id", "string"),
+                ("model", "string"),
+            ],
+        );
+        // the last horizon got lost on its way
+        let mut records = v2_records(points);
+        records.pop();
+        let v2_csv = csv(
+            records,
+            &[
+                ("_field", "string"),
+                ("_value", "double"),
+                (HORIZON_TAG, "string"),
+            ],
+        );
+        let queries = Arc::new(Mutex::new(Vec::new()));
+        let query = warp::post()
+            .and(warp::path!("api" / "v2" / "query"))
+            .and(warp::body::json())
+            .map({
+                let queries = queries.clone();
+                move |body: serde_json::Value| {
+                    let query = body["query"].as_str().unwrap().to_string();
+                    let csv = match query.contains(MEASUREMENT_V2) {
+                        true => v2_csv.clone(),
+                        false => legacy_csv.clone(),
+                    };
+                    queries.lock().push(query);
+                    csv
+                }
+            });
+        let (addr, server) = warp::serve(query).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        let locations = &LOCATIONS.locations[..1];
+        let sink = Sink::Influx {
+            client: Box::new(crate::sink::InfluxClient::new(
+                format!("http://{addr}"),
+                "org",
+                "token",
+            )),
+            buckets: Buckets::from_lookup(|_| None, locations),
+            idempotent: false,
+            profile: None,
+        };
+        let check = SchemaCheck::from_lookup(|_| None).unwrap();
+        let mismatches = check
+            .check(&sink, locations, &NameMapping::default(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(queries.lock().len(), 2);
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].location, "WW Großenkneten");
+        assert_eq!(
+            mismatches[0].to_string(),
+            "WW Großenkneten (vorhersage) issued 2024-05-01 12:00: forecasts \
+             {\"2024-05-01 12:15\":413,\"2024-05-01 12:30\":416} instead of \
+             {\"2024-05-01 12:15\":413,\"2024-05-01 12:30\":416,\"2024-05-01 13:00\":398}"
+        );
+    }
+}
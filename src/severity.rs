@@ -0,0 +1,222 @@
+use crate::error_kind::ErrorKind;
+use crate::points::HandleLocationError;
+use once_cell::sync::Lazy;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Severity mapping of the error kinds, configurable via `SEVERITY_<KIND>` variables.
+pub static MAPPING: Lazy<SeverityMapping> = Lazy::new(|| {
+    SeverityMapping::from_lookup(|key| env::var(key).ok())
+        .unwrap_or_else(|err| panic!("invalid severity mapping, {err}"))
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+#[derive(Debug, Error)]
+#[error("unknown severity {0:?}, expected one of \"info\", \"warning\" or \"critical\"")]
+pub struct ParseSeverityError(String);
+
+impl Severity {
+    pub fn color(self) -> u32 {
+        match self {
+            Severity::Info => 0x95A5A6,
+            Severity::Warning => 0xFEE75C,
+            Severity::Critical => 0x9E2C2C,
+        }
+    }
+}
+
+impl FromStr for Severity {
+    type Err = ParseSeverityError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "info" => Ok(Severity::Info),
+            "warning" | "warn" => Ok(Severity::Warning),
+            "critical" => Ok(Severity::Critical),
+            _ => Err(ParseSeverityError(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        })
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("{key}, {error}")]
+pub struct SeverityMappingError {
+    key: String,
+    error: ParseSeverityError,
+}
+
+#[derive(Debug)]
+pub struct SeverityMapping(BTreeMap<ErrorKind, Severity>);
+
+impl SeverityMapping {
+    /// Builds the mapping from the defaults, overridden by every `SEVERITY_<KIND>` key
+    /// `lookup` returns a value for.
+    ///
+    /// Kinds without a key of their own fall back to the key of the error variant they belong
+    /// to, like `SEVERITY_REQUEST_FORECAST`.
+    pub fn from_lookup(
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<SeverityMapping, SeverityMappingError> {
+        let mut mapping = BTreeMap::new();
+        for kind in ErrorKind::ALL {
+            let value = keys(kind)
+                .into_iter()
+                .find_map(|key| lookup(&key).map(|value| (key, value)));
+            let severity = match value {
+                Some((key, value)) => value
+                    .parse()
+                    .map_err(|error| SeverityMappingError { key, error })?,
+                None => default_severity(kind),
+            };
+            mapping.insert(kind, severity);
+        }
+        Ok(SeverityMapping(mapping))
+    }
+
+    pub fn severity(&self, error: &HandleLocationError) -> Severity {
+        self.0[&error.kind()]
+    }
+}
+
+/// The `SEVERITY_<KIND>` keys of every kind, like `SEVERITY_DNS` and
+/// `SEVERITY_REQUEST_FORECAST`.
+pub fn all_keys() -> Vec<String> {
+    let mut keys: Vec<_> = ErrorKind::ALL.into_iter().flat_map(keys).collect();
+    keys.sort();
+    keys.dedup();
+    keys
+}
+
+/// The keys of the `kind`, its own before the one of its error variant.
+fn keys(kind: ErrorKind) -> [String; 2] {
+    [
+        format!("SEVERITY_{}", kind.code().to_uppercase()),
+        format!("SEVERITY_{}", variant_key(kind)),
+    ]
+}
+
+fn default_severity(kind: ErrorKind) -> Severity {
+    match kind {
+        ErrorKind::InfluxWrite | ErrorKind::Unauthorized => Severity::Critical,
+        ErrorKind::RequestTimeout
+        | ErrorKind::RequestStatus
+        | ErrorKind::Dns
+        | ErrorKind::TlsHandshake
+        | ErrorKind::ConnectTimeout
+        | ErrorKind::ConnectionReset
+        | ErrorKind::Request
+        | ErrorKind::Parse
+        | ErrorKind::TimestampParse
+        | ErrorKind::Serialize
+        | ErrorKind::PointBuild => Severity::Warning,
+    }
+}
+
+/// Key of the [`HandleLocationError`] variant the kind belongs to.
+fn variant_key(kind: ErrorKind) -> &'static str {
+    match kind {
+        ErrorKind::RequestTimeout
+        | ErrorKind::RequestStatus
+        | ErrorKind::Dns
+        | ErrorKind::TlsHandshake
+        | ErrorKind::ConnectTimeout
+        | ErrorKind::ConnectionReset
+        | ErrorKind::Request
+        | ErrorKind::Unauthorized
+        | ErrorKind::Parse => "REQUEST_FORECAST",
+        ErrorKind::TimestampParse => "PARSE_FROM_TIMESTAMP",
+        ErrorKind::Serialize => "SERIALIZE_DATA",
+        ErrorKind::PointBuild => "DATA_POINT",
+        ErrorKind::InfluxWrite => "WRITE_POINTS",
+    }
+}
+
+impl HandleLocationError {
+    pub fn severity(&self) -> Severity {
+        MAPPING.severity(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error() -> HandleLocationError {
+        let error = crate::timestamp::parse("").unwrap_err();
+        HandleLocationError::ParseFromTimestamp(error)
+    }
+
+    #[test]
+    fn default_mapping() {
+        let mapping = SeverityMapping::from_lookup(|_| None).unwrap();
+        assert_eq!(mapping.severity(&parse_error()), Severity::Warning);
+        assert_eq!(mapping.0[&ErrorKind::InfluxWrite], Severity::Critical);
+    }
+
+    #[test]
+    fn configured_mapping() {
+        let mapping = SeverityMapping::from_lookup(|key| match key {
+            "SEVERITY_PARSE_FROM_TIMESTAMP" => Some("Info".to_string()),
+            "SEVERITY_WRITE_POINTS" => Some("warn".to_string()),
+            _ => None,
+        })
+        .unwrap();
+        assert_eq!(mapping.severity(&parse_error()), Severity::Info);
+        assert_eq!(mapping.0[&ErrorKind::InfluxWrite], Severity::Warning);
+        assert_eq!(mapping.0[&ErrorKind::Parse], Severity::Warning);
+    }
+
+    #[test]
+    fn kind_overrides_variant() {
+        let mapping = SeverityMapping::from_lookup(|key| match key {
+            "SEVERITY_REQUEST_FORECAST" => Some("critical".to_string()),
+            "SEVERITY_REQUEST_TIMEOUT" => Some("info".to_string()),
+            _ => None,
+        })
+        .unwrap();
+        assert_eq!(mapping.0[&ErrorKind::RequestTimeout], Severity::Info);
+        assert_eq!(mapping.0[&ErrorKind::Request], Severity::Critical);
+        assert_eq!(mapping.0[&ErrorKind::Parse], Severity::Critical);
+        assert_eq!(mapping.0.len(), ErrorKind::ALL.len());
+    }
+
+    #[test]
+    fn invalid_mapping() {
+        let err = SeverityMapping::from_lookup(|key| {
+            (key == "SEVERITY_DATA_POINT").then(|| "loud".to_string())
+        })
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "SEVERITY_DATA_POINT, unknown severity \"loud\", \
+             expected one of \"info\", \"warning\" or \"critical\""
+        );
+    }
+
+    #[test]
+    fn colors() {
+        assert_eq!(Severity::Info.color(), 0x95A5A6);
+        assert_eq!(Severity::Warning.color(), 0xFEE75C);
+        assert_eq!(Severity::Critical.color(), 0x9E2C2C);
+        assert!(Severity::Info < Severity::Warning && Severity::Warning < Severity::Critical);
+    }
+}
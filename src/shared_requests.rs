@@ -0,0 +1,116 @@
+use crate::locations::Target;
+use std::collections::BTreeMap;
+
+/// Targets requesting the same forecast, the same model at the same coordinates as written
+/// into the request url, so a tick requests it only once for all of them.
+///
+/// Each group is led by its first target in fetch order, which requests the forecast for the
+/// others. Targets at coordinates of their own are not grouped, so unique coordinates request
+/// as before.
+#[derive(Debug)]
+pub struct SharedRequests {
+    /// The index of the target leading the group of each target, its own if it leads.
+    leaders: Vec<usize>,
+
+    /// The names of the targets per leader of a group of at least two.
+    groups: BTreeMap<usize, Vec<String>>,
+}
+
+impl SharedRequests {
+    pub fn new(targets: &[Target]) -> SharedRequests {
+        let mut first: BTreeMap<(String, String, &str), usize> = BTreeMap::new();
+        let leaders: Vec<_> = (targets.iter().enumerate())
+            .map(|(i, target)| {
+                let (lat, lon) = target.location.coordinate_texts();
+                *first.entry((lat, lon, &target.model.name)).or_insert(i)
+            })
+            .collect();
+        let mut groups: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+        for (i, leader) in leaders.iter().enumerate() {
+            groups
+                .entry(*leader)
+                .or_default()
+                .push(format!("{:?}", targets[i].to_string()));
+        }
+        groups.retain(|_, members| members.len() > 1);
+        SharedRequests { leaders, groups }
+    }
+
+    /// The index of the earlier target whose request the `i`th target shares, if any.
+    pub fn leader(&self, i: usize) -> Option<usize> {
+        Some(self.leaders[i]).filter(|leader| *leader != i)
+    }
+
+    /// Whether the `i`th target shares its request with any other target.
+    pub fn is_shared(&self, i: usize) -> bool {
+        self.groups.contains_key(&self.leaders[i])
+    }
+
+    /// The names of the targets sharing the request of the `i`th target.
+    pub fn members(&self, i: usize) -> String {
+        (self.groups.get(&self.leaders[i]))
+            .map(|members| members.join(", "))
+            .unwrap_or_default()
+    }
+
+    /// The groups of targets requested once, each as the names of its targets.
+    pub fn groups(&self) -> impl Iterator<Item = String> + '_ {
+        self.groups.values().map(|members| members.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locations::{Location, Model};
+
+    fn location(id: i64, name: &'static str, lat: &'static str) -> Location {
+        Location {
+            group: "",
+            id,
+            lat,
+            lon: "8.23505",
+            name,
+        }
+    }
+
+    #[test]
+    fn groups_same_coordinates() {
+        let locations = [
+            location(1, "WW A", "52.91098"),
+            location(2, "WW B", "52.9109"),
+            location(3, "WW C", "52.910980"),
+            location(4, "WW D", "52.9109800001"),
+        ];
+        let default = Model::default_model();
+        let other = Model {
+            name: "mittelwert".to_string(),
+            path: "/Mittelwert".to_string(),
+        };
+        let mut targets: Vec<_> = (locations.iter())
+            .map(|location| Target {
+                location,
+                model: &default,
+            })
+            .collect();
+        targets.push(Target {
+            location: &locations[0],
+            model: &other,
+        });
+
+        let shared = SharedRequests::new(&targets);
+        let leaders: Vec<_> = (0..targets.len()).map(|i| shared.leader(i)).collect();
+        assert_eq!(leaders, [None, None, Some(0), Some(0), None]);
+        let grouped: Vec<_> = (0..targets.len()).map(|i| shared.is_shared(i)).collect();
+        assert_eq!(grouped, [true, false, true, true, false]);
+        assert_eq!(shared.members(3), "\"WW A\", \"WW C\", \"WW D\"");
+        assert_eq!(shared.members(1), "");
+        assert_eq!(
+            shared.groups().collect::<Vec<_>>(),
+            ["\"WW A\", \"WW C\", \"WW D\""]
+        );
+
+        let unique = SharedRequests::new(&targets[..2]);
+        assert!((0..2).all(|i| unique.leader(i).is_none() && !unique.is_shared(i)));
+    }
+}
@@ -0,0 +1,489 @@
+use crate::egress::{self, Class, Transfer};
+use crate::locations::Location;
+use crate::names::NAMES;
+use crate::points::{PendingPoint, MEASUREMENT_V2};
+use chrono::{DateTime, SecondsFormat, Utc};
+use influxdb2::api::query::FluxRecord;
+use influxdb2::models::{DataPoint, Query, WriteDataPoint};
+use influxdb2_structmap::value::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::io;
+use std::ops::Deref;
+use std::sync::Arc;
+use thiserror::Error;
+
+/// Bucket written into unless `INFLUXDB_BUCKET` is set.
+pub const BUCKET_NAME: &str = "swat";
+
+/// Longest excerpt of the answer to a failed write kept in its error, InfluxDB names the
+/// rejected line and field within the first few hundred bytes.
+const BODY_EXCERPT_LEN: usize = 500;
+
+/// Headers of the answers of InfluxDB identifying the request, sent by OSS and Cloud.
+const REQUEST_ID_HEADERS: [&str; 2] = ["x-influxdb-request-id", "trace-id"];
+
+/// A failed write, along with the id InfluxDB answered it with if any.
+#[derive(Debug, Error)]
+pub struct WriteError {
+    #[source]
+    pub error: influxdb2::RequestError,
+    pub request_id: Option<String>,
+}
+
+impl fmt::Display for WriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.request_id {
+            Some(id) => write!(f, "{}, request id {id}", self.error),
+            None => write!(f, "{}", self.error),
+        }
+    }
+}
+
+impl From<influxdb2::RequestError> for WriteError {
+    fn from(error: influxdb2::RequestError) -> WriteError {
+        WriteError {
+            error,
+            request_id: None,
+        }
+    }
+}
+
+/// The InfluxDB client of a [`Sink`], writing with requests of its own to keep the body and
+/// the request id of the answers to failed writes, which [`influxdb2::Client`] drops.
+pub struct InfluxClient {
+    client: influxdb2::Client,
+    http: reqwest::Client,
+    token: String,
+}
+
+impl InfluxClient {
+    pub fn new(
+        url: impl Into<String>,
+        org: impl Into<String>,
+        token: impl Into<String>,
+    ) -> InfluxClient {
+        let token = token.into();
+        InfluxClient {
+            client: influxdb2::Client::new(url, org, token.clone()),
+            http: reqwest::Client::new(),
+            token,
+        }
+    }
+
+    /// Writes the line protocol `lines` into `bucket` like
+    /// [`influxdb2::Client::write_line_protocol_with_precision`] with second precision.
+    async fn write_lines(&self, bucket: &str, lines: String) -> Result<(), WriteError> {
+        let mut url = self.client.base.clone();
+        url.set_path("/api/v2/write");
+        let mut request = self.http.post(url).query(&[
+            ("bucket", bucket),
+            ("org", &self.client.org),
+            ("precision", "s"),
+        ]);
+        if !self.token.is_empty() {
+            request = request.header("Authorization", format!("Token {}", self.token));
+        }
+        let processing = |source| influxdb2::RequestError::ReqwestProcessing { source };
+        let response = request.body(lines).send().await.map_err(processing)?;
+        let status = response.status();
+        if status == reqwest::StatusCode::NO_CONTENT {
+            return Ok(());
+        }
+        let request_id = (REQUEST_ID_HEADERS.iter())
+            .find_map(|header| response.headers().get(*header)?.to_str().ok())
+            .map(str::to_string);
+        let text = response.text().await.map_err(processing)?;
+        Err(WriteError {
+            error: influxdb2::RequestError::Http {
+                status,
+                text: crate::webhook::truncate(text.trim(), BODY_EXCERPT_LEN),
+            },
+            request_id,
+        })
+    }
+}
+
+impl Deref for InfluxClient {
+    type Target = influxdb2::Client;
+
+    fn deref(&self) -> &influxdb2::Client {
+        &self.client
+    }
+}
+
+/// Where the data points are written to.
+pub enum Sink {
+    Influx {
+        client: Box<InfluxClient>,
+        buckets: Buckets,
+
+        /// Whether to skip points already written, set via `IDEMPOTENT_WRITES`.
+        idempotent: bool,
+
+        /// Name of the profile tagging every point written, if profiles are configured.
+        profile: Option<Arc<str>>,
+    },
+
+    /// Logs the line protocol to stdout, for running offline.
+    Stdout,
+}
+
+impl Sink {
+    /// The bucket the points of `location` are written into.
+    pub fn bucket(&self, location: &Location) -> &str {
+        match self {
+            Sink::Influx { buckets, .. } => buckets.of(location),
+            Sink::Stdout => BUCKET_NAME,
+        }
+    }
+
+    /// Every bucket written into.
+    pub fn bucket_names(&self) -> BTreeSet<&str> {
+        match self {
+            Sink::Influx { buckets, .. } => buckets.names(),
+            Sink::Stdout => BTreeSet::from([BUCKET_NAME]),
+        }
+    }
+
+    /// The bucket for points not belonging to a location.
+    pub fn default_bucket(&self) -> &str {
+        match self {
+            Sink::Influx { buckets, .. } => buckets.default_bucket(),
+            Sink::Stdout => BUCKET_NAME,
+        }
+    }
+
+    /// Content hashes of the `points` already written into `bucket`, always empty unless
+    /// writes are idempotent.
+    pub async fn existing_hashes(
+        &self,
+        bucket: &str,
+        points: &[PendingPoint<'_>],
+    ) -> Result<BTreeSet<String>, influxdb2::RequestError> {
+        let client = match self {
+            Sink::Influx {
+                client,
+                idempotent: true,
+                ..
+            } => client,
+            _ => return Ok(BTreeSet::new()),
+        };
+        let (Some(start), Some(stop)) = (
+            points.iter().map(|point| point.timestamp).min(),
+            points.iter().map(|point| point.timestamp).max(),
+        ) else {
+            return Ok(BTreeSet::new());
+        };
+
+        let time = |timestamp| {
+            DateTime::<Utc>::from_timestamp(timestamp, 0)
+                .unwrap_or_default()
+                .to_rfc3339_opts(SecondsFormat::Secs, true)
+        };
+        let hashes: Vec<_> = points
+            .iter()
+            .map(|point| format!("{:?}", point.content_hash))
+            .collect();
+        let (field, tag) = (NAMES.field("current"), NAMES.tag("content_hash"));
+        let query = format!(
+            r#"from(bucket: {bucket:?})
+                |> range(start: {}, stop: {})
+                |> filter(fn: (r) => r._measurement == "forecast" or r._measurement == "{MEASUREMENT_V2}")
+                |> filter(fn: (r) => r._field == {field:?})
+                |> filter(fn: (r) => contains(value: r[{tag:?}], set: [{}]))
+                |> keep(columns: [{tag:?}])"#,
+            time(start),
+            time(stop + 1),
+            hashes.join(", ")
+        );
+
+        egress::METER.record(Class::Influx, Transfer::sent(query.len()));
+        let records = client.query_raw(Some(Query::new(query))).await?;
+        let hashes = records
+            .into_iter()
+            .filter_map(|record| match record.values.get(tag) {
+                Some(Value::String(hash)) => Some(hash.clone()),
+                _ => None,
+            })
+            .collect();
+        Ok(hashes)
+    }
+
+    /// Runs the Flux `query`, nothing is found when running offline.
+    pub async fn query(&self, query: String) -> Result<Vec<FluxRecord>, influxdb2::RequestError> {
+        match self {
+            Sink::Influx { client, .. } => {
+                egress::METER.record(Class::Influx, Transfer::sent(query.len()));
+                client.query_raw(Some(Query::new(query))).await
+            }
+            Sink::Stdout => Ok(Vec::new()),
+        }
+    }
+
+    /// Writes the `data_points` into `bucket` with a single request.
+    pub async fn write(&self, bucket: &str, data_points: Vec<DataPoint>) -> Result<(), WriteError> {
+        match self {
+            Sink::Influx { .. } => {
+                let lines = self.lines(data_points);
+                self.write_lines(bucket, lines).await
+            }
+            Sink::Stdout => {
+                for data_point in data_points {
+                    let mut line = Vec::new();
+                    data_point
+                        .write_data_point_to(&mut line)
+                        .expect("writing into a vec cannot fail");
+                    log_println!("{}", String::from_utf8_lossy(&line).trim_end());
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// The `data_points` as the line protocol they are [written](Self::write) with, to write
+    /// them later with [`write_lines`](Self::write_lines).
+    pub fn lines(&self, data_points: Vec<DataPoint>) -> String {
+        let profile = match self {
+            Sink::Influx { profile, .. } => profile.clone(),
+            Sink::Stdout => None,
+        };
+        let mut lines = Vec::new();
+        for point in data_points {
+            let tagged = Tagged {
+                point,
+                profile: profile.clone(),
+            };
+            tagged
+                .write_data_point_to(&mut lines)
+                .expect("writing into a vec cannot fail");
+        }
+        String::from_utf8_lossy(&lines).into_owned()
+    }
+
+    /// Writes the line protocol `lines` into `bucket` with a single request.
+    pub async fn write_lines(&self, bucket: &str, lines: String) -> Result<(), WriteError> {
+        match self {
+            Sink::Influx { client, .. } => {
+                egress::METER.record(Class::Influx, Transfer::sent(lines.len()));
+                client.write_lines(bucket, lines).await
+            }
+            Sink::Stdout => {
+                log_println!("{}", lines.trim_end());
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A data point written with the tag of its profile, if any.
+struct Tagged {
+    point: DataPoint,
+    profile: Option<Arc<str>>,
+}
+
+impl WriteDataPoint for Tagged {
+    fn write_data_point_to<W>(&self, mut w: W) -> io::Result<()>
+    where
+        W: io::Write,
+    {
+        let Some(profile) = &self.profile else {
+            return self.point.write_data_point_to(w);
+        };
+        let mut line = Vec::new();
+        self.point.write_data_point_to(&mut line)?;
+        // the tags follow the measurement, which escapes commas and spaces
+        let mut escaped = false;
+        let end = line
+            .iter()
+            .position(|byte| {
+                let end = !escaped && matches!(byte, b',' | b' ');
+                escaped = !escaped && *byte == b'\\';
+                end
+            })
+            .unwrap_or(line.len());
+        w.write_all(&line[..end])?;
+        // profile names need no escaping
+        write!(w, ",profile={profile}")?;
+        w.write_all(&line[end..])
+    }
+}
+
+/// Failure to make sure a bucket exists on startup.
+#[derive(Debug, Error)]
+pub enum InitBucketError {
+    #[error("could not look up bucket, {0}")]
+    List(#[source] influxdb2::RequestError),
+
+    #[error("could not look up organization, {0}")]
+    Organization(#[source] influxdb2::RequestError),
+
+    #[error("organization {0:?} not found")]
+    UnknownOrganization(String),
+
+    #[error("could not create bucket, {0}")]
+    Create(#[source] influxdb2::RequestError),
+}
+
+/// Bucket of every location, so data of different projects can be kept apart.
+///
+/// Locations are written into `INFLUXDB_BUCKET` unless `INFLUXDB_BUCKET_<SLUG>` is set for
+/// them, with the slug in upper case and `_` as separator, like
+/// `INFLUXDB_BUCKET_WW_GROSSENKNETEN`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Buckets {
+    default: String,
+    locations: BTreeMap<i64, String>,
+}
+
+impl Buckets {
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>, locations: &[Location]) -> Buckets {
+        let locations = locations
+            .iter()
+            .filter_map(|location| {
+                let key = format!("INFLUXDB_BUCKET_{}", location.slug().to_uppercase());
+                let bucket = lookup(&key.replace('-', "_"))?;
+                Some((location.id, bucket))
+            })
+            .collect();
+        Buckets {
+            default: lookup("INFLUXDB_BUCKET").unwrap_or_else(|| BUCKET_NAME.to_string()),
+            locations,
+        }
+    }
+
+    pub fn of(&self, location: &Location) -> &str {
+        self.locations.get(&location.id).unwrap_or(&self.default)
+    }
+
+    /// The bucket of the locations without a bucket of their own.
+    pub fn default_bucket(&self) -> &str {
+        &self.default
+    }
+
+    /// Every bucket written into.
+    pub fn names(&self) -> BTreeSet<&str> {
+        let locations = self.locations.values().map(String::as_str);
+        locations.chain([self.default.as_str()]).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locations::LOCATIONS;
+
+    fn line(point: DataPoint, profile: Option<&str>) -> String {
+        let tagged = Tagged {
+            point,
+            profile: profile.map(Arc::from),
+        };
+        let mut line = Vec::new();
+        tagged.write_data_point_to(&mut line).unwrap();
+        String::from_utf8(line).unwrap()
+    }
+
+    #[test]
+    fn tags_profile() {
+        let point = || {
+            DataPoint::builder("forecast")
+                .tag("name", "WW Großenkneten")
+                .field("current", 42)
+                .timestamp(1709798760)
+                .build()
+                .unwrap()
+        };
+        assert_eq!(
+            line(point(), Some("harz")),
+            "forecast,profile=harz,name=WW\\ Großenkneten current=42i 1709798760\n"
+        );
+        assert_eq!(
+            line(point(), None),
+            "forecast,name=WW\\ Großenkneten current=42i 1709798760\n"
+        );
+
+        let point = DataPoint::builder("collector, schema")
+            .field("version", 1)
+            .build()
+            .unwrap();
+        assert_eq!(
+            line(point, Some("harz")),
+            "collector\\,\\ schema,profile=harz version=1i\n"
+        );
+    }
+
+    #[test]
+    fn buckets_per_location() {
+        let locations = &LOCATIONS.locations;
+        let buckets = Buckets::from_lookup(
+            |key| match key {
+                "INFLUXDB_BUCKET_WW_GROSSENKNETEN" => Some("research-a".to_string()),
+                "INFLUXDB_BUCKET_WW_MARIENHAFE" => Some("research-b".to_string()),
+                _ => None,
+            },
+            locations,
+        );
+        assert_eq!(buckets.of(&locations[0]), "research-a");
+        assert_eq!(buckets.of(&locations[1]), "research-b");
+        assert_eq!(buckets.of(&locations[2]), BUCKET_NAME);
+        assert_eq!(
+            buckets.names(),
+            BTreeSet::from(["research-a", "research-b", BUCKET_NAME])
+        );
+
+        let buckets = Buckets::from_lookup(
+            |key| (key == "INFLUXDB_BUCKET").then(|| "forecasts".to_string()),
+            locations,
+        );
+        assert_eq!(buckets.names(), BTreeSet::from(["forecasts"]));
+    }
+
+    #[tokio::test]
+    async fn keeps_answer_to_failed_writes() {
+        use warp::http::StatusCode;
+        use warp::Filter;
+
+        let rejection = format!(
+            r#"{{"code":"invalid","message":"unable to parse 'forecast,id=1 current=\"x\"': invalid field format{}"}}"#,
+            " ".repeat(1000)
+        );
+        let write = warp::post()
+            .and(warp::path!("api" / "v2" / "write"))
+            .and(warp::query::<BTreeMap<String, String>>())
+            .and(warp::header::<String>("authorization"))
+            .map(move |query: BTreeMap<String, String>, auth: String| {
+                assert_eq!(query["bucket"], "swat");
+                assert_eq!(query["precision"], "s");
+                assert_eq!(auth, "Token token");
+                let reply = warp::reply::with_status(rejection.clone(), StatusCode::BAD_REQUEST);
+                warp::reply::with_header(reply, "X-Influxdb-Request-Id", "0a1b2c3d")
+            });
+        let (addr, server) = warp::serve(write).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        let sink = Sink::Influx {
+            client: Box::new(InfluxClient::new(format!("http://{addr}"), "org", "token")),
+            buckets: Buckets::from_lookup(|_| None, &LOCATIONS.locations),
+            idempotent: false,
+            profile: None,
+        };
+        let point = DataPoint::builder("forecast")
+            .field("current", "x")
+            .build()
+            .unwrap();
+        let err = sink.write("swat", vec![point]).await.unwrap_err();
+        assert_eq!(err.request_id.as_deref(), Some("0a1b2c3d"));
+        let message = err.to_string();
+        assert!(
+            message.starts_with(
+                "HTTP request returned an error: 400 Bad Request, `{\"code\":\"invalid\",\
+                 \"message\":\"unable to parse 'forecast,id=1 current=\\\"x\\\"': invalid \
+                 field format"
+            ),
+            "{message}"
+        );
+        // the padding of the answer is cut off
+        assert!(message.ends_with(" …`, request id 0a1b2c3d"), "{message}");
+        assert!(message.len() < BODY_EXCERPT_LEN + 100, "{message}");
+    }
+}
@@ -0,0 +1,88 @@
+/// Consecutive skipped ticks after which a warning is sent unless `SKIP_ALERT_THRESHOLD` is
+/// set.
+pub const DEFAULT_ALERT_THRESHOLD: u32 = 3;
+
+/// Counts the ticks skipped as the previous one was still running.
+///
+/// A tick is never cancelled for the next one, instead every time the interval fires during a
+/// running tick that fire is skipped, so an overrunning tick neither overlaps with the next one
+/// nor is followed by a burst of catch-up ticks. Skipping more than the threshold in a row
+/// warns once until a tick starts again.
+#[derive(Debug)]
+pub struct SkippedTicks {
+    threshold: u32,
+    total: u64,
+    consecutive: u32,
+}
+
+impl Default for SkippedTicks {
+    fn default() -> Self {
+        SkippedTicks::new(DEFAULT_ALERT_THRESHOLD)
+    }
+}
+
+impl SkippedTicks {
+    pub fn new(threshold: u32) -> SkippedTicks {
+        SkippedTicks {
+            threshold,
+            total: 0,
+            consecutive: 0,
+        }
+    }
+
+    /// Starts a new tick, which ends the skips in a row.
+    pub fn start_tick(&mut self) {
+        self.consecutive = 0;
+    }
+
+    /// Records a skipped tick, returns the warning to send once more than the threshold were
+    /// skipped in a row.
+    pub fn skip(&mut self) -> Option<String> {
+        self.total += 1;
+        self.consecutive += 1;
+        (self.consecutive == self.threshold + 1).then(|| {
+            format!(
+                "skipped {} ticks in a row as the previous one is still running",
+                self.consecutive
+            )
+        })
+    }
+
+    /// `ticks_skipped_total`, the ticks skipped since the start.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    #[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+    pub fn status_text(&self) -> String {
+        match self.total() {
+            0 => String::new(),
+            total => format!("ticks skipped: {total}\n"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warns_once_over_threshold() {
+        let mut skipped = SkippedTicks::new(2);
+        assert_eq!(skipped.skip(), None);
+        assert_eq!(skipped.skip(), None);
+        assert_eq!(
+            skipped.skip().unwrap(),
+            "skipped 3 ticks in a row as the previous one is still running"
+        );
+        assert_eq!(skipped.skip(), None);
+        assert_eq!(skipped.total(), 4);
+
+        // a tick starting ends the run, the total stays
+        skipped.start_tick();
+        assert_eq!(skipped.skip(), None);
+        assert_eq!(skipped.total(), 5);
+        assert_eq!(skipped.status_text(), "ticks skipped: 5\n");
+        assert_eq!(SkippedTicks::default().status_text(), "");
+    }
+}
@@ -0,0 +1,186 @@
+use crate::fixture;
+use crate::locations::{self, Location};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+#[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum SnoozeError {
+    #[error("unknown location {location:?}, expected one of {known}")]
+    Location { location: String, known: String },
+
+    #[error("expected a duration like `90m`, `12h` or `7d`, got {0:?}")]
+    Duration(String),
+}
+
+/// The configured location named, or with the slug or id, `name`.
+#[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+pub fn find(name: &str) -> Result<&'static Location, SnoozeError> {
+    fixture::find_location(name).ok_or_else(|| {
+        let known: Vec<_> = (locations::CONFIGURED.iter())
+            .map(|location| format!("{:?}", location.name))
+            .collect();
+        SnoozeError::Location {
+            location: name.to_string(),
+            known: known.join(", "),
+        }
+    })
+}
+
+/// Parses a duration of minutes, hours or days like `90m`, `12h` or `7d`.
+#[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+pub fn parse_duration(text: &str) -> Result<Duration, SnoozeError> {
+    let invalid = || SnoozeError::Duration(text.to_string());
+    let text = text.trim();
+    let unit = text.chars().last().ok_or_else(invalid)?;
+    let amount: i64 = text[..text.len() - unit.len_utf8()]
+        .parse()
+        .map_err(|_| invalid())?;
+    let duration = match unit {
+        'm' => Duration::try_minutes(amount),
+        'h' => Duration::try_hours(amount),
+        'd' => Duration::try_days(amount),
+        _ => None,
+    };
+    duration
+        .filter(|duration| *duration > Duration::zero())
+        .ok_or_else(invalid)
+}
+
+/// Locations snoozed through the health socket, like during construction work at a site.
+///
+/// A snoozed location is still collected and written, but its errors are neither alerted nor
+/// kept in the health status until the snooze expires.
+#[derive(Debug, Default)]
+pub struct Snoozes {
+    until: BTreeMap<String, DateTime<Utc>>,
+}
+
+impl Snoozes {
+    /// Snoozes the location named `location` until `until`, replacing a running snooze.
+    #[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+    pub fn snooze(&mut self, location: &str, until: DateTime<Utc>) {
+        self.until.insert(location.to_string(), until);
+    }
+
+    /// Ends the snooze of `location` early, returns whether it was snoozed.
+    #[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+    pub fn unsnooze(&mut self, location: &str) -> bool {
+        self.until.remove(location).is_some()
+    }
+
+    pub fn is_snoozed(&self, location: &str, now: DateTime<Utc>) -> bool {
+        self.until.get(location).is_some_and(|until| now < *until)
+    }
+
+    /// Forgets the snoozes that expired by `now`, returns the locations and their expiry.
+    pub fn expire(&mut self, now: DateTime<Utc>) -> Vec<(String, DateTime<Utc>)> {
+        let expired: Vec<_> = (self.until.iter())
+            .filter(|(_, until)| now >= **until)
+            .map(|(location, until)| (location.clone(), *until))
+            .collect();
+        for (location, _) in &expired {
+            self.until.remove(location);
+        }
+        expired
+    }
+
+    /// Describes the snooze of `location` for the answers of the health socket.
+    #[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+    pub fn status(&self, location: &str, now: DateTime<Utc>) -> String {
+        match self.until.get(location) {
+            Some(until) if now < *until => format!(
+                "location {location:?} snoozed until {}",
+                until.format("%Y-%m-%d %H:%M UTC")
+            ),
+            _ => format!("location {location:?} not snoozed"),
+        }
+    }
+
+    /// A line per snoozed location for the status page.
+    #[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+    pub fn status_text(&self, now: DateTime<Utc>) -> String {
+        (self.until.keys())
+            .filter(|location| self.is_snoozed(location, now))
+            .map(|location| self.status(location, now) + "\n")
+            .collect()
+    }
+
+    /// The snoozes for the state file.
+    pub fn export(&self) -> BTreeMap<String, DateTime<Utc>> {
+        self.until.clone()
+    }
+
+    /// Restores the snoozes `persisted` in the state file.
+    pub fn restore(&mut self, persisted: BTreeMap<String, DateTime<Utc>>) {
+        self.until = persisted;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(hour: i64) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap() + Duration::hours(hour)
+    }
+
+    #[test]
+    fn snoozes_until_expiry() {
+        let mut snoozes = Snoozes::default();
+        snoozes.snooze("WW Großenkneten", at(24 * 7));
+        snoozes.snooze("WW Marienhafe", at(1));
+        assert!(snoozes.is_snoozed("WW Großenkneten", at(0)));
+        assert!(!snoozes.is_snoozed("WW Thülsfelde", at(0)));
+        assert_eq!(
+            snoozes.status_text(at(0)),
+            "location \"WW Großenkneten\" snoozed until 2024-05-08 12:00 UTC\n\
+             location \"WW Marienhafe\" snoozed until 2024-05-01 13:00 UTC\n"
+        );
+        assert!(snoozes.expire(at(0)).is_empty());
+
+        // expired snoozes are returned once
+        assert!(!snoozes.is_snoozed("WW Marienhafe", at(1)));
+        assert_eq!(
+            snoozes.expire(at(2)),
+            [("WW Marienhafe".to_string(), at(1))]
+        );
+        assert!(snoozes.expire(at(3)).is_empty());
+        assert_eq!(
+            snoozes.status("WW Marienhafe", at(3)),
+            "location \"WW Marienhafe\" not snoozed"
+        );
+
+        assert!(snoozes.unsnooze("WW Großenkneten"));
+        assert!(!snoozes.unsnooze("WW Großenkneten"));
+        assert!(!snoozes.is_snoozed("WW Großenkneten", at(3)));
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("90m"), Ok(Duration::minutes(90)));
+        assert_eq!(parse_duration("12h"), Ok(Duration::hours(12)));
+        assert_eq!(parse_duration(" 7d"), Ok(Duration::days(7)));
+        for invalid in ["", "7", "d", "0h", "-1d", "1w", "1.5h"] {
+            assert_eq!(
+                parse_duration(invalid),
+                Err(SnoozeError::Duration(invalid.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn finds_locations() {
+        assert_eq!(find("WW Großenkneten").unwrap().id, 1);
+        assert_eq!(find("ww-grossenkneten").unwrap().id, 1);
+        assert_eq!(find("1").unwrap().id, 1);
+
+        let err = find("WW Atlantis").unwrap_err().to_string();
+        assert!(
+            err.starts_with("unknown location \"WW Atlantis\", expected one of \"WW Großenkneten\", \"WW Marienhafe\""),
+            "{err}"
+        );
+    }
+}
@@ -0,0 +1,505 @@
+use crate::severity::Severity;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::ffi::OsString;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::{fs, io};
+use thiserror::Error;
+
+/// Name of the alert fields about the spool.
+pub const NAME: &str = "spool";
+
+/// Messages kept in the spool unless `SPOOL_LIMIT` is set, the oldest are dropped beyond.
+pub const DEFAULT_LIMIT: usize = 10_000;
+
+/// Where failed messages are spooled unless `SPOOL_PATH` is set.
+pub const DEFAULT_PATH: &str = "/tmp/wisdom/swat-collector.spool";
+
+/// A spool holding this share of its limit is notified unless `SPOOL_WARN_PERCENT` is set.
+pub const DEFAULT_WARN_PERCENT: u8 = 80;
+
+#[derive(Debug, Error)]
+pub enum SpoolError {
+    #[error("could not access spool, {0}")]
+    Io(#[from] io::Error),
+
+    #[error("invalid spool entry, {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("expected {0:?} to be valid, {1}")]
+pub struct SpoolThresholdsError(&'static str, String);
+
+/// A message that could not be delivered, tagged with its destination so replaying it routes
+/// it to the output it failed on.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "destination", rename_all = "snake_case")]
+pub enum SpooledMessage {
+    Nats {
+        subject: String,
+        payload: String,
+    },
+
+    /// Line protocol diverted from InfluxDB while its circuit was open.
+    Influx {
+        bucket: String,
+        lines: String,
+    },
+}
+
+impl SpooledMessage {
+    pub fn destination(&self) -> &'static str {
+        match self {
+            SpooledMessage::Nats { .. } => "nats",
+            SpooledMessage::Influx { .. } => "influx",
+        }
+    }
+
+    /// The points in the message, a published forecast is one.
+    fn points(&self) -> usize {
+        match self {
+            SpooledMessage::Nats { .. } => 1,
+            SpooledMessage::Influx { lines, .. } => lines.lines().count(),
+        }
+    }
+}
+
+/// Depth of a filling spool that is notified, `SPOOL_WARN_POINTS` or `SPOOL_WARN_PERCENT` of
+/// the limit of messages, whichever is reached first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpoolThresholds {
+    pub points: Option<usize>,
+    pub percent: u8,
+}
+
+impl SpoolThresholds {
+    pub fn from_lookup(
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<SpoolThresholds, SpoolThresholdsError> {
+        let points = lookup("SPOOL_WARN_POINTS")
+            .map(|points| usize::from_str(points.trim()))
+            .transpose()
+            .map_err(|err| SpoolThresholdsError("SPOOL_WARN_POINTS", err.to_string()))?;
+        let percent = match lookup("SPOOL_WARN_PERCENT") {
+            Some(percent) => match u8::from_str(percent.trim()) {
+                Ok(percent @ 1..=100) => percent,
+                Ok(_) => {
+                    return Err(SpoolThresholdsError(
+                        "SPOOL_WARN_PERCENT",
+                        "expected a percentage from 1 to 100".to_string(),
+                    ))
+                }
+                Err(err) => {
+                    return Err(SpoolThresholdsError("SPOOL_WARN_PERCENT", err.to_string()))
+                }
+            },
+            None => DEFAULT_WARN_PERCENT,
+        };
+        Ok(SpoolThresholds { points, percent })
+    }
+
+    /// The threshold the `depth` of a spool with the `limit` exceeds, if any.
+    fn exceeded(&self, depth: SpoolDepth, limit: usize) -> Option<String> {
+        if self.points.is_some_and(|points| depth.points >= points) {
+            return Some(format!("{} points", self.points.unwrap_or_default()));
+        }
+        (depth.messages * 100 >= limit * self.percent as usize)
+            .then(|| format!("{}% of its limit of {limit} messages", self.percent))
+    }
+}
+
+impl Default for SpoolThresholds {
+    fn default() -> Self {
+        SpoolThresholds {
+            points: None,
+            percent: DEFAULT_WARN_PERCENT,
+        }
+    }
+}
+
+/// How much the spool holds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SpoolDepth {
+    pub messages: usize,
+    pub points: usize,
+
+    /// Size of the spool file.
+    pub bytes: usize,
+}
+
+impl fmt::Display for SpoolDepth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} messages, {} points, {} bytes",
+            self.messages, self.points, self.bytes
+        )
+    }
+}
+
+/// A change of the spool to notify about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpoolNotice {
+    /// The depth exceeds a [threshold](SpoolThresholds).
+    Filling(String),
+
+    /// Messages are dropped to stay within the limit.
+    Dropping(String),
+
+    /// The spool is empty again after filling or dropping.
+    Drained(String),
+}
+
+impl SpoolNotice {
+    /// The severity to alert the notice with, a drained spool is no alert.
+    pub fn severity(&self) -> Option<Severity> {
+        match self {
+            SpoolNotice::Filling(_) => Some(Severity::Warning),
+            SpoolNotice::Dropping(_) => Some(Severity::Critical),
+            SpoolNotice::Drained(_) => None,
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            SpoolNotice::Filling(message)
+            | SpoolNotice::Dropping(message)
+            | SpoolNotice::Drained(message) => message,
+        }
+    }
+}
+
+/// What was notified about the spool since it was last empty.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pressure {
+    Normal,
+    Filling,
+    Dropping,
+}
+
+/// Disk queue of the messages outputs failed to deliver, replayed by them on the next tick.
+///
+/// The queue is kept in memory and written as JSON lines to `path` on every change, so it
+/// survives restarts. At most `limit` messages are kept, the oldest are dropped and counted.
+///
+/// The depth is evaluated after every change. Exceeding the [thresholds](SpoolThresholds) and
+/// dropping messages are noticed once each until the spool drained, so a depth hovering around
+/// a threshold does not flap.
+#[derive(Debug)]
+pub struct Spool {
+    path: PathBuf,
+    limit: usize,
+    messages: VecDeque<SpooledMessage>,
+    dropped: u64,
+    depth: SpoolDepth,
+    thresholds: SpoolThresholds,
+    pressure: Pressure,
+
+    /// Dropped messages at the last evaluation.
+    evaluated_dropped: u64,
+    notices: Vec<SpoolNotice>,
+}
+
+impl Spool {
+    /// Reads the spool at `path`, a missing one is empty.
+    pub fn load(path: PathBuf, limit: usize) -> Result<Spool, SpoolError> {
+        let messages = match fs::read_to_string(&path) {
+            Ok(lines) => lines
+                .lines()
+                .filter(|line| !line.is_empty())
+                .map(serde_json::from_str)
+                .collect::<Result<_, _>>()?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => VecDeque::new(),
+            Err(err) => return Err(err.into()),
+        };
+        let mut spool = Spool {
+            path,
+            limit: limit.max(1),
+            messages,
+            dropped: 0,
+            depth: SpoolDepth::default(),
+            thresholds: SpoolThresholds::default(),
+            pressure: Pressure::Normal,
+            evaluated_dropped: 0,
+            notices: Vec::new(),
+        };
+        spool.trim();
+        spool.measure()?;
+        Ok(spool)
+    }
+
+    /// The spool notifying once the `thresholds` are exceeded, evaluated right away.
+    pub fn with_thresholds(self, thresholds: SpoolThresholds) -> Spool {
+        let mut spool = Spool { thresholds, ..self };
+        spool.evaluate();
+        spool
+    }
+
+    pub fn depth(&self) -> SpoolDepth {
+        self.depth
+    }
+
+    /// Messages dropped since the start to stay within the limit.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Appends the `messages` and writes the spool.
+    pub fn push(
+        &mut self,
+        messages: impl IntoIterator<Item = SpooledMessage>,
+    ) -> Result<(), SpoolError> {
+        self.messages.extend(messages);
+        self.trim();
+        let saved = self.save();
+        self.evaluate();
+        saved
+    }
+
+    /// Removes the messages for `destination` to replay them, the spool is written right away
+    /// so failing replays have to be [pushed](Self::push) again.
+    pub fn take(&mut self, destination: &str) -> Result<Vec<SpooledMessage>, SpoolError> {
+        let (taken, kept): (VecDeque<_>, _) = self
+            .messages
+            .drain(..)
+            .partition(|message| message.destination() == destination);
+        self.messages = kept;
+        if !taken.is_empty() {
+            let saved = self.save();
+            self.evaluate();
+            saved?;
+        }
+        Ok(taken.into())
+    }
+
+    /// The notices since they were taken last.
+    pub fn take_notices(&mut self) -> Vec<SpoolNotice> {
+        std::mem::take(&mut self.notices)
+    }
+
+    #[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+    pub fn status_text(&self) -> String {
+        format!("spool: {}, {} dropped\n", self.depth, self.dropped)
+    }
+
+    /// Notices exceeding the thresholds, dropping messages and draining after either.
+    fn evaluate(&mut self) {
+        let dropping = self.dropped > self.evaluated_dropped;
+        self.evaluated_dropped = self.dropped;
+        let depth = self.depth;
+        if depth.messages == 0 {
+            if self.pressure != Pressure::Normal {
+                self.notices.push(SpoolNotice::Drained(
+                    "spool drained, every spooled message was delivered".to_string(),
+                ));
+            }
+            self.pressure = Pressure::Normal;
+        } else if dropping {
+            if self.pressure != Pressure::Dropping {
+                self.notices.push(SpoolNotice::Dropping(format!(
+                    "spool is full at {} messages and drops the oldest, {} dropped so far",
+                    self.limit, self.dropped
+                )));
+            }
+            self.pressure = Pressure::Dropping;
+        } else if self.pressure == Pressure::Normal {
+            if let Some(threshold) = self.thresholds.exceeded(depth, self.limit) {
+                self.notices.push(SpoolNotice::Filling(format!(
+                    "spool holds {depth}, exceeding {threshold}"
+                )));
+                self.pressure = Pressure::Filling;
+            }
+        }
+    }
+
+    fn trim(&mut self) {
+        while self.messages.len() > self.limit {
+            self.messages.pop_front();
+            self.dropped += 1;
+        }
+    }
+
+    /// The spool file of the messages, updating the depth.
+    fn measure(&mut self) -> Result<String, serde_json::Error> {
+        let mut lines = String::new();
+        for message in &self.messages {
+            lines += &serde_json::to_string(message)?;
+            lines.push('\n');
+        }
+        self.depth = SpoolDepth {
+            messages: self.messages.len(),
+            points: self.messages.iter().map(SpooledMessage::points).sum(),
+            bytes: lines.len(),
+        };
+        Ok(lines)
+    }
+
+    /// Replaces the spool file, so it is never read half written.
+    fn save(&mut self) -> Result<(), SpoolError> {
+        let lines = self.measure()?;
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let temporary = temporary(&self.path);
+        fs::write(&temporary, lines)?;
+        fs::rename(&temporary, &self.path)?;
+        Ok(())
+    }
+}
+
+fn temporary(path: &Path) -> OsString {
+    let mut temporary = OsString::from(path.as_os_str());
+    temporary.push(".tmp");
+    temporary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn nats(subject: &str) -> SpooledMessage {
+        SpooledMessage::Nats {
+            subject: subject.to_string(),
+            payload: "{}".to_string(),
+        }
+    }
+
+    #[test]
+    fn persists_and_bounds() {
+        let path = env::temp_dir().join(format!("swat-collector-spool-{}", std::process::id()));
+        let mut spool = Spool::load(path.clone(), 2).unwrap();
+        assert_eq!(spool.depth().messages, 0);
+
+        spool.push([nats("a"), nats("b"), nats("c")]).unwrap();
+        assert_eq!(spool.dropped(), 1);
+        assert_eq!(
+            fs::read_to_string(&path).unwrap().lines().next(),
+            Some(r#"{"destination":"nats","subject":"b","payload":"{}"}"#)
+        );
+
+        let mut spool = Spool::load(path.clone(), 2).unwrap();
+        assert_eq!(spool.take("nats").unwrap(), [nats("b"), nats("c")]);
+        assert_eq!(Spool::load(path.clone(), 2).unwrap().depth().messages, 0);
+        fs::remove_file(path).unwrap();
+    }
+
+    fn influx(points: usize) -> SpooledMessage {
+        SpooledMessage::Influx {
+            bucket: "swat".to_string(),
+            lines: "forecast current=\"{}\" 1\n".repeat(points),
+        }
+    }
+
+    #[test]
+    fn notices_filling_dropping_and_draining() {
+        let path = env::temp_dir().join(format!(
+            "swat-collector-spool-notices-{}",
+            std::process::id()
+        ));
+        let thresholds = SpoolThresholds {
+            points: Some(20),
+            percent: 50,
+        };
+        let mut spool = Spool::load(path.clone(), 4)
+            .unwrap()
+            .with_thresholds(thresholds);
+
+        // below both thresholds
+        spool.push([influx(5)]).unwrap();
+        assert_eq!(spool.depth().points, 5);
+        assert_eq!(spool.take_notices(), []);
+
+        // half the limit
+        spool.push([nats("a")]).unwrap();
+        let notices = spool.take_notices();
+        assert_eq!(
+            notices,
+            [SpoolNotice::Filling(format!(
+                "spool holds {}, exceeding 50% of its limit of 4 messages",
+                spool.depth()
+            ))]
+        );
+        assert_eq!(notices[0].severity(), Some(Severity::Warning));
+
+        // hovering around the threshold is not noticed again
+        spool.take("nats").unwrap();
+        spool.push([nats("b")]).unwrap();
+        assert_eq!(spool.take_notices(), []);
+
+        // overflowing
+        spool.push([nats("c"), nats("d"), nats("e")]).unwrap();
+        let notices = spool.take_notices();
+        assert_eq!(
+            notices,
+            [SpoolNotice::Dropping(
+                "spool is full at 4 messages and drops the oldest, 1 dropped so far".to_string()
+            )]
+        );
+        assert_eq!(notices[0].severity(), Some(Severity::Critical));
+        spool.push([nats("f")]).unwrap();
+        assert_eq!(spool.take_notices(), []);
+        assert_eq!(spool.dropped(), 2);
+
+        // draining partially and completely
+        spool.take("influx").unwrap();
+        assert_eq!(spool.take_notices(), []);
+        spool.take("nats").unwrap();
+        let notices = spool.take_notices();
+        assert_eq!(
+            notices,
+            [SpoolNotice::Drained(
+                "spool drained, every spooled message was delivered".to_string()
+            )]
+        );
+        assert_eq!(notices[0].severity(), None);
+        assert_eq!(spool.depth(), SpoolDepth::default());
+        assert_eq!(
+            spool.status_text(),
+            "spool: 0 messages, 0 points, 0 bytes, 2 dropped\n"
+        );
+
+        // the points alone exceed their threshold
+        spool.push([influx(25)]).unwrap();
+        assert_eq!(
+            spool.take_notices(),
+            [SpoolNotice::Filling(format!(
+                "spool holds {}, exceeding 20 points",
+                spool.depth()
+            ))]
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn thresholds() {
+        assert_eq!(
+            SpoolThresholds::from_lookup(|_| None).unwrap(),
+            SpoolThresholds::default()
+        );
+        let thresholds = SpoolThresholds::from_lookup(|key| match key {
+            "SPOOL_WARN_POINTS" => Some("5000".to_string()),
+            "SPOOL_WARN_PERCENT" => Some("90".to_string()),
+            _ => None,
+        })
+        .unwrap();
+        assert_eq!(
+            thresholds,
+            SpoolThresholds {
+                points: Some(5000),
+                percent: 90
+            }
+        );
+        assert_eq!(
+            SpoolThresholds::from_lookup(
+                |key| (key == "SPOOL_WARN_PERCENT").then(|| "0".to_string())
+            )
+            .unwrap_err()
+            .to_string(),
+            "expected \"SPOOL_WARN_PERCENT\" to be valid, expected a percentage from 1 to 100"
+        );
+    }
+}
@@ -0,0 +1,76 @@
+use std::time::Duration;
+
+/// How the fetches of a tick are scheduled, configured via `SPREAD_OVER_INTERVAL` and
+/// `SPREAD_ROLLING_WRITES`.
+///
+/// By default every location is fetched right at the start of a tick. Spread over the interval,
+/// each location gets its own phase instead, the `i`-th of `n` locations is fetched `i / n` of
+/// the interval after the start, so large location sets do not hit the swat api in one burst.
+/// The errors of a tick are still alerted together at its end.
+///
+/// The points are written at the end of the tick as well, unless `rolling_writes` writes each
+/// one as soon as it is built.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Spread {
+    pub enabled: bool,
+    pub rolling_writes: bool,
+}
+
+impl Spread {
+    pub fn new(enabled: bool, rolling_writes: bool) -> Spread {
+        Spread {
+            enabled,
+            rolling_writes,
+        }
+    }
+
+    /// Offset from the start of the tick at which the `index`-th of `count` locations is
+    /// fetched within the `interval`.
+    pub fn offset(&self, index: usize, count: usize, interval: Duration) -> Duration {
+        match self.enabled && count > 0 {
+            true => interval * index as u32 / count as u32,
+            false => Duration::ZERO,
+        }
+    }
+
+    /// Points of a bucket written at once, `batch_size` unless the writes are rolling.
+    pub fn batch_size(&self, batch_size: usize) -> usize {
+        match self.enabled && self.rolling_writes {
+            true => 1,
+            false => batch_size,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distributes_phases_evenly() {
+        let interval = Duration::from_secs(120);
+        let spread = Spread::new(true, false);
+        let offsets: Vec<_> = (0..4)
+            .map(|i| spread.offset(i, 4, interval).as_secs())
+            .collect();
+        assert_eq!(offsets, [0, 30, 60, 90]);
+
+        // hundreds of locations stay within the interval
+        let offsets: Vec<_> = (0..500).map(|i| spread.offset(i, 500, interval)).collect();
+        assert!(offsets
+            .windows(2)
+            .all(|pair| pair[1] - pair[0] == offsets[1]));
+        assert!(offsets[499] < interval);
+
+        assert_eq!(Spread::default().offset(3, 4, interval), Duration::ZERO);
+    }
+
+    #[test]
+    fn rolling_writes() {
+        assert_eq!(Spread::new(true, true).batch_size(5000), 1);
+        assert_eq!(Spread::new(true, false).batch_size(5000), 5000);
+
+        // rolling writes only apply while spreading
+        assert_eq!(Spread::new(false, true).batch_size(5000), 5000);
+    }
+}
@@ -0,0 +1,223 @@
+use crate::effective_config::EffectiveConfig;
+use crate::locations;
+use std::collections::BTreeSet;
+
+/// Ticks of a first run whose failures are only logged, overridable via
+/// `FIRST_RUN_QUIET_TICKS`.
+pub const DEFAULT_QUIET_TICKS: u32 = 1;
+
+/// How the collector starts, judged by what earlier runs left behind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Startup {
+    /// Neither a state file nor a schema marker in any bucket, the very first deployment.
+    /// Failures are expected while everything is set up.
+    FirstRun,
+
+    /// The collector ran before, so whatever fails now used to work.
+    Recovering,
+}
+
+impl Startup {
+    /// A first run unless a `state_file` was found or any bucket holds a schema `marker`.
+    pub fn detect(state_file: bool, marker: bool) -> Startup {
+        match state_file || marker {
+            true => Startup::Recovering,
+            false => Startup::FirstRun,
+        }
+    }
+}
+
+/// Counts down the first ticks of a first run, whose failures are logged instead of alerted.
+#[derive(Debug)]
+pub struct QuietStart {
+    remaining: u32,
+}
+
+impl QuietStart {
+    /// Keeps the first `ticks` quiet on a [`Startup::FirstRun`], none otherwise.
+    pub fn new(startup: Startup, ticks: u32) -> QuietStart {
+        QuietStart {
+            remaining: match startup {
+                Startup::FirstRun => ticks,
+                Startup::Recovering => 0,
+            },
+        }
+    }
+
+    /// Whether the failures of the tick starting are not alerted, counting the tick.
+    pub fn quiet(&mut self) -> bool {
+        let quiet = self.remaining > 0;
+        self.remaining = self.remaining.saturating_sub(1);
+        quiet
+    }
+
+    /// The quiet ticks left after the current one.
+    pub fn remaining(&self) -> u32 {
+        self.remaining
+    }
+}
+
+/// The lines logged on a first run, telling what is collected where.
+pub fn onboarding(config: &EffectiveConfig, buckets: &[&str], quiet_ticks: u32) -> Vec<String> {
+    let buckets: Vec<_> = buckets.iter().map(|bucket| format!("{bucket:?}")).collect();
+    vec![
+        "first run, found neither a state file nor a schema marker".to_string(),
+        format!(
+            "  collecting {} locations every {} minutes into the buckets {}",
+            config.locations.len(),
+            config.interval.as_secs() / 60,
+            buckets.join(", ")
+        ),
+        format!(
+            "  {} collector variables set, config hash {}",
+            config.variables.len(),
+            config.hash()
+        ),
+        format!("  not alerting the failures of the first {quiet_ticks} ticks"),
+    ]
+}
+
+/// The lines telling how the `current` configuration differs from the `previous` run's, empty
+/// if it does not.
+pub fn changes(previous: &EffectiveConfig, current: &EffectiveConfig) -> Vec<String> {
+    if previous.hash() == current.hash() {
+        return Vec::new();
+    }
+    let mut lines = vec![format!(
+        "changed since last run, config hash {} -> {}",
+        previous.hash(),
+        current.hash()
+    )];
+    if previous.interval != current.interval {
+        lines.push(format!(
+            "  interval {} -> {} minutes",
+            previous.interval.as_secs() / 60,
+            current.interval.as_secs() / 60
+        ));
+    }
+
+    let (before, after): (BTreeSet<_>, BTreeSet<_>) = (
+        previous.locations.iter().collect(),
+        current.locations.iter().collect(),
+    );
+    for id in after.difference(&before) {
+        lines.push(format!("  + location {}", location_name(**id)));
+    }
+    for id in before.difference(&after) {
+        lines.push(format!("  - location {}", location_name(**id)));
+    }
+
+    for (key, value) in &current.variables {
+        match previous.variables.get(key) {
+            None => lines.push(format!("  + {key}={value:?}")),
+            Some(old) if old != value => lines.push(format!("  ~ {key}={old:?} -> {value:?}")),
+            Some(_) => (),
+        }
+    }
+    for key in previous.variables.keys() {
+        if !current.variables.contains_key(key) {
+            lines.push(format!("  - {key}"));
+        }
+    }
+    lines
+}
+
+fn location_name(id: i64) -> String {
+    match locations::CONFIGURED
+        .iter()
+        .find(|location| location.id == id)
+    {
+        Some(location) => format!("{:?}", location.name),
+        None => format!("#{id}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    fn config(locations: &[i64], variables: &[(&str, &str)]) -> EffectiveConfig {
+        EffectiveConfig {
+            interval: Duration::from_secs(120),
+            locations: locations.to_vec(),
+            variables: (variables.iter())
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect::<BTreeMap<_, _>>(),
+        }
+    }
+
+    #[test]
+    fn detects_first_run() {
+        assert_eq!(Startup::detect(false, false), Startup::FirstRun);
+        assert_eq!(Startup::detect(true, false), Startup::Recovering);
+        assert_eq!(Startup::detect(false, true), Startup::Recovering);
+        assert_eq!(Startup::detect(true, true), Startup::Recovering);
+    }
+
+    #[test]
+    fn quiet_only_on_first_run() {
+        let mut first_run = QuietStart::new(Startup::FirstRun, 2);
+        assert!(first_run.quiet());
+        assert_eq!(first_run.remaining(), 1);
+        assert!(first_run.quiet());
+        assert_eq!(first_run.remaining(), 0);
+        assert!(!first_run.quiet());
+        assert!(!first_run.quiet());
+
+        let mut recovering = QuietStart::new(Startup::Recovering, 2);
+        assert!(!recovering.quiet());
+        assert!(!QuietStart::new(Startup::FirstRun, 0).quiet());
+    }
+
+    #[test]
+    fn renders_changes() {
+        let previous = config(
+            &[1, 2],
+            &[
+                ("QUIET_HOURS", "22:00-06:00"),
+                ("SPREAD_OVER_INTERVAL", "true"),
+            ],
+        );
+        assert!(changes(&previous, &previous.clone()).is_empty());
+
+        let mut current = config(
+            &[1, 3, 9999],
+            &[("QUIET_HOURS", "23:00-06:00"), ("STALE_AFTER", "3")],
+        );
+        current.interval = Duration::from_secs(300);
+        let lines = changes(&previous, &current);
+        assert_eq!(
+            lines[0],
+            format!(
+                "changed since last run, config hash {} -> {}",
+                previous.hash(),
+                current.hash()
+            )
+        );
+        assert_eq!(
+            lines[1..],
+            [
+                "  interval 2 -> 5 minutes",
+                "  + location \"WW Thülsfelde\"",
+                "  + location #9999",
+                "  - location \"WW Marienhafe\"",
+                "  ~ QUIET_HOURS=\"22:00-06:00\" -> \"23:00-06:00\"",
+                "  + STALE_AFTER=\"3\"",
+                "  - SPREAD_OVER_INTERVAL",
+            ]
+        );
+    }
+
+    #[test]
+    fn onboards() {
+        let config = config(&[1, 2], &[("INFLUXDB_BUCKET", "swat")]);
+        let lines = onboarding(&config, &["swat", "research"], 1);
+        assert_eq!(
+            lines[1],
+            "  collecting 2 locations every 2 minutes into the buckets \"swat\", \"research\""
+        );
+        assert_eq!(lines[3], "  not alerting the failures of the first 1 ticks");
+    }
+}
@@ -0,0 +1,526 @@
+#[cfg(feature = "archive")]
+use crate::archive::Archive;
+#[cfg(feature = "chaos")]
+use crate::chaos::Chaos;
+use crate::circuit_breaker::CircuitBreaker;
+use crate::clock::{Clock, SystemClock};
+use crate::duplicates::DuplicateTracker;
+use crate::effective_config::EffectiveConfig;
+use crate::egress::Meter;
+use crate::gaps::GapTracker;
+use crate::groups::LocationGroups;
+#[cfg(feature = "grpc")]
+use crate::grpc::Passes;
+#[cfg(feature = "health-check")]
+use crate::health_check::HealthState;
+use crate::history::ForecastHistory;
+use crate::horizons::HorizonTracker;
+use crate::incident::IncidentTracker;
+use crate::issues::IssueTracker;
+use crate::janitor::Janitor;
+#[cfg(feature = "kafka")]
+use crate::kafka::KafkaOutput;
+use crate::lead_time::LeadTimes;
+use crate::live::LiveFeed;
+use crate::locations::ApiNegotiation;
+use crate::maintenance::Maintenance;
+#[cfg(feature = "nats")]
+use crate::nats::NatsOutput;
+use crate::parse_failures::{self, ParseFailureLog};
+use crate::pipeline::Pipeline;
+use crate::point_rejections::PointRejections;
+use crate::processing::Processing;
+use crate::retry::{Retries, RetryBudget};
+use crate::schema_mode::SchemaMode;
+use crate::skipped_ticks::SkippedTicks;
+use crate::snooze::Snoozes;
+use crate::spool::Spool;
+use crate::spread::Spread;
+use crate::state_file::StateFile;
+use crate::tick_budget::TickBudget;
+use crate::webhook::Mute;
+use parking_lot::{Mutex, RwLock};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// State shared between the collection loop, the notifications and the health listener.
+///
+/// Every part is locked on its own and no lock is held while acquiring another one, so there
+/// is no lock order to keep. Signals are `Copy` and are read out before evaluating them.
+#[derive(Debug)]
+pub struct AppState {
+    #[cfg(feature = "health-check")]
+    pub health: HealthState,
+
+    pub parse_failures: RwLock<ParseFailureLog>,
+
+    /// The alerted errors that need to be resolved.
+    pub incident: RwLock<IncidentTracker>,
+
+    /// Issue times of the forecasts per location.
+    pub issues: RwLock<IssueTracker>,
+
+    /// Typical horizon counts per location and model.
+    pub horizons: RwLock<HorizonTracker>,
+
+    /// Lead times of the points per horizon, counting the horizons clamped to 0.
+    pub lead_times: RwLock<LeadTimes>,
+
+    /// Points per horizon left out of the writes.
+    pub point_rejections: RwLock<PointRejections>,
+
+    /// Time spans without collected forecasts per location.
+    pub gaps: RwLock<GapTracker>,
+
+    /// Locations sharing a grid cell of the swat api.
+    pub duplicates: RwLock<DuplicateTracker>,
+
+    /// Durations per location of the current tick.
+    pub tick_budget: RwLock<TickBudget>,
+
+    /// Ticks skipped as the previous one was still running.
+    pub skipped_ticks: RwLock<SkippedTicks>,
+
+    /// Capacities and depths of the channels between the stages of a tick.
+    pub pipeline: Pipeline,
+
+    /// Whether the fetches are spread over the interval.
+    pub spread: Spread,
+
+    /// Announced maintenance of the swat api, during which failures are not alerted.
+    pub maintenance: Maintenance,
+
+    /// Name of the profile collecting with the state, if profiles are configured.
+    #[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+    pub profile: Option<String>,
+
+    /// Interval of the collection loop, unless it is backing off.
+    pub interval: Duration,
+
+    /// Prunes the directories listed in `PRUNE_DIRS`.
+    pub janitor: Janitor,
+
+    /// Configuration collected with, written as a point on startup.
+    pub config: Option<EffectiveConfig>,
+
+    /// Groups of the collected locations, aggregating the alerts and the status.
+    pub groups: LocationGroups,
+
+    /// Version of the swat api requested, unless running offline.
+    #[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+    pub api: Option<Arc<ApiNegotiation>>,
+
+    /// Bytes transferred per destination, the collector's [`crate::egress::METER`] outside of
+    /// tests.
+    pub egress: Arc<Meter>,
+
+    /// Points written per forecast, from `SCHEMA_MODE` and switchable through the health socket.
+    pub schema_mode: RwLock<SchemaMode>,
+
+    /// Attempts of the forecast requests and the writes, from `REQUEST_ATTEMPTS` and
+    /// `WRITE_ATTEMPTS`.
+    pub retries: Retries,
+
+    /// Retries left in the current tick, shared with the notification queue.
+    pub retry_budget: Arc<RetryBudget>,
+
+    /// Applied to the forecasts before building their points, from `PROCESSING`.
+    pub processing: Processing,
+
+    /// Set through the health socket, shared with the notification queue.
+    pub mute: Arc<RwLock<Mute>>,
+
+    /// The forecasts fetched last per location, shared with the http server.
+    pub history: Arc<RwLock<ForecastHistory>>,
+
+    /// Locations not alerted about, set through the health socket.
+    pub snoozes: RwLock<Snoozes>,
+
+    /// Pushes the fetched forecasts to websocket clients, if `HTTP_ADDR` is set.
+    pub live: Option<Arc<LiveFeed>>,
+
+    /// Parquet archive of the fetched forecasts, if `ARCHIVE_DIR` is set.
+    #[cfg(feature = "archive")]
+    pub archive: Option<Mutex<Archive>>,
+
+    /// Publishes the fetched forecasts, if `KAFKA_BROKERS` is set.
+    #[cfg(feature = "kafka")]
+    pub kafka: Option<KafkaOutput>,
+
+    /// Publishes the fetched forecasts to JetStream, if `NATS_URL` is set.
+    #[cfg(feature = "nats")]
+    pub nats: Option<NatsOutput>,
+
+    /// Failures injected into the forecast requests and the writes, if `CHAOS_CONFIG` is set.
+    #[cfg(feature = "chaos")]
+    pub chaos: Option<Chaos>,
+
+    /// Messages the outputs failed to deliver, if any output spools them.
+    pub spool: Option<Mutex<Spool>>,
+
+    /// Diverts the writes into InfluxDB to the spool while it is down, if
+    /// `CB_FAILURE_THRESHOLD` is set.
+    pub circuit_breaker: Option<Mutex<CircuitBreaker>>,
+
+    /// Passes of the collection loop, for the gRPC clients triggering one.
+    #[cfg(feature = "grpc")]
+    pub passes: Passes,
+
+    /// Where the state is kept across restarts, if `STATE_FILE` is set.
+    pub state_file: Option<PathBuf>,
+
+    /// Writes only into staging buckets, logs the notifications and rejects the control
+    /// commands, from `READ_ONLY`.
+    pub read_only: bool,
+
+    /// Id of the latest tick, kept in the state file so the ids keep increasing across
+    /// restarts.
+    pub tick_id: AtomicU64,
+
+    pub clock: Arc<dyn Clock>,
+}
+
+impl AppState {
+    pub fn new(parse_failure_limit: usize) -> AppState {
+        AppState {
+            #[cfg(feature = "health-check")]
+            health: HealthState::new(),
+            parse_failures: RwLock::new(ParseFailureLog::new(parse_failure_limit)),
+            incident: RwLock::default(),
+            issues: RwLock::default(),
+            horizons: RwLock::default(),
+            lead_times: RwLock::default(),
+            point_rejections: RwLock::default(),
+            gaps: RwLock::new(GapTracker::new(crate::COLLECTION_INTERVAL)),
+            duplicates: RwLock::new(DuplicateTracker::new(
+                crate::duplicates::DEFAULT_TICKS,
+                false,
+            )),
+            tick_budget: RwLock::default(),
+            skipped_ticks: RwLock::default(),
+            pipeline: Pipeline::default(),
+            spread: Spread::default(),
+            maintenance: Maintenance::default(),
+            profile: None,
+            interval: crate::COLLECTION_INTERVAL,
+            janitor: Janitor::default(),
+            config: None,
+            groups: LocationGroups::default(),
+            api: None,
+            egress: Arc::default(),
+            schema_mode: RwLock::default(),
+            retries: Retries::default(),
+            retry_budget: Arc::default(),
+            processing: Processing::default(),
+            mute: Arc::default(),
+            history: Arc::default(),
+            snoozes: RwLock::default(),
+            live: None,
+            #[cfg(feature = "archive")]
+            archive: None,
+            #[cfg(feature = "kafka")]
+            kafka: None,
+            #[cfg(feature = "nats")]
+            nats: None,
+            #[cfg(feature = "chaos")]
+            chaos: None,
+            spool: None,
+            circuit_breaker: None,
+            #[cfg(feature = "grpc")]
+            passes: Passes::default(),
+            state_file: None,
+            read_only: false,
+            tick_id: AtomicU64::new(0),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Forgets the cached state of the locations and models not in `targets` and bounds the
+    /// caches to `capacity` entries, whenever the collected locations are set.
+    pub fn resize_caches(&self, targets: &[String], capacity: usize) {
+        self.issues.write().resize(targets, capacity);
+        self.horizons.write().resize(targets, capacity);
+        self.gaps.write().retain(targets);
+        self.history.write().retain(targets);
+        #[cfg(feature = "health-check")]
+        self.health.resize_caches(targets, capacity);
+    }
+
+    /// The state kept across restarts in the state file.
+    pub fn persisted(&self) -> StateFile {
+        StateFile {
+            horizon_counts: self.horizons.read().counts(),
+            gaps: self.gaps.read().export(),
+            incident: self.incident.read().open_id(),
+            egress: self.egress.today(),
+            snoozes: self.snoozes.read().export(),
+            config: self.config.clone(),
+            mute: self.mute.read().until(self.clock.now_utc()),
+            tick_id: Some(self.tick_id.load(Ordering::Relaxed)).filter(|id| *id > 0),
+        }
+    }
+
+    /// Restores the horizon counts, gaps, open incident, egress, snoozes, mute and tick id
+    /// `persisted` in the state file.
+    pub fn restore(&self, persisted: StateFile) {
+        self.horizons.write().restore(persisted.horizon_counts);
+        self.gaps.write().restore(persisted.gaps);
+        self.incident.write().restore(persisted.incident);
+        self.egress.restore(persisted.egress);
+        self.snoozes.write().restore(persisted.snoozes);
+        self.mute
+            .write()
+            .restore(persisted.mute, self.clock.now_utc());
+        // a reload must not take the ids back
+        self.tick_id
+            .fetch_max(persisted.tick_id.unwrap_or_default(), Ordering::Relaxed);
+    }
+
+    /// Uses the `clock` for the state and its health.
+    #[cfg_attr(not(all(test, feature = "health-check")), allow(dead_code))]
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> AppState {
+        AppState {
+            #[cfg(feature = "health-check")]
+            health: self.health.with_clock(clock.clone()),
+            clock,
+            ..self
+        }
+    }
+
+    pub fn with_issue_tracker(self, issues: IssueTracker) -> AppState {
+        AppState {
+            issues: RwLock::new(issues),
+            ..self
+        }
+    }
+
+    pub fn with_incident_tracker(self, incident: IncidentTracker) -> AppState {
+        AppState {
+            incident: RwLock::new(incident),
+            ..self
+        }
+    }
+
+    pub fn with_horizon_tracker(self, horizons: HorizonTracker) -> AppState {
+        AppState {
+            horizons: RwLock::new(horizons),
+            ..self
+        }
+    }
+
+    pub fn with_duplicate_tracker(self, duplicates: DuplicateTracker) -> AppState {
+        AppState {
+            duplicates: RwLock::new(duplicates),
+            ..self
+        }
+    }
+
+    pub fn with_pipeline(self, pipeline: Pipeline) -> AppState {
+        AppState { pipeline, ..self }
+    }
+
+    pub fn with_spread(self, spread: Spread) -> AppState {
+        AppState { spread, ..self }
+    }
+
+    pub fn with_maintenance(self, maintenance: Maintenance) -> AppState {
+        AppState {
+            maintenance,
+            ..self
+        }
+    }
+
+    /// Names the `profile` collecting with the state, the health file is then written for all
+    /// profiles at once.
+    pub fn with_profile(self, profile: Option<String>) -> AppState {
+        AppState {
+            #[cfg(feature = "health-check")]
+            health: match profile {
+                Some(_) => self.health.without_file(),
+                None => self.health,
+            },
+            profile,
+            ..self
+        }
+    }
+
+    /// Collects every `interval`, the gaps are judged by it as well.
+    pub fn with_interval(self, interval: Duration) -> AppState {
+        AppState {
+            interval,
+            gaps: RwLock::new(GapTracker::new(interval)),
+            ..self
+        }
+    }
+
+    /// Shares the `mute` of alerts with the other profiles.
+    pub fn with_mute(self, mute: Arc<RwLock<Mute>>) -> AppState {
+        AppState { mute, ..self }
+    }
+
+    pub fn with_history(self, history: ForecastHistory) -> AppState {
+        AppState {
+            history: Arc::new(RwLock::new(history)),
+            ..self
+        }
+    }
+
+    pub fn with_janitor(self, janitor: Janitor) -> AppState {
+        AppState { janitor, ..self }
+    }
+
+    pub fn with_config(self, config: EffectiveConfig) -> AppState {
+        AppState {
+            config: Some(config),
+            ..self
+        }
+    }
+
+    pub fn with_groups(self, groups: LocationGroups) -> AppState {
+        AppState { groups, ..self }
+    }
+
+    pub fn with_api(self, api: Option<Arc<ApiNegotiation>>) -> AppState {
+        AppState { api, ..self }
+    }
+
+    pub fn with_egress(self, egress: Arc<Meter>) -> AppState {
+        AppState { egress, ..self }
+    }
+
+    pub fn with_schema_mode(self, schema_mode: SchemaMode) -> AppState {
+        AppState {
+            schema_mode: RwLock::new(schema_mode),
+            ..self
+        }
+    }
+
+    pub fn with_retries(self, retries: Retries) -> AppState {
+        AppState { retries, ..self }
+    }
+
+    pub fn with_read_only(self, read_only: bool) -> AppState {
+        AppState { read_only, ..self }
+    }
+
+    pub fn with_processing(self, processing: Processing) -> AppState {
+        AppState { processing, ..self }
+    }
+
+    pub fn with_live(self, live: Option<Arc<LiveFeed>>) -> AppState {
+        AppState { live, ..self }
+    }
+
+    #[cfg(feature = "archive")]
+    pub fn with_archive(self, archive: Option<Archive>) -> AppState {
+        AppState {
+            archive: archive.map(Mutex::new),
+            ..self
+        }
+    }
+
+    #[cfg(feature = "kafka")]
+    pub fn with_kafka(self, kafka: Option<KafkaOutput>) -> AppState {
+        AppState { kafka, ..self }
+    }
+
+    #[cfg(feature = "nats")]
+    pub fn with_nats(self, nats: Option<NatsOutput>) -> AppState {
+        AppState { nats, ..self }
+    }
+
+    #[cfg(feature = "chaos")]
+    pub fn with_chaos(self, chaos: Option<Chaos>) -> AppState {
+        AppState { chaos, ..self }
+    }
+
+    pub fn with_spool(self, spool: Option<Spool>) -> AppState {
+        AppState {
+            spool: spool.map(Mutex::new),
+            ..self
+        }
+    }
+
+    pub fn with_circuit_breaker(self, circuit_breaker: Option<CircuitBreaker>) -> AppState {
+        AppState {
+            circuit_breaker: circuit_breaker.map(Mutex::new),
+            ..self
+        }
+    }
+
+    pub fn with_tick_budget(self, tick_budget: TickBudget) -> AppState {
+        AppState {
+            tick_budget: RwLock::new(tick_budget),
+            ..self
+        }
+    }
+
+    pub fn with_state_file(self, state_file: Option<PathBuf>) -> AppState {
+        AppState { state_file, ..self }
+    }
+
+    pub fn with_skipped_ticks(self, skipped_ticks: SkippedTicks) -> AppState {
+        AppState {
+            skipped_ticks: RwLock::new(skipped_ticks),
+            ..self
+        }
+    }
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        AppState::new(parse_failures::DEFAULT_LIMIT)
+    }
+}
+
+#[cfg(all(test, feature = "health-check"))]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn concurrent_update_and_read() {
+        let state = Arc::new(AppState::default());
+        let locations = ["WW Großenkneten", "WW Kleinenkneten", "WW Wildeshausen"];
+
+        let writers = (0..4).map(|i| {
+            let state = state.clone();
+            thread::spawn(move || {
+                for n in 0..500 {
+                    let location = locations[(i + n) % locations.len()];
+                    state.health.tick();
+                    state.health.update();
+                    state.health.record_error(location, "parse", "invalid json");
+                    state.parse_failures.write().body(location, "{}");
+                    state.health.clear_error(location);
+                    state
+                        .incident
+                        .write()
+                        .observe(n % 2, location, chrono::Utc::now());
+                }
+            })
+        });
+        let readers = (0..4).map(|_| {
+            let state = state.clone();
+            thread::spawn(move || {
+                for _ in 0..500 {
+                    let _ = state.health.signals();
+                    let _ = state.health.status_text();
+                    let _ = state.health.evaluate_transition();
+                    state.parse_failures.write().start_tick();
+                }
+            })
+        });
+
+        let handles: Vec<_> = writers.chain(readers).collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        // every recorded error was cleared again and the signals are recent
+        assert!(state.health.signals().healthy());
+        assert!(!state.health.status_text().contains("recent errors"));
+    }
+}
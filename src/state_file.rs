@@ -0,0 +1,245 @@
+use crate::effective_config::EffectiveConfig;
+use crate::egress::DailyEgress;
+use crate::gaps::TargetGaps;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsString;
+use std::path::Path;
+use std::{fs, io};
+use thiserror::Error;
+
+/// State kept across restarts in the file at `STATE_FILE`, if set.
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateFile {
+    /// Recent horizon counts per location and model, the baseline for short forecasts.
+    #[serde(default)]
+    pub horizon_counts: BTreeMap<String, Vec<usize>>,
+
+    /// Gaps in the collected forecasts per location and model.
+    #[serde(default)]
+    pub gaps: BTreeMap<String, TargetGaps>,
+
+    /// The id of the incident open when saved, as in the alerts and their audit.
+    #[serde(default)]
+    pub incident: Option<String>,
+
+    /// The bytes transferred today, so a restart does not reset them.
+    #[serde(default)]
+    pub egress: DailyEgress,
+
+    /// The snoozed locations and until when.
+    #[serde(default)]
+    pub snoozes: BTreeMap<String, DateTime<Utc>>,
+
+    /// The configuration collected with when saved, compared with the current one on startup.
+    #[serde(default)]
+    pub config: Option<EffectiveConfig>,
+
+    /// Until when alerts were muted when saved.
+    #[serde(default)]
+    pub mute: Option<DateTime<Utc>>,
+
+    /// The id of the latest tick when saved, the ids of the next ticks follow it.
+    #[serde(default)]
+    pub tick_id: Option<u64>,
+}
+
+/// Version of the exported state, raised whenever an older collector could not import it.
+pub const EXPORT_VERSION: u32 = 1;
+
+/// The [`StateFile`] exported for moving it to another host, see `state export`.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateExport {
+    pub version: u32,
+    pub exported: DateTime<Utc>,
+    pub state: StateFile,
+}
+
+#[derive(Debug, Error)]
+pub enum StateFileError {
+    #[error("could not access state file, {0}")]
+    Io(#[from] io::Error),
+
+    #[error("invalid state file, {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("expected exported state of version {EXPORT_VERSION}, found version {0}")]
+    Version(u32),
+}
+
+impl StateFile {
+    /// Reads the state file at `path`, a missing one is empty.
+    pub fn load(path: &Path) -> Result<StateFile, StateFileError> {
+        match fs::read_to_string(path) {
+            Ok(state) => Ok(serde_json::from_str(&state)?),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(StateFile::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Replaces the state file at `path`, so it is never read half written.
+    pub fn save(&self, path: &Path) -> Result<(), StateFileError> {
+        let mut temporary = OsString::from(path.as_os_str());
+        temporary.push(".tmp");
+        fs::write(&temporary, serde_json::to_string(self)? + "\n")?;
+        fs::rename(&temporary, path)?;
+        Ok(())
+    }
+
+    /// The locations and models with any state, named like [`crate::locations::Target`]'s
+    /// `Display`.
+    pub fn targets(&self) -> BTreeSet<&str> {
+        self.horizon_counts
+            .keys()
+            .chain(self.gaps.keys())
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// The [`targets`](StateFile::targets) missing in the `known` ones, their state is kept
+    /// but not collected.
+    pub fn unknown_targets(&self, known: &[String]) -> Vec<&str> {
+        self.targets()
+            .into_iter()
+            .filter(|target| !known.iter().any(|known| known == target))
+            .collect()
+    }
+}
+
+impl StateExport {
+    pub fn new(state: StateFile, now: DateTime<Utc>) -> StateExport {
+        StateExport {
+            version: EXPORT_VERSION,
+            exported: now,
+            state,
+        }
+    }
+
+    /// Reads the exported state at `path`, failing before reading the state if its version
+    /// differs.
+    pub fn read(path: &Path) -> Result<StateExport, StateFileError> {
+        #[derive(Deserialize)]
+        struct Versioned {
+            version: u32,
+        }
+
+        let export = fs::read_to_string(path)?;
+        let Versioned { version } = serde_json::from_str(&export)?;
+        if version != EXPORT_VERSION {
+            return Err(StateFileError::Version(version));
+        }
+        Ok(serde_json::from_str(&export)?)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), StateFileError> {
+        fs::write(path, serde_json::to_string_pretty(self)? + "\n")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::egress::{Class, Transfer};
+    use std::env;
+
+    #[test]
+    fn round_trip() {
+        let path = env::temp_dir().join(format!("swat-collector-state-{}", std::process::id()));
+        assert_eq!(StateFile::load(&path).unwrap(), StateFile::default());
+
+        let state = StateFile {
+            horizon_counts: BTreeMap::from([("WW Großenkneten".to_string(), vec![36, 35])]),
+            gaps: BTreeMap::from([("WW Großenkneten".to_string(), TargetGaps::default())]),
+            incident: Some("20240501T120000Z".to_string()),
+            egress: DailyEgress {
+                day: chrono::NaiveDate::from_ymd_opt(2024, 5, 1),
+                classes: BTreeMap::from([(Class::Swat, Transfer::sent(412))]),
+            },
+            snoozes: BTreeMap::from([(
+                "WW Großenkneten".to_string(),
+                DateTime::<Utc>::from_timestamp(1715169600, 0).unwrap(),
+            )]),
+            config: Some(EffectiveConfig {
+                interval: std::time::Duration::from_secs(120),
+                locations: vec![1, 2],
+                variables: BTreeMap::from([("STALE_AFTER".to_string(), "3".to_string())]),
+            }),
+            mute: DateTime::<Utc>::from_timestamp(1714568400, 0),
+            tick_id: Some(28_578_000),
+        };
+        state.save(&path).unwrap();
+        assert_eq!(StateFile::load(&path).unwrap(), state);
+
+        // fields added later are missing in older files
+        fs::write(&path, "{}").unwrap();
+        assert_eq!(StateFile::load(&path).unwrap(), StateFile::default());
+        fs::write(&path, "garbage").unwrap();
+        assert!(StateFile::load(&path).is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    fn state() -> StateFile {
+        StateFile {
+            horizon_counts: BTreeMap::from([("WW Großenkneten".to_string(), vec![36, 35])]),
+            gaps: BTreeMap::from([("WW Harpstedt (icon-d2)".to_string(), TargetGaps::default())]),
+            incident: None,
+            egress: DailyEgress::default(),
+            snoozes: BTreeMap::new(),
+            config: None,
+            mute: None,
+            tick_id: None,
+        }
+    }
+
+    #[test]
+    fn export_round_trip() {
+        let path = env::temp_dir().join(format!("swat-collector-export-{}", std::process::id()));
+        let now = DateTime::<Utc>::from_timestamp(1714564800, 0).unwrap();
+        let export = StateExport::new(state(), now);
+        export.write(&path).unwrap();
+        assert_eq!(StateExport::read(&path).unwrap(), export);
+
+        // the imported state is saved as the state file and exported alike again
+        let state_path = path.with_extension("state");
+        export.state.save(&state_path).unwrap();
+        let reexported = StateExport::new(StateFile::load(&state_path).unwrap(), now);
+        assert_eq!(reexported, export);
+
+        fs::remove_file(path).unwrap();
+        fs::remove_file(state_path).unwrap();
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let path = env::temp_dir().join(format!("swat-collector-version-{}", std::process::id()));
+        fs::write(
+            &path,
+            r#"{"version":2,"exported":"2024-05-01T12:00:00Z","baselines":{}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            StateExport::read(&path).unwrap_err().to_string(),
+            "expected exported state of version 1, found version 2"
+        );
+
+        fs::write(&path, r#"{"state":{}}"#).unwrap();
+        assert!(matches!(
+            StateExport::read(&path),
+            Err(StateFileError::Json(_))
+        ));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn flags_unknown_targets() {
+        let state = state();
+        assert_eq!(
+            state.targets(),
+            BTreeSet::from(["WW Großenkneten", "WW Harpstedt (icon-d2)"])
+        );
+        let known = ["WW Großenkneten".to_string(), "WW Harpstedt".to_string()];
+        assert_eq!(state.unknown_targets(&known), ["WW Harpstedt (icon-d2)"]);
+    }
+}
@@ -0,0 +1,28 @@
+//! Client of the SWAT api and its forecasts.
+//!
+//! The forecasts are requested per [`Target`], a location together with one of the forecast
+//! models. The base url of the api is passed to every request, so a proxy or a mock can stand
+//! in for [`DEFAULT_API_URL`]. The [`ApiNegotiation`] decides between the versions of the api.
+//!
+//! ```no_run
+//! use swat_collector::swat::{ForecastSource, Location, Model, Target, DEFAULT_API_URL};
+//!
+//! # async fn collect() -> Result<(), swat_collector::swat::RequestLocationError> {
+//! let source = ForecastSource::Api {
+//!     client: reqwest::Client::new(),
+//!     url: DEFAULT_API_URL.to_string(),
+//!     api: Default::default(),
+//! };
+//! let location = Location { group: "", id: 1, lat: "52.9", lon: "8.2", name: "WW Großenkneten" };
+//! let model = Model::default_model();
+//! let forecast = source.forecast(Target { location: &location, model: &model }).await?;
+//! println!("issued at {}, {} forecasts", forecast.from, forecast.forecasts.len());
+//! # Ok(())
+//! # }
+//! ```
+
+pub use crate::locations::{
+    parse_forecast, parse_forecast_v2, ApiNegotiation, ApiNegotiationError, ApiVersion, Extra,
+    Forecast, ForecastSource, Location, Model, RequestLocationError, Target, DEFAULT_API_URL,
+    DEFAULT_MODEL,
+};
@@ -0,0 +1,272 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use reqwest::header::{self, HeaderMap, HeaderValue};
+use std::fmt;
+use std::fs;
+use thiserror::Error;
+
+/// The value of `SWAT_AUTH` is never part of the errors, it holds the credential.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum SwatAuthError {
+    #[error("expected only one of \"SWAT_AUTH\" and \"SWAT_AUTH_FILE\"")]
+    Ambiguous,
+
+    #[error("could not read \"SWAT_AUTH_FILE\" {path:?}, {error}")]
+    File { path: String, error: String },
+
+    #[error("expected {0:?} to be \"bearer:<token>\" or \"basic:<user>:<password>\"")]
+    Format(&'static str),
+}
+
+/// Credentials sent with every request to the swat api, for running it behind authentication.
+///
+/// Neither `Debug` nor `Display` show the credential, and the header is marked sensitive so it
+/// stays out of the debug output of reqwest as well.
+#[derive(Clone, PartialEq, Eq)]
+pub enum SwatAuth {
+    Bearer(String),
+    Basic { user: String, password: String },
+}
+
+impl SwatAuth {
+    /// Reads the credentials from `SWAT_AUTH` or the file at `SWAT_AUTH_FILE`, none if neither
+    /// is set.
+    pub fn from_lookup(
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<Option<SwatAuth>, SwatAuthError> {
+        let (key, value) = match (lookup("SWAT_AUTH"), lookup("SWAT_AUTH_FILE")) {
+            (None, None) => return Ok(None),
+            (Some(_), Some(_)) => return Err(SwatAuthError::Ambiguous),
+            (Some(value), None) => ("SWAT_AUTH", value),
+            (None, Some(path)) => {
+                let value = fs::read_to_string(&path).map_err(|err| SwatAuthError::File {
+                    path,
+                    error: err.to_string(),
+                })?;
+                ("SWAT_AUTH_FILE", value)
+            }
+        };
+        let auth = match value.trim().split_once(':') {
+            Some(("bearer", token)) if !token.is_empty() => SwatAuth::Bearer(token.to_string()),
+            Some(("basic", credentials)) => match credentials.split_once(':') {
+                Some((user, password)) if !user.is_empty() => SwatAuth::Basic {
+                    user: user.to_string(),
+                    password: password.to_string(),
+                },
+                _ => return Err(SwatAuthError::Format(key)),
+            },
+            _ => return Err(SwatAuthError::Format(key)),
+        };
+        Ok(Some(auth))
+    }
+
+    /// The `Authorization` header, marked sensitive.
+    pub fn header(&self) -> HeaderValue {
+        let value = match self {
+            SwatAuth::Bearer(token) => format!("Bearer {token}"),
+            SwatAuth::Basic { user, password } => {
+                format!("Basic {}", STANDARD.encode(format!("{user}:{password}")))
+            }
+        };
+        let mut header = HeaderValue::from_str(&value)
+            .unwrap_or_else(|_| HeaderValue::from_static("invalid credentials"));
+        header.set_sensitive(true);
+        header
+    }
+
+    /// The `builder` sending the credentials with every request of the client.
+    pub fn apply(&self, builder: reqwest::ClientBuilder) -> reqwest::ClientBuilder {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, self.header());
+        builder.default_headers(headers)
+    }
+}
+
+impl fmt::Debug for SwatAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SwatAuth::Bearer(_) => f.write_str("Bearer(..)"),
+            SwatAuth::Basic { .. } => f.write_str("Basic(..)"),
+        }
+    }
+}
+
+impl fmt::Display for SwatAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SwatAuth::Bearer(_) => f.write_str("a bearer token"),
+            SwatAuth::Basic { .. } => f.write_str("basic auth"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use crate::locations::{Location, Model, RequestLocationError, Target};
+    use crate::retry::{RetryBudget, RetryPolicy};
+    use crate::severity::{Severity, SeverityMapping};
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use warp::http::StatusCode;
+    use warp::Filter;
+
+    const BODY: &str = r#"{"vorhersageZeit": "2024-03-07 08:05", "lat": 52.9, "lon": 8.2,
+        "aktuell": {"2024-03-07 08:05": 0}, "vorhersage": {"2024-03-07 08:10": 1}}"#;
+
+    fn lookup<'a>(vars: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        move |key| {
+            vars.iter()
+                .find(|(name, _)| *name == key)
+                .map(|(_, value)| value.to_string())
+        }
+    }
+
+    /// Mock of the swat api answering with `status` unless it is ok, recording the
+    /// `Authorization` headers.
+    fn serve(status: StatusCode) -> (String, Arc<Mutex<Vec<Option<String>>>>) {
+        let headers = Arc::new(Mutex::new(Vec::new()));
+        let recorded = headers.clone();
+        let route = warp::header::optional::<String>("authorization").map(
+            move |authorization: Option<String>| {
+                recorded.lock().push(authorization);
+                let body = match status {
+                    StatusCode::OK => BODY,
+                    _ => "",
+                };
+                warp::reply::with_status(body, status)
+            },
+        );
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        (format!("http://{addr}"), headers)
+    }
+
+    fn client(auth: &SwatAuth) -> reqwest::Client {
+        auth.apply(reqwest::Client::builder()).build().unwrap()
+    }
+
+    async fn request(client: &reqwest::Client, url: &str) -> Result<(), RequestLocationError> {
+        let location = Location {
+            group: "",
+            id: 1,
+            lat: "52.9",
+            lon: "8.2",
+            name: "WW Großenkneten",
+        };
+        let model = Model::default_model();
+        let target = Target {
+            location: &location,
+            model: &model,
+        };
+        target
+            .location
+            .request_forecast(client, url, target.model)
+            .await
+            .map(drop)
+    }
+
+    #[test]
+    fn reads_credentials() {
+        assert_eq!(SwatAuth::from_lookup(lookup(&[])), Ok(None));
+        assert_eq!(
+            SwatAuth::from_lookup(lookup(&[("SWAT_AUTH", "bearer:s3cret")])),
+            Ok(Some(SwatAuth::Bearer("s3cret".to_string())))
+        );
+        // the password may contain colons
+        assert_eq!(
+            SwatAuth::from_lookup(lookup(&[("SWAT_AUTH", "basic:collector:s3:cret")])),
+            Ok(Some(SwatAuth::Basic {
+                user: "collector".to_string(),
+                password: "s3:cret".to_string()
+            }))
+        );
+
+        let path = std::env::temp_dir().join(format!("swat-auth-{}", std::process::id()));
+        fs::write(&path, "bearer:from-file\n").unwrap();
+        let path_text = path.to_string_lossy().into_owned();
+        let auth = SwatAuth::from_lookup(lookup(&[("SWAT_AUTH_FILE", &path_text)])).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(auth, Some(SwatAuth::Bearer("from-file".to_string())));
+
+        assert_eq!(
+            SwatAuth::from_lookup(lookup(&[
+                ("SWAT_AUTH", "bearer:s3cret"),
+                ("SWAT_AUTH_FILE", &path_text)
+            ])),
+            Err(SwatAuthError::Ambiguous)
+        );
+        for value in ["s3cret", "bearer:", "basic:s3cret", "digest:user:s3cret"] {
+            let err = SwatAuth::from_lookup(lookup(&[("SWAT_AUTH", value)])).unwrap_err();
+            assert_eq!(err, SwatAuthError::Format("SWAT_AUTH"));
+        }
+    }
+
+    #[test]
+    fn never_shows_credentials() {
+        let auths = [
+            SwatAuth::Bearer("s3cret".to_string()),
+            SwatAuth::Basic {
+                user: "collector".to_string(),
+                password: "s3cret".to_string(),
+            },
+        ];
+        for auth in auths {
+            let shown = format!("{auth} {auth:?} {:?}", auth.header());
+            assert!(!shown.contains("s3cret"), "{shown}");
+            assert!(auth.header().is_sensitive());
+        }
+    }
+
+    #[tokio::test]
+    async fn sends_bearer_token() {
+        let (url, headers) = serve(StatusCode::OK);
+        let client = client(&SwatAuth::Bearer("s3cret".to_string()));
+        request(&client, &url).await.unwrap();
+        assert_eq!(*headers.lock(), [Some("Bearer s3cret".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn sends_basic_auth() {
+        let (url, headers) = serve(StatusCode::OK);
+        let client = client(&SwatAuth::Basic {
+            user: "collector".to_string(),
+            password: "s3cret".to_string(),
+        });
+        request(&client, &url).await.unwrap();
+        // base64 of "collector:s3cret"
+        assert_eq!(
+            *headers.lock(),
+            [Some("Basic Y29sbGVjdG9yOnMzY3JldA==".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_unauthorized() {
+        let client = client(&SwatAuth::Bearer("s3cret".to_string()));
+        for status in [StatusCode::UNAUTHORIZED, StatusCode::FORBIDDEN] {
+            let (url, headers) = serve(status);
+            let policy = RetryPolicy {
+                attempts: 3,
+                delay: Duration::from_millis(1),
+            };
+            let clock = MockClock::new();
+            let (result, attempts) = policy
+                .run(&clock, &RetryBudget::default(), || request(&client, &url))
+                .await;
+
+            let err = result.unwrap_err();
+            assert!(
+                matches!(err, RequestLocationError::Unauthorized(answered) if answered == status)
+            );
+            assert!(!err.to_string().contains("s3cret"), "{err}");
+            assert_eq!(attempts.count(), 1);
+            assert_eq!(headers.lock().len(), 1);
+
+            let severities = SeverityMapping::from_lookup(|_| None).unwrap();
+            assert_eq!(severities.severity(&err.into()), Severity::Critical);
+        }
+    }
+}
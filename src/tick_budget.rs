@@ -0,0 +1,157 @@
+use std::time::Duration;
+
+/// Ticks taking longer than this percentage of the interval are alerted unless
+/// `TICK_BUDGET_PERCENT` is set.
+pub const DEFAULT_BUDGET_PERCENT: u32 = 80;
+
+/// Number of the slowest locations named in an overrun warning.
+const SLOWEST: usize = 3;
+
+/// Measures how much of the collection interval a tick takes.
+///
+/// A tick running into the next one delays it, so ticks exceeding the budget are warned about
+/// along with the locations that took the longest.
+#[derive(Debug)]
+pub struct TickBudget {
+    percent: u32,
+
+    /// Durations per location and model of the current tick.
+    durations: Vec<(String, Duration)>,
+
+    /// Time the current tick waited for the phases of the locations, see [`Spread`].
+    ///
+    /// [`Spread`]: crate::spread::Spread
+    waited: Duration,
+}
+
+impl TickBudget {
+    pub fn new(percent: u32) -> TickBudget {
+        TickBudget {
+            percent,
+            durations: Vec::new(),
+            waited: Duration::ZERO,
+        }
+    }
+
+    /// Starts a new tick, forgetting the durations of the last one.
+    pub fn start_tick(&mut self) {
+        self.durations.clear();
+        self.waited = Duration::ZERO;
+    }
+
+    /// Records that the tick waited `duration` for the phase of a location, which does not
+    /// count towards the budget.
+    pub fn wait(&mut self, duration: Duration) {
+        self.waited += duration;
+    }
+
+    /// Time the current tick waited for the phases of the locations so far.
+    pub fn waited(&self) -> Duration {
+        self.waited
+    }
+
+    /// Records that handling `target`, the location and model, took `duration`.
+    pub fn record(&mut self, target: &str, duration: Duration) {
+        self.durations.push((target.to_string(), duration));
+    }
+
+    /// The `n` slowest locations of the current tick, slowest first.
+    fn slowest(&self, n: usize) -> Vec<&(String, Duration)> {
+        let mut durations: Vec<_> = self.durations.iter().collect();
+        durations.sort_by_key(|(_, duration)| std::cmp::Reverse(*duration));
+        durations.truncate(n);
+        durations
+    }
+
+    /// Returns the warning to send if the tick taking `elapsed` exceeded the budget of the
+    /// `interval`, not counting the time waited for the phases.
+    pub fn check(&self, elapsed: Duration, interval: Duration) -> Option<String> {
+        let budget = interval.mul_f64(self.percent as f64 / 100.0);
+        let elapsed = elapsed.saturating_sub(self.waited);
+        if elapsed <= budget {
+            return None;
+        }
+
+        let slowest: Vec<_> = self
+            .slowest(SLOWEST)
+            .into_iter()
+            .map(|(target, duration)| format!("{target} ({:.1}s)", duration.as_secs_f64()))
+            .collect();
+        Some(format!(
+            "tick took {:.1}s, more than {}% of the {}s interval, slowest were {}",
+            elapsed.as_secs_f64(),
+            self.percent,
+            interval.as_secs(),
+            slowest.join(", ")
+        ))
+    }
+}
+
+impl Default for TickBudget {
+    fn default() -> Self {
+        TickBudget::new(DEFAULT_BUDGET_PERCENT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: f64) -> Duration {
+        Duration::from_secs_f64(secs)
+    }
+
+    #[test]
+    fn detects_overrun() {
+        let mut budget = TickBudget::default();
+        budget.start_tick();
+        budget.record("A", secs(20.0));
+        let interval = Duration::from_secs(120);
+
+        assert_eq!(budget.check(secs(90.0), interval), None);
+        assert_eq!(budget.check(secs(96.0), interval), None);
+        assert_eq!(
+            budget.check(secs(96.5), interval).unwrap(),
+            "tick took 96.5s, more than 80% of the 120s interval, slowest were A (20.0s)"
+        );
+
+        let budget = TickBudget::new(50);
+        assert!(budget.check(secs(61.0), interval).is_some());
+    }
+
+    #[test]
+    fn excludes_phase_waits() {
+        let mut budget = TickBudget::default();
+        budget.start_tick();
+        budget.record("A", secs(20.0));
+        budget.wait(secs(90.0));
+        let interval = Duration::from_secs(120);
+        assert_eq!(budget.check(secs(110.0), interval), None);
+        assert!(budget.check(secs(190.0), interval).is_some());
+
+        budget.start_tick();
+        assert!(budget.check(secs(110.0), interval).is_some());
+    }
+
+    #[test]
+    fn names_slowest_three() {
+        let mut budget = TickBudget::default();
+        budget.record("stale", secs(60.0));
+        budget.start_tick();
+        for (target, duration) in [
+            ("A", 1.0),
+            ("B", 12.34),
+            ("C", 0.5),
+            ("D", 30.0),
+            ("E", 8.0),
+        ] {
+            budget.record(target, secs(duration));
+        }
+
+        let message = budget.check(secs(100.0), Duration::from_secs(120)).unwrap();
+        assert!(
+            message.ends_with("slowest were D (30.0s), B (12.3s), E (8.0s)"),
+            "{message}"
+        );
+    }
+}
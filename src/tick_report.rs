@@ -0,0 +1,263 @@
+use crate::locations::Target;
+use crate::points::HandleLocationError;
+use crate::redact;
+use crate::retry::BudgetUsage;
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::{Serialize, Serializer};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// What became of the forecast of a location in a tick.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum Outcome {
+    Written {
+        issued: String,
+        points: usize,
+    },
+
+    /// Spooled while the circuit of the sink is open, written once it closes.
+    Spooled {
+        issued: String,
+        points: usize,
+    },
+
+    /// In the database already, like written by an instance overlapping during a deploy.
+    Existing {
+        issued: String,
+    },
+
+    /// Identical to the forecast of `canonical`, which shares its grid cell.
+    Duplicate {
+        canonical: String,
+    },
+
+    /// The error of the location, kept as is in the [`TickReport::errors`].
+    Failed {
+        kind: &'static str,
+        error: String,
+
+        /// Whether the error was injected by the `CHAOS_CONFIG`.
+        #[cfg(feature = "chaos")]
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        synthetic: bool,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LocationReport {
+    /// The location and model.
+    pub location: String,
+
+    /// Time taken to fetch the forecast and build its points.
+    #[serde(rename = "duration_ms", serialize_with = "millis")]
+    pub duration: Duration,
+
+    #[serde(flatten)]
+    pub outcome: Outcome,
+}
+
+/// What happened in a tick, for the collection loop to act upon and for `once` to print.
+#[derive(Debug, Serialize)]
+pub struct TickReport<'l> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+
+    /// Whether the collector runs with `READ_ONLY`.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub read_only: bool,
+
+    pub tick_id: u64,
+    pub started: DateTime<Utc>,
+
+    #[serde(rename = "elapsed_ms", serialize_with = "millis")]
+    pub elapsed: Duration,
+
+    /// The outcomes in the order the locations are collected in.
+    pub locations: Vec<LocationReport>,
+
+    /// Bytes of line protocol written into InfluxDB.
+    pub bytes_written: u64,
+
+    /// Points of the written forecasts that could not be built.
+    pub points_rejected: u64,
+
+    /// The notifications queued, held for quiet hours or muted during the tick.
+    pub notifications: Vec<String>,
+
+    /// The maintenance window the tick ended in, whose failures are not alerted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub maintenance: Option<String>,
+
+    /// `ticks_skipped_total`, the ticks skipped since the start.
+    #[serde(skip)]
+    pub ticks_skipped_total: u64,
+
+    /// Retries granted and denied in the tick.
+    pub retry_budget: BudgetUsage,
+
+    /// The errors of the failed locations, for alerting.
+    #[serde(skip)]
+    pub errors: Vec<(Target<'l>, HandleLocationError)>,
+}
+
+fn millis<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(duration.as_millis() as u64)
+}
+
+impl TickReport<'_> {
+    /// The points of the forecasts written in the tick.
+    pub fn points_written(&self) -> usize {
+        (self.locations.iter())
+            .map(|location| match location.outcome {
+                Outcome::Written { points, .. } => points,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// The locations that failed.
+    #[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+    pub fn failed(&self) -> Vec<String> {
+        self.errors
+            .iter()
+            .map(|(target, _)| target.to_string())
+            .collect()
+    }
+
+    pub fn json(&self) -> String {
+        serde_json::to_string(self).expect("reports serialize") + "\n"
+    }
+
+    /// A line for the tick and one per location, as printed by `once`.
+    pub fn text(&self) -> String {
+        let written = (self.locations.iter())
+            .filter(|location| matches!(location.outcome, Outcome::Written { .. }))
+            .count();
+        let mut text = String::new();
+        if let Some(profile) = &self.profile {
+            let _ = write!(text, "profile {profile}, ");
+        }
+        let _ = writeln!(
+            text,
+            "tick #{}: wrote {written} of {} locations, {} failed, {} bytes in {:.1}s",
+            self.tick_id,
+            self.locations.len(),
+            self.errors.len(),
+            self.bytes_written,
+            self.elapsed.as_secs_f64()
+        );
+        for location in &self.locations {
+            let outcome = match &location.outcome {
+                Outcome::Written { issued, points } => {
+                    format!("wrote {points} points issued at {issued}")
+                }
+                Outcome::Spooled { issued, points } => {
+                    format!("spooled {points} points issued at {issued}")
+                }
+                Outcome::Existing { issued } => format!("issued at {issued} is in db already"),
+                Outcome::Duplicate { canonical } => format!("same as {canonical:?}"),
+                #[cfg(feature = "chaos")]
+                Outcome::Failed {
+                    kind,
+                    error,
+                    synthetic: true,
+                } => format!("failed ({kind}, synthetic), {error}"),
+                Outcome::Failed { kind, error, .. } => format!("failed ({kind}), {error}"),
+            };
+            let _ = writeln!(text, "  {}: {outcome}", location.location);
+        }
+        if self.retry_budget.denied > 0 {
+            let _ = writeln!(
+                text,
+                "  retry budget of {} retries exhausted, {} retries denied",
+                self.retry_budget.limit, self.retry_budget.denied
+            );
+        }
+        for notification in &self.notifications {
+            let _ = writeln!(text, "  notification: {notification}");
+        }
+        text
+    }
+}
+
+/// Collects the outcomes of the stages of a tick, which run concurrently.
+#[derive(Debug, Default)]
+pub struct TickRecorder {
+    durations: Mutex<HashMap<String, Duration>>,
+    outcomes: Mutex<HashMap<String, Outcome>>,
+    bytes_written: AtomicU64,
+    points_rejected: AtomicU64,
+}
+
+impl TickRecorder {
+    pub fn duration(&self, target: &str, duration: Duration) {
+        self.durations.lock().insert(target.to_string(), duration);
+    }
+
+    pub fn outcome(&self, target: &str, outcome: Outcome) {
+        self.outcomes.lock().insert(target.to_string(), outcome);
+    }
+
+    pub fn written(&self, bytes: usize) {
+        self.bytes_written
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn rejected(&self, points: usize) {
+        self.points_rejected
+            .fetch_add(points as u64, Ordering::Relaxed);
+    }
+
+    /// The report of collecting the `targets`, of which the `errors` failed.
+    pub fn report<'l>(
+        self,
+        tick_id: u64,
+        started: DateTime<Utc>,
+        elapsed: Duration,
+        targets: &[Target<'l>],
+        errors: Vec<(Target<'l>, HandleLocationError)>,
+    ) -> TickReport<'l> {
+        let mut outcomes = self.outcomes.into_inner();
+        for (target, error) in &errors {
+            let outcome = Outcome::Failed {
+                kind: error.kind().code(),
+                error: redact::text(&error.to_string()).into_owned(),
+                #[cfg(feature = "chaos")]
+                synthetic: error.is_synthetic(),
+            };
+            outcomes.insert(target.to_string(), outcome);
+        }
+        let durations = self.durations.into_inner();
+        let locations = targets
+            .iter()
+            .map(ToString::to_string)
+            .filter_map(|target| {
+                let outcome = outcomes.remove(&target)?;
+                Some(LocationReport {
+                    duration: durations.get(&target).copied().unwrap_or_default(),
+                    location: target,
+                    outcome,
+                })
+            })
+            .collect();
+        TickReport {
+            profile: None,
+            read_only: false,
+            tick_id,
+            started,
+            elapsed,
+            locations,
+            bytes_written: self.bytes_written.into_inner(),
+            points_rejected: self.points_rejected.into_inner(),
+            notifications: Vec::new(),
+            maintenance: None,
+            ticks_skipped_total: 0,
+            retry_budget: BudgetUsage::default(),
+            errors,
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use crate::severity::Severity;
+use crate::tick_report::TickReport;
+use crate::version;
+use influxdb2::models::data_point::DataPointError;
+use influxdb2::models::DataPoint;
+
+/// Measurement the statistics of every tick are written into.
+pub const MEASUREMENT: &str = "collector_stats";
+
+/// The statistics point of the tick of the `report`.
+///
+/// The point is tagged with the build of the collector, whether it runs with `READ_ONLY`, the
+/// maintenance window the tick ended in and the highest severity of the failed locations, `none`
+/// without any, and counts the failures per severity and during maintenance along with the
+/// duration of the tick, the points written and rejected and the limit and denied retries of the
+/// retry budget and the ticks skipped since the start. With the `chaos` feature the injected
+/// failures are only counted in `failed_synthetic`.
+pub fn data_point(report: &TickReport) -> Result<DataPoint, DataPointError> {
+    // failures injected by the `CHAOS_CONFIG` are counted apart from the real ones
+    #[cfg(feature = "chaos")]
+    let (synthetic, errors): (Vec<_>, Vec<_>) =
+        (report.errors.iter()).partition(|(_, error)| error.is_synthetic());
+    #[cfg(not(feature = "chaos"))]
+    let errors = &report.errors;
+    let severities: Vec<_> = (errors.iter()).map(|(_, error)| error.severity()).collect();
+    let count = |severity: Severity| severities.iter().filter(|s| **s == severity).count() as i64;
+    let maintenance = match report.maintenance {
+        Some(_) => severities.len(),
+        None => 0,
+    };
+    let worst = match severities.iter().max() {
+        Some(severity) => severity.to_string(),
+        None => "none".to_string(),
+    };
+    let point = DataPoint::builder(MEASUREMENT)
+        .timestamp(report.started.timestamp())
+        .tag("collector_version", version::VERSION)
+        .tag("commit", version::COMMIT)
+        .tag("dirty", version::DIRTY.to_string())
+        .tag("build_time", version::BUILD_TIME)
+        .tag(
+            "maintenance",
+            report.maintenance.as_deref().unwrap_or("none").to_string(),
+        )
+        .tag("read_only", report.read_only.to_string())
+        .tag("severity", worst)
+        .field("tick_id", report.tick_id as i64)
+        .field("elapsed_ms", report.elapsed.as_millis() as i64)
+        .field("locations", report.locations.len() as i64)
+        .field("points_written", report.points_written() as i64)
+        .field("points_rejected", report.points_rejected as i64)
+        .field("failed", severities.len() as i64)
+        .field("failed_info", count(Severity::Info))
+        .field("failed_warning", count(Severity::Warning))
+        .field("failed_critical", count(Severity::Critical))
+        .field("failed_maintenance", maintenance as i64)
+        .field("retry_budget_limit", i64::from(report.retry_budget.limit))
+        .field("retries_denied", i64::from(report.retry_budget.denied))
+        .field("ticks_skipped_total", report.ticks_skipped_total as i64);
+    #[cfg(feature = "chaos")]
+    let point = point.field("failed_synthetic", synthetic.len() as i64);
+    point.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locations::{Model, Target, LOCATIONS};
+    use crate::points::HandleLocationError;
+    use crate::retry::BudgetUsage;
+    use crate::tick_report::{Outcome, TickRecorder};
+    use chrono::TimeZone;
+    use influxdb2::models::WriteDataPoint;
+    use std::time::Duration;
+
+    /// The line protocol of the `point`.
+    pub fn line(point: &DataPoint) -> String {
+        let mut line = Vec::new();
+        point.write_data_point_to(&mut line).unwrap();
+        String::from_utf8(line).unwrap()
+    }
+
+    #[test]
+    fn tags_worst_severity() {
+        let model = Model::default_model();
+        let targets: Vec<_> = (LOCATIONS.locations[..3].iter())
+            .map(|location| Target {
+                location,
+                model: &model,
+            })
+            .collect();
+        let started = chrono::Utc.with_ymd_and_hms(2024, 3, 7, 8, 10, 0).unwrap();
+        let report = |failed: usize| {
+            let errors = (targets[..failed].iter())
+                .map(|target| {
+                    let error = crate::timestamp::parse("").unwrap_err();
+                    (*target, HandleLocationError::ParseFromTimestamp(error))
+                })
+                .collect();
+            let recorder = TickRecorder::default();
+            for target in &targets[failed..] {
+                let issued = "2024-03-07T08:00:00Z".to_string();
+                recorder.outcome(&target.to_string(), Outcome::Existing { issued });
+            }
+            let elapsed = Duration::from_millis(96_500);
+            recorder.report(7, started, elapsed, &targets, errors)
+        };
+
+        let written = line(&data_point(&report(2)).unwrap());
+        let (tags, fields) = written
+            .split_once(",maintenance=none,read_only=false,severity=warning ")
+            .unwrap();
+        assert!(tags.starts_with("collector_stats,build_time="), "{written}");
+        assert!(
+            tags.contains(&format!(",commit={},", version::COMMIT)),
+            "{written}"
+        );
+        assert!(
+            tags.ends_with(&format!(",dirty={}", version::DIRTY)),
+            "{written}"
+        );
+        #[cfg(feature = "chaos")]
+        let synthetic = "failed_synthetic=0i,";
+        #[cfg(not(feature = "chaos"))]
+        let synthetic = "";
+        assert_eq!(
+            fields.trim_end(),
+            format!(
+                "elapsed_ms=96500i,failed=2i,failed_critical=0i,failed_info=0i,\
+                 failed_maintenance=0i,{synthetic}failed_warning=2i,locations=3i,\
+                 points_rejected=0i,points_written=0i,retries_denied=0i,retry_budget_limit=0i,\
+                 tick_id=7i,ticks_skipped_total=0i {}",
+                started.timestamp()
+            )
+        );
+
+        let mut maintenance = report(2);
+        maintenance.maintenance = Some("itwh".to_string());
+        let written = line(&data_point(&maintenance).unwrap());
+        assert!(written.contains(",maintenance=itwh,"), "{written}");
+        assert!(written.contains(",failed_maintenance=2i,"), "{written}");
+
+        let mut read_only = report(0);
+        read_only.read_only = true;
+        read_only.retry_budget = BudgetUsage {
+            limit: 10,
+            used: 10,
+            denied: 4,
+        };
+        read_only.locations[0].outcome = Outcome::Written {
+            issued: "2024-03-07T08:00:00Z".to_string(),
+            points: 35,
+        };
+        read_only.points_rejected = 1;
+        read_only.ticks_skipped_total = 3;
+        let written = line(&data_point(&read_only).unwrap());
+        assert!(
+            written.contains(",read_only=true,severity=none "),
+            "{written}"
+        );
+        assert!(
+            written.contains(
+                ",points_rejected=1i,points_written=35i,retries_denied=4i,\
+                 retry_budget_limit=10i,tick_id=7i,ticks_skipped_total=3i "
+            ),
+            "{written}"
+        );
+        assert!(
+            written.contains(" elapsed_ms=96500i,failed=0i,"),
+            "{written}"
+        );
+    }
+
+    #[cfg(feature = "chaos")]
+    #[test]
+    fn counts_synthetic_failures_apart() {
+        use crate::chaos::Fault;
+        use crate::locations::RequestLocationError;
+
+        let model = Model::default_model();
+        let targets: Vec<_> = (LOCATIONS.locations[..2].iter())
+            .map(|location| Target {
+                location,
+                model: &model,
+            })
+            .collect();
+        let errors = vec![
+            (
+                targets[0],
+                HandleLocationError::RequestForecast(RequestLocationError::Synthetic(
+                    Fault::Timeout,
+                )),
+            ),
+            (
+                targets[1],
+                HandleLocationError::ParseFromTimestamp(crate::timestamp::parse("").unwrap_err()),
+            ),
+        ];
+        let started = chrono::Utc.with_ymd_and_hms(2024, 3, 7, 8, 10, 0).unwrap();
+        let report = TickRecorder::default().report(7, started, Duration::ZERO, &targets, errors);
+
+        let written = line(&data_point(&report).unwrap());
+        assert!(written.contains(",severity=warning "), "{written}");
+        assert!(
+            written.contains(
+                ",failed=1i,failed_critical=0i,failed_info=0i,failed_maintenance=0i,\
+                 failed_synthetic=1i,"
+            ),
+            "{written}"
+        );
+    }
+}
@@ -0,0 +1,278 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use std::sync::atomic::{AtomicU64, Ordering};
+use thiserror::Error;
+
+/// Format the swat api usually states its times in, every time is normalized to it.
+pub const FORMAT: &str = "%Y-%m-%d %H:%M";
+
+/// Formats tried in order, the usual one first.
+const FORMATS: [TimestampFormat; 5] = [
+    TimestampFormat::Padded,
+    TimestampFormat::Unpadded,
+    TimestampFormat::Seconds,
+    TimestampFormat::Iso8601,
+    TimestampFormat::Iso8601Offset,
+];
+
+/// Times normalized per format since the start, see [`matched`].
+static MATCHED: [AtomicU64; FORMATS.len()] = [const { AtomicU64::new(0) }; FORMATS.len()];
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("{raw:?} matches none of the known timestamp formats")]
+pub struct TimestampError {
+    pub raw: String,
+}
+
+/// Formats of the times sent by the swat api.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampFormat {
+    /// `2024-03-07 08:05`
+    Padded,
+
+    /// `2024-3-7 8:05`
+    Unpadded,
+
+    /// `2024-03-07 08:05:00`
+    Seconds,
+
+    /// `2024-03-07T08:05:00`, optionally with fractional seconds or without the seconds
+    Iso8601,
+
+    /// `2024-03-07T08:05:00+01:00`, converted to UTC like the times without an offset are
+    /// taken as
+    Iso8601Offset,
+}
+
+impl TimestampFormat {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TimestampFormat::Padded => "padded",
+            TimestampFormat::Unpadded => "unpadded",
+            TimestampFormat::Seconds => "seconds",
+            TimestampFormat::Iso8601 => "iso8601",
+            TimestampFormat::Iso8601Offset => "iso8601 with offset",
+        }
+    }
+
+    fn parse(self, raw: &str) -> Option<NaiveDateTime> {
+        let parse = |format| NaiveDateTime::parse_from_str(raw, format).ok();
+        match self {
+            // chrono accepts the fields without padding as well
+            TimestampFormat::Padded => {
+                parse_padded(raw).or_else(|| parse(FORMAT).filter(|_| raw.len() == 16))
+            }
+            TimestampFormat::Unpadded => parse(FORMAT),
+            TimestampFormat::Seconds => parse("%Y-%m-%d %H:%M:%S"),
+            TimestampFormat::Iso8601 => {
+                parse("%Y-%m-%dT%H:%M:%S%.f").or_else(|| parse("%Y-%m-%dT%H:%M"))
+            }
+            TimestampFormat::Iso8601Offset => DateTime::parse_from_rfc3339(raw)
+                .ok()
+                .map(|time| time.naive_utc()),
+        }
+    }
+}
+
+/// Parses `raw` in the usual [`FORMAT`] without chrono's format strings, which took most of
+/// the time of normalizing the horizons of a forecast.
+fn parse_padded(raw: &str) -> Option<NaiveDateTime> {
+    let bytes: &[u8; 16] = raw.as_bytes().try_into().ok()?;
+    if (bytes[4], bytes[7], bytes[10], bytes[13]) != (b'-', b'-', b' ', b':') {
+        return None;
+    }
+    let number = |digits: &[u8]| {
+        digits.iter().try_fold(0, |number, digit| {
+            digit
+                .is_ascii_digit()
+                .then(|| number * 10 + u32::from(digit - b'0'))
+        })
+    };
+    let date = NaiveDate::from_ymd_opt(
+        number(&bytes[0..4])? as i32,
+        number(&bytes[5..7])?,
+        number(&bytes[8..10])?,
+    )?;
+    date.and_hms_opt(number(&bytes[11..13])?, number(&bytes[14..16])?, 0)
+}
+
+/// Parses the time `raw` in the first of the known formats it matches.
+pub fn parse(raw: &str) -> Result<(NaiveDateTime, TimestampFormat), TimestampError> {
+    let trimmed = raw.trim();
+    FORMATS
+        .into_iter()
+        .find_map(|format| Some((format.parse(trimmed)?, format)))
+        .ok_or_else(|| TimestampError {
+            raw: raw.to_string(),
+        })
+}
+
+/// Parses the time `raw` and rewrites it in the [`FORMAT`], counting the format it matched.
+pub fn normalize(raw: &mut String) -> Result<NaiveDateTime, TimestampError> {
+    let (time, format) = parse(raw)?;
+    MATCHED[format as usize].fetch_add(1, Ordering::Relaxed);
+    // surrounding whitespace is trimmed as well
+    if format != TimestampFormat::Padded || raw.len() != 16 {
+        *raw = time.format(FORMAT).to_string();
+    }
+    Ok(time)
+}
+
+/// Times normalized per format since the start.
+#[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+pub fn matched() -> Vec<(TimestampFormat, u64)> {
+    FORMATS
+        .into_iter()
+        .map(|format| (format, MATCHED[format as usize].load(Ordering::Relaxed)))
+        .collect()
+}
+
+/// The formats other than the usual one matched so far, empty while there were none.
+#[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+pub fn status_text() -> String {
+    let unusual: Vec<_> = matched()
+        .into_iter()
+        .filter(|(format, count)| *format != TimestampFormat::Padded && *count > 0)
+        .map(|(format, count)| format!("{} {count}", format.as_str()))
+        .collect();
+    match unusual.is_empty() {
+        true => String::new(),
+        false => format!("unusual timestamp formats: {}\n", unusual.join(", ")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_formats() {
+        let cases = [
+            (
+                "2024-03-07 08:05",
+                TimestampFormat::Padded,
+                "2024-03-07 08:05",
+            ),
+            (
+                "2024-3-7 8:05",
+                TimestampFormat::Unpadded,
+                "2024-03-07 08:05",
+            ),
+            (
+                "2024-03-7 08:05",
+                TimestampFormat::Unpadded,
+                "2024-03-07 08:05",
+            ),
+            (
+                " 2024-03-07 08:05\n",
+                TimestampFormat::Padded,
+                "2024-03-07 08:05",
+            ),
+            (
+                "2024-03-07 08:05:00",
+                TimestampFormat::Seconds,
+                "2024-03-07 08:05",
+            ),
+            (
+                "2024-03-07T08:05:00",
+                TimestampFormat::Iso8601,
+                "2024-03-07 08:05",
+            ),
+            (
+                "2024-03-07T08:05:00.250",
+                TimestampFormat::Iso8601,
+                "2024-03-07 08:05",
+            ),
+            (
+                "2024-03-07T08:05",
+                TimestampFormat::Iso8601,
+                "2024-03-07 08:05",
+            ),
+            (
+                "2024-03-07T08:05:00+01:00",
+                TimestampFormat::Iso8601Offset,
+                "2024-03-07 07:05",
+            ),
+            (
+                "2024-03-07T08:05:00Z",
+                TimestampFormat::Iso8601Offset,
+                "2024-03-07 08:05",
+            ),
+        ];
+        for (raw, format, normalized) in cases {
+            let (time, matched) = parse(raw).unwrap_or_else(|err| panic!("{err}"));
+            assert_eq!(matched, format, "{raw:?}");
+            assert_eq!(time.format(FORMAT).to_string(), normalized, "{raw:?}");
+
+            let mut raw = raw.to_string();
+            normalize(&mut raw).unwrap();
+            assert_eq!(raw, normalized);
+        }
+    }
+
+    #[test]
+    fn padded_like_chrono() {
+        let cases = [
+            "2024-03-07 08:05",
+            "1999-12-31 23:59",
+            "2024-02-29 00:00",
+            "2023-02-29 00:00",
+            "2024-13-07 08:05",
+            "2024-03-07 24:05",
+            "2024-03-07 08:60",
+            "2024-03-07T08:05",
+            "2024-03-07 08-05",
+            "2024-03-0x 08:05",
+            "2024-3-7 8:05",
+        ];
+        for raw in cases {
+            let chrono = NaiveDateTime::parse_from_str(raw, FORMAT)
+                .ok()
+                .filter(|_| raw.len() == 16);
+            assert_eq!(parse_padded(raw), chrono, "{raw:?}");
+        }
+    }
+
+    #[test]
+    fn malformed() {
+        let cases = [
+            "",
+            "garbage",
+            "2024-03-07",
+            "08:05",
+            "07.03.2024 08:05",
+            "2024-13-07 08:05",
+            "2024-02-30 08:05",
+            "2024-03-07 24:05",
+            "2024-03-07 08:05 Uhr",
+            "2024-03-07T08:05:00+25:00",
+        ];
+        for raw in cases {
+            let err = parse(raw).unwrap_err();
+            assert_eq!(err.raw, raw);
+            assert_eq!(
+                err.to_string(),
+                format!("{raw:?} matches none of the known timestamp formats")
+            );
+
+            // nothing is rewritten
+            let mut unchanged = raw.to_string();
+            assert!(normalize(&mut unchanged).is_err());
+            assert_eq!(unchanged, raw);
+        }
+    }
+
+    #[test]
+    fn counts_unusual_formats() {
+        let count = |format| {
+            matched()
+                .into_iter()
+                .find(|(matched, _)| *matched == format)
+                .unwrap()
+                .1
+        };
+        let before = count(TimestampFormat::Seconds);
+        normalize(&mut "2024-03-07 08:05:00".to_string()).unwrap();
+        assert!(count(TimestampFormat::Seconds) > before);
+        assert!(status_text().contains("seconds "), "{}", status_text());
+    }
+}
@@ -0,0 +1,80 @@
+use std::future::Future;
+use std::io;
+use std::sync::Arc;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::Notify;
+use tokio::time::Interval;
+
+/// What started a collection pass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pass {
+    Scheduled,
+
+    /// Triggered via `SIGUSR1` to not wait for the next tick after fixing a problem.
+    Manual,
+}
+
+/// Waits for the next tick of `interval` or for a pass requested via `trigger`.
+///
+/// The trigger stores a single permit, so any number of requests made during a running pass
+/// lead to one follow-up pass.
+pub async fn next(interval: &mut Interval, trigger: &Notify) -> Pass {
+    tokio::select! {
+        _ = interval.tick() => Pass::Scheduled,
+        _ = trigger.notified() => Pass::Manual,
+    }
+}
+
+/// Requests a pass via `trigger` whenever the collector receives `SIGUSR1`.
+pub fn listen(trigger: Arc<Notify>) -> io::Result<()> {
+    let mut signals = signal(SignalKind::user_defined1())?;
+    tokio::spawn(async move {
+        while signals.recv().await.is_some() {
+            trigger.notify_one();
+        }
+    });
+    Ok(())
+}
+
+/// Resolves once the collector is asked to stop via `SIGTERM` or `SIGINT`.
+///
+/// The signals are only taken care of while waiting for the next pass, so a running pass is
+/// finished before stopping.
+pub fn shutdown() -> io::Result<impl Future<Output = ()>> {
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    Ok(async move {
+        tokio::select! {
+            _ = terminate.recv() => (),
+            _ = interrupt.recv() => (),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::time::{self, Instant};
+
+    #[tokio::test(start_paused = true)]
+    async fn coalesces_triggers() {
+        let period = Duration::from_secs(120);
+        let mut interval = time::interval_at(Instant::now() + period, period);
+        let trigger = Notify::new();
+
+        // requests during a running pass result in a single follow-up
+        trigger.notify_one();
+        trigger.notify_one();
+        trigger.notify_one();
+        assert_eq!(next(&mut interval, &trigger).await, Pass::Manual);
+
+        let start = Instant::now();
+        assert_eq!(next(&mut interval, &trigger).await, Pass::Scheduled);
+        assert_eq!(start.elapsed(), period);
+
+        trigger.notify_one();
+        assert_eq!(next(&mut interval, &trigger).await, Pass::Manual);
+        assert_eq!(start.elapsed(), period);
+    }
+}
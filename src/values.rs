@@ -0,0 +1,139 @@
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Number;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// A value of a forecast as sent by the swat api.
+///
+/// The api sends integers and, since its next version, fractional millimeters. Infinite values
+/// or NaN spelled as strings are kept apart, so the forecast drops just their entries instead of
+/// failing as a whole. Any other string is no value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reading {
+    Finite(f64),
+    NonFinite,
+}
+
+impl<'de> Deserialize<'de> for Reading {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ReadingVisitor;
+
+        impl Visitor<'_> for ReadingVisitor {
+            type Value = Reading;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a number")
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<Reading, E> {
+                Ok(Reading::Finite(value as f64))
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<Reading, E> {
+                Ok(Reading::Finite(value as f64))
+            }
+
+            fn visit_f64<E: de::Error>(self, value: f64) -> Result<Reading, E> {
+                match value.is_finite() {
+                    true => Ok(Reading::Finite(value)),
+                    false => Ok(Reading::NonFinite),
+                }
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Reading, E> {
+                match value.trim().parse::<f64>() {
+                    Ok(parsed) if !parsed.is_finite() => Ok(Reading::NonFinite),
+                    _ => Err(E::invalid_type(de::Unexpected::Str(value), &self)),
+                }
+            }
+        }
+
+        deserializer.deserialize_any(ReadingVisitor)
+    }
+}
+
+/// The `values` rounded to integers, for the JSON string fields dashboards parse as integers.
+pub fn legacy(values: &BTreeMap<String, f64>) -> BTreeMap<&str, i64> {
+    values
+        .iter()
+        .map(|(time, value)| (time.as_str(), value.round() as i64))
+        .collect()
+}
+
+/// The `values` as JSON numbers, integers without a fraction like the swat api sends them.
+pub fn exact(values: &BTreeMap<String, f64>) -> BTreeMap<&str, Number> {
+    values
+        .iter()
+        .map(|(time, value)| (time.as_str(), number(*value)))
+        .collect()
+}
+
+fn number(value: f64) -> Number {
+    match value.fract() == 0.0 && value.abs() < i64::MAX as f64 {
+        true => Number::from(value as i64),
+        false => Number::from_f64(value).expect("values are finite"),
+    }
+}
+
+/// Serializes the `value` like [exact], keeping the JSON of the streaming outputs as it was
+/// for integers.
+pub fn serialize_exact<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+    number(*value).serialize(serializer)
+}
+
+/// Serializes the `values` like [exact].
+#[cfg_attr(not(any(feature = "kafka", feature = "nats")), allow(dead_code))]
+pub fn serialize_exact_map<S: Serializer>(
+    values: &BTreeMap<String, f64>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    exact(values).serialize(serializer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(json: &str) -> Result<Reading, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    #[test]
+    fn reads_numbers() {
+        assert_eq!(read("412").unwrap(), Reading::Finite(412.0));
+        assert_eq!(read("-3").unwrap(), Reading::Finite(-3.0));
+        assert_eq!(read("412.75").unwrap(), Reading::Finite(412.75));
+        assert_eq!(read("4.1e2").unwrap(), Reading::Finite(410.0));
+
+        for non_finite in ["\"NaN\"", "\"Infinity\"", "\"-inf\""] {
+            assert_eq!(
+                read(non_finite).unwrap(),
+                Reading::NonFinite,
+                "{non_finite}"
+            );
+        }
+        // numbers in strings are no values
+        assert_eq!(
+            read("\"412.5\"").unwrap_err().to_string(),
+            "invalid type: string \"412.5\", expected a number at line 1 column 7"
+        );
+    }
+
+    #[test]
+    fn legacy_and_exact() {
+        let values = BTreeMap::from([
+            ("2024-05-01 12:00".to_string(), 412.0),
+            ("2024-05-01 13:00".to_string(), 412.5),
+            ("2024-05-01 14:00".to_string(), 398.49),
+        ]);
+        assert_eq!(
+            serde_json::to_string(&legacy(&values)).unwrap(),
+            r#"{"2024-05-01 12:00":412,"2024-05-01 13:00":413,"2024-05-01 14:00":398}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&exact(&values)).unwrap(),
+            r#"{"2024-05-01 12:00":412,"2024-05-01 13:00":412.5,"2024-05-01 14:00":398.49}"#
+        );
+    }
+}
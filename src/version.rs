@@ -0,0 +1,55 @@
+/// Package version and the commit built from, e.g. `0.1.0 (1a2b3c4d)`.
+///
+/// The commit is suffixed with `-dirty` for uncommitted changes and `unknown` outside of git.
+pub const VERSION: &str = concat!(
+    env!("CARGO_PKG_VERSION"),
+    " (",
+    env!("GIT_COMMIT"),
+    env!("GIT_DIRTY"),
+    ")"
+);
+
+/// [`VERSION`] along with the build time, printed by `--version`.
+pub const LONG_VERSION: &str = concat!(
+    env!("CARGO_PKG_VERSION"),
+    " (",
+    env!("GIT_COMMIT"),
+    env!("GIT_DIRTY"),
+    ", built ",
+    env!("BUILD_TIME"),
+    ")"
+);
+
+/// The commit built from, `unknown` outside of git.
+pub const COMMIT: &str = env!("GIT_COMMIT");
+
+/// Whether the build had uncommitted changes.
+pub const DIRTY: bool = !env!("GIT_DIRTY").is_empty();
+
+/// When the binary was built, e.g. `2024-03-07 08:10 UTC`.
+pub const BUILD_TIME: &str = env!("BUILD_TIME");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_format() {
+        let (version, rest) = LONG_VERSION.split_once(" (").unwrap();
+        assert_eq!(version, env!("CARGO_PKG_VERSION"));
+        let (commit, build_time) = rest
+            .strip_suffix(')')
+            .unwrap()
+            .split_once(", built ")
+            .unwrap();
+        assert_eq!(commit, concat!(env!("GIT_COMMIT"), env!("GIT_DIRTY")));
+        let hash = commit.strip_suffix("-dirty").unwrap_or(commit);
+        assert!(
+            hash == "unknown" || (hash.len() >= 8 && hash.chars().all(|c| c.is_ascii_hexdigit())),
+            "{hash:?}"
+        );
+        let build_time = build_time.strip_suffix(" UTC").unwrap();
+        chrono::NaiveDateTime::parse_from_str(build_time, "%Y-%m-%d %H:%M").unwrap();
+        assert!(LONG_VERSION.starts_with(VERSION.strip_suffix(')').unwrap()));
+    }
+}
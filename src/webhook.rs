@@ -1,20 +1,266 @@
-use crate::locations::Location;
-use crate::HandleLocationError;
+use crate::egress::{self, Class, Transfer};
+use crate::error_kind::ErrorKind;
+use crate::groups::{self, GroupSummary, LocationGroups};
+use crate::incident::IncidentSummary;
+use crate::locations::Target;
+use crate::points::HandleLocationError;
+use crate::redact;
+use crate::severity::{ParseSeverityError, Severity};
 
+mod audit;
+mod branding;
+mod delivery;
+mod hints;
+mod mute;
+mod queue;
+mod quiet;
+pub use audit::{Audited, DEFAULT_REALERT_SUPPRESS_MINUTES};
+pub use branding::Branding;
+pub use delivery::Delivery;
+pub use hints::Hints;
+pub use mute::Mute;
+#[cfg(feature = "health-check")]
+pub use queue::DeliveryFailures;
+pub use queue::{Notification, NotificationQueue, Notifier};
+pub use quiet::{HeldAlert, QuietHours};
+
+use futures::future;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::future::Future;
+use std::num::ParseIntError;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use thiserror::Error;
 use twilight_http::client::Client as DiscordClient;
-use twilight_http::error::Error as HttpError;
+use twilight_http::error::{Error as HttpError, ErrorType as HttpErrorType};
 use twilight_model::channel::message::Embed;
 use twilight_model::id::marker::WebhookMarker;
 use twilight_model::id::Id;
-use twilight_util::builder::embed::{EmbedBuilder, EmbedFieldBuilder};
-use twilight_validate::embed::FIELD_COUNT;
-use twilight_validate::message::MessageValidationError;
+use twilight_util::builder::embed::{EmbedBuilder, EmbedFieldBuilder, EmbedFooterBuilder};
+use twilight_validate::embed::{
+    DESCRIPTION_LENGTH, EMBED_TOTAL_LENGTH, FIELD_COUNT, FIELD_NAME_LENGTH, FIELD_VALUE_LENGTH,
+    FOOTER_TEXT_LENGTH, TITLE_LENGTH,
+};
+use twilight_validate::message::{MessageValidationError, EMBED_COUNT_LIMIT};
+
+const ALERT_DESCRIPTION: &str =
+    "Some errors occurred.\nAs soon as all requests are successful again you will be notified.";
+const DIGEST_DESCRIPTION: &str =
+    "These errors occurred during quiet hours.\nErrors that are not resolved yet will be resolved as usual.";
+const RESOLVED_COLOR: u32 = 0x57F287;
+const RESOLVED_HISTORY_DESCRIPTION: &str =
+    "Some errors occurred while notifications could not be delivered.\nAll of them are resolved by now.";
 
 pub struct Webhook {
     discord_client: DiscordClient,
+    destinations: Vec<Destination>,
+    branding: Branding,
+}
+
+/// A single Discord webhook the notifications are sent to.
+///
+/// Every destination keeps track whether it was alerted about the current errors, so a
+/// destination that missed the alert still gets it while the others are not notified twice.
+/// Errors below the `min_severity` of a destination are not routed to it.
+#[derive(Debug)]
+pub struct Destination {
     id: Id<WebhookMarker>,
     token: String,
+    min_severity: Severity,
+    alerted: AtomicBool,
+}
+
+#[derive(Debug, Error)]
+pub enum ParseDestinationError {
+    #[error("expected destination in the form of `id:token[:severity]`, got {0:?}")]
+    Format(String),
+
+    #[error("invalid webhook id, {0}")]
+    Id(#[from] ParseIntError),
+
+    #[error("webhook id must not be zero")]
+    ZeroId,
+
+    #[error("invalid minimum severity, {0}")]
+    Severity(#[from] ParseSeverityError),
+}
+
+/// A single field of an alert embed, one per erroneous location or kind of error.
+#[derive(Debug, Clone)]
+pub struct AlertField {
+    name: String,
+    value: String,
+    severity: Severity,
+
+    /// The tick the error occurred in, shown in the footer of the alert, `None` for errors
+    /// found outside of the ticks.
+    tick_id: Option<u64>,
+
+    /// The id of the incident alerted, shown in the footer of the alert.
+    incident: Option<String>,
+}
+
+impl AlertField {
+    pub fn new(name: String, value: String, severity: Severity, tick_id: u64) -> AlertField {
+        AlertField {
+            name,
+            value,
+            severity,
+            tick_id: Some(tick_id),
+            incident: None,
+        }
+    }
+
+    /// A field about an error found outside of the ticks, like by a background check, which
+    /// leaves the tick out of the footer.
+    pub fn outside_tick(name: String, value: String, severity: Severity) -> AlertField {
+        AlertField {
+            name,
+            value,
+            severity,
+            tick_id: None,
+            incident: None,
+        }
+    }
+
+    /// The field as part of the incident with the `id`.
+    pub fn with_incident(self, id: Option<String>) -> AlertField {
+        AlertField {
+            incident: id,
+            ..self
+        }
+    }
+
+    pub fn from_errors(errors: &[(Target, HandleLocationError)], tick_id: u64) -> Vec<AlertField> {
+        errors
+            .iter()
+            .map(|(target, error)| AlertField {
+                name: target.to_string(),
+                value: error.to_string(),
+                severity: error.severity(),
+                tick_id: Some(tick_id),
+                incident: None,
+            })
+            .collect()
+    }
+
+    /// One field per kind of error with a remediation hint from `hints`, listing the affected
+    /// locations and the first of their errors as an example.
+    pub fn grouped(
+        errors: &[(Target, HandleLocationError)],
+        tick_id: u64,
+        hints: &Hints,
+    ) -> Vec<AlertField> {
+        let mut groups: BTreeMap<ErrorKind, Vec<&(Target, HandleLocationError)>> = BTreeMap::new();
+        for error in errors {
+            groups.entry(error.1.kind()).or_default().push(error);
+        }
+
+        groups
+            .into_iter()
+            .map(|(kind, errors)| {
+                let count = match errors.len() {
+                    1 => "1 location".to_string(),
+                    n => format!("{n} locations"),
+                };
+                let affected: Vec<_> = errors
+                    .iter()
+                    .map(|(target, _)| target.to_string())
+                    .collect();
+                AlertField {
+                    name: format!("{} ({count})", Hints::title(kind)),
+                    value: format!(
+                        "{}\nAffected: {}\nExample: {}",
+                        hints.hint(kind),
+                        affected.join(", "),
+                        errors[0].1
+                    ),
+                    severity: errors
+                        .iter()
+                        .map(|(_, error)| error.severity())
+                        .max()
+                        .unwrap_or(Severity::Critical),
+                    tick_id: Some(tick_id),
+                    incident: None,
+                }
+            })
+            .collect()
+    }
+
+    /// One field per location group with failing locations, like `Oldenburg: 3/7 failing`,
+    /// listing the errors of its locations.
+    pub fn by_location_group(
+        errors: &[(Target, HandleLocationError)],
+        tick_id: u64,
+        groups: &LocationGroups,
+    ) -> Vec<AlertField> {
+        let mut errors_of: BTreeMap<&str, Vec<&(Target, HandleLocationError)>> = BTreeMap::new();
+        for error in errors {
+            errors_of
+                .entry(groups::of(error.0.location))
+                .or_default()
+                .push(error);
+        }
+        let failing: Vec<_> = errors
+            .iter()
+            .map(|(target, _)| target.to_string())
+            .collect();
+        groups
+            .summary(failing.iter().map(String::as_str))
+            .into_iter()
+            .filter_map(|summary| {
+                let errors = errors_of.remove(summary.group)?;
+                let GroupSummary {
+                    group,
+                    failing,
+                    total,
+                } = summary;
+                let details: Vec<_> = errors
+                    .iter()
+                    .map(|(target, error)| format!("{target}: {error}"))
+                    .collect();
+                Some(AlertField {
+                    name: format!("{group}: {failing}/{total} failing"),
+                    value: details.join("\n"),
+                    severity: errors
+                        .iter()
+                        .map(|(_, error)| error.severity())
+                        .max()
+                        .unwrap_or(Severity::Critical),
+                    tick_id: Some(tick_id),
+                    incident: None,
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("webhook execution failed for {}", DisplayFailed(.0))]
+pub struct WebhookDeliveryError(pub Vec<(Id<WebhookMarker>, WebhookExecuteError)>);
+
+impl WebhookDeliveryError {
+    /// Whether the delivery failed permanently for every destination it failed for, so
+    /// retrying it cannot succeed.
+    pub fn is_permanent(&self) -> bool {
+        self.0.iter().all(|(_, error)| error.is_permanent())
+    }
+}
+
+struct DisplayFailed<'e>(&'e [(Id<WebhookMarker>, WebhookExecuteError)]);
+
+impl fmt::Display for DisplayFailed<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (id, error)) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{id} ({error})")?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Error)]
@@ -23,48 +269,940 @@ pub enum WebhookExecuteError {
     MessageValidation(#[from] MessageValidationError),
 
     #[error("{0}")]
-    Http(#[from] HttpError),
+    Http(HttpError),
+
+    #[error("discord rejected the webhook, {message}: {}", DisplayRejected(.errors))]
+    Rejected {
+        message: String,
+        errors: Vec<RejectedField>,
+    },
 }
 
-impl Webhook {
-    pub fn new(id: Id<WebhookMarker>, token: String) -> Webhook {
+impl WebhookExecuteError {
+    /// Whether sending the message again fails the same way, as it is invalid or Discord
+    /// refuses it with a client error other than rate limiting.
+    pub fn is_permanent(&self) -> bool {
+        match self {
+            WebhookExecuteError::MessageValidation(_) | WebhookExecuteError::Rejected { .. } => {
+                true
+            }
+            WebhookExecuteError::Http(err) => match err.kind() {
+                HttpErrorType::Response { status, .. } => {
+                    status.is_client_error() && status.get() != 429
+                }
+                _ => false,
+            },
+        }
+    }
+}
+
+/// A single field of a webhook payload Discord refused, e.g. `embeds.0.fields.3.value`.
+#[derive(Debug, PartialEq)]
+pub struct RejectedField {
+    pub path: String,
+    pub message: String,
+}
+
+struct DisplayRejected<'e>(&'e [RejectedField]);
+
+impl fmt::Display for DisplayRejected<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, RejectedField { path, message }) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{path}: {message}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The JSON body Discord responds with on a `400 Bad Request`.
+#[derive(Debug, Deserialize)]
+struct DiscordErrorBody {
+    message: String,
+    #[serde(default)]
+    errors: serde_json::Value,
+}
+
+impl From<HttpError> for WebhookExecuteError {
+    fn from(err: HttpError) -> Self {
+        let HttpErrorType::Response { body, status, .. } = err.kind() else {
+            return WebhookExecuteError::Http(err);
+        };
+
+        if status.get() != 400 {
+            return WebhookExecuteError::Http(err);
+        }
+
+        match serde_json::from_slice::<DiscordErrorBody>(body) {
+            Ok(DiscordErrorBody { message, errors }) => {
+                let mut rejected = Vec::new();
+                collect_rejected_fields(&mut Vec::new(), &errors, &mut rejected);
+                WebhookExecuteError::Rejected {
+                    message,
+                    errors: rejected,
+                }
+            }
+            Err(_) => WebhookExecuteError::Http(err),
+        }
+    }
+}
+
+/// Walks Discord's nested error object and collects every `_errors` entry with its path.
+fn collect_rejected_fields<'v>(
+    path: &mut Vec<&'v str>,
+    errors: &'v serde_json::Value,
+    rejected: &mut Vec<RejectedField>,
+) {
+    let Some(errors) = errors.as_object() else {
+        return;
+    };
+
+    for (key, value) in errors {
+        if key == "_errors" {
+            for error in value.as_array().into_iter().flatten() {
+                rejected.push(RejectedField {
+                    path: path.join("."),
+                    message: error["message"].as_str().unwrap_or_default().to_string(),
+                });
+            }
+            continue;
+        }
+
+        path.push(key);
+        collect_rejected_fields(path, value, rejected);
+        path.pop();
+    }
+}
+
+impl Destination {
+    pub fn new(id: Id<WebhookMarker>, token: String, min_severity: Severity) -> Destination {
         Self {
-            discord_client: DiscordClient::new(String::new()),
             id,
             token,
+            min_severity,
+            alerted: AtomicBool::new(false),
         }
     }
 
-    pub async fn alert(
+    pub fn id(&self) -> Id<WebhookMarker> {
+        self.id
+    }
+
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// The fields that reach the minimum severity of this destination.
+    fn routed<'f>(&self, fields: &'f [AlertField]) -> Vec<&'f AlertField> {
+        fields
+            .iter()
+            .filter(|field| field.severity >= self.min_severity)
+            .collect()
+    }
+
+    fn routes(&self, fields: &[AlertField]) -> bool {
+        fields
+            .iter()
+            .any(|field| field.severity >= self.min_severity)
+    }
+
+    /// Parses a list of destinations separated by `;`, as used in `DISCORD_WEBHOOKS`.
+    pub fn parse_list(list: &str) -> Result<Vec<Destination>, ParseDestinationError> {
+        list.split(';')
+            .map(str::trim)
+            .filter(|destination| !destination.is_empty())
+            .map(Destination::from_str)
+            .collect()
+    }
+}
+
+impl FromStr for Destination {
+    type Err = ParseDestinationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(3, ':');
+        let (Some(id), Some(token)) = (parts.next(), parts.next()) else {
+            return Err(ParseDestinationError::Format(s.to_string()));
+        };
+        let id = Id::new_checked(id.parse()?).ok_or(ParseDestinationError::ZeroId)?;
+        let min_severity = match parts.next() {
+            Some(severity) => severity.parse()?,
+            None => Severity::Info,
+        };
+        Ok(Destination::new(id, token.to_string(), min_severity))
+    }
+}
+
+impl Webhook {
+    pub fn new(destinations: Vec<Destination>) -> Webhook {
+        Self::with_client(DiscordClient::new(String::new()), destinations)
+    }
+
+    pub fn with_client(discord_client: DiscordClient, destinations: Vec<Destination>) -> Webhook {
+        Self {
+            discord_client,
+            destinations,
+            branding: Branding::default(),
+        }
+    }
+
+    pub fn with_branding(self, branding: Branding) -> Webhook {
+        Self { branding, ..self }
+    }
+
+    /// Alerts every destination that was not alerted yet about the errors in `fields` which
+    /// reach its minimum severity.
+    pub async fn alert(&self, fields: &[AlertField]) -> Result<(), WebhookDeliveryError> {
+        let pending = self.destinations.iter().filter(|destination| {
+            !destination.alerted.load(Ordering::Relaxed) && destination.routes(fields)
+        });
+        self.execute_all(pending, Some(true), |destination| async move {
+            let fields = destination.routed(fields);
+            let tick_id = fields.iter().filter_map(|field| field.tick_id).max();
+            let incident = fields.iter().find_map(|field| field.incident.as_deref());
+            let footer = match (tick_id, incident) {
+                (Some(tick_id), Some(incident)) => {
+                    Some(format!("tick #{tick_id} · incident {incident}"))
+                }
+                (Some(tick_id), None) => Some(format!("tick #{tick_id}")),
+                (None, Some(incident)) => Some(format!("incident {incident}")),
+                (None, None) => None,
+            };
+            let reserved = self.branding.added_len() + footer.as_ref().map_or(0, String::len);
+            let footer = footer.map(|footer| EmbedFooterBuilder::new(footer).build());
+            for mut embeds in paginate(&fields, ALERT_DESCRIPTION, reserved) {
+                for embed in embeds.iter_mut() {
+                    embed.footer.clone_from(&footer);
+                }
+                self.execute_embeds_webhook(destination, &embeds).await?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Notifies every previously alerted destination that the errors are resolved.
+    ///
+    /// Destinations that never received the alert for the errors in `history` are sent
+    /// the history instead, so they still learn about the incident.
+    pub async fn resolved(
         &self,
-        errors: &[(&Location, HandleLocationError)],
-    ) -> Result<(), WebhookExecuteError> {
+        history: Option<&[AlertField]>,
+        incident: Option<&IncidentSummary>,
+    ) -> Result<(), WebhookDeliveryError> {
+        let mut description =
+            "All requests have been successful. Collector working as expected again.".to_string();
+        if let Some(incident) = incident {
+            description = format!("{description}\n{incident}");
+        }
         let mut embed = EmbedBuilder::new()
-            .color(0x9E2C2C)
-            .description("Some errors occurred.\nAs soon as all requests are successful again you will be notified.");
-
-        for field in errors.iter().take(FIELD_COUNT).map(|(location, error)| {
-            EmbedFieldBuilder::new(location.name, error.to_string()).build()
-        }) {
-            embed = embed.field(field);
+            .color(RESOLVED_COLOR)
+            .description(description)
+            .build();
+        if let Some(incident) = incident {
+            embed.footer =
+                Some(EmbedFooterBuilder::new(format!("incident {}", incident.id)).build());
         }
 
-        self.execute_embed_webhook(embed.build()).await
+        let pending = self.destinations.iter().filter(|destination| {
+            destination.alerted.load(Ordering::Relaxed)
+                || history.is_some_and(|history| destination.routes(history))
+        });
+        self.execute_all(pending, Some(false), |destination| {
+            let embed = &embed;
+            async move {
+                if destination.alerted.load(Ordering::Relaxed) {
+                    return self
+                        .execute_embeds_webhook(destination, std::slice::from_ref(embed))
+                        .await;
+                }
+
+                let fields = destination.routed(history.unwrap_or_default());
+                let reserved = self.branding.added_len();
+                for embeds in paginate(&fields, RESOLVED_HISTORY_DESCRIPTION, reserved) {
+                    self.execute_embeds_webhook(destination, &embeds).await?;
+                }
+                Ok(())
+            }
+        })
+        .await
     }
 
-    pub async fn resolved(&self) -> Result<(), WebhookExecuteError> {
+    /// Sends the alerts held during quiet hours to every destination they are routed to.
+    ///
+    /// Destinations receiving errors that are not resolved yet are moved into the alerted
+    /// state, so they are notified once these are resolved.
+    pub async fn digest(&self, held: &[HeldAlert]) -> Result<(), WebhookDeliveryError> {
+        let fields: Vec<_> = held.iter().flat_map(HeldAlert::digest_fields).collect();
+        let unresolved: Vec<_> = held
+            .iter()
+            .filter(|alert| !alert.resolved)
+            .flat_map(|alert| alert.fields.iter().cloned())
+            .collect();
+
+        let pending = self
+            .destinations
+            .iter()
+            .filter(|destination| destination.routes(&fields));
+        self.execute_all(pending, None, |destination| {
+            let (fields, unresolved) = (&fields, &unresolved);
+            async move {
+                let (fields, reserved) = (destination.routed(fields), self.branding.added_len());
+                for embeds in paginate(&fields, DIGEST_DESCRIPTION, reserved) {
+                    self.execute_embeds_webhook(destination, &embeds).await?;
+                }
+                if destination.routes(unresolved) {
+                    destination.alerted.store(true, Ordering::Relaxed);
+                }
+                Ok(())
+            }
+        })
+        .await
+    }
+
+    /// Sends a one-off warning to every destination accepting warnings, independent of the
+    /// alert state.
+    pub async fn warning(&self, message: &str) -> Result<(), WebhookDeliveryError> {
         let embed = EmbedBuilder::new()
-            .color(0x57F287)
-            .description("All requests have been successful. Collector working as expected again.");
-        self.execute_embed_webhook(embed.build()).await
+            .color(Severity::Warning.color())
+            .description(message)
+            .build();
+
+        let pending = self
+            .destinations
+            .iter()
+            .filter(|destination| destination.min_severity <= Severity::Warning);
+        self.execute_all(pending, None, |destination| {
+            self.execute_embeds_webhook(destination, std::slice::from_ref(&embed))
+        })
+        .await
     }
 
-    pub async fn execute_embed_webhook(&self, embed: Embed) -> Result<(), WebhookExecuteError> {
-        self.discord_client
-            .execute_webhook(self.id, &self.token)
-            .embeds(&[embed])?
+    /// Runs `execute` concurrently for all `destinations` and moves the successful ones into
+    /// the `alerted` state, if given.
+    async fn execute_all<'d, F, Fut>(
+        &self,
+        destinations: impl Iterator<Item = &'d Destination>,
+        alerted: Option<bool>,
+        execute: F,
+    ) -> Result<(), WebhookDeliveryError>
+    where
+        F: Fn(&'d Destination) -> Fut,
+        Fut: Future<Output = Result<(), WebhookExecuteError>>,
+    {
+        let execute = &execute;
+        let results = future::join_all(destinations.map(|destination| async move {
+            let result = execute(destination).await;
+            if let (Ok(_), Some(alerted)) = (&result, alerted) {
+                destination.alerted.store(alerted, Ordering::Relaxed);
+            }
+            (destination.id, result)
+        }))
+        .await;
+
+        let failed: Vec<_> = results
+            .into_iter()
+            .filter_map(|(id, result)| result.err().map(|err| (id, err)))
+            .collect();
+        match failed.is_empty() {
+            true => Ok(()),
+            false => Err(WebhookDeliveryError(failed)),
+        }
+    }
+
+    pub async fn execute_embeds_webhook(
+        &self,
+        destination: &Destination,
+        embeds: &[Embed],
+    ) -> Result<(), WebhookExecuteError> {
+        let embeds: Vec<_> = embeds
+            .iter()
+            .cloned()
+            .map(|embed| redact_embed(self.branding.apply(embed), redact_text))
+            .map(fit_embed)
+            .collect();
+        let size = serde_json::to_vec(&embeds).map_or(0, |json| json.len());
+        egress::METER.record(Class::Discord, Transfer::sent(size));
+        let mut request = self
+            .discord_client
+            .execute_webhook(destination.id, &destination.token)
+            .embeds(&embeds)?;
+        if let Some(username) = self.branding.username.as_deref() {
+            request = request.username(username)?;
+        }
+        if let Some(avatar_url) = self.branding.avatar_url.as_deref() {
+            request = request.avatar_url(avatar_url);
+        }
+        request.wait().await.map(|_| ()).map_err(|err| err.into())
+    }
+}
+
+fn redact_text(text: &str) -> String {
+    redact::text(text).into_owned()
+}
+
+/// Applies `redact` to all texts of the embed, so no alert shows coordinates unless allowed.
+fn redact_embed(mut embed: Embed, redact: impl Fn(&str) -> String) -> Embed {
+    if let Some(title) = embed.title.as_mut() {
+        *title = redact(title);
+    }
+    if let Some(description) = embed.description.as_mut() {
+        *description = redact(description);
+    }
+    if let Some(footer) = embed.footer.as_mut() {
+        footer.text = redact(&footer.text);
+    }
+    for field in embed.fields.iter_mut() {
+        field.name = redact(&field.name);
+        field.value = redact(&field.value);
+    }
+    embed
+}
+
+/// Truncates all texts of the embed to Discord's limits, we rather send a shortened message
+/// than having it rejected.
+fn fit_embed(mut embed: Embed) -> Embed {
+    fn fit(text: &mut String, max_len: usize) {
+        if text.len() > max_len {
+            *text = truncate(text, max_len);
+        }
+    }
+
+    if let Some(title) = embed.title.as_mut() {
+        fit(title, TITLE_LENGTH);
+    }
+    if let Some(description) = embed.description.as_mut() {
+        fit(description, DESCRIPTION_LENGTH);
+    }
+    if let Some(footer) = embed.footer.as_mut() {
+        fit(&mut footer.text, FOOTER_TEXT_LENGTH);
+    }
+    for field in embed.fields.iter_mut() {
+        fit(&mut field.name, FIELD_NAME_LENGTH);
+        fit(&mut field.value, FIELD_VALUE_LENGTH);
+    }
+
+    embed
+}
+
+/// Splits the alert `fields` into embeds of at most [`FIELD_COUNT`] fields and groups those
+/// into messages of at most [`EMBED_COUNT_LIMIT`] embeds.
+///
+/// Every message stays within [`EMBED_TOTAL_LENGTH`] characters, field values get shortened
+/// if a single embed would already exceed that. The `reserved` characters per embed are left
+/// for what is added to the embeds after paginating, like the [`Branding`].
+/// Every embed is colored by the highest severity of its fields.
+fn paginate(fields: &[&AlertField], description: &str, reserved: usize) -> Vec<Vec<Embed>> {
+    let total = fields.len();
+    let embeds = fields.chunks(FIELD_COUNT).enumerate().map(|(i, chunk)| {
+        let start = i * FIELD_COUNT + 1;
+        let end = start + chunk.len() - 1;
+        let title = match start == end {
+            true => format!("error {start} of {total}"),
+            false => format!("errors {start}–{end} of {total}"),
+        };
+        let description = match i {
+            0 => Some(description),
+            _ => None,
+        };
+        alert_embed(title, description, chunk, reserved)
+    });
+
+    let mut messages: Vec<Vec<Embed>> = Vec::new();
+    let mut message_chars = 0;
+    for embed in embeds {
+        let chars = twilight_validate::embed::chars(&embed) + reserved;
+        match messages.last_mut() {
+            Some(message)
+                if message.len() < EMBED_COUNT_LIMIT
+                    && message_chars + chars <= EMBED_TOTAL_LENGTH =>
+            {
+                message_chars += chars;
+                message.push(embed);
+            }
+            _ => {
+                message_chars = chars;
+                messages.push(vec![embed]);
+            }
+        }
+    }
+
+    messages
+}
+
+fn alert_embed(
+    title: String,
+    description: Option<&str>,
+    fields: &[&AlertField],
+    reserved: usize,
+) -> Embed {
+    // twilight_validate measures the embed length in bytes, so we do that too
+    let fixed_len = reserved
+        + title.len()
+        + description.map(str::len).unwrap_or_default()
+        + fields.iter().map(|field| field.name.len()).sum::<usize>();
+    let value_len = EMBED_TOTAL_LENGTH.saturating_sub(fixed_len) / fields.len().max(1);
+    let severity = fields
+        .iter()
+        .map(|field| field.severity)
+        .max()
+        .unwrap_or(Severity::Critical);
+
+    let mut embed = EmbedBuilder::new().color(severity.color()).title(title);
+    if let Some(description) = description {
+        embed = embed.description(description);
+    }
+
+    for field in fields {
+        let value = truncate(&field.value, value_len);
+        embed = embed.field(EmbedFieldBuilder::new(&field.name, value));
+    }
+
+    embed.build()
+}
+
+/// Shortens `value` to at most `max_len` bytes, marking the cut with an ellipsis.
+pub(crate) fn truncate(value: &str, max_len: usize) -> String {
+    if value.len() <= max_len {
+        return value.to_string();
+    }
+
+    let mut end = max_len.saturating_sub('…'.len_utf8());
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}…", &value[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use warp::http::StatusCode;
+    use warp::Filter;
+
+    pub(super) type Executions = Arc<Mutex<Vec<u64>>>;
+
+    /// Stands in for the Discord API, records the ids of all executed webhooks and answers
+    /// with whatever `respond` returns for that id.
+    pub(super) fn mock_discord(
+        respond: fn(u64) -> (StatusCode, &'static str),
+    ) -> (SocketAddr, Executions) {
+        let executions = Executions::default();
+        let recorded = executions.clone();
+        let route = warp::path!("api" / "v10" / "webhooks" / u64 / String).map(
+            move |id, _token: String| {
+                recorded.lock().push(id);
+                let (status, body) = respond(id);
+                warp::reply::with_status(
+                    warp::reply::with_header(body, "content-type", "application/json"),
+                    status,
+                )
+            },
+        );
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        (addr, executions)
+    }
+
+    pub(super) fn mock_webhook(addr: SocketAddr, ids: &[u64]) -> Webhook {
+        let client = DiscordClient::builder()
+            .proxy(addr.to_string(), true)
+            .ratelimiter(None)
+            .build();
+        let destinations = ids
+            .iter()
+            .map(|id| Destination::new(Id::new(*id), "token".to_string(), Severity::Info))
+            .collect();
+        Webhook::with_client(client, destinations)
+    }
+
+    pub(super) fn errors() -> Vec<AlertField> {
+        let parse_error = crate::timestamp::parse("").unwrap_err();
+        let target = Target {
+            location: &crate::locations::LOCATIONS.locations[0],
+            model: &crate::locations::Model::default_model(),
+        };
+        AlertField::from_errors(
+            &[(target, HandleLocationError::ParseFromTimestamp(parse_error))],
+            1,
+        )
+    }
+
+    fn fields(count: usize, value_len: usize) -> Vec<AlertField> {
+        (1..=count)
+            .map(|i| AlertField {
+                name: format!("location {i}"),
+                value: "e".repeat(value_len),
+                severity: Severity::Warning,
+                tick_id: Some(1),
+                incident: None,
+            })
+            .collect()
+    }
+
+    fn paginate_fields(fields: &[AlertField]) -> Vec<Vec<Embed>> {
+        paginate(&fields.iter().collect::<Vec<_>>(), ALERT_DESCRIPTION, 0)
+    }
+
+    fn titles(messages: &[Vec<Embed>]) -> Vec<Vec<&str>> {
+        messages
+            .iter()
+            .map(|m| m.iter().map(|e| e.title.as_deref().unwrap()).collect())
+            .collect()
+    }
+
+    fn assert_limits(messages: &[Vec<Embed>]) {
+        for message in messages {
+            assert!(message.len() <= EMBED_COUNT_LIMIT);
+            let chars: usize = message.iter().map(twilight_validate::embed::chars).sum();
+            assert!(chars <= EMBED_TOTAL_LENGTH, "message has {chars} chars");
+            twilight_validate::message::embeds(message).unwrap();
+        }
+    }
+
+    #[test]
+    fn location_group_embed() {
+        use crate::groups::tests::{mixed_locations, targets};
+        let targets = targets(mixed_locations());
+        let write_error = Arc::new(crate::sink::WriteError::from(
+            influxdb2::RequestError::Deserializing {
+                text: "unexpected end of input".to_string(),
+            },
+        ));
+        let write = || HandleLocationError::WritePoints {
+            bucket: "swat".to_string(),
+            error: write_error.clone(),
+        };
+        let errors = [
+            (targets[0], write()),
+            (
+                targets[1],
+                HandleLocationError::ParseFromTimestamp(crate::timestamp::parse("").unwrap_err()),
+            ),
+            (targets[4], write()),
+        ];
+        let groups = LocationGroups::new(&targets);
+        let fields = AlertField::by_location_group(&errors, 7, &groups);
+        assert_eq!(fields.len(), 2);
+        insta::assert_debug_snapshot!(paginate_fields(&fields));
+    }
+
+    #[test]
+    fn paginate_single_error() {
+        let messages = paginate_fields(&fields(1, 20));
+        assert_limits(&messages);
+        assert_eq!(titles(&messages), [["error 1 of 1"]]);
+        assert_eq!(messages[0][0].fields.len(), 1);
+        assert_eq!(
+            messages[0][0].description.as_deref(),
+            Some(ALERT_DESCRIPTION)
+        );
+    }
+
+    #[test]
+    fn paginate_full_embed() {
+        let messages = paginate_fields(&fields(25, 20));
+        assert_limits(&messages);
+        assert_eq!(titles(&messages), [["errors 1–25 of 25"]]);
+        assert_eq!(messages[0][0].fields.len(), 25);
+    }
+
+    #[test]
+    fn paginate_one_past_full_embed() {
+        let messages = paginate_fields(&fields(26, 20));
+        assert_limits(&messages);
+        assert_eq!(titles(&messages), [["errors 1–25 of 26", "error 26 of 26"]]);
+        assert_eq!(messages[0][1].fields[0].name, "location 26");
+        assert_eq!(messages[0][1].description, None);
+    }
+
+    #[test]
+    fn paginate_many_messages() {
+        let messages = paginate_fields(&fields(260, 10));
+        assert_limits(&messages);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].len(), 10);
+        assert_eq!(messages[1].len(), 1);
+        assert_eq!(messages[0][1].title.as_deref(), Some("errors 26–50 of 260"));
+        assert_eq!(
+            messages[1][0].title.as_deref(),
+            Some("errors 251–260 of 260")
+        );
+        let field_count: usize = messages.iter().flatten().map(|e| e.fields.len()).sum();
+        assert_eq!(field_count, 260);
+    }
+
+    #[test]
+    fn paginate_shrinks_long_values() {
+        let messages = paginate_fields(&fields(60, 1000));
+        assert_limits(&messages);
+        assert_eq!(messages.len(), 3);
+        let field = &messages[0][0].fields[0];
+        assert!(field.value.ends_with('…'));
+        assert!(field.value.len() < 1000);
+    }
+
+    #[tokio::test]
+    async fn branding_fits_into_full_messages() {
+        let (addr, executions) = mock_discord(|_| (StatusCode::OK, "{}"));
+        let branding = Branding::from_lookup(|key| match key {
+            "NOTIFY_TITLE_PREFIX" => Some("[north]".to_string()),
+            "NOTIFY_FOOTER" => Some("f".repeat(FOOTER_TEXT_LENGTH)),
+            _ => None,
+        });
+        let webhook = mock_webhook(addr, &[1]).with_branding(branding.clone());
+
+        // every message is filled up to the limit before the branding is applied
+        let fields = fields(60, 1000);
+        let messages = paginate(
+            &fields.iter().collect::<Vec<_>>(),
+            ALERT_DESCRIPTION,
+            branding.added_len(),
+        );
+        let branded: Vec<Vec<_>> = (messages.iter())
+            .map(|message| message.iter().cloned().map(|e| branding.apply(e)).collect())
+            .collect();
+        assert_limits(&branded);
+        for message in &messages {
+            webhook
+                .execute_embeds_webhook(&webhook.destinations[0], message)
+                .await
+                .unwrap();
+        }
+        webhook.alert(&fields).await.unwrap();
+        assert_eq!(executions.lock().len(), 2 * messages.len());
+    }
+
+    #[tokio::test]
+    async fn tick_footer_fits_into_full_messages() {
+        let (addr, executions) = mock_discord(|_| (StatusCode::OK, "{}"));
+        let branding = Branding::from_lookup(|key| match key {
+            "NOTIFY_FOOTER" => Some("f".repeat(1000)),
+            _ => None,
+        });
+        let webhook = mock_webhook(addr, &[1]).with_branding(branding);
+
+        // full messages of a tick with a long id
+        let mut fields = fields(60, 1000);
+        for field in fields.iter_mut() {
+            field.tick_id = Some(u64::MAX);
+        }
+        webhook.alert(&fields).await.unwrap();
+        assert_eq!(executions.lock().len(), 3);
+    }
+
+    #[test]
+    fn truncate_keeps_short_values() {
+        assert_eq!(truncate("short", 5), "short");
+        assert_eq!(truncate("longer", 5), "lo…");
+        assert_eq!(truncate("ümläute", 6), "üm…");
+        assert_eq!(truncate("ümläute", 5), "ü…");
+    }
+
+    #[tokio::test]
+    async fn redacts_coordinates_in_embeds() {
+        let location = &crate::locations::LOCATIONS.locations[0];
+        let model = crate::locations::Model::default_model();
+        let url = location.forecast_url("http://127.0.0.1:9", &model);
+        let err = reqwest::get(url).await.unwrap_err();
+        let target = Target {
+            location,
+            model: &model,
+        };
+        let fields = AlertField::from_errors(
+            &[(target, HandleLocationError::RequestForecast(err.into()))],
+            1,
+        );
+        let render = |redact: bool| {
+            let embeds: Vec<_> = paginate_fields(&fields)
+                .into_iter()
+                .flatten()
+                .map(|embed| {
+                    redact_embed(embed, |text| redact::text_with(text, redact).into_owned())
+                })
+                .collect();
+            serde_json::to_string(&embeds).unwrap()
+        };
+
+        let embeds = render(false);
+        assert!(redact::has_precise_coordinates(&embeds), "{embeds}");
+        let embeds = render(true);
+        assert!(!redact::has_precise_coordinates(&embeds), "{embeds}");
+        assert!(embeds.contains("?lat=52.9&lon=8.2"), "{embeds}");
+    }
+
+    #[test]
+    fn fit_embed_truncates_long_field() {
+        let embed = alert_embed(
+            "error 1 of 1".to_string(),
+            Some(ALERT_DESCRIPTION),
+            &[&fields(1, 2000)[0]],
+            0,
+        );
+        assert!(twilight_validate::embed::embed(&embed).is_err());
+
+        let embed = fit_embed(embed);
+        twilight_validate::embed::embed(&embed).unwrap();
+        assert_eq!(embed.fields[0].value.len(), FIELD_VALUE_LENGTH);
+        assert!(embed.fields[0].value.ends_with('…'));
+    }
+
+    #[tokio::test]
+    async fn rejected_webhook_reports_path() {
+        let (addr, _) = mock_discord(|_| {
+            let body = r#"{
+                "code": 50035,
+                "errors": {"embeds": {"0": {"fields": {"3": {"value": {"_errors": [
+                    {"code": "BASE_TYPE_MAX_LENGTH", "message": "Must be 1024 or fewer in length."}
+                ]}}}}}},
+                "message": "Invalid Form Body"
+            }"#;
+            (StatusCode::BAD_REQUEST, body)
+        });
+
+        let webhook = mock_webhook(addr, &[1]);
+        let embed = EmbedBuilder::new().description("test").build();
+        let err = webhook
+            .execute_embeds_webhook(&webhook.destinations[0], &[embed])
             .await
-            .map(|_| ())
-            .map_err(|err| err.into())
+            .unwrap_err();
+        let WebhookExecuteError::Rejected { message, errors } = &err else {
+            panic!("expected rejected error, got {err:?}");
+        };
+        assert_eq!(message, "Invalid Form Body");
+        assert_eq!(
+            errors,
+            &[RejectedField {
+                path: "embeds.0.fields.3.value".to_string(),
+                message: "Must be 1024 or fewer in length.".to_string(),
+            }]
+        );
+        assert_eq!(
+            err.to_string(),
+            "discord rejected the webhook, Invalid Form Body: \
+             embeds.0.fields.3.value: Must be 1024 or fewer in length."
+        );
+    }
+
+    #[test]
+    fn parse_destination_list() {
+        let destinations = Destination::parse_list("1:abc; 2:def;").unwrap();
+        assert_eq!(destinations.len(), 2);
+        assert_eq!(destinations[0].id, Id::new(1));
+        assert_eq!(destinations[0].token, "abc");
+        assert_eq!(destinations[1].id, Id::new(2));
+        assert_eq!(destinations[1].token, "def");
+
+        assert!(matches!(
+            Destination::parse_list("1abc"),
+            Err(ParseDestinationError::Format(_))
+        ));
+        assert!(matches!(
+            Destination::parse_list("x:abc"),
+            Err(ParseDestinationError::Id(_))
+        ));
+        assert!(matches!(
+            Destination::parse_list("0:abc"),
+            Err(ParseDestinationError::ZeroId)
+        ));
+    }
+
+    #[tokio::test]
+    async fn alert_reaches_all_destinations() {
+        let (addr, executions) = mock_discord(|_| (StatusCode::OK, "{}"));
+        let webhook = mock_webhook(addr, &[1, 2]);
+
+        webhook.alert(&errors()).await.unwrap();
+        executions.lock().sort();
+        assert_eq!(*executions.lock(), [1, 2]);
+
+        // already alerted destinations are not alerted again
+        webhook.alert(&errors()).await.unwrap();
+        assert_eq!(executions.lock().len(), 2);
+
+        webhook.resolved(None, None).await.unwrap();
+        executions.lock().sort();
+        assert_eq!(*executions.lock(), [1, 1, 2, 2]);
+    }
+
+    #[tokio::test]
+    async fn alert_reports_failed_destinations() {
+        let (addr, executions) = mock_discord(|id| match id {
+            2 => (StatusCode::INTERNAL_SERVER_ERROR, "{}"),
+            _ => (StatusCode::OK, "{}"),
+        });
+        let webhook = mock_webhook(addr, &[1, 2]);
+
+        let err = webhook.alert(&errors()).await.unwrap_err();
+        assert_eq!(err.0.len(), 1);
+        assert_eq!(err.0[0].0, Id::new(2));
+        assert!(err
+            .to_string()
+            .starts_with("webhook execution failed for 2 ("));
+        executions.lock().sort();
+        assert_eq!(*executions.lock(), [1, 2]);
+
+        // only the failed destination is retried
+        executions.lock().clear();
+        webhook.alert(&errors()).await.unwrap_err();
+        assert_eq!(*executions.lock(), [2]);
+
+        // only the alerted destination gets resolved
+        executions.lock().clear();
+        webhook.resolved(None, None).await.unwrap();
+        assert_eq!(*executions.lock(), [1]);
+    }
+
+    #[test]
+    fn embed_color_follows_severity() {
+        let mut fields = fields(30, 10);
+        fields[3].severity = Severity::Critical;
+        fields[27].severity = Severity::Info;
+        let messages = paginate_fields(&fields);
+        assert_eq!(messages[0][0].color, Some(Severity::Critical.color()));
+        assert_eq!(messages[0][1].color, Some(Severity::Warning.color()));
+
+        let mut info = fields.split_off(27);
+        info.iter_mut()
+            .for_each(|field| field.severity = Severity::Info);
+        let messages = paginate_fields(&info);
+        assert_eq!(messages[0][0].color, Some(Severity::Info.color()));
+    }
+
+    #[test]
+    fn parse_destination_severity() {
+        let destinations = Destination::parse_list("1:abc:warning;2:def").unwrap();
+        assert_eq!(destinations[0].min_severity, Severity::Warning);
+        assert_eq!(destinations[1].min_severity, Severity::Info);
+        assert!(matches!(
+            Destination::parse_list("1:abc:loud"),
+            Err(ParseDestinationError::Severity(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn alert_routes_by_severity() {
+        let (addr, executions) = mock_discord(|_| (StatusCode::OK, "{}"));
+        let client = DiscordClient::builder()
+            .proxy(addr.to_string(), true)
+            .ratelimiter(None)
+            .build();
+        let webhook = Webhook::with_client(
+            client,
+            Destination::parse_list("1:ops:critical;2:archive:info").unwrap(),
+        );
+
+        // the default severity of `errors()` is a warning
+        webhook.alert(&errors()).await.unwrap();
+        assert_eq!(*executions.lock(), [2]);
+
+        // the ops channel was never alerted and therefore gets no resolved message
+        webhook.resolved(None, None).await.unwrap();
+        assert_eq!(*executions.lock(), [2, 2]);
     }
 }
@@ -0,0 +1,531 @@
+use super::queue::{Notification, Notifier};
+use super::truncate;
+use crate::logging;
+use crate::severity::Severity;
+use crate::sink::Sink;
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use influxdb2::api::query::FluxRecord;
+use influxdb2::models::data_point::DataPointError;
+use influxdb2::models::DataPoint;
+use influxdb2_structmap::value::Value;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Measurement of the audited notifications.
+pub const MEASUREMENT: &str = "collector_alerts";
+
+/// Descriptions are cut off beyond this many bytes, the notification itself is not audited.
+const DESCRIPTION_LENGTH: usize = 200;
+
+/// An alert is not sent again for an incident alerted within this many minutes unless
+/// `REALERT_SUPPRESS_MINUTES` is set.
+pub const DEFAULT_REALERT_SUPPRESS_MINUTES: u64 = 60;
+
+/// Writes every delivery attempt of the wrapped [`Notifier`] into the [`MEASUREMENT`], as an
+/// audit trail of the notifications next to the data.
+///
+/// The writes are best-effort. A failed write is only logged and counted, never notified,
+/// as the notification about it would be audited again.
+///
+/// The audit also deduplicates alerts across restarts: an alert of an incident whose last
+/// audited delivery within the suppression window is an alert, not its resolution, is
+/// suppressed, as a collector restarted during the incident alerts it again.
+pub struct Audited<N> {
+    notifier: N,
+    sink: Arc<Sink>,
+
+    /// Audit writes that failed since the start.
+    failures: AtomicU64,
+
+    /// Alerts of incidents alerted this recently are suppressed, none if zero.
+    suppress: Duration,
+}
+
+impl<N: Notifier> Audited<N> {
+    pub fn new(notifier: N, sink: Arc<Sink>) -> Audited<N> {
+        Audited {
+            notifier,
+            sink,
+            failures: AtomicU64::new(0),
+            suppress: Duration::from_secs(DEFAULT_REALERT_SUPPRESS_MINUTES * 60),
+        }
+    }
+
+    /// Suppresses alerts of incidents alerted within the last `minutes`, none if zero.
+    pub fn with_realert_suppress(self, minutes: u64) -> Audited<N> {
+        Audited {
+            suppress: Duration::from_secs(minutes * 60),
+            ..self
+        }
+    }
+
+    /// Audit writes that failed since the start.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn failures(&self) -> u64 {
+        self.failures.load(Ordering::Relaxed)
+    }
+
+    /// Whether the incident of the alert `notification` was alerted within the suppression
+    /// window already, querying failures count as not alerted.
+    async fn alerted(&self, notification: &Notification) -> bool {
+        let Notification::Alert(fields) = notification else {
+            return false;
+        };
+        let Some(incident) = fields.iter().find_map(|field| field.incident.as_deref()) else {
+            return false;
+        };
+        if self.suppress.is_zero() {
+            return false;
+        }
+        let query = query(self.sink.default_bucket(), incident, self.suppress);
+        match self.sink.query(query).await {
+            Ok(records) => last_kind(&records).is_some_and(|kind| kind != "resolved"),
+            Err(err) => {
+                let datetime = logging::datetime();
+                log_eprintln!(
+                    "WARN  [{datetime}]: could not look up earlier alerts of incident \
+                     {incident}, alerting anyway, {err}"
+                );
+                false
+            }
+        }
+    }
+
+    async fn audit(&self, notification: &Notification, status: &str) {
+        let bucket = self.sink.default_bucket();
+        let written = match data_point(notification, self.notifier.channel(), status, Utc::now()) {
+            Ok(point) => (self.sink.write(bucket, vec![point]).await).map_err(|e| e.to_string()),
+            Err(err) => Err(err.to_string()),
+        };
+        if let Err(err) = written {
+            let failures = self.failures.fetch_add(1, Ordering::Relaxed) + 1;
+            let datetime = logging::datetime();
+            log_eprintln!(
+                "WARN  [{datetime}]: could not audit the {}, {err}, {failures} audit writes \
+                 failed so far",
+                notification.describe()
+            );
+        }
+    }
+}
+
+impl<N: Notifier> Notifier for Audited<N>
+where
+    N::Error: Send,
+{
+    type Error = N::Error;
+
+    fn channel(&self) -> &'static str {
+        self.notifier.channel()
+    }
+
+    fn deliver<'a>(
+        &'a self,
+        notification: &'a Notification,
+    ) -> BoxFuture<'a, Result<(), N::Error>> {
+        Box::pin(async move {
+            if self.alerted(notification).await {
+                let datetime = logging::datetime();
+                log_eprintln!(
+                    "INFO  [{datetime}]: not sending the {}, its incident was alerted within \
+                     the last {} minutes already",
+                    notification.describe(),
+                    self.suppress.as_secs() / 60
+                );
+                self.audit(notification, "suppressed").await;
+                return Ok(());
+            }
+            let delivered = self.notifier.deliver(notification).await;
+            let status = match delivered {
+                Ok(()) => "delivered",
+                Err(_) => "failed",
+            };
+            self.audit(notification, status).await;
+            delivered
+        })
+    }
+
+    fn is_permanent(&self, error: &N::Error) -> bool {
+        self.notifier.is_permanent(error)
+    }
+}
+
+/// The Flux query for the kind of the last notification delivered about the `incident` within
+/// the `window`.
+fn query(bucket: &str, incident: &str, window: Duration) -> String {
+    format!(
+        r#"from(bucket: {bucket:?})
+            |> range(start: -{}s)
+            |> filter(fn: (r) => r._measurement == "{MEASUREMENT}" and r.status == "delivered")
+            |> filter(fn: (r) => r._field == "incident_id" and r._value == {incident:?})
+            |> group()
+            |> sort(columns: ["_time"])
+            |> last()
+            |> keep(columns: ["kind"])"#,
+        window.as_secs()
+    )
+}
+
+/// The kind of notification of the [query] `records`, if any was delivered.
+fn last_kind(records: &[FluxRecord]) -> Option<&str> {
+    records
+        .iter()
+        .rev()
+        .find_map(|record| match record.values.get("kind") {
+            Some(Value::String(kind)) => Some(kind.as_str()),
+            _ => None,
+        })
+}
+
+/// The point auditing the delivery of the `notification` to the `channel` at `now`, the
+/// `status` is `delivered`, `failed` or `suppressed` as a duplicate.
+///
+/// The `locations` are the fields of the alerts, one per location unless aggregated by
+/// error kind or location group.
+pub fn data_point(
+    notification: &Notification,
+    channel: &str,
+    status: &str,
+    now: DateTime<Utc>,
+) -> Result<DataPoint, DataPointError> {
+    let (kind, fields, incident) = match notification {
+        Notification::Alert(fields) => {
+            let incident = fields.iter().find_map(|field| field.incident.clone());
+            ("alert", fields.iter().collect(), incident)
+        }
+        Notification::Resolved { history, incident } => (
+            "resolved",
+            history.iter().flatten().collect(),
+            incident.as_ref().map(|incident| incident.id.clone()),
+        ),
+        Notification::Warning(_) => ("warning", Vec::new(), None),
+        Notification::Digest(alerts) => {
+            let fields: Vec<_> = alerts.iter().flat_map(|alert| &alert.fields).collect();
+            let incident = fields.iter().find_map(|field| field.incident.clone());
+            ("digest", fields, incident)
+        }
+    };
+    let severity = match notification {
+        Notification::Resolved { .. } => Severity::Info,
+        Notification::Warning(_) => Severity::Warning,
+        _ => fields
+            .iter()
+            .map(|field| field.severity)
+            .max()
+            .unwrap_or(Severity::Info),
+    };
+    let point = DataPoint::builder(MEASUREMENT)
+        .timestamp(now.timestamp())
+        .tag("severity", severity.to_string())
+        .tag("channel", channel)
+        .tag("status", status)
+        .tag("kind", kind)
+        .field("locations", fields.len() as i64)
+        .field(
+            "description",
+            truncate(&notification.describe(), DESCRIPTION_LENGTH),
+        );
+    match incident {
+        Some(incident) => point.field("incident_id", incident).build(),
+        None => point.build(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::incident::{self, IncidentAction, IncidentSummary, IncidentTracker};
+    use crate::sink::Buckets;
+    use crate::webhook::tests::errors;
+    use crate::webhook::NotificationQueue;
+    use influxdb2::models::WriteDataPoint;
+    use parking_lot::Mutex;
+    use warp::http::StatusCode;
+    use warp::Filter;
+
+    /// Fails the first `fail` deliveries, recording every notification delivered.
+    #[derive(Default)]
+    struct Flaky {
+        fail: Mutex<usize>,
+        delivered: Mutex<Vec<String>>,
+    }
+
+    impl Notifier for Flaky {
+        type Error = String;
+
+        fn channel(&self) -> &'static str {
+            "discord"
+        }
+
+        fn deliver<'a>(
+            &'a self,
+            notification: &'a Notification,
+        ) -> BoxFuture<'a, Result<(), String>> {
+            Box::pin(async move {
+                let mut fail = self.fail.lock();
+                if *fail > 0 {
+                    *fail -= 1;
+                    return Err("unreachable".to_string());
+                }
+                self.delivered.lock().push(notification.describe());
+                Ok(())
+            })
+        }
+    }
+
+    fn line(point: DataPoint) -> String {
+        let mut line = Vec::new();
+        point.write_data_point_to(&mut line).unwrap();
+        String::from_utf8(line).unwrap()
+    }
+
+    /// An InfluxDB answering writes with `status`, recording the lines written.
+    fn mock_influx(status: StatusCode) -> (Arc<Sink>, Arc<Mutex<Vec<String>>>) {
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let write = warp::path!("api" / "v2" / "write")
+            .and(warp::body::bytes())
+            .map({
+                let lines = lines.clone();
+                move |body: warp::hyper::body::Bytes| {
+                    lines
+                        .lock()
+                        .push(String::from_utf8_lossy(&body).into_owned());
+                    status
+                }
+            });
+        let (addr, server) = warp::serve(write).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let sink = Sink::Influx {
+            client: Box::new(crate::sink::InfluxClient::new(
+                format!("http://{addr}"),
+                "org",
+                "token",
+            )),
+            buckets: Buckets::from_lookup(|_| None, &[]),
+            idempotent: false,
+            profile: None,
+        };
+        (Arc::new(sink), lines)
+    }
+
+    /// An InfluxDB keeping the audited lines written, answering the [query] from them.
+    fn mock_audit_history() -> (Arc<Sink>, Arc<Mutex<Vec<String>>>) {
+        let lines = Arc::new(Mutex::new(Vec::<String>::new()));
+        let write = warp::path!("api" / "v2" / "write")
+            .and(warp::body::bytes())
+            .map({
+                let lines = lines.clone();
+                move |body: warp::hyper::body::Bytes| {
+                    lines
+                        .lock()
+                        .push(String::from_utf8_lossy(&body).into_owned());
+                    StatusCode::NO_CONTENT
+                }
+            });
+        let query = warp::path!("api" / "v2" / "query")
+            .and(warp::body::json())
+            .map({
+                let lines = lines.clone();
+                move |body: serde_json::Value| {
+                    let query = body["query"].as_str().unwrap_or_default().to_string();
+                    let kind = lines.lock().iter().rev().find_map(|line| {
+                        let (_, incident) = line.split_once("incident_id=")?;
+                        let incident = incident.split(',').next()?;
+                        let delivered = line.contains(",status=delivered ");
+                        let kind = line.split_once(",kind=")?.1.split(',').next()?;
+                        (delivered && query.contains(incident)).then(|| kind.to_string())
+                    });
+                    let mut csv = "#datatype,string,long,string\n\
+                                   #group,false,false,false\n\
+                                   #default,_result,,\n\
+                                   ,result,table,kind\n"
+                        .to_string();
+                    if let Some(kind) = kind {
+                        csv += &format!(",,0,{kind}\n");
+                    }
+                    csv
+                }
+            });
+        let (addr, server) = warp::serve(write.or(query)).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let sink = Sink::Influx {
+            client: Box::new(crate::sink::InfluxClient::new(
+                format!("http://{addr}"),
+                "org",
+                "token",
+            )),
+            buckets: Buckets::from_lookup(|_| None, &[]),
+            idempotent: false,
+            profile: None,
+        };
+        (Arc::new(sink), lines)
+    }
+
+    /// The notification after a tick with `failures` observed by the `tracker` at `minute`.
+    fn observe(
+        tracker: &mut IncidentTracker,
+        failures: usize,
+        minute: i64,
+    ) -> Option<Notification> {
+        let now = DateTime::<Utc>::from_timestamp(1714564800 + 60 * minute, 0).unwrap();
+        let cause = incident::cause("ww-grossenkneten", "request_forecast");
+        match tracker.observe(failures, &cause, now) {
+            IncidentAction::Alert => Some(Notification::Alert(
+                (errors().into_iter())
+                    .map(|field| field.with_incident(tracker.open_id()))
+                    .collect(),
+            )),
+            IncidentAction::Resolve(incident) => Some(Notification::Resolved {
+                history: None,
+                incident: Some(incident),
+            }),
+            IncidentAction::None => None,
+        }
+    }
+
+    #[test]
+    fn audit_points() {
+        let now = DateTime::<Utc>::from_timestamp(1714564800, 0).unwrap();
+        let fields: Vec<_> = errors()
+            .into_iter()
+            .map(|field| field.with_incident(Some("20240501T120000Z".to_string())))
+            .collect();
+        let alert = Notification::Alert(fields);
+        assert_eq!(
+            line(data_point(&alert, "discord", "delivered", now).unwrap()),
+            "collector_alerts,channel=discord,kind=alert,severity=warning,status=delivered \
+             description=\"alert for WW Großenkneten\",incident_id=\"20240501T120000Z\",\
+             locations=1i 1714564800\n"
+        );
+
+        let resolved = Notification::Resolved {
+            history: None,
+            incident: Some(IncidentSummary {
+                id: "20240501T120000Z".to_string(),
+                duration: chrono::Duration::minutes(16),
+                failures: 4,
+                gaps: Vec::new(),
+            }),
+        };
+        assert_eq!(
+            line(data_point(&resolved, "discord", "failed", now).unwrap()),
+            "collector_alerts,channel=discord,kind=resolved,severity=info,status=failed \
+             description=\"resolution\",incident_id=\"20240501T120000Z\",locations=0i \
+             1714564800\n"
+        );
+
+        // long descriptions are cut off, warnings belong to no incident
+        let warning = Notification::Warning("w".repeat(500));
+        let line = line(data_point(&warning, "discord", "delivered", now).unwrap());
+        assert!(line.starts_with("collector_alerts,channel=discord,kind=warning,severity=warning"));
+        assert!(!line.contains("incident_id"), "{line}");
+        assert!(line.contains("www…\",locations=0i"), "{line}");
+        assert!(line.len() < DESCRIPTION_LENGTH + 150, "{line}");
+    }
+
+    #[tokio::test]
+    async fn audits_delivered_and_failed() {
+        let (sink, lines) = mock_influx(StatusCode::NO_CONTENT);
+        let flaky = Flaky {
+            fail: Mutex::new(1),
+            ..Flaky::default()
+        };
+        let audited = Audited::new(flaky, sink);
+        let alert = Notification::Alert(errors());
+
+        assert!(audited.deliver(&alert).await.is_err());
+        assert!(audited.deliver(&alert).await.is_ok());
+        let lines = lines.lock();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains(",status=failed "), "{}", lines[0]);
+        assert!(lines[1].contains(",status=delivered "), "{}", lines[1]);
+        assert_eq!(audited.failures(), 0);
+    }
+
+    #[tokio::test]
+    async fn failed_audit_is_not_notified() {
+        let (sink, lines) = mock_influx(StatusCode::SERVICE_UNAVAILABLE);
+        let audited = Arc::new(Audited::new(Flaky::default(), sink));
+        let queue = Arc::new(NotificationQueue::new(8));
+        queue.push(Notification::Alert(errors()));
+        queue.push(Notification::Warning("late".to_string()));
+
+        let drain = {
+            let (queue, audited) = (queue.clone(), audited.clone());
+            let delay = Duration::from_millis(10);
+            tokio::spawn(async move { queue.drain(&*audited, delay, delay).await })
+        };
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while audited.failures() < 2 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("both audits attempted");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        drain.abort();
+
+        // both notifications delivered once, nothing was notified about the failed audits
+        assert_eq!(
+            *audited.notifier.delivered.lock(),
+            ["alert for WW Großenkneten", "warning \"late\""]
+        );
+        assert_eq!(lines.lock().len(), 2);
+        assert_eq!(audited.failures(), 2);
+    }
+
+    #[tokio::test]
+    async fn alerts_incident_once_across_restarts() {
+        let (sink, lines) = mock_audit_history();
+
+        // the incident is alerted, then the collector crashes and loses its state
+        let before = Audited::new(Flaky::default(), sink.clone());
+        let mut tracker = IncidentTracker::new(1);
+        for minute in 0..3 {
+            if let Some(notification) = observe(&mut tracker, 1, 2 * minute) {
+                before.deliver(&notification).await.unwrap();
+            }
+        }
+
+        // the restarted collector derives the same incident and suppresses its alert
+        let after = Audited::new(Flaky::default(), sink);
+        let mut tracker = IncidentTracker::new(1);
+        let mut resolution = None;
+        for (minute, failures) in [(8, 1), (10, 1), (12, 0)] {
+            if let Some(notification) = observe(&mut tracker, failures, minute) {
+                after.deliver(&notification).await.unwrap();
+                resolution = Some(notification);
+            }
+        }
+
+        assert_eq!(
+            *before.notifier.delivered.lock(),
+            ["alert for WW Großenkneten"]
+        );
+        assert_eq!(*after.notifier.delivered.lock(), ["resolution"]);
+        let lines = lines.lock().clone();
+        assert!(lines[1].contains(",status=suppressed "), "{}", lines[1]);
+
+        // the resolution refers to the alerted incident
+        let Some(Notification::Resolved {
+            incident: Some(incident),
+            ..
+        }) = resolution
+        else {
+            panic!("expected resolution, got {resolution:?}");
+        };
+        assert_eq!(
+            incident.id,
+            "ww-grossenkneten/request_forecast/20240501T12Z"
+        );
+        assert!(lines[0].contains(&format!("incident_id=\"{}\"", incident.id)));
+        assert!(lines[2].contains(&format!("incident_id=\"{}\"", incident.id)));
+
+        // failing again within the hour is a new incident after the resolution
+        let alert = observe(&mut tracker, 1, 20).unwrap();
+        after.deliver(&alert).await.unwrap();
+        assert_eq!(after.notifier.delivered.lock().len(), 2);
+    }
+}
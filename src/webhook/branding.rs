@@ -0,0 +1,172 @@
+use super::truncate;
+use crate::{logging, version};
+use std::fs;
+use twilight_model::channel::message::Embed;
+use twilight_util::builder::embed::EmbedFooterBuilder;
+use twilight_validate::embed::{FOOTER_TEXT_LENGTH, TITLE_LENGTH};
+use twilight_validate::request::WEBHOOK_USERNAME_LIMIT_MAX;
+
+/// Maximum length of the title prefix, the rest of the title is left for the actual title.
+const TITLE_PREFIX_LENGTH: usize = TITLE_LENGTH / 4;
+
+/// Separates the branded footer from the footer of the embed.
+const FOOTER_SEPARATOR: &str = " · ";
+
+/// Discord rejects avatar urls longer than this.
+const AVATAR_URL_LENGTH: usize = 2048;
+
+/// Customizations applied to every notification, so multiple collector instances posting into
+/// the same channel can be told apart.
+#[derive(Debug, Default, Clone)]
+pub struct Branding {
+    pub title_prefix: Option<String>,
+    pub username: Option<String>,
+    pub avatar_url: Option<String>,
+    pub footer: Option<String>,
+}
+
+impl Branding {
+    /// Reads the branding from the `NOTIFY_*` variables `lookup` returns.
+    ///
+    /// Values exceeding Discord's limits are truncated with a warning.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Branding {
+        let footer = lookup("NOTIFY_FOOTER").unwrap_or_else(|| {
+            let hostname = lookup("HOSTNAME")
+                .or_else(|| fs::read_to_string("/etc/hostname").ok())
+                .map(|hostname| hostname.trim().to_string())
+                .filter(|hostname| !hostname.is_empty())
+                .unwrap_or_else(|| "unknown host".to_string());
+            format!("swat-collector v{} on {hostname}", version::VERSION)
+        });
+
+        let avatar_url = lookup("NOTIFY_AVATAR_URL").filter(|url| {
+            let fits = url.len() <= AVATAR_URL_LENGTH;
+            if !fits {
+                warn_limit("NOTIFY_AVATAR_URL", AVATAR_URL_LENGTH, "ignoring it");
+            }
+            fits
+        });
+
+        Branding {
+            title_prefix: lookup("NOTIFY_TITLE_PREFIX")
+                .map(|prefix| limit("NOTIFY_TITLE_PREFIX", prefix, TITLE_PREFIX_LENGTH)),
+            username: lookup("NOTIFY_USERNAME")
+                .map(|username| limit("NOTIFY_USERNAME", username, WEBHOOK_USERNAME_LIMIT_MAX)),
+            avatar_url,
+            footer: Some(limit("NOTIFY_FOOTER", footer, FOOTER_TEXT_LENGTH)),
+        }
+    }
+
+    /// Characters [`apply`](Self::apply) adds to an embed at most, to be left free by the
+    /// embed.
+    pub fn added_len(&self) -> usize {
+        let prefix = self.title_prefix.as_ref().map(|prefix| prefix.len() + 1);
+        let footer = (self.footer.as_ref()).map(|footer| footer.len() + FOOTER_SEPARATOR.len());
+        prefix.unwrap_or_default() + footer.unwrap_or_default()
+    }
+
+    /// Prefixes the title and sets the footer of the `embed`, a footer the embed already has
+    /// is appended.
+    pub fn apply(&self, mut embed: Embed) -> Embed {
+        if let Some(prefix) = self.title_prefix.as_deref() {
+            embed.title = Some(match embed.title {
+                Some(title) => format!("{prefix} {title}"),
+                None => prefix.to_string(),
+            });
+        }
+
+        if let Some(footer) = self.footer.as_deref() {
+            let footer = match embed.footer {
+                Some(existing) => format!("{footer}{FOOTER_SEPARATOR}{}", existing.text),
+                None => footer.to_string(),
+            };
+            embed.footer = Some(EmbedFooterBuilder::new(footer).build());
+        }
+
+        embed
+    }
+}
+
+fn limit(key: &str, value: String, max_len: usize) -> String {
+    if value.len() <= max_len {
+        return value;
+    }
+
+    warn_limit(key, max_len, "truncating it");
+    truncate(&value, max_len)
+}
+
+fn warn_limit(key: &str, max_len: usize, action: &str) {
+    let datetime = logging::datetime();
+    log_eprintln!("WARN  [{datetime}]: {key:?} is longer than {max_len} bytes, {action}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::severity::Severity;
+    use crate::webhook::{paginate, AlertField, ALERT_DESCRIPTION};
+
+    fn embed() -> Embed {
+        let fields = [AlertField {
+            name: "WW Großenkneten".to_string(),
+            value: "forecast request failed, request failed, timed out".to_string(),
+            severity: Severity::Warning,
+            tick_id: Some(1),
+            incident: None,
+        }];
+        paginate(&fields.iter().collect::<Vec<_>>(), ALERT_DESCRIPTION, 0)
+            .remove(0)
+            .remove(0)
+    }
+
+    #[test]
+    fn default_branding() {
+        let branding = Branding::from_lookup(|key| match key {
+            "HOSTNAME" => Some("collector-test".to_string()),
+            _ => None,
+        });
+        assert_eq!(branding.username, None);
+        assert_eq!(branding.avatar_url, None);
+        insta::with_settings!({filters => vec![(r"\(\w+(-dirty)?\)", "([commit])")]}, {
+            insta::assert_debug_snapshot!(branding.apply(embed()));
+        });
+    }
+
+    #[test]
+    fn custom_branding() {
+        let branding = Branding::from_lookup(|key| match key {
+            "NOTIFY_TITLE_PREFIX" => Some("[north]".to_string()),
+            "NOTIFY_USERNAME" => Some("SWAT North".to_string()),
+            "NOTIFY_AVATAR_URL" => Some("https://example.com/north.png".to_string()),
+            "NOTIFY_FOOTER" => Some("north instance".to_string()),
+            _ => None,
+        });
+        assert_eq!(branding.username.as_deref(), Some("SWAT North"));
+        assert_eq!(
+            branding.avatar_url.as_deref(),
+            Some("https://example.com/north.png")
+        );
+        insta::assert_debug_snapshot!(branding.apply(embed()));
+
+        let mut embed = embed();
+        embed.footer = Some(EmbedFooterBuilder::new("tick #4812").build());
+        let footer = branding.apply(embed).footer.unwrap();
+        assert_eq!(footer.text, "north instance · tick #4812");
+    }
+
+    #[test]
+    fn oversized_branding_is_truncated() {
+        let branding = Branding::from_lookup(|key| match key {
+            "NOTIFY_TITLE_PREFIX" => Some("p".repeat(100)),
+            "NOTIFY_USERNAME" => Some("u".repeat(100)),
+            "NOTIFY_AVATAR_URL" => Some("a".repeat(3000)),
+            "NOTIFY_FOOTER" => Some("f".repeat(3000)),
+            _ => None,
+        });
+        assert_eq!(branding.title_prefix.unwrap().len(), TITLE_PREFIX_LENGTH);
+        assert_eq!(branding.username.unwrap().len(), WEBHOOK_USERNAME_LIMIT_MAX);
+        assert_eq!(branding.avatar_url, None);
+        assert_eq!(branding.footer.unwrap().len(), FOOTER_TEXT_LENGTH);
+    }
+}
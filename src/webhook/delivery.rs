@@ -0,0 +1,127 @@
+use super::queue::{Notification, Notifier};
+use super::{AlertField, Webhook, WebhookDeliveryError};
+use crate::logging;
+use futures::future::BoxFuture;
+use std::convert::Infallible;
+
+/// Logs the notifications instead of sending them, replacing every notifier of a read-only
+/// collector.
+#[derive(Debug, Default)]
+pub struct LogNotifier;
+
+impl LogNotifier {
+    fn log(notification: &Notification) {
+        let datetime = logging::datetime();
+        log_eprintln!(
+            "INFO  [{datetime}]: read-only, not sending the {}",
+            notification.describe()
+        );
+        let fields: &[AlertField] = match notification {
+            Notification::Alert(fields) => fields,
+            Notification::Resolved {
+                history: Some(fields),
+                ..
+            } => fields,
+            _ => &[],
+        };
+        for field in fields {
+            log_eprintln!("INFO  [{datetime}]:   {}: {}", field.name, field.value);
+        }
+    }
+}
+
+impl Notifier for LogNotifier {
+    type Error = Infallible;
+
+    fn channel(&self) -> &'static str {
+        "log"
+    }
+
+    fn deliver<'a>(
+        &'a self,
+        notification: &'a Notification,
+    ) -> BoxFuture<'a, Result<(), Infallible>> {
+        LogNotifier::log(notification);
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// The notifier of the collector, the Discord [`Webhook`] unless it is read-only.
+pub enum Delivery {
+    Discord(Box<Webhook>),
+    Log(LogNotifier),
+}
+
+impl Delivery {
+    /// Delivers via the `webhook`, or only logs with `read_only`.
+    pub fn new(webhook: Webhook, read_only: bool) -> Delivery {
+        match read_only {
+            true => Delivery::Log(LogNotifier),
+            false => Delivery::Discord(Box::new(webhook)),
+        }
+    }
+}
+
+impl Notifier for Delivery {
+    type Error = WebhookDeliveryError;
+
+    fn channel(&self) -> &'static str {
+        match self {
+            Delivery::Discord(webhook) => webhook.channel(),
+            Delivery::Log(log) => log.channel(),
+        }
+    }
+
+    fn deliver<'a>(
+        &'a self,
+        notification: &'a Notification,
+    ) -> BoxFuture<'a, Result<(), WebhookDeliveryError>> {
+        match self {
+            Delivery::Discord(webhook) => webhook.deliver(notification),
+            Delivery::Log(log) => {
+                let logged = log.deliver(notification);
+                Box::pin(async move { logged.await.map_err(|never| match never {}) })
+            }
+        }
+    }
+
+    fn is_permanent(&self, error: &WebhookDeliveryError) -> bool {
+        match self {
+            Delivery::Discord(webhook) => webhook.is_permanent(error),
+            Delivery::Log(_) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::severity::Severity;
+    use crate::webhook::tests::{mock_discord, mock_webhook};
+    use warp::http::StatusCode;
+
+    #[tokio::test]
+    async fn read_only_logs_instead_of_sending() {
+        let (addr, executions) = mock_discord(|_| (StatusCode::OK, "{}"));
+        let alert = Notification::Alert(vec![AlertField::new(
+            "WW Großenkneten".to_string(),
+            "request failed".to_string(),
+            Severity::Critical,
+            7,
+        )]);
+
+        let delivery = Delivery::new(mock_webhook(addr, &[1]), true);
+        assert_eq!(delivery.channel(), "log");
+        logging::capture();
+        delivery.deliver(&alert).await.unwrap();
+        let lines = logging::take_captured();
+        assert!(executions.lock().is_empty());
+        assert!(lines[0].ends_with("read-only, not sending the alert for WW Großenkneten"));
+        assert!(lines[1].ends_with("  WW Großenkneten: request failed"));
+
+        let delivery = Delivery::new(mock_webhook(addr, &[1]), false);
+        assert_eq!(delivery.channel(), "discord");
+        delivery.deliver(&alert).await.unwrap();
+        assert_eq!(*executions.lock(), [1]);
+    }
+}
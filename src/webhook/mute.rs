@@ -0,0 +1,174 @@
+use super::Notification;
+use chrono::{DateTime, Utc};
+
+/// Alerts muted on request through the health socket, like during planned maintenance.
+///
+/// Suppressed alerts and resolutions are counted and summarized once the mute ends.
+#[derive(Debug, Default)]
+pub struct Mute {
+    until: Option<DateTime<Utc>>,
+
+    /// Start of the current mute, kept until the summary is taken.
+    since: Option<DateTime<Utc>>,
+
+    alerts: u64,
+    resolutions: u64,
+}
+
+impl Mute {
+    /// Mutes alerts until `until`, extending or shortening a running mute.
+    pub fn mute(&mut self, until: DateTime<Utc>, now: DateTime<Utc>) {
+        self.since.get_or_insert(now);
+        self.until = Some(until);
+    }
+
+    /// Ends the mute, the summary is taken with [`expire`](Self::expire).
+    #[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+    pub fn unmute(&mut self) {
+        self.until = None;
+    }
+
+    /// Until when alerts are muted, if they are at `now`, as kept in the state file.
+    pub fn until(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.until.filter(|_| self.is_muted(now))
+    }
+
+    /// Mutes alerts until the `until` kept in the state file, unless that passed before `now`.
+    pub fn restore(&mut self, until: Option<DateTime<Utc>>, now: DateTime<Utc>) {
+        if let Some(until) = until.filter(|until| now < *until) {
+            self.mute(until, now);
+        }
+    }
+
+    pub fn is_muted(&self, now: DateTime<Utc>) -> bool {
+        self.until.is_some_and(|until| now < until)
+    }
+
+    /// Counts the `notification` if it is to be suppressed, warnings about the collector
+    /// itself are never muted.
+    pub fn suppress(&mut self, notification: &Notification, now: DateTime<Utc>) -> bool {
+        if !self.is_muted(now) {
+            return false;
+        }
+        match notification {
+            Notification::Alert(_) => self.alerts += 1,
+            Notification::Digest(alerts) => self.alerts += alerts.len() as u64,
+            Notification::Resolved { .. } => self.resolutions += 1,
+            Notification::Warning(_) => return false,
+        }
+        true
+    }
+
+    /// Returns the summary once a mute ended by expiring or being unmuted.
+    pub fn expire(&mut self, now: DateTime<Utc>) -> Option<String> {
+        if self.is_muted(now) {
+            return None;
+        }
+        let since = self.since.take()?;
+        let until = self.until.take().map_or(now, |until| until.min(now));
+        let (alerts, resolutions) = (
+            std::mem::take(&mut self.alerts),
+            std::mem::take(&mut self.resolutions),
+        );
+        Some(format!(
+            "alerts were muted between {} and {}, suppressed {alerts} alerts and \
+             {resolutions} resolutions",
+            since.format("%H:%M"),
+            until.format("%H:%M UTC")
+        ))
+    }
+
+    /// Describes the mute for the status output.
+    #[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+    pub fn status(&self, now: DateTime<Utc>) -> String {
+        match self.until {
+            Some(until) if self.is_muted(now) => format!(
+                "alerts muted until {}, suppressed {} alerts and {} resolutions so far",
+                until.format("%Y-%m-%d %H:%M UTC"),
+                self.alerts,
+                self.resolutions
+            ),
+            _ => "alerts not muted".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webhook::tests::errors;
+    use chrono::{Duration, TimeZone};
+
+    fn at(minute: i64) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap() + Duration::minutes(minute)
+    }
+
+    #[test]
+    fn mutes_until_expiry() {
+        let mut mute = Mute::default();
+        assert!(!mute.suppress(&Notification::Alert(errors()), at(0)));
+        assert_eq!(mute.expire(at(0)), None);
+
+        mute.mute(at(60), at(0));
+        assert!(mute.suppress(&Notification::Alert(errors()), at(10)));
+        assert!(mute.suppress(
+            &Notification::Resolved {
+                history: None,
+                incident: None,
+            },
+            at(20),
+        ));
+        assert!(mute.suppress(&Notification::Alert(errors()), at(30)));
+        assert!(!mute.suppress(&Notification::Warning("socket".to_string()), at(30)));
+        assert_eq!(mute.expire(at(59)), None);
+        assert_eq!(
+            mute.status(at(59)),
+            "alerts muted until 2024-05-01 13:00 UTC, suppressed 2 alerts and 1 resolutions so far"
+        );
+
+        // the summary is returned once
+        assert_eq!(
+            mute.expire(at(61)).unwrap(),
+            "alerts were muted between 12:00 and 13:00 UTC, suppressed 2 alerts and 1 resolutions"
+        );
+        assert_eq!(mute.expire(at(62)), None);
+        assert_eq!(mute.status(at(62)), "alerts not muted");
+        assert!(!mute.suppress(&Notification::Alert(errors()), at(63)));
+    }
+
+    #[test]
+    fn survives_restarts() {
+        let mut mute = Mute::default();
+        mute.mute(at(60), at(0));
+        assert_eq!(mute.until(at(30)), Some(at(60)));
+        assert_eq!(mute.until(at(60)), None);
+
+        // restored after a restart, unless it ran out meanwhile
+        let mut restored = Mute::default();
+        restored.restore(mute.until(at(30)), at(45));
+        assert!(restored.suppress(&Notification::Alert(errors()), at(50)));
+        assert_eq!(
+            restored.expire(at(61)).unwrap(),
+            "alerts were muted between 12:45 and 13:00 UTC, suppressed 1 alerts and 0 resolutions"
+        );
+        let mut expired = Mute::default();
+        expired.restore(Some(at(60)), at(61));
+        assert_eq!(expired.until(at(61)), None);
+        assert_eq!(expired.expire(at(61)), None);
+        expired.restore(None, at(0));
+        assert!(!expired.is_muted(at(1)));
+    }
+
+    #[test]
+    fn unmute_ends_early() {
+        let mut mute = Mute::default();
+        mute.mute(at(60), at(0));
+        assert!(mute.suppress(&Notification::Alert(errors()), at(5)));
+        mute.unmute();
+        assert!(!mute.suppress(&Notification::Alert(errors()), at(6)));
+        assert_eq!(
+            mute.expire(at(10)).unwrap(),
+            "alerts were muted between 12:00 and 12:10 UTC, suppressed 1 alerts and 0 resolutions"
+        );
+    }
+}
@@ -0,0 +1,791 @@
+use super::quiet::{HeldAlert, QuietHours};
+use super::{AlertField, Mute, Webhook, WebhookDeliveryError};
+use crate::clock::{Clock, SystemClock};
+use crate::incident::IncidentSummary;
+use crate::logging;
+use crate::retry::RetryBudget;
+use crate::severity::Severity;
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use parking_lot::{Mutex, RwLock};
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum Notification {
+    Alert(Vec<AlertField>),
+
+    /// The errors are resolved.
+    Resolved {
+        /// The alert, if that was not delivered yet.
+        history: Option<Vec<AlertField>>,
+
+        /// How long the errors lasted.
+        incident: Option<IncidentSummary>,
+    },
+
+    /// Operational problem of the collector itself, like an unusable health socket.
+    Warning(String),
+
+    /// Alerts held back during quiet hours.
+    Digest(Vec<HeldAlert>),
+}
+
+impl Notification {
+    /// Short description for logging a notification that could not be delivered.
+    pub(super) fn describe(&self) -> String {
+        match self {
+            Notification::Alert(fields) => format!("alert for {}", names(fields)),
+            Notification::Resolved {
+                history: Some(fields),
+                ..
+            } => {
+                format!("resolution with history for {}", names(fields))
+            }
+            Notification::Resolved { history: None, .. } => "resolution".to_string(),
+            Notification::Warning(message) => format!("warning {message:?}"),
+            Notification::Digest(alerts) => format!("digest of {} alerts", alerts.len()),
+        }
+    }
+}
+
+fn names(fields: &[AlertField]) -> String {
+    let names: Vec<_> = fields.iter().map(|field| field.name.as_str()).collect();
+    names.join(", ")
+}
+
+/// Most actions kept between two calls of [`NotificationQueue::take_actions`].
+const MAX_ACTIONS: usize = 64;
+
+/// Most alerts held during quiet hours, the oldest is dropped first.
+const MAX_HELD: usize = 32;
+
+/// Failed deliveries to Discord, reported once deliveries succeed again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeliveryFailures {
+    /// Failed delivery attempts since the start of the collector.
+    pub total: u64,
+
+    /// Notifications that failed to be delivered since the current outage started.
+    pub undelivered: u64,
+
+    /// Start of the current outage.
+    pub since: Option<DateTime<Utc>>,
+
+    /// Id of the notification that failed last, retries are counted only once.
+    last_failed: Option<u64>,
+}
+
+impl DeliveryFailures {
+    pub const NONE: DeliveryFailures = DeliveryFailures {
+        total: 0,
+        undelivered: 0,
+        since: None,
+        last_failed: None,
+    };
+
+    fn record(&mut self, id: u64, now: DateTime<Utc>) {
+        self.total += 1;
+        self.since.get_or_insert(now);
+        if self.last_failed != Some(id) {
+            self.undelivered += 1;
+            self.last_failed = Some(id);
+        }
+    }
+
+    /// Ends the current outage, returns the warning reporting it.
+    fn recover(&mut self, now: DateTime<Utc>) -> Option<String> {
+        let since = self.since.take()?;
+        let undelivered = std::mem::take(&mut self.undelivered);
+        self.last_failed = None;
+        Some(format!(
+            "{undelivered} notifications could not be delivered to Discord between {} and {}, \
+             they were delivered late",
+            since.format("%H:%M"),
+            now.format("%H:%M UTC")
+        ))
+    }
+}
+
+impl fmt::Display for DeliveryFailures {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} failed deliveries", self.total)?;
+        if let Some(since) = self.since {
+            write!(
+                f,
+                ", {} notifications undelivered since {}",
+                self.undelivered,
+                since.format("%Y-%m-%d %H:%M UTC")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+struct Held {
+    alerts: VecDeque<HeldAlert>,
+
+    /// An alert of the current errors was sent, so their resolution has to be sent as well.
+    delivered: bool,
+}
+
+/// Bounded queue in front of the [`Webhook`], delivering notifications in order and retrying
+/// them until Discord accepts them.
+///
+/// When full, the oldest notification is dropped.
+/// An alert that is resolved before it could be delivered is collapsed into the resolved
+/// notification.
+///
+/// During [`QuietHours`] alerts below critical are held back and released as a single digest
+/// once the quiet hours end, resolving them meanwhile only marks them as resolved.
+///
+/// While [`Mute`]d alerts and resolutions are dropped, the summary of them is queued once the
+/// mute ends.
+pub struct NotificationQueue {
+    entries: Mutex<VecDeque<(u64, Notification)>>,
+    capacity: usize,
+    next_id: AtomicU64,
+    dropped: AtomicU64,
+
+    /// Notifications dropped as they failed permanently, see
+    /// [`Notifier::is_permanent`].
+    rejected: AtomicU64,
+    pushed: Notify,
+    quiet_hours: Option<QuietHours>,
+    held: Mutex<Held>,
+    failures: Mutex<DeliveryFailures>,
+    mute: Arc<RwLock<Mute>>,
+    clock: Arc<dyn Clock>,
+
+    /// Retries of the collection tick, shared with the forecast requests and the writes.
+    retry_budget: Arc<RetryBudget>,
+
+    /// What became of the notifications pushed since the last [`take_actions`](Self::take_actions).
+    actions: Mutex<Vec<String>>,
+}
+
+impl NotificationQueue {
+    pub fn new(capacity: usize) -> NotificationQueue {
+        Self {
+            entries: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity: capacity.max(1),
+            next_id: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+            pushed: Notify::new(),
+            quiet_hours: None,
+            held: Mutex::default(),
+            failures: Mutex::new(DeliveryFailures::NONE),
+            mute: Arc::default(),
+            clock: Arc::new(SystemClock),
+            retry_budget: Arc::default(),
+            actions: Mutex::default(),
+        }
+    }
+
+    pub fn with_quiet_hours(self, quiet_hours: Option<QuietHours>) -> NotificationQueue {
+        Self {
+            quiet_hours,
+            ..self
+        }
+    }
+
+    /// Shares the `mute` with the health socket setting it.
+    pub fn with_mute(self, mute: Arc<RwLock<Mute>>) -> NotificationQueue {
+        Self { mute, ..self }
+    }
+
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> NotificationQueue {
+        Self { clock, ..self }
+    }
+
+    /// Takes the retries of failed deliveries from the `retry_budget` of the ticks.
+    pub fn with_retry_budget(self, retry_budget: Arc<RetryBudget>) -> NotificationQueue {
+        Self {
+            retry_budget,
+            ..self
+        }
+    }
+
+    pub fn push(&self, notification: Notification) {
+        self.push_at(notification, self.clock.now_utc());
+    }
+
+    fn push_at(&self, notification: Notification, now: DateTime<Utc>) {
+        let description = notification.describe();
+        if self.mute.write().suppress(&notification, now) {
+            self.act(format!("muted {description}"));
+            return;
+        }
+        if self.hold(&notification, now) {
+            self.act(format!("held {description}"));
+            return;
+        }
+        self.act(format!("queued {description}"));
+
+        let mut entries = self.entries.lock();
+
+        let notification = match notification {
+            Notification::Resolved {
+                history: None,
+                incident,
+            } => {
+                let pending_alert = entries
+                    .iter()
+                    .rposition(|(_, n)| matches!(n, Notification::Alert(_)));
+                let history = match pending_alert.and_then(|i| entries.remove(i)) {
+                    Some((_, Notification::Alert(fields))) => Some(fields),
+                    _ => None,
+                };
+                Notification::Resolved { history, incident }
+            }
+            notification => notification,
+        };
+
+        if entries.len() >= self.capacity {
+            entries.pop_front();
+            let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            let datetime = logging::datetime();
+            log_eprintln!(
+                "WARN  [{datetime}]: notification queue full, \
+                 dropped {dropped} notifications so far"
+            );
+        }
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        entries.push_back((id, notification));
+        self.pushed.notify_one();
+    }
+
+    /// Holds back the `notification` if quiet hours apply to it.
+    fn hold(&self, notification: &Notification, now: DateTime<Utc>) -> bool {
+        let Some(quiet_hours) = self.quiet_hours.as_ref() else {
+            return false;
+        };
+
+        let mut held = self.held.lock();
+        match notification {
+            Notification::Alert(fields) => {
+                let critical = fields.iter().any(|f| f.severity >= Severity::Critical);
+                if critical || !quiet_hours.is_quiet(now) {
+                    held.delivered = true;
+                    return false;
+                }
+
+                if held.alerts.len() >= MAX_HELD {
+                    held.alerts.pop_front();
+                    let datetime = logging::LOG_TIME.format(now);
+                    log_eprintln!(
+                        "WARN  [{datetime}]: too many alerts held during quiet hours, \
+                         dropped the oldest"
+                    );
+                }
+                held.alerts.push_back(HeldAlert {
+                    fields: fields.clone(),
+                    at: now,
+                    resolved: false,
+                });
+                true
+            }
+            Notification::Resolved { history: None, .. } => {
+                let mut resolved_held = false;
+                for alert in held.alerts.iter_mut().filter(|alert| !alert.resolved) {
+                    alert.resolved = true;
+                    resolved_held = true;
+                }
+                let delivered = std::mem::take(&mut held.delivered);
+                resolved_held && !delivered
+            }
+            _ => false,
+        }
+    }
+
+    /// Queues the held alerts as a digest once the quiet hours ended.
+    pub fn release_digest(&self, now: DateTime<Utc>) {
+        let Some(quiet_hours) = self.quiet_hours.as_ref() else {
+            return;
+        };
+        if quiet_hours.is_quiet(now) {
+            return;
+        }
+
+        let alerts: Vec<_> = {
+            let mut held = self.held.lock();
+            held.delivered |= held.alerts.iter().any(|alert| !alert.resolved);
+            held.alerts.drain(..).collect()
+        };
+        if !alerts.is_empty() {
+            self.push_at(Notification::Digest(alerts), now);
+        }
+    }
+
+    /// Queues the summary of the suppressed alerts once the mute ended.
+    pub fn release_muted(&self, now: DateTime<Utc>) {
+        let summary = self.mute.write().expire(now);
+        if let Some(summary) = summary {
+            let datetime = logging::LOG_TIME.format(now);
+            log_eprintln!("INFO  [{datetime}]: {summary}");
+            self.push_at(Notification::Warning(summary), now);
+        }
+    }
+
+    async fn front(&self) -> (u64, Notification) {
+        loop {
+            if let Some(front) = self.entries.lock().front().cloned() {
+                return front;
+            }
+            self.pushed.notified().await;
+        }
+    }
+
+    fn remove(&self, id: u64) {
+        self.entries.lock().retain(|(entry_id, _)| *entry_id != id);
+    }
+
+    #[cfg_attr(not(feature = "health-check"), allow(dead_code))]
+    pub fn delivery_failures(&self) -> DeliveryFailures {
+        *self.failures.lock()
+    }
+
+    fn act(&self, action: String) {
+        let mut actions = self.actions.lock();
+        if actions.len() < MAX_ACTIONS {
+            actions.push(action);
+        }
+    }
+
+    /// What became of the notifications pushed since the last call, like `queued alert for …`.
+    pub fn take_actions(&self) -> Vec<String> {
+        std::mem::take(&mut *self.actions.lock())
+    }
+
+    /// Waits at most `timeout` for the queued notifications to be delivered, returns whether
+    /// they were.
+    pub async fn flushed(&self, timeout: Duration) -> bool {
+        let deadline = tokio::time::Instant::now() + timeout;
+        while !self.entries.lock().is_empty() {
+            if tokio::time::Instant::now() >= deadline {
+                return false;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        true
+    }
+
+    /// Delivers the queued notifications to the `notifier` forever, backing off exponentially
+    /// from `retry_delay` up to `max_retry_delay` while the deliveries fail.
+    ///
+    /// Notifications are never given up on, but once the retry budget of the tick is used up
+    /// they are retried only every `max_retry_delay`.
+    ///
+    /// Once deliveries succeed again after failing, a warning reports the outage.
+    ///
+    /// Notifications are only given up on if they failed [permanently](Notifier::is_permanent),
+    /// then they are dropped so they do not hold up the notifications queued after them.
+    pub async fn drain<N: Notifier>(
+        &self,
+        notifier: &N,
+        retry_delay: Duration,
+        max_retry_delay: Duration,
+    ) {
+        let mut delay = retry_delay;
+        loop {
+            let (id, notification) = self.front().await;
+            match notifier.deliver(&notification).await {
+                Ok(()) => {
+                    self.remove(id);
+                    delay = retry_delay;
+                    let recovered = self.failures.lock().recover(self.clock.now_utc());
+                    if let Some(message) = recovered {
+                        let datetime = logging::datetime();
+                        log_eprintln!("INFO  [{datetime}]: {message}");
+                        self.push(Notification::Warning(message));
+                    }
+                }
+                Err(err) if notifier.is_permanent(&err) => {
+                    self.remove(id);
+                    let rejected = self.rejected.fetch_add(1, Ordering::Relaxed) + 1;
+                    let datetime = logging::datetime();
+                    log_eprintln!(
+                        "ERROR [{datetime}]: {err}, dropping the notification as it cannot be \
+                         delivered, dropped {rejected} such notifications so far"
+                    );
+                }
+                Err(err) => {
+                    let failures = {
+                        let mut failures = self.failures.lock();
+                        failures.record(id, self.clock.now_utc());
+                        *failures
+                    };
+                    let wait = match self.retry_budget.take() {
+                        true => delay,
+                        false => max_retry_delay,
+                    };
+                    let datetime = logging::datetime();
+                    log_eprintln!(
+                        "ERROR [{datetime}]: could not deliver {}, {err}, retrying in {} seconds, \
+                         {failures}",
+                        notification.describe(),
+                        wait.as_secs()
+                    );
+                    tokio::time::sleep(wait).await;
+                    delay = (delay * 2).min(max_retry_delay);
+                }
+            }
+        }
+    }
+}
+
+/// Delivers the notifications drained from a [NotificationQueue], like the Discord [Webhook].
+pub trait Notifier: Send + Sync {
+    type Error: fmt::Display;
+
+    /// Name of the channel notified, like `discord`.
+    fn channel(&self) -> &'static str;
+
+    /// Delivers the `notification`, failed deliveries are retried by the queue.
+    fn deliver<'a>(
+        &'a self,
+        notification: &'a Notification,
+    ) -> BoxFuture<'a, Result<(), Self::Error>>;
+
+    /// Whether a delivery failing with `error` fails again however often it is retried, like
+    /// a message Discord rejects, so the queue drops the notification instead.
+    fn is_permanent(&self, _error: &Self::Error) -> bool {
+        false
+    }
+}
+
+impl Notifier for Webhook {
+    type Error = WebhookDeliveryError;
+
+    fn channel(&self) -> &'static str {
+        "discord"
+    }
+
+    fn deliver<'a>(
+        &'a self,
+        notification: &'a Notification,
+    ) -> BoxFuture<'a, Result<(), WebhookDeliveryError>> {
+        Box::pin(async move {
+            match notification {
+                Notification::Alert(fields) => self.alert(fields).await,
+                Notification::Resolved { history, incident } => {
+                    self.resolved(history.as_deref(), incident.as_ref()).await
+                }
+                Notification::Warning(message) => self.warning(message).await,
+                Notification::Digest(alerts) => self.digest(alerts).await,
+            }
+        })
+    }
+
+    fn is_permanent(&self, error: &WebhookDeliveryError) -> bool {
+        error.is_permanent()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webhook::tests::{errors, mock_discord, mock_webhook};
+    use chrono::{NaiveTime, TimeZone};
+    use chrono_tz::Tz;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+    use warp::http::StatusCode;
+
+    fn resolved() -> Notification {
+        Notification::Resolved {
+            history: None,
+            incident: None,
+        }
+    }
+
+    const RETRY_DELAY: Duration = Duration::from_millis(10);
+
+    fn quiet_queue() -> NotificationQueue {
+        let time = |hour| NaiveTime::from_hms_opt(hour, 0, 0).unwrap();
+        let quiet_hours = QuietHours::new(time(22), time(6), Tz::UTC);
+        NotificationQueue::new(8).with_quiet_hours(Some(quiet_hours))
+    }
+
+    fn at(day: u32, hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, day, hour, 0, 0).unwrap()
+    }
+
+    fn critical() -> Vec<AlertField> {
+        let mut fields = errors();
+        fields[0].severity = Severity::Critical;
+        fields
+    }
+
+    #[test]
+    fn resolved_collapses_pending_alert() {
+        let queue = NotificationQueue::new(8);
+        queue.push(Notification::Alert(errors()));
+        queue.push(resolved());
+
+        let entries = queue.entries.lock();
+        assert_eq!(entries.len(), 1);
+        let Notification::Resolved {
+            history: Some(history),
+            ..
+        } = &entries[0].1
+        else {
+            panic!("expected resolved with history, got {:?}", entries[0].1);
+        };
+        assert_eq!(history.len(), 1);
+    }
+
+    #[test]
+    fn full_queue_drops_oldest() {
+        let queue = NotificationQueue::new(2);
+        queue.push(Notification::Alert(errors()));
+        queue.push(resolved());
+        queue.push(Notification::Alert(errors()));
+        queue.push(Notification::Alert(errors()));
+        assert_eq!(queue.entries.lock().len(), 2);
+        assert_eq!(queue.dropped.load(Ordering::Relaxed), 1);
+        assert!(queue
+            .entries
+            .lock()
+            .iter()
+            .all(|(_, n)| matches!(n, Notification::Alert(_))));
+    }
+
+    #[tokio::test]
+    async fn flaky_webhook_eventually_delivers() {
+        static REQUESTS: AtomicUsize = AtomicUsize::new(0);
+        let (addr, executions) = mock_discord(|_| {
+            // the first two requests fail
+            match REQUESTS.fetch_add(1, Ordering::Relaxed) {
+                0 | 1 => (StatusCode::BAD_GATEWAY, "{}"),
+                _ => (StatusCode::OK, "{}"),
+            }
+        });
+        let webhook = Arc::new(mock_webhook(addr, &[1]));
+        let queue = Arc::new(NotificationQueue::new(8));
+
+        queue.push(Notification::Alert(errors()));
+        let drain = {
+            let (queue, webhook) = (queue.clone(), webhook.clone());
+            tokio::spawn(async move { queue.drain(&*webhook, RETRY_DELAY, RETRY_DELAY * 4).await })
+        };
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !queue.entries.lock().is_empty() {
+                tokio::time::sleep(RETRY_DELAY).await;
+            }
+        })
+        .await
+        .expect("alert delivered");
+        // the alert and the warning about the late alert
+        assert_eq!(*executions.lock(), [1, 1, 1, 1]);
+        let failures = queue.delivery_failures();
+        assert_eq!((failures.total, failures.since), (2, None));
+        drain.abort();
+    }
+
+    #[test]
+    fn delivery_failures_are_counted() {
+        let mut failures = DeliveryFailures::NONE;
+        assert_eq!(failures.recover(at(1, 12)), None);
+
+        // retries of the same notification are only counted as attempts
+        failures.record(1, at(1, 12));
+        failures.record(1, at(1, 13));
+        failures.record(2, at(1, 14));
+        assert_eq!((failures.total, failures.undelivered), (3, 2));
+        assert_eq!(
+            failures.to_string(),
+            "3 failed deliveries, 2 notifications undelivered since 2024-05-01 12:00 UTC"
+        );
+
+        assert_eq!(
+            failures.recover(at(1, 15)).unwrap(),
+            "2 notifications could not be delivered to Discord between 12:00 and 15:00 UTC, \
+             they were delivered late"
+        );
+        assert_eq!(failures.to_string(), "3 failed deliveries");
+        assert_eq!(failures.recover(at(1, 16)), None);
+    }
+
+    #[tokio::test]
+    async fn rejected_notifications_are_dropped() {
+        let (addr, executions) = mock_discord(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                r#"{"message": "Invalid Form Body", "code": 50035}"#,
+            )
+        });
+        let webhook = Arc::new(mock_webhook(addr, &[1]));
+        let queue = Arc::new(NotificationQueue::new(8));
+
+        queue.push(Notification::Alert(errors()));
+        queue.push(Notification::Alert(errors()));
+        let drain = {
+            let (queue, webhook) = (queue.clone(), webhook.clone());
+            tokio::spawn(async move { queue.drain(&*webhook, RETRY_DELAY, RETRY_DELAY).await })
+        };
+
+        // neither is retried, the first alert does not hold up the second
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !queue.entries.lock().is_empty() {
+                tokio::time::sleep(RETRY_DELAY).await;
+            }
+        })
+        .await
+        .expect("notifications dropped");
+        assert_eq!(*executions.lock(), [1, 1]);
+        assert_eq!(queue.rejected.load(Ordering::Relaxed), 2);
+        drain.abort();
+    }
+
+    #[tokio::test]
+    async fn undelivered_alert_is_resolved_with_history() {
+        let (addr, executions) = mock_discord(|_| (StatusCode::OK, "{}"));
+        let webhook = mock_webhook(addr, &[1]);
+        let queue = NotificationQueue::new(8);
+
+        // discord was unreachable during the whole incident
+        queue.push(Notification::Alert(errors()));
+        queue.push(resolved());
+
+        let (id, notification) = queue.front().await;
+        webhook.deliver(&notification).await.unwrap();
+        queue.remove(id);
+
+        // a single message containing the history was sent
+        assert_eq!(*executions.lock(), [1]);
+        assert!(queue.entries.lock().is_empty());
+    }
+
+    #[test]
+    fn quiet_hours_hold_alerts() {
+        let queue = quiet_queue();
+
+        // a blip during the night is held and its resolution is not sent
+        queue.push_at(Notification::Alert(errors()), at(1, 23));
+        queue.push_at(resolved(), at(2, 0));
+        // another incident still going on when the quiet hours end
+        queue.push_at(Notification::Alert(errors()), at(2, 1));
+        assert!(queue.entries.lock().is_empty());
+
+        queue.release_digest(at(2, 5));
+        assert!(queue.entries.lock().is_empty());
+
+        queue.release_digest(at(2, 6));
+        {
+            let entries = queue.entries.lock();
+            assert_eq!(entries.len(), 1);
+            let Notification::Digest(alerts) = &entries[0].1 else {
+                panic!("expected digest, got {:?}", entries[0].1);
+            };
+            let resolved: Vec<_> = alerts.iter().map(|alert| alert.resolved).collect();
+            assert_eq!(resolved, [true, false]);
+        }
+        assert!(queue.held.lock().alerts.is_empty());
+
+        // the alert delivered with the digest is resolved as usual
+        queue.push_at(resolved(), at(2, 7));
+        assert_eq!(queue.entries.lock().len(), 2);
+    }
+
+    #[test]
+    fn quiet_hours_pass_critical_alerts() {
+        let queue = quiet_queue();
+        queue.push_at(Notification::Alert(critical()), at(1, 23));
+        queue.push_at(resolved(), at(2, 0));
+        assert_eq!(queue.entries.lock().len(), 1);
+        assert!(matches!(
+            queue.entries.lock()[0].1,
+            Notification::Resolved {
+                history: Some(_),
+                ..
+            }
+        ));
+
+        // outside of quiet hours nothing is held
+        queue.push_at(Notification::Alert(errors()), at(2, 12));
+        assert_eq!(queue.entries.lock().len(), 2);
+        assert!(queue.held.lock().alerts.is_empty());
+    }
+
+    #[test]
+    fn muted_alerts_are_summarized() {
+        let mute = Arc::new(RwLock::new(Mute::default()));
+        let queue = NotificationQueue::new(8).with_mute(mute.clone());
+        mute.write().mute(at(1, 13), at(1, 12));
+
+        queue.push_at(Notification::Alert(errors()), at(1, 12));
+        queue.push_at(resolved(), at(1, 12));
+        queue.release_muted(at(1, 12));
+        assert!(queue.entries.lock().is_empty());
+
+        queue.release_muted(at(1, 13));
+        {
+            let entries = queue.entries.lock();
+            assert_eq!(entries.len(), 1);
+            let Notification::Warning(summary) = &entries[0].1 else {
+                panic!("expected warning, got {:?}", entries[0].1);
+            };
+            assert!(summary.ends_with("suppressed 1 alerts and 1 resolutions"));
+        }
+
+        queue.push_at(Notification::Alert(errors()), at(1, 14));
+        assert_eq!(queue.entries.lock().len(), 2);
+    }
+
+    #[test]
+    fn takes_actions() {
+        let queue = quiet_queue();
+        queue.push_at(Notification::Warning("slow".to_string()), at(1, 12));
+        queue.push_at(Notification::Alert(errors()), at(1, 23));
+
+        let actions = queue.take_actions();
+        assert_eq!(actions.len(), 2);
+        assert_eq!(actions[0], "queued warning \"slow\"");
+        assert!(actions[1].starts_with("held alert for "), "{}", actions[1]);
+        assert!(queue.take_actions().is_empty());
+    }
+
+    #[test]
+    fn held_alerts_are_bounded() {
+        let queue = quiet_queue();
+        for _ in 0..MAX_HELD + 5 {
+            queue.push_at(Notification::Alert(errors()), at(1, 23));
+        }
+        assert_eq!(queue.held.lock().alerts.len(), MAX_HELD);
+    }
+
+    #[tokio::test]
+    async fn digest_alerts_destinations() {
+        let (addr, executions) = mock_discord(|_| (StatusCode::OK, "{}"));
+        let webhook = mock_webhook(addr, &[1]);
+        let held = |resolved| HeldAlert {
+            fields: errors(),
+            at: at(1, 23),
+            resolved,
+        };
+
+        // only resolved errors, nothing left to resolve later
+        webhook
+            .deliver(&Notification::Digest(vec![held(true)]))
+            .await
+            .unwrap();
+        assert!(!webhook.destinations[0].alerted.load(Ordering::Relaxed));
+
+        webhook
+            .deliver(&Notification::Digest(vec![held(true), held(false)]))
+            .await
+            .unwrap();
+        assert!(webhook.destinations[0].alerted.load(Ordering::Relaxed));
+        assert_eq!(*executions.lock(), [1, 1]);
+    }
+}
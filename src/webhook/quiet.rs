@@ -0,0 +1,202 @@
+use super::AlertField;
+use chrono::{DateTime, NaiveTime, Utc};
+use chrono_tz::Tz;
+use thiserror::Error;
+
+/// Time of day during which alerts below critical are held back and later sent as a digest,
+/// configured via `QUIET_HOURS` like `22:00-06:00` in the `QUIET_HOURS_TIMEZONE`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuietHours {
+    start: NaiveTime,
+    end: NaiveTime,
+    timezone: Tz,
+}
+
+#[derive(Debug, Error)]
+pub enum QuietHoursError {
+    #[error("expected quiet hours in the form of `HH:MM-HH:MM`, got {0:?}")]
+    Format(String),
+
+    #[error("invalid time {0:?}, {1}")]
+    Time(String, #[source] chrono::ParseError),
+
+    #[error("unknown timezone {0:?}")]
+    Timezone(String),
+}
+
+impl QuietHours {
+    pub fn new(start: NaiveTime, end: NaiveTime, timezone: Tz) -> QuietHours {
+        QuietHours {
+            start,
+            end,
+            timezone,
+        }
+    }
+
+    /// Reads `QUIET_HOURS` and `QUIET_HOURS_TIMEZONE` (defaulting to UTC) from `lookup`,
+    /// returns `None` if no quiet hours are configured.
+    pub fn from_lookup(
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<Option<QuietHours>, QuietHoursError> {
+        let Some(hours) = lookup("QUIET_HOURS") else {
+            return Ok(None);
+        };
+        let (start, end) = hours
+            .split_once('-')
+            .ok_or_else(|| QuietHoursError::Format(hours.clone()))?;
+        let parse = |time: &str| {
+            NaiveTime::parse_from_str(time.trim(), "%H:%M")
+                .map_err(|err| QuietHoursError::Time(time.trim().to_string(), err))
+        };
+
+        let timezone = match lookup("QUIET_HOURS_TIMEZONE") {
+            Some(timezone) => timezone
+                .trim()
+                .parse()
+                .map_err(|_| QuietHoursError::Timezone(timezone))?,
+            None => Tz::UTC,
+        };
+        Ok(Some(QuietHours::new(parse(start)?, parse(end)?, timezone)))
+    }
+
+    /// Whether `now` lies within the quiet hours, evaluated on the wall clock of the
+    /// configured timezone.
+    ///
+    /// The start is inclusive, the end exclusive, a range with the start after the end spans
+    /// midnight and equal start and end are never quiet.
+    pub fn is_quiet(&self, now: DateTime<Utc>) -> bool {
+        let time = now.with_timezone(&self.timezone).time();
+        match self.start <= self.end {
+            true => self.start <= time && time < self.end,
+            false => self.start <= time || time < self.end,
+        }
+    }
+}
+
+/// An alert held back during quiet hours.
+#[derive(Debug, Clone)]
+pub struct HeldAlert {
+    pub fields: Vec<AlertField>,
+    pub at: DateTime<Utc>,
+
+    /// The errors were resolved before the quiet hours ended.
+    pub resolved: bool,
+}
+
+impl HeldAlert {
+    /// The fields of the alert, named with the time and whether they are resolved already.
+    pub fn digest_fields(&self) -> impl Iterator<Item = AlertField> + '_ {
+        let at = self.at.format("%Y-%m-%d %H:%M UTC");
+        let state = match self.resolved {
+            true => ", resolved",
+            false => "",
+        };
+        self.fields.iter().map(move |field| AlertField {
+            name: format!("{} ({at}{state})", field.name),
+            ..field.clone()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn time(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    fn utc(month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, month, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn same_day_range() {
+        let quiet = QuietHours::new(time(12, 0), time(14, 0), Tz::UTC);
+        assert!(!quiet.is_quiet(utc(5, 1, 11, 59)));
+        assert!(quiet.is_quiet(utc(5, 1, 12, 0)));
+        assert!(quiet.is_quiet(utc(5, 1, 13, 59)));
+        assert!(!quiet.is_quiet(utc(5, 1, 14, 0)));
+    }
+
+    #[test]
+    fn range_crossing_midnight() {
+        let quiet = QuietHours::new(time(22, 0), time(6, 0), Tz::UTC);
+        assert!(!quiet.is_quiet(utc(5, 1, 21, 59)));
+        assert!(quiet.is_quiet(utc(5, 1, 22, 0)));
+        assert!(quiet.is_quiet(utc(5, 1, 23, 59)));
+        assert!(quiet.is_quiet(utc(5, 2, 0, 0)));
+        assert!(quiet.is_quiet(utc(5, 2, 5, 59)));
+        assert!(!quiet.is_quiet(utc(5, 2, 6, 0)));
+        assert!(!quiet.is_quiet(utc(5, 2, 12, 0)));
+    }
+
+    #[test]
+    fn empty_range() {
+        let quiet = QuietHours::new(time(6, 0), time(6, 0), Tz::UTC);
+        assert!(!quiet.is_quiet(utc(5, 1, 6, 0)));
+        assert!(!quiet.is_quiet(utc(5, 1, 18, 0)));
+    }
+
+    #[test]
+    fn timezone_and_dst() {
+        let quiet = QuietHours::new(time(22, 0), time(6, 0), Tz::Europe__Berlin);
+
+        // winter time is UTC+1
+        assert!(!quiet.is_quiet(utc(1, 15, 20, 59)));
+        assert!(quiet.is_quiet(utc(1, 15, 21, 0)));
+        assert!(quiet.is_quiet(utc(1, 16, 4, 59)));
+        assert!(!quiet.is_quiet(utc(1, 16, 5, 0)));
+
+        // summer time is UTC+2
+        assert!(!quiet.is_quiet(utc(7, 15, 19, 59)));
+        assert!(quiet.is_quiet(utc(7, 15, 20, 0)));
+        assert!(quiet.is_quiet(utc(7, 16, 3, 59)));
+        assert!(!quiet.is_quiet(utc(7, 16, 4, 0)));
+
+        // the night the clocks are set forward, 02:00 local becomes 03:00
+        assert!(quiet.is_quiet(utc(3, 31, 0, 59)));
+        assert!(quiet.is_quiet(utc(3, 31, 1, 0)));
+        assert!(quiet.is_quiet(utc(3, 31, 3, 59)));
+        assert!(!quiet.is_quiet(utc(3, 31, 4, 0)));
+
+        // the night the clocks are set back, 03:00 local becomes 02:00 again
+        assert!(quiet.is_quiet(utc(10, 27, 0, 30)));
+        assert!(quiet.is_quiet(utc(10, 27, 1, 30)));
+        assert!(quiet.is_quiet(utc(10, 27, 4, 59)));
+        assert!(!quiet.is_quiet(utc(10, 27, 5, 0)));
+    }
+
+    #[test]
+    fn parse_config() {
+        let quiet = QuietHours::from_lookup(|key| match key {
+            "QUIET_HOURS" => Some("22:00 - 06:30".to_string()),
+            "QUIET_HOURS_TIMEZONE" => Some("Europe/Berlin".to_string()),
+            _ => None,
+        })
+        .unwrap();
+        assert_eq!(
+            quiet,
+            Some(QuietHours::new(
+                time(22, 0),
+                time(6, 30),
+                Tz::Europe__Berlin
+            ))
+        );
+
+        assert_eq!(QuietHours::from_lookup(|_| None).unwrap(), None);
+
+        let err = QuietHours::from_lookup(|key| (key == "QUIET_HOURS").then(|| "22".to_string()))
+            .unwrap_err();
+        assert!(matches!(err, QuietHoursError::Format(_)));
+        let err = QuietHours::from_lookup(|key| match key {
+            "QUIET_HOURS" => Some("22:00-06:00".to_string()),
+            "QUIET_HOURS_TIMEZONE" => Some("Mars/Olympus".to_string()),
+            _ => None,
+        })
+        .unwrap_err();
+        assert_eq!(err.to_string(), "unknown timezone \"Mars/Olympus\"");
+    }
+}